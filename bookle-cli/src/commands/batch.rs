@@ -1,14 +1,19 @@
 //! Batch conversion command implementation
 
+use super::diagnostics;
+use super::input::{is_supported, read_input};
+use super::progress;
 use anyhow::{bail, Context, Result};
-use bookle_core::decoder::decoder_for_extension;
-use bookle_core::encoder::encoder_for_format;
+use bookle_core::decoder::DecodeContext;
+use bookle_core::encoder::EncodeContext;
+use bookle_core::{CancellationToken, FormatRegistry};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use std::fs::{self, File};
-use std::io::BufReader;
+use std::io::Cursor;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 /// Batch convert multiple ebooks
 pub fn batch(input_dir: &str, output_dir: &str, format: &str, jobs: usize) -> Result<()> {
@@ -22,12 +27,7 @@ pub fn batch(input_dir: &str, output_dir: &str, format: &str, jobs: usize) -> Re
     let files: Vec<_> = fs::read_dir(input_path)?
         .filter_map(|e| e.ok())
         .map(|e| e.path())
-        .filter(|p| {
-            p.extension()
-                .and_then(|e| e.to_str())
-                .map(|ext| decoder_for_extension(ext).is_some())
-                .unwrap_or(false)
-        })
+        .filter(|p| is_supported(p))
         .collect();
 
     if files.is_empty() {
@@ -38,7 +38,8 @@ pub fn batch(input_dir: &str, output_dir: &str, format: &str, jobs: usize) -> Re
     println!("Found {} files to convert", files.len());
 
     // Get encoder
-    let encoder = encoder_for_format(format)
+    let encoder = FormatRegistry::builtin()
+        .encoder_for_format(format)
         .with_context(|| format!("No encoder available for {} format", format))?;
 
     // Set up progress tracking
@@ -53,6 +54,18 @@ pub fn batch(input_dir: &str, output_dir: &str, format: &str, jobs: usize) -> Re
 
     let success_count = AtomicUsize::new(0);
     let error_count = AtomicUsize::new(0);
+    let cancelled_count = AtomicUsize::new(0);
+
+    // On Ctrl-C, abandon the files in flight and skip the rest
+    let cancellation = CancellationToken::new();
+    let ctrl_c = tokio::runtime::Handle::try_current().ok().map(|handle| {
+        let token = cancellation.clone();
+        handle.spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                token.cancel();
+            }
+        })
+    });
 
     // Configure thread pool
     rayon::ThreadPoolBuilder::new()
@@ -62,12 +75,29 @@ pub fn batch(input_dir: &str, output_dir: &str, format: &str, jobs: usize) -> Re
 
     // Process files in parallel
     files.par_iter().for_each(|file_path| {
-        let result = process_file(file_path, output_path, &*encoder);
+        if cancellation.is_cancelled() {
+            cancelled_count.fetch_add(1, Ordering::Relaxed);
+            overall_pb.inc(1);
+            return;
+        }
+
+        // Follow the file on its own bar while it converts
+        let file_pb = multi_progress.insert_before(&overall_pb, ProgressBar::new(0));
+        file_pb.set_style(progress::file_style());
+        file_pb.enable_steady_tick(Duration::from_millis(100));
+
+        let result = process_file(file_path, output_path, &*encoder, &file_pb, &cancellation);
+
+        file_pb.finish_and_clear();
+        multi_progress.remove(&file_pb);
 
         match result {
             Ok(_) => {
                 success_count.fetch_add(1, Ordering::Relaxed);
             }
+            Err(_) if cancellation.is_cancelled() => {
+                cancelled_count.fetch_add(1, Ordering::Relaxed);
+            }
             Err(e) => {
                 error_count.fetch_add(1, Ordering::Relaxed);
                 tracing::error!("Failed to convert {:?}: {}", file_path, e);
@@ -78,14 +108,27 @@ pub fn batch(input_dir: &str, output_dir: &str, format: &str, jobs: usize) -> Re
     });
 
     overall_pb.finish();
+    if let Some(ctrl_c) = ctrl_c {
+        ctrl_c.abort();
+    }
 
     let success = success_count.load(Ordering::Relaxed);
     let errors = error_count.load(Ordering::Relaxed);
+    let cancelled = cancelled_count.load(Ordering::Relaxed);
 
     println!("\nBatch conversion complete:");
     println!("  Success: {}", success);
     println!("  Errors:  {}", errors);
+    if cancelled > 0 {
+        println!("  Cancelled: {}", cancelled);
+    }
 
+    if cancellation.is_cancelled() {
+        bail!(
+            "Batch conversion cancelled with {} files unconverted",
+            cancelled
+        );
+    }
     if errors > 0 {
         bail!("Batch conversion completed with {} errors", errors);
     }
@@ -97,19 +140,31 @@ fn process_file(
     input_path: &Path,
     output_dir: &Path,
     encoder: &dyn bookle_core::encoder::Encoder,
+    pb: &ProgressBar,
+    cancellation: &CancellationToken,
 ) -> Result<()> {
-    // Get decoder based on extension
-    let ext = input_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .context("Could not determine file extension")?;
-
-    let decoder = decoder_for_extension(ext).context("No decoder available")?;
-
-    // Read and decode
-    let file = File::open(input_path)?;
-    let mut reader = BufReader::new(file);
-    let book = decoder.decode(&mut reader)?;
+    let name = input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    // Get decoder for the detected format
+    let file = read_input(input_path)?;
+    let decoder = file.decoder();
+
+    // Decode, resolving referenced assets next to the input file
+    pb.set_message(format!("Decoding {}", name));
+    let context = DecodeContext::new()
+        .with_base_path(input_path.parent().unwrap_or(Path::new(".")))
+        .with_progress(progress::listener(pb.clone()))
+        .with_cancellation(cancellation.clone());
+    context.progress().set_bytes_total(file.data.len() as u64);
+    let mut reader = context.progress().reader(Cursor::new(file.data));
+    let book = decoder.decode_with_context(&mut reader, &context)?;
+
+    for diagnostic in context.diagnostics() {
+        diagnostics::log(input_path, &diagnostic);
+    }
 
     // Build output path
     let stem = input_path
@@ -118,9 +173,24 @@ fn process_file(
         .context("Could not determine output filename from input")?;
     let output_file = output_dir.join(format!("{}.{}", stem, encoder.file_extension()));
 
-    // Encode
+    // Encode, removing the partial output if cancelled
+    pb.set_message(format!("Encoding {}", name));
+    pb.set_position(0);
     let mut output = File::create(&output_file)?;
-    encoder.encode(&book, &mut output)?;
+    let encode_context = EncodeContext::new()
+        .with_progress(progress::listener(pb.clone()))
+        .with_cancellation(cancellation.clone());
+    if let Err(e) = encoder.encode_with_context(&book, &mut output, &encode_context) {
+        if cancellation.is_cancelled() {
+            drop(output);
+            fs::remove_file(&output_file).ok();
+        }
+        return Err(e.into());
+    }
+
+    for diagnostic in encode_context.diagnostics() {
+        diagnostics::log(&output_file, &diagnostic);
+    }
 
     tracing::info!("Converted {:?} -> {:?}", input_path, output_file);
 
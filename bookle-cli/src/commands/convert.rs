@@ -1,53 +1,52 @@
 //! Convert command implementation
 
-use anyhow::{Context, Result};
-use bookle_core::decoder::decoder_for_extension;
-use bookle_core::encoder::encoder_for_format;
-use indicatif::{ProgressBar, ProgressStyle};
+use super::diagnostics;
+use super::input::{read_input, Input};
+use super::progress;
+use anyhow::{bail, Context, Result};
+use bookle_core::decoder::{
+    DecodeContext, DecodeOptions, Decoder, MarkdownProjectDecoder, SplitOptions,
+};
+use bookle_core::encoder::EncodeContext;
+use bookle_core::{Book, FormatRegistry};
+use indicatif::ProgressBar;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::Cursor;
 use std::path::Path;
 use std::time::Duration;
 
+/// Decoder options for the convert command
+#[derive(Debug, Default)]
+pub struct ConvertOptions {
+    /// Options that only some formats support, such as a PDF's page range
+    pub decode: DecodeOptions,
+    /// Where to start chapters, and their maximum length in characters, for
+    /// formats whose decoders split a block stream (marked in `bookle formats`)
+    pub split: SplitOptions,
+}
+
 /// Convert an ebook from one format to another
-pub fn convert(input: &str, output: &str, format: &str) -> Result<()> {
+pub fn convert(input: &str, output: &str, format: &str, options: &ConvertOptions) -> Result<()> {
     let input_path = Path::new(input);
     let output_path = Path::new(output);
 
-    // Get file extension
-    let ext = input_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .context("Could not determine input file extension")?;
-
-    // Get decoder
-    let decoder = decoder_for_extension(ext)
-        .with_context(|| format!("No decoder available for .{} files", ext))?;
-
     // Get encoder
-    let encoder = encoder_for_format(format)
+    let encoder = FormatRegistry::builtin()
+        .encoder_for_format(format)
         .with_context(|| format!("No encoder available for {} format", format))?;
 
-    // Set up progress bar with animation
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg}")
-            .unwrap(),
-    );
+    // Set up a progress bar that follows the decoder and encoder
+    let pb = ProgressBar::new(0);
+    pb.set_style(progress::file_style());
     pb.enable_steady_tick(Duration::from_millis(100));
 
-    // Read input file
-    pb.set_message("Reading input file...");
-    let file =
-        File::open(input_path).with_context(|| format!("Failed to open input file: {}", input))?;
-    let mut reader = BufReader::new(file);
-
-    // Decode
+    // Decode, resolving referenced assets next to the input
     pb.set_message("Decoding...");
-    let book = decoder
-        .decode(&mut reader)
-        .with_context(|| format!("Failed to decode {}", input))?;
+    let (book, context) = decode_input(input_path, options, &pb)?;
+
+    for diagnostic in context.diagnostics() {
+        pb.suspend(|| diagnostics::log(input_path, &diagnostic));
+    }
 
     tracing::info!(
         "Decoded '{}' with {} chapters",
@@ -57,13 +56,19 @@ pub fn convert(input: &str, output: &str, format: &str) -> Result<()> {
 
     // Encode
     pb.set_message(format!("Encoding to {}...", encoder.format_name()));
+    pb.set_position(0);
     let mut output_file = File::create(output_path)
         .with_context(|| format!("Failed to create output file: {}", output))?;
 
+    let encode_context = EncodeContext::new().with_progress(progress::listener(pb.clone()));
     encoder
-        .encode(&book, &mut output_file)
+        .encode_with_context(&book, &mut output_file, &encode_context)
         .with_context(|| format!("Failed to encode to {}", format))?;
 
+    for diagnostic in encode_context.diagnostics() {
+        pb.suspend(|| diagnostics::log(output_path, &diagnostic));
+    }
+
     pb.finish_with_message(format!(
         "Converted '{}' to {} -> {}",
         book.metadata.title,
@@ -73,3 +78,99 @@ pub fn convert(input: &str, output: &str, format: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Decode an input file, or a Markdown project directory or manifest
+fn decode_input(
+    input_path: &Path,
+    options: &ConvertOptions,
+    pb: &ProgressBar,
+) -> Result<(Book, DecodeContext)> {
+    let input = input_path.display();
+
+    if let Some((root, manifest)) = MarkdownProjectDecoder::project_root(input_path) {
+        if options.decode.pages.is_some() || options.decode.password.is_some() {
+            bail!("--pages and --password are only supported for PDF input");
+        }
+        if !options.split.is_empty() {
+            bail!("--split and --max-chapter-size don't apply to Markdown projects, which have a chapter per file");
+        }
+
+        let context = DecodeContext::new()
+            .with_base_path(root)
+            .with_progress(progress::listener(pb.clone()));
+        let book = MarkdownProjectDecoder::new()
+            .decode_project(&context, manifest)
+            .with_context(|| format!("Failed to decode project {}", input))?;
+        return Ok((book, context));
+    }
+
+    // Get decoder for the detected format
+    let file = read_input(input_path)?;
+    let decoder = decoder_with_options(&file, options)?;
+    let context = DecodeContext::new()
+        .with_base_path(input_path.parent().unwrap_or(Path::new(".")))
+        .with_progress(progress::listener(pb.clone()));
+    context.progress().set_bytes_total(file.data.len() as u64);
+    let mut reader = context.progress().reader(Cursor::new(file.data));
+
+    let book = decoder
+        .decode_with_context(&mut reader, &context)
+        .with_context(|| format!("Failed to decode {}", input))?;
+    Ok((book, context))
+}
+
+/// Get the decoder for an input, applying any decoder options
+fn decoder_with_options(input: &Input, options: &ConvertOptions) -> Result<Box<dyn Decoder>> {
+    let format = input.format;
+
+    // Each group of flags is checked on its own, to name those that don't apply
+    let paging = DecodeOptions {
+        pages: options.decode.pages.clone(),
+        password: options.decode.password.clone(),
+        ..DecodeOptions::default()
+    };
+    let extraction = DecodeOptions {
+        main_content: options.decode.main_content,
+        ..DecodeOptions::default()
+    };
+    for (flags, group) in [
+        ("--pages and --password are", &paging),
+        ("--main-content is", &extraction),
+    ] {
+        if !group.is_empty() && !format.supports_options(group) {
+            bail!(
+                "{} only supported for {} input",
+                flags,
+                formats_supporting(group)
+            );
+        }
+    }
+
+    let mut decoder = format
+        .decoder_with_options(&options.decode)
+        .with_context(|| {
+            format!(
+                "These options can't be combined for {} input",
+                format.name()
+            )
+        })?;
+
+    if !options.split.is_empty() && !decoder.set_split_options(&options.split) {
+        bail!(
+            "--split and --max-chapter-size don't apply to {} input, whose chapters come from the document itself (`bookle formats` lists the formats they apply to)",
+            format.name()
+        );
+    }
+
+    Ok(decoder)
+}
+
+/// Names of the input formats that support every option set in `options`
+fn formats_supporting(options: &DecodeOptions) -> String {
+    let names: Vec<&str> = FormatRegistry::builtin()
+        .decoders()
+        .filter(|entry| entry.supports_options(options))
+        .map(|entry| entry.name())
+        .collect();
+    names.join(" or ")
+}
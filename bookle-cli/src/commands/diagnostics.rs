@@ -0,0 +1,14 @@
+//! Logging of the diagnostics reported while decoding and encoding
+
+use bookle_core::{Diagnostic, Severity};
+use std::path::Path;
+
+/// Log a diagnostic about `file`, showing informational ones only in verbose mode
+pub fn log(file: &Path, diagnostic: &Diagnostic) {
+    match diagnostic.severity {
+        Severity::Info => tracing::debug!("{}: {}", file.display(), diagnostic),
+        Severity::Warning | Severity::Error => {
+            tracing::warn!("{}: {}", file.display(), diagnostic)
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Formats command implementation
+
+use anyhow::Result;
+use bookle_core::FormatRegistry;
+use serde::Serialize;
+
+/// A registered format
+#[derive(Serialize)]
+struct FormatInfo {
+    name: String,
+    extensions: Vec<String>,
+    mime_types: Vec<String>,
+    aliases: Vec<String>,
+    /// Whether the format is recognised from file contents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detected: Option<bool>,
+    /// Whether `--split` and `--max-chapter-size` apply to the format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    splits_chapters: Option<bool>,
+}
+
+/// Registered formats output
+#[derive(Serialize)]
+struct FormatsInfo {
+    input: Vec<FormatInfo>,
+    output: Vec<FormatInfo>,
+}
+
+/// List the formats that can be read and written
+pub fn formats(json: bool) -> Result<()> {
+    let registry = FormatRegistry::builtin();
+    let info = FormatsInfo {
+        input: registry
+            .decoders()
+            .map(|entry| FormatInfo {
+                name: entry.name().to_string(),
+                extensions: entry.extensions().to_vec(),
+                mime_types: entry.mime_types().to_vec(),
+                aliases: entry.aliases().to_vec(),
+                detected: Some(entry.has_probe()),
+                splits_chapters: Some(entry.splits_chapters()),
+            })
+            .collect(),
+        output: registry
+            .encoders()
+            .map(|entry| FormatInfo {
+                name: entry.name().to_string(),
+                extensions: entry.extensions().to_vec(),
+                mime_types: entry.mime_types().to_vec(),
+                aliases: entry.aliases().to_vec(),
+                detected: None,
+                splits_chapters: None,
+            })
+            .collect(),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!("Input formats:");
+        print_formats(&info.input);
+        println!();
+        println!("Output formats (--format):");
+        print_formats(&info.output);
+        println!();
+        println!("* recognised from file contents");
+        println!("+ chapters set with --split and --max-chapter-size");
+    }
+
+    Ok(())
+}
+
+fn print_formats(formats: &[FormatInfo]) {
+    for format in formats {
+        let detected = if format.detected == Some(true) {
+            '*'
+        } else {
+            ' '
+        };
+        let splits = if format.splits_chapters == Some(true) {
+            '+'
+        } else {
+            ' '
+        };
+        let mut names: Vec<String> = format
+            .extensions
+            .iter()
+            .map(|e| format!(".{}", e))
+            .collect();
+        names.extend(format.aliases.iter().cloned());
+        println!(
+            "  {}{}{:<20} {:<32} {}",
+            detected,
+            splits,
+            format.name,
+            names.join(", "),
+            format.mime_types.join(", ")
+        );
+    }
+}
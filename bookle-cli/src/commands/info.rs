@@ -1,10 +1,10 @@
 //! Info command implementation
 
+use super::input::read_input;
 use anyhow::{Context, Result};
-use bookle_core::decoder::decoder_for_extension;
+use bookle_core::decoder::{DecodeContext, MarkdownProjectDecoder};
 use serde::Serialize;
-use std::fs::File;
-use std::io::BufReader;
+use std::io::Cursor;
 use std::path::Path;
 
 /// Book info output
@@ -23,24 +23,19 @@ struct BookInfo {
 pub fn info(input: &str, json: bool) -> Result<()> {
     let input_path = Path::new(input);
 
-    // Get file extension
-    let ext = input_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .context("Could not determine input file extension")?;
-
-    // Get decoder
-    let decoder = decoder_for_extension(ext)
-        .with_context(|| format!("No decoder available for .{} files", ext))?;
-
-    // Read and decode
-    let file =
-        File::open(input_path).with_context(|| format!("Failed to open input file: {}", input))?;
-    let mut reader = BufReader::new(file);
+    let book = if let Some((root, manifest)) = MarkdownProjectDecoder::project_root(input_path) {
+        let context = DecodeContext::new().with_base_path(root);
+        MarkdownProjectDecoder::new()
+            .decode_project(&context, manifest)
+            .with_context(|| format!("Failed to decode project {}", input))?
+    } else {
+        // Read the file and get the decoder for its format
+        let file = read_input(input_path)?;
 
-    let book = decoder
-        .decode(&mut reader)
-        .with_context(|| format!("Failed to decode {}", input))?;
+        file.decoder()
+            .decode(&mut Cursor::new(file.data))
+            .with_context(|| format!("Failed to decode {}", input))?
+    };
 
     let info = BookInfo {
         title: book.metadata.title.clone(),
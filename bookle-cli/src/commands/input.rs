@@ -0,0 +1,73 @@
+//! Input format detection shared by the commands
+
+use anyhow::{bail, Context, Result};
+use bookle_core::decoder::Decoder;
+use bookle_core::registry::DecoderEntry;
+use bookle_core::FormatRegistry;
+use std::fs;
+use std::path::Path;
+
+/// An input file read into memory, with the format that reads it
+pub struct Input {
+    pub data: Vec<u8>,
+    pub format: &'static DecoderEntry,
+}
+
+impl Input {
+    /// Create a decoder for the input's format
+    pub fn decoder(&self) -> Box<dyn Decoder> {
+        self.format.decoder()
+    }
+}
+
+/// Read an input file and work out its format
+///
+/// The format is detected from the file's contents where it can be, so that
+/// misnamed and extensionless files are decoded correctly. Formats without a
+/// signature, such as Markdown, are recognised by their extension.
+pub fn read_input(path: &Path) -> Result<Input> {
+    let registry = FormatRegistry::builtin();
+    let data =
+        fs::read(path).with_context(|| format!("Failed to open input file: {}", path.display()))?;
+    let named = registry.input_extension(path);
+
+    let format = match registry.detect(&data) {
+        Some(format) => {
+            if named.is_some_and(|ext| !format.extensions().contains(&ext.to_lowercase())) {
+                tracing::debug!(
+                    "{} contains {} content, decoding it as such",
+                    path.display(),
+                    format.name()
+                );
+            }
+            format
+        }
+        None => match named {
+            Some(ext) => match registry.decoder_entry_for_extension(&ext) {
+                Some(format) => format,
+                None => bail!("No decoder available for .{} files", ext),
+            },
+            None => bail!("Could not determine the format of {}", path.display()),
+        },
+    };
+    Ok(Input { data, format })
+}
+
+/// Whether a file is in a format that can be decoded, by its extension or,
+/// failing that, its contents
+///
+/// Only the start of the file is read, so scanning a directory of large
+/// files stays cheap.
+pub fn is_supported(path: &Path) -> bool {
+    let registry = FormatRegistry::builtin();
+    if registry
+        .input_extension(path)
+        .is_some_and(|ext| registry.decoder_entry_for_extension(&ext).is_some())
+    {
+        return true;
+    }
+    path.is_file()
+        && fs::File::open(path)
+            .and_then(|mut file| registry.detect_reader(&mut file))
+            .is_ok_and(|format| format.is_some())
+}
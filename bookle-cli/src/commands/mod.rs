@@ -2,10 +2,15 @@
 
 mod batch;
 mod convert;
+mod diagnostics;
+mod formats;
 mod info;
+mod input;
+mod progress;
 mod validate;
 
 pub use batch::batch;
-pub use convert::convert;
+pub use convert::{convert, ConvertOptions};
+pub use formats::formats;
 pub use info::info;
 pub use validate::validate;
@@ -0,0 +1,36 @@
+//! Progress bars driven by the progress that decoders and encoders report
+
+use bookle_core::Progress;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Style for a bar following a single file
+pub fn file_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template("{spinner:.green} {msg} {bar:30.cyan/blue} {pos}/{len}")
+        .unwrap()
+        .progress_chars("##-")
+}
+
+/// Move `bar` along with a decode or encode
+///
+/// Chapters and resources are counted together once either total is known;
+/// until then the bar follows the bytes of input read.
+pub fn listener(bar: ProgressBar) -> impl Fn(&Progress) + Send + Sync + 'static {
+    move |progress| {
+        let (position, length) =
+            if progress.chapters_total.is_some() || progress.resources_total.is_some() {
+                (
+                    progress.chapters + progress.resources,
+                    progress.chapters_total.unwrap_or(0) + progress.resources_total.unwrap_or(0),
+                )
+            } else if let Some(total) = progress.bytes_total {
+                bar.set_length(total);
+                bar.set_position(progress.bytes);
+                return;
+            } else {
+                return;
+            };
+        bar.set_length(length as u64);
+        bar.set_position(position as u64);
+    }
+}
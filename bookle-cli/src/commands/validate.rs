@@ -1,36 +1,39 @@
 //! Validate command implementation
 
-use anyhow::{bail, Context, Result};
-use bookle_core::decoder::decoder_for_extension;
-use std::fs::File;
-use std::io::BufReader;
+use super::input::read_input;
+use anyhow::{bail, Result};
+use bookle_core::decoder::DecodeContext;
+use std::io::Cursor;
 use std::path::Path;
 
 /// Validate an ebook file
 pub fn validate(input: &str, _strict: bool) -> Result<()> {
     let input_path = Path::new(input);
 
-    // Get file extension
-    let ext = input_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .context("Could not determine input file extension")?;
+    // Read the file and get the decoder for its format
+    let file = read_input(input_path)?;
+    let decoder = file.decoder();
+    let kind = file.format.name();
+    let mut reader = Cursor::new(file.data);
 
-    // Get decoder
-    let decoder = decoder_for_extension(ext)
-        .with_context(|| format!("No decoder available for .{} files", ext))?;
+    // Resolve referenced assets next to the input file so missing ones are reported
+    let context =
+        DecodeContext::new().with_base_path(input_path.parent().unwrap_or(Path::new(".")));
 
-    // Try to read and decode
-    let file =
-        File::open(input_path).with_context(|| format!("Failed to open input file: {}", input))?;
-    let mut reader = BufReader::new(file);
-
-    match decoder.decode(&mut reader) {
+    match decoder.decode_with_context(&mut reader, &context) {
         Ok(book) => {
-            println!("Valid {} file", ext.to_uppercase());
+            println!("Valid {} file", kind);
             println!("  Title: {}", book.metadata.title);
             println!("  Chapters: {}", book.chapters.len());
 
+            let diagnostics = context.diagnostics();
+            if !diagnostics.is_empty() {
+                println!("  Diagnostics:");
+                for diagnostic in diagnostics {
+                    println!("    - {}", diagnostic);
+                }
+            }
+
             // TODO: Add more validation checks (strict mode)
             // - Check all resource references are valid
             // - Validate TOC structure
@@ -40,7 +43,7 @@ pub fn validate(input: &str, _strict: bool) -> Result<()> {
             Ok(())
         }
         Err(e) => {
-            eprintln!("Invalid {} file: {}", ext.to_uppercase(), e);
+            eprintln!("Invalid {} file: {}", kind, e);
             bail!("Validation failed for {}", input);
         }
     }
@@ -3,7 +3,9 @@
 mod commands;
 
 use anyhow::Result;
+use bookle_core::decoder::{DecodeOptions, SplitOptions, SplitStrategy};
 use clap::{Parser, Subcommand};
+use std::ops::RangeInclusive;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 /// Parse and validate jobs argument (must be at least 1)
@@ -18,6 +20,39 @@ fn parse_jobs(s: &str) -> Result<usize, String> {
     }
 }
 
+/// Parse a 1-based page range such as `10-45`, `10-` or `7`
+fn parse_page_range(s: &str) -> Result<RangeInclusive<u32>, String> {
+    let page = |p: &str| -> Result<u32, String> {
+        match p.trim().parse() {
+            Ok(0) => Err("page numbers start at 1".to_string()),
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("'{}' is not a valid page number", p)),
+        }
+    };
+
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (
+            if start.trim().is_empty() {
+                1
+            } else {
+                page(start)?
+            },
+            if end.trim().is_empty() {
+                u32::MAX
+            } else {
+                page(end)?
+            },
+        ),
+        None => (page(s)?, page(s)?),
+    };
+
+    if start > end {
+        Err(format!("page range '{}' ends before it starts", s))
+    } else {
+        Ok(start..=end)
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "bookle")]
 #[command(author, version, about, long_about = None)]
@@ -34,21 +69,41 @@ struct Cli {
 enum Commands {
     /// Convert an ebook to another format
     Convert {
-        /// Input file path
+        /// Input file, Markdown project directory, or project manifest
         input: String,
 
         /// Output file path
         #[arg(short, long)]
         output: String,
 
-        /// Output format (epub, pdf)
+        /// Output format (epub, kepub, pdf; see `formats`)
         #[arg(short, long, default_value = "epub")]
         format: String,
+
+        /// Pages to convert from PDF input, e.g. 10-45
+        #[arg(long, value_parser = parse_page_range)]
+        pages: Option<RangeInclusive<u32>>,
+
+        /// Password for encrypted PDF input
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Where to start chapters: h1-h6, hr, page, none or regex:<pattern>
+        #[arg(long, value_name = "STRATEGY")]
+        split: Option<SplitStrategy>,
+
+        /// Split chapters longer than this many characters of text
+        #[arg(long, value_name = "CHARS")]
+        max_chapter_size: Option<usize>,
+
+        /// Keep only the main article content of HTML input
+        #[arg(long)]
+        main_content: bool,
     },
 
     /// Display information about an ebook
     Info {
-        /// Input file path
+        /// Input file, Markdown project directory, or project manifest
         input: String,
 
         /// Output as JSON
@@ -75,7 +130,7 @@ enum Commands {
         #[arg(short, long)]
         output_dir: String,
 
-        /// Output format (epub, pdf)
+        /// Output format (epub, kepub, pdf; see `formats`)
         #[arg(short, long, default_value = "epub")]
         format: String,
 
@@ -83,6 +138,13 @@ enum Commands {
         #[arg(short, long, default_value = "4", value_parser = parse_jobs)]
         jobs: usize,
     },
+
+    /// List the formats that can be read and written
+    Formats {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[tokio::main]
@@ -106,7 +168,25 @@ async fn main() -> Result<()> {
             input,
             output,
             format,
-        } => commands::convert(&input, &output, &format),
+            pages,
+            password,
+            split,
+            max_chapter_size,
+            main_content,
+        } => {
+            let options = commands::ConvertOptions {
+                decode: DecodeOptions {
+                    pages,
+                    password,
+                    main_content,
+                },
+                split: SplitOptions {
+                    strategy: split,
+                    max_chapter_size,
+                },
+            };
+            commands::convert(&input, &output, &format, &options)
+        }
 
         Commands::Info { input, json } => commands::info(&input, json),
 
@@ -118,5 +198,7 @@ async fn main() -> Result<()> {
             format,
             jobs,
         } => commands::batch(&input_dir, &output_dir, &format, jobs),
+
+        Commands::Formats { json } => commands::formats(json),
     }
 }
@@ -169,6 +169,37 @@ fn test_batch_negative_jobs() {
     .failure();
 }
 
+#[test]
+fn test_convert_invalid_page_range() {
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert", "book.pdf", "--output", "out.epub", "--pages", "45-10",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("ends before it starts"));
+}
+
+#[test]
+fn test_convert_pages_requires_pdf() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(&temp_dir, "test.md", "# Test\n\nContent");
+    let output = temp_dir.path().join("output.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--pages",
+        "1-2",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("only supported for PDF"));
+}
+
 #[test]
 fn test_convert_unsupported_format() {
     let temp_dir = TempDir::new().unwrap();
@@ -252,6 +283,95 @@ fn test_convert_markdown_to_typst() {
     );
 }
 
+#[test]
+fn test_convert_markdown_project_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let project = temp_dir.path().join("book");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(
+        project.join("src/SUMMARY.md"),
+        "# Summary\n\n- [One](one.md)\n- [Two](two.md)\n",
+    )
+    .unwrap();
+    fs::write(project.join("src/one.md"), "# One\n\nSee [two](two.md).\n").unwrap();
+    fs::write(project.join("src/two.md"), "# Two\n\nThe end.\n").unwrap();
+    fs::write(
+        project.join("book.toml"),
+        "[book]\ntitle = \"Project Book\"\n",
+    )
+    .unwrap();
+    let output = temp_dir.path().join("output.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        project.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+    ])
+    .assert()
+    .success();
+
+    assert!(output.exists(), "Output file should be created");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["info", project.join("book.toml").to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Project Book"))
+        .stdout(predicate::str::contains("Chapters:    2"));
+}
+
+#[test]
+fn test_convert_split_strategy() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "test.md",
+        "# Book\n\n## One\n\nFirst.\n\n## Two\n\nSecond.\n",
+    );
+    let output = temp_dir.path().join("output.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--split",
+        "h2",
+    ])
+    .assert()
+    .success();
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--split",
+        "h9",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("unknown split strategy"));
+
+    // EPUB chapters come from the spine, so they can't be split differently
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        output.to_str().unwrap(),
+        "-o",
+        temp_dir.path().join("again.epub").to_str().unwrap(),
+        "--split",
+        "h2",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("don't apply to EPUB input"));
+}
+
 #[test]
 fn test_info_markdown() {
     let temp_dir = TempDir::new().unwrap();
@@ -302,6 +422,24 @@ fn test_validate_markdown() {
         .success();
 }
 
+#[test]
+fn test_validate_reports_missing_image() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "test.md",
+        "# Book\n\n![Figure](images/missing.png)\n",
+    );
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["validate", input.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Missing asset: images/missing.png",
+        ));
+}
+
 #[test]
 fn test_batch_empty_directory() {
     let temp_dir = TempDir::new().unwrap();
@@ -361,3 +499,310 @@ fn test_verbose_flag() {
         .assert()
         .success();
 }
+
+#[test]
+fn test_info_plain_text() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "story.txt",
+        "CHAPTER I\n\nIt began on a Tuesday.\n\nCHAPTER II\n\nIt ended on a Wednesday.\n",
+    );
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["info", input.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"Chapters:\s+2\n").unwrap());
+}
+
+#[test]
+fn test_convert_html_main_content() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "article.html",
+        r#"<html><head><title>An Article</title></head><body>
+<nav><a href="/">Home</a></nav>
+<div class="post"><p>The body of the article, which is long enough to be counted as content.</p></div>
+</body></html>"#,
+    );
+    let output = temp_dir.path().join("article.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--main-content",
+    ])
+    .assert()
+    .success();
+    assert!(output.exists());
+}
+
+#[test]
+fn test_convert_main_content_requires_html() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(&temp_dir, "test.md", "# Title\n\nText\n");
+    let output = temp_dir.path().join("test.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--main-content",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("only supported for HTML input"));
+}
+
+#[test]
+fn test_info_fictionbook() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "story.fb2",
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0">
+  <description><title-info><book-title>Short Story</book-title><lang>en</lang></title-info></description>
+  <body><section><title><p>One</p></title><p>The only chapter.</p></section></body>
+</FictionBook>"#,
+    );
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["info", input.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Short Story"));
+}
+
+#[test]
+fn test_convert_cbz_to_epub() {
+    use std::io::{Read, Write};
+
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("issue.cbz");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&input).unwrap());
+    for (name, data) in [
+        ("ComicInfo.xml", &b"<ComicInfo><Series>Akira</Series><Number>1</Number><Manga>YesAndRightToLeft</Manga></ComicInfo>"[..]),
+        ("page10.png", b"\x89PNG\r\n\x1a\n10"),
+        ("page2.png", b"\x89PNG\r\n\x1a\n2"),
+    ] {
+        writer
+            .start_file(name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap();
+    let output = temp_dir.path().join("issue.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+    ])
+    .assert()
+    .success();
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&output).unwrap()).unwrap();
+    let mut opf = String::new();
+    archive
+        .by_name("OEBPS/content.opf")
+        .unwrap()
+        .read_to_string(&mut opf)
+        .unwrap();
+    assert!(opf.contains("Akira #1"));
+    assert!(opf.contains("page-progression-direction=\"rtl\""));
+    assert!(opf.contains("href=\"images/page2.png\""));
+}
+
+#[test]
+fn test_info_xml_sniffs_root_element() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "thesis.xml",
+        r#"<TEI xmlns="http://www.tei-c.org/ns/1.0"><teiHeader><fileDesc>
+<titleStmt><title>On Marginalia</title></titleStmt></fileDesc></teiHeader>
+<text><body><div><head>One</head><p>Text.</p></div><div><head>Two</head><p>More.</p></div></body></text></TEI>"#,
+    );
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["info", input.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("On Marginalia"))
+        .stdout(predicate::str::contains("Chapters:    2"));
+}
+
+#[test]
+fn test_info_detects_format_from_contents() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "test.md",
+        "# Sniffed Book\n\nIntro.\n\n# Chapter 1\n\nContent here.",
+    );
+    let epub = temp_dir.path().join("book.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "--output",
+        epub.to_str().unwrap(),
+    ])
+    .assert()
+    .success();
+
+    // An upload without an extension, and a file with the wrong one
+    for name in ["upload", "book.pdf"] {
+        let renamed = temp_dir.path().join(name);
+        fs::copy(&epub, &renamed).unwrap();
+
+        let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+        cmd.args(["info", renamed.to_str().unwrap()])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Sniffed Book"));
+
+        let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+        cmd.args(["validate", renamed.to_str().unwrap()])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Valid EPUB file"));
+    }
+}
+
+#[test]
+fn test_batch_detects_extensionless_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(&input_dir).unwrap();
+
+    let source = create_test_markdown(&temp_dir, "source.md", "# Sniffed Book\n\nContent.");
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        source.to_str().unwrap(),
+        "--output",
+        input_dir.join("upload").to_str().unwrap(),
+        "--format",
+        "epub",
+    ])
+    .assert()
+    .success();
+    fs::write(input_dir.join("notes"), "Nothing to recognise here.").unwrap();
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "batch",
+        input_dir.to_str().unwrap(),
+        "--output-dir",
+        output_dir.to_str().unwrap(),
+        "--format",
+        "typst",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Found 1 files to convert"));
+}
+
+#[test]
+fn test_convert_rst_with_split() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "handbook.rst",
+        "Handbook\n########\n\nOne\n===\n\nFirst.\n\nDetail\n------\n\nMore.\n\nTwo\n===\n\nSecond.\n",
+    );
+    let output = temp_dir.path().join("handbook.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "--split",
+        "h2",
+    ])
+    .assert()
+    .success();
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["info", output.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Handbook"))
+        .stdout(predicate::str::contains("Chapters:    3"));
+}
+
+#[test]
+fn test_formats_lists_registered_formats() {
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.arg("formats")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("reStructuredText"))
+        .stdout(predicate::str::contains(".kepub.epub"))
+        .stdout(predicate::str::contains("Output formats"));
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    let output = cmd.args(["formats", "--json"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["input"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|format| format["name"] == "EPUB" && format["detected"] == true));
+    assert!(json["input"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|format| format["name"] == "Org" && format["splits_chapters"] == true));
+    assert!(json["output"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|format| format["aliases"][0] == "pdf"));
+}
+
+#[test]
+fn test_diagnostics_reported_by_validate_and_verbose_convert() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = create_test_markdown(
+        &temp_dir,
+        "page.html",
+        "<h1>Notice</h1><p>Read <blink>this</blink> now</p>",
+    );
+    let output = temp_dir.path().join("page.epub");
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args(["validate", input.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "info[unknown-element]: Kept <blink> as plain text",
+        ));
+
+    let mut cmd = Command::cargo_bin("bookle-cli").unwrap();
+    cmd.args([
+        "convert",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "-v",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("info[unknown-element]"));
+}
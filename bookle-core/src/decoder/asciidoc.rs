@@ -0,0 +1,2460 @@
+//! AsciiDoc decoder implementation
+//!
+//! Covers the commonly used subset of AsciiDoc: the document header (title,
+//! author and revision lines, attribute entries), section titles, paragraphs and
+//! lists, delimited blocks (listing, literal, quote, verse, example, sidebar and
+//! open blocks), admonitions, tables, block and inline images, footnotes and
+//! cross references. `include::` directives and `ifdef`/`ifndef` conditionals
+//! are resolved before parsing, with included files read through the decode
+//! context relative to the file that includes them.
+
+use super::chapters::{place_footnotes, ChapterSplitter, SplitOptions, SplitStrategy};
+use super::context::{resolve_link, AssetLoader};
+use super::front_matter::FrontMatter;
+use super::markup::{bare_url, dedent, default_alt, MAX_INCLUDE_DEPTH};
+use super::text::decode_bytes;
+use super::xml::trim_inlines;
+use super::DecodeContext;
+use crate::diagnostic::Diagnostic;
+use crate::error::ParseError;
+use crate::types::{
+    AdmonitionKind, Block, Book, DefinitionItem, Inline, Metadata, ResourceStore, TableCell,
+    TableData,
+};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::sync::OnceLock;
+
+/// Schemes of URLs that are links without any markup
+const URL_SCHEMES: &[&str] = &["https://", "http://", "ftp://", "irc://"];
+
+/// Decoder for AsciiDoc documents
+pub struct AsciiDocDecoder {
+    /// Where the document is divided into chapters
+    splitter: ChapterSplitter,
+}
+
+impl AsciiDocDecoder {
+    pub fn new() -> Self {
+        Self {
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Set where the document is divided into chapters (`==` sections by default)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+}
+
+impl Default for AsciiDocDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for AsciiDocDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read AsciiDoc: {}", e)))?;
+        let (text, _) = decode_bytes(&data);
+
+        let mut preprocessor = Preprocessor {
+            context,
+            defined: HashSet::new(),
+        };
+        let mut lines = Vec::new();
+        preprocessor.expand(&text, "", 0, &mut lines);
+
+        let mut parser = Parser::new(lines);
+        let title = parser.header();
+        let mut blocks = parser.blocks(None);
+
+        let title = title
+            .or_else(|| super::MarkdownDecoder::extract_title(&blocks))
+            .unwrap_or_else(|| "Untitled".to_string());
+        let mut metadata = Metadata::new(title, "en");
+        FrontMatter::from_fields(parser.metadata_fields()).apply_to(&mut metadata);
+
+        // Load images and the cover, pointing references at the stored resources
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        if let Some(dir) = parser.attributes.get("imagesdir") {
+            loader.set_dir(dir.trim_end_matches('/'));
+        }
+        loader.load_images(&mut blocks);
+        if let Some(cover) = &metadata.cover_resource_key {
+            metadata.cover_resource_key = loader.load(cover);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+        let mut chapters = self.splitter.split(blocks);
+        place_footnotes(&mut chapters, parser.footnotes);
+        for chapter in chapters {
+            book.add_chapter(chapter);
+        }
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["adoc", "asciidoc", "asc"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/asciidoc", "text/x-asciidoc"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// Resolves includes and conditionals line by line
+struct Preprocessor<'a> {
+    context: &'a DecodeContext,
+    /// Attributes defined so far, for `ifdef` and `ifndef`
+    defined: HashSet<String>,
+}
+
+impl Preprocessor<'_> {
+    /// Append the lines of `text`, read from directory `dir`, to `out`
+    fn expand(&mut self, text: &str, dir: &str, depth: usize, out: &mut Vec<String>) {
+        // Whether each enclosing conditional is satisfied
+        let mut conditions: Vec<bool> = Vec::new();
+
+        for line in text.lines() {
+            if let Some((kind, target, content)) = conditional(line) {
+                if kind == "endif" {
+                    conditions.pop();
+                    continue;
+                }
+                let satisfied = match kind {
+                    "ifdef" => self.is_defined(target),
+                    "ifndef" => !self.is_defined(target),
+                    // Expressions are not evaluated; their content is kept
+                    _ => true,
+                };
+                match content {
+                    Some(content) => {
+                        if satisfied && conditions.iter().all(|c| *c) {
+                            out.push(content.to_string());
+                        }
+                    }
+                    None => conditions.push(satisfied),
+                }
+                continue;
+            }
+            if !conditions.iter().all(|c| *c) {
+                continue;
+            }
+
+            if let Some((target, attributes)) = include_directive(line) {
+                self.include(target, attributes, dir, depth, out);
+                continue;
+            }
+
+            if let Some((name, value)) = attribute_entry(line) {
+                match value {
+                    Some(_) => self.defined.insert(name.to_string()),
+                    None => self.defined.remove(name),
+                };
+            }
+            out.push(line.to_string());
+        }
+    }
+
+    /// Whether `ifdef` attributes are set: any of `a,b`, or all of `a+b`
+    fn is_defined(&self, names: &str) -> bool {
+        if names.contains('+') {
+            names
+                .split('+')
+                .all(|name| self.defined.contains(name.trim()))
+        } else {
+            names
+                .split(',')
+                .any(|name| self.defined.contains(name.trim()))
+        }
+    }
+
+    /// Append the selected lines of an included file
+    fn include(
+        &mut self,
+        target: &str,
+        attributes: &str,
+        dir: &str,
+        depth: usize,
+        out: &mut Vec<String>,
+    ) {
+        let Some(path) = resolve_link(dir, target).filter(|_| depth < MAX_INCLUDE_DEPTH) else {
+            self.context.report(
+                Diagnostic::warning("skipped-include", format!("Skipped include of {}", target))
+                    .with_path(target),
+            );
+            return;
+        };
+        let data = match self.context.read_asset(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                self.context.report(
+                    Diagnostic::warning(
+                        "unresolved-include",
+                        format!("Unresolved include {}: {}", target, e),
+                    )
+                    .with_path(path.clone()),
+                );
+                return;
+            }
+        };
+        let (text, _) = decode_bytes(&data);
+        let options = parse_attribute_list(attributes);
+        let text = select_lines(&text, &options.named);
+
+        let included_dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let mut lines = Vec::new();
+        self.expand(&text, included_dir, depth + 1, &mut lines);
+
+        // Shift section levels by `leveloffset`, such as +1 for a chapter
+        // included into a part
+        let offset: i32 = options
+            .named
+            .get("leveloffset")
+            .and_then(|offset| offset.trim_start_matches('+').parse().ok())
+            .unwrap_or(0);
+        for line in lines {
+            out.push(match section_title(&line) {
+                Some((level, title)) if offset != 0 => {
+                    let level = (level as i32 + offset).clamp(0, 5) as usize;
+                    format!("{} {}", "=".repeat(level + 1), title)
+                }
+                _ => line,
+            });
+        }
+    }
+}
+
+/// Lines of an included file chosen by its `lines` or `tag`/`tags` option
+fn select_lines(text: &str, options: &HashMap<String, String>) -> String {
+    if let Some(ranges) = options.get("lines") {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut selected = Vec::new();
+        for range in ranges.split([';', ',']) {
+            let (start, end) = match range.split_once("..") {
+                Some((start, end)) => (
+                    start.trim().parse().unwrap_or(1),
+                    end.trim().parse().unwrap_or(lines.len()),
+                ),
+                None => {
+                    let line = range.trim().parse().unwrap_or(0);
+                    (line, line)
+                }
+            };
+            let end = end.min(lines.len());
+            if start >= 1 && start <= end {
+                selected.extend_from_slice(&lines[start - 1..end]);
+            }
+        }
+        return selected.join("\n");
+    }
+
+    let Some(tags) = options.get("tag").or_else(|| options.get("tags")) else {
+        return text.to_string();
+    };
+    let tags: Vec<&str> = tags.split([';', ',']).map(str::trim).collect();
+    let mut active: Vec<&str> = Vec::new();
+    let mut selected = Vec::new();
+    for line in text.lines() {
+        if let Some(name) = tag_marker(line, "tag::") {
+            if tags.contains(&name) {
+                active.push(name);
+            }
+            continue;
+        }
+        if let Some(name) = tag_marker(line, "end::") {
+            active.retain(|tag| *tag != name);
+            continue;
+        }
+        if !active.is_empty() {
+            selected.push(line);
+        }
+    }
+    selected.join("\n")
+}
+
+/// Name in a `tag::name[]` or `end::name[]` marker, usually within a comment
+fn tag_marker<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = line.find(prefix)? + prefix.len();
+    let rest = &line[start..];
+    let end = rest.find("[]")?;
+    Some(&rest[..end])
+}
+
+/// A preprocessor conditional: its kind, target and any single-line content
+fn conditional(line: &str) -> Option<(&str, &str, Option<&str>)> {
+    let line = line.trim_end();
+    let (kind, rest) = line.split_once("::")?;
+    if !matches!(kind, "ifdef" | "ifndef" | "ifeval" | "endif") {
+        return None;
+    }
+    let open = rest.find('[')?;
+    let content = rest[open + 1..].strip_suffix(']')?;
+    Some((
+        kind,
+        &rest[..open],
+        (!content.is_empty() && kind != "ifeval").then_some(content),
+    ))
+}
+
+/// Target and attribute list of an `include::target[attributes]` line
+fn include_directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim_end().strip_prefix("include::")?;
+    let open = rest.find('[')?;
+    let attributes = rest[open + 1..].strip_suffix(']')?;
+    Some((&rest[..open], attributes))
+}
+
+/// Name and value of an attribute entry such as `:name: value`
+///
+/// The value is `None` for entries that unset the attribute (`:name!:`).
+fn attribute_entry(line: &str) -> Option<(&str, Option<&str>)> {
+    static ENTRY: OnceLock<Regex> = OnceLock::new();
+    let entry = ENTRY.get_or_init(|| Regex::new(r"^:(!?)(\w[\w-]*)(!?):(?:\s+(.*))?$").unwrap());
+    let captures = entry.captures(line.trim_end())?;
+    let name = captures.get(2)?.as_str();
+    if !captures[1].is_empty() || !captures[3].is_empty() {
+        return Some((name, None));
+    }
+    Some((name, Some(captures.get(4).map_or("", |m| m.as_str()))))
+}
+
+/// Level (0 for `=`) and text of a section title
+fn section_title(line: &str) -> Option<(usize, &str)> {
+    let marker = line.chars().next().filter(|c| *c == '=' || *c == '#')?;
+    let level = line.chars().take_while(|c| *c == marker).count();
+    let rest = &line[level..];
+    if level > 6 || !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches(marker).trim_end();
+    (!title.is_empty()).then_some((level - 1, title))
+}
+
+/// Id generated for a section without an explicit one, as Asciidoctor does
+fn section_id(title: &str) -> String {
+    let mut id = String::from("_");
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            id.extend(c.to_lowercase());
+        } else if !id.ends_with('_') {
+            id.push('_');
+        }
+    }
+    if id.len() > 1 {
+        id.trim_end_matches('_').to_string()
+    } else {
+        id
+    }
+}
+
+/// Attributes given to a block by the `[...]` line above it
+#[derive(Debug, Default)]
+struct BlockAttributes {
+    /// Title from a `.Title` line
+    title: Option<String>,
+    id: Option<String>,
+    /// Positional attributes; the first is the block style
+    positional: Vec<String>,
+    named: HashMap<String, String>,
+    roles: Vec<String>,
+    options: Vec<String>,
+}
+
+impl BlockAttributes {
+    fn style(&self) -> Option<&str> {
+        self.positional
+            .first()
+            .map(String::as_str)
+            .filter(|style| !style.is_empty())
+    }
+
+    fn has_option(&self, option: &str) -> bool {
+        self.options.iter().any(|o| o == option)
+            || self
+                .named
+                .get("options")
+                .or_else(|| self.named.get("opts"))
+                .is_some_and(|options| options.split(',').any(|o| o.trim() == option))
+    }
+
+    /// Add the attributes of a further `[...]` line
+    fn merge(&mut self, other: BlockAttributes) {
+        if other.id.is_some() {
+            self.id = other.id;
+        }
+        if !other.positional.is_empty() {
+            self.positional = other.positional;
+        }
+        self.named.extend(other.named);
+        self.roles.extend(other.roles);
+        self.options.extend(other.options);
+    }
+}
+
+/// Parse an attribute list such as `source,rust` or `cols="1,2",options=header`
+///
+/// The first positional attribute may use the shorthand `style#id.role%option`.
+fn parse_attribute_list(list: &str) -> BlockAttributes {
+    let mut attributes = BlockAttributes::default();
+    for (index, item) in split_attributes(list).into_iter().enumerate() {
+        match item.split_once('=') {
+            Some((name, value)) if !name.trim().contains(' ') && !name.trim().is_empty() => {
+                attributes
+                    .named
+                    .insert(name.trim().to_string(), unquote(value.trim()).to_string());
+            }
+            _ if index == 0 => {
+                let mut style = String::new();
+                let mut part = String::new();
+                let mut kind = ' ';
+                for c in item.chars().chain(std::iter::once('#')) {
+                    if matches!(c, '#' | '.' | '%') {
+                        match kind {
+                            ' ' => style = std::mem::take(&mut part),
+                            '#' => attributes.id = Some(std::mem::take(&mut part)),
+                            '.' => attributes.roles.push(std::mem::take(&mut part)),
+                            _ => attributes.options.push(std::mem::take(&mut part)),
+                        }
+                        kind = c;
+                    } else {
+                        part.push(c);
+                    }
+                }
+                attributes.id = attributes.id.filter(|id| !id.is_empty());
+                attributes.positional.push(style);
+            }
+            _ => attributes.positional.push(unquote(&item).to_string()),
+        }
+    }
+    attributes
+}
+
+/// Split an attribute list at commas outside quotes
+fn split_attributes(list: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    for c in list.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (c, Some(q)) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (',', None) => items.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() || !items.is_empty() {
+        items.push(current.trim().to_string());
+    }
+    items
+}
+
+/// A value without its surrounding quotes
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Kind and marker of a list item line, with the item's text
+fn list_item(line: &str) -> Option<(String, &str)> {
+    static ITEM: OnceLock<Regex> = OnceLock::new();
+    static TERM: OnceLock<Regex> = OnceLock::new();
+    let item = ITEM.get_or_init(|| {
+        Regex::new(r"^\s*(\*{1,5}|-|\.{1,5}|\d+\.|[a-zA-Z]\.|[ivxIVX]+\))\s+(.*)$").unwrap()
+    });
+    if let Some(captures) = item.captures(line) {
+        let marker = captures.get(1)?.as_str();
+        // Numbered markers of any number belong to the same list
+        let marker = if marker.ends_with('.') && !marker.starts_with('.') {
+            if marker.as_bytes()[0].is_ascii_digit() {
+                "1.".to_string()
+            } else {
+                "a.".to_string()
+            }
+        } else if marker.ends_with(')') {
+            "i)".to_string()
+        } else {
+            marker.to_string()
+        };
+        return Some((marker, captures.get(2)?.as_str()));
+    }
+
+    let term = TERM.get_or_init(|| Regex::new(r"^\s*(\S.*?)(:{2,4}|;;)(?:\s+(.*))?$").unwrap());
+    let captures = term.captures(line)?;
+    if line.contains("::[") || captures[1].ends_with(':') {
+        return None;
+    }
+    Some((
+        captures[2].to_string(),
+        line.trim_start(), // the term is split off by the caller
+    ))
+}
+
+/// Whether a list marker introduces definition list items
+fn is_term_marker(marker: &str) -> bool {
+    marker.starts_with("::") || marker == ";;"
+}
+
+/// Split a definition list line into its term and any text after the marker
+fn split_term<'a>(line: &'a str, marker: &str) -> (&'a str, &'a str) {
+    match line.find(&format!("{} ", marker)) {
+        Some(index) => (&line[..index], line[index + marker.len()..].trim()),
+        None => (line.trim_end().strip_suffix(marker).unwrap_or(line), ""),
+    }
+}
+
+/// Block delimiters, whose closing line must match the opening one
+fn is_delimiter(line: &str) -> bool {
+    if line == "--" || line.starts_with("```") {
+        return true;
+    }
+    if line.len() >= 4 {
+        let first = line.chars().next().unwrap_or_default();
+        if matches!(first, '-' | '.' | '_' | '=' | '*' | '+' | '/')
+            && line.chars().all(|c| c == first)
+        {
+            return true;
+        }
+    }
+    matches!(line, "|===" | ",===" | ":===" | "!===")
+}
+
+/// Parser for the body of a preprocessed document
+struct Parser {
+    lines: Vec<String>,
+    pos: usize,
+    /// Document attributes, for `{name}` references and metadata
+    attributes: HashMap<String, String>,
+    /// Section titles by id, for the text of cross references
+    titles: HashMap<String, String>,
+    /// Section ids by title, for references such as `<<Section Title>>`
+    ids: HashMap<String, String>,
+    /// Footnotes defined inline, in order
+    footnotes: Vec<(String, Block)>,
+    /// Images found inline, placed after the paragraph that holds them
+    inline_images: Vec<Block>,
+}
+
+impl Parser {
+    fn new(lines: Vec<String>) -> Self {
+        let mut parser = Self {
+            lines,
+            pos: 0,
+            attributes: HashMap::new(),
+            titles: HashMap::new(),
+            ids: HashMap::new(),
+            footnotes: Vec::new(),
+            inline_images: Vec::new(),
+        };
+        parser.collect_sections();
+        parser
+    }
+
+    /// Record the id and title of every section, so references can be
+    /// resolved before the section is reached
+    fn collect_sections(&mut self) {
+        let mut anchor: Option<String> = None;
+        let mut in_block: Option<&str> = None;
+        for line in &self.lines {
+            let line = line.trim_end();
+            if let Some(delimiter) = in_block {
+                if line == delimiter {
+                    in_block = None;
+                }
+                continue;
+            }
+            if is_delimiter(line) {
+                in_block = Some(line);
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                anchor = block_anchor(line).or(anchor);
+                continue;
+            }
+            if let Some((_, title)) = section_title(line) {
+                let (inline_id, title) = title_anchor(title);
+                let id = anchor
+                    .take()
+                    .or(inline_id)
+                    .unwrap_or_else(|| section_id(&title));
+                self.ids.insert(title.clone(), id.clone());
+                self.titles.insert(id, title);
+            }
+            if !line.is_empty() {
+                anchor = None;
+            }
+        }
+    }
+
+    /// Parse the document header, returning the document title
+    fn header(&mut self) -> Option<String> {
+        while let Some(line) = self.lines.get(self.pos) {
+            let line = line.trim_end();
+            if line.is_empty() || (line.starts_with("//") && !line.starts_with("////")) {
+                self.pos += 1;
+            } else if let Some((name, value)) = attribute_entry(line) {
+                self.set_attribute(name.to_string(), value.map(str::to_string));
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let title = match self.lines.get(self.pos).and_then(|l| section_title(l)) {
+            Some((0, title)) => title.to_string(),
+            _ => return self.attributes.get("doctitle").cloned(),
+        };
+        self.pos += 1;
+        self.attributes
+            .insert("doctitle".to_string(), title.clone());
+
+        // Author and revision lines follow the title directly
+        let mut header_lines = 0;
+        while let Some(line) = self.lines.get(self.pos) {
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("//") {
+                self.pos += 1;
+            } else if let Some((name, value)) = attribute_entry(&line) {
+                self.set_attribute(name.to_string(), value.map(str::to_string));
+                self.pos += 1;
+            } else if header_lines == 0 {
+                let authors: Vec<String> = line
+                    .split(';')
+                    .map(|author| {
+                        author
+                            .split('<')
+                            .next()
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string()
+                    })
+                    .filter(|author| !author.is_empty())
+                    .collect();
+                self.attributes
+                    .entry("author".to_string())
+                    .or_insert_with(|| authors.join("; "));
+                header_lines += 1;
+                self.pos += 1;
+            } else if header_lines == 1 {
+                // v1.0, 2020-01-01: Remark
+                let (revision, remark) = line.split_once(':').unwrap_or((line.as_str(), ""));
+                let (number, date) = match revision.split_once(',') {
+                    Some((number, date)) => (Some(number.trim()), date.trim()),
+                    None if revision.trim_start().starts_with(['v', 'V']) => {
+                        (Some(revision.trim()), "")
+                    }
+                    None => (None, revision.trim()),
+                };
+                if let Some(number) = number {
+                    let number = number.trim_start_matches(['v', 'V']).to_string();
+                    self.attributes.insert("revnumber".to_string(), number);
+                }
+                if !date.is_empty() {
+                    self.attributes
+                        .insert("revdate".to_string(), date.to_string());
+                }
+                if !remark.trim().is_empty() {
+                    self.attributes
+                        .insert("revremark".to_string(), remark.trim().to_string());
+                }
+                header_lines += 1;
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        Some(title)
+    }
+
+    /// Set or unset a document attribute
+    fn set_attribute(&mut self, name: String, value: Option<String>) {
+        match value {
+            Some(value) => {
+                let value = self.substitute(&value);
+                self.attributes.insert(name, value);
+            }
+            None => {
+                self.attributes.remove(&name);
+            }
+        }
+    }
+
+    /// Metadata fields from the document attributes, in front matter form
+    fn metadata_fields(&self) -> Map<String, Value> {
+        let mut fields = Map::new();
+        let mut set = |key: &str, value: Value| {
+            fields.insert(key.to_string(), value);
+        };
+        let list = |value: &str, separators: &[char]| {
+            Value::Array(
+                value
+                    .split(separators)
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            )
+        };
+
+        if let Some(title) = self.attributes.get("doctitle") {
+            set("title", Value::String(title.clone()));
+        }
+        if let Some(authors) = self
+            .attributes
+            .get("authors")
+            .or_else(|| self.attributes.get("author"))
+        {
+            set("author", list(authors, &[';', ',']));
+        }
+        for (attribute, key) in [
+            ("revdate", "date"),
+            ("description", "description"),
+            ("lang", "lang"),
+            ("publisher", "publisher"),
+            ("copyright", "rights"),
+            ("isbn", "identifier"),
+        ] {
+            if let Some(value) = self.attributes.get(attribute) {
+                set(key, Value::String(value.clone()));
+            }
+        }
+        if let Some(keywords) = self.attributes.get("keywords") {
+            set("keywords", list(keywords, &[',']));
+        }
+        let cover = ["front-cover-image", "cover-image", "epub-cover-image"]
+            .iter()
+            .find_map(|name| self.attributes.get(*name));
+        if let Some(cover) = cover {
+            // The value may be an image macro, `image:cover.jpg[]`
+            let target = cover
+                .strip_prefix("image:")
+                .map(|rest| rest.trim_start_matches(':'))
+                .and_then(|rest| rest.split('[').next())
+                .unwrap_or(cover);
+            set("cover", Value::String(target.to_string()));
+        }
+        fields
+    }
+
+    /// Parse blocks up to the closing `delimiter`, or the end of the document
+    fn blocks(&mut self, delimiter: Option<&str>) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if Some(line.trim_end()) == delimiter {
+                self.pos += 1;
+                break;
+            }
+            blocks.extend(self.next_block(delimiter));
+        }
+        blocks
+    }
+
+    /// Parse the next block, with any attribute and title lines above it
+    fn next_block(&mut self, enclosing: Option<&str>) -> Vec<Block> {
+        let mut attributes = BlockAttributes::default();
+        while let Some(line) = self.lines.get(self.pos) {
+            let line = line.trim_end().to_string();
+            if Some(line.as_str()) == enclosing {
+                return vec![];
+            }
+            if line.is_empty() {
+                self.pos += 1;
+                continue;
+            }
+            if line.starts_with("////") && line.chars().all(|c| c == '/') {
+                self.pos += 1;
+                self.raw_lines(&line);
+                continue;
+            }
+            if line.starts_with("//") && !line.starts_with("///") {
+                self.pos += 1;
+                continue;
+            }
+            if let Some((name, value)) = attribute_entry(&line) {
+                self.set_attribute(name.to_string(), value.map(str::to_string));
+                self.pos += 1;
+                continue;
+            }
+            if line.starts_with("[[") && line.ends_with("]]") {
+                let id = &line[2..line.len() - 2];
+                attributes.id = Some(id.split(',').next().unwrap_or(id).to_string());
+                self.pos += 1;
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') && !line.starts_with("[[") {
+                attributes.merge(parse_attribute_list(&line[1..line.len() - 1]));
+                self.pos += 1;
+                continue;
+            }
+            if let Some(title) = line.strip_prefix('.') {
+                if !title.starts_with([' ', '.', '\t']) && !title.is_empty() {
+                    attributes.title = Some(title.to_string());
+                    self.pos += 1;
+                    continue;
+                }
+            }
+            return self.block(&line, attributes, enclosing);
+        }
+        vec![]
+    }
+
+    /// Parse a block starting at the current line
+    fn block(
+        &mut self,
+        line: &str,
+        attributes: BlockAttributes,
+        enclosing: Option<&str>,
+    ) -> Vec<Block> {
+        if let Some((level, title)) = section_title(line) {
+            self.pos += 1;
+            let (inline_id, title) = title_anchor(title);
+            let id = attributes
+                .id
+                .or(inline_id)
+                .unwrap_or_else(|| section_id(&title));
+            return vec![Block::Header {
+                level: level.clamp(1, 6) as u8,
+                content: self.inlines(&title),
+                anchor: Some(id),
+            }];
+        }
+
+        if matches!(line, "'''" | "---" | "***" | "- - -" | "* * *") {
+            self.pos += 1;
+            return vec![Block::ThematicBreak];
+        }
+        if line == "<<<" {
+            self.pos += 1;
+            return vec![];
+        }
+
+        if is_delimiter(line) {
+            self.pos += 1;
+            return self.delimited_block(line, attributes);
+        }
+
+        if let Some(rest) = line.strip_prefix("image::") {
+            self.pos += 1;
+            return self.block_image(rest, attributes).into_iter().collect();
+        }
+        if block_macro(line) {
+            // toc::[], video::, audio:: and other macros without text
+            self.pos += 1;
+            return vec![];
+        }
+
+        if let Some((label, text)) = line.split_once(": ") {
+            if let Some(kind) =
+                AdmonitionKind::from_name(label).filter(|_| label == label.to_uppercase())
+            {
+                let mut text = text.to_string();
+                self.pos += 1;
+                for next in self.paragraph_lines(enclosing) {
+                    text.push('\n');
+                    text.push_str(&next);
+                }
+                let mut content = self.titled(&attributes);
+                content.extend(self.paragraph(&text, false));
+                return vec![Block::Admonition { kind, content }];
+            }
+        }
+
+        if let Some((marker, _)) = list_item(line) {
+            return vec![self.list(&marker, &mut Vec::new(), enclosing)];
+        }
+
+        if line.starts_with([' ', '\t']) && attributes.style().is_none() {
+            // A literal paragraph, indented
+            let mut lines = vec![line.to_string()];
+            self.pos += 1;
+            lines.extend(self.paragraph_lines(enclosing));
+            return vec![Block::CodeBlock {
+                lang: None,
+                code: dedent(&lines).join("\n"),
+            }];
+        }
+
+        self.pos += 1;
+        let mut lines = vec![line.to_string()];
+        lines.extend(self.paragraph_lines(enclosing));
+        self.styled_paragraph(lines, attributes)
+    }
+
+    /// Lines continuing a paragraph, up to a blank line or block boundary
+    fn paragraph_lines(&mut self, enclosing: Option<&str>) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            let trimmed = line.trim_end();
+            if trimmed.is_empty() || Some(trimmed) == enclosing || is_delimiter(trimmed) {
+                break;
+            }
+            if trimmed.starts_with("//") && !trimmed.starts_with("///") {
+                self.pos += 1;
+                continue;
+            }
+            lines.push(trimmed.to_string());
+            self.pos += 1;
+        }
+        lines
+    }
+
+    /// Convert a paragraph according to its style, such as `[source]` or `[quote]`
+    fn styled_paragraph(&mut self, lines: Vec<String>, attributes: BlockAttributes) -> Vec<Block> {
+        let style = attributes.style().map(str::to_lowercase);
+        let text = lines.join("\n");
+        match style.as_deref() {
+            Some("source" | "listing") => vec![Block::CodeBlock {
+                lang: self.source_language(&attributes),
+                code: text,
+            }],
+            Some("literal") => vec![Block::CodeBlock {
+                lang: None,
+                code: text,
+            }],
+            Some("quote") => {
+                let mut content = self.paragraph(&text, false);
+                content.extend(self.attribution(&attributes));
+                vec![Block::Blockquote(content)]
+            }
+            Some("verse") => {
+                let mut content = self.paragraph(&text, true);
+                content.extend(self.attribution(&attributes));
+                vec![Block::Blockquote(content)]
+            }
+            Some(style) if AdmonitionKind::from_name(style).is_some() => {
+                let mut content = self.titled(&attributes);
+                content.extend(self.paragraph(&text, false));
+                vec![Block::Admonition {
+                    kind: AdmonitionKind::from_name(style).unwrap_or(AdmonitionKind::Note),
+                    content,
+                }]
+            }
+            _ => {
+                let mut blocks = self.titled(&attributes);
+                blocks.extend(self.paragraph(&text, attributes.has_option("hardbreaks")));
+                blocks
+            }
+        }
+    }
+
+    /// Parse a delimited block, whose opening line has been consumed
+    fn delimited_block(&mut self, delimiter: &str, attributes: BlockAttributes) -> Vec<Block> {
+        let style = attributes.style().map(str::to_lowercase);
+
+        if let Some(lang) = delimiter.strip_prefix("```") {
+            let code = self.raw_lines("```");
+            let lang = Some(lang.trim())
+                .filter(|lang| !lang.is_empty())
+                .map(str::to_string)
+                .or_else(|| self.source_language(&attributes));
+            return vec![Block::CodeBlock { lang, code }];
+        }
+
+        match delimiter.chars().next().unwrap_or_default() {
+            '|' | ',' | ':' | '!' => {
+                let mut blocks = self.titled(&attributes);
+                blocks.push(Block::Table(self.table(delimiter, &attributes)));
+                blocks
+            }
+            '-' if delimiter.len() >= 4 => {
+                let code = self.raw_lines(delimiter);
+                let mut blocks = self.titled(&attributes);
+                let lang = match style.as_deref() {
+                    Some("source") | None => self.source_language(&attributes),
+                    _ => None,
+                };
+                blocks.push(Block::CodeBlock { lang, code });
+                blocks
+            }
+            '.' => {
+                let code = self.raw_lines(delimiter);
+                let mut blocks = self.titled(&attributes);
+                blocks.push(Block::CodeBlock { lang: None, code });
+                blocks
+            }
+            '+' => {
+                // Raw passthrough content for other backends
+                self.raw_lines(delimiter);
+                vec![]
+            }
+            '/' => {
+                self.raw_lines(delimiter);
+                vec![]
+            }
+            '_' if style.as_deref() == Some("verse") => {
+                let text = self.raw_lines(delimiter);
+                let mut content = self.paragraph(&text, true);
+                content.extend(self.attribution(&attributes));
+                vec![Block::Blockquote(content)]
+            }
+            '_' => {
+                let mut content = self.blocks(Some(delimiter));
+                content.extend(self.attribution(&attributes));
+                vec![Block::Blockquote(content)]
+            }
+            _ => {
+                // Example (====), sidebar (****) and open (--) blocks
+                let mut content = self.titled(&attributes);
+                content.extend(self.blocks(Some(delimiter)));
+                if let Some(kind) = style.as_deref().and_then(AdmonitionKind::from_name) {
+                    return vec![Block::Admonition { kind, content }];
+                }
+                match (delimiter.chars().next(), style.as_deref()) {
+                    (Some('='), _) | (_, Some("example")) => vec![Block::Container {
+                        id: attributes.id,
+                        classes: vec!["example".to_string()],
+                        content,
+                    }],
+                    (Some('*'), _) | (_, Some("sidebar")) => vec![Block::Container {
+                        id: attributes.id,
+                        classes: vec!["sidebar".to_string()],
+                        content,
+                    }],
+                    (_, Some("quote")) => vec![Block::Blockquote(content)],
+                    _ => content,
+                }
+            }
+        }
+    }
+
+    /// Lines up to a closing delimiter, as written
+    fn raw_lines(&mut self, delimiter: &str) -> String {
+        let mut lines = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            self.pos += 1;
+            if line.trim_end() == delimiter {
+                break;
+            }
+            lines.push(line.trim_end());
+        }
+        lines.join("\n")
+    }
+
+    /// Language of a source block: `[source,rust]` or the `source-language` attribute
+    fn source_language(&self, attributes: &BlockAttributes) -> Option<String> {
+        attributes
+            .positional
+            .get(1)
+            .filter(|_| matches!(attributes.style(), Some("source") | None))
+            .cloned()
+            .or_else(|| attributes.named.get("language").cloned())
+            .or_else(|| self.attributes.get("source-language").cloned())
+            .filter(|lang| !lang.is_empty())
+    }
+
+    /// A bold paragraph for a block's title, if it has one
+    fn titled(&mut self, attributes: &BlockAttributes) -> Vec<Block> {
+        match &attributes.title {
+            Some(title) => {
+                let content = self.inlines(title);
+                vec![Block::Paragraph(vec![Inline::Bold(content)])]
+            }
+            None => vec![],
+        }
+    }
+
+    /// The attribution paragraph of a quote, `[quote, Author, Source]`
+    fn attribution(&mut self, attributes: &BlockAttributes) -> Vec<Block> {
+        let parts: Vec<&str> = attributes
+            .positional
+            .iter()
+            .skip(1)
+            .map(String::as_str)
+            .chain(attributes.named.get("attribution").map(String::as_str))
+            .filter(|part| !part.is_empty())
+            .collect();
+        if parts.is_empty() {
+            return vec![];
+        }
+        let mut credit = vec![Inline::text("\u{2014} ")];
+        credit.extend(self.inlines(&parts.join(", ")));
+        vec![Block::Paragraph(credit)]
+    }
+
+    /// An image from `image::target[alt,...]`
+    fn block_image(&mut self, rest: &str, attributes: BlockAttributes) -> Option<Block> {
+        let open = rest.find('[')?;
+        let target = self.substitute(&rest[..open]);
+        let macro_attributes = parse_attribute_list(rest[open + 1..].trim_end().strip_suffix(']')?);
+        let alt = macro_attributes
+            .named
+            .get("alt")
+            .cloned()
+            .or_else(|| macro_attributes.style().map(str::to_string))
+            .unwrap_or_else(|| default_alt(&target));
+        let caption = attributes
+            .title
+            .clone()
+            .or_else(|| macro_attributes.named.get("title").cloned());
+        Some(Block::Image {
+            resource_key: target,
+            caption,
+            alt,
+        })
+    }
+
+    /// Parse a list whose first item is at the current line
+    ///
+    /// `parents` holds the markers of the lists this one is nested in; an item
+    /// with one of those markers ends this list.
+    fn list(&mut self, marker: &str, parents: &mut Vec<String>, enclosing: Option<&str>) -> Block {
+        let mut items: Vec<Vec<Block>> = Vec::new();
+        let mut terms: Vec<DefinitionItem> = Vec::new();
+        let is_terms = is_term_marker(marker);
+
+        while let Some(line) = self.lines.get(self.pos).map(|l| l.trim_end().to_string()) {
+            if line.is_empty() {
+                // Blank lines may separate items of the same list
+                let next = self.lines[self.pos..]
+                    .iter()
+                    .position(|l| !l.trim().is_empty())
+                    .map(|offset| self.pos + offset);
+                let continues = next
+                    .and_then(|next| list_item(&self.lines[next]))
+                    .is_some_and(|(next_marker, _)| {
+                        next_marker == marker || !parents.contains(&next_marker)
+                    });
+                match next {
+                    Some(next) if continues => self.pos = next,
+                    _ => break,
+                }
+                continue;
+            }
+
+            let Some((item_marker, text)) = list_item(&line) else {
+                break;
+            };
+            if item_marker != marker {
+                if parents.contains(&item_marker) {
+                    break;
+                }
+                // A different marker starts a list nested in the current item
+                parents.push(marker.to_string());
+                let nested = self.list(&item_marker, parents, enclosing);
+                parents.pop();
+                if let Some(item) = items.last_mut() {
+                    item.push(nested);
+                } else if let Some(term) = terms.last_mut() {
+                    match term.definitions.last_mut() {
+                        Some(definition) => definition.push(nested),
+                        None => term.definitions.push(vec![nested]),
+                    }
+                } else {
+                    items.push(vec![nested]);
+                }
+                continue;
+            }
+
+            self.pos += 1;
+            let (term, text) = if is_terms {
+                let (term, text) = split_term(text, marker);
+                (Some(term.to_string()), text.to_string())
+            } else {
+                (None, text.to_string())
+            };
+
+            // The item's text continues on following lines
+            let mut text = text;
+            while let Some(next) = self.lines.get(self.pos) {
+                let next = next.trim_end();
+                if next.is_empty()
+                    || next == "+"
+                    || Some(next) == enclosing
+                    || is_delimiter(next)
+                    || list_item(next).is_some()
+                    || (next.starts_with('[') && next.ends_with(']'))
+                {
+                    break;
+                }
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(next.trim_start());
+                self.pos += 1;
+            }
+            let mut content = self.paragraph(&text, false);
+
+            // A `+` line attaches the following block to the item
+            while self.lines.get(self.pos).map(|l| l.trim_end()) == Some("+") {
+                self.pos += 1;
+                content.extend(self.next_block(enclosing));
+            }
+
+            match term {
+                Some(term) => {
+                    let term = self.inlines(&term);
+                    terms.push(DefinitionItem {
+                        term,
+                        definitions: if content.is_empty() {
+                            vec![]
+                        } else {
+                            vec![content]
+                        },
+                    });
+                }
+                None => items.push(content),
+            }
+        }
+
+        if is_terms {
+            Block::DefinitionList(terms)
+        } else {
+            Block::List {
+                items,
+                ordered: !marker.starts_with(['*', '-']),
+            }
+        }
+    }
+
+    /// Parse an AsciiDoc (`|===`) or delimiter-separated (`,===`, `:===`) table
+    fn table(&mut self, delimiter: &str, attributes: &BlockAttributes) -> TableData {
+        let mut lines = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            self.pos += 1;
+            if line.trim_end() == delimiter {
+                break;
+            }
+            lines.push(line.trim_end().to_string());
+        }
+
+        let separator = attributes
+            .named
+            .get("separator")
+            .and_then(|s| s.chars().next())
+            .unwrap_or_else(|| delimiter.chars().next().unwrap_or('|'));
+        let format = attributes.named.get("format").map(String::as_str);
+
+        // Each cell with the index of the line it starts on
+        let mut cells: Vec<(usize, TableCell)> = Vec::new();
+        if delimiter.starts_with([',', ':']) || matches!(format, Some("csv" | "dsv" | "tsv")) {
+            for (index, line) in lines.iter().enumerate() {
+                if line.is_empty() {
+                    continue;
+                }
+                for value in split_attributes_by(line, separator) {
+                    cells.push((index, TableCell::new(self.inlines(unquote(&value)))));
+                }
+            }
+        } else {
+            // Each separator starts a cell; text before the first separator
+            // on a line continues the cell before it
+            let mut current: Option<(usize, (u32, u32), String)> = None;
+            for (index, line) in lines.iter().enumerate() {
+                let segments: Vec<&str> = line.split(separator).collect();
+                for (position, segment) in segments.iter().enumerate() {
+                    let starts_cell = position + 1 < segments.len();
+                    let (text, spec) = if starts_cell {
+                        cell_spec(segment)
+                    } else {
+                        (*segment, None)
+                    };
+                    if let Some((_, _, content)) = current.as_mut() {
+                        if position == 0 {
+                            content.push('\n');
+                        }
+                        content.push_str(text);
+                    }
+                    if starts_cell {
+                        if let Some(cell) = current.take() {
+                            cells.push(self.table_cell(cell));
+                        }
+                        current = Some((index, spec.unwrap_or((1, 1)), String::new()));
+                    }
+                }
+            }
+            if let Some(cell) = current.take() {
+                cells.push(self.table_cell(cell));
+            }
+        }
+
+        // Column count from `cols`, or the cells on the first line
+        let columns = attributes
+            .named
+            .get("cols")
+            .map(|cols| match cols.split_once('*') {
+                Some((count, _)) if !count.contains(',') => count.trim().parse().unwrap_or(1),
+                _ => cols.split([',', ';']).count(),
+            })
+            .unwrap_or_else(|| {
+                let first = cells.first().map_or(0, |(line, _)| *line);
+                cells
+                    .iter()
+                    .filter(|(line, _)| *line == first)
+                    .map(|(_, cell)| cell.colspan as usize)
+                    .sum()
+            })
+            .max(1);
+
+        // An implicit header is a first line of cells followed by a blank line
+        let implicit_header = cells.first().is_some_and(|(first, _)| {
+            lines.get(first + 1).is_some_and(|line| line.is_empty())
+                && cells.iter().filter(|(line, _)| line == first).count() == columns
+        });
+        let has_header = (attributes.has_option("header") || implicit_header)
+            && !attributes.has_option("noheader");
+
+        // Group cells into rows, allowing for cells spanning rows from above
+        let mut rows: Vec<Vec<TableCell>> = Vec::new();
+        let mut row = Vec::new();
+        let mut width = 0;
+        let mut spans: Vec<(u32, usize)> = Vec::new();
+        for (_, cell) in cells {
+            if row.is_empty() {
+                width = spans.iter().map(|(_, colspan)| colspan).sum();
+            }
+            width += cell.colspan as usize;
+            row.push(cell);
+            if width >= columns {
+                spans.retain_mut(|(remaining, _)| {
+                    *remaining -= 1;
+                    *remaining > 0
+                });
+                spans.extend(
+                    row.iter()
+                        .filter(|cell: &&TableCell| cell.rowspan > 1)
+                        .map(|cell| (cell.rowspan - 1, cell.colspan as usize)),
+                );
+                rows.push(std::mem::take(&mut row));
+            }
+        }
+        if !row.is_empty() {
+            rows.push(row);
+        }
+
+        let headers = if has_header && !rows.is_empty() {
+            rows.remove(0)
+        } else {
+            Vec::new()
+        };
+        TableData { headers, rows }
+    }
+
+    /// A table cell from its starting line, spans and text
+    fn table_cell(
+        &mut self,
+        (line, (colspan, rowspan), content): (usize, (u32, u32), String),
+    ) -> (usize, TableCell) {
+        let mut cell = TableCell::new(self.inlines(content.trim()));
+        cell.colspan = colspan;
+        cell.rowspan = rowspan;
+        (line, cell)
+    }
+
+    /// Convert paragraph text, with any inline images following it
+    fn paragraph(&mut self, text: &str, hard_breaks: bool) -> Vec<Block> {
+        let text = if hard_breaks {
+            text.replace('\n', " +\n")
+        } else {
+            text.to_string()
+        };
+        let mut blocks = Vec::new();
+        let inlines = self.inlines(&text);
+        if !inlines.is_empty() {
+            blocks.push(Block::Paragraph(inlines));
+        }
+        blocks.append(&mut self.inline_images);
+        blocks
+    }
+
+    /// Replace `{name}` attribute references
+    fn substitute(&self, text: &str) -> String {
+        if !text.contains('{') {
+            return text.to_string();
+        }
+        static REFERENCE: OnceLock<Regex> = OnceLock::new();
+        let reference = REFERENCE.get_or_init(|| Regex::new(r"\\?\{(\w[\w-]*)\}").unwrap());
+        reference
+            .replace_all(text, |captures: &regex::Captures| {
+                let whole = &captures[0];
+                if let Some(escaped) = whole.strip_prefix('\\') {
+                    return escaped.to_string();
+                }
+                let name = &captures[1];
+                if let Some(value) = self.attributes.get(name) {
+                    return value.clone();
+                }
+                match name {
+                    "nbsp" => "\u{A0}",
+                    "zwsp" | "empty" => "",
+                    "sp" => " ",
+                    "amp" => "&",
+                    "lt" => "<",
+                    "gt" => ">",
+                    "startsb" => "[",
+                    "endsb" => "]",
+                    "vbar" => "|",
+                    "caret" => "^",
+                    "tilde" => "~",
+                    "plus" => "+",
+                    "apos" => "'",
+                    "quot" => "\"",
+                    "deg" => "\u{B0}",
+                    "wj" => "\u{2060}",
+                    _ => whole,
+                }
+                .to_string()
+            })
+            .into_owned()
+    }
+
+    /// Convert inline text, trimming the whitespace at its ends
+    fn inlines(&mut self, text: &str) -> Vec<Inline> {
+        let text = self.substitute(text);
+        let mut inlines = self.scan(&text);
+        trim_inlines(&mut inlines);
+        inlines
+    }
+
+    /// Convert inline markup
+    fn scan(&mut self, text: &str) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut plain = String::new();
+        let mut index = 0;
+        while index < text.len() {
+            let rest = &text[index..];
+            let previous = text[..index].chars().next_back();
+
+            if let Some((found, length)) = self.markup(rest, previous) {
+                if !plain.is_empty() {
+                    inlines.push(Inline::Text(replacements(&std::mem::take(&mut plain))));
+                }
+                inlines.extend(found);
+                index += length;
+                continue;
+            }
+
+            let c = rest.chars().next().unwrap_or_default();
+            match c {
+                '\\' => {
+                    // A backslash escapes the markup that follows it
+                    let next = rest[1..].chars().next();
+                    match next {
+                        Some(next) if "*_`#^~[<+{".contains(next) => {
+                            plain.push(next);
+                            index += 1 + next.len_utf8();
+                            continue;
+                        }
+                        _ => plain.push(c),
+                    }
+                }
+                '\n' => plain.push(' '),
+                _ => plain.push(c),
+            }
+            index += c.len_utf8();
+        }
+        if !plain.is_empty() {
+            inlines.push(Inline::Text(replacements(&plain)));
+        }
+        inlines
+    }
+
+    /// Recognise inline markup at the start of `rest`, returning what it
+    /// converts to and its length
+    fn markup(&mut self, rest: &str, previous: Option<char>) -> Option<(Vec<Inline>, usize)> {
+        let word_before = previous.is_some_and(|c| c.is_alphanumeric());
+
+        if rest.starts_with(" +\n") {
+            return Some((vec![Inline::Break], 3));
+        }
+        if rest == " +" {
+            return Some((vec![], 2));
+        }
+
+        // Macros: footnote:[...], link:target[...], xref:id[...], image:target[...]
+        if !word_before {
+            if let Some(found) = self.inline_macro(rest) {
+                return Some(found);
+            }
+        }
+
+        if let Some(after) = rest.strip_prefix("<<") {
+            let end = after.find(">>")?;
+            let reference = &after[..end];
+            let (target, text) = match reference.split_once(',') {
+                Some((target, text)) => (target.trim(), Some(text.trim())),
+                None => (reference.trim(), None),
+            };
+            return Some((vec![self.cross_reference(target, text)], end + 4));
+        }
+
+        if rest.starts_with("[[") {
+            let end = rest.find("]]")?;
+            let id = &rest[2..end];
+            if id.starts_with('[') {
+                // A bibliography anchor, [[[id]]], shows its label
+                let label = id.trim_start_matches('[');
+                return Some((vec![Inline::Text(format!("[{}]", label))], end + 3));
+            }
+            return Some((vec![], end + 2));
+        }
+
+        // [line-through]#text# and other roles on marked text
+        if rest.starts_with('[') && !word_before {
+            let close = rest.find("]#")?;
+            let role = &rest[1..close];
+            if role.contains(['[', ']', '\n']) {
+                return None;
+            }
+            let after = &rest[close + 1..];
+            let (content, length) =
+                unconstrained(after, "##").or_else(|| constrained(after, None, '#'))?;
+            let children = self.scan(content);
+            let inlines = if role.contains("line-through") {
+                vec![Inline::Strikethrough(children)]
+            } else {
+                children
+            };
+            return Some((inlines, close + 1 + length));
+        }
+
+        if !word_before {
+            if let Some(url) = bare_url(rest, URL_SCHEMES, &['[', '<', '>']) {
+                let after = &rest[url.len()..];
+                if let Some((text, length)) = bracketed(after) {
+                    let children = self.link_text(text, url);
+                    return Some((
+                        vec![Inline::Link {
+                            children,
+                            url: url.to_string(),
+                        }],
+                        url.len() + length,
+                    ));
+                }
+                return Some((
+                    vec![Inline::Link {
+                        children: vec![Inline::text(url)],
+                        url: url.to_string(),
+                    }],
+                    url.len(),
+                ));
+            }
+        }
+
+        // Passthrough text, +++text+++ and +text+
+        if let Some((content, length)) = unconstrained(rest, "+++") {
+            return Some((vec![Inline::text(content)], length));
+        }
+        if let Some((content, length)) = constrained(rest, previous, '+') {
+            return Some((vec![Inline::text(content)], length));
+        }
+
+        for (marks, mark) in [("``", '`'), ("**", '*'), ("__", '_'), ("##", '#')] {
+            let found = unconstrained(rest, marks).or_else(|| constrained(rest, previous, mark));
+            if let Some((content, length)) = found {
+                let inline = match mark {
+                    '`' => {
+                        let code = content
+                            .strip_prefix('+')
+                            .and_then(|c| c.strip_suffix('+'))
+                            .unwrap_or(content);
+                        Inline::Code(code.replace('\n', " "))
+                    }
+                    '*' => Inline::Bold(self.scan(content)),
+                    '_' => Inline::Italic(self.scan(content)),
+                    _ => return Some((self.scan(content), length)),
+                };
+                return Some((vec![inline], length));
+            }
+        }
+
+        for (mark, superscript) in [('^', true), ('~', false)] {
+            if rest.starts_with(mark) {
+                let end = rest[1..].find(mark)? + 1;
+                let content = &rest[1..end];
+                if content.is_empty() || content.contains(char::is_whitespace) {
+                    return None;
+                }
+                let children = self.scan(content);
+                let inline = if superscript {
+                    Inline::Superscript(children)
+                } else {
+                    Inline::Subscript(children)
+                };
+                return Some((vec![inline], end + 1));
+            }
+        }
+        None
+    }
+
+    /// Recognise an inline macro such as `footnote:[text]` or `link:url[text]`
+    fn inline_macro(&mut self, rest: &str) -> Option<(Vec<Inline>, usize)> {
+        static MACRO: OnceLock<Regex> = OnceLock::new();
+        let pattern = MACRO.get_or_init(|| {
+            Regex::new(r"^(footnote|footnoteref|link|mailto|xref|image|kbd|btn|menu|pass|icon|anchor):([^\s\[]*)\[")
+                .unwrap()
+        });
+        let captures = pattern.captures(rest)?;
+        let name = captures.get(1)?.as_str();
+        let target = captures.get(2)?.as_str();
+        let head = captures.get(0)?.end() - 1;
+        let (text, length) = bracketed(&rest[head..])?;
+        let length = head + length;
+
+        let inlines = match name {
+            "footnote" | "footnoteref" => {
+                let (id, text) = if name == "footnoteref" {
+                    match text.split_once(',') {
+                        Some((id, text)) => (Some(id.trim()), text),
+                        None => (Some(text.trim()), ""),
+                    }
+                } else {
+                    (Some(target).filter(|t| !t.is_empty()), text)
+                };
+                let id = match id {
+                    Some(id) => id.to_string(),
+                    None => format!("footnote-{}", self.footnotes.len() + 1),
+                };
+                let defined = self.footnotes.iter().any(|(note, _)| *note == id);
+                if !text.trim().is_empty() && !defined {
+                    let content = self.inlines(text);
+                    self.footnotes.push((
+                        id.clone(),
+                        Block::Footnote {
+                            id: id.clone(),
+                            content: vec![Block::Paragraph(content)],
+                        },
+                    ));
+                }
+                vec![Inline::FootnoteRef { id }]
+            }
+            "link" | "mailto" => {
+                let url = if name == "mailto" {
+                    format!("mailto:{}", target)
+                } else {
+                    target.to_string()
+                };
+                let children = self.link_text(text, &url);
+                vec![Inline::Link { children, url }]
+            }
+            "xref" => {
+                let text = Some(text.trim()).filter(|t| !t.is_empty());
+                vec![self.cross_reference(target, text)]
+            }
+            "image" => {
+                let attributes = parse_attribute_list(text);
+                let alt = attributes
+                    .named
+                    .get("alt")
+                    .cloned()
+                    .or_else(|| attributes.style().map(str::to_string))
+                    .unwrap_or_else(|| default_alt(target));
+                self.inline_images.push(Block::Image {
+                    resource_key: target.to_string(),
+                    caption: None,
+                    alt,
+                });
+                vec![]
+            }
+            "kbd" | "btn" => vec![Inline::Code(text.to_string())],
+            "menu" => {
+                let mut path = vec![target];
+                path.extend(text.split('>').map(str::trim).filter(|t| !t.is_empty()));
+                vec![Inline::Text(path.join(" \u{203A} "))]
+            }
+            "pass" => vec![Inline::text(text)],
+            // Icons and anchors have no text
+            _ => vec![],
+        };
+        Some((inlines, length))
+    }
+
+    /// Text of a link, or the URL when none is given
+    fn link_text(&mut self, text: &str, url: &str) -> Vec<Inline> {
+        // A trailing caret asks for a new window, which has no meaning here
+        let text = text.split(",window=").next().unwrap_or(text);
+        let text = unquote(text.trim_end_matches('^'));
+        if text.trim().is_empty() {
+            vec![Inline::text(url.trim_start_matches("mailto:"))]
+        } else {
+            self.scan(text)
+        }
+    }
+
+    /// A link to a section, by id, by title, or in another document
+    fn cross_reference(&mut self, target: &str, text: Option<&str>) -> Inline {
+        let target = target.trim();
+        let id = match target.split_once('#') {
+            Some((_, fragment)) => fragment.to_string(),
+            None => self
+                .ids
+                .get(target)
+                .cloned()
+                .unwrap_or_else(|| target.to_string()),
+        };
+        let children = match text {
+            Some(text) => self.scan(text),
+            None => vec![Inline::Text(
+                self.titles
+                    .get(&id)
+                    .map(|title| self.substitute(title))
+                    .unwrap_or_else(|| format!("[{}]", id)),
+            )],
+        };
+        Inline::Link {
+            children,
+            url: format!("#{}", id),
+        }
+    }
+}
+
+/// Split a delimiter-separated line, honouring quotes
+fn split_attributes_by(line: &str, separator: char) -> Vec<String> {
+    if separator == ',' {
+        return split_attributes(line);
+    }
+    line.split(separator)
+        .map(|v| v.trim().to_string())
+        .collect()
+}
+
+/// The span given before a cell's separator, such as `2+` or `.3+`
+///
+/// Returns the text before the span and the column and row spans.
+fn cell_spec(segment: &str) -> (&str, Option<(u32, u32)>) {
+    static SPEC: OnceLock<Regex> = OnceLock::new();
+    let spec = SPEC.get_or_init(|| {
+        Regex::new(r"(?:^|\s)((\d+)?(?:\.(\d+))?\+|\d+\*)?([<^>])?(\.[<^>])?([aehlmsdv])?$")
+            .unwrap()
+    });
+    let Some(captures) = spec.captures(segment) else {
+        return (segment, None);
+    };
+    let whole = captures.get(0).map_or("", |m| m.as_str()).trim_start();
+    if whole.is_empty() {
+        return (segment, None);
+    }
+    let text = &segment[..segment.len() - whole.len()];
+    let span = captures.get(1).map(|m| m.as_str()).unwrap_or_default();
+    if !span.ends_with('+') {
+        return (text, Some((1, 1)));
+    }
+    let colspan = captures
+        .get(2)
+        .and_then(|m| m.as_str().parse().ok())
+        .unwrap_or(1);
+    let rowspan = captures
+        .get(3)
+        .and_then(|m| m.as_str().parse().ok())
+        .unwrap_or(1);
+    (text, Some((colspan, rowspan)))
+}
+
+/// Whether a line is a block macro without text, such as `toc::[]`
+fn block_macro(line: &str) -> bool {
+    static MACRO: OnceLock<Regex> = OnceLock::new();
+    let pattern = MACRO.get_or_init(|| Regex::new(r"^[a-z][\w-]*::\S*\[.*\]$").unwrap());
+    pattern.is_match(line)
+}
+
+/// An id given by an anchor in a section title, `Title [[id]]`, and the title
+/// without it
+fn title_anchor(title: &str) -> (Option<String>, String) {
+    if let Some(start) = title.find("[[") {
+        if let Some(length) = title[start..].find("]]") {
+            let id = title[start + 2..start + length]
+                .split(',')
+                .next()
+                .unwrap_or_default();
+            let rest = format!("{}{}", &title[..start], &title[start + length + 2..]);
+            return (Some(id.to_string()), rest.trim().to_string());
+        }
+    }
+    (None, title.to_string())
+}
+
+/// The id given by a block attribute line, `[[id]]` or `[#id]`
+fn block_anchor(line: &str) -> Option<String> {
+    if let Some(inner) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+        return inner.split(',').next().map(str::to_string);
+    }
+    parse_attribute_list(&line[1..line.len() - 1]).id
+}
+
+/// Content of a bracketed attribute list at the start of `rest`, and its length
+fn bracketed(rest: &str) -> Option<(&str, usize)> {
+    if !rest.starts_with('[') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut escaped = false;
+    for (index, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((&rest[1..index], index + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Constrained formatting, such as `*bold*` between word boundaries
+fn constrained(rest: &str, previous: Option<char>, mark: char) -> Option<(&str, usize)> {
+    if !rest.starts_with(mark) || previous.is_some_and(|c| c.is_alphanumeric() || c == mark) {
+        return None;
+    }
+    let body = &rest[1..];
+    if body.starts_with(char::is_whitespace) || body.starts_with(mark) {
+        return None;
+    }
+    for (index, c) in body.char_indices() {
+        if c != mark || index == 0 {
+            continue;
+        }
+        let before = body[..index].chars().next_back();
+        let after = body[index + 1..].chars().next();
+        if before.is_some_and(|c| !c.is_whitespace())
+            && !after.is_some_and(|c| c.is_alphanumeric() || c == mark)
+        {
+            return Some((&body[..index], index + 2));
+        }
+    }
+    None
+}
+
+/// Unconstrained formatting, such as `**bold**` anywhere in a word
+fn unconstrained<'a>(rest: &'a str, marks: &str) -> Option<(&'a str, usize)> {
+    let body = rest.strip_prefix(marks)?;
+    let end = body.find(marks).filter(|end| *end > 0)?;
+    Some((&body[..end], marks.len() * 2 + end))
+}
+
+/// Typographic replacements made in plain text
+fn replacements(text: &str) -> String {
+    text.replace("(C)", "\u{A9}")
+        .replace("(R)", "\u{AE}")
+        .replace("(TM)", "\u{2122}")
+        .replace(" -- ", "\u{2009}\u{2014}\u{2009}")
+        .replace("...", "\u{2026}")
+        .replace("->", "\u{2192}")
+        .replace("<-", "\u{2190}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::markdown::inlines_to_text;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    fn decode(text: &str) -> Book {
+        AsciiDocDecoder::new()
+            .decode(&mut Cursor::new(text.as_bytes()))
+            .unwrap()
+    }
+
+    /// Blocks of every chapter, in order
+    fn blocks(text: &str) -> Vec<Block> {
+        decode(text)
+            .chapters
+            .into_iter()
+            .flat_map(|chapter| chapter.content)
+            .collect()
+    }
+
+    /// Inlines of the only paragraph in `text`
+    fn paragraph(text: &str) -> Vec<Inline> {
+        match &blocks(text)[..] {
+            [Block::Paragraph(inlines)] => inlines.clone(),
+            other => panic!("expected one paragraph, got {:?}", other),
+        }
+    }
+
+    /// The only table in `text`
+    fn table(text: &str) -> TableData {
+        blocks(text)
+            .into_iter()
+            .find_map(|block| match block {
+                Block::Table(table) => Some(table),
+                _ => None,
+            })
+            .expect("expected a table")
+    }
+
+    /// Text of each cell, row by row
+    fn cell_text(cells: &[TableCell]) -> Vec<String> {
+        cells
+            .iter()
+            .map(|cell| inlines_to_text(&cell.content))
+            .collect()
+    }
+
+    /// Decode `text` with its includes read from `files` in a temporary directory
+    fn decode_with_files(text: &str, files: &[(&str, &str)]) -> (Book, DecodeContext) {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let context = DecodeContext::new().with_base_path(dir.path());
+        let book = AsciiDocDecoder::new()
+            .decode_with_context(&mut Cursor::new(text.as_bytes()), &context)
+            .unwrap();
+        (book, context)
+    }
+
+    #[test]
+    fn test_decode_header() {
+        let book = decode(
+            "= Engineering Handbook\nAda Lovelace <ada@example.com>; Charles Babbage\n\
+             v2.1, 2021-06-01: Second edition\n:description: How we build things\n\
+             :keywords: engineering, process\n:lang: fr\n\nText.\n",
+        );
+        assert_eq!(book.metadata.title, "Engineering Handbook");
+        assert_eq!(
+            book.metadata.creator,
+            vec!["Ada Lovelace", "Charles Babbage"]
+        );
+        assert_eq!(
+            book.metadata.description.as_deref(),
+            Some("How we build things")
+        );
+        assert_eq!(book.metadata.subject, vec!["engineering", "process"]);
+        assert_eq!(book.metadata.language, "fr");
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("2021-06-01".to_string())
+        );
+    }
+
+    #[test]
+    fn test_attribute_references() {
+        assert_eq!(
+            paragraph(
+                ":product: Widget\n:name: {product} Pro\n:gone: x\n:gone!:\n\n\
+                 The {name}, {gone}, \\{product}{nbsp}and {startsb}1{endsb}."
+            ),
+            vec![Inline::text(
+                "The Widget Pro, {gone}, {product}\u{A0}and [1]."
+            )]
+        );
+    }
+
+    #[test]
+    fn test_section_ids() {
+        let found = blocks(
+            "== Getting Started!\n\n[[setup]]\n== Setup\n\n[#deploy.wide]\n== Deploying\n\n\
+             === Rolling Back [[rollback]]\n\n## Markdown Style\n",
+        );
+        let anchors: Vec<(u8, Option<&str>)> = found
+            .iter()
+            .filter_map(|block| match block {
+                Block::Header { level, anchor, .. } => Some((*level, anchor.as_deref())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            anchors,
+            [
+                (1, Some("_getting_started")),
+                (1, Some("setup")),
+                (1, Some("deploy")),
+                (2, Some("rollback")),
+                (1, Some("_markdown_style")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cross_references() {
+        let book = decode(
+            "== Setup [[setup]]\n\n\
+             See <<deploy>>, <<Deploying>>, <<deploy,the rollout>>, xref:deploy[], \
+             xref:other.adoc#intro[Other] and <<missing>>.\n\n\
+             [[deploy]]\n== Deploying\n\nText.\n",
+        );
+        let Block::Paragraph(text) = &book.chapters[0].content[1] else {
+            panic!("expected a paragraph");
+        };
+        let links: Vec<(String, &str)> = text
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Link { children, url } => Some((inlines_to_text(children), url.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("Deploying".to_string(), "#deploy"),
+                ("Deploying".to_string(), "#deploy"),
+                ("the rollout".to_string(), "#deploy"),
+                ("Deploying".to_string(), "#deploy"),
+                ("Other".to_string(), "#intro"),
+                ("[missing]".to_string(), "#missing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let book = decode(
+            "Welcome.footnote:[Updated *quarterly*.] Again.footnote:disclaimer[Opinions are ours.] \
+             Later.footnote:disclaimer[] Old.footnoteref:[legacy,Old style.]\n",
+        );
+        let content = &book.chapters[0].content;
+        let Block::Paragraph(text) = &content[0] else {
+            panic!("expected a paragraph, got {:?}", content[0]);
+        };
+        let refs: Vec<&str> = text
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::FootnoteRef { id } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(refs, ["footnote-1", "disclaimer", "disclaimer", "legacy"]);
+
+        let notes: Vec<&Block> = content
+            .iter()
+            .filter(|block| matches!(block, Block::Footnote { .. }))
+            .collect();
+        assert_eq!(notes.len(), 3);
+        assert_eq!(
+            *notes[0],
+            Block::Footnote {
+                id: "footnote-1".to_string(),
+                content: vec![Block::Paragraph(vec![
+                    Inline::text("Updated "),
+                    Inline::Bold(vec![Inline::text("quarterly")]),
+                    Inline::text("."),
+                ])],
+            }
+        );
+    }
+
+    #[test]
+    fn test_table_header_and_spans() {
+        let data = table(
+            ".Tools\n[cols=\"1,2\",options=\"header\"]\n|===\n|Tool |Use\n\
+             |cargo |Builds _everything_\n2+|Spans both\n.2+|Tall |One\n|Two\n|===\n",
+        );
+        assert_eq!(cell_text(&data.headers), ["Tool", "Use"]);
+        assert_eq!(data.rows.len(), 4);
+        assert_eq!(
+            data.rows[0][1].content,
+            vec![
+                Inline::text("Builds "),
+                Inline::Italic(vec![Inline::text("everything")])
+            ]
+        );
+        assert_eq!(data.rows[1][0].colspan, 2);
+        assert_eq!(data.rows[2][0].rowspan, 2);
+        assert_eq!(cell_text(&data.rows[3]), ["Two"]);
+
+        // A first line of cells followed by a blank line is a header
+        let data = table("|===\n|Name |Age\n\n|Ada\n|36\n|===\n");
+        assert_eq!(cell_text(&data.headers), ["Name", "Age"]);
+        assert_eq!(cell_text(&data.rows[0]), ["Ada", "36"]);
+
+        // Without one, every row is a body row
+        let data = table("[%noheader]\n|===\n|a |b\n\n|c |d\n|===\n");
+        assert!(data.headers.is_empty());
+        assert_eq!(data.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_delimiter_separated_tables() {
+        let data = table(",===\nName,Motto\n\"Lovelace, Ada\",\"Think\"\n,===\n");
+        assert_eq!(cell_text(&data.rows[0]), ["Name", "Motto"]);
+        assert_eq!(cell_text(&data.rows[1]), ["Lovelace, Ada", "Think"]);
+
+        let data = table(":===\na:b\n:===\n");
+        assert_eq!(cell_text(&data.rows[0]), ["a", "b"]);
+
+        let data = table("[format=csv,separator=;]\n|===\nx;y\n|===\n");
+        assert_eq!(cell_text(&data.rows[0]), ["x", "y"]);
+    }
+
+    #[test]
+    fn test_include_selects_lines_and_tags() {
+        let (book, _) = decode_with_files(
+            "include::code.rs[tag=main]\n\ninclude::notes.txt[lines=1;3..]\n",
+            &[
+                (
+                    "code.rs",
+                    "// tag::main[]\nMain part.\n// end::main[]\n// tag::other[]\nOther.\n// end::other[]\n",
+                ),
+                ("notes.txt", "One.\nTwo.\nThree.\nFour.\n"),
+            ],
+        );
+        assert_eq!(
+            book.chapters[0].content,
+            vec![
+                Block::Paragraph(vec![Inline::text("Main part.")]),
+                Block::Paragraph(vec![Inline::text("One. Three. Four.")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_and_cyclic_includes_are_reported() {
+        let (book, context) = decode_with_files(
+            "Start.\n\ninclude::missing.adoc[]\n\ninclude::loop.adoc[]\n",
+            &[("loop.adoc", "Loop.\n\ninclude::loop.adoc[]\n")],
+        );
+        let codes: Vec<String> = context
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect();
+        assert_eq!(codes, ["unresolved-include", "skipped-include"]);
+        let loops = book.chapters[0]
+            .content
+            .iter()
+            .filter(|block| **block == Block::Paragraph(vec![Inline::text("Loop.")]))
+            .count();
+        assert_eq!(loops, MAX_INCLUDE_DEPTH);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        assert_eq!(
+            paragraph(
+                ":draft:\n:print:\n\nifdef::draft[]\nDraft.\nendif::[]\n\
+                 ifndef::draft[]\nFinal.\nendif::[]\n\
+                 ifdef::web,print[Either.]\nifdef::web+print[Both.]\n\
+                 ifeval::[1 > 2]\nKept.\nendif::[]"
+            ),
+            vec![Inline::text("Draft. Either. Kept.")]
+        );
+    }
+
+    #[test]
+    fn test_formatting_marks() {
+        assert_eq!(
+            paragraph("*bold* _italic_ `code` #mark# **un**constrained x^2^ H~2~O"),
+            vec![
+                Inline::Bold(vec![Inline::text("bold")]),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("italic")]),
+                Inline::text(" "),
+                Inline::Code("code".to_string()),
+                Inline::text(" "),
+                Inline::text("mark"),
+                Inline::text(" "),
+                Inline::Bold(vec![Inline::text("un")]),
+                Inline::text("constrained x"),
+                Inline::Superscript(vec![Inline::text("2")]),
+                Inline::text(" H"),
+                Inline::Subscript(vec![Inline::text("2")]),
+                Inline::text("O"),
+            ]
+        );
+        assert_eq!(
+            paragraph("[line-through]#gone# +*literal*+ `+{x}+` (C) a -- b..."),
+            vec![
+                Inline::Strikethrough(vec![Inline::text("gone")]),
+                Inline::text(" "),
+                Inline::text("*literal*"),
+                Inline::text(" "),
+                Inline::Code("{x}".to_string()),
+                Inline::text(" \u{A9} a\u{2009}\u{2014}\u{2009}b\u{2026}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_formatting_needs_word_boundaries() {
+        for text in [
+            "2*3*4 and snake_case_name",
+            "a * spaced * mark",
+            "\\*escaped* and \\_this_",
+            "x^two words^ and ~~",
+        ] {
+            let expected = text.replace("\\*", "*").replace("\\_", "_");
+            assert_eq!(inlines_to_text(&paragraph(text)), expected, "{:?}", text);
+            assert!(
+                paragraph(text)
+                    .iter()
+                    .all(|inline| matches!(inline, Inline::Text(_))),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_links_and_inline_macros() {
+        let found = blocks(
+            "Visit https://example.com[the *site*^], https://example.org. \
+             link:guide.pdf[Guide] mailto:ada@example.com[] kbd:[Ctrl+T] \
+             menu:File[Save > As] image:icons/big_logo.png[] done.\n",
+        );
+        let [Block::Paragraph(text), image] = &found[..] else {
+            panic!("expected a paragraph and an image, got {:?}", found);
+        };
+        let links: Vec<(&Vec<Inline>, &str)> = text
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Link { children, url } => Some((children, url.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (
+                    &vec![
+                        Inline::text("the "),
+                        Inline::Bold(vec![Inline::text("site")])
+                    ],
+                    "https://example.com"
+                ),
+                (
+                    &vec![Inline::text("https://example.org")],
+                    "https://example.org"
+                ),
+                (&vec![Inline::text("Guide")], "guide.pdf"),
+                (
+                    &vec![Inline::text("ada@example.com")],
+                    "mailto:ada@example.com"
+                ),
+            ]
+        );
+        assert!(text.contains(&Inline::Code("Ctrl+T".to_string())));
+        assert!(text.contains(&Inline::text("File \u{203A} Save \u{203A} As")));
+        assert_eq!(
+            *image,
+            Block::Image {
+                resource_key: "icons/big_logo.png".to_string(),
+                caption: None,
+                alt: "big logo".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_delimited_blocks() {
+        let found = blocks(
+            ":source-language: python\n\n[source,rust]\n----\nfn main() {}\n----\n\n\
+             ----\nprint()\n----\n\n....\n  literal\n....\n\n\
+             [quote, Ada Lovelace, Notes]\n____\nThe engine weaves.\n____\n\n\
+             [verse]\n____\nOne\nTwo\n____\n\n\
+             .Example\n====\nInside.\n====\n\n****\nAside.\n****\n\n\
+             ++++\n<b>raw</b>\n++++\n\n////\nComment.\n////\n\n\
+             [TIP]\n====\nTry it.\n====\n\nNOTE: Read *first*.\n",
+        );
+        assert_eq!(
+            found[0],
+            Block::CodeBlock {
+                lang: Some("rust".to_string()),
+                code: "fn main() {}".to_string(),
+            }
+        );
+        assert_eq!(
+            found[1],
+            Block::CodeBlock {
+                lang: Some("python".to_string()),
+                code: "print()".to_string(),
+            }
+        );
+        assert_eq!(
+            found[2],
+            Block::CodeBlock {
+                lang: None,
+                code: "  literal".to_string(),
+            }
+        );
+        assert_eq!(
+            found[3],
+            Block::Blockquote(vec![
+                Block::Paragraph(vec![Inline::text("The engine weaves.")]),
+                Block::Paragraph(vec![
+                    Inline::text("\u{2014} "),
+                    Inline::text("Ada Lovelace, Notes")
+                ]),
+            ])
+        );
+        assert_eq!(
+            found[4],
+            Block::Blockquote(vec![Block::Paragraph(vec![
+                Inline::text("One"),
+                Inline::Break,
+                Inline::text("Two"),
+            ])])
+        );
+        assert_eq!(
+            found[5],
+            Block::Container {
+                id: None,
+                classes: vec!["example".to_string()],
+                content: vec![
+                    Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Example")])]),
+                    Block::Paragraph(vec![Inline::text("Inside.")]),
+                ],
+            }
+        );
+        assert!(matches!(&found[6], Block::Container { classes, .. } if classes == &["sidebar"]));
+        assert!(matches!(
+            &found[7],
+            Block::Admonition {
+                kind: AdmonitionKind::Tip,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &found[8],
+            Block::Admonition {
+                kind: AdmonitionKind::Note,
+                ..
+            }
+        ));
+        assert_eq!(found.len(), 9);
+    }
+
+    #[test]
+    fn test_lists() {
+        // A comment line ends a list; after a blank line alone, a list with
+        // another marker would nest in the last item
+        let found = blocks(
+            "* One\n** Nested\n* Two\ncontinued\n+\n----\ncode\n----\n\n//\n\
+             . First\n. Second\n\n//\nCPU:: The processor\nRAM::\n  The memory\n",
+        );
+        let Block::List {
+            items,
+            ordered: false,
+        } = &found[0]
+        else {
+            panic!("expected a list, got {:?}", found[0]);
+        };
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0][1], Block::List { items, .. } if items.len() == 1));
+        assert_eq!(
+            items[1],
+            vec![
+                Block::Paragraph(vec![Inline::text("Two continued")]),
+                Block::CodeBlock {
+                    lang: None,
+                    code: "code".to_string(),
+                },
+            ]
+        );
+
+        assert!(matches!(&found[1], Block::List { items, ordered: true } if items.len() == 2));
+
+        let Block::DefinitionList(definitions) = &found[2] else {
+            panic!("expected a definition list, got {:?}", found[2]);
+        };
+        assert_eq!(definitions[0].term, vec![Inline::text("CPU")]);
+        assert_eq!(
+            definitions[1].definitions,
+            vec![vec![Block::Paragraph(vec![Inline::text("The memory")])]]
+        );
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        // Unclosed markup and macros stay as text
+        assert_eq!(
+            paragraph("Open <<ref and [[id and footnote:[note and *bold and {unknown}"),
+            vec![Inline::text(
+                "Open <<ref and [[id and footnote:[note and *bold and {unknown}"
+            )]
+        );
+
+        // An unterminated block runs to the end of the document
+        assert_eq!(
+            blocks("----\ncode\n== Not a section\n"),
+            vec![Block::CodeBlock {
+                lang: None,
+                code: "code\n== Not a section".to_string(),
+            }]
+        );
+        assert_eq!(table("|===\n|a |b\n").rows.len(), 1);
+
+        // An unterminated conditional hides the rest of the document
+        assert_eq!(
+            blocks("Shown.\n\nifdef::missing[]\nHidden.\n"),
+            vec![Block::Paragraph(vec![Inline::text("Shown.")])]
+        );
+
+        // Broken headers, attribute lists and invalid bytes still decode
+        let book = AsciiDocDecoder::new()
+            .decode(&mut Cursor::new(
+                b"=\n[cols=\"\n|===\n2+|\xff\n|===\nimage::[\n".as_slice(),
+            ))
+            .unwrap();
+        assert_eq!(book.metadata.title, "Untitled");
+    }
+
+    #[test]
+    fn test_includes_resolved_relative_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("chapters")).unwrap();
+        std::fs::write(
+            dir.path().join("chapters/intro.adoc"),
+            "= Introduction\n\ninclude::snippet.txt[lines=2]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("chapters/snippet.txt"),
+            "skip\nIncluded text.\n",
+        )
+        .unwrap();
+
+        let context = DecodeContext::new().with_base_path(dir.path());
+        let book = AsciiDocDecoder::new()
+            .decode_with_context(
+                &mut Cursor::new("= Book\n\ninclude::chapters/intro.adoc[leveloffset=+1]\n"),
+                &context,
+            )
+            .unwrap();
+        assert_eq!(book.chapters[0].title, "Introduction");
+        assert_eq!(
+            book.chapters[0].content[1],
+            Block::Paragraph(vec![Inline::text("Included text.")])
+        );
+    }
+}
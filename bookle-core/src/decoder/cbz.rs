@@ -0,0 +1,460 @@
+//! CBZ comic archive decoder implementation
+//!
+//! A CBZ file is a ZIP archive of page images, read in the natural order of their
+//! names (`page2` before `page10`). An optional `ComicInfo.xml` describes the
+//! issue: its series and number, the creators, whether it is manga read right to
+//! left, and per-page details such as which image is the front cover.
+
+use super::context::sniff_mime_type;
+use super::front_matter::parse_date;
+use super::project::natural_cmp;
+use super::xml;
+use super::DecodeContext;
+use crate::error::ParseError;
+use crate::types::{
+    Block, Book, Chapter, Metadata, ReadingDirection, Resource, ResourceStore, SeriesInfo, TocEntry,
+};
+use roxmltree::Node;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+/// Extensions of the images that make up a comic's pages
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "avif", "bmp"];
+
+/// `ComicInfo.xml` elements naming creators, in the order they are listed
+const CREATOR_ELEMENTS: &[&str] = &[
+    "Writer",
+    "Penciller",
+    "Inker",
+    "Colorist",
+    "Letterer",
+    "CoverArtist",
+];
+
+/// Decoder for CBZ comic archives
+pub struct CbzDecoder;
+
+/// Page details from the `<Pages>` element of `ComicInfo.xml`
+#[derive(Default)]
+struct PageInfo {
+    /// Page type, such as `FrontCover`, `Story` or `Deleted`
+    kind: Option<String>,
+    /// Title of the story or section starting on this page
+    bookmark: Option<String>,
+}
+
+impl CbzDecoder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for CbzDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for CbzDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    /// Decode a comic, counting its pages on the context's progress
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read CBZ: {}", e)))?;
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(data))
+            .map_err(|e| ParseError::MalformedContent(format!("Invalid CBZ archive: {}", e)))?;
+
+        let mut names: Vec<String> = archive
+            .file_names()
+            .filter(|name| is_page_image(name))
+            .map(str::to_string)
+            .collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        if names.is_empty() {
+            return Err(ParseError::MalformedContent(
+                "No images in CBZ archive".to_string(),
+            ));
+        }
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        let mut pages = HashMap::new();
+        let comic_info = archive
+            .file_names()
+            .find(|name| name.rsplit('/').next() == Some("ComicInfo.xml"))
+            .map(str::to_string);
+        if let Some(name) = comic_info {
+            let data = read_entry(&mut archive, &name)?;
+            let text = xml::decode_xml(&data);
+            let document = xml::parse(&text)?;
+            pages = apply_comic_info(document.root_element(), &mut metadata);
+        }
+
+        let progress = context.progress();
+        progress.set_chapters_total(names.len());
+        let mut resources = ResourceStore::new();
+        let mut chapters = Vec::new();
+        let mut toc = Vec::new();
+        let mut cover = None;
+        for (index, name) in names.iter().enumerate() {
+            context.check_cancelled()?;
+            let page = pages.remove(&index).unwrap_or_default();
+            if page.kind.as_deref() == Some("Deleted") {
+                progress.chapter_done();
+                continue;
+            }
+
+            let data = read_entry(&mut archive, name)?;
+            let filename = name.rsplit('/').next().unwrap_or(name);
+            let mime = sniff_mime_type(&data, name);
+            let key = resources.add(Resource::new(mime, data).with_filename(filename));
+
+            let number = chapters.len() + 1;
+            let id = format!("page-{}", number);
+            if let Some(bookmark) = page.bookmark {
+                toc.push(TocEntry::new(bookmark, format!("#{}", id)));
+            }
+            if page.kind.as_deref() == Some("FrontCover") && cover.is_none() {
+                cover = Some(key.clone());
+            }
+
+            chapters.push(
+                Chapter::new(format!("Page {}", number))
+                    .with_id(id)
+                    .with_content(vec![Block::Image {
+                        resource_key: key,
+                        caption: None,
+                        alt: format!("Page {}", number),
+                    }]),
+            );
+            progress.chapter_done();
+        }
+
+        metadata.cover_resource_key = cover.or_else(|| {
+            chapters
+                .first()
+                .and_then(|chapter| match chapter.content.first() {
+                    Some(Block::Image { resource_key, .. }) => Some(resource_key.clone()),
+                    _ => None,
+                })
+        });
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+        book.chapters = chapters;
+        book.toc = toc;
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["cbz"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/vnd.comicbook+zip", "application/x-cbz"]
+    }
+}
+
+/// Whether an archive entry is a page image, rather than a directory, hidden
+/// file or macOS resource fork
+fn is_page_image(name: &str) -> bool {
+    if name.ends_with('/') || name.starts_with("__MACOSX/") {
+        return false;
+    }
+    let file = name.rsplit('/').next().unwrap_or(name);
+    if file.starts_with('.') {
+        return false;
+    }
+    file.rsplit_once('.')
+        .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Read an archive entry
+fn read_entry(
+    archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>,
+    name: &str,
+) -> Result<Vec<u8>, ParseError> {
+    let mut data = Vec::new();
+    archive
+        .by_name(name)
+        .map_err(|e| ParseError::MalformedContent(format!("Invalid CBZ archive: {}", e)))?
+        .read_to_end(&mut data)
+        .map_err(|e| ParseError::MalformedContent(format!("Failed to read {}: {}", name, e)))?;
+    Ok(data)
+}
+
+/// Fill in metadata from `ComicInfo.xml`, returning its page details by image index
+fn apply_comic_info(root: Node, metadata: &mut Metadata) -> HashMap<usize, PageInfo> {
+    let field = |name: &str| {
+        xml::child(root, name)
+            .map(xml::text)
+            .filter(|value| !value.is_empty())
+    };
+    let list = |name: &str| -> Vec<String> {
+        field(name)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let series = field("Series");
+    let number = field("Number");
+    if let Some(title) = field("Title") {
+        metadata.title = title;
+    } else if let Some(series) = &series {
+        metadata.title = match &number {
+            Some(number) => format!("{} #{}", series, number),
+            None => series.clone(),
+        };
+    }
+    if let Some(series) = series {
+        let position = number.as_deref().and_then(|n| n.parse().ok());
+        metadata.series = Some(SeriesInfo::new(series, position));
+    }
+
+    for element in CREATOR_ELEMENTS {
+        for creator in list(element) {
+            if !metadata.creator.contains(&creator) {
+                metadata.creator.push(creator);
+            }
+        }
+    }
+
+    metadata.subject = list("Genre");
+    for tag in list("Tags") {
+        if !metadata.subject.contains(&tag) {
+            metadata.subject.push(tag);
+        }
+    }
+    metadata.description = field("Summary");
+    metadata.publisher = field("Publisher");
+    if let Some(language) = field("LanguageISO") {
+        metadata.language = language;
+    }
+    if let Some(year) = field("Year") {
+        let date = match (field("Month"), field("Day")) {
+            (Some(month), Some(day)) => format!("{}-{:0>2}-{:0>2}", year, month, day),
+            (Some(month), None) => format!("{}-{:0>2}", year, month),
+            _ => year,
+        };
+        metadata.date = parse_date(&date);
+    }
+    if field("Manga").as_deref() == Some("YesAndRightToLeft") {
+        metadata.reading_direction = ReadingDirection::RightToLeft;
+    }
+
+    xml::child(root, "Pages")
+        .into_iter()
+        .flat_map(|pages| xml::children(pages, "Page"))
+        .filter_map(|page| {
+            let index = page.attribute("Image")?.parse().ok()?;
+            let info = PageInfo {
+                kind: page.attribute("Type").map(str::to_string),
+                bookmark: page
+                    .attribute("Bookmark")
+                    .filter(|bookmark| !bookmark.trim().is_empty())
+                    .map(|bookmark| bookmark.trim().to_string()),
+            };
+            Some((index, info))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use std::io::Write;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn cbz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn decode(data: Vec<u8>) -> Book {
+        CbzDecoder::new().decode(&mut Cursor::new(data)).unwrap()
+    }
+
+    /// Distinct PNG bytes, so that pages don't share a resource
+    fn page(n: u8) -> Vec<u8> {
+        [PNG, &[n]].concat()
+    }
+
+    #[test]
+    fn test_decode_pages_in_natural_order() {
+        let (p1, p2, p10) = (page(1), page(2), page(10));
+        let book = decode(cbz(&[
+            ("comic/page10.png", &p10),
+            ("comic/page2.png", &p2),
+            ("comic/page1.png", &p1),
+            ("comic/.thumbs.png", PNG),
+            ("__MACOSX/comic/._page1.png", PNG),
+            ("comic/notes.txt", b"not a page"),
+        ]));
+
+        assert_eq!(book.chapters.len(), 3);
+        let files: Vec<_> = book
+            .chapters
+            .iter()
+            .map(|chapter| match &chapter.content[0] {
+                Block::Image { resource_key, .. } => book
+                    .resources
+                    .get(resource_key)
+                    .and_then(|r| r.original_filename.clone())
+                    .unwrap(),
+                other => panic!("expected image, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(files, vec!["page1.png", "page2.png", "page10.png"]);
+        assert_eq!(book.chapters[0].title, "Page 1");
+
+        let first = match &book.chapters[0].content[0] {
+            Block::Image { resource_key, .. } => resource_key.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(book.metadata.cover_resource_key, Some(first));
+        assert_eq!(
+            book.metadata.reading_direction,
+            ReadingDirection::LeftToRight
+        );
+    }
+
+    #[test]
+    fn test_same_named_pages_stay_apart_in_epub() {
+        use crate::encoder::{Encoder, EpubEncoder};
+        use std::io::Read;
+
+        let (p1, p2) = (page(1), page(2));
+        let book = decode(cbz(&[("vol1/01.png", &p1), ("vol2/01.png", &p2)]));
+        let mut output = Vec::new();
+        EpubEncoder::new().encode(&book, &mut output).unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(output)).unwrap();
+        let mut read = |name: &str| {
+            let mut data = Vec::new();
+            archive
+                .by_name(&format!("OEBPS/{}", name))
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            data
+        };
+        let image = |xhtml: Vec<u8>| {
+            let xhtml = String::from_utf8(xhtml).unwrap();
+            let start = xhtml.find("src=\"").unwrap() + 5;
+            let end = start + xhtml[start..].find('"').unwrap();
+            xhtml[start..end].to_string()
+        };
+        let first = image(read("chapter_1.xhtml"));
+        let second = image(read("chapter_2.xhtml"));
+        assert_ne!(first, second);
+        assert_eq!(read(&first), p1);
+        assert_eq!(read(&second), p2);
+    }
+
+    #[test]
+    fn test_decode_comic_info() {
+        let comic_info = br#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Series>Nausicaa</Series>
+  <Number>2</Number>
+  <Summary>The war spreads.</Summary>
+  <Year>1984</Year>
+  <Month>3</Month>
+  <Writer>Hayao Miyazaki</Writer>
+  <Penciller>Hayao Miyazaki, Assistant</Penciller>
+  <Publisher>Tokuma Shoten</Publisher>
+  <Genre>Science Fiction, Adventure</Genre>
+  <LanguageISO>ja</LanguageISO>
+  <Manga>YesAndRightToLeft</Manga>
+  <Pages>
+    <Page Image="0" Type="InnerCover"/>
+    <Page Image="1" Type="FrontCover" Bookmark="Chapter 1"/>
+    <Page Image="2" Type="Deleted"/>
+  </Pages>
+</ComicInfo>"#;
+        let (p1, p2, p3) = (page(1), page(2), page(3));
+        let book = decode(cbz(&[
+            ("ComicInfo.xml", comic_info),
+            ("001.png", &p1),
+            ("002.png", &p2),
+            ("003.png", &p3),
+        ]));
+
+        let metadata = &book.metadata;
+        assert_eq!(metadata.title, "Nausicaa #2");
+        let series = metadata.series.as_ref().unwrap();
+        assert_eq!(series.name, "Nausicaa");
+        assert_eq!(series.position, Some(2.0));
+        assert_eq!(metadata.creator, vec!["Hayao Miyazaki", "Assistant"]);
+        assert_eq!(metadata.subject, vec!["Science Fiction", "Adventure"]);
+        assert_eq!(metadata.description.as_deref(), Some("The war spreads."));
+        assert_eq!(metadata.publisher.as_deref(), Some("Tokuma Shoten"));
+        assert_eq!(metadata.language, "ja");
+        assert_eq!(
+            metadata.date.map(|d| d.format("%Y-%m").to_string()),
+            Some("1984-03".to_string())
+        );
+        assert_eq!(metadata.reading_direction, ReadingDirection::RightToLeft);
+
+        // The deleted page is dropped, and the marked front cover is the cover
+        assert_eq!(book.chapters.len(), 2);
+        let second = match &book.chapters[1].content[0] {
+            Block::Image { resource_key, .. } => resource_key.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(metadata.cover_resource_key, Some(second));
+        assert_eq!(book.toc.len(), 1);
+        assert_eq!(book.toc[0].title, "Chapter 1");
+        assert_eq!(book.toc[0].href, "#page-2");
+    }
+
+    #[test]
+    fn test_progress_and_cancellation() {
+        use crate::progress::CancellationToken;
+        use std::sync::{Arc, Mutex};
+
+        let (p1, p2) = (page(1), page(2));
+        let data = cbz(&[("page1.png", &p1), ("page2.png", &p2)]);
+
+        let last = Arc::new(Mutex::new(None));
+        let seen = last.clone();
+        let context = DecodeContext::new()
+            .with_progress(move |progress| *seen.lock().unwrap() = Some(*progress));
+        CbzDecoder::new()
+            .decode_with_context(&mut Cursor::new(data.clone()), &context)
+            .unwrap();
+        let progress = last.lock().unwrap().unwrap();
+        assert_eq!(progress.chapters, 2);
+        assert_eq!(progress.chapters_total, Some(2));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let context = DecodeContext::new().with_cancellation(token);
+        let result = CbzDecoder::new().decode_with_context(&mut Cursor::new(data), &context);
+        assert!(matches!(result, Err(ParseError::Cancelled)));
+    }
+}
@@ -0,0 +1,480 @@
+//! Chapter splitting for decoders that produce a flat stream of blocks
+//!
+//! Many formats, from Markdown to PDF, arrive as one long sequence of blocks. A
+//! [`ChapterSplitter`] decides where that sequence is cut into chapters, and
+//! [`SplitOptions`] override a decoder's default through
+//! [`Decoder::set_split_options`](super::Decoder::set_split_options).
+//! Formats that keep their notes apart from the text, such as FB2 and DOCX, then
+//! place each footnote in the chapter that refers to it.
+
+use super::markdown::inlines_to_text;
+use crate::types::{Block, Chapter, Inline};
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+/// Where a block stream is divided into chapters
+#[derive(Debug, Clone)]
+pub enum SplitStrategy {
+    /// Headings at this level or above (1 splits at H1 only, 2 at H1 and H2)
+    HeadingLevel(u8),
+    /// Headings whose text matches a pattern, such as `^Chapter \d+`
+    HeadingPattern(Regex),
+    /// Thematic breaks (`***` in Markdown, `<hr>` in HTML), which are removed
+    ThematicBreak,
+    /// Page breaks reported by the decoder
+    PageBreak,
+    /// No boundaries; only a maximum chapter size splits the content
+    None,
+}
+
+impl FromStr for SplitStrategy {
+    type Err = String;
+
+    /// Parse `h1`-`h6`, `hr`, `page`, `none` or `regex:<pattern>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(pattern) = s.strip_prefix("regex:") {
+            return Regex::new(pattern)
+                .map(SplitStrategy::HeadingPattern)
+                .map_err(|e| format!("invalid heading pattern: {}", e));
+        }
+
+        match s.to_lowercase().as_str() {
+            "hr" | "break" | "thematic-break" => Ok(SplitStrategy::ThematicBreak),
+            "page" | "page-break" => Ok(SplitStrategy::PageBreak),
+            "none" => Ok(SplitStrategy::None),
+            level => level
+                .strip_prefix('h')
+                .and_then(|n| n.parse().ok())
+                .filter(|n| (1..=6).contains(n))
+                .map(SplitStrategy::HeadingLevel)
+                .ok_or_else(|| {
+                    format!(
+                        "unknown split strategy '{}' (expected h1-h6, hr, page, none or regex:<pattern>)",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl fmt::Display for SplitStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitStrategy::HeadingLevel(level) => write!(f, "h{}", level),
+            SplitStrategy::HeadingPattern(pattern) => write!(f, "regex:{}", pattern),
+            SplitStrategy::ThematicBreak => write!(f, "hr"),
+            SplitStrategy::PageBreak => write!(f, "page"),
+            SplitStrategy::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Divides a decoded block stream into chapters
+#[derive(Debug, Clone)]
+pub struct ChapterSplitter {
+    /// Where chapters begin
+    strategy: SplitStrategy,
+    /// Chapters with more characters of text than this are split further
+    max_size: Option<usize>,
+}
+
+/// What a block means for the chapter it appears in
+enum Boundary {
+    /// Starts a chapter with this title, and belongs to it
+    Heading(String),
+    /// Starts an untitled chapter, and belongs to it
+    Break,
+    /// Separates chapters, and belongs to neither
+    Separator,
+}
+
+impl ChapterSplitter {
+    pub fn new(strategy: SplitStrategy) -> Self {
+        Self {
+            strategy,
+            max_size: None,
+        }
+    }
+
+    /// Change where chapters begin
+    pub fn with_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Split chapters with more than `chars` characters of text at block boundaries
+    pub fn with_max_size(mut self, chars: usize) -> Self {
+        self.max_size = Some(chars.max(1));
+        self
+    }
+
+    /// The strategy in use
+    pub fn strategy(&self) -> &SplitStrategy {
+        &self.strategy
+    }
+
+    /// Split blocks into chapters
+    pub fn split(&self, blocks: Vec<Block>) -> Vec<Chapter> {
+        self.split_with_page_breaks(blocks, &[])
+    }
+
+    /// Split blocks into chapters, given the sorted indices of blocks that start a new page
+    pub fn split_with_page_breaks(
+        &self,
+        blocks: Vec<Block>,
+        page_breaks: &[usize],
+    ) -> Vec<Chapter> {
+        let mut sections: Vec<(Option<String>, Vec<Block>)> = Vec::new();
+        let mut current_blocks = Vec::new();
+        let mut current_title: Option<String> = None;
+
+        for (index, block) in blocks.into_iter().enumerate() {
+            let page_break = index > 0 && page_breaks.binary_search(&index).is_ok();
+
+            match self.boundary(&block, page_break) {
+                Some(boundary) => {
+                    if !current_blocks.is_empty() || current_title.is_some() {
+                        sections.push((current_title.take(), std::mem::take(&mut current_blocks)));
+                    }
+                    match boundary {
+                        Boundary::Heading(title) => {
+                            current_title = Some(title);
+                            current_blocks.push(block);
+                        }
+                        Boundary::Break => current_blocks.push(block),
+                        Boundary::Separator => {}
+                    }
+                }
+                None => current_blocks.push(block),
+            }
+        }
+
+        if !current_blocks.is_empty() || current_title.is_some() {
+            sections.push((current_title, current_blocks));
+        }
+
+        let mut chapters = Vec::new();
+        for (title, blocks) in sections {
+            // Chapters started by a break take their title from their first heading
+            let title = title
+                .or_else(|| first_heading(&blocks))
+                .unwrap_or_else(|| "Untitled".to_string());
+
+            match self.max_size {
+                Some(max_size) => chapters.extend(split_by_size(title, blocks, max_size)),
+                None => chapters.push(Chapter::new(title).with_content(blocks)),
+            }
+        }
+
+        // If no chapters were created, make a single chapter
+        if chapters.is_empty() {
+            chapters.push(Chapter::new("Content").with_content(vec![]));
+        }
+
+        chapters
+    }
+
+    /// Whether a block starts a new chapter
+    fn boundary(&self, block: &Block, page_break: bool) -> Option<Boundary> {
+        match (&self.strategy, block) {
+            (SplitStrategy::HeadingLevel(max), Block::Header { level, content, .. })
+                if level <= max =>
+            {
+                Some(Boundary::Heading(inlines_to_text(content)))
+            }
+            (SplitStrategy::HeadingPattern(pattern), Block::Header { content, .. }) => {
+                let text = inlines_to_text(content);
+                pattern.is_match(&text).then_some(Boundary::Heading(text))
+            }
+            (SplitStrategy::ThematicBreak, Block::ThematicBreak) => Some(Boundary::Separator),
+            (SplitStrategy::PageBreak, _) if page_break => Some(Boundary::Break),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ChapterSplitter {
+    fn default() -> Self {
+        Self::new(SplitStrategy::HeadingLevel(1))
+    }
+}
+
+/// Overrides for where a decoder splits chapters
+#[derive(Debug, Clone, Default)]
+pub struct SplitOptions {
+    /// Where chapters begin, instead of the decoder's default
+    pub strategy: Option<SplitStrategy>,
+    /// Split chapters with more characters of text than this
+    pub max_chapter_size: Option<usize>,
+}
+
+impl SplitOptions {
+    /// Whether nothing is overridden
+    pub fn is_empty(&self) -> bool {
+        self.strategy.is_none() && self.max_chapter_size.is_none()
+    }
+
+    /// Override a splitter's settings
+    pub fn apply(&self, splitter: &mut ChapterSplitter) {
+        if let Some(strategy) = &self.strategy {
+            splitter.strategy = strategy.clone();
+        }
+        if let Some(chars) = self.max_chapter_size {
+            splitter.max_size = Some(chars.max(1));
+        }
+    }
+}
+
+/// Append each footnote to the chapter that first refers to it, and any others
+/// to a closing "Notes" chapter
+pub(crate) fn place_footnotes(chapters: &mut Vec<Chapter>, mut footnotes: Vec<(String, Block)>) {
+    for chapter in chapters.iter_mut() {
+        let mut ids = Vec::new();
+        footnote_refs(&chapter.content, &mut ids);
+        for id in ids {
+            if let Some(index) = footnotes.iter().position(|(note_id, _)| *note_id == id) {
+                chapter.content.push(footnotes.remove(index).1);
+            }
+        }
+    }
+
+    if !footnotes.is_empty() {
+        let content = footnotes
+            .into_iter()
+            .map(|(_, footnote)| footnote)
+            .collect();
+        chapters.push(Chapter::new("Notes").with_content(content));
+    }
+}
+
+/// Collect the ids of footnote references, in document order
+fn footnote_refs(blocks: &[Block], ids: &mut Vec<String>) {
+    fn inline_refs(inlines: &[Inline], ids: &mut Vec<String>) {
+        for inline in inlines {
+            match inline {
+                Inline::FootnoteRef { id } if !ids.contains(id) => ids.push(id.clone()),
+                Inline::Bold(children)
+                | Inline::Italic(children)
+                | Inline::Strikethrough(children)
+                | Inline::Superscript(children)
+                | Inline::Subscript(children)
+                | Inline::Link { children, .. } => inline_refs(children, ids),
+                _ => {}
+            }
+        }
+    }
+
+    for block in blocks {
+        match block {
+            Block::Paragraph(content) | Block::Header { content, .. } => inline_refs(content, ids),
+            Block::Blockquote(children)
+            | Block::Footnote {
+                content: children, ..
+            }
+            | Block::Admonition {
+                content: children, ..
+            }
+            | Block::Container {
+                content: children, ..
+            } => footnote_refs(children, ids),
+            Block::List { items, .. } => {
+                for item in items {
+                    footnote_refs(item, ids);
+                }
+            }
+            Block::DefinitionList(items) => {
+                for item in items {
+                    inline_refs(&item.term, ids);
+                    for definition in &item.definitions {
+                        footnote_refs(definition, ids);
+                    }
+                }
+            }
+            Block::Table(table) => {
+                for cell in table.headers.iter().chain(table.rows.iter().flatten()) {
+                    inline_refs(&cell.content, ids);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Split an oversized chapter into numbered parts at block boundaries
+fn split_by_size(title: String, blocks: Vec<Block>, max_size: usize) -> Vec<Chapter> {
+    let mut parts: Vec<Vec<Block>> = vec![Vec::new()];
+    let mut size = 0;
+
+    for block in blocks {
+        let len = text_len(&block);
+        let current = parts.last_mut().unwrap();
+        if !current.is_empty() && size + len > max_size {
+            parts.push(Vec::new());
+            size = 0;
+        }
+        size += len;
+        parts.last_mut().unwrap().push(block);
+    }
+
+    let count = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, blocks)| {
+            let title = if count > 1 && i > 0 {
+                format!("{} ({})", title, i + 1)
+            } else {
+                title.clone()
+            };
+            Chapter::new(title).with_content(blocks)
+        })
+        .collect()
+}
+
+/// Characters of text in a block
+fn text_len(block: &Block) -> usize {
+    match block {
+        Block::Header { content, .. } | Block::Paragraph(content) => {
+            inlines_to_text(content).chars().count()
+        }
+        Block::List { items, .. } => items.iter().flatten().map(text_len).sum(),
+        Block::Blockquote(children)
+        | Block::Footnote {
+            content: children, ..
+        }
+        | Block::Admonition {
+            content: children, ..
+        }
+        | Block::Container {
+            content: children, ..
+        } => children.iter().map(text_len).sum(),
+        Block::DefinitionList(items) => items
+            .iter()
+            .map(|item| {
+                inlines_to_text(&item.term).chars().count()
+                    + item
+                        .definitions
+                        .iter()
+                        .flatten()
+                        .map(text_len)
+                        .sum::<usize>()
+            })
+            .sum(),
+        Block::CodeBlock { code, .. } => code.chars().count(),
+        Block::Table(table) => table
+            .headers
+            .iter()
+            .chain(table.rows.iter().flatten())
+            .map(|cell| inlines_to_text(&cell.content).chars().count())
+            .sum(),
+        Block::Image { alt, caption, .. } => {
+            alt.chars().count() + caption.as_ref().map_or(0, |c| c.chars().count())
+        }
+        Block::ThematicBreak => 0,
+    }
+}
+
+/// Text of the first heading of any level
+fn first_heading(blocks: &[Block]) -> Option<String> {
+    blocks.iter().find_map(|block| match block {
+        Block::Header { content, .. } => Some(inlines_to_text(content)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Inline;
+
+    fn heading(level: u8, text: &str) -> Block {
+        Block::header(level, vec![Inline::text(text)])
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::paragraph(vec![Inline::text(text)])
+    }
+
+    fn titles(chapters: &[Chapter]) -> Vec<&str> {
+        chapters.iter().map(|c| c.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_split_heading_level() {
+        let blocks = vec![
+            paragraph("Preface"),
+            heading(2, "One"),
+            heading(3, "Section"),
+            heading(2, "Two"),
+        ];
+
+        let chapters = ChapterSplitter::new(SplitStrategy::HeadingLevel(2)).split(blocks.clone());
+        assert_eq!(titles(&chapters), vec!["Untitled", "One", "Two"]);
+
+        // Without a chapter heading, the first heading of any level names the chapter
+        let chapters = ChapterSplitter::default().split(blocks);
+        assert_eq!(titles(&chapters), vec!["One"]);
+    }
+
+    #[test]
+    fn test_split_heading_pattern() {
+        let blocks = vec![
+            heading(1, "Chapter 1"),
+            heading(1, "Interlude"),
+            heading(1, "Chapter 2"),
+        ];
+        let splitter = ChapterSplitter::new("regex:^Chapter \\d+".parse().unwrap());
+
+        let chapters = splitter.split(blocks);
+        assert_eq!(titles(&chapters), vec!["Chapter 1", "Chapter 2"]);
+        assert_eq!(chapters[0].content.len(), 2);
+    }
+
+    #[test]
+    fn test_split_thematic_break() {
+        let blocks = vec![
+            paragraph("One"),
+            Block::ThematicBreak,
+            heading(3, "Two"),
+            paragraph("Text"),
+            Block::ThematicBreak,
+        ];
+
+        let chapters = ChapterSplitter::new(SplitStrategy::ThematicBreak).split(blocks);
+        assert_eq!(titles(&chapters), vec!["Untitled", "Two"]);
+        assert_eq!(chapters[1].content.len(), 2);
+    }
+
+    #[test]
+    fn test_split_page_breaks() {
+        let blocks = vec![paragraph("a"), paragraph("b"), paragraph("c")];
+
+        let chapters =
+            ChapterSplitter::new(SplitStrategy::PageBreak).split_with_page_breaks(blocks, &[0, 2]);
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].content, vec![paragraph("c")]);
+    }
+
+    #[test]
+    fn test_split_max_size() {
+        let blocks = vec![heading(1, "Long"), paragraph("abcdef"), paragraph("ghijkl")];
+
+        let chapters = ChapterSplitter::default().with_max_size(10).split(blocks);
+        assert_eq!(titles(&chapters), vec!["Long", "Long (2)"]);
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert!(matches!(
+            "h3".parse::<SplitStrategy>(),
+            Ok(SplitStrategy::HeadingLevel(3))
+        ));
+        assert!(matches!(
+            "hr".parse::<SplitStrategy>(),
+            Ok(SplitStrategy::ThematicBreak)
+        ));
+        assert!("h7".parse::<SplitStrategy>().is_err());
+        assert!("regex:(".parse::<SplitStrategy>().is_err());
+    }
+}
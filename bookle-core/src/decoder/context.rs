@@ -0,0 +1,538 @@
+//! Decode context for resolving assets referenced by a document
+//!
+//! Formats such as Markdown and HTML refer to images by relative path rather than
+//! embedding them. A [`DecodeContext`] tells the decoder where the document came
+//! from, so those files can be loaded into the book's [`ResourceStore`].
+
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::error::{ParseError, StorageError};
+use crate::progress::{CancellationToken, Progress, ProgressTracker};
+use crate::storage::StorageProvider;
+use crate::types::{Block, Resource, ResourceStore};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Where referenced assets are read from
+enum AssetSource {
+    /// A local directory
+    Directory(PathBuf),
+    /// A storage backend, with a path prefix for the document's location
+    Storage {
+        provider: Arc<dyn StorageProvider>,
+        prefix: String,
+    },
+}
+
+/// Context passed to decoders alongside the input stream
+#[derive(Default)]
+pub struct DecodeContext {
+    source: Option<AssetSource>,
+    diagnostics: Diagnostics,
+    progress: ProgressTracker,
+}
+
+impl DecodeContext {
+    /// Create a context without an asset source
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve relative asset paths against a local directory
+    pub fn with_base_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.source = Some(AssetSource::Directory(path.into()));
+        self
+    }
+
+    /// Resolve relative asset paths through a storage provider, under `prefix`
+    ///
+    /// Storage reads are performed synchronously. When called from within a Tokio
+    /// runtime, that runtime must be multi-threaded.
+    pub fn with_storage(
+        mut self,
+        provider: Arc<dyn StorageProvider>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.source = Some(AssetSource::Storage {
+            provider,
+            prefix: prefix.into(),
+        });
+        self
+    }
+
+    /// Call `listener` with the decode's progress as it changes
+    ///
+    /// Bytes are only counted for input read through [`ProgressTracker::reader`].
+    pub fn with_progress(mut self, listener: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.progress.set_listener(listener);
+        self
+    }
+
+    /// Stop decoding when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.progress.set_cancellation(token);
+        self
+    }
+
+    /// Progress of the decode, for decoders to update
+    pub fn progress(&self) -> &ProgressTracker {
+        &self.progress
+    }
+
+    /// Fail with [`ParseError::Cancelled`] if decoding has been cancelled
+    pub fn check_cancelled(&self) -> Result<(), ParseError> {
+        if self.progress.is_cancelled() {
+            Err(ParseError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether assets can be loaded through this context
+    pub fn has_asset_source(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Read an asset by path relative to the document
+    ///
+    /// Paths may not escape the base location.
+    pub fn read_asset(&self, path: &str) -> Result<Vec<u8>, StorageError> {
+        let relative = normalize_path(path).ok_or_else(|| {
+            StorageError::PermissionDenied(format!("{} is outside the document", path))
+        })?;
+
+        match &self.source {
+            None => Err(StorageError::NotFound(path.to_string())),
+            Some(AssetSource::Directory(base)) => {
+                let full = base.join(&relative);
+                std::fs::read(&full).map_err(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => {
+                        StorageError::NotFound(full.display().to_string())
+                    }
+                    _ => StorageError::BackendError(format!("{}: {}", full.display(), e)),
+                })
+            }
+            Some(AssetSource::Storage { provider, prefix }) => {
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                let full = if prefix.is_empty() {
+                    relative
+                } else {
+                    format!("{}/{}", prefix.trim_end_matches('/'), relative)
+                };
+                block_on(provider.read(&full))
+            }
+        }
+    }
+
+    /// List every file under `dir`, as paths relative to the document
+    ///
+    /// Only local directories can be listed; hidden files and directories are skipped.
+    pub fn list_assets(&self, dir: &str) -> Result<Vec<String>, StorageError> {
+        let relative = normalize_path(dir).ok_or_else(|| {
+            StorageError::PermissionDenied(format!("{} is outside the document", dir))
+        })?;
+
+        match &self.source {
+            Some(AssetSource::Directory(base)) => {
+                let mut files = Vec::new();
+                list_directory(base, &relative, &mut files)?;
+                files.sort();
+                Ok(files)
+            }
+            _ => Err(StorageError::BackendError(
+                "only local directories can be listed".to_string(),
+            )),
+        }
+    }
+
+    /// Record a non-fatal problem found while decoding
+    pub fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.report(diagnostic);
+    }
+
+    /// Diagnostics recorded so far
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.to_vec()
+    }
+
+    /// Messages of the diagnostics recorded so far
+    pub fn warnings(&self) -> Vec<String> {
+        self.diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+}
+
+/// Loads referenced assets into a resource store, remembering what was already loaded
+pub(crate) struct AssetLoader<'a> {
+    context: &'a DecodeContext,
+    resources: &'a mut ResourceStore,
+    loaded: HashMap<String, Option<String>>,
+    /// Directory links are relative to, for documents below the base location
+    dir: String,
+}
+
+impl<'a> AssetLoader<'a> {
+    pub fn new(context: &'a DecodeContext, resources: &'a mut ResourceStore) -> Self {
+        Self {
+            context,
+            resources,
+            loaded: HashMap::new(),
+            dir: String::new(),
+        }
+    }
+
+    /// Resolve subsequent links relative to `dir` rather than the base location
+    pub fn set_dir(&mut self, dir: impl Into<String>) {
+        self.dir = dir.into();
+    }
+
+    /// Load the asset a document link points to, returning its resource key
+    ///
+    /// Remote URLs and links that can't be loaded return `None`; failures are
+    /// reported as diagnostics on the context.
+    pub fn load(&mut self, link: &str) -> Option<String> {
+        if !self.context.has_asset_source() {
+            return None;
+        }
+        let Some(path) = resolve_link(&self.dir, link) else {
+            // Remote links are left alone, but local files outside the base
+            // location are refused, so say why they are missing
+            if local_path(link).is_some() && self.loaded.insert(link.to_string(), None).is_none() {
+                self.context.report(
+                    Diagnostic::warning(
+                        "outside-base-path",
+                        format!("Not loading {}, which is outside the base location", link),
+                    )
+                    .with_path(link),
+                );
+            }
+            return None;
+        };
+
+        if let Some(key) = self.loaded.get(&path) {
+            return key.clone();
+        }
+
+        let key = match self.context.read_asset(&path) {
+            Ok(data) => {
+                let mime = sniff_mime_type(&data, &path);
+                let filename = path.rsplit('/').next().unwrap_or(&path).to_string();
+                Some(
+                    self.resources
+                        .add(Resource::new(mime, data).with_filename(filename)),
+                )
+            }
+            Err(StorageError::NotFound(_)) => {
+                self.context.report(
+                    Diagnostic::warning("missing-asset", format!("Missing asset: {}", path))
+                        .with_path(path.clone()),
+                );
+                None
+            }
+            Err(e) => {
+                self.context.report(
+                    Diagnostic::warning(
+                        "unreadable-asset",
+                        format!("Could not load asset {}: {}", path, e),
+                    )
+                    .with_path(path.clone()),
+                );
+                None
+            }
+        };
+
+        self.loaded.insert(path, key.clone());
+        key
+    }
+
+    /// Load every image referenced by `blocks`, rewriting their keys
+    pub fn load_images(&mut self, blocks: &mut [Block]) {
+        rewrite_images(blocks, &mut |link| self.load(link));
+    }
+}
+
+/// Point every image in `blocks` at the key `load` gives for its current one
+///
+/// Images for which `load` returns `None` are left as they are.
+pub(crate) fn rewrite_images(blocks: &mut [Block], load: &mut dyn FnMut(&str) -> Option<String>) {
+    for block in blocks {
+        match block {
+            Block::Image { resource_key, .. } => {
+                if let Some(key) = load(resource_key) {
+                    *resource_key = key;
+                }
+            }
+            Block::Blockquote(children)
+            | Block::Footnote {
+                content: children, ..
+            }
+            | Block::Admonition {
+                content: children, ..
+            }
+            | Block::Container {
+                content: children, ..
+            } => rewrite_images(children, load),
+            Block::List { items, .. } => {
+                for item in items {
+                    rewrite_images(item, load);
+                }
+            }
+            Block::DefinitionList(items) => {
+                for definition in items.iter_mut().flat_map(|item| &mut item.definitions) {
+                    rewrite_images(definition, load);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Local file path for a document link, or `None` for remote and data URLs
+fn local_path(link: &str) -> Option<String> {
+    let link = link.trim();
+    let link = link.split(['#', '?']).next().unwrap_or(link);
+    if link.is_empty() || link.starts_with("//") {
+        return None;
+    }
+
+    // Anything with a URL scheme is remote ("C:" style drive letters aside)
+    if let Some((scheme, _)) = link.split_once(':') {
+        let is_scheme = scheme.len() > 1
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if is_scheme {
+            return None;
+        }
+    }
+
+    Some(percent_decode(link.strip_prefix("file://").unwrap_or(link)))
+}
+
+/// Resolve a document link relative to `dir`, giving a path relative to the base location
+///
+/// Returns `None` for remote links and links that escape the base location.
+pub(crate) fn resolve_link(dir: &str, link: &str) -> Option<String> {
+    let path = local_path(link)?;
+    let joined = if path.starts_with('/') || dir.is_empty() {
+        path
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), path)
+    };
+
+    let normalized = normalize_path(&joined)?;
+    Some(normalized.to_string_lossy().replace('\\', "/"))
+}
+
+/// Recursively collect the files below `base/dir`
+fn list_directory(base: &Path, dir: &Path, files: &mut Vec<String>) -> Result<(), StorageError> {
+    let full = base.join(dir);
+    let entries = std::fs::read_dir(&full).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => StorageError::NotFound(full.display().to_string()),
+        _ => StorageError::BackendError(format!("{}: {}", full.display(), e)),
+    })?;
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = dir.join(&name);
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => list_directory(base, &path, files)?,
+            Ok(_) => files.push(path.to_string_lossy().replace('\\', "/")),
+            Err(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Resolve `.` and `..` components, returning `None` if the path escapes its base
+fn normalize_path(path: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Decode `%XX` escapes in a URL path
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Determine the MIME type of an asset from its content, falling back to its extension
+pub(crate) fn sniff_mime_type(data: &[u8], path: &str) -> String {
+    let sniffed = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if data.starts_with(b"BM") && path.to_lowercase().ends_with(".bmp") {
+        Some("image/bmp")
+    } else if data.len() >= 12 && &data[4..12] == b"ftypavif" {
+        Some("image/avif")
+    } else {
+        let head = String::from_utf8_lossy(&data[..data.len().min(512)]).to_lowercase();
+        head.contains("<svg").then_some("image/svg+xml")
+    };
+
+    if let Some(mime) = sniffed {
+        return mime.to_string();
+    }
+
+    let extension = path.rsplit('.').next().unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "css" => "text/css",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+    .to_string()
+}
+
+/// Run a future to completion from synchronous code
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start a Tokio runtime for storage access")
+            .block_on(future),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(
+            local_path("img/fig%201.png#x"),
+            Some("img/fig 1.png".into())
+        );
+        assert_eq!(local_path("https://example.com/a.png"), None);
+        assert_eq!(local_path("data:image/png;base64,AAAA"), None);
+        assert_eq!(local_path("//cdn.example.com/a.png"), None);
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        assert_eq!(
+            resolve_link("part1", "../images/a.png"),
+            Some("images/a.png".into())
+        );
+        assert_eq!(
+            resolve_link("part1", "/cover.png"),
+            Some("cover.png".into())
+        );
+        assert_eq!(resolve_link("", "../a.png"), None);
+    }
+
+    #[test]
+    fn test_normalize_path_rejects_escape() {
+        assert_eq!(normalize_path("a/../b.png"), Some(PathBuf::from("b.png")));
+        assert_eq!(normalize_path("../secret"), None);
+    }
+
+    #[test]
+    fn test_sniff_mime_type() {
+        assert_eq!(
+            sniff_mime_type(b"\x89PNG\r\n\x1a\n....", "x.jpg"),
+            "image/png"
+        );
+        assert_eq!(sniff_mime_type(b"<?xml?><svg/>", "x"), "image/svg+xml");
+        assert_eq!(sniff_mime_type(b"????", "photo.JPG"), "image/jpeg");
+    }
+
+    #[test]
+    fn test_load_from_directory_warns_on_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("img")).unwrap();
+        std::fs::write(dir.path().join("img/a.gif"), b"GIF89a...").unwrap();
+
+        let context = DecodeContext::new().with_base_path(dir.path());
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(&context, &mut resources);
+
+        let key = loader.load("img/a.gif").unwrap();
+        assert!(loader.load("img/missing.png").is_none());
+
+        assert_eq!(resources.get(&key).unwrap().mime_type, "image/gif");
+        assert_eq!(context.warnings(), vec!["Missing asset: img/missing.png"]);
+    }
+
+    #[test]
+    fn test_load_warns_on_escape() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = DecodeContext::new().with_base_path(dir.path());
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(&context, &mut resources);
+
+        assert!(loader.load("../secret.png").is_none());
+        assert!(loader.load("../secret.png").is_none());
+        assert!(loader.load("https://example.com/a.png").is_none());
+
+        let diagnostics = context.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "outside-base-path");
+        assert_eq!(
+            diagnostics[0].location.path.as_deref(),
+            Some("../secret.png")
+        );
+    }
+
+    #[test]
+    fn test_load_from_storage() {
+        let storage = Arc::new(MemoryStorage::new());
+        block_on(storage.write("books/one/cover.png", b"\x89PNG\r\n\x1a\n".to_vec())).unwrap();
+
+        let context = DecodeContext::new().with_storage(storage, "books/one");
+        assert!(context.read_asset("./cover.png").is_ok());
+        assert!(context.read_asset("../two/cover.png").is_err());
+    }
+}
@@ -0,0 +1,564 @@
+//! Format detection from file contents
+//!
+//! File names are not always trustworthy: a `.epub` may be a Kobo KEPUB, a
+//! `.mobi` a KF8 book, a `.prc` a PalmDOC database, and uploads often have no
+//! extension at all. [`detect_format`] looks at the bytes instead: signatures
+//! such as `%PDF` and `ITOLITLS`, the `mimetype` entry and layout of ZIP
+//! containers, the type and creator codes of Palm databases, and the root
+//! element of XML documents. Plain-text formats such as Markdown have no
+//! signature and are left to the file extension.
+//!
+//! [`detect_format_in`] does the same for a file that has not been read into
+//! memory, reading only its start and, for ZIP containers, the directory at
+//! its end.
+
+use super::{decoder_for_extension, docbook, xml, Decoder};
+use std::fmt;
+use std::io::{self, Cursor, Read, Seek};
+use zip::ZipArchive;
+
+/// How many bytes at the start of a file are searched for a text signature
+const SNIFF_LEN: usize = 1024;
+
+/// How many bytes at the start of a file [`detect_format_in`] reads
+pub const HEAD_LEN: usize = 64 * 1024;
+
+/// Most content documents read from an EPUB when looking for Kobo spans
+const KOBO_SCAN_ENTRIES: usize = 3;
+
+/// Host of the namespaces Kobo's tools declare in a KEPUB's package document
+const KOBO_HOST: &str = "kobobooks.com";
+
+/// Version of the Mobipocket format used by KF8 books
+const KF8_VERSION: u32 = 8;
+
+/// An input format recognised from a file's contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Epub,
+    Kepub,
+    Mobi,
+    Azw3,
+    PalmDoc,
+    Pdf,
+    Lit,
+    Fb2,
+    Docx,
+    Odt,
+    Rtf,
+    Html,
+    Htmlz,
+    Cbz,
+    DocBook,
+    Tei,
+}
+
+impl Format {
+    /// The file extension whose decoder reads this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Epub => "epub",
+            Format::Kepub => "kepub.epub",
+            Format::Mobi => "mobi",
+            Format::Azw3 => "azw3",
+            Format::PalmDoc => "pdb",
+            Format::Pdf => "pdf",
+            Format::Lit => "lit",
+            Format::Fb2 => "fb2",
+            Format::Docx => "docx",
+            Format::Odt => "odt",
+            Format::Rtf => "rtf",
+            Format::Html => "html",
+            Format::Htmlz => "htmlz",
+            Format::Cbz => "cbz",
+            Format::DocBook => "dbk",
+            Format::Tei => "tei",
+        }
+    }
+
+    /// Human-readable name of the format
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Epub => "EPUB",
+            Format::Kepub => "KEPUB",
+            Format::Mobi => "MOBI",
+            Format::Azw3 => "KF8 (AZW3)",
+            Format::PalmDoc => "PalmDOC",
+            Format::Pdf => "PDF",
+            Format::Lit => "Microsoft LIT",
+            Format::Fb2 => "FictionBook",
+            Format::Docx => "Word",
+            Format::Odt => "OpenDocument Text",
+            Format::Rtf => "RTF",
+            Format::Html => "HTML",
+            Format::Htmlz => "HTMLZ",
+            Format::Cbz => "Comic book archive",
+            Format::DocBook => "DocBook",
+            Format::Tei => "TEI",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Detect the format of a file from its contents
+///
+/// Returns `None` for formats without a recognisable signature, such as
+/// Markdown and plain text.
+pub fn detect_format(data: &[u8]) -> Option<Format> {
+    if data.starts_with(b"PK\x03\x04") {
+        return detect_zip(data);
+    }
+    if data.starts_with(b"ITOLITLS") {
+        return Some(Format::Lit);
+    }
+    if let Some(format) = detect_pdb(data) {
+        return Some(format);
+    }
+
+    // PDF readers accept the signature anywhere in the first kilobyte
+    let head = &data[..data.len().min(SNIFF_LEN)];
+    if head.windows(5).any(|w| w == b"%PDF-") {
+        return Some(Format::Pdf);
+    }
+
+    let text = skip_bom_and_whitespace(head);
+    if text.starts_with(b"{\\rtf") {
+        return Some(Format::Rtf);
+    }
+    if text.starts_with(b"<") {
+        return detect_markup(data);
+    }
+    None
+}
+
+/// Detect the format of a file from a reader, without reading all of it
+///
+/// Only the first [`HEAD_LEN`] bytes are read, except for ZIP containers, where
+/// the directory at the end of the file and the entries that tell the formats
+/// apart are read instead. XML documents cut off by the limit are recognised by
+/// their root element's name.
+pub fn detect_format_in<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Format>> {
+    let head = read_head(reader)?;
+    if head.starts_with(b"PK\x03\x04") {
+        reader.rewind()?;
+        return Ok(ZipArchive::new(reader)
+            .ok()
+            .and_then(|mut archive| detect_archive(&mut archive)));
+    }
+    Ok(detect_format(&head))
+}
+
+/// Read the first [`HEAD_LEN`] bytes of a file
+pub(crate) fn read_head<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    reader.take(HEAD_LEN as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Get the decoder for a file's contents, as recognised by [`detect_format`]
+pub fn decoder_for_bytes(data: &[u8]) -> Option<Box<dyn Decoder>> {
+    decoder_for_extension(detect_format(data)?.extension())
+}
+
+/// Tell apart the formats that use a ZIP container
+fn detect_zip(data: &[u8]) -> Option<Format> {
+    detect_archive(&mut ZipArchive::new(Cursor::new(data)).ok()?)
+}
+
+/// Tell apart the formats that use a ZIP container, from an opened archive
+fn detect_archive<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<Format> {
+    // EPUB and OpenDocument store their media type, uncompressed, as the first entry
+    if let Some(mimetype) = read_entry(archive, "mimetype") {
+        match mimetype.trim() {
+            "application/epub+zip" => return Some(epub_flavour(archive)),
+            "application/vnd.oasis.opendocument.text" => return Some(Format::Odt),
+            _ => {}
+        }
+    }
+
+    let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+    let has = |name: &str| names.iter().any(|n| n.eq_ignore_ascii_case(name));
+    if has("META-INF/container.xml") {
+        return Some(epub_flavour(archive));
+    }
+    if has("word/document.xml") {
+        return Some(Format::Docx);
+    }
+    if has("content.xml") && has("META-INF/manifest.xml") {
+        return Some(Format::Odt);
+    }
+    if has("index.html") || has("index.htm") {
+        return Some(Format::Htmlz);
+    }
+
+    let files: Vec<&String> = names.iter().filter(|n| !n.ends_with('/')).collect();
+    if files.len() == 1 && files[0].to_lowercase().ends_with(".fb2") {
+        return Some(Format::Fb2);
+    }
+    let is_image = |name: &str| {
+        let name = name.to_lowercase();
+        [".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    };
+    if files.iter().any(|name| is_image(name))
+        && files
+            .iter()
+            .all(|name| is_image(name) || name.eq_ignore_ascii_case("ComicInfo.xml"))
+    {
+        return Some(Format::Cbz);
+    }
+    None
+}
+
+/// Whether an EPUB is a Kobo KEPUB, marked by Kobo spans in its content
+///
+/// Kobo's tools also declare their namespace in the package document, which is
+/// checked first.
+fn epub_flavour<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Format {
+    let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+    for name in names.iter().filter(|n| n.to_lowercase().ends_with(".opf")) {
+        if read_entry(archive, name).is_some_and(|opf| declares_kobo_namespace(&opf)) {
+            return Format::Kepub;
+        }
+    }
+    let content = names.iter().filter(|n| {
+        let n = n.to_lowercase();
+        n.ends_with(".xhtml") || n.ends_with(".html") || n.ends_with(".htm")
+    });
+    for name in content.take(KOBO_SCAN_ENTRIES) {
+        if read_entry(archive, name).is_some_and(|html| has_kobo_spans(&html)) {
+            return Format::Kepub;
+        }
+    }
+    Format::Epub
+}
+
+/// Whether a package document declares a namespace on a kobobooks.com URI
+fn declares_kobo_namespace(opf: &str) -> bool {
+    let Ok(document) = roxmltree::Document::parse(opf) else {
+        return false;
+    };
+    document
+        .descendants()
+        .flat_map(|node| node.namespaces())
+        .any(|namespace| {
+            let host = namespace
+                .uri()
+                .split_once("://")
+                .map_or("", |(_, rest)| rest.split('/').next().unwrap_or(rest));
+            host == KOBO_HOST || host.ends_with(&format!(".{}", KOBO_HOST))
+        })
+}
+
+/// Whether a content document holds Kobo spans, `<span class="koboSpan" id="kobo.1.1">`
+fn has_kobo_spans(html: &str) -> bool {
+    [
+        "class=\"koboSpan\"",
+        "class='koboSpan'",
+        "id=\"kobo.",
+        "id='kobo.",
+    ]
+    .iter()
+    .any(|marker| html.contains(marker))
+}
+
+/// Text of a ZIP entry, if it exists
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Option<String> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).ok()?;
+    Some(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Recognise a Palm database from its type and creator codes
+///
+/// Mobipocket databases are KF8 books when their MOBI header has version 8.
+fn detect_pdb(data: &[u8]) -> Option<Format> {
+    match data.get(60..68)? {
+        b"TEXtREAd" => Some(Format::PalmDoc),
+        b"BOOKMOBI" => {
+            // Record 0 holds the PalmDOC header and then the MOBI header
+            let record0 = u32::from_be_bytes(data.get(78..82)?.try_into().ok()?) as usize;
+            let mobi = data.get(record0 + 16..record0 + 40)?;
+            let version = u32::from_be_bytes(mobi.get(20..24)?.try_into().ok()?);
+            if &mobi[..4] == b"MOBI" && version == KF8_VERSION {
+                Some(Format::Azw3)
+            } else {
+                Some(Format::Mobi)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Recognise an XML or HTML document from its root element
+fn detect_markup(data: &[u8]) -> Option<Format> {
+    let text = xml::decode_xml(data);
+    if let Ok(document) = roxmltree::Document::parse(&text) {
+        let root = document.root_element().tag_name().name();
+        return match root {
+            "FictionBook" => Some(Format::Fb2),
+            "TEI" => Some(Format::Tei),
+            "html" => Some(Format::Html),
+            _ if docbook::ROOT_ELEMENTS.contains(&root) => Some(Format::DocBook),
+            _ => None,
+        };
+    }
+
+    // A document cut off at the sniffing limit is recognised by its root's name
+    match root_element_name(&text) {
+        Some("FictionBook") => return Some(Format::Fb2),
+        Some("TEI") => return Some(Format::Tei),
+        Some(root) if docbook::ROOT_ELEMENTS.contains(&root) => return Some(Format::DocBook),
+        _ => {}
+    }
+
+    // HTML is rarely well-formed XML
+    let mut end = text.len().min(SNIFF_LEN);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let head = text[..end].to_ascii_lowercase();
+    (head.contains("<!doctype html") || head.contains("<html")).then_some(Format::Html)
+}
+
+/// Local name of the first element in a document that could not be parsed,
+/// skipping the XML declaration, processing instructions, comments and doctype
+fn root_element_name(text: &str) -> Option<&str> {
+    let mut rest = text;
+    loop {
+        rest = &rest[rest.find('<')? + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = &comment[comment.find("-->")? + 3..];
+        } else if !rest.starts_with(['?', '!']) {
+            let end = rest.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+            let name = &rest[..end];
+            return Some(name.rsplit(':').next().unwrap_or(name));
+        }
+    }
+}
+
+/// The data after a byte order mark and leading whitespace
+fn skip_bom_and_whitespace(data: &[u8]) -> &[u8] {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    &data[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    fn zip(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut output = Vec::new();
+        {
+            let mut writer = zip::ZipWriter::new(Cursor::new(&mut output));
+            for (name, content) in entries {
+                writer.start_file(*name, FileOptions::default()).unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        output
+    }
+
+    fn pdb(kind: &[u8; 8], record0: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; 60];
+        data.extend_from_slice(kind);
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&86u32.to_be_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(record0);
+        data
+    }
+
+    fn mobi_record(version: u32) -> Vec<u8> {
+        let mut record = vec![0u8; 16];
+        record.extend_from_slice(b"MOBI");
+        record.extend_from_slice(&[0; 16]);
+        record.extend_from_slice(&version.to_be_bytes());
+        record
+    }
+
+    #[test]
+    fn test_detect_zip_formats() {
+        let epub = zip(&[
+            ("mimetype", "application/epub+zip"),
+            ("OEBPS/content.opf", "<package/>"),
+            ("OEBPS/ch1.xhtml", "<p>Text</p>"),
+        ]);
+        assert_eq!(detect_format(&epub), Some(Format::Epub));
+
+        let kepub = zip(&[
+            ("mimetype", "application/epub+zip"),
+            ("OEBPS/content.opf", "<package/>"),
+            (
+                "OEBPS/ch1.xhtml",
+                r#"<p><span class="koboSpan" id="kobo.1.1">Text</span></p>"#,
+            ),
+        ]);
+        assert_eq!(detect_format(&kepub), Some(Format::Kepub));
+
+        let kobo_opf = zip(&[
+            ("mimetype", "application/epub+zip"),
+            (
+                "OEBPS/content.opf",
+                r#"<package xmlns="http://www.idpf.org/2007/opf"><metadata xmlns:kobo="http://ns.kobobooks.com/"/></package>"#,
+            ),
+            ("OEBPS/ch1.xhtml", "<p>Text</p>"),
+        ]);
+        assert_eq!(detect_format(&kobo_opf), Some(Format::Kepub));
+
+        let docx = zip(&[("[Content_Types].xml", ""), ("word/document.xml", "")]);
+        assert_eq!(detect_format(&docx), Some(Format::Docx));
+
+        let cbz = zip(&[("001.jpg", ""), ("002.jpg", ""), ("ComicInfo.xml", "")]);
+        assert_eq!(detect_format(&cbz), Some(Format::Cbz));
+
+        let fbz = zip(&[("book.fb2", "<FictionBook/>")]);
+        assert_eq!(detect_format(&fbz), Some(Format::Fb2));
+    }
+
+    #[test]
+    fn test_kobo_words_do_not_make_a_kepub() {
+        let epub = zip(&[
+            ("mimetype", "application/epub+zip"),
+            (
+                "OEBPS/content.opf",
+                r#"<package xmlns="http://www.idpf.org/2007/opf" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <metadata>
+    <dc:title>Kobold Tales</dc:title>
+    <dc:identifier>https://www.kobo.com/ebook/kobold-tales</dc:identifier>
+    <dc:source>http://example.com/kobobooks.com/</dc:source>
+  </metadata>
+</package>"#,
+            ),
+            (
+                "OEBPS/ch1.xhtml",
+                r#"<p class="kobold">Buy it at kobo.com, where koboSpan markup is added.</p>"#,
+            ),
+        ]);
+        assert_eq!(detect_format(&epub), Some(Format::Epub));
+    }
+
+    #[test]
+    fn test_detect_signatures() {
+        assert_eq!(detect_format(b"%PDF-1.7\n"), Some(Format::Pdf));
+        assert_eq!(detect_format(b"ITOLITLS\x01\0\0\0"), Some(Format::Lit));
+        assert_eq!(detect_format(b"{\\rtf1\\ansi Hello}"), Some(Format::Rtf));
+        assert_eq!(
+            detect_format(&pdb(b"TEXtREAd", &[0; 16])),
+            Some(Format::PalmDoc)
+        );
+        assert_eq!(
+            detect_format(&pdb(b"BOOKMOBI", &mobi_record(6))),
+            Some(Format::Mobi)
+        );
+        assert_eq!(
+            detect_format(&pdb(b"BOOKMOBI", &mobi_record(8))),
+            Some(Format::Azw3)
+        );
+        assert_eq!(
+            detect_format(b"<?xml version=\"1.0\"?>\n<FictionBook/>"),
+            Some(Format::Fb2)
+        );
+        assert_eq!(
+            detect_format(b"<!DOCTYPE html><html><body><p>Hi<br></body></html>"),
+            Some(Format::Html)
+        );
+        assert_eq!(detect_format(b"# Markdown\n\nText."), None);
+    }
+
+    #[test]
+    fn test_detect_html_with_multibyte_text_at_sniff_limit() {
+        let mut html = String::from("<!DOCTYPE html><html><body><p>");
+        html.push_str(&"a".repeat(SNIFF_LEN - 1 - html.len()));
+        html.push_str("é<br></p></body></html>");
+        assert!(!html.is_char_boundary(SNIFF_LEN));
+
+        assert_eq!(detect_format(html.as_bytes()), Some(Format::Html));
+    }
+
+    /// A reader that records how far into the data it was read
+    struct Tracked {
+        data: Cursor<Vec<u8>>,
+        read_to: u64,
+    }
+
+    impl Tracked {
+        fn new(data: Vec<u8>) -> Self {
+            Self {
+                data: Cursor::new(data),
+                read_to: 0,
+            }
+        }
+    }
+
+    impl Read for Tracked {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.data.read(buf)?;
+            self.read_to = self.read_to.max(self.data.position());
+            Ok(read)
+        }
+    }
+
+    impl Seek for Tracked {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_detect_format_in_reads_only_the_head() {
+        let mut fb2 = String::from("<?xml version=\"1.0\"?>\n<!-- <p> -->\n<FictionBook><body>");
+        while fb2.len() < HEAD_LEN * 4 {
+            fb2.push_str("<p>Paragraph.</p>");
+        }
+        fb2.push_str("</body></FictionBook>");
+        let mut reader = Tracked::new(fb2.into_bytes());
+        assert_eq!(detect_format_in(&mut reader).unwrap(), Some(Format::Fb2));
+        assert_eq!(reader.read_to, HEAD_LEN as u64);
+
+        let mut text = Tracked::new(vec![b'a'; HEAD_LEN * 4]);
+        assert_eq!(detect_format_in(&mut text).unwrap(), None);
+        assert_eq!(text.read_to, HEAD_LEN as u64);
+    }
+
+    #[test]
+    fn test_detect_format_in_reads_zip_directory() {
+        // The chapter pushes the central directory well past the head
+        let chapter = format!("<p>{}</p>", "Text. ".repeat(HEAD_LEN));
+        let mut output = Vec::new();
+        {
+            let mut writer = zip::ZipWriter::new(Cursor::new(&mut output));
+            let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            for (name, content) in [
+                ("mimetype", "application/epub+zip"),
+                ("OEBPS/ch1.xhtml", chapter.as_str()),
+                ("OEBPS/content.opf", "<package/>"),
+            ] {
+                writer.start_file(name, stored).unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert!(output.len() > HEAD_LEN * 4);
+
+        assert_eq!(
+            detect_format_in(&mut Cursor::new(output)).unwrap(),
+            Some(Format::Epub)
+        );
+    }
+}
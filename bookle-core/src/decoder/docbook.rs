@@ -0,0 +1,1315 @@
+//! DocBook decoder implementation
+//!
+//! DocBook 5 (and the namespace-less DocBook 4) describes a book as nested
+//! divisions: a `<book>` holds `<part>`s, `<chapter>`s, `<preface>`s and
+//! `<appendix>`es, each of which holds `<section>`s of block elements. Every
+//! division becomes a chapter, nested in the TOC, while sections become headings
+//! within it. An `<article>` is divided at its top-level sections instead.
+//! Document properties come from the `<info>` element, and images are files
+//! referenced by `<imagedata fileref>`, loaded through the decode context.
+
+use super::chapters::place_footnotes;
+use super::context::AssetLoader;
+use super::front_matter::parse_date;
+use super::xml::{self, trim_inlines};
+use super::DecodeContext;
+use crate::error::ParseError;
+use crate::types::{
+    AdmonitionKind, Block, Book, Chapter, DefinitionItem, Inline, Metadata, ResourceStore,
+    TableCell, TableData, TocEntry,
+};
+use roxmltree::Node;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Root elements of DocBook documents
+pub(crate) const ROOT_ELEMENTS: &[&str] = &["book", "article", "part", "chapter"];
+
+/// Elements that become chapters of their own
+const DIVISIONS: &[&str] = &[
+    "part",
+    "chapter",
+    "preface",
+    "appendix",
+    "article",
+    "glossary",
+    "bibliography",
+    "colophon",
+    "dedication",
+    "acknowledgements",
+    "reference",
+];
+
+/// Elements that become headings within a chapter
+const SECTIONS: &[&str] = &[
+    "section",
+    "sect1",
+    "sect2",
+    "sect3",
+    "sect4",
+    "sect5",
+    "simplesect",
+    "refsect1",
+    "refsect2",
+    "refsect3",
+];
+
+/// Block elements that may appear inside a paragraph
+const BLOCKS_IN_PARAGRAPH: &[&str] = &[
+    "itemizedlist",
+    "orderedlist",
+    "variablelist",
+    "programlisting",
+    "screen",
+    "literallayout",
+    "blockquote",
+    "figure",
+    "informalfigure",
+    "mediaobject",
+    "table",
+    "informaltable",
+    "note",
+    "tip",
+    "important",
+    "warning",
+    "caution",
+    "example",
+    "informalexample",
+];
+
+/// Inline elements holding code or other literal text
+const CODE_ELEMENTS: &[&str] = &[
+    "literal",
+    "code",
+    "command",
+    "filename",
+    "option",
+    "varname",
+    "function",
+    "classname",
+    "methodname",
+    "parameter",
+    "userinput",
+    "computeroutput",
+    "envar",
+    "constant",
+    "type",
+    "property",
+    "tag",
+    "systemitem",
+    "prompt",
+    "replaceable",
+];
+
+/// Decoder for DocBook XML
+pub struct DocBookDecoder;
+
+/// State shared while converting a document
+struct Converter {
+    /// Titles of elements with an id, for the text of cross references
+    titles: HashMap<String, String>,
+    /// Footnotes collected from the text, in order
+    footnotes: RefCell<Vec<(String, Block)>>,
+    /// Number of chapters made, for generating ids
+    chapters: usize,
+}
+
+impl DocBookDecoder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for DocBookDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for DocBookDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read DocBook: {}", e)))?;
+
+        let text = xml::decode_xml(&data);
+        let document = xml::parse(&text)?;
+        let root = document.root_element();
+        if !ROOT_ELEMENTS.contains(&root.tag_name().name()) {
+            return Err(ParseError::UnsupportedFormat(
+                "Not a DocBook document".to_string(),
+            ));
+        }
+
+        let mut converter = Converter {
+            titles: root
+                .descendants()
+                .filter_map(|node| {
+                    let id = xml::attribute(node, "id")?;
+                    let title = title_of(node).map(xml::text)?;
+                    Some((id.to_string(), title))
+                })
+                .collect(),
+            footnotes: RefCell::new(Vec::new()),
+            chapters: 0,
+        };
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        if let Some(title) = title_of(root).map(xml::text).filter(|t| !t.is_empty()) {
+            metadata.title = title;
+        }
+        if let Some(lang) = xml::attribute(root, "lang") {
+            metadata.language = lang.to_string();
+        }
+        let cover = info_of(root).and_then(|info| apply_info(info, &mut metadata));
+
+        let mut chapters = Vec::new();
+        let toc = if root.tag_name().name() == "article" {
+            converter.article_to_chapters(root, &metadata.title, &mut chapters)
+        } else if root.tag_name().name() == "book" {
+            converter.book_to_chapters(root, &metadata.title, &mut chapters)
+        } else {
+            vec![converter.division_to_chapters(root, 0, &mut chapters)]
+        };
+        place_footnotes(&mut chapters, converter.footnotes.into_inner());
+
+        // Images are referenced by path, relative to the document
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        for chapter in &mut chapters {
+            loader.load_images(&mut chapter.content);
+        }
+        metadata.cover_resource_key = cover.and_then(|cover| loader.load(&cover));
+
+        let mut book = Book::with_metadata(metadata);
+        book.chapters = chapters;
+        book.toc = toc;
+        book.resources = resources;
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["dbk", "docbook"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/docbook+xml"]
+    }
+}
+
+impl Converter {
+    /// Convert a book: any content before its first division opens the book,
+    /// and each division follows as a chapter
+    fn book_to_chapters(
+        &mut self,
+        book: Node,
+        title: &str,
+        chapters: &mut Vec<Chapter>,
+    ) -> Vec<TocEntry> {
+        let mut toc = Vec::new();
+        let intro = self.blocks(book, 1);
+        if !intro.is_empty() {
+            let id = self.next_id(None);
+            toc.push(TocEntry::new(title, format!("#{}", id)));
+            chapters.push(Chapter::new(title).with_id(id).with_content(intro));
+        }
+        for division in divisions(book) {
+            toc.push(self.division_to_chapters(division, 0, chapters));
+        }
+        toc
+    }
+
+    /// Convert an article, with a chapter for each top-level section
+    fn article_to_chapters(
+        &mut self,
+        article: Node,
+        title: &str,
+        chapters: &mut Vec<Chapter>,
+    ) -> Vec<TocEntry> {
+        let mut toc = Vec::new();
+        let mut intro = Vec::new();
+        let mut sections = Vec::new();
+        for child in article.children().filter(|n| n.is_element()) {
+            if SECTIONS.contains(&child.tag_name().name()) {
+                sections.push(child);
+            } else if sections.is_empty() {
+                for block in self.element_to_blocks(child, 1) {
+                    push_block(&mut intro, block);
+                }
+            }
+        }
+
+        if !intro.is_empty() || sections.is_empty() {
+            let id = self.next_id(None);
+            toc.push(TocEntry::new(title, format!("#{}", id)));
+            chapters.push(Chapter::new(title).with_id(id).with_content(intro));
+        }
+        for section in sections {
+            toc.push(self.division_to_chapters(section, 0, chapters));
+        }
+        toc
+    }
+
+    /// Convert a division and those nested in it, returning its TOC entry
+    fn division_to_chapters(
+        &mut self,
+        division: Node,
+        depth: u32,
+        chapters: &mut Vec<Chapter>,
+    ) -> TocEntry {
+        let title_node = title_of(division);
+        let title = title_node
+            .map(xml::text)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| default_title(division.tag_name().name()));
+        let id = self.next_id(xml::attribute(division, "id"));
+        let level = (depth + 1).min(6) as u8;
+
+        let mut content = vec![Block::Header {
+            level,
+            content: match title_node {
+                Some(node) => self.inlines(node),
+                None => vec![Inline::text(&title)],
+            },
+            anchor: None,
+        }];
+        content.extend(self.blocks(division, level));
+        chapters.push(Chapter::new(&title).with_id(&id).with_content(content));
+
+        let children = divisions(division)
+            .map(|child| self.division_to_chapters(child, depth + 1, chapters))
+            .collect();
+
+        TocEntry::new(title, format!("#{}", id))
+            .with_level(depth)
+            .with_children(children)
+    }
+
+    /// A chapter id, from the division's id or generated
+    fn next_id(&mut self, id: Option<&str>) -> String {
+        self.chapters += 1;
+        id.map(str::to_string)
+            .unwrap_or_else(|| format!("chapter-{}", self.chapters))
+    }
+
+    /// Convert the block children of a container, skipping its title, its
+    /// `<info>` and any divisions within it
+    ///
+    /// `level` is the heading level of the container's own title.
+    fn blocks(&self, node: Node, level: u8) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        for child in node.children().filter(|n| n.is_element()) {
+            let name = child.tag_name().name();
+            if DIVISIONS.contains(&name) || is_title_or_info(name) {
+                continue;
+            }
+            for block in self.element_to_blocks(child, level) {
+                push_block(&mut blocks, block);
+            }
+        }
+        blocks
+    }
+
+    /// Convert a block-level element
+    fn element_to_blocks(&self, element: Node, level: u8) -> Vec<Block> {
+        let name = element.tag_name().name();
+        if SECTIONS.contains(&name) {
+            let level = (level + 1).min(6);
+            let mut blocks = Vec::new();
+            if let Some(title) = title_of(element) {
+                blocks.push(Block::Header {
+                    level,
+                    content: self.inlines(title),
+                    anchor: xml::attribute(element, "id").map(str::to_string),
+                });
+            }
+            blocks.extend(self.blocks(element, level));
+            return blocks;
+        }
+
+        match name {
+            "para" | "simpara" => self.paragraph(element, level),
+            "formalpara" => {
+                let mut blocks = Vec::new();
+                if let Some(title) = title_of(element) {
+                    blocks.push(Block::Paragraph(vec![Inline::Bold(self.inlines(title))]));
+                }
+                blocks.extend(self.blocks(element, level));
+                blocks
+            }
+            "partintro" | "abstract" | "glossdiv" | "qandaset" | "qandaentry" | "question"
+            | "answer" => self.blocks(element, level),
+            "bridgehead" => vec![Block::Header {
+                level: (level + 1).min(6),
+                content: self.inlines(element),
+                anchor: xml::attribute(element, "id").map(str::to_string),
+            }],
+            "itemizedlist" | "orderedlist" => {
+                let mut blocks = self.titled(element);
+                blocks.push(Block::List {
+                    items: xml::children(element, "listitem")
+                        .map(|item| self.blocks(item, level))
+                        .collect(),
+                    ordered: name == "orderedlist",
+                });
+                blocks
+            }
+            "variablelist" => {
+                let mut blocks = self.titled(element);
+                blocks.push(Block::DefinitionList(
+                    xml::children(element, "varlistentry")
+                        .map(|entry| self.definition(entry, "term", "listitem", level))
+                        .collect(),
+                ));
+                blocks
+            }
+            "glosslist" => vec![Block::DefinitionList(
+                xml::children(element, "glossentry")
+                    .map(|entry| self.definition(entry, "glossterm", "glossdef", level))
+                    .collect(),
+            )],
+            "glossentry" => vec![Block::DefinitionList(vec![self.definition(
+                element,
+                "glossterm",
+                "glossdef",
+                level,
+            )])],
+            "programlisting" | "screen" | "synopsis" => vec![Block::CodeBlock {
+                lang: element.attribute("language").map(str::to_string),
+                code: code_text(element),
+            }],
+            "literallayout" if element.attribute("class") == Some("monospaced") => {
+                vec![Block::CodeBlock {
+                    lang: None,
+                    code: code_text(element),
+                }]
+            }
+            "literallayout" | "address" => vec![Block::Paragraph(self.lines(element))],
+            "blockquote" | "epigraph" => {
+                let mut content = self.blocks(element, level);
+                if let Some(attribution) = xml::child(element, "attribution") {
+                    let mut credit = vec![Inline::text("\u{2014} ")];
+                    credit.extend(self.inlines(attribution));
+                    content.push(Block::Paragraph(credit));
+                }
+                vec![Block::Blockquote(content)]
+            }
+            "note" | "tip" | "important" | "warning" | "caution" => {
+                let mut content = self.titled(element);
+                content.extend(self.blocks(element, level));
+                vec![Block::Admonition {
+                    kind: AdmonitionKind::from_name(name).unwrap_or(AdmonitionKind::Note),
+                    content,
+                }]
+            }
+            "figure" | "informalfigure" => {
+                let caption = title_of(element).map(xml::text);
+                element
+                    .descendants()
+                    .filter(|n| n.tag_name().name() == "mediaobject")
+                    .filter_map(|media| image(media, caption.clone()))
+                    .collect()
+            }
+            "mediaobject" => image(element, None).into_iter().collect(),
+            "table" | "informaltable" => {
+                let mut blocks = self.titled(element);
+                blocks.push(Block::Table(self.table(element)));
+                blocks
+            }
+            "example" | "informalexample" | "sidebar" => {
+                let mut blocks = self.titled(element);
+                blocks.extend(self.blocks(element, level));
+                blocks
+            }
+            "attribution" | "anchor" | "indexterm" | "remark" | "index" | "toc" => vec![],
+            _ if is_title_or_info(name) => vec![],
+            _ => self.paragraph(element, level),
+        }
+    }
+
+    /// Convert a paragraph, whose content may include block elements such as
+    /// lists and program listings
+    fn paragraph(&self, element: Node, level: u8) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut inlines = Vec::new();
+        let flush = |inlines: &mut Vec<Inline>, blocks: &mut Vec<Block>| {
+            trim_inlines(inlines);
+            if !inlines.is_empty() {
+                blocks.push(Block::Paragraph(std::mem::take(inlines)));
+            }
+        };
+
+        for child in element.children() {
+            if child.is_element() && BLOCKS_IN_PARAGRAPH.contains(&child.tag_name().name()) {
+                flush(&mut inlines, &mut blocks);
+                blocks.extend(self.element_to_blocks(child, level));
+            } else {
+                inlines.extend(self.inline(child));
+            }
+        }
+        flush(&mut inlines, &mut blocks);
+        blocks
+    }
+
+    /// A bold paragraph for an element's title, if it has one
+    fn titled(&self, element: Node) -> Vec<Block> {
+        title_of(element)
+            .map(|title| Block::Paragraph(vec![Inline::Bold(self.inlines(title))]))
+            .into_iter()
+            .collect()
+    }
+
+    /// Convert an entry of a variable list or glossary
+    fn definition(&self, entry: Node, term: &str, definition: &str, level: u8) -> DefinitionItem {
+        let mut terms = Vec::new();
+        for node in xml::children(entry, term) {
+            if !terms.is_empty() {
+                terms.push(Inline::text(", "));
+            }
+            terms.extend(self.inlines(node));
+        }
+        DefinitionItem {
+            term: terms,
+            definitions: xml::children(entry, definition)
+                .map(|node| self.blocks(node, level))
+                .collect(),
+        }
+    }
+
+    /// Convert a CALS table (`<tgroup>`) or an HTML table
+    fn table(&self, table: Node) -> TableData {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+
+        if let Some(group) = xml::child(table, "tgroup") {
+            let columns: Vec<&str> = xml::children(group, "colspec")
+                .map(|colspec| colspec.attribute("colname").unwrap_or_default())
+                .collect();
+            let spans: HashMap<&str, (&str, &str)> = xml::children(group, "spanspec")
+                .filter_map(|spanspec| {
+                    Some((
+                        spanspec.attribute("spanname")?,
+                        (
+                            spanspec.attribute("namest")?,
+                            spanspec.attribute("nameend")?,
+                        ),
+                    ))
+                })
+                .collect();
+            let column = |name: &str| columns.iter().position(|c| *c == name);
+
+            for part in group.children().filter(|n| n.is_element()) {
+                let is_head = match part.tag_name().name() {
+                    "thead" => true,
+                    "tbody" | "tfoot" => false,
+                    _ => continue,
+                };
+                for row in xml::children(part, "row") {
+                    let cells: Vec<TableCell> = row
+                        .children()
+                        .filter(|n| n.is_element())
+                        .map(|entry| {
+                            let mut cell = TableCell::new(self.cell_inlines(entry));
+                            let range = entry
+                                .attribute("spanname")
+                                .and_then(|name| spans.get(name).copied())
+                                .or_else(|| {
+                                    Some((entry.attribute("namest")?, entry.attribute("nameend")?))
+                                });
+                            if let Some((start, end)) = range {
+                                if let (Some(start), Some(end)) = (column(start), column(end)) {
+                                    cell.colspan = (end.saturating_sub(start) + 1) as u32;
+                                }
+                            }
+                            cell.rowspan = entry
+                                .attribute("morerows")
+                                .and_then(|n| n.parse::<u32>().ok())
+                                .map_or(1, |n| n + 1);
+                            cell
+                        })
+                        .collect();
+                    if is_head && headers.is_empty() {
+                        headers = cells;
+                    } else {
+                        rows.push(cells);
+                    }
+                }
+            }
+            return TableData { headers, rows };
+        }
+
+        // HTML tables have rows directly or within thead/tbody
+        let html_rows = table
+            .descendants()
+            .filter(|n| n.tag_name().name() == "tr" && n.parent_element() == Some(table))
+            .chain(
+                table
+                    .children()
+                    .filter(|n| matches!(n.tag_name().name(), "thead" | "tbody" | "tfoot"))
+                    .flat_map(|part| xml::children(part, "tr")),
+            );
+        for row in html_rows {
+            let in_head = row
+                .parent_element()
+                .is_some_and(|parent| parent.tag_name().name() == "thead");
+            let cells: Vec<Node> = row.children().filter(|n| n.is_element()).collect();
+            let all_headers = cells.iter().all(|cell| cell.tag_name().name() == "th");
+            let cells: Vec<TableCell> = cells
+                .iter()
+                .map(|cell| {
+                    let span = |name| {
+                        cell.attribute(name)
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(1)
+                    };
+                    let mut table_cell = TableCell::new(self.cell_inlines(*cell));
+                    table_cell.colspan = span("colspan");
+                    table_cell.rowspan = span("rowspan");
+                    table_cell
+                })
+                .collect();
+            if headers.is_empty() && rows.is_empty() && (in_head || all_headers) {
+                headers = cells;
+            } else {
+                rows.push(cells);
+            }
+        }
+        TableData { headers, rows }
+    }
+
+    /// Content of a table cell, with its paragraphs on separate lines
+    fn cell_inlines(&self, cell: Node) -> Vec<Inline> {
+        let paragraphs: Vec<Node> = cell
+            .children()
+            .filter(|n| matches!(n.tag_name().name(), "para" | "simpara"))
+            .collect();
+        if paragraphs.is_empty() {
+            return self.inlines(cell);
+        }
+        let mut content = Vec::new();
+        for paragraph in paragraphs {
+            if !content.is_empty() {
+                content.push(Inline::Break);
+            }
+            content.extend(self.inlines(paragraph));
+        }
+        content
+    }
+
+    /// Content of a line-oriented element, keeping its line breaks
+    fn lines(&self, element: Node) -> Vec<Inline> {
+        let text = code_text(element);
+        let mut content = Vec::new();
+        for line in text.lines() {
+            if !content.is_empty() {
+                content.push(Inline::Break);
+            }
+            content.push(Inline::text(line.trim()));
+        }
+        content
+    }
+
+    /// Convert inline content, trimming the whitespace at its ends
+    fn inlines(&self, node: Node) -> Vec<Inline> {
+        let mut inlines: Vec<Inline> = node
+            .children()
+            .flat_map(|child| self.inline(child))
+            .collect();
+        trim_inlines(&mut inlines);
+        inlines
+    }
+
+    /// Convert the children of an inline container, without trimming
+    fn children_to_inlines(&self, node: Node) -> Vec<Inline> {
+        node.children()
+            .flat_map(|child| self.inline(child))
+            .collect()
+    }
+
+    /// Convert one inline node
+    fn inline(&self, node: Node) -> Vec<Inline> {
+        if node.is_text() {
+            let text = xml::collapse_whitespace(node.text().unwrap_or_default());
+            return if text.is_empty() {
+                vec![]
+            } else {
+                vec![Inline::Text(text)]
+            };
+        }
+        if !node.is_element() {
+            return vec![];
+        }
+
+        let name = node.tag_name().name();
+        if CODE_ELEMENTS.contains(&name) {
+            return vec![Inline::Code(xml::text(node))];
+        }
+        let children = || self.children_to_inlines(node);
+        match name {
+            "emphasis" => match node.attribute("role") {
+                Some("bold" | "strong") => vec![Inline::Bold(children())],
+                Some("strikethrough" | "del") => vec![Inline::Strikethrough(children())],
+                _ => vec![Inline::Italic(children())],
+            },
+            "citetitle" | "foreignphrase" | "firstterm" | "glossterm" => {
+                vec![Inline::Italic(children())]
+            }
+            "superscript" => vec![Inline::Superscript(children())],
+            "subscript" => vec![Inline::Subscript(children())],
+            "quote" => {
+                let mut content = vec![Inline::text("\u{201C}")];
+                content.extend(children());
+                content.push(Inline::text("\u{201D}"));
+                content
+            }
+            "link" | "ulink" | "uri" | "email" => {
+                let url = xml::attribute(node, "href")
+                    .or_else(|| node.attribute("url"))
+                    .map(str::to_string)
+                    .or_else(|| xml::attribute(node, "linkend").map(|id| format!("#{}", id)))
+                    .unwrap_or_else(|| {
+                        let text = xml::text(node);
+                        if name == "email" {
+                            format!("mailto:{}", text)
+                        } else {
+                            text
+                        }
+                    });
+                let mut content = children();
+                if content.is_empty() {
+                    content.push(Inline::text(url.trim_start_matches('#')));
+                }
+                vec![Inline::Link {
+                    children: content,
+                    url,
+                }]
+            }
+            "xref" => {
+                let Some(id) = xml::attribute(node, "linkend") else {
+                    return vec![];
+                };
+                let text = self
+                    .titles
+                    .get(id)
+                    .cloned()
+                    .unwrap_or_else(|| id.to_string());
+                vec![Inline::Link {
+                    children: vec![Inline::Text(text)],
+                    url: format!("#{}", id),
+                }]
+            }
+            "footnote" => {
+                let mut footnotes = self.footnotes.borrow_mut();
+                let id = xml::attribute(node, "id")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("footnote-{}", footnotes.len() + 1));
+                drop(footnotes);
+                let content = self.blocks(node, 6);
+                footnotes = self.footnotes.borrow_mut();
+                footnotes.push((
+                    id.clone(),
+                    Block::Footnote {
+                        id: id.clone(),
+                        content,
+                    },
+                ));
+                vec![Inline::FootnoteRef { id }]
+            }
+            "footnoteref" => xml::attribute(node, "linkend")
+                .map(|id| Inline::FootnoteRef { id: id.to_string() })
+                .into_iter()
+                .collect(),
+            "inlinemediaobject" => xml::child(node, "textobject")
+                .map(|text| vec![Inline::Text(xml::text(text))])
+                .unwrap_or_default(),
+            "anchor" | "indexterm" | "remark" => vec![],
+            // <phrase>, names and unknown elements pass their content through
+            _ => children(),
+        }
+    }
+}
+
+/// Add a block, merging adjacent definition lists such as glossary entries
+fn push_block(blocks: &mut Vec<Block>, block: Block) {
+    if let (Some(Block::DefinitionList(previous)), Block::DefinitionList(items)) =
+        (blocks.last_mut(), &block)
+    {
+        previous.extend(items.iter().cloned());
+        return;
+    }
+    blocks.push(block);
+}
+
+/// Divisions directly within an element
+fn divisions<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(|n| n.is_element() && DIVISIONS.contains(&n.tag_name().name()))
+}
+
+/// Whether an element holds a title or properties rather than content
+fn is_title_or_info(name: &str) -> bool {
+    matches!(name, "title" | "subtitle" | "titleabbrev" | "info") || name.ends_with("info")
+}
+
+/// The `<info>` element of a division, or its DocBook 4 equivalent
+fn info_of<'a, 'input>(node: Node<'a, 'input>) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name().ends_with("info"))
+}
+
+/// The title of an element, given directly or in its `<info>`
+fn title_of<'a, 'input>(node: Node<'a, 'input>) -> Option<Node<'a, 'input>> {
+    xml::child(node, "title").or_else(|| info_of(node).and_then(|info| xml::child(info, "title")))
+}
+
+/// Title for a division that has none
+fn default_title(name: &str) -> String {
+    let mut title = name.to_string();
+    if let Some(first) = title.get_mut(..1) {
+        first.make_ascii_uppercase();
+    }
+    title
+}
+
+/// Text of a program listing or other verbatim element, as written
+fn code_text(element: Node) -> String {
+    let text: String = element
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    text.trim_start_matches(['\r', '\n']).trim_end().to_string()
+}
+
+/// An image block from a `<mediaobject>`, pointing at the file it references
+fn image(media: Node, caption: Option<String>) -> Option<Block> {
+    let data = media
+        .descendants()
+        .find(|n| n.tag_name().name() == "imagedata")?;
+    let path = data
+        .attribute("fileref")
+        .or_else(|| xml::attribute(data, "href"))?;
+    let alt = media
+        .descendants()
+        .find(|n| matches!(n.tag_name().name(), "alt" | "textobject"))
+        .map(xml::text)
+        .unwrap_or_default();
+    Some(Block::Image {
+        resource_key: path.to_string(),
+        caption,
+        alt,
+    })
+}
+
+/// Name of a person or organisation from an `<author>` or `<editor>`
+fn person_name(person: Node) -> Option<String> {
+    let name = xml::child(person, "personname").unwrap_or(person);
+    let parts: Vec<String> = [
+        "honorific",
+        "firstname",
+        "givenname",
+        "othername",
+        "surname",
+    ]
+    .iter()
+    .filter_map(|part| xml::child(name, part).map(xml::text))
+    .filter(|part| !part.is_empty())
+    .collect();
+    if !parts.is_empty() {
+        return Some(parts.join(" "));
+    }
+    let name = xml::child(person, "orgname")
+        .map(xml::text)
+        .unwrap_or_else(|| xml::text(name));
+    Some(name).filter(|name| !name.is_empty())
+}
+
+/// Fill in metadata from an `<info>` element, returning the cover image path
+fn apply_info(info: Node, metadata: &mut Metadata) -> Option<String> {
+    if let Some(title) = xml::child(info, "title").map(xml::text) {
+        if !title.is_empty() {
+            metadata.title = title;
+        }
+    }
+
+    let authors = xml::children(info, "author")
+        .chain(xml::children(info, "authorgroup").flat_map(|group| xml::children(group, "author")));
+    metadata.creator = authors.filter_map(person_name).collect();
+    if metadata.creator.is_empty() {
+        metadata.creator = xml::children(info, "editor")
+            .filter_map(person_name)
+            .collect();
+    }
+
+    if let Some(date) = xml::child(info, "pubdate").or_else(|| xml::child(info, "date")) {
+        metadata.date = parse_date(&xml::text(date));
+    }
+    metadata.publisher = xml::child(info, "publisher")
+        .map(|publisher| {
+            xml::child(publisher, "publishername")
+                .map(xml::text)
+                .unwrap_or_else(|| xml::text(publisher))
+        })
+        .filter(|publisher| !publisher.is_empty());
+
+    if let Some(abstract_) = xml::child(info, "abstract") {
+        let paragraphs: Vec<String> = abstract_
+            .children()
+            .filter(|n| n.is_element() && n.tag_name().name() != "title")
+            .map(xml::text)
+            .filter(|p| !p.is_empty())
+            .collect();
+        if !paragraphs.is_empty() {
+            metadata.description = Some(paragraphs.join("\n\n"));
+        }
+    }
+
+    metadata.subject = info
+        .descendants()
+        .filter(|n| matches!(n.tag_name().name(), "keyword" | "subjectterm"))
+        .map(xml::text)
+        .filter(|subject| !subject.is_empty())
+        .collect();
+
+    if let Some(copyright) = xml::child(info, "copyright") {
+        let years: Vec<String> = xml::children(copyright, "year").map(xml::text).collect();
+        let holders: Vec<String> = xml::children(copyright, "holder").map(xml::text).collect();
+        metadata.rights = Some(format!(
+            "Copyright \u{A9} {} {}",
+            years.join(", "),
+            holders.join(", ")
+        ))
+        .map(|rights| rights.trim().to_string());
+    }
+
+    let isbn = xml::children(info, "biblioid")
+        .find(|id| id.attribute("class") == Some("isbn"))
+        .or_else(|| xml::child(info, "isbn"))
+        .map(xml::text);
+    if let Some(isbn) = isbn.filter(|isbn| !isbn.is_empty()) {
+        metadata.identifier = isbn;
+    }
+
+    xml::child(info, "cover")
+        .and_then(|cover| {
+            cover
+                .descendants()
+                .find(|n| n.tag_name().name() == "mediaobject")
+        })
+        .or_else(|| {
+            xml::children(info, "mediaobject")
+                .find(|media| media.attribute("role") == Some("cover"))
+        })
+        .and_then(|media| image(media, None))
+        .and_then(|block| match block {
+            Block::Image { resource_key, .. } => Some(resource_key),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::markdown::inlines_to_text;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    const NS: &str = r#"xmlns="http://docbook.org/ns/docbook" xmlns:xlink="http://www.w3.org/1999/xlink" version="5.0""#;
+
+    fn decode(xml: &str) -> Book {
+        DocBookDecoder::new()
+            .decode(&mut Cursor::new(xml.as_bytes()))
+            .unwrap()
+    }
+
+    /// Blocks of an article with no sections, which all open its first chapter
+    fn article(body: &str) -> Vec<Block> {
+        let book = decode(&format!(
+            "<article {}><title>T</title>{}</article>",
+            NS, body
+        ));
+        assert_eq!(book.chapters.len(), 1);
+        book.chapters.into_iter().next().unwrap().content
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph(vec![Inline::text(text)])
+    }
+
+    fn table(body: &str) -> TableData {
+        let blocks = article(body);
+        blocks
+            .into_iter()
+            .find_map(|block| match block {
+                Block::Table(table) => Some(table),
+                _ => None,
+            })
+            .expect("a table")
+    }
+
+    fn cell_text(cell: &TableCell) -> String {
+        inlines_to_text(&cell.content)
+    }
+
+    #[test]
+    fn test_decode_metadata() {
+        let book = decode(&format!(
+            r#"<book {} xml:lang="en-GB">
+              <info>
+                <title>Field Guide</title>
+                <author><personname><firstname>Jo</firstname><surname>Smith</surname></personname></author>
+                <author><orgname>Bird Society</orgname></author>
+                <pubdate>2019-03-04</pubdate>
+                <publisher><publishername>Campus Press</publishername></publisher>
+                <copyright><year>2019</year><holder>Jo Smith</holder></copyright>
+                <keywordset><keyword>Birds</keyword></keywordset>
+                <biblioid class="isbn">978-0-00-000000-0</biblioid>
+              </info>
+              <chapter><title>One</title><para>Text.</para></chapter>
+            </book>"#,
+            NS
+        ));
+
+        assert_eq!(book.metadata.title, "Field Guide");
+        assert_eq!(book.metadata.creator, vec!["Jo Smith", "Bird Society"]);
+        assert_eq!(book.metadata.language, "en-GB");
+        assert_eq!(book.metadata.publisher.as_deref(), Some("Campus Press"));
+        assert_eq!(
+            book.metadata.rights.as_deref(),
+            Some("Copyright \u{A9} 2019 Jo Smith")
+        );
+        assert_eq!(book.metadata.subject, vec!["Birds"]);
+        assert_eq!(book.metadata.identifier, "978-0-00-000000-0");
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("2019-03-04".to_string())
+        );
+    }
+
+    #[test]
+    fn test_book_divisions() {
+        let book = decode(&format!(
+            r#"<book {}>
+              <title>Guide</title>
+              <para>Preface text.</para>
+              <part xml:id="p1">
+                <title>Basics</title>
+                <chapter xml:id="intro"><title>Introduction</title><para>Text.</para></chapter>
+                <chapter><para>Untitled.</para></chapter>
+              </part>
+              <appendix><title>Extra</title></appendix>
+            </book>"#,
+            NS
+        ));
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Guide", "Basics", "Introduction", "Chapter", "Extra"]
+        );
+        assert_eq!(book.chapters[0].content, vec![paragraph("Preface text.")]);
+        assert!(matches!(
+            book.chapters[2].content[0],
+            Block::Header { level: 2, .. }
+        ));
+
+        let toc: Vec<&str> = book.toc.iter().map(|e| e.href.as_str()).collect();
+        assert_eq!(toc, vec!["#chapter-1", "#p1", "#chapter-5"]);
+        let parts: Vec<&str> = book.toc[1]
+            .children
+            .iter()
+            .map(|e| e.href.as_str())
+            .collect();
+        assert_eq!(parts, vec!["#intro", "#chapter-4"]);
+    }
+
+    #[test]
+    fn test_article_sections() {
+        let book = decode(&format!(
+            r#"<article {}>
+              <info><title>Notes</title></info>
+              <para>Opening.</para>
+              <sect1 xml:id="s1">
+                <title>First</title>
+                <para>One.</para>
+                <sect2 xml:id="s1-1"><title>Deeper</title><para>Two.</para></sect2>
+                <bridgehead>Aside</bridgehead>
+              </sect1>
+            </article>"#,
+            NS
+        ));
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Notes", "First"]);
+        assert_eq!(
+            book.chapters[1].content[2],
+            Block::Header {
+                level: 2,
+                content: vec![Inline::text("Deeper")],
+                anchor: Some("s1-1".into()),
+            }
+        );
+        assert_eq!(
+            book.chapters[1].content[4],
+            Block::Header {
+                level: 2,
+                content: vec![Inline::text("Aside")],
+                anchor: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        assert_eq!(
+            article(
+                r#"<para><emphasis>a</emphasis> <emphasis role="bold">b</emphasis> <emphasis role="strikethrough">c</emphasis> <citetitle>d</citetitle> x<superscript>2</superscript> <command>ls</command> <quote>q</quote><remark>hidden</remark></para>"#
+            ),
+            vec![Block::Paragraph(vec![
+                Inline::Italic(vec![Inline::text("a")]),
+                Inline::text(" "),
+                Inline::Bold(vec![Inline::text("b")]),
+                Inline::text(" "),
+                Inline::Strikethrough(vec![Inline::text("c")]),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("d")]),
+                Inline::text(" x"),
+                Inline::Superscript(vec![Inline::text("2")]),
+                Inline::text(" "),
+                Inline::Code("ls".into()),
+                Inline::text(" "),
+                Inline::text("\u{201C}"),
+                Inline::text("q"),
+                Inline::text("\u{201D}"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_links_and_cross_references() {
+        let book = decode(&format!(
+            r#"<article {}>
+              <title>T</title>
+              <para>See <xref linkend="later"/>, <xref linkend="nowhere"/>, <link xlink:href="https://example.com">the site</link>, <link linkend="later"/> and <email>jo@example.com</email>.</para>
+              <section xml:id="later"><title>Later</title><para>Text.</para></section>
+            </article>"#,
+            NS
+        ));
+
+        let link = |text: &str, url: &str| Inline::Link {
+            children: vec![Inline::text(text)],
+            url: url.into(),
+        };
+        assert_eq!(
+            book.chapters[0].content,
+            vec![Block::Paragraph(vec![
+                Inline::text("See "),
+                link("Later", "#later"),
+                Inline::text(", "),
+                link("nowhere", "#nowhere"),
+                Inline::text(", "),
+                link("the site", "https://example.com"),
+                Inline::text(", "),
+                link("later", "#later"),
+                Inline::text(" and "),
+                link("jo@example.com", "mailto:jo@example.com"),
+                Inline::text("."),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        assert_eq!(
+            article(
+                r#"<para>One<footnote xml:id="fn-a"><para>First.</para></footnote> two<footnote><para>Second.</para></footnote> again<footnoteref linkend="fn-a"/></para>"#
+            ),
+            vec![
+                Block::Paragraph(vec![
+                    Inline::text("One"),
+                    Inline::FootnoteRef { id: "fn-a".into() },
+                    Inline::text(" two"),
+                    Inline::FootnoteRef {
+                        id: "footnote-2".into()
+                    },
+                    Inline::text(" again"),
+                    Inline::FootnoteRef { id: "fn-a".into() },
+                ]),
+                Block::Footnote {
+                    id: "fn-a".into(),
+                    content: vec![paragraph("First.")],
+                },
+                Block::Footnote {
+                    id: "footnote-2".into(),
+                    content: vec![paragraph("Second.")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks_inside_paragraphs() {
+        assert_eq!(
+            article(
+                r#"<para>Run this:
+                  <programlisting language="sh">init --all
+  done</programlisting>
+                  then <itemizedlist><listitem><para>check</para></listitem></itemizedlist></para>"#
+            ),
+            vec![
+                paragraph("Run this:"),
+                Block::CodeBlock {
+                    lang: Some("sh".into()),
+                    code: "init --all\n  done".into(),
+                },
+                paragraph("then"),
+                Block::List {
+                    items: vec![vec![paragraph("check")]],
+                    ordered: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lists_and_verbatim() {
+        assert_eq!(
+            article(
+                r#"<orderedlist><title>Steps</title><listitem><para>One</para></listitem></orderedlist>
+                  <variablelist><varlistentry><term>A</term><term>B</term><listitem><para>Letters</para></listitem></varlistentry></variablelist>
+                  <glossentry><glossterm>C</glossterm><glossdef><para>Sea</para></glossdef></glossentry>
+                  <literallayout>  first
+  second</literallayout>
+                  <literallayout class="monospaced">a  b</literallayout>"#
+            ),
+            vec![
+                Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Steps")])]),
+                Block::List {
+                    items: vec![vec![paragraph("One")]],
+                    ordered: true,
+                },
+                Block::DefinitionList(vec![
+                    DefinitionItem {
+                        term: vec![Inline::text("A"), Inline::text(", "), Inline::text("B")],
+                        definitions: vec![vec![paragraph("Letters")]],
+                    },
+                    DefinitionItem {
+                        term: vec![Inline::text("C")],
+                        definitions: vec![vec![paragraph("Sea")]],
+                    },
+                ]),
+                Block::Paragraph(vec![
+                    Inline::text("first"),
+                    Inline::Break,
+                    Inline::text("second"),
+                ]),
+                Block::CodeBlock {
+                    lang: None,
+                    code: "a  b".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cals_table_spans() {
+        let table = table(
+            r#"<table><title>Sizes</title><tgroup cols="3">
+              <colspec colname="a"/><colspec colname="b"/><colspec colname="c"/>
+              <spanspec spanname="bc" namest="b" nameend="c"/>
+              <thead><row><entry>Bird</entry><entry namest="b" nameend="c">Size</entry></row></thead>
+              <tbody>
+                <row><entry morerows="1">Wren</entry><entry>10</entry><entry>cm</entry></row>
+                <row><entry spanname="bc"><para>small</para><para>bird</para></entry></row>
+              </tbody>
+              <tfoot><row><entry namest="a" nameend="z">Total</entry></row></tfoot>
+            </tgroup></table>"#,
+        );
+
+        assert_eq!(table.headers.len(), 2);
+        assert_eq!(table.headers[1].colspan, 2);
+        assert_eq!(table.rows[0][0].rowspan, 2);
+        assert_eq!(table.rows[0][1].rowspan, 1);
+        assert_eq!(table.rows[1][0].colspan, 2);
+        assert_eq!(
+            table.rows[1][0].content,
+            vec![Inline::text("small"), Inline::Break, Inline::text("bird")]
+        );
+        // A span to an unknown column is ignored
+        assert_eq!(cell_text(&table.rows[2][0]), "Total");
+        assert_eq!(table.rows[2][0].colspan, 1);
+    }
+
+    #[test]
+    fn test_html_table() {
+        let table = table(
+            r#"<informaltable><tr><th>Name</th><th>Size</th></tr><tr><td colspan="2">Wide</td></tr><tr><td rowspan="3">Tall</td><td>x</td></tr></informaltable>"#,
+        );
+
+        let headers: Vec<String> = table.headers.iter().map(cell_text).collect();
+        assert_eq!(headers, vec!["Name", "Size"]);
+        assert_eq!(table.rows[0][0].colspan, 2);
+        assert_eq!(table.rows[1][0].rowspan, 3);
+    }
+
+    #[test]
+    fn test_admonitions_and_quotes() {
+        assert_eq!(
+            article(
+                r#"<warning><title>Careful</title><para>Hot.</para></warning>
+                  <blockquote><attribution>Jo</attribution><para>Quoted.</para></blockquote>"#
+            ),
+            vec![
+                Block::Admonition {
+                    kind: AdmonitionKind::Warning,
+                    content: vec![
+                        Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Careful")])]),
+                        paragraph("Hot."),
+                    ],
+                },
+                Block::Blockquote(vec![
+                    paragraph("Quoted."),
+                    Block::Paragraph(vec![Inline::text("\u{2014} "), Inline::text("Jo")]),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let decoder = DocBookDecoder::new();
+        let decode = |xml: &str| decoder.decode(&mut Cursor::new(xml.as_bytes()));
+
+        assert!(decode("<book><chapter>").is_err());
+        assert!(matches!(
+            decode("<html><body/></html>"),
+            Err(ParseError::UnsupportedFormat(_))
+        ));
+
+        // Missing references, bad spans and empty divisions are tolerated
+        let book = decode(&format!(
+            r#"<article {}><para><xref/><footnoteref/>Text</para>
+              <informaltable><tgroup cols="1"><tbody><row><entry morerows="x" namest="q">A</entry></row></tbody></tgroup></informaltable>
+              <section/></article>"#,
+            NS
+        ))
+        .unwrap();
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Untitled", "Section"]);
+        assert_eq!(book.chapters[0].content[0], paragraph("Text"));
+        let Block::Table(table) = &book.chapters[0].content[1] else {
+            panic!("expected a table, got {:?}", book.chapters[0].content[1]);
+        };
+        assert_eq!((table.rows[0][0].colspan, table.rows[0][0].rowspan), (1, 1));
+    }
+}
@@ -0,0 +1,1195 @@
+//! DOCX decoder implementation
+//!
+//! A DOCX file is a ZIP package of WordprocessingML parts. The text lives in
+//! `word/document.xml`; paragraph styles (`styles.xml`) decide which paragraphs
+//! are headings, numbering definitions (`numbering.xml`) which are list items,
+//! and relationships (`document.xml.rels`) resolve images and hyperlinks.
+//! Footnotes and endnotes have parts of their own, and `docProps/core.xml` holds
+//! the document properties.
+
+use super::chapters::place_footnotes;
+use super::context::sniff_mime_type;
+use super::front_matter::parse_date;
+use super::markdown::inlines_to_text;
+use super::xml::{self, trim_inlines};
+use super::{ChapterSplitter, SplitOptions, SplitStrategy};
+use crate::error::ParseError;
+use crate::types::{Block, Book, Inline, Metadata, Resource, ResourceStore, TableCell, TableData};
+use roxmltree::Node;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+/// Decoder for Word documents (DOCX)
+pub struct DocxDecoder {
+    splitter: ChapterSplitter,
+}
+
+impl DocxDecoder {
+    pub fn new() -> Self {
+        Self {
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Choose where chapters begin (by default, at Heading 1 paragraphs)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+}
+
+impl Default for DocxDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for DocxDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read DOCX: {}", e)))?;
+
+        let mut package = Package::open(data)?;
+        let document_path = package.main_document();
+        let document_text = package
+            .read_xml(&document_path)
+            .ok_or_else(|| ParseError::MissingField(document_path.clone()))?;
+        let document = xml::parse(&document_text)?;
+
+        let base = document_path
+            .rsplit_once('/')
+            .map(|(dir, _)| format!("{}/", dir))
+            .unwrap_or_default();
+        let rels_path = format!("{}_rels/{}.rels", base, &document_path[base.len()..]);
+
+        let styles_text = package.read_xml(&format!("{}styles.xml", base));
+        let styles = match &styles_text {
+            Some(text) => Styles::parse(&xml::parse(text)?),
+            None => Styles::default(),
+        };
+        let numbering_text = package.read_xml(&format!("{}numbering.xml", base));
+        let numbering = match &numbering_text {
+            Some(text) => Numbering::parse(&xml::parse(text)?),
+            None => Numbering::default(),
+        };
+        let relationships = match package.read_xml(&rels_path) {
+            Some(text) => relationships(&xml::parse(&text)?, &base),
+            None => HashMap::new(),
+        };
+
+        let converter = Converter {
+            styles,
+            numbering,
+            relationships,
+            package: RefCell::new(package),
+            resources: RefCell::new(ResourceStore::new()),
+            images: RefCell::new(HashMap::new()),
+            title: RefCell::new(None),
+        };
+
+        let body = xml::child(document.root_element(), "body")
+            .ok_or_else(|| ParseError::MissingField("w:body".to_string()))?;
+        let mut output = Output::default();
+        converter.body_to_blocks(body, &mut output);
+        let (blocks, page_breaks) = output.finish();
+
+        // Footnotes and endnotes
+        let mut footnotes = Vec::new();
+        for (part, prefix) in [("footnotes.xml", "footnote"), ("endnotes.xml", "endnote")] {
+            let text = converter
+                .package
+                .borrow_mut()
+                .read_xml(&format!("{}{}", base, part));
+            let Some(text) = text else {
+                continue;
+            };
+            let notes = xml::parse(&text)?;
+            for note in notes.root_element().children().filter(|n| n.is_element()) {
+                let (Some(id), None) = (xml::attribute(note, "id"), xml::attribute(note, "type"))
+                else {
+                    continue;
+                };
+                let mut output = Output::default();
+                converter.body_to_blocks(note, &mut output);
+                let id = format!("{}-{}", prefix, id);
+                footnotes.push((
+                    id.clone(),
+                    Block::Footnote {
+                        id,
+                        content: output.finish().0,
+                    },
+                ));
+            }
+        }
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        if let Some(text) = converter.package.borrow_mut().read_xml("docProps/core.xml") {
+            apply_core_properties(&xml::parse(&text)?, &mut metadata);
+        }
+        if let Some(text) = converter.package.borrow_mut().read_xml("docProps/app.xml") {
+            let properties = xml::parse(&text)?;
+            metadata.publisher = xml::child(properties.root_element(), "Company")
+                .map(xml::text)
+                .filter(|company| !company.is_empty());
+        }
+        if metadata.title == "Untitled" {
+            if let Some(title) = converter.title.take() {
+                metadata.title = title;
+            }
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.chapters = self.splitter.split_with_page_breaks(blocks, &page_breaks);
+        place_footnotes(&mut book.chapters, footnotes);
+        book.resources = converter.resources.into_inner();
+
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["docx"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// The ZIP package holding a document's parts
+struct Package {
+    archive: zip::ZipArchive<Cursor<Vec<u8>>>,
+}
+
+impl Package {
+    fn open(data: Vec<u8>) -> Result<Self, ParseError> {
+        let archive = zip::ZipArchive::new(Cursor::new(data))
+            .map_err(|e| ParseError::MalformedContent(format!("Invalid DOCX package: {}", e)))?;
+        Ok(Self { archive })
+    }
+
+    /// Read a part, if present
+    fn read(&mut self, path: &str) -> Option<Vec<u8>> {
+        let mut file = self.archive.by_name(path).ok()?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).ok()?;
+        Some(data)
+    }
+
+    /// Read an XML part as text
+    fn read_xml(&mut self, path: &str) -> Option<String> {
+        self.read(path).map(|data| xml::decode_xml(&data))
+    }
+
+    /// Path of the main document part, from the package relationships
+    fn main_document(&mut self) -> String {
+        self.read_xml("_rels/.rels")
+            .and_then(|text| {
+                let rels = xml::parse(&text).ok()?;
+                let target = rels
+                    .root_element()
+                    .children()
+                    .filter(|n| n.is_element())
+                    .find(|rel| {
+                        rel.attribute("Type")
+                            .is_some_and(|t| t.ends_with("/officeDocument"))
+                    })?
+                    .attribute("Target")?;
+                Some(target.trim_start_matches('/').to_string())
+            })
+            .unwrap_or_else(|| "word/document.xml".to_string())
+    }
+}
+
+/// Relationship targets by id, with package paths resolved against `base`
+///
+/// External targets (hyperlinks) are kept as they are.
+fn relationships(rels: &roxmltree::Document, base: &str) -> HashMap<String, String> {
+    rels.root_element()
+        .children()
+        .filter(|n| n.is_element())
+        .filter_map(|rel| {
+            let id = rel.attribute("Id")?;
+            let target = rel.attribute("Target")?;
+            let target = if rel.attribute("TargetMode") == Some("External") {
+                target.to_string()
+            } else {
+                resolve_path(base, target)
+            };
+            Some((id.to_string(), target))
+        })
+        .collect()
+}
+
+/// Resolve a relative part path, handling `..` segments
+fn resolve_path(base: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut segments: Vec<&str> = base.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => {}
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Fill in metadata from the core properties part
+fn apply_core_properties(core: &roxmltree::Document, metadata: &mut Metadata) {
+    let root = core.root_element();
+    let value = |name| {
+        xml::child(root, name)
+            .map(xml::text)
+            .filter(|value| !value.is_empty())
+    };
+
+    if let Some(title) = value("title") {
+        metadata.title = title;
+    }
+    if let Some(creator) = value("creator") {
+        metadata.creator = creator
+            .split(';')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    metadata.subject = value("subject").into_iter().collect();
+    if let Some(keywords) = value("keywords") {
+        metadata.subject.extend(
+            keywords
+                .split([',', ';'])
+                .map(str::trim)
+                .filter(|keyword| !keyword.is_empty())
+                .map(str::to_string),
+        );
+    }
+    metadata.description = value("description");
+    if let Some(language) = value("language") {
+        metadata.language = language;
+    }
+    if let Some(identifier) = value("identifier") {
+        metadata.identifier = identifier;
+    }
+    metadata.date = value("created").and_then(|date| parse_date(&date));
+}
+
+/// A paragraph style
+#[derive(Default)]
+struct Style {
+    name: String,
+    based_on: Option<String>,
+    outline_level: Option<u8>,
+    numbering: Option<(String, u8)>,
+}
+
+/// Paragraph and character styles by id
+#[derive(Default)]
+struct Styles {
+    styles: HashMap<String, Style>,
+}
+
+impl Styles {
+    fn parse(styles: &roxmltree::Document) -> Self {
+        let styles = xml::children(styles.root_element(), "style")
+            .filter_map(|style| {
+                let id = xml::attribute(style, "styleId")?;
+                let paragraph = xml::child(style, "pPr");
+                let style = Style {
+                    name: xml::child(style, "name")
+                        .and_then(|name| xml::attribute(name, "val"))
+                        .unwrap_or(id)
+                        .to_lowercase(),
+                    based_on: xml::child(style, "basedOn")
+                        .and_then(|based_on| xml::attribute(based_on, "val"))
+                        .map(str::to_string),
+                    outline_level: paragraph.and_then(outline_level),
+                    numbering: paragraph.and_then(numbering_reference),
+                };
+                Some((id.to_string(), style))
+            })
+            .collect();
+        Self { styles }
+    }
+
+    /// A style and those it is based on, nearest first
+    fn chain<'a>(&'a self, id: &str) -> impl Iterator<Item = &'a Style> + 'a {
+        let mut next = self.styles.get(id);
+        // Limit the depth in case of cycles
+        std::iter::from_fn(move || {
+            let style = next?;
+            next = style.based_on.as_deref().and_then(|id| self.styles.get(id));
+            Some(style)
+        })
+        .take(16)
+    }
+
+    fn name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.styles
+            .get(id)
+            .map(|style| style.name.as_str())
+            .unwrap_or(id)
+    }
+
+    /// Heading level of paragraphs in a style
+    fn heading_level(&self, id: &str) -> Option<u8> {
+        self.chain(id).find_map(|style| {
+            style
+                .name
+                .strip_prefix("heading ")
+                .and_then(|level| level.trim().parse().ok())
+                .or(style.outline_level)
+        })
+    }
+
+    /// List numbering applied by a style
+    fn numbering(&self, id: &str) -> Option<(String, u8)> {
+        self.chain(id).find_map(|style| style.numbering.clone())
+    }
+
+    /// Whether a style, or one it is based on, has one of these names
+    fn is_a(&self, id: &str, names: &[&str]) -> bool {
+        self.chain(id)
+            .any(|style| names.contains(&style.name.as_str()))
+    }
+}
+
+/// Heading level from an `<w:outlineLvl>` (0 is Heading 1, 9 is body text)
+fn outline_level(properties: Node) -> Option<u8> {
+    let level: u8 = xml::attribute(xml::child(properties, "outlineLvl")?, "val")?
+        .parse()
+        .ok()?;
+    (level < 6).then_some(level + 1)
+}
+
+/// Numbering instance and level from an `<w:numPr>`
+fn numbering_reference(properties: Node) -> Option<(String, u8)> {
+    let numbering = xml::child(properties, "numPr")?;
+    let id = xml::attribute(xml::child(numbering, "numId")?, "val")?;
+    let level = xml::child(numbering, "ilvl")
+        .and_then(|level| xml::attribute(level, "val"))
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(0);
+    Some((id.to_string(), level))
+}
+
+/// List numbering definitions
+#[derive(Default)]
+struct Numbering {
+    /// Abstract numbering id of each numbering instance
+    instances: HashMap<String, String>,
+    /// Number format of each level of each abstract numbering
+    formats: HashMap<String, HashMap<u8, String>>,
+}
+
+impl Numbering {
+    fn parse(numbering: &roxmltree::Document) -> Self {
+        let root = numbering.root_element();
+        let instances = xml::children(root, "num")
+            .filter_map(|num| {
+                let id = xml::attribute(num, "numId")?;
+                let abstract_id = xml::attribute(xml::child(num, "abstractNumId")?, "val")?;
+                Some((id.to_string(), abstract_id.to_string()))
+            })
+            .collect();
+        let formats = xml::children(root, "abstractNum")
+            .filter_map(|definition| {
+                let id = xml::attribute(definition, "abstractNumId")?;
+                let levels = xml::children(definition, "lvl")
+                    .filter_map(|level| {
+                        let index = xml::attribute(level, "ilvl")?.parse().ok()?;
+                        let format = xml::attribute(xml::child(level, "numFmt")?, "val")?;
+                        Some((index, format.to_string()))
+                    })
+                    .collect();
+                Some((id.to_string(), levels))
+            })
+            .collect();
+        Self { instances, formats }
+    }
+
+    /// Whether a list level is numbered rather than bulleted
+    fn is_ordered(&self, id: &str, level: u8) -> bool {
+        self.instances
+            .get(id)
+            .and_then(|abstract_id| self.formats.get(abstract_id))
+            .and_then(|levels| levels.get(&level))
+            .is_some_and(|format| format != "bullet" && format != "none")
+    }
+}
+
+/// Character formatting of a run
+#[derive(Clone, Copy, PartialEq, Default)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+    superscript: bool,
+    subscript: bool,
+    code: bool,
+}
+
+impl Format {
+    /// Wrap text in this formatting
+    fn apply(self, content: Vec<Inline>) -> Vec<Inline> {
+        if self.code {
+            return vec![Inline::Code(inlines_to_text(&content))];
+        }
+
+        let mut content = content;
+        if self.subscript {
+            content = vec![Inline::Subscript(content)];
+        }
+        if self.superscript {
+            content = vec![Inline::Superscript(content)];
+        }
+        if self.strikethrough {
+            content = vec![Inline::Strikethrough(content)];
+        }
+        if self.italic {
+            content = vec![Inline::Italic(content)];
+        }
+        if self.bold {
+            content = vec![Inline::Bold(content)];
+        }
+        content
+    }
+}
+
+/// A paragraph's converted content
+#[derive(Default)]
+struct Paragraph {
+    inlines: Vec<Inline>,
+    images: Vec<Block>,
+    page_break: bool,
+}
+
+impl Paragraph {
+    /// Append a run's content, merging it with the previous run when both have
+    /// the same formatting
+    fn push(
+        &mut self,
+        format: Format,
+        content: Vec<Inline>,
+        pending: &mut Option<(Format, Vec<Inline>)>,
+    ) {
+        match pending {
+            Some((current, inlines)) if *current == format => inlines.extend(content),
+            _ => {
+                self.flush(pending);
+                *pending = Some((format, content));
+            }
+        }
+    }
+
+    fn flush(&mut self, pending: &mut Option<(Format, Vec<Inline>)>) {
+        if let Some((format, content)) = pending.take() {
+            self.inlines.extend(format.apply(merge_text(content)));
+        }
+    }
+}
+
+/// Blocks produced from a body, with paragraphs grouped into lists, quotes and
+/// code blocks
+#[derive(Default)]
+struct Output {
+    blocks: Vec<Block>,
+    page_breaks: Vec<usize>,
+    /// A page break precedes the next block
+    page_break: bool,
+    /// List items (level, ordered, content) awaiting their list
+    list: Vec<(u8, bool, Vec<Block>)>,
+    /// Numbering instance of the pending list items
+    list_id: Option<String>,
+    quote: Vec<Block>,
+    code: Vec<String>,
+}
+
+impl Output {
+    fn push(&mut self, block: Block) {
+        self.flush();
+        self.push_block(block);
+    }
+
+    fn push_block(&mut self, block: Block) {
+        if std::mem::take(&mut self.page_break) {
+            self.page_breaks.push(self.blocks.len());
+        }
+        self.blocks.push(block);
+    }
+
+    fn flush(&mut self) {
+        self.list_id = None;
+        if !self.list.is_empty() {
+            let items = std::mem::take(&mut self.list);
+            let list = build_list(&items);
+            self.push_block(list);
+        }
+        if !self.quote.is_empty() {
+            let content = std::mem::take(&mut self.quote);
+            self.push_block(Block::Blockquote(content));
+        }
+        if !self.code.is_empty() {
+            let code = std::mem::take(&mut self.code).join("\n");
+            self.push_block(Block::CodeBlock { lang: None, code });
+        }
+    }
+
+    fn finish(mut self) -> (Vec<Block>, Vec<usize>) {
+        self.flush();
+        (self.blocks, self.page_breaks)
+    }
+}
+
+/// Build a (possibly nested) list from items in document order
+fn build_list(items: &[(u8, bool, Vec<Block>)]) -> Block {
+    let base = items.iter().map(|(level, _, _)| *level).min().unwrap_or(0);
+    let ordered = items
+        .iter()
+        .find(|(level, _, _)| *level == base)
+        .is_some_and(|(_, ordered, _)| *ordered);
+
+    let mut list_items: Vec<Vec<Block>> = Vec::new();
+    let mut index = 0;
+    while index < items.len() {
+        let (level, _, content) = &items[index];
+        if *level <= base {
+            list_items.push(content.clone());
+            index += 1;
+            continue;
+        }
+
+        // Deeper items form a list nested in the preceding item
+        let end = items[index..]
+            .iter()
+            .position(|(level, _, _)| *level <= base)
+            .map_or(items.len(), |offset| index + offset);
+        let nested = build_list(&items[index..end]);
+        match list_items.last_mut() {
+            Some(item) => item.push(nested),
+            None => list_items.push(vec![nested]),
+        }
+        index = end;
+    }
+
+    Block::List {
+        items: list_items,
+        ordered,
+    }
+}
+
+/// State shared while converting a document
+struct Converter {
+    styles: Styles,
+    numbering: Numbering,
+    /// Relationship targets by id
+    relationships: HashMap<String, String>,
+    package: RefCell<Package>,
+    resources: RefCell<ResourceStore>,
+    /// Resource keys of images already loaded, by part path
+    images: RefCell<HashMap<String, String>>,
+    /// Text of the first Title paragraph
+    title: RefCell<Option<String>>,
+}
+
+impl Converter {
+    /// Convert the paragraphs and tables of a body, note or table cell
+    fn body_to_blocks(&self, node: Node, output: &mut Output) {
+        for child in node.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "p" => self.paragraph_to_blocks(child, output),
+                "tbl" => {
+                    let table = self.table(child);
+                    output.push(Block::Table(table));
+                }
+                // Content controls and tracked insertions wrap ordinary content
+                "sdt" | "sdtContent" | "ins" | "customXml" => self.body_to_blocks(child, output),
+                _ => {}
+            }
+        }
+    }
+
+    fn paragraph_to_blocks(&self, paragraph: Node, output: &mut Output) {
+        let properties = xml::child(paragraph, "pPr");
+        let style = properties
+            .and_then(|p| xml::child(p, "pStyle"))
+            .and_then(|style| xml::attribute(style, "val"))
+            .unwrap_or("Normal");
+
+        if properties.is_some_and(|p| xml::child(p, "pageBreakBefore").is_some()) {
+            output.flush();
+            output.page_break = true;
+        }
+
+        let mut content = Paragraph::default();
+        let mut pending = None;
+        self.runs(paragraph, &mut content, &mut pending);
+        content.flush(&mut pending);
+        let Paragraph {
+            mut inlines,
+            images,
+            page_break,
+        } = content;
+        trim_inlines(&mut inlines);
+
+        let heading = properties
+            .and_then(outline_level)
+            .or_else(|| self.styles.heading_level(style));
+        let numbering = properties
+            .and_then(numbering_reference)
+            .or_else(|| self.styles.numbering(style))
+            .filter(|(id, _)| id != "0");
+
+        if self.styles.is_a(style, &["title"]) {
+            let text = inlines_to_text(&inlines);
+            let mut title = self.title.borrow_mut();
+            if title.is_none() && !text.trim().is_empty() {
+                *title = Some(text.trim().to_string());
+            }
+        } else if let Some(level) = heading {
+            if !inlines.is_empty() {
+                output.push(Block::Header {
+                    level: level.min(6),
+                    content: inlines,
+                    anchor: None,
+                });
+            }
+        } else if let Some((id, level)) = &numbering {
+            // A different numbering instance is a separate list
+            let other_list = output.list_id.as_ref().is_some_and(|list| list != id);
+            if other_list || !output.quote.is_empty() || !output.code.is_empty() {
+                output.flush();
+            }
+            output.list_id = Some(id.clone());
+            let ordered = self.numbering.is_ordered(id, *level);
+            let mut item = Vec::new();
+            if !inlines.is_empty() {
+                item.push(Block::Paragraph(inlines));
+            }
+            item.extend(images.iter().cloned());
+            output.list.push((*level, ordered, item));
+        } else if self
+            .styles
+            .is_a(style, &["quote", "intense quote", "block text"])
+        {
+            if !output.list.is_empty() || !output.code.is_empty() {
+                output.flush();
+            }
+            if !inlines.is_empty() {
+                output.quote.push(Block::Paragraph(inlines));
+            }
+        } else if self.is_code_style(style) {
+            if !output.list.is_empty() || !output.quote.is_empty() {
+                output.flush();
+            }
+            output.code.push(inlines_to_text(&inlines));
+        } else if !inlines.is_empty() {
+            output.push(Block::Paragraph(inlines));
+        }
+
+        // Images in list items stay in their item
+        if numbering.is_none() {
+            for image in images {
+                output.push(image);
+            }
+        }
+
+        if page_break {
+            output.flush();
+            output.page_break = true;
+        }
+    }
+
+    fn is_code_style(&self, style: &str) -> bool {
+        self.styles.chain(style).any(|style| {
+            style.name.contains("code")
+                || style.name == "html preformatted"
+                || style.name == "source text"
+        })
+    }
+
+    /// Convert the runs of a paragraph, hyperlink or other run container
+    fn runs(
+        &self,
+        node: Node,
+        paragraph: &mut Paragraph,
+        pending: &mut Option<(Format, Vec<Inline>)>,
+    ) {
+        for child in node.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "r" => self.run(child, paragraph, pending),
+                "hyperlink" => {
+                    let url = xml::attribute(child, "id")
+                        .and_then(|id| self.relationships.get(id).cloned())
+                        .or_else(|| xml::attribute(child, "anchor").map(|a| format!("#{}", a)));
+
+                    let mut link = Paragraph::default();
+                    let mut link_pending = None;
+                    self.runs(child, &mut link, &mut link_pending);
+                    link.flush(&mut link_pending);
+                    paragraph.images.extend(link.images);
+
+                    match url {
+                        Some(url) => paragraph.push(
+                            Format::default(),
+                            vec![Inline::Link {
+                                children: link.inlines,
+                                url,
+                            }],
+                            pending,
+                        ),
+                        None => paragraph.push(Format::default(), link.inlines, pending),
+                    }
+                }
+                // Deleted text is left out; other wrappers pass their runs through
+                "del" | "moveFrom" | "pPr" => {}
+                _ => self.runs(child, paragraph, pending),
+            }
+        }
+    }
+
+    /// Convert a run of text with uniform formatting
+    fn run(
+        &self,
+        run: Node,
+        paragraph: &mut Paragraph,
+        pending: &mut Option<(Format, Vec<Inline>)>,
+    ) {
+        let format = xml::child(run, "rPr")
+            .map(|properties| self.format(properties))
+            .unwrap_or_default();
+
+        for child in run.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "t" => {
+                    let text = child.text().unwrap_or_default();
+                    if !text.is_empty() {
+                        paragraph.push(format, vec![Inline::text(text)], pending);
+                    }
+                }
+                "tab" => paragraph.push(format, vec![Inline::text(" ")], pending),
+                "noBreakHyphen" => paragraph.push(format, vec![Inline::text("\u{2011}")], pending),
+                "br" if xml::attribute(child, "type") == Some("page") => {
+                    paragraph.page_break = true
+                }
+                "br" | "cr" => paragraph.push(Format::default(), vec![Inline::Break], pending),
+                "footnoteReference" | "endnoteReference" => {
+                    let prefix = if child.tag_name().name() == "footnoteReference" {
+                        "footnote"
+                    } else {
+                        "endnote"
+                    };
+                    if let Some(id) = xml::attribute(child, "id") {
+                        let id = format!("{}-{}", prefix, id);
+                        paragraph.push(
+                            Format::default(),
+                            vec![Inline::FootnoteRef { id }],
+                            pending,
+                        );
+                    }
+                }
+                "drawing" | "pict" | "object" => {
+                    if let Some(image) = self.image(child) {
+                        paragraph.images.push(image);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Formatting from run properties, including the run's character style
+    fn format(&self, properties: Node) -> Format {
+        let toggle = |name| {
+            xml::child(properties, name).is_some_and(|element| {
+                !matches!(xml::attribute(element, "val"), Some("0" | "false" | "off"))
+            })
+        };
+        let vertical = xml::child(properties, "vertAlign").and_then(|v| xml::attribute(v, "val"));
+        let style = xml::child(properties, "rStyle")
+            .and_then(|style| xml::attribute(style, "val"))
+            .map(|id| self.styles.name(id).to_string())
+            .unwrap_or_default();
+        let font = xml::child(properties, "rFonts")
+            .and_then(|fonts| xml::attribute(fonts, "ascii"))
+            .unwrap_or_default()
+            .to_lowercase();
+
+        Format {
+            bold: toggle("b") || style == "strong",
+            italic: toggle("i") || style == "emphasis",
+            strikethrough: toggle("strike") || toggle("dstrike"),
+            superscript: vertical == Some("superscript"),
+            subscript: vertical == Some("subscript"),
+            code: style.contains("code")
+                || ["courier", "consolas", "menlo", "monaco"]
+                    .iter()
+                    .any(|mono| font.contains(mono)),
+        }
+    }
+
+    /// Load the image of a drawing into the resource store
+    fn image(&self, drawing: Node) -> Option<Block> {
+        let reference = drawing
+            .descendants()
+            .find(|n| matches!(n.tag_name().name(), "blip" | "imagedata"))?;
+        let id = xml::attribute(reference, "embed").or_else(|| xml::attribute(reference, "id"))?;
+        let path = self.relationships.get(id)?;
+
+        let properties = drawing
+            .descendants()
+            .find(|n| n.tag_name().name() == "docPr");
+        let alt = properties
+            .and_then(|p| p.attribute("descr").or(p.attribute("title")))
+            .unwrap_or_default()
+            .to_string();
+
+        let cached = self.images.borrow().get(path).cloned();
+        let resource_key = match cached {
+            Some(key) => key,
+            None => {
+                let data = self.package.borrow_mut().read(path)?;
+                let mime = sniff_mime_type(&data, path);
+                let filename = path.rsplit('/').next().unwrap_or(path);
+                let key = self
+                    .resources
+                    .borrow_mut()
+                    .add(Resource::new(mime, data).with_filename(filename));
+                self.images.borrow_mut().insert(path.clone(), key.clone());
+                key
+            }
+        };
+
+        Some(Block::Image {
+            resource_key,
+            caption: None,
+            alt,
+        })
+    }
+
+    /// Convert a table, spanning cells merged across columns and rows
+    fn table(&self, table: Node) -> TableData {
+        let mut headers = Vec::new();
+        let mut rows: Vec<Vec<TableCell>> = Vec::new();
+        // Cell (row, index) that a vertical merge in each grid column continues
+        let mut merges: HashMap<usize, (usize, usize)> = HashMap::new();
+
+        for (row_index, row) in xml::children(table, "tr").enumerate() {
+            let is_header = row_index == 0
+                && xml::child(row, "trPr").is_some_and(|p| xml::child(p, "tblHeader").is_some());
+
+            let mut cells = Vec::new();
+            let mut column = 0;
+            for cell in xml::children(row, "tc") {
+                let properties = xml::child(cell, "tcPr");
+                let colspan = properties
+                    .and_then(|p| xml::child(p, "gridSpan"))
+                    .and_then(|span| xml::attribute(span, "val"))
+                    .and_then(|span| span.parse().ok())
+                    .unwrap_or(1u32);
+                let merge = properties.and_then(|p| xml::child(p, "vMerge"));
+                let merge_start = merge.map(|m| xml::attribute(m, "val") == Some("restart"));
+
+                if merge_start == Some(false) {
+                    // Continues the cell above, whose row span grows instead
+                    if let Some(&(merge_row, merge_index)) = merges.get(&column) {
+                        if let Some(above) = rows
+                            .get_mut(merge_row)
+                            .and_then(|row| row.get_mut(merge_index))
+                        {
+                            above.rowspan += 1;
+                        }
+                    }
+                    column += colspan as usize;
+                    continue;
+                }
+
+                let mut content = Vec::new();
+                for paragraph in xml::children(cell, "p") {
+                    let mut converted = Paragraph::default();
+                    let mut pending = None;
+                    self.runs(paragraph, &mut converted, &mut pending);
+                    converted.flush(&mut pending);
+                    trim_inlines(&mut converted.inlines);
+                    if converted.inlines.is_empty() {
+                        continue;
+                    }
+                    if !content.is_empty() {
+                        content.push(Inline::Break);
+                    }
+                    content.extend(converted.inlines);
+                }
+
+                let mut table_cell = TableCell::new(content);
+                table_cell.colspan = colspan;
+                if merge_start == Some(true) && !is_header {
+                    merges.insert(column, (rows.len(), cells.len()));
+                } else {
+                    merges.remove(&column);
+                }
+                cells.push(table_cell);
+                column += colspan as usize;
+            }
+
+            if is_header {
+                headers = cells;
+            } else {
+                rows.push(cells);
+            }
+        }
+
+        TableData { headers, rows }
+    }
+}
+
+/// Merge adjacent text inlines
+fn merge_text(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut merged: Vec<Inline> = Vec::with_capacity(inlines.len());
+    for inline in inlines {
+        match (merged.last_mut(), inline) {
+            (Some(Inline::Text(previous)), Inline::Text(text)) => previous.push_str(&text),
+            (_, inline) => merged.push(inline),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    const NS: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+
+    fn docx(body: &str, parts: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let document = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><w:document {}><w:body>{}</w:body></w:document>"#,
+            NS, body
+        );
+        for (name, content) in [("word/document.xml", document.as_str())]
+            .into_iter()
+            .chain(parts.iter().copied())
+        {
+            archive.start_file(name, FileOptions::default()).unwrap();
+            archive.write_all(content.as_bytes()).unwrap();
+        }
+        archive.finish().unwrap().into_inner()
+    }
+
+    fn decode(data: Vec<u8>) -> Book {
+        DocxDecoder::new().decode(&mut Cursor::new(data)).unwrap()
+    }
+
+    fn styles() -> String {
+        format!(
+            r#"<w:styles {}>
+              <w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/></w:style>
+              <w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style>
+              <w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Heading1"/></w:style>
+              <w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/></w:style>
+            </w:styles>"#,
+            NS
+        )
+    }
+
+    #[test]
+    fn test_decode_headings_and_formatting() {
+        let body = r#"
+          <w:p><w:pPr><w:pStyle w:val="Title"/></w:pPr><w:r><w:t>My Novel</w:t></w:r></w:p>
+          <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Chapter One</w:t></w:r></w:p>
+          <w:p><w:r><w:t xml:space="preserve">Plain </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">bold </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>text</w:t></w:r><w:r><w:rPr><w:vertAlign w:val="superscript"/></w:rPr><w:t>2</w:t></w:r></w:p>
+          <w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Section</w:t></w:r></w:p>
+          <w:p><w:pPr><w:pStyle w:val="Quote"/></w:pPr><w:r><w:t>Quoted</w:t></w:r></w:p>
+          <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Chapter Two</w:t></w:r></w:p>
+        "#;
+        let book = decode(docx(body, &[("word/styles.xml", &styles())]));
+
+        assert_eq!(book.metadata.title, "My Novel");
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Chapter One", "Chapter Two"]);
+
+        let content = &book.chapters[0].content;
+        assert_eq!(
+            content[1],
+            Block::Paragraph(vec![
+                Inline::text("Plain "),
+                Inline::Bold(vec![Inline::text("bold text")]),
+                Inline::Superscript(vec![Inline::text("2")]),
+            ])
+        );
+        assert!(matches!(content[2], Block::Header { level: 2, .. }));
+        assert_eq!(
+            content[3],
+            Block::Blockquote(vec![Block::Paragraph(vec![Inline::text("Quoted")])])
+        );
+    }
+
+    #[test]
+    fn test_decode_lists() {
+        let numbering = format!(
+            r#"<w:numbering {}>
+              <w:abstractNum w:abstractNumId="0">
+                <w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl>
+                <w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl>
+              </w:abstractNum>
+              <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+            </w:numbering>"#,
+            NS
+        );
+        let item = |level: u8, text: &str| {
+            format!(
+                r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="{}"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>{}</w:t></w:r></w:p>"#,
+                level, text
+            )
+        };
+        let body = [item(0, "One"), item(1, "Nested"), item(0, "Two")].concat();
+        let book = decode(docx(&body, &[("word/numbering.xml", &numbering)]));
+
+        let paragraph = |text: &str| Block::Paragraph(vec![Inline::text(text)]);
+        assert_eq!(
+            book.chapters[0].content,
+            vec![Block::List {
+                items: vec![
+                    vec![
+                        paragraph("One"),
+                        Block::List {
+                            items: vec![vec![paragraph("Nested")]],
+                            ordered: false,
+                        },
+                    ],
+                    vec![paragraph("Two")],
+                ],
+                ordered: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_decode_adjacent_lists() {
+        let numbering = format!(
+            r#"<w:numbering {}>
+              <w:abstractNum w:abstractNumId="0">
+                <w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl>
+              </w:abstractNum>
+              <w:abstractNum w:abstractNumId="1">
+                <w:lvl w:ilvl="0"><w:numFmt w:val="bullet"/></w:lvl>
+              </w:abstractNum>
+              <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+              <w:num w:numId="2"><w:abstractNumId w:val="1"/></w:num>
+            </w:numbering>"#,
+            NS
+        );
+        let item = |id: u8, text: &str| {
+            format!(
+                r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="{}"/></w:numPr></w:pPr><w:r><w:t>{}</w:t></w:r></w:p>"#,
+                id, text
+            )
+        };
+        let body = [item(1, "First"), item(1, "Second"), item(2, "Bullet")].concat();
+        let book = decode(docx(&body, &[("word/numbering.xml", &numbering)]));
+
+        let paragraph = |text: &str| Block::Paragraph(vec![Inline::text(text)]);
+        assert_eq!(
+            book.chapters[0].content,
+            vec![
+                Block::List {
+                    items: vec![vec![paragraph("First")], vec![paragraph("Second")]],
+                    ordered: true,
+                },
+                Block::List {
+                    items: vec![vec![paragraph("Bullet")]],
+                    ordered: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_tables_notes_and_images() {
+        let body = r#"
+          <w:tbl>
+            <w:tr><w:trPr><w:tblHeader/></w:trPr><w:tc><w:p><w:r><w:t>A</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>B</w:t></w:r></w:p></w:tc></w:tr>
+            <w:tr><w:tc><w:tcPr><w:vMerge w:val="restart"/></w:tcPr><w:p><w:r><w:t>1</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>2</w:t></w:r></w:p></w:tc></w:tr>
+            <w:tr><w:tc><w:tcPr><w:vMerge/></w:tcPr><w:p/></w:tc><w:tc><w:p><w:r><w:t>3</w:t></w:r></w:p></w:tc></w:tr>
+          </w:tbl>
+          <w:p><w:r><w:t>Noted</w:t></w:r><w:r><w:footnoteReference w:id="1"/></w:r></w:p>
+          <w:p><w:r><w:drawing><wp:docPr xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing" id="1" descr="A picture"/><a:blip xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" r:embed="rId1"/></w:drawing></w:r></w:p>
+        "#;
+        let footnotes = format!(
+            r#"<w:footnotes {}>
+              <w:footnote w:type="separator" w:id="-1"><w:p/></w:footnote>
+              <w:footnote w:id="1"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> The note.</w:t></w:r></w:p></w:footnote>
+            </w:footnotes>"#,
+            NS
+        );
+        let rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+            <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/>
+          </Relationships>"#;
+        let core = r#"<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+            <dc:title>Manuscript</dc:title><dc:creator>Jane Doe</dc:creator><cp:keywords>draft, fiction</cp:keywords><dcterms:created>2024-03-01T09:00:00Z</dcterms:created>
+          </cp:coreProperties>"#;
+        let book = decode(docx(
+            body,
+            &[
+                ("word/footnotes.xml", &footnotes),
+                ("word/_rels/document.xml.rels", rels),
+                ("word/media/image1.png", "\u{89}PNG"),
+                ("docProps/core.xml", core),
+            ],
+        ));
+
+        assert_eq!(book.metadata.title, "Manuscript");
+        assert_eq!(book.metadata.creator, vec!["Jane Doe"]);
+        assert_eq!(book.metadata.subject, vec!["draft", "fiction"]);
+        assert!(book.metadata.date.is_some());
+
+        let content = &book.chapters[0].content;
+        let Block::Table(table) = &content[0] else {
+            panic!("expected a table, got {:?}", content[0]);
+        };
+        assert_eq!(table.headers.len(), 2);
+        assert_eq!(table.rows[0][0].rowspan, 2);
+        assert_eq!(table.rows[1].len(), 1);
+
+        assert_eq!(
+            content[1],
+            Block::Paragraph(vec![
+                Inline::text("Noted"),
+                Inline::FootnoteRef {
+                    id: "footnote-1".into()
+                },
+            ])
+        );
+        assert!(matches!(&content[2], Block::Image { alt, .. } if alt == "A picture"));
+        assert_eq!(book.resources.len(), 1);
+        assert_eq!(
+            content[3],
+            Block::Footnote {
+                id: "footnote-1".into(),
+                content: vec![Block::Paragraph(vec![Inline::text("The note.")])],
+            }
+        );
+    }
+}
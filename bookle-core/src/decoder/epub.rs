@@ -1,5 +1,9 @@
 //! EPUB decoder implementation
 
+use super::context::rewrite_images;
+use super::html::HtmlConverter;
+use super::DecodeContext;
+use crate::diagnostic::Diagnostic;
 use crate::error::ParseError;
 use crate::types::{Block, Book, Chapter, Inline, Metadata, TocEntry};
 use std::io::Read;
@@ -23,257 +27,16 @@ impl EpubDecoder {
         self
     }
 
-    /// Parse HTML content into Block AST
-    fn parse_html_to_blocks(&self, html: &str) -> Result<Vec<Block>, ParseError> {
-        use scraper::{Html, Selector};
-
-        let document = Html::parse_document(html);
-
-        // Select body content (or root if no body)
-        let body_selector = Selector::parse("body").unwrap();
-        let root = document
-            .select(&body_selector)
-            .next()
-            .map(|el| el.inner_html())
-            .unwrap_or_else(|| html.to_string());
-
-        // Re-parse the body content
-        let fragment = Html::parse_fragment(&root);
-        let mut blocks = Vec::new();
-
-        // Process top-level elements
-        for element in fragment.root_element().children() {
-            if let Some(el) = element.value().as_element() {
-                if let Some(block) = self.element_to_block(el, &fragment, element)? {
-                    blocks.push(block);
-                }
-            }
-        }
-
-        Ok(blocks)
-    }
-
-    /// Convert an HTML element to a Block
-    fn element_to_block(
+    /// Parse HTML content into Block AST, reporting elements kept as plain text
+    fn parse_html_to_blocks(
         &self,
-        element: &scraper::node::Element,
-        _document: &scraper::Html,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Option<Block>, ParseError> {
-        let tag = element.name();
-
-        match tag {
-            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-                let level = tag[1..].parse::<u8>().unwrap_or(1);
-                let content = self.children_to_inlines(node)?;
-                let anchor = element.attr("id").map(|s| s.to_string());
-                Ok(Some(Block::Header {
-                    level,
-                    content,
-                    anchor,
-                }))
-            }
-            "p" => {
-                let content = self.children_to_inlines(node)?;
-                if content.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(Block::Paragraph(content)))
-                }
-            }
-            "ul" | "ol" => {
-                let ordered = tag == "ol";
-                let mut items = Vec::new();
-
-                for child in node.children() {
-                    if let Some(el) = child.value().as_element() {
-                        if el.name() == "li" {
-                            let item_blocks = self.li_to_blocks(child)?;
-                            items.push(item_blocks);
-                        }
-                    }
-                }
-
-                Ok(Some(Block::List { items, ordered }))
-            }
-            "blockquote" => {
-                let inner_blocks = self.children_to_blocks(node)?;
-                Ok(Some(Block::Blockquote(inner_blocks)))
-            }
-            "pre" => {
-                let code = self.get_text_content(node);
-                let lang = None; // Could extract from class
-                Ok(Some(Block::CodeBlock { lang, code }))
-            }
-            "hr" => Ok(Some(Block::ThematicBreak)),
-            "div" | "section" | "article" => {
-                // Container elements - recurse into children
-                let inner = self.children_to_blocks(node)?;
-                if inner.len() == 1 {
-                    Ok(Some(inner.into_iter().next().unwrap()))
-                } else if inner.is_empty() {
-                    Ok(None)
-                } else {
-                    // Wrap multiple blocks in a blockquote for now
-                    // TODO: Better handling of div containers
-                    Ok(Some(Block::Blockquote(inner)))
-                }
-            }
-            "img" => {
-                let src = element.attr("src").unwrap_or_default().to_string();
-                let alt = element.attr("alt").unwrap_or_default().to_string();
-                Ok(Some(Block::Image {
-                    resource_key: src, // Will be resolved later
-                    caption: None,
-                    alt,
-                }))
-            }
-            _ => {
-                // Unknown block element - try to extract as paragraph
-                let content = self.children_to_inlines(node)?;
-                if content.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(Block::Paragraph(content)))
-                }
-            }
-        }
-    }
-
-    /// Convert children to blocks
-    fn children_to_blocks(
-        &self,
-        node: ego_tree::NodeRef<scraper::Node>,
+        html: &str,
+        context: &DecodeContext,
+        path: &str,
     ) -> Result<Vec<Block>, ParseError> {
-        let mut blocks = Vec::new();
-        for child in node.children() {
-            if let Some(el) = child.value().as_element() {
-                if let Some(block) =
-                    self.element_to_block(el, &scraper::Html::new_document(), child)?
-                {
-                    blocks.push(block);
-                }
-            }
-        }
-        Ok(blocks)
-    }
-
-    /// Convert list item to blocks
-    fn li_to_blocks(
-        &self,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Block>, ParseError> {
-        // Check if li contains block elements
-        let has_blocks = node.children().any(|c| {
-            c.value()
-                .as_element()
-                .map(|e| matches!(e.name(), "p" | "ul" | "ol" | "blockquote" | "pre"))
-                .unwrap_or(false)
-        });
-
-        if has_blocks {
-            self.children_to_blocks(node)
-        } else {
-            // Treat as inline content wrapped in paragraph
-            let inlines = self.children_to_inlines(node)?;
-            if inlines.is_empty() {
-                Ok(vec![])
-            } else {
-                Ok(vec![Block::Paragraph(inlines)])
-            }
-        }
-    }
-
-    /// Convert children to inline elements
-    fn children_to_inlines(
-        &self,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Inline>, ParseError> {
-        let mut inlines = Vec::new();
-
-        for child in node.children() {
-            match child.value() {
-                scraper::Node::Text(text) => {
-                    let s = text.trim();
-                    if !s.is_empty() {
-                        inlines.push(Inline::Text(s.to_string()));
-                    }
-                }
-                scraper::Node::Element(el) => {
-                    let inline = self.element_to_inline(el, child)?;
-                    inlines.extend(inline);
-                }
-                _ => {}
-            }
-        }
-
-        Ok(inlines)
-    }
-
-    /// Convert an HTML element to inline elements
-    fn element_to_inline(
-        &self,
-        element: &scraper::node::Element,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Inline>, ParseError> {
-        let tag = element.name();
-
-        match tag {
-            "b" | "strong" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Bold(children)])
-            }
-            "i" | "em" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Italic(children)])
-            }
-            "code" => {
-                let text = self.get_text_content(node);
-                Ok(vec![Inline::Code(text)])
-            }
-            "a" => {
-                let children = self.children_to_inlines(node)?;
-                let url = element.attr("href").unwrap_or("#").to_string();
-                Ok(vec![Inline::Link { children, url }])
-            }
-            "sup" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Superscript(children)])
-            }
-            "sub" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Subscript(children)])
-            }
-            "s" | "strike" | "del" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Strikethrough(children)])
-            }
-            "br" => Ok(vec![Inline::Break]),
-            "span" => {
-                // Pass through span content
-                self.children_to_inlines(node)
-            }
-            _ => {
-                // Unknown inline - extract text
-                let text = self.get_text_content(node);
-                if text.is_empty() {
-                    Ok(vec![])
-                } else {
-                    Ok(vec![Inline::Text(text)])
-                }
-            }
-        }
-    }
-
-    /// Get text content of a node
-    fn get_text_content(&self, node: ego_tree::NodeRef<scraper::Node>) -> String {
-        let mut text = String::new();
-        for descendant in node.descendants() {
-            if let scraper::Node::Text(t) = descendant.value() {
-                text.push_str(t);
-            }
-        }
-        text
+        HtmlConverter::new(self.strict_sanitization)
+            .reporting_to(context, Some(path))
+            .parse_document(html)
     }
 
     /// Extract metadata from EPUB
@@ -359,25 +122,31 @@ impl EpubDecoder {
     fn extract_resources(
         &self,
         epub: &mut epub::doc::EpubDoc<std::io::Cursor<Vec<u8>>>,
-    ) -> (
-        crate::types::ResourceStore,
-        std::collections::HashMap<String, String>,
-    ) {
+        context: &DecodeContext,
+    ) -> Result<
+        (
+            crate::types::ResourceStore,
+            std::collections::HashMap<String, String>,
+        ),
+        ParseError,
+    > {
         use crate::types::{Resource, ResourceStore};
 
         let mut store = ResourceStore::new();
         let mut id_to_key = std::collections::HashMap::new();
 
-        // Get all resource IDs
-        let resource_ids: Vec<String> = epub.resources.keys().cloned().collect();
+        // Get all resource IDs, leaving out HTML/XHTML content (those are chapters)
+        let resource_ids: Vec<String> = epub
+            .resources
+            .iter()
+            .filter(|(_, item)| !(item.mime.contains("html") || item.mime.contains("xml")))
+            .map(|(id, _)| id.clone())
+            .collect();
+        context.progress().set_resources_total(resource_ids.len());
 
         for id in resource_ids {
+            context.check_cancelled()?;
             if let Some((data, mime)) = epub.get_resource(&id) {
-                // Skip HTML/XHTML content (those are chapters)
-                if mime.contains("html") || mime.contains("xml") {
-                    continue;
-                }
-
                 // Add resource to store
                 let resource = Resource::new(&mime, data).with_filename(&id);
                 let key = store.add(resource);
@@ -385,42 +154,31 @@ impl EpubDecoder {
                 // Map original ID to content-addressed key for later reference
                 id_to_key.insert(id, key);
             }
+            context.progress().resource_done();
         }
 
-        (store, id_to_key)
+        Ok((store, id_to_key))
     }
 
     /// Rewrite image references in blocks to use content-addressed keys
+    ///
+    /// References that match no resource are left as they are and added to `unresolved`.
     fn rewrite_image_refs(
         blocks: &mut [Block],
         id_to_key: &std::collections::HashMap<String, String>,
+        unresolved: &mut Vec<String>,
     ) {
-        for block in blocks {
-            match block {
-                Block::Image { resource_key, .. } => {
-                    // Try to find the key by matching the end of the path
-                    if let Some(new_key) = id_to_key
-                        .iter()
-                        .find(|(id, _)| {
-                            resource_key.ends_with(id.as_str())
-                                || id.ends_with(resource_key.as_str())
-                        })
-                        .map(|(_, key)| key.clone())
-                    {
-                        *resource_key = new_key;
-                    }
-                }
-                Block::List { items, .. } => {
-                    for item in items {
-                        Self::rewrite_image_refs(item, id_to_key);
-                    }
-                }
-                Block::Blockquote(inner) | Block::Footnote { content: inner, .. } => {
-                    Self::rewrite_image_refs(inner, id_to_key);
-                }
-                _ => {}
+        rewrite_images(blocks, &mut |resource_key| {
+            // Try to find the key by matching the end of the path
+            let key = id_to_key
+                .iter()
+                .find(|(id, _)| resource_key.ends_with(id.as_str()) || id.ends_with(resource_key))
+                .map(|(_, key)| key.clone());
+            if key.is_none() {
+                unresolved.push(resource_key.to_string());
             }
-        }
+            key
+        });
     }
 
     /// Flatten TOC tree to (href, title) pairs
@@ -448,6 +206,7 @@ impl EpubDecoder {
                 Inline::FootnoteRef { id } => format!("[{}]", id),
                 Inline::Ruby { base, .. } => base.clone(),
                 Inline::Break => " ".to_string(),
+                Inline::Checkbox { .. } => String::new(),
             })
             .collect()
     }
@@ -461,6 +220,15 @@ impl Default for EpubDecoder {
 
 impl super::Decoder for EpubDecoder {
     fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    /// Decode a book, reporting spine items, images and elements that were lost
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
         // Read all data into memory
         let mut data = Vec::new();
         reader
@@ -480,22 +248,41 @@ impl super::Decoder for EpubDecoder {
         book.toc = self.extract_toc(&epub);
 
         // Extract resources (images, fonts, etc.) with ID mapping
-        let (resources, id_to_key) = self.extract_resources(&mut epub);
+        let (resources, id_to_key) = self.extract_resources(&mut epub, context)?;
         book.resources = resources;
 
         // Build a map of TOC entries by href for chapter title lookup
         let toc_titles: std::collections::HashMap<String, String> =
-            book.toc.iter().flat_map(|e| Self::flatten_toc(e)).collect();
+            book.toc.iter().flat_map(Self::flatten_toc).collect();
 
         // Process spine (reading order)
         let spine = epub.spine.clone();
+        context.progress().set_chapters_total(spine.len());
         for item in &spine {
+            context.check_cancelled()?;
             let item_id = &item.idref;
+            let path = epub
+                .resources
+                .get(item_id)
+                .map(|resource| resource.path.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_else(|| item_id.clone());
             if let Some((content, _mime)) = epub.get_resource_str(item_id) {
-                let mut blocks = self.parse_html_to_blocks(&content)?;
+                let chapter_index = book.chapters.len();
+                let mut blocks = self.parse_html_to_blocks(&content, context, &path)?;
 
                 // Rewrite image references to use content-addressed keys
-                Self::rewrite_image_refs(&mut blocks, &id_to_key);
+                let mut unresolved = Vec::new();
+                Self::rewrite_image_refs(&mut blocks, &id_to_key, &mut unresolved);
+                for image in unresolved {
+                    context.report(
+                        Diagnostic::warning(
+                            "unresolved-image",
+                            format!("No resource in the book for image {}", image),
+                        )
+                        .with_path(path.clone())
+                        .with_chapter(chapter_index),
+                    );
+                }
 
                 // Try to get chapter title from TOC using precise matching
                 let title = toc_titles
@@ -528,7 +315,16 @@ impl super::Decoder for EpubDecoder {
                     .with_id(item_id.clone())
                     .with_content(blocks);
                 book.add_chapter(chapter);
+            } else {
+                context.report(
+                    Diagnostic::warning(
+                        "skipped-spine-item",
+                        format!("Skipped spine item {}, which could not be read", item_id),
+                    )
+                    .with_path(path),
+                );
             }
+            context.progress().chapter_done();
         }
 
         Ok(book)
@@ -557,7 +353,9 @@ mod tests {
             </body>
         "#;
 
-        let blocks = decoder.parse_html_to_blocks(html).unwrap();
+        let blocks = decoder
+            .parse_html_to_blocks(html, &DecodeContext::new(), "")
+            .unwrap();
         assert_eq!(blocks.len(), 2);
 
         match &blocks[0] {
@@ -568,4 +366,45 @@ mod tests {
             _ => panic!("Expected header"),
         }
     }
+
+    #[test]
+    fn test_rewrite_image_refs_in_nested_blocks() {
+        use crate::types::{AdmonitionKind, DefinitionItem};
+
+        let image = |key: &str| Block::Image {
+            resource_key: key.to_string(),
+            caption: None,
+            alt: String::new(),
+        };
+        let mut blocks = vec![
+            Block::Admonition {
+                kind: AdmonitionKind::Note,
+                content: vec![image("../images/a.png")],
+            },
+            Block::Container {
+                id: None,
+                classes: Vec::new(),
+                content: vec![image("images/gone.png")],
+            },
+            Block::DefinitionList(vec![DefinitionItem {
+                term: vec![Inline::text("Term")],
+                definitions: vec![vec![image("images/b.png")]],
+            }]),
+        ];
+        let id_to_key = [("images/a.png", "key-a"), ("images/b.png", "key-b")]
+            .into_iter()
+            .map(|(id, key)| (id.to_string(), key.to_string()))
+            .collect();
+
+        let mut unresolved = Vec::new();
+        EpubDecoder::rewrite_image_refs(&mut blocks, &id_to_key, &mut unresolved);
+
+        let mut keys = Vec::new();
+        rewrite_images(&mut blocks, &mut |key| {
+            keys.push(key.to_string());
+            None
+        });
+        assert_eq!(keys, vec!["key-a", "images/gone.png", "key-b"]);
+        assert_eq!(unresolved, vec!["images/gone.png"]);
+    }
 }
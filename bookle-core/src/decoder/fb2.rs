@@ -0,0 +1,674 @@
+//! FictionBook 2 decoder implementation
+//!
+//! FB2 is a single XML document: `<description>` holds the metadata, one or more
+//! `<body>` elements hold nested `<section>`s of text (a body named `notes` holds
+//! footnotes), and images are embedded as base64 `<binary>` elements. FBZ and
+//! `.fb2.zip` files are ZIP archives containing one such document.
+
+use super::chapters::place_footnotes;
+use super::front_matter::parse_date;
+use super::xml::{self, trim_inlines};
+use crate::error::ParseError;
+use crate::types::{
+    Block, Book, Chapter, Inline, Metadata, Resource, ResourceStore, SeriesInfo, TableCell,
+    TableData, TocEntry,
+};
+use base64::Engine;
+use roxmltree::Node;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+
+/// Decoder for FictionBook 2 (FB2, FBZ)
+pub struct Fb2Decoder;
+
+/// State shared while converting a document
+struct Converter {
+    /// Resource keys of `<binary>` images, by id
+    images: HashMap<String, String>,
+    /// Ids of sections in notes bodies
+    notes: HashSet<String>,
+    /// Number of sections seen, for generating chapter ids
+    sections: usize,
+}
+
+impl Fb2Decoder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Extract the FB2 document from an FBZ archive
+    fn unzip(data: Vec<u8>) -> Result<Vec<u8>, ParseError> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data))
+            .map_err(|e| ParseError::MalformedContent(format!("Invalid FBZ archive: {}", e)))?;
+
+        let name = archive
+            .file_names()
+            .find(|name| name.to_lowercase().ends_with(".fb2"))
+            .map(str::to_string)
+            .ok_or_else(|| {
+                ParseError::MalformedContent("No .fb2 document in archive".to_string())
+            })?;
+
+        let mut document = Vec::new();
+        archive
+            .by_name(&name)
+            .map_err(|e| ParseError::MalformedContent(format!("Invalid FBZ archive: {}", e)))?
+            .read_to_end(&mut document)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read {}: {}", name, e)))?;
+        Ok(document)
+    }
+}
+
+impl Default for Fb2Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for Fb2Decoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read FB2: {}", e)))?;
+
+        if data.starts_with(b"PK\x03\x04") {
+            data = Self::unzip(data)?;
+        }
+
+        let text = xml::decode_xml(&data);
+        let document = xml::parse(&text)?;
+        let root = document.root_element();
+        if root.tag_name().name() != "FictionBook" {
+            return Err(ParseError::UnsupportedFormat(
+                "Not a FictionBook document".to_string(),
+            ));
+        }
+
+        // Embedded images
+        let mut resources = ResourceStore::new();
+        let mut images = HashMap::new();
+        for binary in xml::children(root, "binary") {
+            let (Some(id), Some(data)) = (binary.attribute("id"), binary.text()) else {
+                continue;
+            };
+            let data: String = data.split_whitespace().collect();
+            let Ok(data) = base64::engine::general_purpose::STANDARD.decode(data) else {
+                continue;
+            };
+            let mime = binary
+                .attribute("content-type")
+                .unwrap_or("application/octet-stream");
+            let key = resources.add(Resource::new(mime, data).with_filename(id));
+            images.insert(id.to_string(), key);
+        }
+
+        // Notes bodies hold footnotes rather than text
+        let (note_bodies, bodies): (Vec<_>, Vec<_>) = xml::children(root, "body")
+            .partition(|body| matches!(body.attribute("name"), Some("notes" | "comments")));
+
+        let notes_sections: Vec<Node> = note_bodies
+            .iter()
+            .flat_map(|body| xml::children(*body, "section"))
+            .collect();
+        let mut converter = Converter {
+            images,
+            notes: notes_sections
+                .iter()
+                .filter_map(|section| section.attribute("id"))
+                .map(str::to_string)
+                .collect(),
+            sections: 0,
+        };
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        if let Some(description) = xml::child(root, "description") {
+            converter.apply_description(description, &mut metadata);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+
+        for body in bodies {
+            let (chapters, toc) = converter.body_to_chapters(body);
+            book.chapters.extend(chapters);
+            book.toc.extend(toc);
+        }
+
+        let footnotes: Vec<(String, Block)> = notes_sections
+            .iter()
+            .filter_map(|section| {
+                let id = section.attribute("id")?;
+                let content = converter.children_to_blocks(*section, 6, true);
+                Some((
+                    id.to_string(),
+                    Block::Footnote {
+                        id: id.to_string(),
+                        content,
+                    },
+                ))
+            })
+            .collect();
+        place_footnotes(&mut book.chapters, footnotes);
+
+        if book.chapters.is_empty() {
+            book.add_chapter(Chapter::new("Content"));
+        }
+
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["fb2", "fbz", "fb2.zip"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &[
+            "application/x-fictionbook+xml",
+            "application/x-zip-compressed-fb2",
+        ]
+    }
+}
+
+impl Converter {
+    /// Fill in metadata from `<title-info>` and `<publish-info>`
+    fn apply_description(&self, description: Node, metadata: &mut Metadata) {
+        if let Some(info) = xml::child(description, "title-info") {
+            if let Some(title) = xml::child(info, "book-title").map(xml::text) {
+                if !title.is_empty() {
+                    metadata.title = title;
+                }
+            }
+
+            metadata.creator = xml::children(info, "author")
+                .filter_map(author_name)
+                .collect();
+            metadata.subject = xml::children(info, "genre")
+                .map(xml::text)
+                .filter(|genre| !genre.is_empty())
+                .collect();
+
+            if let Some(annotation) = xml::child(info, "annotation") {
+                let paragraphs: Vec<String> = annotation
+                    .children()
+                    .filter(|n| n.is_element())
+                    .map(xml::text)
+                    .filter(|p| !p.is_empty())
+                    .collect();
+                if !paragraphs.is_empty() {
+                    metadata.description = Some(paragraphs.join("\n\n"));
+                }
+            }
+
+            if let Some(lang) = xml::child(info, "lang").map(xml::text) {
+                if !lang.is_empty() {
+                    metadata.language = lang;
+                }
+            }
+
+            if let Some(date) = xml::child(info, "date") {
+                let value = date.attribute("value").map(str::to_string);
+                metadata.date = parse_date(&value.unwrap_or_else(|| xml::text(date)));
+            }
+
+            if let Some(sequence) = xml::child(info, "sequence") {
+                if let Some(name) = sequence.attribute("name").filter(|n| !n.trim().is_empty()) {
+                    let position = sequence
+                        .attribute("number")
+                        .and_then(|n| n.trim().parse().ok());
+                    metadata.series = Some(SeriesInfo::new(name.trim(), position));
+                }
+            }
+
+            let cover = xml::child(info, "coverpage")
+                .and_then(|coverpage| xml::child(coverpage, "image"))
+                .and_then(|image| self.image_key(image));
+            if cover.is_some() {
+                metadata.cover_resource_key = cover;
+            }
+        }
+
+        if let Some(info) = xml::child(description, "publish-info") {
+            if let Some(publisher) = xml::child(info, "publisher").map(xml::text) {
+                if !publisher.is_empty() {
+                    metadata.publisher = Some(publisher);
+                }
+            }
+            if metadata.date.is_none() {
+                metadata.date =
+                    xml::child(info, "year").and_then(|year| parse_date(&xml::text(year)));
+            }
+            if let Some(isbn) = xml::child(info, "isbn").map(xml::text) {
+                if !isbn.is_empty() {
+                    metadata.identifier = isbn;
+                }
+            }
+        }
+    }
+
+    /// Convert a body into chapters, with one chapter per section
+    ///
+    /// A section's own content, before its first subsection, forms its chapter;
+    /// subsections follow as chapters of their own, nested in the TOC.
+    fn body_to_chapters(&mut self, body: Node) -> (Vec<Chapter>, Vec<TocEntry>) {
+        let mut chapters = Vec::new();
+        let mut toc = Vec::new();
+
+        // The body's title and epigraphs, before any section, open the book
+        let intro = self.children_to_blocks(body, 1, false);
+        if !intro.is_empty() {
+            let title = xml::child(body, "title")
+                .map(title_text)
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| "Untitled".to_string());
+            let id = self.next_id(None);
+            toc.push(TocEntry::new(&title, format!("#{}", id)));
+            chapters.push(Chapter::new(title).with_id(id).with_content(intro));
+        }
+
+        for section in xml::children(body, "section") {
+            toc.push(self.section_to_chapters(section, 0, &mut chapters));
+        }
+
+        (chapters, toc)
+    }
+
+    /// Convert a section and its subsections, returning its TOC entry
+    fn section_to_chapters(
+        &mut self,
+        section: Node,
+        depth: u32,
+        chapters: &mut Vec<Chapter>,
+    ) -> TocEntry {
+        let title = xml::child(section, "title")
+            .map(title_text)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| "Untitled".to_string());
+        let id = self.next_id(section.attribute("id"));
+        let level = (depth + 1).min(6) as u8;
+
+        let content = self.children_to_blocks(section, level, false);
+        chapters.push(Chapter::new(&title).with_id(&id).with_content(content));
+
+        let children = xml::children(section, "section")
+            .map(|subsection| self.section_to_chapters(subsection, depth + 1, chapters))
+            .collect();
+
+        TocEntry::new(title, format!("#{}", id))
+            .with_level(depth)
+            .with_children(children)
+    }
+
+    /// A chapter id, from the section's id or generated
+    fn next_id(&mut self, id: Option<&str>) -> String {
+        self.sections += 1;
+        id.map(str::to_string)
+            .unwrap_or_else(|| format!("section-{}", self.sections))
+    }
+
+    /// Convert the block children of a body, section or other container
+    ///
+    /// Subsections are skipped; `level` is the heading level of the container's
+    /// title, which is omitted when `skip_title` is set.
+    fn children_to_blocks(&self, node: Node, level: u8, skip_title: bool) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        for child in node.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "section" => {}
+                "title" if skip_title => {}
+                "title" => {
+                    let content = self.title_inlines(child);
+                    if !content.is_empty() {
+                        blocks.push(Block::Header {
+                            level,
+                            content,
+                            anchor: None,
+                        });
+                    }
+                }
+                _ => blocks.extend(self.element_to_blocks(child, level)),
+            }
+        }
+        blocks
+    }
+
+    /// Convert a block-level element
+    fn element_to_blocks(&self, element: Node, level: u8) -> Vec<Block> {
+        match element.tag_name().name() {
+            "p" => {
+                let content = self.inlines(element);
+                if content.is_empty() {
+                    vec![]
+                } else {
+                    vec![Block::Paragraph(content)]
+                }
+            }
+            "subtitle" => vec![Block::Header {
+                level: (level + 1).min(6),
+                content: self.inlines(element),
+                anchor: element.attribute("id").map(str::to_string),
+            }],
+            "epigraph" | "cite" | "annotation" => {
+                vec![Block::Blockquote(
+                    self.children_to_blocks(element, level, true),
+                )]
+            }
+            "poem" => vec![Block::Blockquote(self.poem_to_blocks(element, level))],
+            "text-author" | "date" => {
+                vec![Block::Paragraph(vec![Inline::Italic(
+                    self.inlines(element),
+                )])]
+            }
+            "image" => self
+                .image_key(element)
+                .map(|resource_key| Block::Image {
+                    resource_key,
+                    caption: element.attribute("title").map(str::to_string),
+                    alt: element.attribute("alt").unwrap_or_default().to_string(),
+                })
+                .into_iter()
+                .collect(),
+            "table" => vec![Block::Table(self.table(element))],
+            // Blank lines between paragraphs carry no content of their own
+            "empty-line" => vec![],
+            _ => {
+                let content = self.inlines(element);
+                if content.is_empty() {
+                    vec![]
+                } else {
+                    vec![Block::Paragraph(content)]
+                }
+            }
+        }
+    }
+
+    /// Convert a poem, keeping each stanza's lines apart
+    fn poem_to_blocks(&self, poem: Node, level: u8) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        for child in poem.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "title" => blocks.push(Block::Paragraph(vec![Inline::Bold(
+                    self.title_inlines(child),
+                )])),
+                "stanza" => {
+                    let mut content = Vec::new();
+                    for line in child.children().filter(|n| n.is_element()) {
+                        match line.tag_name().name() {
+                            "v" => {
+                                if !content.is_empty() {
+                                    content.push(Inline::Break);
+                                }
+                                content.extend(self.inlines(line));
+                            }
+                            "title" | "subtitle" => blocks
+                                .push(Block::Paragraph(vec![Inline::Bold(self.inlines(line))])),
+                            _ => {}
+                        }
+                    }
+                    if !content.is_empty() {
+                        blocks.push(Block::Paragraph(content));
+                    }
+                }
+                _ => blocks.extend(self.element_to_blocks(child, level)),
+            }
+        }
+        blocks
+    }
+
+    /// Convert a table, taking header cells from `<th>`
+    fn table(&self, table: Node) -> TableData {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+
+        for row in xml::children(table, "tr") {
+            let cells: Vec<Node> = row.children().filter(|n| n.is_element()).collect();
+            let all_headers = cells.iter().all(|cell| cell.tag_name().name() == "th");
+            let cells: Vec<TableCell> = cells
+                .iter()
+                .map(|cell| {
+                    let span = |name| {
+                        cell.attribute(name)
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(1)
+                    };
+                    let mut table_cell = TableCell::new(self.inlines(*cell));
+                    table_cell.colspan = span("colspan");
+                    table_cell.rowspan = span("rowspan");
+                    table_cell
+                })
+                .collect();
+
+            if headers.is_empty() && rows.is_empty() && all_headers {
+                headers = cells;
+            } else {
+                rows.push(cells);
+            }
+        }
+
+        TableData { headers, rows }
+    }
+
+    /// Convert inline content, trimming the whitespace at its ends
+    fn inlines(&self, node: Node) -> Vec<Inline> {
+        let mut inlines = self.children_to_inlines(node);
+        trim_inlines(&mut inlines);
+        inlines
+    }
+
+    /// Convert the children of an inline container
+    fn children_to_inlines(&self, node: Node) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        for child in node.children() {
+            if child.is_text() {
+                let text = xml::collapse_whitespace(child.text().unwrap_or_default());
+                if !text.is_empty() {
+                    inlines.push(Inline::Text(text));
+                }
+                continue;
+            }
+            if !child.is_element() {
+                continue;
+            }
+
+            let children = || self.children_to_inlines(child);
+            match child.tag_name().name() {
+                "strong" => inlines.push(Inline::Bold(children())),
+                "emphasis" => inlines.push(Inline::Italic(children())),
+                "strikethrough" => inlines.push(Inline::Strikethrough(children())),
+                "sub" => inlines.push(Inline::Subscript(children())),
+                "sup" => inlines.push(Inline::Superscript(children())),
+                "code" => inlines.push(Inline::Code(xml::text(child))),
+                "a" => {
+                    let href = xml::attribute(child, "href").unwrap_or_default();
+                    match href.strip_prefix('#') {
+                        Some(id) if self.notes.contains(id) => {
+                            inlines.push(Inline::FootnoteRef { id: id.to_string() })
+                        }
+                        _ => inlines.push(Inline::Link {
+                            children: children(),
+                            url: href.to_string(),
+                        }),
+                    }
+                }
+                "image" => {
+                    if let Some(alt) = child.attribute("alt") {
+                        inlines.push(Inline::text(alt));
+                    }
+                }
+                // <style> and unknown elements pass their content through
+                _ => inlines.extend(children()),
+            }
+        }
+        inlines
+    }
+
+    /// Inline content of a `<title>`, with its paragraphs on separate lines
+    fn title_inlines(&self, title: Node) -> Vec<Inline> {
+        let mut content = Vec::new();
+        for paragraph in xml::children(title, "p") {
+            if !content.is_empty() {
+                content.push(Inline::Break);
+            }
+            content.extend(self.inlines(paragraph));
+        }
+        content
+    }
+
+    /// Resource key of the `<binary>` an `<image>` refers to
+    fn image_key(&self, image: Node) -> Option<String> {
+        let href = xml::attribute(image, "href")?;
+        self.images.get(href.trim_start_matches('#')).cloned()
+    }
+}
+
+/// Full name of an `<author>`, falling back to their nickname
+fn author_name(author: Node) -> Option<String> {
+    let parts: Vec<String> = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|part| xml::child(author, part).map(xml::text))
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    if parts.is_empty() {
+        xml::child(author, "nickname")
+            .map(xml::text)
+            .filter(|nickname| !nickname.is_empty())
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Plain text of a `<title>`, with its paragraphs joined
+fn title_text(title: Node) -> String {
+    let lines: Vec<String> = xml::children(title, "p")
+        .map(xml::text)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.is_empty() {
+        xml::text(title)
+    } else {
+        lines.join(". ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    const SAMPLE: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description>
+    <title-info>
+      <genre>sf</genre>
+      <author><first-name>Arkady</first-name><last-name>Strugatsky</last-name></author>
+      <book-title>Roadside Picnic</book-title>
+      <coverpage><image l:href="#cover.png"/></coverpage>
+      <lang>ru</lang>
+      <sequence name="Noon Universe" number="3"/>
+    </title-info>
+  </description>
+  <body>
+    <title><p>Roadside Picnic</p></title>
+    <epigraph><p>You have to make the good out of the bad.</p><text-author>Robert Penn Warren</text-author></epigraph>
+    <section id="part1">
+      <title><p>Part One</p></title>
+      <p>Text with a note<a l:href="#n1" type="note">1</a> and <emphasis>emphasis</emphasis>.</p>
+      <section>
+        <title><p>Chapter 1</p></title>
+        <poem><stanza><v>Line one</v><v>Line two</v></stanza></poem>
+        <image l:href="#cover.png"/>
+      </section>
+    </section>
+  </body>
+  <body name="notes">
+    <section id="n1"><title><p>1</p></title><p>The note.</p></section>
+  </body>
+  <binary id="cover.png" content-type="image/png">iVBORw0KGgo=</binary>
+</FictionBook>"##;
+
+    fn decode(data: &[u8]) -> Book {
+        Fb2Decoder::new()
+            .decode(&mut Cursor::new(data.to_vec()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode_metadata() {
+        let book = decode(SAMPLE.as_bytes());
+        let metadata = &book.metadata;
+
+        assert_eq!(metadata.title, "Roadside Picnic");
+        assert_eq!(metadata.creator, vec!["Arkady Strugatsky"]);
+        assert_eq!(metadata.subject, vec!["sf"]);
+        assert_eq!(metadata.language, "ru");
+        assert_eq!(
+            metadata.series,
+            Some(SeriesInfo::new("Noon Universe", Some(3.0)))
+        );
+        assert!(metadata.cover_resource_key.is_some());
+        assert_eq!(book.resources.len(), 1);
+    }
+
+    #[test]
+    fn test_decode_sections() {
+        let book = decode(SAMPLE.as_bytes());
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Roadside Picnic", "Part One", "Chapter 1"]);
+        assert_eq!(book.toc.len(), 2);
+        assert_eq!(book.toc[1].children[0].title, "Chapter 1");
+
+        // The epigraph follows the body title
+        assert!(matches!(book.chapters[0].content[1], Block::Blockquote(_)));
+
+        // The poem keeps its lines, and the image resolves to the binary
+        let chapter = &book.chapters[2].content;
+        assert_eq!(
+            chapter[1],
+            Block::Blockquote(vec![Block::Paragraph(vec![
+                Inline::text("Line one"),
+                Inline::Break,
+                Inline::text("Line two"),
+            ])])
+        );
+        assert!(matches!(&chapter[2], Block::Image { resource_key, .. }
+            if Some(resource_key) == book.metadata.cover_resource_key.as_ref()));
+    }
+
+    #[test]
+    fn test_decode_footnotes() {
+        let book = decode(SAMPLE.as_bytes());
+        let chapter = &book.chapters[1].content;
+
+        assert_eq!(
+            chapter[1],
+            Block::Paragraph(vec![
+                Inline::text("Text with a note"),
+                Inline::FootnoteRef { id: "n1".into() },
+                Inline::text(" and "),
+                Inline::Italic(vec![Inline::text("emphasis")]),
+                Inline::text("."),
+            ])
+        );
+        assert_eq!(
+            chapter.last(),
+            Some(&Block::Footnote {
+                id: "n1".into(),
+                content: vec![Block::Paragraph(vec![Inline::text("The note.")])],
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_fbz() {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file("book.fb2", zip::write::FileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut archive, SAMPLE.as_bytes()).unwrap();
+        let data = archive.finish().unwrap().into_inner();
+
+        assert_eq!(decode(&data).metadata.title, "Roadside Picnic");
+    }
+}
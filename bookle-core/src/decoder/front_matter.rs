@@ -0,0 +1,376 @@
+//! Front matter metadata for text-based formats
+//!
+//! Supports Pandoc-style YAML blocks delimited by `---` (closed by `---` or `...`)
+//! and Hugo/Zola-style TOML blocks delimited by `+++`, placed at the very start of
+//! the document.
+
+use super::DecodeContext;
+use crate::diagnostic::Diagnostic;
+use crate::error::ParseError;
+use crate::types::{Metadata, SeriesInfo};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde_json::{Map, Value};
+
+/// Parsed front matter fields
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FrontMatter {
+    fields: Map<String, Value>,
+}
+
+/// Split leading front matter from a document
+///
+/// Returns the parsed front matter (if any) and the remaining content. A leading
+/// `---` block that isn't a YAML mapping is left in place, since it is more likely
+/// a thematic break than metadata. A block that fails to parse as YAML or TOML is
+/// dropped from the content and reported as an `invalid-front-matter` warning.
+pub(crate) fn extract<'a>(
+    content: &'a str,
+    context: &DecodeContext,
+) -> (Option<FrontMatter>, &'a str) {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    let Some((delimiter, body)) = ["---", "+++"]
+        .into_iter()
+        .find_map(|d| opening_line(content, d).map(|body| (d, body)))
+    else {
+        return (None, content);
+    };
+
+    let closing: &[&str] = if delimiter == "---" {
+        &["---", "..."]
+    } else {
+        &["+++"]
+    };
+
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        if closing.contains(&line.trim_end()) {
+            let source = &body[..offset];
+            let rest = &body[offset + line.len()..];
+
+            let parsed = if delimiter == "---" {
+                FrontMatter::from_yaml(source)
+            } else {
+                FrontMatter::from_toml(source).map(Some)
+            };
+            return match parsed {
+                Ok(Some(front_matter)) => (Some(front_matter), rest),
+                Ok(None) => (None, content),
+                Err(e) => {
+                    context.report(
+                        Diagnostic::warning("invalid-front-matter", e.to_string()).with_line(1),
+                    );
+                    (None, rest)
+                }
+            };
+        }
+        offset += line.len();
+    }
+
+    // An unterminated block is just content
+    (None, content)
+}
+
+/// Content following an opening delimiter line, if the document starts with one
+fn opening_line<'a>(content: &'a str, delimiter: &str) -> Option<&'a str> {
+    let rest = content.strip_prefix(delimiter)?;
+    let (line, body) = rest.split_once('\n')?;
+    line.trim().is_empty().then_some(body)
+}
+
+impl FrontMatter {
+    /// Parse a YAML mapping, returning `None` if the source is valid YAML but not a mapping
+    pub fn from_yaml(source: &str) -> Result<Option<Self>, ParseError> {
+        let value = serde_yaml::from_str::<Value>(source).map_err(|e| {
+            ParseError::MalformedContent(format!("Invalid YAML front matter: {}", e))
+        })?;
+        Ok(match value {
+            Value::Object(fields) => Some(Self { fields }),
+            // An empty block parses as null
+            Value::Null => Some(Self::default()),
+            _ => None,
+        })
+    }
+
+    /// Parse a TOML table
+    pub fn from_toml(source: &str) -> Result<Self, ParseError> {
+        let table: toml::Table = toml::from_str(source).map_err(|e| {
+            ParseError::MalformedContent(format!("Invalid TOML front matter: {}", e))
+        })?;
+
+        let fields = table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_json(value)))
+            .collect();
+        Ok(Self { fields })
+    }
+
+    /// Fields gathered from another kind of header, such as AsciiDoc attributes
+    pub fn from_fields(fields: Map<String, Value>) -> Self {
+        Self { fields }
+    }
+
+    /// Look up the first of several alternative keys
+    fn get(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().find_map(|key| self.fields.get(*key))
+    }
+
+    /// Raw value of a field
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+
+    /// Fields of a nested table, such as `[book]` in an mdBook `book.toml`
+    pub fn section(&self, key: &str) -> Option<Self> {
+        match self.fields.get(key)? {
+            Value::Object(fields) => Some(Self {
+                fields: fields.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Document title
+    pub fn title(&self) -> Option<String> {
+        self.get(&["title"]).and_then(|value| match value {
+            // Pandoc EPUB metadata allows a list of typed titles
+            Value::Array(titles) => titles
+                .iter()
+                .find(|t| t.get("type").and_then(Value::as_str) == Some("main"))
+                .or_else(|| titles.first())
+                .and_then(text),
+            _ => text(value),
+        })
+    }
+
+    /// Cover image path
+    pub fn cover(&self) -> Option<String> {
+        self.get(&["cover", "cover-image", "cover_image", "coverImage"])
+            .and_then(text)
+    }
+
+    /// Copy front matter fields into book metadata, overriding what is already there
+    pub fn apply_to(&self, metadata: &mut Metadata) {
+        if let Some(title) = self.title() {
+            metadata.title = title;
+        }
+
+        let authors = self
+            .get(&["author", "authors", "creator", "creators"])
+            .map(list)
+            .unwrap_or_default();
+        if !authors.is_empty() {
+            metadata.creator = authors;
+        }
+
+        if let Some(language) = self.get(&["lang", "language"]).and_then(text) {
+            metadata.language = language;
+        }
+
+        if let Some(date) = self.get(&["date"]).and_then(text) {
+            metadata.date = parse_date(&date).or(metadata.date);
+        }
+
+        if let Some(description) = self
+            .get(&["description", "abstract", "summary"])
+            .and_then(text)
+        {
+            metadata.description = Some(description);
+        }
+
+        if let Some(publisher) = self.get(&["publisher"]).and_then(text) {
+            metadata.publisher = Some(publisher);
+        }
+
+        let subjects = self
+            .get(&["subject", "subjects", "keywords", "tags"])
+            .map(list)
+            .unwrap_or_default();
+        if !subjects.is_empty() {
+            metadata.subject = subjects;
+        }
+
+        if let Some(series) = self.series() {
+            metadata.series = Some(series);
+        }
+
+        if let Some(rights) = self.get(&["rights", "copyright", "license"]).and_then(text) {
+            metadata.rights = Some(rights);
+        }
+
+        if let Some(identifier) = self.get(&["identifier", "isbn", "id"]).and_then(first) {
+            metadata.identifier = identifier;
+        }
+
+        if let Some(cover) = self.cover() {
+            metadata.cover_resource_key = Some(cover);
+        }
+    }
+
+    /// Series name and position, from `series` (string or table) and `series_index`
+    fn series(&self) -> Option<SeriesInfo> {
+        let series = self.get(&["series", "belongs-to-collection"])?;
+        let position_keys = [
+            "series_index",
+            "series-index",
+            "series_position",
+            "group-position",
+        ];
+
+        let (name, position) = match series {
+            Value::Object(fields) => (
+                fields
+                    .get("name")
+                    .or_else(|| fields.get("title"))
+                    .and_then(text)?,
+                ["position", "index", "number"]
+                    .iter()
+                    .find_map(|key| fields.get(*key))
+                    .and_then(number),
+            ),
+            _ => (text(series)?, None),
+        };
+
+        let position = position.or_else(|| self.get(&position_keys).and_then(number));
+        Some(SeriesInfo::new(name, position))
+    }
+}
+
+/// Text of a scalar value, or of the `name`/`text` field of a table
+fn text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Object(fields) => return fields.get("name").or(fields.get("text")).and_then(text),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// First value of a list, or a single scalar
+fn first(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) => items.iter().find_map(text),
+        _ => text(value),
+    }
+}
+
+/// Values of a list, or of a single scalar (comma-separated strings are not split)
+fn list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items.iter().filter_map(text).collect(),
+        _ => text(value).into_iter().collect(),
+    }
+}
+
+/// Numeric value of a number or numeric string
+fn number(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parse a date in RFC 3339 or `YYYY[-MM[-DD]]` form
+pub(crate) fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", date), "%Y-%m-%d"))
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01-01", date), "%Y-%m-%d"))
+        .ok()?;
+    Some(Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0)?))
+}
+
+/// Convert a TOML value to the JSON value model used for all front matter
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_yaml() {
+        let content = "---\ntitle: A Book\nauthor:\n  - Ann\n  - Bob\nlang: fr\n...\n# Hello\n";
+        let (front_matter, rest) = extract(content, &DecodeContext::new());
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        front_matter.unwrap().apply_to(&mut metadata);
+
+        assert_eq!(rest, "# Hello\n");
+        assert_eq!(metadata.title, "A Book");
+        assert_eq!(metadata.creator, vec!["Ann", "Bob"]);
+        assert_eq!(metadata.language, "fr");
+    }
+
+    #[test]
+    fn test_extract_toml() {
+        let content = "+++\ntitle = \"Rust\"\ndate = 2021-03-04\n\n[series]\nname = \"Books\"\nposition = 2\n+++\nBody";
+        let (front_matter, rest) = extract(content, &DecodeContext::new());
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        front_matter.unwrap().apply_to(&mut metadata);
+
+        assert_eq!(rest, "Body");
+        assert_eq!(metadata.title, "Rust");
+        assert_eq!(
+            metadata.date.unwrap().to_rfc3339(),
+            "2021-03-04T00:00:00+00:00"
+        );
+        assert_eq!(metadata.series, Some(SeriesInfo::new("Books", Some(2.0))));
+    }
+
+    #[test]
+    fn test_extract_leaves_thematic_break() {
+        let content = "---\nJust some text.\n---\nMore.";
+        let (front_matter, rest) = extract(content, &DecodeContext::new());
+        assert!(front_matter.is_none());
+        assert_eq!(rest, content);
+    }
+
+    #[test]
+    fn test_extract_drops_invalid_blocks() {
+        for content in [
+            "---\ntitle: [unclosed\n---\n# Hello\n",
+            "+++\ntitle = unquoted\n+++\n# Hello\n",
+        ] {
+            let context = DecodeContext::new();
+            let (front_matter, rest) = extract(content, &context);
+            assert!(front_matter.is_none());
+            assert_eq!(rest, "# Hello\n");
+
+            let diagnostics = context.diagnostics();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].code, "invalid-front-matter");
+        }
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert!(parse_date("2020-05-17").is_some());
+        assert!(parse_date("2020-05").is_some());
+        assert!(parse_date("1999").is_some());
+        assert!(parse_date("2020-05-17T10:00:00+02:00").is_some());
+        assert!(parse_date("last spring").is_none());
+    }
+}
@@ -0,0 +1,1323 @@
+//! HTML decoder, and the HTML to IR mapping shared by the HTML-based decoders
+//!
+//! EPUB and MOBI content documents, raw HTML embedded in Markdown and standalone
+//! HTML pages are all converted to blocks and inlines here.
+
+use super::context::{resolve_link, rewrite_images, sniff_mime_type, AssetLoader};
+use super::front_matter::parse_date;
+use super::project::{rewrite_links, slugify};
+use super::readability::main_content;
+use super::text::decode_bytes;
+use super::xml;
+use super::{ChapterSplitter, DecodeContext, DecodeOptions, SplitOptions, SplitStrategy};
+use crate::diagnostic::Diagnostic;
+use crate::error::ParseError;
+use crate::types::{
+    Block, Book, Chapter, Inline, Metadata, Resource, ResourceStore, TableCell, TableData,
+};
+use base64::Engine;
+use ego_tree::NodeRef;
+use encoding_rs::Encoding;
+use scraper::{Html, Node, Selector};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
+
+/// Elements whose content is never document text
+const NON_CONTENT_ELEMENTS: &[&str] = &["script", "style", "template", "head", "title", "noscript"];
+
+/// Embedded and interactive elements removed in strict mode
+const UNSAFE_ELEMENTS: &[&str] = &[
+    "iframe", "frame", "frameset", "object", "embed", "applet", "form", "input", "button",
+    "textarea", "select", "option", "link", "meta", "base", "svg", "math", "canvas", "audio",
+    "video", "source", "track", "dialog",
+];
+
+/// URL schemes removed from links and images in strict mode
+const UNSAFE_SCHEMES: &[&str] = &["javascript:", "vbscript:", "data:text/html"];
+
+/// Elements converted as blocks when they appear alongside inline content
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "ul",
+    "ol",
+    "blockquote",
+    "pre",
+    "table",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "div",
+    "section",
+    "article",
+    "hr",
+    "img",
+];
+
+/// Converts HTML into blocks and inlines
+pub(crate) struct HtmlConverter<'a> {
+    /// Whether unsafe elements and URLs are removed
+    strict: bool,
+    /// Where unknown elements are reported, with the path of the document
+    report: Option<(&'a DecodeContext, Option<String>)>,
+    /// Unknown inline elements kept as plain text since last reported
+    unknown: RefCell<BTreeSet<String>>,
+}
+
+impl<'a> HtmlConverter<'a> {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            report: None,
+            unknown: RefCell::default(),
+        }
+    }
+
+    /// Report unknown elements kept as plain text to `context` after each parse
+    pub fn reporting_to(mut self, context: &'a DecodeContext, path: Option<&str>) -> Self {
+        self.report = Some((context, path.map(|p| p.to_string())));
+        self
+    }
+
+    /// Report the unknown elements kept as plain text since the last call, if
+    /// there were any
+    pub fn report_unknown_elements(&self) {
+        let unknown = std::mem::take(&mut *self.unknown.borrow_mut());
+        let Some((context, path)) = &self.report else {
+            return;
+        };
+        if unknown.is_empty() {
+            return;
+        }
+
+        let tags: Vec<String> = unknown.iter().map(|tag| format!("<{}>", tag)).collect();
+        let mut diagnostic = Diagnostic::info(
+            "unknown-element",
+            format!("Kept {} as plain text", tags.join(", ")),
+        );
+        if let Some(path) = path {
+            diagnostic = diagnostic.with_path(path.clone());
+        }
+        context.report(diagnostic);
+    }
+
+    /// Parse the contents of an HTML document's body
+    pub fn parse_document(&self, html: &str) -> Result<Vec<Block>, ParseError> {
+        let fragment = Self::body_fragment(html);
+        let blocks = self.children_to_blocks(*fragment.root_element())?;
+        self.report_unknown_elements();
+        Ok(blocks)
+    }
+
+    /// Parse an HTML fragment, wrapping loose text and inline elements in paragraphs
+    pub fn parse_fragment(&self, html: &str) -> Result<Vec<Block>, ParseError> {
+        let fragment = Html::parse_fragment(html);
+        let blocks = self.mixed_to_blocks(*fragment.root_element())?;
+        self.report_unknown_elements();
+        Ok(blocks)
+    }
+
+    /// Convert children that mix blocks with loose inline content
+    fn mixed_to_blocks(&self, node: NodeRef<Node>) -> Result<Vec<Block>, ParseError> {
+        let mut blocks = Vec::new();
+        let mut inlines = Vec::new();
+        for child in node.children() {
+            let is_block = child
+                .value()
+                .as_element()
+                .is_some_and(|el| BLOCK_ELEMENTS.contains(&el.name()));
+
+            if is_block {
+                flush_paragraph(&mut inlines, &mut blocks);
+                blocks.extend(self.element_to_blocks(child)?);
+            } else {
+                inlines.extend(self.node_to_inlines(child)?);
+            }
+        }
+        flush_paragraph(&mut inlines, &mut blocks);
+
+        Ok(blocks)
+    }
+
+    /// Parse an HTML fragment as inline content
+    pub fn parse_inlines(&self, html: &str) -> Result<Vec<Inline>, ParseError> {
+        let fragment = Html::parse_fragment(html);
+        let inlines = self.children_to_inlines(*fragment.root_element())?;
+        self.report_unknown_elements();
+        Ok(inlines)
+    }
+
+    /// Re-parse a document's body (or the whole input, if it has none) as a fragment
+    pub fn body_fragment(html: &str) -> Html {
+        let document = Html::parse_document(html);
+
+        // Select body content (or root if no body)
+        let body_selector = Selector::parse("body").unwrap();
+        let root = document
+            .select(&body_selector)
+            .next()
+            .map(|el| el.inner_html())
+            .unwrap_or_else(|| html.to_string());
+
+        Html::parse_fragment(&root)
+    }
+
+    /// Whether an element is dropped along with its content
+    fn is_removed(&self, tag: &str) -> bool {
+        NON_CONTENT_ELEMENTS.contains(&tag) || (self.strict && UNSAFE_ELEMENTS.contains(&tag))
+    }
+
+    /// A URL attribute, unless strict mode considers it unsafe
+    fn url_attr<'e>(&self, element: &'e scraper::node::Element, name: &str) -> Option<&'e str> {
+        let url = element.attr(name)?;
+        let lower = url.trim().to_lowercase();
+        let unsafe_url = UNSAFE_SCHEMES
+            .iter()
+            .any(|scheme| lower.starts_with(scheme));
+        (!(self.strict && unsafe_url)).then_some(url)
+    }
+
+    /// Convert an HTML element to a Block
+    pub fn element_to_block(&self, node: NodeRef<Node>) -> Result<Option<Block>, ParseError> {
+        let Some(element) = node.value().as_element() else {
+            return Ok(None);
+        };
+        let tag = element.name();
+        if self.is_removed(tag) {
+            return Ok(None);
+        }
+
+        match tag {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = tag[1..].parse::<u8>().unwrap_or(1);
+                let content = self.children_to_inlines(node)?;
+                let anchor = element.attr("id").map(|s| s.to_string());
+                Ok(Some(Block::Header {
+                    level,
+                    content,
+                    anchor,
+                }))
+            }
+            "p" => {
+                // A paragraph holding nothing but an image is the image
+                let mut children = node.children().filter(|child| match child.value() {
+                    Node::Text(text) => !text.trim().is_empty(),
+                    Node::Element(_) => true,
+                    _ => false,
+                });
+                if let (Some(only), None) = (children.next(), children.next()) {
+                    if only.value().as_element().is_some_and(|e| e.name() == "img") {
+                        return self.element_to_block(only);
+                    }
+                }
+
+                let content = self.children_to_inlines(node)?;
+                if content.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(Block::Paragraph(content)))
+                }
+            }
+            "ul" | "ol" => {
+                let ordered = tag == "ol";
+                let mut items = Vec::new();
+
+                for child in node.children() {
+                    if let Some(el) = child.value().as_element() {
+                        if el.name() == "li" {
+                            let item_blocks = self.li_to_blocks(child)?;
+                            items.push(item_blocks);
+                        }
+                    }
+                }
+
+                Ok(Some(Block::List { items, ordered }))
+            }
+            "blockquote" => {
+                let inner_blocks = self.children_to_blocks(node)?;
+                Ok(Some(Block::Blockquote(inner_blocks)))
+            }
+            "pre" => {
+                let code = text_content(node);
+                let lang = code_language(node);
+                Ok(Some(Block::CodeBlock { lang, code }))
+            }
+            "hr" => Ok(Some(Block::ThematicBreak)),
+            "table" => Ok(Some(Block::Table(self.table_to_data(node)?))),
+            "figure" => self.figure_to_block(node),
+            "div" | "section" | "article" => {
+                let mut inner = self.li_to_blocks(node)?;
+                Ok(match inner.len() {
+                    0 => None,
+                    1 => inner.pop(),
+                    _ => Some(Block::Container {
+                        id: element.attr("id").map(|s| s.to_string()),
+                        classes: element.classes().map(|c| c.to_string()).collect(),
+                        content: inner,
+                    }),
+                })
+            }
+            "img" => Ok(self.url_attr(element, "src").map(|src| Block::Image {
+                resource_key: src.to_string(), // Will be resolved later
+                caption: None,
+                alt: element.attr("alt").unwrap_or_default().to_string(),
+            })),
+            _ => {
+                // Unknown block element - try to extract as paragraph
+                let content = self.children_to_inlines(node)?;
+                if content.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(Block::Paragraph(content)))
+                }
+            }
+        }
+    }
+
+    /// Convert an HTML element to blocks, splicing the contents of layout
+    /// containers such as `<div>` into the surrounding block list
+    pub fn element_to_blocks(&self, node: NodeRef<Node>) -> Result<Vec<Block>, ParseError> {
+        let is_container = node.value().as_element().is_some_and(|el| {
+            matches!(el.name(), "div" | "section" | "article") && !self.is_removed(el.name())
+        });
+
+        if is_container {
+            self.li_to_blocks(node)
+        } else {
+            Ok(self.element_to_block(node)?.into_iter().collect())
+        }
+    }
+
+    /// Convert children to blocks
+    pub fn children_to_blocks(&self, node: NodeRef<Node>) -> Result<Vec<Block>, ParseError> {
+        let mut blocks = Vec::new();
+        for child in node.children() {
+            blocks.extend(self.element_to_blocks(child)?);
+        }
+        Ok(blocks)
+    }
+
+    /// Convert list item (or other container) to blocks
+    fn li_to_blocks(&self, node: NodeRef<Node>) -> Result<Vec<Block>, ParseError> {
+        // Check if li contains block elements
+        let has_blocks = node.children().any(|c| {
+            c.value()
+                .as_element()
+                .is_some_and(|e| BLOCK_ELEMENTS.contains(&e.name()))
+        });
+
+        if has_blocks {
+            self.children_to_blocks(node)
+        } else {
+            // Treat as inline content wrapped in paragraph
+            let inlines = self.children_to_inlines(node)?;
+            if inlines.is_empty() {
+                Ok(vec![])
+            } else {
+                Ok(vec![Block::Paragraph(inlines)])
+            }
+        }
+    }
+
+    /// Convert a `<figure>` to an image with its `<figcaption>`, or to its contents
+    fn figure_to_block(&self, node: NodeRef<Node>) -> Result<Option<Block>, ParseError> {
+        let caption = node
+            .descendants()
+            .find(|n| {
+                n.value()
+                    .as_element()
+                    .is_some_and(|e| e.name() == "figcaption")
+            })
+            .map(|n| text_content(n).trim().to_string())
+            .filter(|caption| !caption.is_empty());
+
+        let image = node
+            .descendants()
+            .filter_map(|n| n.value().as_element())
+            .find(|e| e.name() == "img");
+
+        if let Some(image) = image {
+            if let Some(src) = self.url_attr(image, "src") {
+                return Ok(Some(Block::Image {
+                    resource_key: src.to_string(),
+                    caption,
+                    alt: image.attr("alt").unwrap_or_default().to_string(),
+                }));
+            }
+        }
+
+        // Figures of code, quotes or tables keep their content
+        let inner: Vec<Block> = node
+            .children()
+            .filter(|n| {
+                n.value()
+                    .as_element()
+                    .is_some_and(|e| e.name() != "figcaption")
+            })
+            .map(|n| self.element_to_blocks(n))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(match inner.len() {
+            0 => None,
+            1 => inner.into_iter().next(),
+            _ => Some(Block::Blockquote(inner)),
+        })
+    }
+
+    /// Convert a `<table>`, taking headers from `<thead>` or a leading row of `<th>` cells
+    fn table_to_data(&self, node: NodeRef<Node>) -> Result<TableData, ParseError> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+
+        for row in node.descendants() {
+            let Some(element) = row.value().as_element() else {
+                continue;
+            };
+            if element.name() != "tr" {
+                continue;
+            }
+
+            let in_head = row
+                .ancestors()
+                .filter_map(|n| n.value().as_element())
+                .any(|e| e.name() == "thead");
+
+            let mut cells = Vec::new();
+            let mut all_headers = true;
+            for cell in row.children() {
+                let Some(cell_element) = cell.value().as_element() else {
+                    continue;
+                };
+                if !matches!(cell_element.name(), "td" | "th") {
+                    continue;
+                }
+                all_headers &= cell_element.name() == "th";
+
+                let span = |name: &str| {
+                    cell_element
+                        .attr(name)
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(1)
+                };
+                let mut table_cell = TableCell::new(self.children_to_inlines(cell)?);
+                table_cell.colspan = span("colspan");
+                table_cell.rowspan = span("rowspan");
+                cells.push(table_cell);
+            }
+
+            if cells.is_empty() {
+                continue;
+            }
+            if headers.is_empty() && rows.is_empty() && (in_head || all_headers) {
+                headers = cells;
+            } else {
+                rows.push(cells);
+            }
+        }
+
+        Ok(TableData { headers, rows })
+    }
+
+    /// Convert a node to inline elements
+    fn node_to_inlines(&self, node: NodeRef<Node>) -> Result<Vec<Inline>, ParseError> {
+        match node.value() {
+            Node::Text(text) => {
+                let s = text.trim();
+                if s.is_empty() {
+                    Ok(vec![])
+                } else {
+                    Ok(vec![Inline::Text(s.to_string())])
+                }
+            }
+            Node::Element(el) => self.element_to_inline(el, node),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Convert children to inline elements
+    pub fn children_to_inlines(&self, node: NodeRef<Node>) -> Result<Vec<Inline>, ParseError> {
+        let mut inlines = Vec::new();
+        for child in node.children() {
+            inlines.extend(self.node_to_inlines(child)?);
+        }
+        Ok(inlines)
+    }
+
+    /// Convert an HTML element to inline elements
+    fn element_to_inline(
+        &self,
+        element: &scraper::node::Element,
+        node: NodeRef<Node>,
+    ) -> Result<Vec<Inline>, ParseError> {
+        let tag = element.name();
+        if self.is_removed(tag) {
+            return Ok(vec![]);
+        }
+
+        match tag {
+            "b" | "strong" => {
+                let children = self.children_to_inlines(node)?;
+                Ok(vec![Inline::Bold(children)])
+            }
+            "i" | "em" | "cite" | "dfn" | "var" => {
+                let children = self.children_to_inlines(node)?;
+                Ok(vec![Inline::Italic(children)])
+            }
+            "code" | "kbd" | "samp" | "tt" => {
+                let text = text_content(node);
+                Ok(vec![Inline::Code(text)])
+            }
+            "a" => {
+                let children = self.children_to_inlines(node)?;
+                match self.url_attr(element, "href") {
+                    Some(url) => Ok(vec![Inline::Link {
+                        children,
+                        url: url.to_string(),
+                    }]),
+                    None if element.attr("href").is_some() => Ok(children),
+                    None => Ok(vec![Inline::Link {
+                        children,
+                        url: "#".to_string(),
+                    }]),
+                }
+            }
+            "sup" => {
+                let children = self.children_to_inlines(node)?;
+                Ok(vec![Inline::Superscript(children)])
+            }
+            "sub" => {
+                let children = self.children_to_inlines(node)?;
+                Ok(vec![Inline::Subscript(children)])
+            }
+            "s" | "strike" | "del" => {
+                let children = self.children_to_inlines(node)?;
+                Ok(vec![Inline::Strikethrough(children)])
+            }
+            "br" => Ok(vec![Inline::Break]),
+            "ruby" => {
+                // Base text is everything outside <rt>/<rp>
+                let annotation: String = node
+                    .children()
+                    .filter(|n| n.value().as_element().is_some_and(|e| e.name() == "rt"))
+                    .map(text_content)
+                    .collect();
+                let base: String = node
+                    .children()
+                    .filter(|n| {
+                        n.value()
+                            .as_element()
+                            .is_none_or(|e| !matches!(e.name(), "rt" | "rp"))
+                    })
+                    .map(text_content)
+                    .collect();
+                Ok(vec![Inline::Ruby {
+                    base: base.trim().to_string(),
+                    annotation: annotation.trim().to_string(),
+                }])
+            }
+            "span" | "u" | "ins" | "mark" | "small" | "abbr" | "q" | "time" | "bdi" | "bdo" => {
+                // Pass through content
+                self.children_to_inlines(node)
+            }
+            _ => {
+                // Unknown inline - extract text
+                self.unknown.borrow_mut().insert(tag.to_string());
+                let text = text_content(node);
+                if text.is_empty() {
+                    Ok(vec![])
+                } else {
+                    Ok(vec![Inline::Text(text)])
+                }
+            }
+        }
+    }
+}
+
+/// Decoder for HTML pages (HTML, XHTML) and zipped HTML (HTMLZ)
+///
+/// A single page resolves its images through the [`DecodeContext`]. An HTMLZ
+/// archive starts from its `index.html` and follows local links to the other
+/// pages it contains, loading their images from the archive.
+pub struct HtmlDecoder {
+    strict_sanitization: bool,
+    extract_main_content: bool,
+    splitter: ChapterSplitter,
+}
+
+impl HtmlDecoder {
+    pub fn new() -> Self {
+        Self {
+            strict_sanitization: true,
+            extract_main_content: false,
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Enable or disable removal of scripts, embedded objects and unsafe URLs
+    pub fn with_strict_sanitization(mut self, strict: bool) -> Self {
+        self.strict_sanitization = strict;
+        self
+    }
+
+    /// Keep only each page's main content, dropping navigation, sidebars,
+    /// comments and footers
+    pub fn with_main_content_extraction(mut self, extract: bool) -> Self {
+        self.extract_main_content = extract;
+        self
+    }
+
+    /// Choose where chapters begin (by default, at H1 headings)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+
+    /// Convert a page's content to blocks, reporting elements kept as plain text
+    fn page_blocks(
+        &self,
+        document: &Html,
+        title: &str,
+        context: &DecodeContext,
+        path: Option<&str>,
+    ) -> Result<Vec<Block>, ParseError> {
+        let converter = HtmlConverter::new(self.strict_sanitization).reporting_to(context, path);
+        let content = self
+            .extract_main_content
+            .then(|| main_content(document))
+            .flatten();
+        let mut blocks = match &content {
+            Some(content) => converter.parse_fragment(content)?,
+            None => converter.parse_document(&document.html())?,
+        };
+        if content.is_none() {
+            return Ok(blocks);
+        }
+
+        // The headline often sits outside the article body
+        let has_title = blocks
+            .iter()
+            .any(|block| matches!(block, Block::Header { level: 1, .. }));
+        if !has_title && !title.is_empty() {
+            blocks.insert(
+                0,
+                Block::Header {
+                    level: 1,
+                    content: vec![Inline::text(title)],
+                    anchor: None,
+                },
+            );
+        }
+        Ok(blocks)
+    }
+
+    /// Decode an HTMLZ archive or zipped site
+    fn decode_archive(&self, data: Vec<u8>, context: &DecodeContext) -> Result<Book, ParseError> {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+            .map_err(|e| ParseError::MalformedContent(format!("Invalid HTMLZ archive: {}", e)))?;
+        let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).map_err(|e| {
+                ParseError::MalformedContent(format!("Invalid HTMLZ archive: {}", e))
+            })?;
+            if file.is_dir() {
+                continue;
+            }
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).map_err(|e| {
+                ParseError::MalformedContent(format!("Failed to read {}: {}", file.name(), e))
+            })?;
+            files.insert(file.name().trim_start_matches('/').to_string(), data);
+        }
+
+        let mut pages: Vec<&String> = files.keys().filter(|path| is_html_path(path)).collect();
+        pages.sort_by_key(|path| (path.matches('/').count(), !is_index(path), path.as_str()));
+        let entry = pages
+            .first()
+            .map(|path| path.to_string())
+            .ok_or_else(|| ParseError::MissingField("HTML page in archive".to_string()))?;
+
+        // Pages in the order their links are first reached from the entry page
+        let mut order = vec![entry];
+        let mut documents = Vec::new();
+        let mut next = 0;
+        while next < order.len() {
+            let path = order[next].clone();
+            next += 1;
+            let document = Html::parse_document(&decode_html(&files[&path]));
+            for link in local_links(&document) {
+                let Some(target) = resolve_link(parent_dir(&path), &link) else {
+                    continue;
+                };
+                if is_html_path(&target) && files.contains_key(&target) && !order.contains(&target)
+                {
+                    order.push(target);
+                }
+            }
+            documents.push((path, document));
+        }
+
+        let mut metadata = document_metadata(&documents[0].1);
+        let opf = files
+            .keys()
+            .find(|path| path.ends_with(".opf"))
+            .and_then(|path| files.get(path));
+        if let Some(opf) = opf {
+            let text = xml::decode_xml(opf);
+            apply_opf_metadata(&xml::parse(&text)?, &mut metadata);
+        }
+
+        let mut resources = ResourceStore::new();
+        let mut loaded: HashMap<String, String> = HashMap::new();
+        let mut load = |dir: &str, link: &str, resources: &mut ResourceStore| {
+            if let Some(key) =
+                data_uri(link).map(|(mime, data)| resources.add(Resource::new(mime, data)))
+            {
+                return Some(key);
+            }
+            let path = resolve_link(dir, link)?;
+            if let Some(key) = loaded.get(&path) {
+                return Some(key.clone());
+            }
+            let data = files.get(&path)?.clone();
+            let mime = sniff_mime_type(&data, &path);
+            let filename = path.rsplit('/').next().unwrap_or(&path).to_string();
+            let key = resources.add(Resource::new(mime, data).with_filename(filename));
+            loaded.insert(path, key.clone());
+            Some(key)
+        };
+
+        let cover = metadata.cover_resource_key.take().or_else(|| {
+            ["cover.jpg", "cover.jpeg", "cover.png"]
+                .into_iter()
+                .find(|name| files.contains_key(*name))
+                .map(str::to_string)
+        });
+        if let Some(cover) = cover {
+            metadata.cover_resource_key = load(parent_dir(&documents[0].0), &cover, &mut resources);
+        }
+
+        // Each page becomes one or more chapters, the first taking an id for links
+        let mut ids: HashMap<String, String> = HashMap::new();
+        let mut chapters: Vec<(String, Vec<Chapter>)> = Vec::new();
+        for (path, document) in &documents {
+            let title = document_metadata(document).title;
+            let mut blocks = self.page_blocks(document, &title, context, Some(path))?;
+            rewrite_images(&mut blocks, &mut |link| {
+                let key = load(parent_dir(path), link, &mut resources);
+                if key.is_none() && resolve_link(parent_dir(path), link).is_some() {
+                    context.report(
+                        Diagnostic::warning(
+                            "unresolved-image",
+                            format!("No file in the archive for image {}", link),
+                        )
+                        .with_path(path.clone()),
+                    );
+                }
+                key
+            });
+
+            let mut page_chapters = self.splitter.split(blocks);
+            let mut id = slugify(&path.replace(['/', '.'], "-"));
+            while ids.values().any(|used| *used == id) {
+                id.push_str("-page");
+            }
+            if let Some(first) = page_chapters.first_mut() {
+                first.id = Some(id.clone());
+            }
+            ids.insert(path.clone(), id);
+            chapters.push((path.clone(), page_chapters));
+        }
+
+        // Links between pages point at the chapters they became
+        let mut book = Book::with_metadata(metadata);
+        for (path, mut page_chapters) in chapters {
+            let rewrite = |url: &str| {
+                let (target, fragment) = url.split_once('#').unwrap_or((url, ""));
+                if target.is_empty() {
+                    return None;
+                }
+                let target = resolve_link(parent_dir(&path), target)?;
+                let id = ids.get(&target)?;
+                Some(if fragment.is_empty() {
+                    format!("#{}", id)
+                } else {
+                    format!("#{}", fragment)
+                })
+            };
+            for chapter in &mut page_chapters {
+                rewrite_links(&mut chapter.content, &rewrite);
+            }
+            book.chapters.extend(page_chapters);
+        }
+        book.resources = resources;
+
+        Ok(book)
+    }
+}
+
+impl Default for HtmlDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for HtmlDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read HTML: {}", e)))?;
+
+        if data.starts_with(b"PK\x03\x04") {
+            return self.decode_archive(data, context);
+        }
+
+        let document = Html::parse_document(&decode_html(&data));
+        let mut metadata = document_metadata(&document);
+        let mut blocks = self.page_blocks(&document, &metadata.title, context, None)?;
+
+        // Images embedded as data URIs, then those next to the page
+        let mut resources = ResourceStore::new();
+        rewrite_images(&mut blocks, &mut |link| {
+            data_uri(link).map(|(mime, data)| resources.add(Resource::new(mime, data)))
+        });
+        let mut loader = AssetLoader::new(context, &mut resources);
+        loader.load_images(&mut blocks);
+        if let Some(cover) = &metadata.cover_resource_key {
+            metadata.cover_resource_key = loader.load(cover);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+        for chapter in self.splitter.split(blocks) {
+            book.add_chapter(chapter);
+        }
+
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["html", "htm", "xhtml", "htmlz"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/html", "application/xhtml+xml", "application/x-htmlz"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+
+    fn set_decode_options(&mut self, options: &DecodeOptions) -> bool {
+        if options.pages.is_some() || options.password.is_some() {
+            return false;
+        }
+        if options.main_content {
+            self.extract_main_content = true;
+        }
+        true
+    }
+}
+
+/// Decode a page using its byte order mark or `<meta charset>`, falling back to
+/// UTF-8 or Windows-1252
+fn decode_html(data: &[u8]) -> String {
+    if Encoding::for_bom(data).is_none() {
+        let head = String::from_utf8_lossy(&data[..data.len().min(1024)]).to_lowercase();
+        let declared = head
+            .find("charset")
+            .map(|i| head[i + "charset".len()..].trim_start())
+            .and_then(|rest| rest.strip_prefix('='))
+            .map(|rest| rest.trim_start().trim_start_matches(['"', '\'']))
+            .and_then(|rest| {
+                let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))?;
+                Encoding::for_label(&rest.as_bytes()[..end])
+            });
+        if let Some(encoding) = declared {
+            let (text, _) = encoding.decode_without_bom_handling(data);
+            return text.into_owned();
+        }
+    }
+    decode_bytes(data).0
+}
+
+/// Metadata from a page's `<title>`, `lang`, `<meta>` and Open Graph tags
+fn document_metadata(document: &Html) -> Metadata {
+    let meta = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            let selector =
+                Selector::parse(&format!("meta[name='{0}'], meta[property='{0}']", name)).ok()?;
+            document
+                .select(&selector)
+                .filter_map(|element| element.value().attr("content"))
+                .map(str::trim)
+                .find(|content| !content.is_empty())
+                .map(str::to_string)
+        })
+    };
+    let select_text = |selector: &str| {
+        let selector = Selector::parse(selector).ok()?;
+        let element = document.select(&selector).next()?;
+        let text = element.text().collect::<String>();
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        (!text.is_empty()).then_some(text)
+    };
+
+    let title = meta(&["og:title", "twitter:title"])
+        .or_else(|| select_text("title"))
+        .or_else(|| select_text("h1"))
+        .unwrap_or_else(|| "Untitled".to_string());
+    let language = Selector::parse("html")
+        .ok()
+        .and_then(|selector| {
+            let html = document.select(&selector).next()?;
+            html.value()
+                .attr("lang")
+                .or_else(|| html.value().attr("xml:lang"))
+                .map(str::to_string)
+        })
+        .or_else(|| meta(&["og:locale", "language"]).map(|locale| locale.replace('_', "-")))
+        .unwrap_or_else(|| "en".to_string());
+
+    let mut metadata = Metadata::new(title, language);
+    metadata.creator = meta(&["author", "article:author", "dc.creator", "twitter:creator"])
+        .into_iter()
+        .collect();
+    metadata.description = meta(&["description", "og:description", "dc.description"]);
+    metadata.publisher = meta(&["og:site_name", "publisher", "dc.publisher"]);
+    metadata.subject = meta(&["keywords"])
+        .map(|keywords| {
+            keywords
+                .split(',')
+                .map(str::trim)
+                .filter(|keyword| !keyword.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    metadata.date = meta(&["article:published_time", "date", "dc.date"])
+        .and_then(|date| parse_date(&date).or_else(|| parse_date(date.get(..10)?)));
+    metadata.cover_resource_key = meta(&["og:image"]);
+    metadata
+}
+
+/// Fill in metadata from an OPF package document, as bundled in HTMLZ files
+fn apply_opf_metadata(opf: &roxmltree::Document, metadata: &mut Metadata) {
+    let Some(meta) = xml::child(opf.root_element(), "metadata") else {
+        return;
+    };
+    let values = |name| -> Vec<String> {
+        xml::children(meta, name)
+            .map(xml::text)
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+
+    if let Some(title) = values("title").into_iter().next() {
+        metadata.title = title;
+    }
+    let creators = values("creator");
+    if !creators.is_empty() {
+        metadata.creator = creators;
+    }
+    let subjects = values("subject");
+    if !subjects.is_empty() {
+        metadata.subject = subjects;
+    }
+    if let Some(language) = values("language").into_iter().next() {
+        metadata.language = language;
+    }
+    if let Some(description) = values("description").into_iter().next() {
+        metadata.description = Some(description);
+    }
+    if let Some(publisher) = values("publisher").into_iter().next() {
+        metadata.publisher = Some(publisher);
+    }
+    if let Some(identifier) = values("identifier").into_iter().next() {
+        metadata.identifier = identifier;
+    }
+    if let Some(date) = values("date").into_iter().next() {
+        metadata.date = parse_date(&date).or_else(|| parse_date(date.get(..10)?));
+    }
+}
+
+/// Targets of a page's links, in document order
+fn local_links(document: &Html) -> Vec<String> {
+    let selector = Selector::parse("a[href]").unwrap();
+    document
+        .select(&selector)
+        .filter_map(|link| link.value().attr("href"))
+        .map(|href| href.split('#').next().unwrap_or(href).to_string())
+        .filter(|href| !href.is_empty())
+        .collect()
+}
+
+/// Media type and data of a base64 `data:` URI
+fn data_uri(link: &str) -> Option<(String, Vec<u8>)> {
+    let (header, data) = link.trim().strip_prefix("data:")?.split_once(',')?;
+    let mime = header.strip_suffix(";base64")?;
+    let data: String = data.split_whitespace().collect();
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .ok()?;
+    let mime = if mime.is_empty() {
+        "application/octet-stream"
+    } else {
+        mime
+    };
+    Some((mime.to_string(), data))
+}
+
+fn is_html_path(path: &str) -> bool {
+    let path = path.to_lowercase();
+    [".html", ".htm", ".xhtml"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
+
+fn is_index(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path).to_lowercase();
+    name.starts_with("index.")
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Get text content of a node
+pub(crate) fn text_content(node: NodeRef<Node>) -> String {
+    let mut text = String::new();
+    for descendant in node.descendants() {
+        if let Node::Text(t) = descendant.value() {
+            text.push_str(t);
+        }
+    }
+    text
+}
+
+/// Language of a `<pre><code class="language-x">` block
+fn code_language(node: NodeRef<Node>) -> Option<String> {
+    node.descendants()
+        .filter_map(|n| n.value().as_element())
+        .filter_map(|e| e.attr("class"))
+        .flat_map(str::split_whitespace)
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .map(str::to_string)
+}
+
+/// Wrap pending inline content in a paragraph
+fn flush_paragraph(inlines: &mut Vec<Inline>, blocks: &mut Vec<Block>) {
+    if !inlines.is_empty() {
+        blocks.push(Block::Paragraph(std::mem::take(inlines)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table() {
+        let converter = HtmlConverter::new(true);
+        let html = "<table><thead><tr><th>A</th><th>B</th></tr></thead>\
+                    <tbody><tr><td colspan=\"2\">1</td></tr></tbody></table>";
+
+        let blocks = converter.parse_fragment(html).unwrap();
+        let [Block::Table(table)] = &blocks[..] else {
+            panic!("expected a table, got {:?}", blocks);
+        };
+        assert_eq!(table.headers.len(), 2);
+        assert_eq!(table.rows[0][0].colspan, 2);
+    }
+
+    #[test]
+    fn test_parse_figure() {
+        let converter = HtmlConverter::new(true);
+        let html =
+            "<figure><img src=\"a.png\" alt=\"A\"><figcaption>The caption</figcaption></figure>";
+
+        assert_eq!(
+            converter.parse_fragment(html).unwrap(),
+            vec![Block::Image {
+                resource_key: "a.png".into(),
+                caption: Some("The caption".into()),
+                alt: "A".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_div_contents_join_the_parent() {
+        let converter = HtmlConverter::new(true);
+        let html = "<div><p>One</p><section><p>Two</p><div>Three</div></section></div>";
+
+        assert_eq!(
+            converter.parse_fragment(html).unwrap(),
+            vec![
+                Block::Paragraph(vec![Inline::text("One")]),
+                Block::Paragraph(vec![Inline::text("Two")]),
+                Block::Paragraph(vec![Inline::text("Three")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_removes_unsafe_content() {
+        let html = "<p>Hi<script>alert(1)</script><a href=\"javascript:alert(1)\">x</a></p><iframe src=\"x\">frame</iframe>";
+
+        let strict = HtmlConverter::new(true).parse_fragment(html).unwrap();
+        assert_eq!(
+            strict,
+            vec![Block::Paragraph(vec![
+                Inline::text("Hi"),
+                Inline::text("x")
+            ])]
+        );
+
+        let lenient = HtmlConverter::new(false).parse_fragment(html).unwrap();
+        assert_eq!(lenient.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_inlines() {
+        let converter = HtmlConverter::new(true);
+        let inlines = converter
+            .parse_inlines("<kbd>Ctrl</kbd><sup>2</sup><br><ruby>漢<rt>kan</rt></ruby>")
+            .unwrap();
+
+        assert_eq!(
+            inlines,
+            vec![
+                Inline::Code("Ctrl".into()),
+                Inline::Superscript(vec![Inline::text("2")]),
+                Inline::Break,
+                Inline::Ruby {
+                    base: "漢".into(),
+                    annotation: "kan".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_page_metadata_and_chapters() {
+        use crate::decoder::Decoder;
+
+        let html = r#"<!DOCTYPE html><html lang="fr"><head>
+            <meta charset="utf-8"><title>Page title</title>
+            <meta property="og:title" content="Le Article">
+            <meta name="author" content="Marie Curie">
+            <meta property="og:site_name" content="The Journal">
+            <meta name="description" content="About radium">
+          </head><body>
+            <h1>One</h1><p>First <img src="data:image/png;base64,iVBORw0KGgo="></p>
+            <p><img src="data:image/png;base64,iVBORw0KGgo=" alt="Dot"></p>
+            <h1>Two</h1><p>Second</p>
+          </body></html>"#;
+        let book = HtmlDecoder::new()
+            .decode(&mut std::io::Cursor::new(html))
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "Le Article");
+        assert_eq!(book.metadata.language, "fr");
+        assert_eq!(book.metadata.creator, vec!["Marie Curie"]);
+        assert_eq!(book.metadata.publisher.as_deref(), Some("The Journal"));
+        assert_eq!(book.metadata.description.as_deref(), Some("About radium"));
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["One", "Two"]);
+        assert_eq!(book.resources.len(), 1);
+        assert!(
+            matches!(&book.chapters[0].content[2], Block::Image { resource_key, .. }
+            if book.resources.get(resource_key).is_some())
+        );
+    }
+
+    #[test]
+    fn test_decode_splits_chapters_inside_divs() {
+        use crate::decoder::Decoder;
+
+        let html = r#"<html><body><div class="wrap">
+            <div class="content">
+              <h1>Title</h1><p>Intro</p><p>More</p>
+              <section><h1>Part one</h1><p>First</p></section>
+              <section><h1>Part two</h1><p>Second</p></section>
+            </div>
+            <div class="footer">Footer</div>
+          </div></body></html>"#;
+        let book = HtmlDecoder::new()
+            .decode(&mut std::io::Cursor::new(html))
+            .unwrap();
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Title", "Part one", "Part two"]);
+        assert_eq!(
+            book.chapters[2].content.last(),
+            Some(&Block::Paragraph(vec![Inline::text("Footer")]))
+        );
+    }
+
+    #[test]
+    fn test_reports_unknown_elements_and_missing_images() {
+        use crate::decoder::Decoder;
+        use crate::diagnostic::Severity;
+
+        let dir = tempfile::tempdir().unwrap();
+        let html = "<p>A <blink>loud</blink> <marquee>word</marquee></p><img src=\"gone.png\">";
+        let context = DecodeContext::new().with_base_path(dir.path());
+        HtmlDecoder::new()
+            .decode_with_context(&mut std::io::Cursor::new(html), &context)
+            .unwrap();
+
+        let diagnostics = context.diagnostics();
+        let codes: Vec<(&str, Severity)> = diagnostics
+            .iter()
+            .map(|d| (d.code.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ("unknown-element", Severity::Info),
+                ("missing-asset", Severity::Warning)
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "Kept <blink>, <marquee> as plain text"
+        );
+        assert_eq!(diagnostics[1].location.path.as_deref(), Some("gone.png"));
+    }
+
+    #[test]
+    fn test_decode_htmlz_follows_links() {
+        use crate::decoder::Decoder;
+        use std::io::Write;
+
+        let files = [
+            (
+                "index.html",
+                r#"<html><head><title>Site</title></head><body><h1>Contents</h1>
+                   <p><a href="pages/b.html">B</a> <a href="pages/a.html#top">A</a></p></body></html>"#,
+            ),
+            (
+                "pages/a.html",
+                r#"<html><body><h1 id="top">Page A</h1><img src="../images/pic.png"></body></html>"#,
+            ),
+            (
+                "pages/b.html",
+                r#"<html><body><h1>Page B</h1><p><a href="a.html">Back to A</a></p></body></html>"#,
+            ),
+            (
+                "pages/unlinked.html",
+                "<html><body><h1>Orphan</h1></body></html>",
+            ),
+            ("images/pic.png", "\u{89}PNG\r\n\u{1a}\n"),
+        ];
+        let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in files {
+            archive
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            archive.write_all(content.as_bytes()).unwrap();
+        }
+        let data = archive.finish().unwrap().into_inner();
+
+        let book = HtmlDecoder::new()
+            .decode(&mut std::io::Cursor::new(data))
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "Site");
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Contents", "Page B", "Page A"]);
+        assert_eq!(book.resources.len(), 1);
+
+        let link_target = |chapter: &Chapter| match &chapter.content[1] {
+            Block::Paragraph(inlines) => inlines.iter().find_map(|inline| match inline {
+                Inline::Link { url, .. } => Some(url.clone()),
+                _ => None,
+            }),
+            _ => None,
+        };
+        assert_eq!(
+            link_target(&book.chapters[0]).as_deref(),
+            Some("#pages-b-html")
+        );
+        assert_eq!(
+            link_target(&book.chapters[1]),
+            book.chapters[2].id.as_ref().map(|id| format!("#{}", id))
+        );
+    }
+
+    #[test]
+    fn test_same_named_htmlz_images_stay_apart_in_epub() {
+        use crate::decoder::Decoder;
+        use crate::encoder::{Encoder, EpubEncoder};
+        use std::io::{Read, Write};
+
+        let files = [
+            (
+                "index.html",
+                r#"<html><body><h1>One</h1><img src="a/pic.png"><h1>Two</h1><img src="b/pic.png"></body></html>"#,
+            ),
+            ("a/pic.png", "first"),
+            ("b/pic.png", "second"),
+        ];
+        let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in files {
+            archive
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            archive.write_all(content.as_bytes()).unwrap();
+        }
+        let data = archive.finish().unwrap().into_inner();
+        let book = HtmlDecoder::new()
+            .decode(&mut std::io::Cursor::new(data))
+            .unwrap();
+
+        let mut output = Vec::new();
+        EpubEncoder::new().encode(&book, &mut output).unwrap();
+        let mut epub = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            epub.by_name(&format!("OEBPS/{}", name))
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let mut images = Vec::new();
+        for chapter in ["chapter_1.xhtml", "chapter_2.xhtml"] {
+            let xhtml = read(chapter);
+            let start = xhtml.find("src=\"").unwrap() + 5;
+            let end = start + xhtml[start..].find('"').unwrap();
+            images.push(read(&xhtml[start..end]));
+        }
+        assert_eq!(images, vec!["first", "second"]);
+    }
+}
@@ -86,12 +86,11 @@ impl KepubDecoder {
     /// Simple span removal that preserves content
     fn remove_kobo_spans_simple(&self, html: &str) -> String {
         let mut result = String::with_capacity(html.len());
-        let mut chars = html.chars().peekable();
         let mut in_tag = false;
         let mut current_tag = String::new();
         let mut skip_closing_spans = 0;
 
-        while let Some(c) = chars.next() {
+        for c in html.chars() {
             if c == '<' {
                 in_tag = true;
                 current_tag.clear();
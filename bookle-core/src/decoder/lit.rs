@@ -104,11 +104,9 @@ impl LitDecoder {
                 let hi = data[j + 1];
 
                 // Check for ASCII printable character in UTF-16LE (hi byte = 0)
-                if hi == 0 && lo >= 0x20 && lo <= 0x7E {
+                if hi == 0 && (0x20..=0x7E).contains(&lo) {
                     chars.push(lo as char);
                     j += 2;
-                } else if !chars.is_empty() {
-                    break;
                 } else {
                     break;
                 }
@@ -1,8 +1,18 @@
 //! Markdown decoder implementation
 
+use super::chapters::{ChapterSplitter, SplitOptions, SplitStrategy};
+use super::context::AssetLoader;
+use super::front_matter::{self, FrontMatter};
+use super::html::HtmlConverter;
+use super::DecodeContext;
 use crate::error::ParseError;
-use crate::types::{Block, Book, Chapter, Inline, Metadata, TableCell, TableData};
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use crate::types::{
+    AdmonitionKind, Block, Book, DefinitionItem, Inline, Metadata, ResourceStore, TableCell,
+    TableData,
+};
+use pulldown_cmark::{
+    BlockQuoteKind, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd,
+};
 use std::io::Read;
 
 /// Decoder for Markdown format
@@ -13,6 +23,22 @@ pub struct MarkdownDecoder {
     enable_strikethrough: bool,
     /// Whether to enable footnotes extension
     enable_footnotes: bool,
+    /// Whether to enable `- [x]` task lists
+    enable_task_lists: bool,
+    /// Whether to enable `Term` / `: Definition` lists
+    enable_definition_lists: bool,
+    /// Whether to enable GitHub-style `> [!NOTE]` admonitions
+    enable_admonitions: bool,
+    /// Whether to convert quotes, dashes and ellipses to typographic forms
+    enable_smart_punctuation: bool,
+    /// Whether to enable `{#id .class}` heading attributes
+    enable_attributes: bool,
+    /// Whether to enable `:::` fenced containers
+    enable_containers: bool,
+    /// Whether unsafe tags and URLs are removed from raw HTML
+    strict_html: bool,
+    /// Where the document is divided into chapters
+    splitter: ChapterSplitter,
 }
 
 impl MarkdownDecoder {
@@ -21,6 +47,14 @@ impl MarkdownDecoder {
             enable_tables: true,
             enable_strikethrough: true,
             enable_footnotes: true,
+            enable_task_lists: false,
+            enable_definition_lists: false,
+            enable_admonitions: false,
+            enable_smart_punctuation: false,
+            enable_attributes: true,
+            enable_containers: false,
+            strict_html: true,
+            splitter: ChapterSplitter::default(),
         }
     }
 
@@ -42,6 +76,69 @@ impl MarkdownDecoder {
         self
     }
 
+    /// Enable or disable task list parsing, keeping each item's checked state
+    pub fn with_task_lists(mut self, enable: bool) -> Self {
+        self.enable_task_lists = enable;
+        self
+    }
+
+    /// Enable or disable definition list parsing
+    pub fn with_definition_lists(mut self, enable: bool) -> Self {
+        self.enable_definition_lists = enable;
+        self
+    }
+
+    /// Enable or disable admonitions written as `> [!NOTE]` blockquotes
+    pub fn with_admonitions(mut self, enable: bool) -> Self {
+        self.enable_admonitions = enable;
+        self
+    }
+
+    /// Enable or disable smart quotes, dashes and ellipses
+    pub fn with_smart_punctuation(mut self, enable: bool) -> Self {
+        self.enable_smart_punctuation = enable;
+        self
+    }
+
+    /// Enable or disable `{#id .class}` attributes on headings (on by default)
+    ///
+    /// A heading's id becomes its anchor.
+    pub fn with_attributes(mut self, enable: bool) -> Self {
+        self.enable_attributes = enable;
+        self
+    }
+
+    /// Enable or disable `:::` fenced containers
+    ///
+    /// A container opens with `::: {#id .class}` (or `::: class`) and closes with
+    /// `:::`. Containers whose class names an admonition kind, such as
+    /// `::: warning`, become admonitions.
+    pub fn with_containers(mut self, enable: bool) -> Self {
+        self.enable_containers = enable;
+        self
+    }
+
+    /// Set whether raw HTML is sanitized (on by default)
+    ///
+    /// Scripts and styles are always dropped. Strict mode also removes embedded
+    /// and interactive elements such as `<iframe>` and `<form>`, and `javascript:` URLs.
+    pub fn with_strict_html(mut self, strict: bool) -> Self {
+        self.strict_html = strict;
+        self
+    }
+
+    /// Set where the document is divided into chapters (H1 headings by default)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+
     fn get_parser_options(&self) -> Options {
         let mut options = Options::empty();
         if self.enable_tables {
@@ -53,17 +150,60 @@ impl MarkdownDecoder {
         if self.enable_footnotes {
             options.insert(Options::ENABLE_FOOTNOTES);
         }
-        options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+        if self.enable_task_lists {
+            options.insert(Options::ENABLE_TASKLISTS);
+        }
+        if self.enable_definition_lists {
+            options.insert(Options::ENABLE_DEFINITION_LIST);
+        }
+        if self.enable_admonitions {
+            options.insert(Options::ENABLE_GFM);
+        }
+        if self.enable_smart_punctuation {
+            options.insert(Options::ENABLE_SMART_PUNCTUATION);
+        }
+        if self.enable_attributes {
+            options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+        }
         options
     }
 
     /// Parse markdown content into blocks
-    fn parse_markdown(&self, content: &str) -> Result<Vec<Block>, ParseError> {
+    fn parse_markdown(
+        &self,
+        content: &str,
+        context: &DecodeContext,
+    ) -> Result<Vec<Block>, ParseError> {
+        if !self.enable_containers {
+            return self.parse_commonmark(content, context);
+        }
+
+        let mut blocks = Vec::new();
+        for segment in split_containers(content) {
+            match segment {
+                Segment::Markdown(markdown) => {
+                    blocks.extend(self.parse_commonmark(&markdown, context)?)
+                }
+                Segment::Container { attributes, body } => {
+                    let content = self.parse_markdown(&body, context)?;
+                    blocks.push(container_block(&attributes, content));
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Parse markdown without `:::` containers
+    fn parse_commonmark(
+        &self,
+        content: &str,
+        context: &DecodeContext,
+    ) -> Result<Vec<Block>, ParseError> {
         let options = self.get_parser_options();
         let parser = Parser::new_ext(content, options);
         let events: Vec<Event> = parser.collect();
 
-        let mut state = ParserState::new();
+        let mut state = ParserState::new(context);
         self.process_events(&events, &mut state)?;
 
         Ok(state.blocks)
@@ -109,6 +249,11 @@ impl MarkdownDecoder {
                 state.blocks.push(Block::ThematicBreak);
                 Ok(start + 1)
             }
+            Event::TaskListMarker(checked) => {
+                state.push_inline(Inline::Checkbox { checked: *checked });
+                Ok(start + 1)
+            }
+            Event::InlineHtml(html) => self.process_inline_html(events, start, html, state),
             Event::End(_) => Ok(start + 1),
             _ => Ok(start + 1),
         }
@@ -127,7 +272,7 @@ impl MarkdownDecoder {
                 self.process_heading(events, start, level, id.as_deref(), state)
             }
             Tag::Paragraph => self.process_paragraph(events, start, state),
-            Tag::BlockQuote => self.process_blockquote(events, start, state),
+            Tag::BlockQuote(kind) => self.process_blockquote(events, start, kind, state),
             Tag::CodeBlock(kind) => self.process_code_block(events, start, kind, state),
             Tag::List(start_num) => self.process_list(events, start, start_num, state),
             Tag::Item => self.process_list_item(events, start, state),
@@ -153,6 +298,8 @@ impl MarkdownDecoder {
             Tag::FootnoteDefinition(label) => {
                 self.process_footnote_def(events, start, label.to_string(), state)
             }
+            Tag::HtmlBlock => self.process_html_block(events, start, state),
+            Tag::DefinitionList => self.process_definition_list(events, start, state),
             _ => Ok(start + 1),
         }
     }
@@ -185,7 +332,7 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Heading(level));
-        let inlines = self.collect_inlines(events, start + 1, end)?;
+        let inlines = self.collect_inlines(events, start + 1, end, state.context)?;
 
         let level_num = match level {
             HeadingLevel::H1 => 1,
@@ -213,10 +360,14 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Paragraph);
-        let inlines = self.collect_inlines(events, start + 1, end)?;
 
-        if !inlines.is_empty() {
-            state.blocks.push(Block::Paragraph(inlines));
+        // Images are blocks in the IR, so they split the paragraph they appear in
+        let mut inner = ParserState::new(state.context);
+        self.process_events(&events[start + 1..end], &mut inner)?;
+        state.blocks.extend(inner.blocks);
+
+        if !inner.inlines.is_empty() {
+            state.blocks.push(Block::Paragraph(inner.inlines));
         }
 
         Ok(end + 1)
@@ -227,14 +378,29 @@ impl MarkdownDecoder {
         &self,
         events: &[Event],
         start: usize,
+        kind: Option<BlockQuoteKind>,
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
-        let end = self.find_end_tag(events, start, &TagEnd::BlockQuote);
+        let end = self.find_end_tag(events, start, &TagEnd::BlockQuote(kind));
 
-        let mut inner_state = ParserState::new();
+        let mut inner_state = ParserState::new(state.context);
         self.process_events(&events[start + 1..end], &mut inner_state)?;
 
-        state.blocks.push(Block::Blockquote(inner_state.blocks));
+        // GitHub alerts (`> [!NOTE]`) only carry a kind when admonitions are enabled
+        let block = match kind {
+            Some(kind) => Block::Admonition {
+                kind: match kind {
+                    BlockQuoteKind::Note => AdmonitionKind::Note,
+                    BlockQuoteKind::Tip => AdmonitionKind::Tip,
+                    BlockQuoteKind::Important => AdmonitionKind::Important,
+                    BlockQuoteKind::Warning => AdmonitionKind::Warning,
+                    BlockQuoteKind::Caution => AdmonitionKind::Caution,
+                },
+                content: inner_state.blocks,
+            },
+            None => Block::Blockquote(inner_state.blocks),
+        };
+        state.blocks.push(block);
 
         Ok(end + 1)
     }
@@ -286,8 +452,8 @@ impl MarkdownDecoder {
             if let Event::Start(Tag::Item) = &events[i] {
                 let item_end = self.find_end_tag(events, i, &TagEnd::Item);
 
-                let mut item_state = ParserState::new();
-                self.process_events(&events[i + 1..item_end], &mut item_state)?;
+                let mut item_state = ParserState::new(state.context);
+                self.process_item_events(&events[i + 1..item_end], &mut item_state)?;
                 items.push(item_state.blocks);
 
                 i = item_end + 1;
@@ -301,6 +467,78 @@ impl MarkdownDecoder {
         Ok(end + 1)
     }
 
+    /// Process the contents of a list item or definition
+    ///
+    /// Items in tight lists hold their text directly rather than in a paragraph, so
+    /// any text is wrapped in one before the next block and at the end.
+    fn process_item_events(
+        &self,
+        events: &[Event],
+        state: &mut ParserState,
+    ) -> Result<(), ParseError> {
+        let mut i = 0;
+        while i < events.len() {
+            if starts_block(&events[i]) {
+                state.flush_paragraph();
+            }
+            i = self.process_event(events, i, state)?;
+        }
+        state.flush_paragraph();
+        Ok(())
+    }
+
+    /// Process a definition list
+    fn process_definition_list(
+        &self,
+        events: &[Event],
+        start: usize,
+        state: &mut ParserState,
+    ) -> Result<usize, ParseError> {
+        let end = self.find_end_tag(events, start, &TagEnd::DefinitionList);
+
+        let mut items: Vec<DefinitionItem> = Vec::new();
+        let mut i = start + 1;
+
+        while i < end {
+            match &events[i] {
+                Event::Start(Tag::DefinitionListTitle) => {
+                    let title_end = self.find_end_tag(events, i, &TagEnd::DefinitionListTitle);
+                    items.push(DefinitionItem {
+                        term: self.collect_inlines(events, i + 1, title_end, state.context)?,
+                        definitions: Vec::new(),
+                    });
+                    i = title_end + 1;
+                }
+                Event::Start(Tag::DefinitionListDefinition) => {
+                    let definition_end =
+                        self.find_end_tag(events, i, &TagEnd::DefinitionListDefinition);
+
+                    let mut definition_state = ParserState::new(state.context);
+                    self.process_item_events(
+                        &events[i + 1..definition_end],
+                        &mut definition_state,
+                    )?;
+
+                    if items.is_empty() {
+                        items.push(DefinitionItem {
+                            term: Vec::new(),
+                            definitions: Vec::new(),
+                        });
+                    }
+                    if let Some(item) = items.last_mut() {
+                        item.definitions.push(definition_state.blocks);
+                    }
+                    i = definition_end + 1;
+                }
+                _ => i += 1,
+            }
+        }
+
+        state.blocks.push(Block::DefinitionList(items));
+
+        Ok(end + 1)
+    }
+
     /// Process a list item (called when inside a list)
     fn process_list_item(
         &self,
@@ -310,7 +548,7 @@ impl MarkdownDecoder {
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Item);
 
-        let mut item_state = ParserState::new();
+        let mut item_state = ParserState::new(state.context);
         self.process_events(&events[start + 1..end], &mut item_state)?;
 
         // Store blocks temporarily - they'll be collected by the parent list
@@ -389,7 +627,7 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::TableCell);
-        let inlines = self.collect_inlines(events, start + 1, end)?;
+        let inlines = self.collect_inlines(events, start + 1, end, state.context)?;
 
         state.current_row.push(TableCell::new(inlines));
 
@@ -404,7 +642,7 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Emphasis);
-        let inlines = self.collect_inlines(events, start + 1, end)?;
+        let inlines = self.collect_inlines(events, start + 1, end, state.context)?;
 
         state.push_inline(Inline::Italic(inlines));
 
@@ -419,7 +657,7 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Strong);
-        let inlines = self.collect_inlines(events, start + 1, end)?;
+        let inlines = self.collect_inlines(events, start + 1, end, state.context)?;
 
         state.push_inline(Inline::Bold(inlines));
 
@@ -434,7 +672,7 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Strikethrough);
-        let inlines = self.collect_inlines(events, start + 1, end)?;
+        let inlines = self.collect_inlines(events, start + 1, end, state.context)?;
 
         state.push_inline(Inline::Strikethrough(inlines));
 
@@ -450,7 +688,7 @@ impl MarkdownDecoder {
         state: &mut ParserState,
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::Link);
-        let children = self.collect_inlines(events, start + 1, end)?;
+        let children = self.collect_inlines(events, start + 1, end, state.context)?;
 
         state.push_inline(Inline::Link { children, url });
 
@@ -470,12 +708,21 @@ impl MarkdownDecoder {
 
         // Collect alt text from children if not provided
         let alt_text = if alt.is_empty() {
-            let inlines = self.collect_inlines(events, start + 1, end)?;
+            let inlines = self.collect_inlines(events, start + 1, end, state.context)?;
             inlines_to_text(&inlines)
         } else {
             alt
         };
 
+        // Close off any text that preceded the image
+        let preceding = std::mem::take(&mut state.inlines);
+        if preceding
+            .iter()
+            .any(|i| !matches!(i, Inline::Text(t) if t.trim().is_empty()))
+        {
+            state.blocks.push(Block::Paragraph(preceding));
+        }
+
         state.blocks.push(Block::Image {
             resource_key: src,
             caption: None,
@@ -495,7 +742,7 @@ impl MarkdownDecoder {
     ) -> Result<usize, ParseError> {
         let end = self.find_end_tag(events, start, &TagEnd::FootnoteDefinition);
 
-        let mut inner_state = ParserState::new();
+        let mut inner_state = ParserState::new(state.context);
         self.process_events(&events[start + 1..end], &mut inner_state)?;
 
         state.blocks.push(Block::Footnote {
@@ -506,20 +753,84 @@ impl MarkdownDecoder {
         Ok(end + 1)
     }
 
+    /// Process a block of raw HTML
+    fn process_html_block(
+        &self,
+        events: &[Event],
+        start: usize,
+        state: &mut ParserState,
+    ) -> Result<usize, ParseError> {
+        let end = self.find_end_tag(events, start, &TagEnd::HtmlBlock);
+
+        let html: String = events[start + 1..end]
+            .iter()
+            .filter_map(|event| match event {
+                Event::Html(html) | Event::Text(html) => Some(html.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let converter = HtmlConverter::new(self.strict_html).reporting_to(state.context, None);
+        state.blocks.extend(converter.parse_fragment(&html)?);
+
+        Ok(end + 1)
+    }
+
+    /// Process an inline HTML tag, along with the Markdown up to its closing tag
+    fn process_inline_html(
+        &self,
+        events: &[Event],
+        start: usize,
+        tag: &str,
+        state: &mut ParserState,
+    ) -> Result<usize, ParseError> {
+        let converter = HtmlConverter::new(self.strict_html).reporting_to(state.context, None);
+
+        // Render the Markdown between the tags, so `<sup>*a*</sup>` keeps its emphasis
+        let (html, next) = match find_closing_html(events, start, tag) {
+            Some(close) => {
+                let mut html = tag.to_string();
+                pulldown_cmark::html::push_html(
+                    &mut html,
+                    events[start + 1..=close].iter().cloned(),
+                );
+                (html, close + 1)
+            }
+            None => (tag.to_string(), start + 1),
+        };
+
+        for inline in converter.parse_inlines(&html)? {
+            state.push_inline(inline);
+        }
+
+        Ok(next)
+    }
+
     /// Collect inline elements from events
     fn collect_inlines(
         &self,
         events: &[Event],
         start: usize,
         end: usize,
+        context: &DecodeContext,
     ) -> Result<Vec<Inline>, ParseError> {
-        let mut state = ParserState::new();
+        let mut state = ParserState::new(context);
         self.process_events(&events[start..end], &mut state)?;
         Ok(state.inlines)
     }
 
+    /// Split front matter from a document and parse its body
+    pub(crate) fn parse_document(
+        &self,
+        content: &str,
+        context: &DecodeContext,
+    ) -> Result<(Option<FrontMatter>, Vec<Block>), ParseError> {
+        let (front_matter, body) = front_matter::extract(content, context);
+        Ok((front_matter, self.parse_markdown(body, context)?))
+    }
+
     /// Extract title from content (first H1)
-    fn extract_title(blocks: &[Block]) -> Option<String> {
+    pub(crate) fn extract_title(blocks: &[Block]) -> Option<String> {
         for block in blocks {
             if let Block::Header {
                 level: 1, content, ..
@@ -530,46 +841,6 @@ impl MarkdownDecoder {
         }
         None
     }
-
-    /// Split blocks into chapters by H1 headings
-    fn split_into_chapters(blocks: Vec<Block>) -> Vec<Chapter> {
-        let mut chapters = Vec::new();
-        let mut current_blocks = Vec::new();
-        let mut current_title: Option<String> = None;
-
-        for block in blocks {
-            if let Block::Header {
-                level: 1, content, ..
-            } = &block
-            {
-                // Save previous chapter if exists
-                if !current_blocks.is_empty() || current_title.is_some() {
-                    let title = current_title
-                        .take()
-                        .unwrap_or_else(|| "Untitled".to_string());
-                    chapters.push(Chapter::new(title).with_content(current_blocks));
-                    current_blocks = Vec::new();
-                }
-                current_title = Some(inlines_to_text(content));
-                current_blocks.push(block);
-            } else {
-                current_blocks.push(block);
-            }
-        }
-
-        // Don't forget the last chapter
-        if !current_blocks.is_empty() || current_title.is_some() {
-            let title = current_title.unwrap_or_else(|| "Untitled".to_string());
-            chapters.push(Chapter::new(title).with_content(current_blocks));
-        }
-
-        // If no chapters, create a single chapter
-        if chapters.is_empty() {
-            chapters.push(Chapter::new("Content").with_content(vec![]));
-        }
-
-        chapters
-    }
 }
 
 impl Default for MarkdownDecoder {
@@ -580,24 +851,45 @@ impl Default for MarkdownDecoder {
 
 impl super::Decoder for MarkdownDecoder {
     fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
         // Read all content
         let mut content = String::new();
         reader
             .read_to_string(&mut content)
             .map_err(|e| ParseError::MalformedContent(format!("Failed to read markdown: {}", e)))?;
 
-        // Parse markdown
-        let blocks = self.parse_markdown(&content)?;
+        // Separate front matter from the document body and parse markdown
+        let (front_matter, mut blocks) = self.parse_document(&content, context)?;
 
         // Extract title from first H1
         let title = Self::extract_title(&blocks).unwrap_or_else(|| "Untitled".to_string());
 
-        // Create metadata
-        let metadata = Metadata::new(title, "en");
+        // Create metadata, letting front matter override what was inferred
+        let mut metadata = Metadata::new(title, "en");
+        if let Some(front_matter) = &front_matter {
+            front_matter.apply_to(&mut metadata);
+        }
+
+        // Load local images and the cover, pointing references at the stored resources
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        loader.load_images(&mut blocks);
+        if let Some(cover) = &metadata.cover_resource_key {
+            metadata.cover_resource_key = loader.load(cover).or(metadata.cover_resource_key);
+        }
+
         let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
 
-        // Split into chapters by H1 headings
-        let chapters = Self::split_into_chapters(blocks);
+        // Split into chapters (at H1 headings unless configured otherwise)
+        let chapters = self.splitter.split(blocks);
         for chapter in chapters {
             book.add_chapter(chapter);
         }
@@ -612,10 +904,17 @@ impl super::Decoder for MarkdownDecoder {
     fn supported_mime_types(&self) -> &[&str] {
         &["text/markdown", "text/x-markdown"]
     }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
 }
 
 /// Parser state for tracking context during parsing
-struct ParserState {
+struct ParserState<'a> {
+    /// Where problems with embedded HTML are reported
+    context: &'a DecodeContext,
     blocks: Vec<Block>,
     inlines: Vec<Inline>,
     // Table state
@@ -624,9 +923,10 @@ struct ParserState {
     current_row: Vec<TableCell>,
 }
 
-impl ParserState {
-    fn new() -> Self {
+impl<'a> ParserState<'a> {
+    fn new(context: &'a DecodeContext) -> Self {
         Self {
+            context,
             blocks: Vec::new(),
             inlines: Vec::new(),
             table_headers: Vec::new(),
@@ -644,10 +944,184 @@ impl ParserState {
     fn push_inline(&mut self, inline: Inline) {
         self.inlines.push(inline);
     }
+
+    /// Wrap pending inline content in a paragraph
+    fn flush_paragraph(&mut self) {
+        if !self.inlines.is_empty() {
+            self.blocks
+                .push(Block::Paragraph(std::mem::take(&mut self.inlines)));
+        }
+    }
+}
+
+/// Whether an event starts block-level content
+fn starts_block(event: &Event) -> bool {
+    match event {
+        Event::Start(tag) => matches!(
+            tag,
+            Tag::Paragraph
+                | Tag::Heading { .. }
+                | Tag::BlockQuote(_)
+                | Tag::CodeBlock(_)
+                | Tag::HtmlBlock
+                | Tag::List(_)
+                | Tag::Table(_)
+                | Tag::DefinitionList
+                | Tag::FootnoteDefinition(_)
+        ),
+        Event::Rule => true,
+        _ => false,
+    }
+}
+
+/// Part of a document containing `:::` fenced containers
+enum Segment {
+    /// Markdown outside any container
+    Markdown(String),
+    /// A container, with the attributes from its opening fence
+    Container { attributes: String, body: String },
+}
+
+/// Split top-level `:::` containers out of a document
+///
+/// Fences inside code blocks are ignored, and nested containers are left in the
+/// body of the outermost one. A container left open runs to the end of the document.
+fn split_containers(content: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut markdown = String::new();
+    let mut container: Option<(String, String)> = None;
+    let mut depth = 0;
+    let mut code_fence: Option<&str> = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+
+        if let Some(fence) = code_fence {
+            if trimmed.starts_with(fence) {
+                code_fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            code_fence = Some(&trimmed[..3]);
+        } else if let Some(attributes) = container_fence(trimmed) {
+            match (&mut container, attributes) {
+                (None, Some(attributes)) => {
+                    if !markdown.is_empty() {
+                        segments.push(Segment::Markdown(std::mem::take(&mut markdown)));
+                    }
+                    container = Some((attributes.to_string(), String::new()));
+                    depth = 1;
+                    continue;
+                }
+                (Some(_), Some(_)) => depth += 1,
+                (Some(_), None) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let (attributes, body) = container.take().unwrap();
+                        segments.push(Segment::Container { attributes, body });
+                        continue;
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+
+        match &mut container {
+            Some((_, body)) => body.push_str(line),
+            None => markdown.push_str(line),
+        }
+    }
+
+    if let Some((attributes, body)) = container {
+        segments.push(Segment::Container { attributes, body });
+    }
+    if !markdown.is_empty() {
+        segments.push(Segment::Markdown(markdown));
+    }
+
+    segments
+}
+
+/// For a `:::` fence line, the attributes of an opening fence, or `None` for a closing one
+fn container_fence(line: &str) -> Option<Option<&str>> {
+    if !line.starts_with(":::") {
+        return None;
+    }
+    let attributes = line.trim_matches(':').trim();
+    Some((!attributes.is_empty()).then_some(attributes))
+}
+
+/// Build a container block from fence attributes such as `{#id .class}` or `class`
+fn container_block(attributes: &str, content: Vec<Block>) -> Block {
+    let mut id = None;
+    let mut classes = Vec::new();
+
+    match attributes
+        .strip_prefix('{')
+        .and_then(|a| a.strip_suffix('}'))
+    {
+        Some(list) => {
+            for attribute in list.split_whitespace() {
+                if let Some(value) = attribute.strip_prefix('#') {
+                    id = Some(value.to_string());
+                } else if let Some(value) = attribute.strip_prefix('.') {
+                    classes.push(value.to_string());
+                }
+            }
+        }
+        None => classes.extend(attributes.split_whitespace().map(str::to_string)),
+    }
+
+    match classes
+        .iter()
+        .find_map(|class| AdmonitionKind::from_name(class))
+    {
+        Some(kind) if id.is_none() && classes.len() == 1 => Block::Admonition { kind, content },
+        _ => Block::Container {
+            id,
+            classes,
+            content,
+        },
+    }
+}
+
+/// Find the inline HTML event that closes the opening tag at `start`
+fn find_closing_html(events: &[Event], start: usize, tag: &str) -> Option<usize> {
+    let name = html_tag_name(tag.strip_prefix('<')?)?;
+    if tag.ends_with("/>") {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (i, event) in events.iter().enumerate().skip(start + 1) {
+        let Event::InlineHtml(html) = event else {
+            continue;
+        };
+        if let Some(closing) = html.strip_prefix("</") {
+            if html_tag_name(closing).is_some_and(|n| n.eq_ignore_ascii_case(name)) {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            }
+        } else if let Some(opening) = html.strip_prefix('<') {
+            if html_tag_name(opening).is_some_and(|n| n.eq_ignore_ascii_case(name)) {
+                depth += 1;
+            }
+        }
+    }
+    None
+}
+
+/// The element name at the start of a tag, after its `<` or `</`
+fn html_tag_name(tag: &str) -> Option<&str> {
+    let end = tag
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .unwrap_or(tag.len());
+    (end > 0 && tag.starts_with(|c: char| c.is_ascii_alphabetic())).then(|| &tag[..end])
 }
 
 /// Convert inline elements to plain text
-fn inlines_to_text(inlines: &[Inline]) -> String {
+pub(crate) fn inlines_to_text(inlines: &[Inline]) -> String {
     inlines
         .iter()
         .map(|i| match i {
@@ -663,6 +1137,7 @@ fn inlines_to_text(inlines: &[Inline]) -> String {
             Inline::FootnoteRef { id } => format!("[{}]", id),
             Inline::Ruby { base, .. } => base.clone(),
             Inline::Break => " ".to_string(),
+            Inline::Checkbox { .. } => String::new(),
         })
         .collect()
 }
@@ -677,7 +1152,9 @@ mod tests {
         let decoder = MarkdownDecoder::new();
         let markdown = "# Hello World\n\nThis is a paragraph.";
 
-        let blocks = decoder.parse_markdown(markdown).unwrap();
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
 
         assert_eq!(blocks.len(), 2);
         match &blocks[0] {
@@ -694,7 +1171,9 @@ mod tests {
         let decoder = MarkdownDecoder::new();
         let markdown = "This is **bold** and *italic* text.";
 
-        let blocks = decoder.parse_markdown(markdown).unwrap();
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
 
         assert_eq!(blocks.len(), 1);
         if let Block::Paragraph(inlines) = &blocks[0] {
@@ -710,7 +1189,9 @@ mod tests {
         let decoder = MarkdownDecoder::new();
         let markdown = "- Item 1\n- Item 2\n- Item 3";
 
-        let blocks = decoder.parse_markdown(markdown).unwrap();
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
 
         assert_eq!(blocks.len(), 1);
         if let Block::List { items, ordered } = &blocks[0] {
@@ -726,7 +1207,9 @@ mod tests {
         let decoder = MarkdownDecoder::new();
         let markdown = "```rust\nfn main() {}\n```";
 
-        let blocks = decoder.parse_markdown(markdown).unwrap();
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
 
         assert_eq!(blocks.len(), 1);
         if let Block::CodeBlock { lang, code } = &blocks[0] {
@@ -742,7 +1225,9 @@ mod tests {
         let decoder = MarkdownDecoder::new();
         let markdown = "Check out [this link](https://example.com).";
 
-        let blocks = decoder.parse_markdown(markdown).unwrap();
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
 
         if let Block::Paragraph(inlines) = &blocks[0] {
             let has_link = inlines
@@ -766,12 +1251,82 @@ mod tests {
         assert_eq!(book.chapters.len(), 2);
     }
 
+    #[test]
+    fn test_decode_front_matter() {
+        let decoder = MarkdownDecoder::new();
+        let markdown = "---\ntitle: Front Matter Title\nauthor: Jane Doe\nlang: de\n\
+                        subject: [Fiction, Mystery]\n---\n\n# Heading\n\nContent.";
+
+        let mut cursor = std::io::Cursor::new(markdown);
+        let book = decoder.decode(&mut cursor).unwrap();
+
+        assert_eq!(book.metadata.title, "Front Matter Title");
+        assert_eq!(book.metadata.creator, vec!["Jane Doe"]);
+        assert_eq!(book.metadata.language, "de");
+        assert_eq!(book.metadata.subject, vec!["Fiction", "Mystery"]);
+
+        // The front matter block must not leak into the content
+        let first = &book.chapters[0].content[0];
+        assert!(matches!(first, Block::Header { .. }));
+    }
+
+    #[test]
+    fn test_decode_invalid_front_matter() {
+        use crate::decoder::Decoder;
+
+        for markdown in [
+            "---\ntitle: [unclosed\n---\n\n# Heading\n\nContent.",
+            "+++\ntitle = unquoted\n+++\n\n# Heading\n\nContent.",
+        ] {
+            let context = DecodeContext::new();
+            let mut cursor = std::io::Cursor::new(markdown);
+            let book = MarkdownDecoder::new()
+                .decode_with_context(&mut cursor, &context)
+                .unwrap();
+
+            // The block is dropped rather than read as a break and a heading
+            assert_eq!(book.chapters.len(), 1);
+            assert_eq!(book.metadata.title, "Heading");
+            assert_eq!(context.diagnostics()[0].code, "invalid-front-matter");
+        }
+    }
+
+    #[test]
+    fn test_decode_loads_local_images() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("img")).unwrap();
+        std::fs::write(dir.path().join("img/fig1.png"), b"\x89PNG\r\n\x1a\nrest").unwrap();
+
+        let decoder = MarkdownDecoder::new();
+        let markdown = "# Book\n\n![Figure](img/fig1.png)\n\n![Gone](img/gone.png)";
+        let context = DecodeContext::new().with_base_path(dir.path());
+
+        let mut cursor = std::io::Cursor::new(markdown);
+        let book = decoder.decode_with_context(&mut cursor, &context).unwrap();
+
+        let keys: Vec<&str> = book.chapters[0]
+            .content
+            .iter()
+            .filter_map(|b| match b {
+                Block::Image { resource_key, .. } => Some(resource_key.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let resource = book.resources.get(keys[0]).unwrap();
+        assert_eq!(resource.mime_type, "image/png");
+        assert_eq!(keys[1], "img/gone.png");
+        assert_eq!(context.warnings(), vec!["Missing asset: img/gone.png"]);
+    }
+
     #[test]
     fn test_parse_table() {
         let decoder = MarkdownDecoder::new();
         let markdown = "| A | B |\n|---|---|\n| 1 | 2 |";
 
-        let blocks = decoder.parse_markdown(markdown).unwrap();
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
 
         assert_eq!(blocks.len(), 1);
         if let Block::Table(table) = &blocks[0] {
@@ -781,4 +1336,200 @@ mod tests {
             panic!("Expected table");
         }
     }
+
+    #[test]
+    fn test_parse_inline_html() {
+        let decoder = MarkdownDecoder::new();
+        let blocks = decoder
+            .parse_markdown(
+                "E = mc<sup>*2*</sup>, press <kbd>Ctrl</kbd><br>done",
+                &DecodeContext::new(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![Block::Paragraph(vec![
+                Inline::text("E = mc"),
+                Inline::Superscript(vec![Inline::Italic(vec![Inline::text("2")])]),
+                Inline::text(", press "),
+                Inline::Code("Ctrl".into()),
+                Inline::Break,
+                Inline::text("done"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_parse_html_block() {
+        let decoder = MarkdownDecoder::new();
+        let markdown = "Before\n\n<figure>\n<img src=\"map.png\" alt=\"Map\">\n<figcaption>The map</figcaption>\n</figure>\n\nAfter";
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[1],
+            Block::Image {
+                resource_key: "map.png".into(),
+                caption: Some("The map".into()),
+                alt: "Map".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_reports_unknown_html_elements() {
+        let context = DecodeContext::new();
+        MarkdownDecoder::new()
+            .parse_markdown(
+                "A <blink>loud</blink> word\n\n<div><marquee>Hi</marquee></div>\n",
+                &context,
+            )
+            .unwrap();
+
+        let messages: Vec<String> = context
+            .diagnostics()
+            .into_iter()
+            .filter(|d| d.code == "unknown-element")
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["Kept <blink> as plain text", "Kept <marquee> as plain text"]
+        );
+    }
+
+    #[test]
+    fn test_strict_html() {
+        let markdown = "<div>Text<script>alert(1)</script></div>\n\n<iframe src=\"https://example.com\"></iframe>\n";
+
+        let blocks = MarkdownDecoder::new()
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
+        assert_eq!(blocks, vec![Block::Paragraph(vec![Inline::text("Text")])]);
+
+        let blocks = MarkdownDecoder::new()
+            .with_strict_html(false)
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
+        assert_eq!(blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_task_list() {
+        let decoder = MarkdownDecoder::new().with_task_lists(true);
+        let blocks = decoder
+            .parse_markdown("- [x] Done\n- [ ] Todo", &DecodeContext::new())
+            .unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![Block::List {
+                items: vec![
+                    vec![Block::Paragraph(vec![
+                        Inline::Checkbox { checked: true },
+                        Inline::text("Done"),
+                    ])],
+                    vec![Block::Paragraph(vec![
+                        Inline::Checkbox { checked: false },
+                        Inline::text("Todo"),
+                    ])],
+                ],
+                ordered: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_definition_list() {
+        let decoder = MarkdownDecoder::new().with_definition_lists(true);
+        let blocks = decoder
+            .parse_markdown(
+                "Apple\n: A fruit\n: A company\n\nRust\n: A language",
+                &DecodeContext::new(),
+            )
+            .unwrap();
+
+        let [Block::DefinitionList(items)] = &blocks[..] else {
+            panic!("expected a definition list, got {:?}", blocks);
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].term, vec![Inline::text("Apple")]);
+        assert_eq!(items[0].definitions.len(), 2);
+        assert_eq!(
+            items[1].definitions[0],
+            vec![Block::Paragraph(vec![Inline::text("A language")])]
+        );
+    }
+
+    #[test]
+    fn test_parse_admonition() {
+        let markdown = "> [!WARNING]\n> Hot surface";
+
+        let blocks = MarkdownDecoder::new()
+            .with_admonitions(true)
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
+        assert_eq!(
+            blocks,
+            vec![Block::Admonition {
+                kind: AdmonitionKind::Warning,
+                content: vec![Block::Paragraph(vec![Inline::text("Hot surface")])],
+            }]
+        );
+
+        // Without the extension it stays a blockquote
+        let blocks = MarkdownDecoder::new()
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
+        assert!(matches!(blocks[0], Block::Blockquote(_)));
+    }
+
+    #[test]
+    fn test_smart_punctuation() {
+        let decoder = MarkdownDecoder::new().with_smart_punctuation(true);
+        let blocks = decoder
+            .parse_markdown("\"Wait\" -- it's...", &DecodeContext::new())
+            .unwrap();
+
+        let Block::Paragraph(content) = &blocks[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            inlines_to_text(content),
+            "\u{201c}Wait\u{201d} \u{2013} it\u{2019}s\u{2026}"
+        );
+    }
+
+    #[test]
+    fn test_parse_containers() {
+        let decoder = MarkdownDecoder::new().with_containers(true);
+        let markdown = "# Title {#intro .lead}\n\n::: {#box .aside}\nInside\n\n::: tip\nNested\n:::\n:::\n\n```\n:::\n```\n";
+        let blocks = decoder
+            .parse_markdown(markdown, &DecodeContext::new())
+            .unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(
+            &blocks[0],
+            Block::Header { anchor: Some(anchor), .. } if anchor == "intro"
+        ));
+        assert_eq!(
+            blocks[1],
+            Block::Container {
+                id: Some("box".into()),
+                classes: vec!["aside".into()],
+                content: vec![
+                    Block::Paragraph(vec![Inline::text("Inside")]),
+                    Block::Admonition {
+                        kind: AdmonitionKind::Tip,
+                        content: vec![Block::Paragraph(vec![Inline::text("Nested")])],
+                    },
+                ],
+            }
+        );
+        assert!(matches!(&blocks[2], Block::CodeBlock { code, .. } if code.trim() == ":::"));
+    }
 }
@@ -0,0 +1,74 @@
+//! Helpers shared by the lightweight markup decoders: AsciiDoc,
+//! reStructuredText and Org
+
+/// How deeply includes may nest, which also stops include cycles
+pub(crate) const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Remove the indentation shared by every non-blank line
+pub(crate) fn dedent(lines: &[String]) -> Vec<String> {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or_default().to_string())
+        .collect()
+}
+
+/// A standalone URL with one of `schemes` at the start of `rest`, ending at
+/// whitespace or one of `stops`, without trailing punctuation
+pub(crate) fn bare_url<'a>(rest: &'a str, schemes: &[&str], stops: &[char]) -> Option<&'a str> {
+    if !schemes.iter().any(|scheme| rest.starts_with(scheme)) {
+        return None;
+    }
+    let end = rest
+        .find(|c: char| c.is_whitespace() || stops.contains(&c))
+        .unwrap_or(rest.len());
+    let url = rest[..end].trim_end_matches(['.', ',', ';', ':', ')', '!', '?', '"', '\'']);
+    (url.len() > url.find(':')? + 3).then_some(url)
+}
+
+/// Alternative text for an image without any, from its file name
+pub(crate) fn default_alt(target: &str) -> String {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem.replace(['-', '_'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedent() {
+        let lines = ["    a".to_string(), String::new(), "      b".to_string()];
+        assert_eq!(dedent(&lines), vec!["a", "", "  b"]);
+    }
+
+    #[test]
+    fn test_bare_url() {
+        let schemes = ["https://", "mailto:"];
+        assert_eq!(
+            bare_url("https://example.com/a. Next", &schemes, &[]),
+            Some("https://example.com/a")
+        );
+        assert_eq!(
+            bare_url("https://example.com[text]", &schemes, &['[']),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            bare_url("mailto:ada@example.com", &schemes, &[]),
+            Some("mailto:ada@example.com")
+        );
+        assert_eq!(bare_url("https://", &schemes, &[]), None);
+        assert_eq!(bare_url("ftp://example.com", &schemes, &[]), None);
+    }
+
+    #[test]
+    fn test_default_alt() {
+        assert_eq!(default_alt("img/big_red-dog.png"), "big red dog");
+    }
+}
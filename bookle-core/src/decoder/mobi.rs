@@ -1,7 +1,10 @@
 //! MOBI/AZW decoder implementation
 
+use super::chapters::{ChapterSplitter, SplitOptions, SplitStrategy};
+use super::html::HtmlConverter;
+use super::DecodeContext;
 use crate::error::ParseError;
-use crate::types::{Block, Book, Chapter, Inline, Metadata};
+use crate::types::{Block, Book, Metadata};
 use mobi::Mobi;
 use std::io::Read;
 
@@ -11,12 +14,15 @@ use std::io::Read;
 pub struct MobiDecoder {
     /// Whether to sanitize HTML strictly
     strict_sanitization: bool,
+    /// Where the book is divided into chapters
+    splitter: ChapterSplitter,
 }
 
 impl MobiDecoder {
     pub fn new() -> Self {
         Self {
             strict_sanitization: true,
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(2)),
         }
     }
 
@@ -26,288 +32,52 @@ impl MobiDecoder {
         self
     }
 
-    /// Parse HTML content into Block AST (reusing logic from EPUB decoder approach)
-    fn parse_html_to_blocks(&self, html: &str) -> Result<Vec<Block>, ParseError> {
-        use scraper::{Html, Selector};
-
-        let document = Html::parse_document(html);
-
-        // Select body content (or root if no body)
-        let body_selector = Selector::parse("body").unwrap();
-        let root = document
-            .select(&body_selector)
-            .next()
-            .map(|el| el.inner_html())
-            .unwrap_or_else(|| html.to_string());
-
-        // Re-parse the body content
-        let fragment = Html::parse_fragment(&root);
-        let mut blocks = Vec::new();
-
-        // Process top-level elements
-        for element in fragment.root_element().children() {
-            if let Some(el) = element.value().as_element() {
-                if let Some(block) = self.element_to_block(el, &fragment, element)? {
-                    blocks.push(block);
-                }
-            }
-        }
-
-        Ok(blocks)
+    /// Set where the book is divided into chapters (H1 and H2 headings by default)
+    ///
+    /// [`SplitStrategy::PageBreak`] splits at `<mbp:pagebreak>` markers.
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
     }
 
-    /// Convert an HTML element to a Block
-    fn element_to_block(
-        &self,
-        element: &scraper::node::Element,
-        _document: &scraper::Html,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Option<Block>, ParseError> {
-        let tag = element.name();
-
-        match tag {
-            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-                let level = tag[1..].parse::<u8>().unwrap_or(1);
-                let content = self.children_to_inlines(node)?;
-                let anchor = element.attr("id").map(|s| s.to_string());
-                Ok(Some(Block::Header {
-                    level,
-                    content,
-                    anchor,
-                }))
-            }
-            "p" => {
-                let content = self.children_to_inlines(node)?;
-                if content.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(Block::Paragraph(content)))
-                }
-            }
-            "ul" | "ol" => {
-                let ordered = tag == "ol";
-                let mut items = Vec::new();
-
-                for child in node.children() {
-                    if let Some(el) = child.value().as_element() {
-                        if el.name() == "li" {
-                            let item_blocks = self.li_to_blocks(child)?;
-                            items.push(item_blocks);
-                        }
-                    }
-                }
-
-                Ok(Some(Block::List { items, ordered }))
-            }
-            "blockquote" => {
-                let inner_blocks = self.children_to_blocks(node)?;
-                Ok(Some(Block::Blockquote(inner_blocks)))
-            }
-            "pre" => {
-                let code = self.get_text_content(node);
-                let lang = None;
-                Ok(Some(Block::CodeBlock { lang, code }))
-            }
-            "hr" => Ok(Some(Block::ThematicBreak)),
-            "div" | "section" | "article" => {
-                // Container elements - recurse into children
-                let inner = self.children_to_blocks(node)?;
-                if inner.len() == 1 {
-                    Ok(Some(inner.into_iter().next().unwrap()))
-                } else if inner.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(Block::Blockquote(inner)))
-                }
-            }
-            "img" => {
-                let src = element.attr("src").unwrap_or_default().to_string();
-                let alt = element.attr("alt").unwrap_or_default().to_string();
-                Ok(Some(Block::Image {
-                    resource_key: src,
-                    caption: None,
-                    alt,
-                }))
-            }
-            _ => {
-                // Unknown block element - try to extract as paragraph
-                let content = self.children_to_inlines(node)?;
-                if content.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(Block::Paragraph(content)))
-                }
-            }
-        }
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
     }
 
-    /// Convert children to blocks
-    fn children_to_blocks(
+    /// Parse HTML content into Block AST
+    ///
+    /// Also returns the indices of blocks that follow an `<mbp:pagebreak>`.
+    fn parse_html_to_blocks(
         &self,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Block>, ParseError> {
+        html: &str,
+        context: &DecodeContext,
+    ) -> Result<(Vec<Block>, Vec<usize>), ParseError> {
+        let converter = HtmlConverter::new(self.strict_sanitization).reporting_to(context, None);
+        let fragment = HtmlConverter::body_fragment(html);
         let mut blocks = Vec::new();
-        for child in node.children() {
-            if let Some(el) = child.value().as_element() {
-                if let Some(block) =
-                    self.element_to_block(el, &scraper::Html::new_document(), child)?
-                {
-                    blocks.push(block);
-                }
-            }
-        }
-        Ok(blocks)
-    }
+        let mut page_breaks = Vec::new();
 
-    /// Convert list item to blocks
-    fn li_to_blocks(
-        &self,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Block>, ParseError> {
-        let has_blocks = node.children().any(|c| {
-            c.value()
+        // Process top-level elements
+        for element in fragment.root_element().children() {
+            // Page breaks aren't void elements to an HTML parser, so the content
+            // that follows one ends up inside it
+            if element
+                .value()
                 .as_element()
-                .map(|e| matches!(e.name(), "p" | "ul" | "ol" | "blockquote" | "pre"))
-                .unwrap_or(false)
-        });
-
-        if has_blocks {
-            self.children_to_blocks(node)
-        } else {
-            let inlines = self.children_to_inlines(node)?;
-            if inlines.is_empty() {
-                Ok(vec![])
+                .is_some_and(|el| el.name() == "mbp:pagebreak")
+            {
+                page_breaks.push(blocks.len());
+                blocks.extend(converter.children_to_blocks(element)?);
             } else {
-                Ok(vec![Block::Paragraph(inlines)])
-            }
-        }
-    }
-
-    /// Convert children to inline elements
-    fn children_to_inlines(
-        &self,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Inline>, ParseError> {
-        let mut inlines = Vec::new();
-
-        for child in node.children() {
-            match child.value() {
-                scraper::Node::Text(text) => {
-                    let s = text.trim();
-                    if !s.is_empty() {
-                        inlines.push(Inline::Text(s.to_string()));
-                    }
-                }
-                scraper::Node::Element(el) => {
-                    let inline = self.element_to_inline(el, child)?;
-                    inlines.extend(inline);
-                }
-                _ => {}
+                blocks.extend(converter.element_to_blocks(element)?);
             }
         }
 
-        Ok(inlines)
-    }
-
-    /// Convert an HTML element to inline elements
-    fn element_to_inline(
-        &self,
-        element: &scraper::node::Element,
-        node: ego_tree::NodeRef<scraper::Node>,
-    ) -> Result<Vec<Inline>, ParseError> {
-        let tag = element.name();
-
-        match tag {
-            "b" | "strong" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Bold(children)])
-            }
-            "i" | "em" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Italic(children)])
-            }
-            "code" => {
-                let text = self.get_text_content(node);
-                Ok(vec![Inline::Code(text)])
-            }
-            "a" => {
-                let children = self.children_to_inlines(node)?;
-                let url = element.attr("href").unwrap_or("#").to_string();
-                Ok(vec![Inline::Link { children, url }])
-            }
-            "sup" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Superscript(children)])
-            }
-            "sub" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Subscript(children)])
-            }
-            "s" | "strike" | "del" => {
-                let children = self.children_to_inlines(node)?;
-                Ok(vec![Inline::Strikethrough(children)])
-            }
-            "br" => Ok(vec![Inline::Break]),
-            "span" => self.children_to_inlines(node),
-            _ => {
-                let text = self.get_text_content(node);
-                if text.is_empty() {
-                    Ok(vec![])
-                } else {
-                    Ok(vec![Inline::Text(text)])
-                }
-            }
-        }
-    }
-
-    /// Get text content of a node
-    fn get_text_content(&self, node: ego_tree::NodeRef<scraper::Node>) -> String {
-        let mut text = String::new();
-        for descendant in node.descendants() {
-            if let scraper::Node::Text(t) = descendant.value() {
-                text.push_str(t);
-            }
-        }
-        text
-    }
-
-    /// Split content into chapters based on headers
-    fn split_into_chapters(blocks: Vec<Block>) -> Vec<Chapter> {
-        let mut chapters = Vec::new();
-        let mut current_blocks = Vec::new();
-        let mut current_title: Option<String> = None;
-
-        for block in blocks {
-            let is_chapter_heading = matches!(&block, Block::Header { level, .. } if *level <= 2);
-
-            if is_chapter_heading {
-                if !current_blocks.is_empty() || current_title.is_some() {
-                    let title = current_title
-                        .take()
-                        .unwrap_or_else(|| "Untitled".to_string());
-                    chapters.push(Chapter::new(title).with_content(current_blocks));
-                    current_blocks = Vec::new();
-                }
-
-                if let Block::Header { content, .. } = &block {
-                    current_title = Some(inlines_to_text(content));
-                }
-                current_blocks.push(block);
-            } else {
-                current_blocks.push(block);
-            }
-        }
-
-        if !current_blocks.is_empty() || current_title.is_some() {
-            let title = current_title.unwrap_or_else(|| "Untitled".to_string());
-            chapters.push(Chapter::new(title).with_content(current_blocks));
-        }
-
-        if chapters.is_empty() {
-            chapters.push(Chapter::new("Content").with_content(vec![]));
-        }
-
-        chapters
+        converter.report_unknown_elements();
+        page_breaks.dedup();
+        Ok((blocks, page_breaks))
     }
 }
 
@@ -319,6 +89,14 @@ impl Default for MobiDecoder {
 
 impl super::Decoder for MobiDecoder {
     fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
         // Read all data into memory
         let mut data = Vec::new();
         reader
@@ -347,10 +125,10 @@ impl super::Decoder for MobiDecoder {
         let content = mobi.content_as_string_lossy();
 
         // Parse the HTML content
-        let blocks = self.parse_html_to_blocks(&content)?;
+        let (blocks, page_breaks) = self.parse_html_to_blocks(&content, context)?;
 
         // Split into chapters
-        let chapters = Self::split_into_chapters(blocks);
+        let chapters = self.splitter.split_with_page_breaks(blocks, &page_breaks);
         for chapter in chapters {
             book.add_chapter(chapter);
         }
@@ -359,7 +137,7 @@ impl super::Decoder for MobiDecoder {
     }
 
     fn supported_extensions(&self) -> &[&str] {
-        &["mobi", "azw", "azw3", "prc"]
+        &["mobi", "azw", "azw3"]
     }
 
     fn supported_mime_types(&self) -> &[&str] {
@@ -368,27 +146,11 @@ impl super::Decoder for MobiDecoder {
             "application/vnd.amazon.ebook",
         ]
     }
-}
 
-/// Convert inline elements to plain text
-fn inlines_to_text(inlines: &[Inline]) -> String {
-    inlines
-        .iter()
-        .map(|i| match i {
-            Inline::Text(s) => s.clone(),
-            Inline::Bold(children) | Inline::Italic(children) | Inline::Strikethrough(children) => {
-                inlines_to_text(children)
-            }
-            Inline::Link { children, .. } => inlines_to_text(children),
-            Inline::Code(s) => s.clone(),
-            Inline::Superscript(children) | Inline::Subscript(children) => {
-                inlines_to_text(children)
-            }
-            Inline::FootnoteRef { id } => format!("[{}]", id),
-            Inline::Ruby { base, .. } => base.clone(),
-            Inline::Break => " ".to_string(),
-        })
-        .collect()
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
 }
 
 #[cfg(test)]
@@ -405,7 +167,9 @@ mod tests {
             </body>
         "#;
 
-        let blocks = decoder.parse_html_to_blocks(html).unwrap();
+        let (blocks, _) = decoder
+            .parse_html_to_blocks(html, &DecodeContext::new())
+            .unwrap();
         assert_eq!(blocks.len(), 2);
 
         match &blocks[0] {
@@ -427,7 +191,9 @@ mod tests {
             </ul>
         "#;
 
-        let blocks = decoder.parse_html_to_blocks(html).unwrap();
+        let (blocks, _) = decoder
+            .parse_html_to_blocks(html, &DecodeContext::new())
+            .unwrap();
         assert_eq!(blocks.len(), 1);
 
         if let Block::List { items, ordered } = &blocks[0] {
@@ -437,4 +203,33 @@ mod tests {
             panic!("Expected list");
         }
     }
+
+    #[test]
+    fn test_parse_page_breaks() {
+        let decoder = MobiDecoder::new();
+        let html = "<body><p>One</p><mbp:pagebreak/><p>Two</p><p>Three</p></body>";
+
+        let (blocks, page_breaks) = decoder
+            .parse_html_to_blocks(html, &DecodeContext::new())
+            .unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(page_breaks, vec![1]);
+
+        let chapters = ChapterSplitter::new(SplitStrategy::PageBreak)
+            .split_with_page_breaks(blocks, &page_breaks);
+        assert_eq!(chapters.len(), 2);
+    }
+
+    #[test]
+    fn test_reports_unknown_elements() {
+        let context = DecodeContext::new();
+        MobiDecoder::new()
+            .parse_html_to_blocks("<p>A <blink>loud</blink> word</p>", &context)
+            .unwrap();
+
+        let diagnostics = context.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "unknown-element");
+        assert_eq!(diagnostics[0].message, "Kept <blink> as plain text");
+    }
 }
@@ -1,62 +1,126 @@
 //! Decoders for converting input formats to the IR
 
+mod asciidoc;
+mod cbz;
+mod chapters;
+mod context;
+mod detect;
+mod docbook;
+mod docx;
 mod epub;
+mod fb2;
+mod front_matter;
+mod html;
 mod kepub;
 mod lit;
 mod markdown;
+mod markup;
 mod mobi;
+mod odt;
+mod options;
+mod org;
+mod pdb;
 mod pdf;
+mod project;
+mod readability;
+mod rst;
+mod rtf;
+mod tei;
+mod text;
+mod xml;
 
+pub use asciidoc::AsciiDocDecoder;
+pub use cbz::CbzDecoder;
+pub use chapters::{ChapterSplitter, SplitOptions, SplitStrategy};
+pub(crate) use context::rewrite_images;
+pub use context::DecodeContext;
+pub(crate) use detect::read_head;
+pub use detect::{decoder_for_bytes, detect_format, detect_format_in, Format, HEAD_LEN};
+pub use docbook::DocBookDecoder;
+pub use docx::DocxDecoder;
 pub use epub::EpubDecoder;
+pub use fb2::Fb2Decoder;
+pub use html::HtmlDecoder;
 pub use kepub::KepubDecoder;
 pub use lit::LitDecoder;
 pub use markdown::MarkdownDecoder;
 pub use mobi::MobiDecoder;
+pub use odt::OdtDecoder;
+pub use options::DecodeOptions;
+pub use org::OrgDecoder;
+pub use pdb::PdbDecoder;
 pub use pdf::PdfDecoder;
+pub use project::MarkdownProjectDecoder;
+pub use rst::RstDecoder;
+pub use rtf::RtfDecoder;
+pub use tei::TeiDecoder;
+pub use text::TextDecoder;
+pub use xml::XmlDecoder;
 
 use crate::error::ParseError;
+use crate::registry::FormatRegistry;
 use crate::types::Book;
 use std::io::Read;
+use std::path::Path;
 
 /// Trait for decoding ebook formats into the IR
 pub trait Decoder: Send + Sync {
     /// Decode a book from a reader
     fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError>;
 
+    /// Decode a book, loading assets referenced by the document through `context`
+    ///
+    /// Formats that embed all of their assets ignore the context.
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let _ = context;
+        self.decode(reader)
+    }
+
     /// File extensions this decoder supports
     fn supported_extensions(&self) -> &[&str];
 
     /// MIME types this decoder supports
     fn supported_mime_types(&self) -> &[&str];
+
+    /// Override where chapters are split, returning whether the decoder supports it
+    ///
+    /// Decoders that cut a flat block stream into chapters with a
+    /// [`ChapterSplitter`] apply the options. Formats whose chapters come from
+    /// the document's own structure, such as EPUB spine items, are left unchanged.
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        let _ = options;
+        false
+    }
+
+    /// Apply format-specific options, returning whether the decoder supports
+    /// every option that is set
+    ///
+    /// Options that are not set leave the decoder unchanged. Most formats
+    /// support none of them.
+    fn set_decode_options(&mut self, options: &DecodeOptions) -> bool {
+        options.is_empty()
+    }
 }
 
 /// Get the appropriate decoder for a file extension
+///
+/// Looks the extension up in the built-in [`FormatRegistry`].
 pub fn decoder_for_extension(ext: &str) -> Option<Box<dyn Decoder>> {
-    // Handle compound extensions like "kepub.epub"
-    let ext_lower = ext.to_lowercase();
-
-    match ext_lower.as_str() {
-        "kepub.epub" | "kepub" => Some(Box::new(KepubDecoder::new())),
-        "epub" => Some(Box::new(EpubDecoder::new())),
-        "lit" => Some(Box::new(LitDecoder::new())),
-        "md" | "markdown" | "mdown" | "mkd" => Some(Box::new(MarkdownDecoder::new())),
-        "pdf" => Some(Box::new(PdfDecoder::new())),
-        "mobi" | "azw" | "azw3" | "prc" => Some(Box::new(MobiDecoder::new())),
-        _ => None,
-    }
+    FormatRegistry::builtin().decoder_for_extension(ext)
+}
+
+/// Get the extension of an input file for [`decoder_for_extension`]
+///
+/// Compound extensions such as `.fb2.zip` are returned whole.
+pub fn input_extension(path: &Path) -> Option<String> {
+    FormatRegistry::builtin().input_extension(path)
 }
 
 /// Get the appropriate decoder for a MIME type
 pub fn decoder_for_mime_type(mime: &str) -> Option<Box<dyn Decoder>> {
-    match mime {
-        "application/x-kobo-epub+zip" => Some(Box::new(KepubDecoder::new())),
-        "application/epub+zip" => Some(Box::new(EpubDecoder::new())),
-        "application/x-ms-reader" | "application/x-ms-lit" => Some(Box::new(LitDecoder::new())),
-        "text/markdown" | "text/x-markdown" => Some(Box::new(MarkdownDecoder::new())),
-        "application/pdf" => Some(Box::new(PdfDecoder::new())),
-        "application/x-mobipocket-ebook" | "application/vnd.amazon.ebook" => {
-            Some(Box::new(MobiDecoder::new()))
-        }
-        _ => None,
-    }
+    FormatRegistry::builtin().decoder_for_mime_type(mime)
 }
@@ -0,0 +1,1084 @@
+//! ODT decoder implementation
+//!
+//! An OpenDocument Text file is a ZIP package. The text lives in `content.xml`,
+//! named styles in `styles.xml` and document properties in `meta.xml`. Most
+//! character formatting is carried by automatic styles (`T1`, `P2`, ...) that
+//! inherit from named styles, so styles are resolved through their parents
+//! before spans are mapped to inline formatting.
+
+use super::chapters::place_footnotes;
+use super::context::sniff_mime_type;
+use super::front_matter::parse_date;
+use super::markdown::inlines_to_text;
+use super::xml::{self, trim_inlines};
+use super::{ChapterSplitter, SplitOptions, SplitStrategy};
+use crate::error::ParseError;
+use crate::types::{Block, Book, Inline, Metadata, Resource, ResourceStore, TableCell, TableData};
+use roxmltree::Node;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+/// Decoder for OpenDocument Text (ODT)
+pub struct OdtDecoder {
+    splitter: ChapterSplitter,
+}
+
+impl OdtDecoder {
+    pub fn new() -> Self {
+        Self {
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Choose where chapters begin (by default, at level 1 headings)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+}
+
+impl Default for OdtDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for OdtDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read ODT: {}", e)))?;
+
+        let archive = zip::ZipArchive::new(Cursor::new(data))
+            .map_err(|e| ParseError::MalformedContent(format!("Invalid ODT package: {}", e)))?;
+        let package = RefCell::new(archive);
+        let read = |path: &str| -> Option<Vec<u8>> {
+            let mut package = package.borrow_mut();
+            let mut file = package.by_name(path).ok()?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).ok()?;
+            Some(data)
+        };
+
+        let content_text = read("content.xml")
+            .map(|data| xml::decode_xml(&data))
+            .ok_or_else(|| ParseError::MissingField("content.xml".to_string()))?;
+        let content = xml::parse(&content_text)?;
+        let styles_text = read("styles.xml").map(|data| xml::decode_xml(&data));
+        let styles_document = styles_text.as_deref().map(xml::parse).transpose()?;
+
+        // Named styles first, so that automatic styles can override them
+        let mut styles = Styles::default();
+        if let Some(document) = &styles_document {
+            styles.add(document.root_element());
+        }
+        styles.add(content.root_element());
+
+        let mut converter = Converter {
+            styles,
+            read: &read,
+            resources: RefCell::new(ResourceStore::new()),
+            images: RefCell::new(HashMap::new()),
+            footnotes: RefCell::new(Vec::new()),
+            title: RefCell::new(None),
+            notes: RefCell::new(0),
+        };
+
+        let text = xml::child(content.root_element(), "body")
+            .and_then(|body| xml::child(body, "text"))
+            .ok_or_else(|| ParseError::MissingField("office:text".to_string()))?;
+        let mut output = Output::default();
+        converter.blocks(text, &mut output);
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        if let Some(data) = read("meta.xml") {
+            let text = xml::decode_xml(&data);
+            let meta = xml::parse(&text)?;
+            if let Some(meta) = xml::child(meta.root_element(), "meta") {
+                apply_meta(meta, &mut metadata);
+            }
+        }
+        if metadata.title == "Untitled" {
+            if let Some(title) = converter.title.take() {
+                metadata.title = title;
+            }
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.chapters = self
+            .splitter
+            .split_with_page_breaks(output.blocks, &output.page_breaks);
+        place_footnotes(&mut book.chapters, converter.footnotes.take());
+        book.resources = std::mem::take(converter.resources.get_mut());
+
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["odt"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/vnd.oasis.opendocument.text"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// Fill in metadata from `<office:meta>`
+fn apply_meta(meta: Node, metadata: &mut Metadata) {
+    let value = |name| {
+        xml::child(meta, name)
+            .map(xml::text)
+            .filter(|value| !value.is_empty())
+    };
+
+    if let Some(title) = value("title") {
+        metadata.title = title;
+    }
+    if let Some(creator) = value("creator").or_else(|| value("initial-creator")) {
+        metadata.creator = vec![creator];
+    }
+    metadata.subject = value("subject").into_iter().collect();
+    metadata.subject.extend(
+        xml::children(meta, "keyword")
+            .map(xml::text)
+            .filter(|keyword| !keyword.is_empty()),
+    );
+    metadata.description = value("description");
+    if let Some(language) = value("language") {
+        metadata.language = language;
+    }
+    metadata.date = value("creation-date")
+        .or_else(|| value("date"))
+        .and_then(|date| parse_date(&date).or_else(|| parse_date(date.get(..10)?)));
+}
+
+/// Properties of a style that matter for conversion; `None` is inherited
+#[derive(Default, Clone)]
+struct Style {
+    display_name: String,
+    parent: Option<String>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    strikethrough: Option<bool>,
+    /// Positive for superscript, negative for subscript, zero for neither
+    position: Option<i8>,
+    monospace: Option<bool>,
+    page_break: Option<bool>,
+}
+
+/// Styles by name, and whether list styles are numbered at each level
+#[derive(Default)]
+struct Styles {
+    styles: HashMap<String, Style>,
+    lists: HashMap<String, HashMap<u8, bool>>,
+}
+
+impl Styles {
+    /// Add the styles and list styles found under a document root
+    fn add(&mut self, root: Node) {
+        let containers = ["styles", "automatic-styles"]
+            .into_iter()
+            .filter_map(|name| xml::child(root, name));
+
+        for container in containers {
+            for style in container.children().filter(|n| n.is_element()) {
+                let Some(name) = xml::attribute(style, "name") else {
+                    continue;
+                };
+                match style.tag_name().name() {
+                    "style" => {
+                        self.styles.insert(name.to_string(), parse_style(style));
+                    }
+                    "list-style" => {
+                        let levels = style
+                            .children()
+                            .filter(|n| n.is_element())
+                            .filter_map(|level| {
+                                let index = xml::attribute(level, "level")?.parse().ok()?;
+                                let numbered = level.tag_name().name() == "list-level-style-number"
+                                    && xml::attribute(level, "num-format")
+                                        .is_some_and(|format| !format.is_empty());
+                                Some((index, numbered))
+                            })
+                            .collect();
+                        self.lists.insert(name.to_string(), levels);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// A style and those it inherits from, nearest first
+    fn chain<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a Style> + 'a {
+        let mut next = self.styles.get(name);
+        std::iter::from_fn(move || {
+            let style = next?;
+            next = style
+                .parent
+                .as_deref()
+                .and_then(|name| self.styles.get(name));
+            Some(style)
+        })
+        .take(16)
+    }
+
+    /// The first value of a property along a style's inheritance chain
+    fn resolve<T>(&self, name: &str, property: impl Fn(&Style) -> Option<T>) -> Option<T> {
+        self.chain(name).find_map(property)
+    }
+
+    /// Whether a style, or one it inherits from, has one of these display names
+    fn is_a(&self, name: &str, names: &[&str]) -> bool {
+        self.chain(name)
+            .any(|style| names.contains(&style.display_name.as_str()))
+    }
+
+    /// Character formatting of a span style, on top of `base`
+    fn format(&self, name: &str, base: Format) -> Format {
+        let position = self.resolve(name, |s| s.position).unwrap_or(0);
+        Format {
+            bold: self.resolve(name, |s| s.bold).unwrap_or(base.bold),
+            italic: self.resolve(name, |s| s.italic).unwrap_or(base.italic),
+            strikethrough: self
+                .resolve(name, |s| s.strikethrough)
+                .unwrap_or(base.strikethrough),
+            superscript: position > 0 || (position == 0 && base.superscript),
+            subscript: position < 0 || (position == 0 && base.subscript),
+            code: self.resolve(name, |s| s.monospace).unwrap_or(base.code)
+                || self.is_a(name, &["source text", "teletype", "example"]),
+        }
+    }
+
+    /// Whether a list level is numbered rather than bulleted
+    fn is_ordered(&self, list_style: &str, level: u8) -> bool {
+        self.lists
+            .get(list_style)
+            .and_then(|levels| levels.get(&level))
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+fn parse_style(style: Node) -> Style {
+    let name = xml::attribute(style, "name").unwrap_or_default();
+    let mut parsed = Style {
+        display_name: xml::attribute(style, "display-name")
+            .unwrap_or(name)
+            .replace("_20_", " ")
+            .to_lowercase(),
+        parent: xml::attribute(style, "parent-style-name").map(str::to_string),
+        ..Style::default()
+    };
+
+    if let Some(text) = xml::child(style, "text-properties") {
+        parsed.bold = xml::attribute(text, "font-weight")
+            .map(|weight| weight == "bold" || weight.parse::<u32>().is_ok_and(|w| w >= 600));
+        parsed.italic =
+            xml::attribute(text, "font-style").map(|style| style == "italic" || style == "oblique");
+        parsed.strikethrough =
+            xml::attribute(text, "text-line-through-style").map(|line| line != "none");
+        parsed.position = xml::attribute(text, "text-position").map(|position| {
+            match position.split_whitespace().next().unwrap_or_default() {
+                "super" => 1,
+                "sub" => -1,
+                percent => match percent.trim_end_matches('%').parse::<f32>() {
+                    Ok(p) if p > 0.0 => 1,
+                    Ok(p) if p < 0.0 => -1,
+                    _ => 0,
+                },
+            }
+        });
+        parsed.monospace = xml::attribute(text, "font-name")
+            .or_else(|| xml::attribute(text, "font-family"))
+            .map(|font| {
+                let font = font.to_lowercase();
+                ["courier", "mono", "consolas", "menlo"]
+                    .iter()
+                    .any(|mono| font.contains(mono))
+            });
+    }
+
+    if let Some(paragraph) = xml::child(style, "paragraph-properties") {
+        parsed.page_break = xml::attribute(paragraph, "break-before").map(|b| b == "page");
+    }
+
+    parsed
+}
+
+/// Character formatting of a span
+#[derive(Clone, Copy, PartialEq, Default)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+    superscript: bool,
+    subscript: bool,
+    code: bool,
+}
+
+impl Format {
+    /// Wrap inline content in this formatting
+    fn apply(self, content: Vec<Inline>) -> Vec<Inline> {
+        if self.code {
+            return vec![Inline::Code(inlines_to_text(&content))];
+        }
+
+        let mut content = content;
+        if self.subscript {
+            content = vec![Inline::Subscript(content)];
+        }
+        if self.superscript {
+            content = vec![Inline::Superscript(content)];
+        }
+        if self.strikethrough {
+            content = vec![Inline::Strikethrough(content)];
+        }
+        if self.italic {
+            content = vec![Inline::Italic(content)];
+        }
+        if self.bold {
+            content = vec![Inline::Bold(content)];
+        }
+        content
+    }
+}
+
+/// Blocks converted so far, with the indices of those starting a new page
+#[derive(Default)]
+struct Output {
+    blocks: Vec<Block>,
+    page_breaks: Vec<usize>,
+}
+
+impl Output {
+    /// Append a block, merging consecutive quotations and preformatted lines
+    fn push(&mut self, block: Block, page_break: bool) {
+        if page_break {
+            self.page_breaks.push(self.blocks.len());
+        } else {
+            match (self.blocks.last_mut(), &block) {
+                (Some(Block::Blockquote(previous)), Block::Blockquote(content)) => {
+                    previous.extend(content.iter().cloned());
+                    return;
+                }
+                (Some(Block::CodeBlock { code: previous, .. }), Block::CodeBlock { code, .. }) => {
+                    previous.push('\n');
+                    previous.push_str(code);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.blocks.push(block);
+    }
+}
+
+/// State shared while converting a document
+struct Converter<'a> {
+    styles: Styles,
+    /// Reads a file from the package
+    read: &'a dyn Fn(&str) -> Option<Vec<u8>>,
+    resources: RefCell<ResourceStore>,
+    /// Resource keys of images already loaded, by package path
+    images: RefCell<HashMap<String, String>>,
+    /// Note bodies, collected as their citations are reached
+    footnotes: RefCell<Vec<(String, Block)>>,
+    /// Text of the first Title paragraph
+    title: RefCell<Option<String>>,
+    /// Number of notes without an id, for generating one
+    notes: RefCell<usize>,
+}
+
+impl Converter<'_> {
+    /// Convert the block content of the document, a section or a note
+    fn blocks(&self, node: Node, output: &mut Output) {
+        for child in node.children().filter(|n| n.is_element()) {
+            self.block(child, output);
+        }
+    }
+
+    /// Convert a block-level element
+    fn block(&self, child: Node, output: &mut Output) {
+        match child.tag_name().name() {
+            "h" => {
+                let level = xml::attribute(child, "outline-level")
+                    .and_then(|level| level.parse::<u8>().ok())
+                    .unwrap_or(1)
+                    .clamp(1, 6);
+                let (content, images) = self.paragraph_content(child);
+                if !content.is_empty() {
+                    let block = Block::Header {
+                        level,
+                        content,
+                        anchor: None,
+                    };
+                    output.push(block, self.page_break(child));
+                }
+                for image in images {
+                    output.push(image, false);
+                }
+            }
+            "p" => self.paragraph(child, output),
+            "list" => {
+                let list = self.list(child, None, 1);
+                output.push(list, false);
+            }
+            "table" => {
+                let table = self.table(child);
+                output.push(Block::Table(table), false);
+            }
+            // Generated indexes repeat the document's own headings
+            "table-of-content" | "alphabetical-index" | "illustration-index" | "table-index"
+            | "object-index" | "user-index" | "bibliography" => {}
+            "section" | "soft-page-break" | "note-body" | "list-header" => {
+                self.blocks(child, output)
+            }
+            "frame" => {
+                if let Some(image) = self.image(child) {
+                    output.push(image, false);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Convert a paragraph, sending it to the metadata title, a quotation or a
+    /// code block according to its style
+    fn paragraph(&self, paragraph: Node, output: &mut Output) {
+        let style = xml::attribute(paragraph, "style-name").unwrap_or_default();
+        let page_break = self.page_break(paragraph);
+        let (content, images) = self.paragraph_content(paragraph);
+
+        if self.styles.is_a(style, &["title"]) {
+            let text = inlines_to_text(&content);
+            let mut title = self.title.borrow_mut();
+            if title.is_none() && !text.trim().is_empty() {
+                *title = Some(text.trim().to_string());
+            }
+        } else if self.styles.is_a(style, &["preformatted text"]) {
+            let code = inlines_to_text(&content);
+            output.push(Block::CodeBlock { lang: None, code }, page_break);
+        } else if !content.is_empty() {
+            let block = Block::Paragraph(content);
+            if self.styles.is_a(style, &["quotations"]) {
+                output.push(Block::Blockquote(vec![block]), page_break);
+            } else {
+                output.push(block, page_break);
+            }
+        }
+
+        for image in images {
+            output.push(image, false);
+        }
+    }
+
+    /// Whether a paragraph's style starts it on a new page
+    fn page_break(&self, paragraph: Node) -> bool {
+        xml::attribute(paragraph, "style-name")
+            .and_then(|style| self.styles.resolve(style, |s| s.page_break))
+            .unwrap_or(false)
+    }
+
+    /// Inline content of a paragraph or heading, and the images it anchors
+    fn paragraph_content(&self, paragraph: Node) -> (Vec<Inline>, Vec<Block>) {
+        let mut images = Vec::new();
+        let mut inlines = self.inlines(paragraph, Format::default(), &mut images);
+        trim_inlines(&mut inlines);
+        (inlines, images)
+    }
+
+    /// Convert inline content, collecting anchored images into `images`
+    fn inlines(&self, node: Node, format: Format, images: &mut Vec<Block>) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut text = String::new();
+        let flush = |text: &mut String, inlines: &mut Vec<Inline>| {
+            if !text.is_empty() {
+                inlines.extend(format.apply(vec![Inline::Text(std::mem::take(text))]));
+            }
+        };
+
+        for child in node.children() {
+            if child.is_text() {
+                text.push_str(&xml::collapse_whitespace(child.text().unwrap_or_default()));
+                continue;
+            }
+            if !child.is_element() {
+                continue;
+            }
+
+            match child.tag_name().name() {
+                "s" => {
+                    let count = xml::attribute(child, "c")
+                        .and_then(|c| c.parse().ok())
+                        .unwrap_or(1usize);
+                    text.push_str(&" ".repeat(count));
+                }
+                "tab" => text.push(' '),
+                "line-break" => {
+                    flush(&mut text, &mut inlines);
+                    inlines.push(Inline::Break);
+                }
+                "span" => {
+                    flush(&mut text, &mut inlines);
+                    let format = match xml::attribute(child, "style-name") {
+                        Some(style) => self.styles.format(style, format),
+                        None => format,
+                    };
+                    inlines.extend(self.inlines(child, format, images));
+                }
+                "a" => {
+                    flush(&mut text, &mut inlines);
+                    let children = self.inlines(child, format, images);
+                    match xml::attribute(child, "href") {
+                        Some(url) => inlines.push(Inline::Link {
+                            children,
+                            url: url.to_string(),
+                        }),
+                        None => inlines.extend(children),
+                    }
+                }
+                "note" => {
+                    flush(&mut text, &mut inlines);
+                    let id = self.note(child);
+                    inlines.push(Inline::FootnoteRef { id });
+                }
+                "frame" => {
+                    if let Some(image) = self.image(child) {
+                        images.push(image);
+                    }
+                }
+                // Bookmarks, reference marks and change marks carry no text
+                "bookmark" | "bookmark-start" | "bookmark-end" | "reference-mark" | "change"
+                | "change-start" | "change-end" | "soft-page-break" | "annotation" => {}
+                _ => {
+                    flush(&mut text, &mut inlines);
+                    inlines.extend(self.inlines(child, format, images));
+                }
+            }
+        }
+
+        flush(&mut text, &mut inlines);
+        inlines
+    }
+
+    /// Convert a note's body to a footnote, returning its id
+    fn note(&self, note: Node) -> String {
+        let id = xml::attribute(note, "id")
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                let mut count = self.notes.borrow_mut();
+                *count += 1;
+                format!("note-{}", count)
+            });
+
+        let mut output = Output::default();
+        if let Some(body) = xml::child(note, "note-body") {
+            self.blocks(body, &mut output);
+        }
+        self.footnotes.borrow_mut().push((
+            id.clone(),
+            Block::Footnote {
+                id: id.clone(),
+                content: output.blocks,
+            },
+        ));
+        id
+    }
+
+    /// Convert a list; nested lists inherit the outermost list's style
+    fn list(&self, list: Node, style: Option<&str>, level: u8) -> Block {
+        let style = style.or_else(|| xml::attribute(list, "style-name"));
+        let ordered = style.is_some_and(|style| self.styles.is_ordered(style, level));
+
+        let mut items = Vec::new();
+        for item in list.children().filter(|n| n.is_element()) {
+            if !matches!(item.tag_name().name(), "list-item" | "list-header") {
+                continue;
+            }
+
+            let mut content = Vec::new();
+            for child in item.children().filter(|n| n.is_element()) {
+                if child.tag_name().name() == "list" {
+                    content.push(self.list(child, style, level + 1));
+                } else {
+                    let mut output = Output::default();
+                    self.block(child, &mut output);
+                    content.extend(output.blocks);
+                }
+            }
+            items.push(content);
+        }
+
+        Block::List { items, ordered }
+    }
+
+    /// Convert a table; covered cells are left out, as their space belongs to a
+    /// spanning cell
+    fn table(&self, table: Node) -> TableData {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+
+        for child in table.children().filter(|n| n.is_element()) {
+            let (group, header) = match child.tag_name().name() {
+                "table-header-rows" => (xml::children(child, "table-row").collect(), true),
+                "table-rows" | "table-row-group" => {
+                    (xml::children(child, "table-row").collect(), false)
+                }
+                "table-row" => (vec![child], false),
+                _ => continue,
+            };
+            for row in group {
+                let cells: Vec<TableCell> = xml::children(row, "table-cell")
+                    .map(|cell| self.cell(cell))
+                    .collect();
+                if header {
+                    headers = cells;
+                } else {
+                    rows.push(cells);
+                }
+            }
+        }
+
+        TableData { headers, rows }
+    }
+
+    fn cell(&self, cell: Node) -> TableCell {
+        let mut content = Vec::new();
+        let mut images = Vec::new();
+        for paragraph in cell
+            .children()
+            .filter(|n| matches!(n.tag_name().name(), "p" | "h"))
+        {
+            let mut inlines = self.inlines(paragraph, Format::default(), &mut images);
+            trim_inlines(&mut inlines);
+            if inlines.is_empty() {
+                continue;
+            }
+            if !content.is_empty() {
+                content.push(Inline::Break);
+            }
+            content.extend(inlines);
+        }
+
+        let span = |name| {
+            xml::attribute(cell, name)
+                .and_then(|span| span.parse().ok())
+                .unwrap_or(1)
+        };
+        let mut table_cell = TableCell::new(content);
+        table_cell.colspan = span("number-columns-spanned");
+        table_cell.rowspan = span("number-rows-spanned");
+        table_cell
+    }
+
+    /// Load the image in a frame into the resource store
+    fn image(&self, frame: Node) -> Option<Block> {
+        let image = xml::child(frame, "image")?;
+        let path = xml::attribute(image, "href")?.trim_start_matches("./");
+        if path.contains("://") {
+            return None;
+        }
+
+        let cached = self.images.borrow().get(path).cloned();
+        let resource_key = match cached {
+            Some(key) => key,
+            None => {
+                let data = (self.read)(path)?;
+                let mime = sniff_mime_type(&data, path);
+                let filename = path.rsplit('/').next().unwrap_or(path);
+                let key = self
+                    .resources
+                    .borrow_mut()
+                    .add(Resource::new(mime, data).with_filename(filename));
+                self.images
+                    .borrow_mut()
+                    .insert(path.to_string(), key.clone());
+                key
+            }
+        };
+
+        let alt = xml::child(frame, "title")
+            .or_else(|| xml::child(frame, "desc"))
+            .map(xml::text)
+            .unwrap_or_default();
+
+        Some(Block::Image {
+            resource_key,
+            caption: None,
+            alt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    const NS: &str = r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0""#;
+
+    fn odt(automatic_styles: &str, text: &str, parts: &[(&str, &[u8])]) -> Vec<u8> {
+        let content = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><office:document-content {}><office:automatic-styles>{}</office:automatic-styles><office:body><office:text>{}</office:text></office:body></office:document-content>"#,
+            NS, automatic_styles, text
+        );
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in [("content.xml", content.as_bytes())]
+            .into_iter()
+            .chain(parts.iter().copied())
+        {
+            archive.start_file(name, FileOptions::default()).unwrap();
+            archive.write_all(data).unwrap();
+        }
+        archive.finish().unwrap().into_inner()
+    }
+
+    fn decode(data: Vec<u8>) -> Book {
+        OdtDecoder::new().decode(&mut Cursor::new(data)).unwrap()
+    }
+
+    /// Blocks of all chapters of a document with only automatic styles
+    fn blocks(automatic_styles: &str, text: &str) -> Vec<Block> {
+        decode(odt(automatic_styles, text, &[]))
+            .chapters
+            .into_iter()
+            .flat_map(|chapter| chapter.content)
+            .collect()
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph(vec![Inline::text(text)])
+    }
+
+    #[test]
+    fn test_metadata() {
+        let meta = format!(
+            r#"<office:document-meta {}><office:meta><dc:title>Draft</dc:title><meta:initial-creator>Ann Writer</meta:initial-creator><dc:subject>Fiction</dc:subject><meta:keyword>novel</meta:keyword><dc:language>fr</dc:language><meta:creation-date>2024-05-06T10:11:12</meta:creation-date></office:meta></office:document-meta>"#,
+            NS
+        );
+        let book = decode(odt(
+            "",
+            "<text:p>Text</text:p>",
+            &[("meta.xml", meta.as_bytes())],
+        ));
+
+        assert_eq!(book.metadata.title, "Draft");
+        assert_eq!(book.metadata.creator, vec!["Ann Writer"]);
+        assert_eq!(book.metadata.subject, vec!["Fiction", "novel"]);
+        assert_eq!(book.metadata.language, "fr");
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("2024-05-06".to_string())
+        );
+
+        // Without a title property, the first Title paragraph gives it
+        let styles = r#"
+          <style:style style:name="Title" style:family="paragraph"/>
+          <style:style style:name="P1" style:family="paragraph" style:parent-style-name="Title"/>
+        "#;
+        let book = decode(odt(
+            styles,
+            r#"<text:p text:style-name="P1"> The Title </text:p><text:p>Text</text:p>"#,
+            &[],
+        ));
+        assert_eq!(book.metadata.title, "The Title");
+        assert_eq!(book.chapters[0].content, vec![paragraph("Text")]);
+    }
+
+    #[test]
+    fn test_headings_and_page_breaks() {
+        let styles = r#"<style:style style:name="P1" style:family="paragraph"><style:paragraph-properties fo:break-before="page"/></style:style>"#;
+        let text = r#"
+          <text:h text:outline-level="1">First</text:h>
+          <text:h text:outline-level="2">Part</text:h>
+          <text:p>One</text:p>
+          <text:p text:style-name="P1">Two</text:p>
+          <text:h text:outline-level="9">Deep</text:h>
+          <text:h text:outline-level="1">Second</text:h>
+        "#;
+        let book = decode(odt(styles, text, &[]));
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["First", "Second"]);
+        assert!(matches!(
+            book.chapters[0].content[1],
+            Block::Header { level: 2, .. }
+        ));
+        assert!(matches!(
+            book.chapters[0].content[4],
+            Block::Header { level: 6, .. }
+        ));
+
+        // Paragraph styles that break the page are reported to the splitter
+        let book = OdtDecoder::new()
+            .with_split_strategy(SplitStrategy::PageBreak)
+            .decode(&mut Cursor::new(odt(styles, text, &[])))
+            .unwrap();
+        assert_eq!(book.chapters.len(), 2);
+        assert_eq!(book.chapters[1].content[0], paragraph("Two"));
+    }
+
+    #[test]
+    fn test_style_inheritance() {
+        // Named styles come from styles.xml, automatic styles from content.xml
+        let named = format!(
+            r#"<office:document-styles {}><office:styles>
+              <style:style style:name="Emphasis" style:family="text"><style:text-properties fo:font-style="italic"/></style:style>
+              <style:style style:name="Source_20_Text" style:display-name="Source Text" style:family="text"/>
+              <style:style style:name="Quotations" style:family="paragraph"/>
+              <style:style style:name="Preformatted_20_Text" style:display-name="Preformatted Text" style:family="paragraph"/>
+            </office:styles></office:document-styles>"#,
+            NS
+        );
+        let automatic = r#"
+          <style:style style:name="T1" style:family="text" style:parent-style-name="Emphasis"><style:text-properties fo:font-weight="bold"/></style:style>
+          <style:style style:name="T2" style:family="text" style:parent-style-name="T1"><style:text-properties fo:font-style="normal"/></style:style>
+          <style:style style:name="T3" style:family="text" style:parent-style-name="Source_20_Text"/>
+          <style:style style:name="T4" style:family="text"><style:text-properties style:text-position="33% 58%"/></style:style>
+          <style:style style:name="L1" style:family="text" style:parent-style-name="L2"><style:text-properties fo:font-weight="700"/></style:style>
+          <style:style style:name="L2" style:family="text" style:parent-style-name="L1"/>
+          <style:style style:name="P1" style:family="paragraph" style:parent-style-name="Quotations"/>
+          <style:style style:name="P2" style:family="paragraph" style:parent-style-name="Preformatted_20_Text"/>
+        "#;
+        let text = r#"
+          <text:p><text:span text:style-name="Emphasis">a</text:span> <text:span text:style-name="T1">b</text:span> <text:span text:style-name="T2">c</text:span></text:p>
+          <text:p><text:span text:style-name="T3">code()</text:span> x<text:span text:style-name="T4">2</text:span> <text:span text:style-name="L1">loop</text:span></text:p>
+          <text:p text:style-name="P1">Quoted.</text:p>
+          <text:p text:style-name="P1">More.</text:p>
+          <text:p text:style-name="P2">line 1</text:p>
+          <text:p text:style-name="P2">line 2</text:p>
+        "#;
+        let book = decode(odt(automatic, text, &[("styles.xml", named.as_bytes())]));
+
+        assert_eq!(
+            book.chapters[0].content,
+            vec![
+                Block::Paragraph(vec![
+                    Inline::Italic(vec![Inline::text("a")]),
+                    Inline::text(" "),
+                    Inline::Bold(vec![Inline::Italic(vec![Inline::text("b")])]),
+                    Inline::text(" "),
+                    Inline::Bold(vec![Inline::text("c")]),
+                ]),
+                // A cycle of parents ends rather than looping forever
+                Block::Paragraph(vec![
+                    Inline::Code("code()".into()),
+                    Inline::text(" x"),
+                    Inline::Superscript(vec![Inline::text("2")]),
+                    Inline::text(" "),
+                    Inline::Bold(vec![Inline::text("loop")]),
+                ]),
+                Block::Blockquote(vec![paragraph("Quoted."), paragraph("More.")]),
+                Block::CodeBlock {
+                    lang: None,
+                    code: "line 1\nline 2".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spaces_tabs_links_and_breaks() {
+        assert_eq!(
+            blocks(
+                "",
+                r#"<text:p>A<text:s text:c="3"/>B<text:tab/>C<text:line-break/><text:a xlink:href="https://example.com">D</text:a><text:bookmark text:name="x"/></text:p>"#
+            ),
+            vec![Block::Paragraph(vec![
+                Inline::text("A   B C"),
+                Inline::Break,
+                Inline::Link {
+                    children: vec![Inline::text("D")],
+                    url: "https://example.com".into(),
+                },
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let styles = r#"
+          <text:list-style style:name="L1">
+            <text:list-level-style-number text:level="1" style:num-format="1"/>
+            <text:list-level-style-bullet text:level="2" text:bullet-char="•"/>
+          </text:list-style>
+        "#;
+        let text = r#"
+          <text:list text:style-name="L1">
+            <text:list-item><text:p>One</text:p>
+              <text:list><text:list-item><text:p>Nested</text:p></text:list-item></text:list>
+            </text:list-item>
+          </text:list>
+          <text:list><text:list-item><text:p>Plain</text:p></text:list-item></text:list>
+        "#;
+
+        assert_eq!(
+            blocks(styles, text),
+            vec![
+                Block::List {
+                    items: vec![vec![
+                        paragraph("One"),
+                        Block::List {
+                            items: vec![vec![paragraph("Nested")]],
+                            ordered: false,
+                        },
+                    ]],
+                    ordered: true,
+                },
+                Block::List {
+                    items: vec![vec![paragraph("Plain")]],
+                    ordered: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        let text = r#"
+          <table:table>
+            <table:table-header-rows><table:table-row><table:table-cell><text:p>A</text:p></table:table-cell><table:table-cell><text:p>B</text:p></table:table-cell></table:table-row></table:table-header-rows>
+            <table:table-row><table:table-cell table:number-columns-spanned="2"><text:p>Wide</text:p></table:table-cell><table:covered-table-cell/></table:table-row>
+            <table:table-rows><table:table-row><table:table-cell table:number-rows-spanned="2"><text:p>Tall</text:p><text:p>cell</text:p></table:table-cell><table:table-cell/></table:table-row></table:table-rows>
+          </table:table>
+        "#;
+        let content = blocks("", text);
+        let Block::Table(table) = &content[0] else {
+            panic!("expected a table, got {:?}", content[0]);
+        };
+
+        assert_eq!(table.headers.len(), 2);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0].len(), 1);
+        assert_eq!(table.rows[0][0].colspan, 2);
+        assert_eq!(table.rows[1][0].rowspan, 2);
+        assert_eq!(
+            table.rows[1][0].content,
+            vec![Inline::text("Tall"), Inline::Break, Inline::text("cell")]
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        let text = r#"
+          <text:p>Noted<text:note text:id="ftn1" text:note-class="footnote"><text:note-citation>1</text:note-citation><text:note-body><text:p>The note.</text:p></text:note-body></text:note> and<text:note text:note-class="endnote"><text:note-body><text:p>Unnamed.</text:p></text:note-body></text:note></text:p>
+        "#;
+
+        assert_eq!(
+            blocks("", text),
+            vec![
+                Block::Paragraph(vec![
+                    Inline::text("Noted"),
+                    Inline::FootnoteRef { id: "ftn1".into() },
+                    Inline::text(" and"),
+                    Inline::FootnoteRef {
+                        id: "note-1".into()
+                    },
+                ]),
+                Block::Footnote {
+                    id: "ftn1".into(),
+                    content: vec![paragraph("The note.")],
+                },
+                Block::Footnote {
+                    id: "note-1".into(),
+                    content: vec![paragraph("Unnamed.")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_images() {
+        let text = r#"
+          <text:p><draw:frame><draw:image xlink:href="Pictures/image.png"/><svg:title xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0">Figure</svg:title></draw:frame></text:p>
+          <text:p><draw:frame><draw:image xlink:href="./Pictures/image.png"/></draw:frame></text:p>
+          <text:p><draw:frame><draw:image xlink:href="Pictures/missing.png"/></draw:frame></text:p>
+          <text:p><draw:frame><draw:image xlink:href="https://example.com/remote.png"/></draw:frame></text:p>
+        "#;
+        let book = decode(odt(
+            "",
+            text,
+            &[("Pictures/image.png", b"\x89PNG\r\n\x1a\n")],
+        ));
+
+        // The same picture is stored once; missing and remote ones are dropped
+        let content = &book.chapters[0].content;
+        assert_eq!(content.len(), 2);
+        assert!(matches!(&content[0], Block::Image { alt, .. } if alt == "Figure"));
+        let (
+            Block::Image {
+                resource_key: a, ..
+            },
+            Block::Image {
+                resource_key: b, ..
+            },
+        ) = (&content[0], &content[1])
+        else {
+            panic!("expected images, got {:?}", content);
+        };
+        assert_eq!(a, b);
+        assert_eq!(book.resources.len(), 1);
+        assert_eq!(book.resources.get(a).unwrap().mime_type, "image/png");
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let decoder = OdtDecoder::new();
+        let decode = |data: Vec<u8>| decoder.decode(&mut Cursor::new(data));
+
+        assert!(matches!(
+            decode(b"not a zip".to_vec()),
+            Err(ParseError::MalformedContent(_))
+        ));
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file("mimetype", FileOptions::default())
+            .unwrap();
+        archive
+            .write_all(b"application/vnd.oasis.opendocument.text")
+            .unwrap();
+        let no_content = archive.finish().unwrap().into_inner();
+        assert!(matches!(
+            decode(no_content),
+            Err(ParseError::MissingField(_))
+        ));
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file("content.xml", FileOptions::default())
+            .unwrap();
+        archive
+            .write_all(b"<office:document-content><text:p>")
+            .unwrap();
+        assert!(decode(archive.finish().unwrap().into_inner()).is_err());
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file("content.xml", FileOptions::default())
+            .unwrap();
+        archive
+            .write_all(format!("<office:document-content {}/>", NS).as_bytes())
+            .unwrap();
+        assert!(matches!(
+            decode(archive.finish().unwrap().into_inner()),
+            Err(ParseError::MissingField(_))
+        ));
+    }
+}
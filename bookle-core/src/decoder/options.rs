@@ -0,0 +1,25 @@
+//! Format-specific decoder options
+
+use std::ops::RangeInclusive;
+
+/// Options that only some decoders support, such as a PDF's page range
+///
+/// Decoders take the options they understand through
+/// [`Decoder::set_decode_options`](super::Decoder::set_decode_options), which
+/// reports whether every option that is set applies to the format.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// 1-based range of pages to decode
+    pub pages: Option<RangeInclusive<u32>>,
+    /// Password for encrypted input
+    pub password: Option<String>,
+    /// Keep only the main article content of a web page
+    pub main_content: bool,
+}
+
+impl DecodeOptions {
+    /// Whether no option is set
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_none() && self.password.is_none() && !self.main_content
+    }
+}
@@ -0,0 +1,1500 @@
+//! Org-mode decoder implementation
+//!
+//! Covers the parts of Org syntax used for long-form writing: in-buffer
+//! keywords (`#+TITLE`, `#+AUTHOR`, `#+LANGUAGE`, `#+DATE` and friends) for
+//! metadata, outline headlines (with their TODO keywords, priorities and tags
+//! removed), greater blocks (`SRC`, `EXAMPLE`, `QUOTE`, `VERSE`, `CENTER` and
+//! special blocks), plain and description lists, tables, footnotes, links and
+//! emphasis markup. Headlines marked `COMMENT`, comments, drawers and planning
+//! lines are dropped.
+
+use super::chapters::{place_footnotes, ChapterSplitter, SplitOptions, SplitStrategy};
+use super::context::AssetLoader;
+use super::front_matter::FrontMatter;
+use super::markup::{bare_url, dedent, default_alt};
+use super::project::slugify;
+use super::text::decode_bytes;
+use super::xml::trim_inlines;
+use super::DecodeContext;
+use crate::error::ParseError;
+use crate::types::{
+    AdmonitionKind, Block, Book, DefinitionItem, Inline, Metadata, ResourceStore, TableCell,
+    TableData,
+};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::OnceLock;
+
+/// Schemes of URLs that are links without any markup
+const URL_SCHEMES: &[&str] = &["https://", "http://", "ftp://"];
+
+/// Extensions of links shown as images when they have no description
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp"];
+
+/// Decoder for Org-mode documents
+pub struct OrgDecoder {
+    /// Where the document is divided into chapters
+    splitter: ChapterSplitter,
+}
+
+impl OrgDecoder {
+    pub fn new() -> Self {
+        Self {
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Set where the document is divided into chapters (top-level headlines by default)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+}
+
+impl Default for OrgDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for OrgDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read Org: {}", e)))?;
+        let (text, _) = decode_bytes(&data);
+        let lines: Vec<String> = text.lines().map(|l| l.trim_end().to_string()).collect();
+
+        let mut parser = Parser::new(&lines);
+        let mut blocks = parser.blocks(&lines, true);
+
+        let title = parser
+            .title
+            .clone()
+            .or_else(|| super::MarkdownDecoder::extract_title(&blocks))
+            .unwrap_or_else(|| "Untitled".to_string());
+        let mut metadata = Metadata::new(title, "en");
+        FrontMatter::from_fields(std::mem::take(&mut parser.keywords)).apply_to(&mut metadata);
+
+        // Load images, pointing references at the stored resources
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        loader.load_images(&mut blocks);
+        if let Some(cover) = &metadata.cover_resource_key {
+            metadata.cover_resource_key = loader.load(cover);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+        let mut chapters = self.splitter.split(blocks);
+        place_footnotes(&mut chapters, parser.footnotes);
+        for chapter in chapters {
+            book.add_chapter(chapter);
+        }
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["org"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/org", "text/x-org"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// A parsed headline: its level, its title text and whether it is commented out
+struct Headline {
+    level: usize,
+    title: String,
+    commented: bool,
+}
+
+/// Parse a headline line, `** TODO [#A] Title :tag:`
+fn headline(line: &str) -> Option<Headline> {
+    static HEADLINE: OnceLock<Regex> = OnceLock::new();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    static COOKIE: OnceLock<Regex> = OnceLock::new();
+    let pattern = HEADLINE.get_or_init(|| {
+        Regex::new(r"^(\*+)(?:\s+(?:TODO|DONE)\b)?(?:\s+\[#[A-Za-z0-9]\])?(?:\s+(.*))?$").unwrap()
+    });
+    let tags = TAGS.get_or_init(|| Regex::new(r"\s+:[\w@#%:]+:\s*$").unwrap());
+    let cookie = COOKIE.get_or_init(|| Regex::new(r"\s*\[\d*(?:/\d*|%)\]").unwrap());
+
+    let captures = pattern.captures(line)?;
+    let level = captures[1].len();
+    // A line of stars alone is a headline only if followed by a space
+    if captures.get(2).is_none() && !line[level..].starts_with(' ') {
+        return None;
+    }
+    let mut title = captures.get(2).map_or("", |m| m.as_str()).to_string();
+    let commented = title == "COMMENT" || title.starts_with("COMMENT ");
+    title = tags.replace(&title, "").to_string();
+    title = cookie.replace_all(&title, "").trim().to_string();
+    Some(Headline {
+        level,
+        title,
+        commented,
+    })
+}
+
+/// Key and value of an in-buffer setting, `#+KEY: value`
+fn keyword(line: &str) -> Option<(String, &str)> {
+    let rest = line.trim_start().strip_prefix("#+")?;
+    let (key, value) = rest.split_once(':')?;
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key.to_ascii_lowercase(), value.trim()))
+}
+
+/// A plain list item: its indentation, whether it is numbered, and the
+/// column its text starts at
+fn list_item(line: &str) -> Option<(usize, bool, usize)> {
+    static ITEM: OnceLock<Regex> = OnceLock::new();
+    let item =
+        ITEM.get_or_init(|| Regex::new(r"^(\s*)([-+*]|\d+[.)]|[A-Za-z][.)])(\s+|$)").unwrap());
+    let captures = item.captures(line)?;
+    let indent = captures[1].len();
+    let marker = &captures[2];
+    // An unindented star starts a headline, not an item
+    if marker == "*" && indent == 0 {
+        return None;
+    }
+    let ordered = !matches!(marker, "-" | "+" | "*");
+    Some((indent, ordered, captures[0].len()))
+}
+
+/// Whether a line is a footnote definition, `[fn:label] text`
+fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("[fn:")?;
+    let end = rest.find(']')?;
+    let label = &rest[..end];
+    if label.is_empty() || label.contains(':') {
+        return None;
+    }
+    Some((label, rest[end + 1..].trim()))
+}
+
+/// Whether a line is a horizontal rule, five or more dashes
+fn is_rule(line: &str) -> bool {
+    let line = line.trim();
+    line.len() >= 5 && line.chars().all(|c| c == '-')
+}
+
+/// The name and parameters of a `#+BEGIN_NAME` line
+fn block_start(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let rest = trimmed
+        .get(..8)
+        .filter(|start| start.eq_ignore_ascii_case("#+begin_"))
+        .map(|_| &trimmed[8..])?;
+    let (name, parameters) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some((name.to_ascii_lowercase(), parameters.trim()))
+}
+
+/// Whether a line starts an element that ends a paragraph
+fn starts_element(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    headline(line).is_some()
+        || trimmed.starts_with("#+")
+        || trimmed.starts_with('|')
+        || trimmed == ":"
+        || trimmed.starts_with(": ")
+        || trimmed == "#"
+        || trimmed.starts_with("# ")
+        || list_item(line).is_some()
+        || footnote_definition(line).is_some()
+        || is_rule(line)
+}
+
+/// Parser state for a document
+struct Parser {
+    /// Document title, from `#+TITLE`
+    title: Option<String>,
+    /// Metadata keywords, in front matter form
+    keywords: Map<String, Value>,
+    /// Heading anchors by headline title, custom id or target name
+    anchors: HashMap<String, String>,
+    /// Footnotes defined in the document, in order
+    footnotes: Vec<(String, Block)>,
+    /// Anonymous inline footnotes seen so far
+    anonymous_notes: usize,
+    /// Caption for the next image or table, from `#+CAPTION`
+    caption: Option<String>,
+    /// Images linked from within a paragraph, placed after it
+    inline_images: Vec<Block>,
+}
+
+impl Parser {
+    fn new(lines: &[String]) -> Self {
+        let mut parser = Self {
+            title: None,
+            keywords: Map::new(),
+            anchors: HashMap::new(),
+            footnotes: Vec::new(),
+            anonymous_notes: 0,
+            caption: None,
+            inline_images: Vec::new(),
+        };
+        parser.scan(lines);
+        parser
+    }
+
+    /// Gather keywords and headline anchors before parsing, so that links can
+    /// be resolved wherever they appear
+    fn scan(&mut self, lines: &[String]) {
+        static TARGET: OnceLock<Regex> = OnceLock::new();
+        let target = TARGET.get_or_init(|| Regex::new(r"<<([^<>\n]+)>>").unwrap());
+
+        let mut authors = Vec::new();
+        let mut current_anchor = None;
+        for (index, line) in lines.iter().enumerate() {
+            if let Some(headline) = headline(line) {
+                let anchor =
+                    custom_id(lines, index + 1).unwrap_or_else(|| slugify(&headline.title));
+                self.anchors
+                    .entry(headline.title)
+                    .or_insert_with(|| anchor.clone());
+                current_anchor = Some(anchor);
+                continue;
+            }
+            for captures in target.captures_iter(line) {
+                let anchor = current_anchor
+                    .clone()
+                    .unwrap_or_else(|| slugify(&captures[1]));
+                self.anchors
+                    .entry(captures[1].to_string())
+                    .or_insert(anchor);
+            }
+            let Some((key, value)) = keyword(line) else {
+                continue;
+            };
+            if value.is_empty() {
+                continue;
+            }
+            match key.as_str() {
+                "title" => {
+                    let title = match self.title.take() {
+                        Some(title) => format!("{} {}", title, value),
+                        None => value.to_string(),
+                    };
+                    self.title = Some(title);
+                }
+                "author" => authors.extend(
+                    value
+                        .split(" and ")
+                        .map(|author| Value::String(author.trim().to_string())),
+                ),
+                "language" => {
+                    self.keywords
+                        .insert("lang".to_string(), Value::String(value.to_string()));
+                }
+                "date" => {
+                    let date = value.trim_matches(['<', '>', '[', ']']);
+                    let date = date.split_whitespace().next().unwrap_or(date);
+                    self.keywords
+                        .insert("date".to_string(), Value::String(date.to_string()));
+                }
+                "description" => {
+                    let description = match self.keywords.get("description") {
+                        Some(Value::String(previous)) => format!("{} {}", previous, value),
+                        _ => value.to_string(),
+                    };
+                    self.keywords
+                        .insert("description".to_string(), Value::String(description));
+                }
+                "keywords" | "filetags" => {
+                    // File tags are written `:one:two:`, keywords with commas or spaces
+                    let separator = if key == "filetags" {
+                        ':'
+                    } else if value.contains(',') {
+                        ','
+                    } else {
+                        ' '
+                    };
+                    let keywords = value
+                        .split(separator)
+                        .map(str::trim)
+                        .filter(|k| !k.is_empty())
+                        .map(|k| Value::String(k.to_string()))
+                        .collect();
+                    self.keywords
+                        .insert("keywords".to_string(), Value::Array(keywords));
+                }
+                "publisher" | "rights" | "copyright" | "isbn" | "series" | "cover" => {
+                    self.keywords
+                        .insert(key.clone(), Value::String(value.to_string()));
+                }
+                _ => {}
+            }
+        }
+        if !authors.is_empty() {
+            self.keywords
+                .insert("author".to_string(), Value::Array(authors));
+        }
+    }
+
+    /// Parse blocks; headlines are recognised only at the top level
+    fn blocks(&mut self, lines: &[String], top_level: bool) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                index += 1;
+                continue;
+            }
+
+            if top_level {
+                if let Some(headline) = headline(line) {
+                    index += 1;
+                    if headline.commented {
+                        // A commented headline is dropped with its subtree
+                        while let Some(line) = lines.get(index) {
+                            if headline_level(line).is_some_and(|level| level <= headline.level) {
+                                break;
+                            }
+                            index += 1;
+                        }
+                        continue;
+                    }
+                    while lines.get(index).is_some_and(|line| is_planning(line)) {
+                        index += 1;
+                    }
+                    let anchor = self.anchors.get(&headline.title).cloned();
+                    blocks.push(Block::Header {
+                        level: headline.level.min(6) as u8,
+                        content: self.inlines(&headline.title),
+                        anchor,
+                    });
+                    continue;
+                }
+            }
+
+            let (found, next) = self.block(lines, index);
+            blocks.extend(found);
+            index = next.max(index + 1);
+        }
+        blocks
+    }
+
+    /// Parse the element starting at `index`, returning it and the index after it
+    fn block(&mut self, lines: &[String], index: usize) -> (Vec<Block>, usize) {
+        let line = &lines[index];
+        let trimmed = line.trim();
+
+        if let Some((name, parameters)) = block_start(line) {
+            let end_marker = format!("#+end_{}", name);
+            let end = lines[index + 1..]
+                .iter()
+                .position(|l| l.trim().eq_ignore_ascii_case(&end_marker))
+                .map_or(lines.len(), |offset| index + 1 + offset);
+            let content = &lines[index + 1..end];
+            return (self.greater_block(&name, parameters, content), end + 1);
+        }
+
+        // Keywords were gathered before parsing, apart from captions
+        if trimmed.starts_with("#+") {
+            match keyword(line) {
+                Some((key, value)) if key == "caption" => self.caption = Some(value.to_string()),
+                _ => {}
+            }
+            return (vec![], index + 1);
+        }
+
+        if trimmed == "#" || trimmed.starts_with("# ") {
+            return (vec![], index + 1);
+        }
+
+        // Drawers hold properties and logbooks, which are not content
+        if trimmed.len() > 2
+            && trimmed.starts_with(':')
+            && trimmed.ends_with(':')
+            && !trimmed.contains(' ')
+        {
+            let end = lines[index + 1..]
+                .iter()
+                .position(|l| l.trim().eq_ignore_ascii_case(":END:"));
+            if let Some(offset) = end {
+                return (vec![], index + offset + 2);
+            }
+        }
+
+        if trimmed == ":" || trimmed.starts_with(": ") {
+            let end = lines[index..]
+                .iter()
+                .position(|l| {
+                    let l = l.trim();
+                    l != ":" && !l.starts_with(": ")
+                })
+                .map_or(lines.len(), |offset| index + offset);
+            let code = lines[index..end]
+                .iter()
+                .map(|l| l.trim().strip_prefix(": ").unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join("\n");
+            return (vec![Block::CodeBlock { lang: None, code }], end);
+        }
+
+        if is_rule(line) {
+            return (vec![Block::ThematicBreak], index + 1);
+        }
+
+        if trimmed.starts_with('|') {
+            let end = lines[index..]
+                .iter()
+                .position(|l| !l.trim().starts_with('|'))
+                .map_or(lines.len(), |offset| index + offset);
+            let mut blocks = self.take_caption();
+            blocks.push(Block::Table(self.table(&lines[index..end])));
+            return (blocks, end);
+        }
+
+        if let Some((label, text)) = footnote_definition(line) {
+            return (vec![], self.footnote(lines, index, label, text));
+        }
+
+        if list_item(line).is_some() {
+            return self.list(lines, index);
+        }
+
+        self.paragraph(lines, index)
+    }
+
+    /// Convert a `#+BEGIN_NAME` ... `#+END_NAME` block
+    fn greater_block(&mut self, name: &str, parameters: &str, content: &[String]) -> Vec<Block> {
+        match name {
+            "src" | "example" => {
+                let lang = (name == "src")
+                    .then(|| parameters.split_whitespace().next())
+                    .flatten()
+                    .map(str::to_string);
+                // Lines starting with `*` or `#+` are escaped with a comma
+                let code: Vec<String> = dedent(content)
+                    .into_iter()
+                    .map(|line| match line.strip_prefix(',') {
+                        Some(rest) if rest.starts_with('*') || rest.starts_with("#+") => {
+                            rest.to_string()
+                        }
+                        _ => line,
+                    })
+                    .collect();
+                let mut blocks = self.take_caption();
+                blocks.push(Block::CodeBlock {
+                    lang,
+                    code: code.join("\n"),
+                });
+                blocks
+            }
+            "quote" => vec![Block::Blockquote(self.blocks(&dedent(content), false))],
+            "verse" => {
+                let mut stanzas = Vec::new();
+                for stanza in content.split(|line| line.trim().is_empty()) {
+                    if stanza.is_empty() {
+                        continue;
+                    }
+                    let mut inlines = Vec::new();
+                    for line in stanza {
+                        if !inlines.is_empty() {
+                            inlines.push(Inline::Break);
+                        }
+                        inlines.extend(self.inlines(line));
+                    }
+                    stanzas.push(Block::Paragraph(inlines));
+                }
+                vec![Block::Blockquote(stanzas)]
+            }
+            "comment" | "export" => vec![],
+            _ => {
+                let content = self.blocks(&dedent(content), false);
+                match AdmonitionKind::from_name(name) {
+                    Some(kind) => vec![Block::Admonition { kind, content }],
+                    None => vec![Block::Container {
+                        id: None,
+                        classes: vec![name.to_string()],
+                        content,
+                    }],
+                }
+            }
+        }
+    }
+
+    /// Parse a footnote definition, which runs until the next definition or
+    /// headline or two blank lines, returning the index after it
+    fn footnote(&mut self, lines: &[String], index: usize, label: &str, text: &str) -> usize {
+        let mut end = index + 1;
+        let mut blank = 0;
+        while let Some(line) = lines.get(end) {
+            if line.trim().is_empty() {
+                blank += 1;
+                if blank == 2 {
+                    break;
+                }
+            } else if footnote_definition(line).is_some() || headline(line).is_some() {
+                break;
+            } else {
+                blank = 0;
+            }
+            end += 1;
+        }
+        let mut body = vec![text.to_string()];
+        body.extend(dedent(&lines[index + 1..end]));
+        let content = self.blocks(&body, false);
+        let id = footnote_id(label);
+        self.footnotes
+            .push((id.clone(), Block::Footnote { id, content }));
+        end
+    }
+
+    /// Parse a plain or description list whose first item is at `index`
+    fn list(&mut self, lines: &[String], mut index: usize) -> (Vec<Block>, usize) {
+        let Some((indent, ordered, _)) = list_item(&lines[index]) else {
+            return (vec![], index + 1);
+        };
+        let mut items = Vec::new();
+        let mut definitions = Vec::new();
+        while let Some((_, _, width)) = lines.get(index).and_then(|line| list_item(line)).filter(
+            |(item_indent, item_ordered, _)| *item_indent == indent && *item_ordered == ordered,
+        ) {
+            // The item continues on lines indented past its bullet
+            let mut end = index + 1;
+            let mut blank = 0;
+            while let Some(line) = lines.get(end) {
+                if line.trim().is_empty() {
+                    blank += 1;
+                    if blank == 2 {
+                        break;
+                    }
+                } else if line.len() - line.trim_start().len() > indent {
+                    blank = 0;
+                } else {
+                    break;
+                }
+                end += 1;
+            }
+            let mut body_end = end;
+            while body_end > index + 1 && lines[body_end - 1].trim().is_empty() {
+                body_end -= 1;
+            }
+
+            let mut first = lines[index][width.min(lines[index].len())..].to_string();
+            // A counter cookie may reset the numbering, `[@3]`
+            if first.starts_with("[@") {
+                if let Some(close) = first.find(']') {
+                    first = first[close + 1..].trim_start().to_string();
+                }
+            }
+            let checkbox = ["[ ]", "[X]", "[x]", "[-]"]
+                .iter()
+                .find(|box_| first.starts_with(**box_))
+                .map(|box_| {
+                    first = first[box_.len()..].trim_start().to_string();
+                    Inline::Checkbox {
+                        checked: *box_ != "[ ]",
+                    }
+                });
+
+            let mut body = vec![];
+            // An unordered item with `term :: definition` belongs to a description list
+            let term = first
+                .split_once(" :: ")
+                .map(|(t, d)| (t.to_string(), d.to_string()))
+                .or_else(|| {
+                    first
+                        .strip_suffix(" ::")
+                        .map(|t| (t.to_string(), String::new()))
+                })
+                .filter(|_| !ordered);
+            match &term {
+                Some((_, definition)) => body.push(definition.clone()),
+                None => body.push(first.clone()),
+            }
+            body.extend(dedent(&lines[index + 1..body_end]));
+            let mut content = self.blocks(&body, false);
+            if let Some(checkbox) = checkbox {
+                match content.first_mut() {
+                    Some(Block::Paragraph(inlines)) => inlines.insert(0, checkbox),
+                    _ => content.insert(0, Block::Paragraph(vec![checkbox])),
+                }
+            }
+            match term {
+                Some((term, _)) => definitions.push(DefinitionItem {
+                    term: self.inlines(&term),
+                    definitions: vec![content],
+                }),
+                None => items.push(content),
+            }
+
+            index = end;
+            if blank == 2 {
+                break;
+            }
+            while lines.get(index).is_some_and(|l| l.trim().is_empty()) {
+                index += 1;
+            }
+        }
+
+        let mut blocks = Vec::new();
+        if !definitions.is_empty() {
+            blocks.push(Block::DefinitionList(definitions));
+        }
+        if !items.is_empty() {
+            blocks.push(Block::List { items, ordered });
+        }
+        (blocks, index)
+    }
+
+    /// Parse a table; rows above the first rule are its header
+    fn table(&mut self, lines: &[String]) -> TableData {
+        static ALIGNMENT: OnceLock<Regex> = OnceLock::new();
+        let alignment = ALIGNMENT.get_or_init(|| Regex::new(r"^<[lrc]?\d*>$").unwrap());
+
+        let mut rows: Vec<Vec<TableCell>> = Vec::new();
+        let mut header_rows = None;
+        for line in lines {
+            let line = line.trim();
+            if line.starts_with("|-") {
+                if header_rows.is_none() && !rows.is_empty() {
+                    header_rows = Some(rows.len());
+                }
+                continue;
+            }
+            let inner = line.trim_start_matches('|');
+            let inner = inner.strip_suffix('|').unwrap_or(inner);
+            let cells: Vec<&str> = inner.split('|').map(str::trim).collect();
+            // Rows of width and alignment cookies only format the table
+            if cells
+                .iter()
+                .all(|cell| cell.is_empty() || alignment.is_match(cell))
+            {
+                continue;
+            }
+            rows.push(
+                cells
+                    .iter()
+                    .map(|cell| TableCell::new(self.inlines(cell)))
+                    .collect(),
+            );
+        }
+        let headers = match header_rows {
+            Some(count) if count < rows.len() => {
+                let mut header: Vec<Vec<TableCell>> = rows.drain(..count).collect();
+                header.remove(0)
+            }
+            _ => Vec::new(),
+        };
+        TableData { headers, rows }
+    }
+
+    /// Parse a paragraph, which runs until a blank line or another element
+    fn paragraph(&mut self, lines: &[String], index: usize) -> (Vec<Block>, usize) {
+        let end = lines[index + 1..]
+            .iter()
+            .position(|l| l.trim().is_empty() || starts_element(l))
+            .map_or(lines.len(), |offset| index + 1 + offset);
+        let text = lines[index..end]
+            .iter()
+            .map(|l| l.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // A paragraph holding only an image link is a figure
+        if let Some(image) = self.standalone_image(&text) {
+            return (vec![image], end);
+        }
+
+        let mut blocks = Vec::new();
+        let inlines = self.inlines(&text);
+        if !inlines.is_empty() {
+            blocks.push(Block::Paragraph(inlines));
+        }
+        blocks.append(&mut self.inline_images);
+        (blocks, end)
+    }
+
+    /// The image of a paragraph made of a single `[[file:image.png]]` link
+    fn standalone_image(&mut self, text: &str) -> Option<Block> {
+        let target = text.strip_prefix("[[")?.strip_suffix("]]")?;
+        if target.contains("][") || target.contains(']') {
+            return None;
+        }
+        let path = image_path(target)?;
+        let caption = self.caption.take();
+        Some(Block::Image {
+            alt: caption.clone().unwrap_or_else(|| default_alt(&path)),
+            resource_key: path,
+            caption,
+        })
+    }
+
+    /// A bold paragraph for a pending `#+CAPTION`
+    fn take_caption(&mut self) -> Vec<Block> {
+        match self.caption.take() {
+            Some(caption) => vec![Block::Paragraph(vec![Inline::Bold(self.inlines(&caption))])],
+            None => vec![],
+        }
+    }
+
+    /// Convert inline text, trimming the whitespace at its ends
+    fn inlines(&mut self, text: &str) -> Vec<Inline> {
+        let mut inlines = self.scan_inlines(text);
+        trim_inlines(&mut inlines);
+        inlines
+    }
+
+    /// Convert inline markup
+    fn scan_inlines(&mut self, text: &str) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut plain = String::new();
+        let mut index = 0;
+        while index < text.len() {
+            let rest = &text[index..];
+            let previous = text[..index].chars().next_back();
+
+            if let Some((found, length)) = self.markup(rest, previous) {
+                for inline in found {
+                    match inline {
+                        Inline::Text(text) => plain.push_str(&text),
+                        inline => {
+                            if !plain.is_empty() {
+                                inlines.push(Inline::Text(std::mem::take(&mut plain)));
+                            }
+                            inlines.push(inline);
+                        }
+                    }
+                }
+                index += length;
+                continue;
+            }
+
+            let c = rest.chars().next().unwrap_or_default();
+            plain.push(if c == '\n' { ' ' } else { c });
+            index += c.len_utf8();
+        }
+        if !plain.is_empty() {
+            inlines.push(Inline::Text(plain));
+        }
+        inlines
+    }
+
+    /// Recognise inline markup at the start of `rest`, returning what it
+    /// converts to and its length
+    fn markup(&mut self, rest: &str, previous: Option<char>) -> Option<(Vec<Inline>, usize)> {
+        // A line ending in `\\` breaks the line
+        if let Some(after) = rest.strip_prefix("\\\\") {
+            if after.is_empty() || after.starts_with('\n') {
+                let length = 2 + usize::from(after.starts_with('\n'));
+                return Some((vec![Inline::Break], length));
+            }
+        }
+
+        if let Some(link) = rest.strip_prefix("[[") {
+            let end = link.find("]]")?;
+            let body = &link[..end];
+            let (target, description) = match body.split_once("][") {
+                Some((target, description)) => (target, Some(description)),
+                None => (body, None),
+            };
+            return Some((self.link(target, description), end + 4));
+        }
+
+        if let Some(note) = rest.strip_prefix("[fn:") {
+            let end = closing_bracket(note)?;
+            let body = &note[..end];
+            let id = match body.split_once(':') {
+                // An inline definition, named or anonymous
+                Some((label, definition)) => {
+                    let id = if label.is_empty() {
+                        self.anonymous_notes += 1;
+                        format!("footnote-anonymous-{}", self.anonymous_notes)
+                    } else {
+                        footnote_id(label)
+                    };
+                    let content = vec![Block::Paragraph(self.inlines(definition))];
+                    self.footnotes.push((
+                        id.clone(),
+                        Block::Footnote {
+                            id: id.clone(),
+                            content,
+                        },
+                    ));
+                    id
+                }
+                None => footnote_id(body),
+            };
+            return Some((vec![Inline::FootnoteRef { id }], end + 5));
+        }
+
+        if let Some(target) = rest.strip_prefix("<<") {
+            // Targets were gathered before parsing; radio targets keep their text
+            let end = target.find(">>")?;
+            let text = target[..end].trim_start_matches('<').to_string();
+            let radio = target[..end].starts_with('<');
+            let length = end + 4 + usize::from(radio && target[end + 2..].starts_with('>'));
+            let found = if radio {
+                vec![Inline::Text(text)]
+            } else {
+                vec![]
+            };
+            return Some((found, length));
+        }
+
+        if let Some(snippet) = rest.strip_prefix("@@") {
+            // Export snippets are for one backend only
+            let end = snippet.find("@@")?;
+            if snippet[..end].contains(':') {
+                return Some((vec![], end + 4));
+            }
+        }
+
+        if previous.is_some_and(|c| !c.is_whitespace()) {
+            if let Some(script) = self.script(rest) {
+                return Some(script);
+            }
+        }
+
+        let c = rest.chars().next()?;
+        if "*/_=~+".contains(c)
+            && previous.is_none_or(|p| p.is_whitespace() || "-({'\"".contains(p))
+        {
+            if let Some(content) = emphasis(rest, c) {
+                let length = content.len() + 2;
+                let inline = match c {
+                    '*' => Inline::Bold(self.scan_inlines(content)),
+                    // Underline has no equivalent, so it is shown as emphasis
+                    '/' | '_' => Inline::Italic(self.scan_inlines(content)),
+                    '+' => Inline::Strikethrough(self.scan_inlines(content)),
+                    _ => Inline::Code(content.replace('\n', " ")),
+                };
+                return Some((vec![inline], length));
+            }
+        }
+
+        if previous.is_none_or(|p| !p.is_alphanumeric()) {
+            if let Some(url) = bare_url(rest, URL_SCHEMES, &['<', '>', '[', ']']) {
+                return Some((
+                    vec![Inline::Link {
+                        children: vec![Inline::text(url)],
+                        url: url.to_string(),
+                    }],
+                    url.len(),
+                ));
+            }
+        }
+        None
+    }
+
+    /// Superscript or subscript in braces, `x^{2}` or `H_{2}O`
+    fn script(&mut self, rest: &str) -> Option<(Vec<Inline>, usize)> {
+        let (marker, body) = rest.split_at_checked(1)?;
+        if !matches!(marker, "^" | "_") {
+            return None;
+        }
+        let body = body.strip_prefix('{')?;
+        let end = body.find('}')?;
+        let content = self.scan_inlines(&body[..end]);
+        let inline = if marker == "^" {
+            Inline::Superscript(content)
+        } else {
+            Inline::Subscript(content)
+        };
+        Some((vec![inline], end + 3))
+    }
+
+    /// Convert a `[[target][description]]` link
+    fn link(&mut self, target: &str, description: Option<&str>) -> Vec<Inline> {
+        if description.is_none() {
+            if let Some(path) = image_path(target) {
+                self.inline_images.push(Block::Image {
+                    alt: default_alt(&path),
+                    resource_key: path,
+                    caption: None,
+                });
+                return vec![];
+            }
+        }
+
+        let (url, text) = if let Some(heading) = target.strip_prefix('*') {
+            let anchor = self
+                .anchors
+                .get(heading)
+                .cloned()
+                .unwrap_or_else(|| slugify(heading));
+            (format!("#{}", anchor), heading.to_string())
+        } else if let Some(id) = target
+            .strip_prefix('#')
+            .or_else(|| target.strip_prefix("id:"))
+        {
+            (format!("#{}", id), target.to_string())
+        } else if let Some(path) = target.strip_prefix("file:") {
+            (path.to_string(), path.to_string())
+        } else if target.contains(':') || target.starts_with(['.', '/']) {
+            (target.to_string(), target.to_string())
+        } else {
+            // A fuzzy link names a target or a headline
+            let anchor = self
+                .anchors
+                .get(target)
+                .cloned()
+                .unwrap_or_else(|| slugify(target));
+            (format!("#{}", anchor), target.to_string())
+        };
+
+        let children = match description {
+            Some(description) => self.scan_inlines(description),
+            None => vec![Inline::Text(text)],
+        };
+        vec![Inline::Link { children, url }]
+    }
+}
+
+/// Level of a headline line, if it is one
+fn headline_level(line: &str) -> Option<usize> {
+    headline(line).map(|headline| headline.level)
+}
+
+/// The `CUSTOM_ID` property of the headline whose drawer starts at `index`
+fn custom_id(lines: &[String], mut index: usize) -> Option<String> {
+    // Planning lines come between the headline and its property drawer
+    while lines.get(index).is_some_and(|line| is_planning(line)) {
+        index += 1;
+    }
+    if !lines
+        .get(index)?
+        .trim()
+        .eq_ignore_ascii_case(":PROPERTIES:")
+    {
+        return None;
+    }
+    for line in &lines[index + 1..] {
+        let line = line.trim();
+        if line.eq_ignore_ascii_case(":END:") {
+            break;
+        }
+        let Some(rest) = line.strip_prefix(":") else {
+            continue;
+        };
+        if let Some((key, value)) = rest.split_once(':') {
+            if key.eq_ignore_ascii_case("CUSTOM_ID") && !value.trim().is_empty() {
+                return Some(value.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Whether a line is a planning line under a headline
+fn is_planning(line: &str) -> bool {
+    let line = line.trim_start();
+    ["SCHEDULED:", "DEADLINE:", "CLOSED:"]
+        .iter()
+        .any(|keyword| line.starts_with(keyword))
+}
+
+/// Id for a footnote label: `[fn:1]` and `[fn:name]` both keep their label
+fn footnote_id(label: &str) -> String {
+    format!("footnote-{}", label)
+}
+
+/// The index of the `]` closing a footnote reference, skipping nested brackets
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return Some(index),
+            ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Content of emphasis marked by `marker` at the start of `rest`
+///
+/// The content may not start or end with whitespace, and the closing marker
+/// must be followed by whitespace, punctuation or the end of the text.
+fn emphasis(rest: &str, marker: char) -> Option<&str> {
+    let body = &rest[marker.len_utf8()..];
+    if body.starts_with(char::is_whitespace) || body.starts_with(marker) {
+        return None;
+    }
+    let mut offset = 0;
+    while let Some(found) = body[offset..].find(marker) {
+        let end = offset + found;
+        let before = body[..end].chars().next_back();
+        let after = body[end + 1..].chars().next();
+        if end > 0
+            && before.is_some_and(|c| !c.is_whitespace())
+            && after.is_none_or(|c| c.is_whitespace() || "-.,;:!?')}[\"\\".contains(c))
+        {
+            let content = &body[..end];
+            // Emphasis may span at most two lines
+            if content.matches('\n').count() > 1 {
+                return None;
+            }
+            return Some(content);
+        }
+        offset = end + 1;
+    }
+    None
+}
+
+/// Path of a link target that points at a local image
+fn image_path(target: &str) -> Option<String> {
+    let path = target.strip_prefix("file:").unwrap_or(target);
+    if path.contains("://") || (path.contains(':') && !target.starts_with("file:")) {
+        return None;
+    }
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    IMAGE_EXTENSIONS
+        .contains(&extension.as_str())
+        .then(|| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::markdown::inlines_to_text;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    fn decode(text: &str) -> Book {
+        OrgDecoder::new()
+            .decode(&mut Cursor::new(text.as_bytes()))
+            .unwrap()
+    }
+
+    /// Blocks of every chapter, in order
+    fn blocks(text: &str) -> Vec<Block> {
+        decode(text)
+            .chapters
+            .into_iter()
+            .flat_map(|chapter| chapter.content)
+            .collect()
+    }
+
+    /// Inlines of the only paragraph in `text`
+    fn paragraph(text: &str) -> Vec<Inline> {
+        match &blocks(text)[..] {
+            [Block::Paragraph(inlines)] => inlines.clone(),
+            other => panic!("expected one paragraph, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_keywords() {
+        let book = decode(
+            "#+TITLE: Engineering\n#+TITLE: Handbook\n#+AUTHOR: Ada Lovelace and Charles Babbage\n\
+             #+LANGUAGE: fr\n#+DATE: <2021-06-01 Tue>\n#+FILETAGS: :rust:docs:\n\nText.\n",
+        );
+        assert_eq!(book.metadata.title, "Engineering Handbook");
+        assert_eq!(
+            book.metadata.creator,
+            vec!["Ada Lovelace", "Charles Babbage"]
+        );
+        assert_eq!(book.metadata.language, "fr");
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("2021-06-01".to_string())
+        );
+        assert_eq!(book.metadata.subject, vec!["rust", "docs"]);
+    }
+
+    #[test]
+    fn test_headlines_lose_todo_keywords_tags_and_cookies() {
+        let book = decode(
+            "* TODO [#A] Getting Started [1/3] :setup:work:\n\
+             SCHEDULED: <2021-06-01 Tue>\nText.\n\
+             * COMMENT Drafts\nNot exported.\n** Still a draft\n\
+             * DONE Deploying\nMore.\n",
+        );
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["Getting Started", "Deploying"]);
+        assert_eq!(
+            book.chapters[0].content[1],
+            Block::Paragraph(vec![Inline::text("Text.")])
+        );
+        assert!(!blocks("*bold* text\n")
+            .iter()
+            .any(|block| matches!(block, Block::Header { .. })));
+    }
+
+    #[test]
+    fn test_table_rows_above_first_rule_are_the_header() {
+        let found = blocks(
+            "#+CAPTION: Tools\n| Tool  | Use   |\n|-------+-------|\n| <l>   | <r10> |\n\
+             | cargo | *builds* |\n| rustc | compiles |\n",
+        );
+        assert_eq!(
+            found[0],
+            Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Tools")])])
+        );
+        let Block::Table(table) = &found[1] else {
+            panic!("expected a table, got {:?}", found[1]);
+        };
+        let headers: Vec<String> = table
+            .headers
+            .iter()
+            .map(|cell| inlines_to_text(&cell.content))
+            .collect();
+        assert_eq!(headers, ["Tool", "Use"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(
+            table.rows[0][1].content,
+            vec![Inline::Bold(vec![Inline::text("builds")])]
+        );
+
+        // Without a rule every row is a body row
+        let [Block::Table(table)] = &blocks("| a | b |\n| c | d |\n")[..] else {
+            panic!("expected a table");
+        };
+        assert!(table.headers.is_empty());
+        assert_eq!(table.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_include_and_export_directives_are_dropped() {
+        let found = blocks(
+            "#+INCLUDE: \"chapter.org\"\nBefore.\n\n\
+             #+BEGIN_EXPORT html\n<b>raw</b>\n#+END_EXPORT\n\nAfter @@html:<br>@@ text.\n",
+        );
+        assert_eq!(
+            found,
+            vec![
+                Block::Paragraph(vec![Inline::text("Before.")]),
+                Block::Paragraph(vec![Inline::text("After  text.")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footnote_definitions_and_references() {
+        let book = decode(
+            "Text.[fn:1] More.[fn:note] Inline.[fn:named: Said here.] Anon.[fn:: Nameless [sic].]\n\n\
+             [fn:1] First paragraph.\n\n  Second paragraph.\n\n\n\
+             After the note.\n\n[fn:note] Named note.\n",
+        );
+        let content = &book.chapters[0].content;
+        let Block::Paragraph(text) = &content[0] else {
+            panic!("expected a paragraph, got {:?}", content[0]);
+        };
+        let refs: Vec<&str> = text
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::FootnoteRef { id } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            refs,
+            [
+                "footnote-1",
+                "footnote-note",
+                "footnote-named",
+                "footnote-anonymous-1"
+            ]
+        );
+        // Two blank lines end a definition
+        assert_eq!(
+            content[1],
+            Block::Paragraph(vec![Inline::text("After the note.")])
+        );
+
+        let notes: HashMap<&str, &Vec<Block>> = content
+            .iter()
+            .filter_map(|block| match block {
+                Block::Footnote { id, content } => Some((id.as_str(), content)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(notes.len(), 4);
+        assert_eq!(notes["footnote-1"].len(), 2);
+        assert_eq!(
+            notes["footnote-anonymous-1"][0],
+            Block::Paragraph(vec![Inline::text("Nameless [sic].")])
+        );
+    }
+
+    #[test]
+    fn test_cross_references_resolve_to_anchors() {
+        let book = decode(
+            "* Setup\n:PROPERTIES:\n:CUSTOM_ID: install\n:END:\n\
+             Read [[*Usage][the usage]], [[*Setup]], [[#install]], [[limits]] and [[Usage]].\n\
+             * Usage\nThe <<limits>>limits and <<<radio>>> targets.\n",
+        );
+        assert_eq!(
+            book.chapters[0].content[0],
+            Block::Header {
+                level: 1,
+                content: vec![Inline::text("Setup")],
+                anchor: Some("install".to_string()),
+            }
+        );
+        let Block::Paragraph(text) = &book.chapters[0].content[1] else {
+            panic!("expected a paragraph");
+        };
+        let urls: Vec<&str> = text
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Link { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(urls, ["#usage", "#install", "#install", "#usage", "#usage"]);
+        assert_eq!(
+            text[1],
+            Inline::Link {
+                children: vec![Inline::text("the usage")],
+                url: "#usage".to_string(),
+            }
+        );
+
+        // Plain targets vanish, radio targets keep their text
+        assert_eq!(
+            book.chapters[1].content[1],
+            Block::Paragraph(vec![Inline::text("The limits and radio targets.")])
+        );
+    }
+
+    #[test]
+    fn test_emphasis_markers() {
+        assert_eq!(
+            paragraph("*Bold* /italic/ _under_ ~code~ =verbatim= +strike+ H_{2}O x^{2}"),
+            vec![
+                Inline::Bold(vec![Inline::text("Bold")]),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("italic")]),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("under")]),
+                Inline::text(" "),
+                Inline::Code("code".to_string()),
+                Inline::text(" "),
+                Inline::Code("verbatim".to_string()),
+                Inline::text(" "),
+                Inline::Strikethrough(vec![Inline::text("strike")]),
+                Inline::text(" H"),
+                Inline::Subscript(vec![Inline::text("2")]),
+                Inline::text("O x"),
+                Inline::Superscript(vec![Inline::text("2")]),
+            ]
+        );
+        assert_eq!(
+            paragraph("(*nested /inner/ bold*)."),
+            vec![
+                Inline::text("("),
+                Inline::Bold(vec![
+                    Inline::text("nested "),
+                    Inline::Italic(vec![Inline::text("inner")]),
+                    Inline::text(" bold"),
+                ]),
+                Inline::text(")."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emphasis_needs_word_boundaries() {
+        for text in [
+            "2*3*4 and a/b/c",
+            "snake_case_name",
+            "a * spaced * and / spaced /",
+            "=x=y and ~~",
+            "*one\ntwo\nthree*",
+        ] {
+            assert_eq!(
+                paragraph(text),
+                vec![Inline::text(text.replace('\n', " "))],
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_links_urls_and_images() {
+        assert_eq!(
+            paragraph(
+                "See [[https://example.com][the *site*]], https://example.org/a. and [[file:notes.txt]]"
+            ),
+            vec![
+                Inline::text("See "),
+                Inline::Link {
+                    children: vec![
+                        Inline::text("the "),
+                        Inline::Bold(vec![Inline::text("site")])
+                    ],
+                    url: "https://example.com".to_string(),
+                },
+                Inline::text(", "),
+                Inline::Link {
+                    children: vec![Inline::text("https://example.org/a")],
+                    url: "https://example.org/a".to_string(),
+                },
+                Inline::text(". and "),
+                Inline::Link {
+                    children: vec![Inline::text("notes.txt")],
+                    url: "notes.txt".to_string(),
+                },
+            ]
+        );
+
+        let found = blocks(
+            "#+CAPTION: Overview\n[[file:img/diagram.png]]\n\nA [[./big_map.jpg]] inline.\n",
+        );
+        assert_eq!(
+            found,
+            vec![
+                Block::Image {
+                    resource_key: "img/diagram.png".to_string(),
+                    caption: Some("Overview".to_string()),
+                    alt: "Overview".to_string(),
+                },
+                Block::Paragraph(vec![Inline::text("A  inline.")]),
+                Block::Image {
+                    resource_key: "./big_map.jpg".to_string(),
+                    caption: None,
+                    alt: "big map".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks_keep_their_content() {
+        let found = blocks(
+            "#+begin_src rust :exports code\n  fn main() {\n      println!(\"hi\");\n  }\n  ,* not a headline\n#+end_src\n\n\
+             : fixed\n: width\n\n\
+             #+BEGIN_VERSE\nRoses are red\nViolets are blue\n\nNew stanza\n#+END_VERSE\n\n\
+             #+BEGIN_WARNING\nCareful.\n#+END_WARNING\n\n#+BEGIN_COMMENT\nHidden.\n#+END_COMMENT\n",
+        );
+        assert_eq!(
+            found[0],
+            Block::CodeBlock {
+                lang: Some("rust".to_string()),
+                code: "fn main() {\n    println!(\"hi\");\n}\n* not a headline".to_string(),
+            }
+        );
+        assert_eq!(
+            found[1],
+            Block::CodeBlock {
+                lang: None,
+                code: "fixed\nwidth".to_string(),
+            }
+        );
+        let Block::Blockquote(stanzas) = &found[2] else {
+            panic!("expected a verse, got {:?}", found[2]);
+        };
+        assert_eq!(stanzas.len(), 2);
+        assert!(matches!(
+            &found[3],
+            Block::Admonition {
+                kind: AdmonitionKind::Warning,
+                ..
+            }
+        ));
+        assert_eq!(found.len(), 4);
+    }
+
+    #[test]
+    fn test_plain_and_description_lists() {
+        let found = blocks(
+            "- [X] One\n  continued\n  - Nested\n- [ ] Two\n\n1. [@3] Third\n2. Fourth\n\n\
+             - CPU :: The processor\n- RAM ::\n",
+        );
+        let Block::List {
+            items,
+            ordered: false,
+        } = &found[0]
+        else {
+            panic!("expected a list, got {:?}", found[0]);
+        };
+        assert_eq!(
+            items[0][0],
+            Block::Paragraph(vec![
+                Inline::Checkbox { checked: true },
+                Inline::text("One continued")
+            ])
+        );
+        assert!(matches!(items[0][1], Block::List { .. }));
+        assert_eq!(
+            items[1][0],
+            Block::Paragraph(vec![
+                Inline::Checkbox { checked: false },
+                Inline::text("Two")
+            ])
+        );
+
+        let Block::List {
+            items,
+            ordered: true,
+        } = &found[1]
+        else {
+            panic!("expected a numbered list, got {:?}", found[1]);
+        };
+        assert_eq!(items[0][0], Block::Paragraph(vec![Inline::text("Third")]));
+
+        let Block::DefinitionList(definitions) = &found[2] else {
+            panic!("expected a description list, got {:?}", found[2]);
+        };
+        assert_eq!(definitions[0].term, vec![Inline::text("CPU")]);
+        assert_eq!(definitions[1].term, vec![Inline::text("RAM")]);
+    }
+
+    #[test]
+    fn test_malformed_markup_stays_text() {
+        assert_eq!(
+            paragraph("Open [[link and [fn:1 and <<target and @@html: and x^{2 end"),
+            vec![Inline::text(
+                "Open [[link and [fn:1 and <<target and @@html: and x^{2 end"
+            )]
+        );
+
+        // An unterminated block runs to the end of the document
+        assert_eq!(
+            blocks("#+BEGIN_SRC\ncode\n* Not a headline\n"),
+            vec![Block::CodeBlock {
+                lang: None,
+                code: "code\n* Not a headline".to_string(),
+            }]
+        );
+
+        // A drawer without `:END:` is text, and a line of stars alone is not a headline
+        assert_eq!(
+            blocks("**\n:LOGBOOK:\nnote\n"),
+            vec![Block::Paragraph(vec![Inline::text("** :LOGBOOK: note")])]
+        );
+
+        // Invalid bytes, empty table rows and empty footnote labels decode
+        let book = OrgDecoder::new()
+            .decode(&mut Cursor::new(
+                b"* \xff title\n|\n|-\n[fn:] x\n".as_slice(),
+            ))
+            .unwrap();
+        assert_eq!(book.chapters.len(), 1);
+    }
+}
@@ -0,0 +1,382 @@
+//! Palm database (PDB) decoder implementation
+//!
+//! A PDB file is a Palm OS database: a 78-byte header naming the database and
+//! its type and creator codes, then a list of record offsets. PalmDOC books
+//! (`TEXtREAd`) keep a small header in record 0 and the text in the records after
+//! it, usually compressed with PalmDoc's LZ77 variant. Mobipocket books use the
+//! same container (`BOOKMOBI`) and are handed to [`MobiDecoder`]; eReader books
+//! (`PNRdPPrs`) are recognised but not supported.
+
+use super::chapters::{SplitOptions, SplitStrategy};
+use super::{DecodeContext, Decoder, MobiDecoder, TextDecoder};
+use crate::error::ParseError;
+use crate::types::Book;
+use chrono::{DateTime, Utc};
+use std::io::{Cursor, Read};
+
+/// Length of the database header, up to the record list
+const HEADER_LEN: usize = 78;
+
+/// Most bytes of text a byte of compressed PalmDOC can decompress to
+///
+/// A two-byte back-reference copies at most ten bytes.
+const MAX_EXPANSION: usize = 5;
+
+/// Seconds between the Palm OS epoch (1904) and the Unix epoch
+const PALM_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Decoder for Palm database ebooks (PalmDOC, and Mobipocket by delegation)
+pub struct PdbDecoder {
+    /// Decoder for the text of PalmDOC books
+    text: TextDecoder,
+    /// Decoder for Mobipocket books in a PDB container
+    mobi: MobiDecoder,
+}
+
+/// Kind of book a database holds, from its type and creator codes
+#[derive(Debug, PartialEq)]
+enum PdbKind {
+    PalmDoc,
+    Mobipocket,
+    EReader,
+}
+
+impl PdbDecoder {
+    pub fn new() -> Self {
+        Self {
+            text: TextDecoder::new(),
+            mobi: MobiDecoder::new(),
+        }
+    }
+
+    /// Set where the book is divided into chapters (parts and chapters by default)
+    ///
+    /// PalmDOC text uses the same heading detection as [`TextDecoder`].
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.text = self.text.with_split_strategy(strategy.clone());
+        self.mobi = self.mobi.with_split_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.text = self.text.with_max_chapter_size(chars);
+        self.mobi = self.mobi.with_max_chapter_size(chars);
+        self
+    }
+
+    /// Decode the text records of a PalmDOC book
+    fn decode_palm_doc(
+        &self,
+        data: &[u8],
+        records: &[usize],
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let header = record(data, records, 0)?;
+        if header.len() < 16 {
+            return Err(ParseError::MalformedContent(
+                "PalmDOC header is truncated".to_string(),
+            ));
+        }
+        let compression = u16::from_be_bytes([header[0], header[1]]);
+        let text_length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let record_count = u16::from_be_bytes([header[8], header[9]]) as usize;
+
+        // The header's length is untrusted; the records bound the real text
+        let mut text = Vec::with_capacity(text_length.min(data.len() * MAX_EXPANSION));
+        for index in 1..=record_count.min(records.len().saturating_sub(1)) {
+            let record = record(data, records, index)?;
+            match compression {
+                1 => text.extend_from_slice(record),
+                2 => decompress_palm_doc(record, &mut text),
+                17480 => {
+                    return Err(ParseError::UnsupportedFormat(
+                        "HUFF/CDIC compressed PalmDOC".to_string(),
+                    ))
+                }
+                other => {
+                    return Err(ParseError::UnsupportedFormat(format!(
+                        "PalmDOC compression type {}",
+                        other
+                    )))
+                }
+            }
+        }
+        if text_length > 0 {
+            text.truncate(text_length);
+        }
+
+        self.text
+            .decode_with_context(&mut Cursor::new(text), context)
+    }
+}
+
+impl Default for PdbDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for PdbDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read PDB: {}", e)))?;
+
+        if data.len() < HEADER_LEN {
+            return Err(ParseError::MalformedContent(
+                "File is too short for a Palm database".to_string(),
+            ));
+        }
+
+        let kind = match (&data[60..64], &data[64..68]) {
+            (b"TEXt", b"REAd") => PdbKind::PalmDoc,
+            (b"BOOK", b"MOBI") => PdbKind::Mobipocket,
+            (b"PNRd", b"PPrs") => PdbKind::EReader,
+            (kind, creator) => {
+                return Err(ParseError::UnsupportedFormat(format!(
+                    "Palm database of type {}{}",
+                    String::from_utf8_lossy(kind),
+                    String::from_utf8_lossy(creator)
+                )))
+            }
+        };
+
+        match kind {
+            PdbKind::Mobipocket => self
+                .mobi
+                .decode_with_context(&mut Cursor::new(data), context),
+            PdbKind::EReader => Err(ParseError::UnsupportedFormat(
+                "eReader (PNRdPPrs) books are not supported".to_string(),
+            )),
+            PdbKind::PalmDoc => {
+                let records = record_offsets(&data)?;
+                let mut book = self.decode_palm_doc(&data, &records, context)?;
+
+                let name = database_name(&data);
+                if !name.is_empty() {
+                    book.metadata.title = name;
+                }
+                let created = u32::from_be_bytes([data[36], data[37], data[38], data[39]]);
+                book.metadata.date = palm_date(created);
+                Ok(book)
+            }
+        }
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["pdb", "prc"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/vnd.palm", "application/x-palm-database"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        self.text.set_split_options(options) && self.mobi.set_split_options(options)
+    }
+}
+
+/// Offsets of the database's records, from the record list after the header
+fn record_offsets(data: &[u8]) -> Result<Vec<usize>, ParseError> {
+    let count = u16::from_be_bytes([data[76], data[77]]) as usize;
+    let list = data
+        .get(HEADER_LEN..HEADER_LEN + count * 8)
+        .ok_or_else(|| ParseError::MalformedContent("PDB record list is truncated".to_string()))?;
+    Ok(list
+        .chunks_exact(8)
+        .map(|entry| u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize)
+        .collect())
+}
+
+/// Bytes of a record, which run up to the start of the next one
+fn record<'a>(data: &'a [u8], records: &[usize], index: usize) -> Result<&'a [u8], ParseError> {
+    let start = *records
+        .get(index)
+        .ok_or_else(|| ParseError::MalformedContent(format!("Missing PDB record {}", index)))?;
+    let end = records.get(index + 1).copied().unwrap_or(data.len());
+    data.get(start..end.max(start)).ok_or_else(|| {
+        ParseError::MalformedContent(format!("PDB record {} is out of range", index))
+    })
+}
+
+/// Name of the database, from the NUL-padded first 32 bytes
+fn database_name(data: &[u8]) -> String {
+    let name = &data[..32];
+    let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    let (name, _) = super::text::decode_bytes(&name[..end]);
+    name.replace('_', " ").trim().to_string()
+}
+
+/// Convert a PDB timestamp to a date
+///
+/// Palm OS counts seconds from 1904 as an unsigned number, but many tools wrote
+/// signed seconds from 1970 instead; the two are told apart by the high bit.
+fn palm_date(seconds: u32) -> Option<DateTime<Utc>> {
+    if seconds == 0 {
+        return None;
+    }
+    let unix = if seconds & 0x8000_0000 != 0 {
+        seconds as i64 - PALM_EPOCH_OFFSET
+    } else {
+        seconds as i64
+    };
+    DateTime::from_timestamp(unix, 0)
+}
+
+/// Decompress a PalmDoc LZ77 record onto the end of `out`
+///
+/// Each byte is a literal, a count of literals to copy, a space followed by a
+/// character, or the first of two bytes giving a distance back into the output
+/// and a length to copy from there.
+fn decompress_palm_doc(input: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < input.len() {
+        let byte = input[i];
+        i += 1;
+        match byte {
+            0x01..=0x08 => {
+                let end = (i + byte as usize).min(input.len());
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+            }
+            0x00 | 0x09..=0x7F => out.push(byte),
+            0x80..=0xBF => {
+                let Some(&next) = input.get(i) else {
+                    break;
+                };
+                i += 1;
+                let pair = (u16::from(byte) << 8 | u16::from(next)) & 0x3FFF;
+                let distance = (pair >> 3) as usize;
+                let length = (pair & 0x07) as usize + 3;
+                if distance == 0 || distance > out.len() {
+                    continue;
+                }
+                // The source may overlap what is being written, so copy byte by byte
+                let start = out.len() - distance;
+                for offset in 0..length {
+                    out.push(out[start + offset]);
+                }
+            }
+            0xC0..=0xFF => {
+                out.push(b' ');
+                out.push(byte ^ 0x80);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a database with the given type/creator and records
+    fn pdb(name: &str, kind: &[u8; 8], records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0u8; HEADER_LEN];
+        data[..name.len()].copy_from_slice(name.as_bytes());
+        data[36..40].copy_from_slice(&1_000_000_000u32.to_be_bytes());
+        data[60..68].copy_from_slice(kind);
+        data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+
+        let mut offset = HEADER_LEN + records.len() * 8 + 2;
+        for record in records {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&[0; 4]);
+            offset += record.len();
+        }
+        data.extend_from_slice(&[0, 0]);
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    fn palm_doc_header(compression: u16, length: usize, records: u16) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&compression.to_be_bytes());
+        header.extend_from_slice(&[0, 0]);
+        header.extend_from_slice(&(length as u32).to_be_bytes());
+        header.extend_from_slice(&records.to_be_bytes());
+        header.extend_from_slice(&4096u16.to_be_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header
+    }
+
+    #[test]
+    fn test_decompress_palm_doc() {
+        // "abc" literal, a copy of 3 bytes from 3 back, a space pair, and a
+        // counted literal run
+        let input = [b'a', b'b', b'c', 0x80, 0x18, 0xE1, 0x02, 0xC0, b'!', b'\n'];
+        let mut out = Vec::new();
+        decompress_palm_doc(&input, &mut out);
+        assert_eq!(out, b"abcabc a\xC0!\n");
+    }
+
+    #[test]
+    fn test_decode_palm_doc() {
+        let text = "CHAPTER I\n\nIt was a dark night.\n\nCHAPTER II\n\nThe storm passed.\n";
+        let (first, second) = text.as_bytes().split_at(20);
+        let book = PdbDecoder::new()
+            .decode(&mut Cursor::new(pdb(
+                "Dark_Night",
+                b"TEXtREAd",
+                &[
+                    palm_doc_header(1, text.len(), 2),
+                    first.to_vec(),
+                    second.to_vec(),
+                ],
+            )))
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "Dark Night");
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y").to_string()),
+            Some("2001".to_string())
+        );
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["CHAPTER I", "CHAPTER II"]);
+    }
+
+    #[test]
+    fn test_decode_palm_doc_with_overstated_length() {
+        let text = "CHAPTER I\n\nA short book.\n";
+        let book = PdbDecoder::new()
+            .decode(&mut Cursor::new(pdb(
+                "Short",
+                b"TEXtREAd",
+                &[
+                    palm_doc_header(1, u32::MAX as usize, 1),
+                    text.as_bytes().to_vec(),
+                ],
+            )))
+            .unwrap();
+
+        assert_eq!(book.chapters.len(), 1);
+        assert_eq!(book.chapters[0].title, "CHAPTER I");
+    }
+
+    #[test]
+    fn test_decode_unsupported_kinds() {
+        let decoder = PdbDecoder::new();
+        let ereader = pdb("Book", b"PNRdPPrs", &[vec![0; 16]]);
+        assert!(matches!(
+            decoder.decode(&mut Cursor::new(ereader)),
+            Err(ParseError::UnsupportedFormat(_))
+        ));
+
+        let other = pdb("Addresses", b"DATAaddr", &[vec![0; 16]]);
+        assert!(matches!(
+            decoder.decode(&mut Cursor::new(other)),
+            Err(ParseError::UnsupportedFormat(_))
+        ));
+    }
+}
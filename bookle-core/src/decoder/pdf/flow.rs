@@ -0,0 +1,653 @@
+//! Reading order reconstruction for PDF pages
+//!
+//! Content streams draw text in whatever order the producing application chose, which
+//! for multi-column layouts usually interleaves lines from neighbouring columns. This
+//! module finds column gutters on each page, reads the columns in the book's reading
+//! direction and keeps captions and sidebars out of the main body flow.
+
+use super::layout::{continues_across_break, join_line, paragraph_text, split_paragraphs};
+use super::layout::{PageText, TextLine};
+use crate::types::ReadingDirection;
+use std::collections::HashMap;
+
+/// Largest share of a page's lines that may cross a gutter (titles, abstracts, footers)
+const MAX_GUTTER_COVERAGE: f64 = 0.2;
+
+/// Minimum gutter width, in ems of body text
+const MIN_GUTTER_EMS: f64 = 1.0;
+
+/// Minimum number of lines a column must hold for its gutter to count
+const MIN_COLUMN_LINES: usize = 3;
+
+/// Text smaller than this fraction of the body size is caption or sidebar text
+const SMALL_TEXT_RATIO: f64 = 0.9;
+
+/// Maximum distance (in ems of the caption text) between an image and its caption
+const CAPTION_GAP_EMS: f64 = 2.0;
+
+/// An image placed on a page, with its bounding box in top-down page coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PageImage {
+    /// Resource key of the image
+    pub key: String,
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+/// A piece of page content in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PageItem {
+    /// Body paragraph
+    Paragraph(String),
+    /// Paragraphs of a sidebar, kept apart from the body flow
+    Aside(Vec<String>),
+    /// Image, by resource key, with the caption set beside it
+    Image {
+        key: String,
+        caption: Option<String>,
+    },
+}
+
+/// Something drawn on the page that takes part in reading order
+#[derive(Clone, Copy)]
+enum Element<'a> {
+    Line(&'a TextLine),
+    Image(&'a PageImage),
+}
+
+impl Element<'_> {
+    /// Top edge used for ordering
+    fn top(&self) -> f64 {
+        match self {
+            Element::Line(line) => line.y - line.font_size,
+            Element::Image(image) => image.top,
+        }
+    }
+
+    fn left(&self) -> f64 {
+        match self {
+            Element::Line(line) => line.x,
+            Element::Image(image) => image.left,
+        }
+    }
+
+    fn right(&self) -> f64 {
+        match self {
+            Element::Line(line) => line.right(),
+            Element::Image(image) => image.right,
+        }
+    }
+}
+
+/// A paragraph with the geometry needed to classify it
+struct TextBlock<'a> {
+    lines: &'a [TextLine],
+    /// Whether this paragraph opens a run of lines (after a column, band or image change)
+    run_start: bool,
+    /// Whether this paragraph sits in a sidebar column
+    sidebar: bool,
+}
+
+impl TextBlock<'_> {
+    fn font_size(&self) -> f64 {
+        self.lines.iter().map(|l| l.font_size).fold(0.0, f64::max)
+    }
+
+    fn top(&self) -> f64 {
+        self.lines
+            .iter()
+            .map(|l| l.y - l.font_size)
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    fn bottom(&self) -> f64 {
+        self.lines
+            .iter()
+            .map(|l| l.y)
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    fn overlaps(&self, image: &PageImage) -> bool {
+        let left = self.lines.iter().map(|l| l.x).fold(f64::INFINITY, f64::min);
+        let right = self.lines.iter().map(TextLine::right).fold(0.0, f64::max);
+        left < image.right && right > image.left
+    }
+}
+
+/// A stretch of the reading order
+enum Run<'a> {
+    Lines { lines: Vec<TextLine>, sidebar: bool },
+    Image(&'a PageImage),
+}
+
+/// Most common font size across the document, weighted by text length
+pub(crate) fn body_font_size(pages: &[PageText]) -> f64 {
+    let mut sizes: HashMap<i64, usize> = HashMap::new();
+    for line in pages.iter().flat_map(|page| &page.lines) {
+        *sizes
+            .entry((line.font_size * 2.0).round() as i64)
+            .or_default() += line.text.len();
+    }
+
+    sizes
+        .into_iter()
+        .max_by_key(|(size, count)| (*count, *size))
+        .map(|(size, _)| size as f64 / 2.0)
+        .unwrap_or(12.0)
+}
+
+/// Lay out a page's text and images as content items in reading order
+pub(crate) fn page_items(
+    lines: &[TextLine],
+    images: &[PageImage],
+    body_size: f64,
+    direction: ReadingDirection,
+) -> Vec<PageItem> {
+    let runs = reading_order(lines, images, body_size, direction);
+
+    let mut blocks = Vec::new();
+    for (index, run) in runs.iter().enumerate() {
+        match run {
+            Run::Lines { lines, sidebar } => {
+                for (n, paragraph) in split_paragraphs(lines).into_iter().enumerate() {
+                    blocks.push((
+                        index,
+                        Some(TextBlock {
+                            lines: paragraph,
+                            run_start: n == 0,
+                            sidebar: *sidebar,
+                        }),
+                    ));
+                }
+            }
+            Run::Image(_) => blocks.push((index, None)),
+        }
+    }
+
+    let mut items: Vec<PageItem> = Vec::new();
+    let mut last_image: Option<&PageImage> = None;
+    let mut pending_caption: Option<String> = None;
+
+    for (position, (run, block)) in blocks.iter().enumerate() {
+        let Some(block) = block else {
+            let Run::Image(image) = &runs[*run] else {
+                continue;
+            };
+            items.push(PageItem::Image {
+                key: image.key.clone(),
+                caption: pending_caption.take(),
+            });
+            last_image = Some(image);
+            continue;
+        };
+
+        let text = paragraph_text(block.lines);
+        let small = block.font_size() < body_size * SMALL_TEXT_RATIO;
+
+        if small {
+            let gap = block.font_size() * CAPTION_GAP_EMS;
+
+            // A caption directly below the image just placed
+            if let (Some(image), Some(PageItem::Image { caption, .. })) =
+                (last_image, items.last_mut())
+            {
+                let distance = block.top() - image.bottom;
+                if caption.is_none() && distance.abs() <= gap && block.overlaps(image) {
+                    *caption = Some(text);
+                    continue;
+                }
+            }
+
+            // A caption directly above the next image
+            if let Some((next, None)) = blocks.get(position + 1) {
+                if let Run::Image(image) = &runs[*next] {
+                    let distance = image.top - block.bottom();
+                    if distance.abs() <= gap && block.overlaps(image) {
+                        pending_caption = Some(text);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        last_image = None;
+
+        if block.sidebar {
+            match items.last_mut() {
+                Some(PageItem::Aside(paragraphs)) if !block.run_start => paragraphs.push(text),
+                _ => items.push(PageItem::Aside(vec![text])),
+            }
+            continue;
+        }
+
+        // A paragraph broken by a column change or an image picks up where it left off
+        if block.run_start {
+            if let Some(previous) = last_paragraph(&mut items) {
+                if continues_across_break(previous, &text) {
+                    join_line(previous, &text);
+                    continue;
+                }
+            }
+        }
+
+        items.push(PageItem::Paragraph(text));
+    }
+
+    items
+}
+
+/// Join per-page content, re-uniting paragraphs split by a page break
+///
+/// Images and sidebars at the top of a page do not interrupt a continuing paragraph;
+/// they are placed after it instead. Also returns the index of the first item of each
+/// page after the first.
+pub(crate) fn merge_pages(pages: Vec<Vec<PageItem>>) -> (Vec<PageItem>, Vec<usize>) {
+    let mut merged: Vec<PageItem> = Vec::new();
+    let mut page_starts = Vec::new();
+
+    for mut items in pages {
+        let first_text = items
+            .iter()
+            .position(|item| matches!(item, PageItem::Paragraph(_)));
+
+        if let (Some(last), Some(index)) = (last_paragraph(&mut merged), first_text) {
+            if let PageItem::Paragraph(first) = &items[index] {
+                if continues_across_break(last, first) {
+                    join_line(last, first);
+                    items.remove(index);
+                }
+            }
+        }
+
+        if !merged.is_empty() && !items.is_empty() {
+            page_starts.push(merged.len());
+        }
+        merged.extend(items);
+    }
+
+    (merged, page_starts)
+}
+
+/// The most recent body paragraph, looking past images and sidebars
+fn last_paragraph(items: &mut [PageItem]) -> Option<&mut String> {
+    items.iter_mut().rev().find_map(|item| match item {
+        PageItem::Paragraph(text) => Some(text),
+        _ => None,
+    })
+}
+
+/// Order a page's lines and images into runs
+///
+/// Elements crossing a gutter split the page into horizontal bands. Within each band
+/// the columns are read one after another in the reading direction.
+fn reading_order<'a>(
+    lines: &'a [TextLine],
+    images: &'a [PageImage],
+    body_size: f64,
+    direction: ReadingDirection,
+) -> Vec<Run<'a>> {
+    let columns = find_columns(lines, body_size);
+    let tolerance = body_size * 0.5;
+
+    let column_of = |element: &Element| {
+        columns.iter().position(|(left, right)| {
+            element.left() >= left - tolerance && element.right() <= right + tolerance
+        })
+    };
+
+    let mut elements: Vec<Element> = lines.iter().map(Element::Line).collect();
+    if columns.len() > 1 {
+        // Multiple columns: the stream order can't be trusted, so go by position
+        elements.sort_by(|a, b| a.top().total_cmp(&b.top()));
+    }
+
+    // Images are slotted in before the first line below their top edge
+    let mut sorted_images: Vec<&PageImage> = images.iter().collect();
+    sorted_images.sort_by(|a, b| a.top.total_cmp(&b.top));
+    for image in sorted_images {
+        let index = elements
+            .iter()
+            .position(|e| matches!(e, Element::Line(_)) && e.top() >= image.top)
+            .unwrap_or(elements.len());
+        elements.insert(index, Element::Image(image));
+    }
+
+    // Assign bands and columns; runs of spanning elements get a band of their own
+    let mut band = 0;
+    let mut spanning = false;
+    let mut placed: Vec<(usize, Option<usize>, Element)> = Vec::new();
+    for element in elements {
+        let column = match column_of(&element) {
+            _ if columns.len() <= 1 => Some(0),
+            column => column,
+        };
+        if column.is_none() != spanning {
+            spanning = column.is_none();
+            band += 1;
+        }
+        placed.push((band, column, element));
+    }
+
+    let mut column_order: Vec<usize> = (0..columns.len().max(1)).collect();
+    if direction == ReadingDirection::RightToLeft {
+        column_order.reverse();
+    }
+
+    let sidebars: Vec<bool> = (0..columns.len())
+        .map(|column| {
+            let sizes: Vec<f64> = placed
+                .iter()
+                .filter_map(|(_, c, e)| match e {
+                    Element::Line(line) if *c == Some(column) => Some(line.font_size),
+                    _ => None,
+                })
+                .collect();
+            columns.len() > 1 && median(sizes) < body_size * SMALL_TEXT_RATIO
+        })
+        .collect();
+
+    let mut runs = Vec::new();
+    for band in 0..=band {
+        let in_band: Vec<&(usize, Option<usize>, Element)> =
+            placed.iter().filter(|(b, _, _)| *b == band).collect();
+
+        if in_band.iter().all(|(_, column, _)| column.is_none()) {
+            for (_, _, element) in &in_band {
+                push_element(&mut runs, *element, false);
+            }
+            end_run(&mut runs);
+            continue;
+        }
+
+        for &column in &column_order {
+            let sidebar = sidebars.get(column).copied().unwrap_or(false);
+            for (_, _, element) in in_band.iter().filter(|(_, c, _)| *c == Some(column)) {
+                push_element(&mut runs, *element, sidebar);
+            }
+            end_run(&mut runs);
+        }
+    }
+
+    runs.retain(|run| !matches!(run, Run::Lines { lines, .. } if lines.is_empty()));
+    runs
+}
+
+/// Append an element to the current run of lines, or as an image run
+fn push_element<'a>(runs: &mut Vec<Run<'a>>, element: Element<'a>, sidebar: bool) {
+    match element {
+        Element::Line(line) => match runs.last_mut() {
+            Some(Run::Lines {
+                lines,
+                sidebar: run_sidebar,
+            }) => {
+                if lines.is_empty() {
+                    *run_sidebar = sidebar;
+                }
+                lines.push(line.clone());
+            }
+            _ => runs.push(Run::Lines {
+                lines: vec![line.clone()],
+                sidebar,
+            }),
+        },
+        Element::Image(image) => runs.push(Run::Image(image)),
+    }
+}
+
+/// Close the current run so the next line starts a new one
+fn end_run(runs: &mut Vec<Run>) {
+    if matches!(runs.last(), Some(Run::Lines { lines, .. }) if !lines.is_empty()) {
+        runs.push(Run::Lines {
+            lines: Vec::new(),
+            sidebar: false,
+        });
+    }
+}
+
+/// Find the columns of a page as `(left, right)` ranges, left to right
+///
+/// A gutter is a vertical strip crossed by few lines, with enough lines entirely on
+/// either side of it. Pages without gutters have a single column.
+fn find_columns(lines: &[TextLine], body_size: f64) -> Vec<(f64, f64)> {
+    let Some(left) = lines.iter().map(|l| l.x).min_by(f64::total_cmp) else {
+        return Vec::new();
+    };
+    let right = lines.iter().map(TextLine::right).fold(left, f64::max);
+
+    let mut events: Vec<(f64, i32)> = lines
+        .iter()
+        .flat_map(|line| [(line.x, 1), (line.right(), -1)])
+        .collect();
+    events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    let limit = (lines.len() as f64 * MAX_GUTTER_COVERAGE).floor() as i32;
+    let mut gutters = Vec::new();
+    let mut coverage = 0;
+    let mut gap_start: Option<f64> = None;
+
+    for (x, delta) in events {
+        coverage += delta;
+        if coverage <= limit {
+            gap_start.get_or_insert(x);
+        } else if let Some(start) = gap_start.take() {
+            if start > left && x - start >= body_size * MIN_GUTTER_EMS {
+                gutters.push((start, x));
+            }
+        }
+    }
+
+    // Drop gutters that leave too few lines on one side
+    loop {
+        let columns = columns_between(left, right, &gutters);
+        let sparse = columns.iter().position(|(l, r)| {
+            lines
+                .iter()
+                .filter(|line| line.x >= *l && line.right() <= *r)
+                .count()
+                < MIN_COLUMN_LINES
+        });
+        match sparse {
+            Some(column) if !gutters.is_empty() => {
+                gutters.remove(column.min(gutters.len() - 1));
+            }
+            _ => return columns,
+        }
+    }
+}
+
+/// Column ranges separated by gutters
+fn columns_between(left: f64, right: f64, gutters: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut columns = Vec::new();
+    let mut start = left;
+    for (gutter_start, gutter_end) in gutters {
+        columns.push((start, *gutter_start));
+        start = *gutter_end;
+    }
+    columns.push((start, right));
+    columns
+}
+
+/// Median of a list of values (0 when empty)
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    values[values.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, x: f64, y: f64, width: f64) -> TextLine {
+        TextLine {
+            text: text.to_string(),
+            x,
+            y,
+            width,
+            font_size: 10.0,
+        }
+    }
+
+    fn texts(items: &[PageItem]) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| match item {
+                PageItem::Paragraph(text) => text.clone(),
+                PageItem::Aside(paragraphs) => format!("[aside] {}", paragraphs.join(" / ")),
+                PageItem::Image { key, caption } => {
+                    format!("[{}] {}", key, caption.as_deref().unwrap_or(""))
+                }
+            })
+            .collect()
+    }
+
+    /// Two columns drawn line by line across the page, as many producers emit them
+    fn two_column_page() -> Vec<TextLine> {
+        let mut lines = vec![line("A Study of Columns", 72.0, 60.0, 468.0)];
+        let left = [
+            "Left one begins",
+            "and continues",
+            "down the column",
+            "to its end.",
+        ];
+        let right = [
+            "Right one begins",
+            "and continues",
+            "down the column",
+            "to its end.",
+        ];
+        for (row, (l, r)) in left.iter().zip(right.iter()).enumerate() {
+            let y = 100.0 + row as f64 * 12.0;
+            lines.push(line(l, 72.0, y, 220.0));
+            lines.push(line(r, 320.0, y, 220.0));
+        }
+        lines
+    }
+
+    #[test]
+    fn test_find_columns() {
+        let columns = find_columns(&two_column_page(), 10.0);
+        assert_eq!(columns, vec![(72.0, 292.0), (320.0, 540.0)]);
+
+        let single: Vec<TextLine> = (0..6)
+            .map(|i| line("Body text", 72.0, 100.0 + i as f64 * 12.0, 468.0))
+            .collect();
+        assert_eq!(find_columns(&single, 10.0).len(), 1);
+    }
+
+    #[test]
+    fn test_columns_read_in_order() {
+        let items = page_items(&two_column_page(), &[], 10.0, ReadingDirection::LeftToRight);
+        assert_eq!(
+            texts(&items),
+            vec![
+                "A Study of Columns",
+                "Left one begins and continues down the column to its end.",
+                "Right one begins and continues down the column to its end.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_columns_right_to_left() {
+        let items = page_items(&two_column_page(), &[], 10.0, ReadingDirection::RightToLeft);
+        assert_eq!(
+            texts(&items)[1..],
+            [
+                "Right one begins and continues down the column to its end.",
+                "Left one begins and continues down the column to its end.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_continues_into_next_column() {
+        let mut lines = vec![];
+        for row in 0..4 {
+            let y = 100.0 + row as f64 * 12.0;
+            lines.push(line("words words words", 72.0, y, 220.0));
+            lines.push(line("more words here", 320.0, y, 220.0));
+        }
+        lines.last_mut().unwrap().text = "the end.".to_string();
+
+        let items = page_items(&lines, &[], 10.0, ReadingDirection::LeftToRight);
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn test_sidebar_kept_apart() {
+        let mut lines = vec![];
+        for row in 0..4 {
+            let y = 100.0 + row as f64 * 12.0;
+            lines.push(line("body words words", 72.0, y, 300.0));
+            let mut note = line("sidebar note", 420.0, y, 120.0);
+            note.font_size = 8.0;
+            lines.push(note);
+        }
+
+        let items = page_items(&lines, &[], 10.0, ReadingDirection::LeftToRight);
+        assert!(matches!(items[0], PageItem::Paragraph(_)));
+        assert!(matches!(&items[1], PageItem::Aside(p) if p.len() == 1));
+    }
+
+    #[test]
+    fn test_caption_attached_to_image() {
+        let mut caption = line("Figure 1: A chart.", 72.0, 320.0, 200.0);
+        caption.font_size = 8.0;
+        let lines = vec![
+            line("Text above the figure.", 72.0, 100.0, 300.0),
+            caption,
+            line("Text below the figure.", 72.0, 400.0, 300.0),
+        ];
+        let images = vec![PageImage {
+            key: "chart".to_string(),
+            left: 72.0,
+            top: 150.0,
+            right: 372.0,
+            bottom: 305.0,
+        }];
+
+        let items = page_items(&lines, &images, 10.0, ReadingDirection::LeftToRight);
+        assert_eq!(
+            texts(&items),
+            vec![
+                "Text above the figure.",
+                "[chart] Figure 1: A chart.",
+                "Text below the figure.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_pages() {
+        let paragraph = |text: &str| PageItem::Paragraph(text.to_string());
+        let pages = vec![
+            vec![paragraph("Intro."), paragraph("The story contin-")],
+            vec![
+                PageItem::Image {
+                    key: "figure".to_string(),
+                    caption: None,
+                },
+                paragraph("ues on the next page."),
+                paragraph("New one."),
+            ],
+        ];
+
+        let (merged, page_starts) = merge_pages(pages);
+
+        assert_eq!(
+            texts(&merged),
+            vec![
+                "Intro.",
+                "The story continues on the next page.",
+                "[figure] ",
+                "New one.",
+            ]
+        );
+        assert_eq!(page_starts, vec![2]);
+    }
+}
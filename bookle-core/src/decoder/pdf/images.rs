@@ -0,0 +1,441 @@
+//! Image extraction from PDF pages
+//!
+//! Image XObjects are located by walking each page's content stream while tracking
+//! the current transformation matrix, so every image can be placed in reading order
+//! relative to the surrounding text. JPEG data is passed through untouched; raw and
+//! Flate-compressed rasters are re-encoded as PNG.
+
+use crate::types::Resource;
+use pdf_extract::content::Content;
+use pdf_extract::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+
+/// Maximum depth of nested form XObjects to follow
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Minimum number of pages an image must repeat on to be treated as decoration
+const MIN_DECORATION_REPEATS: usize = 3;
+
+/// 2D affine transformation matrix `[a b c d e f]`
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// An image drawn on a page
+pub(crate) struct PlacedImage {
+    /// Object ID of the image XObject
+    pub id: ObjectId,
+    /// Left edge in points
+    pub left: f64,
+    /// Top edge in points, measured from the top of the page
+    pub top: f64,
+    /// Right edge in points
+    pub right: f64,
+    /// Bottom edge in points, measured from the top of the page
+    pub bottom: f64,
+    /// Decoded image data
+    pub resource: Resource,
+}
+
+/// Colour space of raster image samples
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Palette of RGB triples
+    Indexed(Vec<u8>),
+}
+
+/// Extract all images drawn on a page that are at least `min_size` pixels in both dimensions
+pub(crate) fn page_images(
+    doc: &Document,
+    page_id: ObjectId,
+    page_height: f64,
+    min_size: u32,
+) -> Vec<PlacedImage> {
+    let Ok(content) = doc.get_page_content(page_id) else {
+        return Vec::new();
+    };
+
+    let mut resources = Vec::new();
+    if let Ok((inline, ids)) = doc.get_page_resources(page_id) {
+        resources.extend(inline);
+        resources.extend(ids.into_iter().filter_map(|id| doc.get_dictionary(id).ok()));
+    }
+
+    let mut placements = Vec::new();
+    walk_content(doc, &content, &resources, IDENTITY, 0, &mut placements);
+
+    placements
+        .into_iter()
+        .filter_map(|(id, ctm, stream)| {
+            let width = dict_number(&stream.dict, b"Width")? as u32;
+            let height = dict_number(&stream.dict, b"Height")? as u32;
+            if width < min_size || height < min_size {
+                return None;
+            }
+            let resource = decode_image(doc, stream, width, height)?;
+            let (left, bottom, right, top) = bounds(&ctm);
+            Some(PlacedImage {
+                id,
+                left,
+                top: page_height - top,
+                right,
+                bottom: page_height - bottom,
+                resource,
+            })
+        })
+        .collect()
+}
+
+/// Drop images that repeat on many pages (logos, ornaments, page backgrounds)
+pub(crate) fn drop_repeated(pages: &mut [Vec<PlacedImage>]) {
+    let mut counts: HashMap<ObjectId, usize> = HashMap::new();
+    for images in pages.iter() {
+        let mut ids: Vec<ObjectId> = images.iter().map(|image| image.id).collect();
+        ids.dedup();
+        for id in ids {
+            *counts.entry(id).or_default() += 1;
+        }
+    }
+
+    for images in pages.iter_mut() {
+        images.retain(|image| counts[&image.id] < MIN_DECORATION_REPEATS);
+    }
+}
+
+/// Walk a content stream collecting `(id, ctm, stream)` for every image painted
+fn walk_content<'a>(
+    doc: &'a Document,
+    content: &[u8],
+    resources: &[&'a Dictionary],
+    base: Matrix,
+    depth: usize,
+    placements: &mut Vec<(ObjectId, Matrix, &'a Stream)>,
+) {
+    let Ok(content) = Content::decode(content) else {
+        return;
+    };
+
+    let mut ctm = base;
+    let mut stack = Vec::new();
+
+    for operation in &content.operations {
+        match operation.operator.as_str() {
+            "q" => stack.push(ctm),
+            "Q" => ctm = stack.pop().unwrap_or(base),
+            "cm" => {
+                if let Some(matrix) = matrix_from(&operation.operands) {
+                    ctm = multiply(&matrix, &ctm);
+                }
+            }
+            "Do" => {
+                let Some(name) = operation.operands.first().and_then(|o| o.as_name().ok()) else {
+                    continue;
+                };
+                let Some((id, stream)) = lookup_xobject(doc, resources, name) else {
+                    continue;
+                };
+
+                match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                    Ok(b"Image") => placements.push((id, ctm, stream)),
+                    Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                        let matrix = stream
+                            .dict
+                            .get(b"Matrix")
+                            .ok()
+                            .and_then(|m| m.as_array().ok())
+                            .and_then(|m| matrix_from(m))
+                            .unwrap_or(IDENTITY);
+
+                        // Forms without their own resources inherit the caller's
+                        let form_resources = stream
+                            .dict
+                            .get(b"Resources")
+                            .ok()
+                            .and_then(|r| doc.dereference(r).ok())
+                            .and_then(|(_, r)| r.as_dict().ok())
+                            .map(|r| vec![r])
+                            .unwrap_or_else(|| resources.to_vec());
+
+                        if let Ok(form_content) = stream.get_plain_content() {
+                            walk_content(
+                                doc,
+                                &form_content,
+                                &form_resources,
+                                multiply(&matrix, &ctm),
+                                depth + 1,
+                                placements,
+                            );
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Find a named XObject in a list of resource dictionaries
+fn lookup_xobject<'a>(
+    doc: &'a Document,
+    resources: &[&'a Dictionary],
+    name: &[u8],
+) -> Option<(ObjectId, &'a Stream)> {
+    resources.iter().find_map(|resources| {
+        let xobjects = resources.get(b"XObject").ok()?;
+        let (_, xobjects) = doc.dereference(xobjects).ok()?;
+        let id = xobjects
+            .as_dict()
+            .ok()?
+            .get(name)
+            .ok()?
+            .as_reference()
+            .ok()?;
+        let stream = doc.get_object(id).ok()?.as_stream().ok()?;
+        Some((id, stream))
+    })
+}
+
+/// Decode an image XObject into a resource
+fn decode_image(doc: &Document, stream: &Stream, width: u32, height: u32) -> Option<Resource> {
+    let dict = &stream.dict;
+
+    // Stencil masks are glyph-like shapes painted in the fill colour, not pictures
+    if dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false)
+    {
+        return None;
+    }
+
+    let filters = stream.filters().unwrap_or_default();
+    match filters.last().map(String::as_str) {
+        Some("DCTDecode") if filters.len() == 1 => {
+            return Some(Resource::new("image/jpeg", stream.content.clone()));
+        }
+        Some("FlateDecode" | "LZWDecode" | "ASCII85Decode") | None => {}
+        _ => return None,
+    }
+
+    // lopdf refuses to decompress image streams directly, so decode a copy without
+    // the Subtype marker to get predictor handling for free
+    let samples = if filters.is_empty() {
+        stream.content.clone()
+    } else {
+        let mut plain = stream.clone();
+        plain.dict.remove(b"Subtype");
+        plain.decompressed_content().ok()?
+    };
+
+    let bits = dict_number(dict, b"BitsPerComponent").unwrap_or(8.0) as u8;
+    let color_space = color_space(doc, dict.get(b"ColorSpace").ok()?)?;
+    let png = encode_png(width, height, bits, &color_space, &samples)?;
+
+    Some(Resource::new("image/png", png))
+}
+
+/// Resolve an image colour space
+fn color_space(doc: &Document, object: &Object) -> Option<ColorSpace> {
+    let (_, object) = doc.dereference(object).ok()?;
+
+    match object {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"CalGray" | b"G" => Some(ColorSpace::Gray),
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(ColorSpace::Rgb),
+            b"DeviceCMYK" | b"CMYK" => Some(ColorSpace::Cmyk),
+            _ => None,
+        },
+        Object::Array(items) => {
+            let family = items.first()?.as_name().ok()?;
+            match family {
+                b"ICCBased" => {
+                    let (_, profile) = doc.dereference(items.get(1)?).ok()?;
+                    match dict_number(&profile.as_stream().ok()?.dict, b"N")? as u8 {
+                        1 => Some(ColorSpace::Gray),
+                        3 => Some(ColorSpace::Rgb),
+                        4 => Some(ColorSpace::Cmyk),
+                        _ => None,
+                    }
+                }
+                b"CalGray" => Some(ColorSpace::Gray),
+                b"CalRGB" => Some(ColorSpace::Rgb),
+                b"Indexed" | b"I" => {
+                    let base = color_space(doc, items.get(1)?)?;
+                    let (_, lookup) = doc.dereference(items.get(3)?).ok()?;
+                    let table = match lookup {
+                        Object::String(bytes, _) => bytes.clone(),
+                        Object::Stream(stream) => stream.get_plain_content().ok()?,
+                        _ => return None,
+                    };
+                    let palette = match base {
+                        ColorSpace::Rgb => table,
+                        ColorSpace::Gray => table.iter().flat_map(|&v| [v, v, v]).collect(),
+                        ColorSpace::Cmyk => table.chunks_exact(4).flat_map(cmyk_to_rgb).collect(),
+                        ColorSpace::Indexed(_) => return None,
+                    };
+                    Some(ColorSpace::Indexed(palette))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Encode raw image samples as PNG
+fn encode_png(
+    width: u32,
+    height: u32,
+    bits: u8,
+    color_space: &ColorSpace,
+    samples: &[u8],
+) -> Option<Vec<u8>> {
+    let (color_type, components, data) = match color_space {
+        ColorSpace::Gray => (png::ColorType::Grayscale, 1, samples.to_vec()),
+        ColorSpace::Rgb => (png::ColorType::Rgb, 3, samples.to_vec()),
+        ColorSpace::Indexed(_) => (png::ColorType::Indexed, 1, samples.to_vec()),
+        ColorSpace::Cmyk if bits == 8 => (
+            png::ColorType::Rgb,
+            3,
+            samples.chunks_exact(4).flat_map(cmyk_to_rgb).collect(),
+        ),
+        ColorSpace::Cmyk => return None,
+    };
+
+    let depth = match (bits, color_type) {
+        (1, png::ColorType::Grayscale | png::ColorType::Indexed) => png::BitDepth::One,
+        (2, png::ColorType::Grayscale | png::ColorType::Indexed) => png::BitDepth::Two,
+        (4, png::ColorType::Grayscale | png::ColorType::Indexed) => png::BitDepth::Four,
+        (8, _) => png::BitDepth::Eight,
+        (16, png::ColorType::Grayscale | png::ColorType::Rgb) => png::BitDepth::Sixteen,
+        _ => return None,
+    };
+
+    // CMYK samples were already converted to 8-bit RGB above
+    let row_bytes = (width as usize * components * bits as usize).div_ceil(8);
+    let expected = row_bytes * height as usize;
+    if data.len() < expected {
+        return None;
+    }
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut output, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(depth);
+        if let ColorSpace::Indexed(palette) = color_space {
+            encoder.set_palette(palette.clone());
+        }
+        let mut writer = encoder.write_header().ok()?;
+        writer.write_image_data(&data[..expected]).ok()?;
+        writer.finish().ok()?;
+    }
+
+    Some(output)
+}
+
+/// Naive CMYK to RGB conversion
+fn cmyk_to_rgb(cmyk: &[u8]) -> [u8; 3] {
+    let k = 255 - cmyk[3] as u32;
+    let channel = |c: u8| ((255 - c as u32) * k / 255) as u8;
+    [channel(cmyk[0]), channel(cmyk[1]), channel(cmyk[2])]
+}
+
+/// Bounding box `(min x, min y, max x, max y)` in PDF user space of the unit square
+/// transformed by `ctm`
+fn bounds(ctm: &Matrix) -> (f64, f64, f64, f64) {
+    [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+        .iter()
+        .map(|(x, y)| {
+            (
+                x * ctm[0] + y * ctm[2] + ctm[4],
+                x * ctm[1] + y * ctm[3] + ctm[5],
+            )
+        })
+        .fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(x0, y0, x1, y1), (x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        )
+}
+
+/// Multiply two matrices (`a` applied first, then `b`)
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+/// Build a matrix from six numeric operands
+fn matrix_from(operands: &[Object]) -> Option<Matrix> {
+    if operands.len() != 6 {
+        return None;
+    }
+    let mut matrix = IDENTITY;
+    for (slot, operand) in matrix.iter_mut().zip(operands) {
+        *slot = number(operand)?;
+    }
+    Some(matrix)
+}
+
+/// Read a numeric dictionary entry
+fn dict_number(dict: &Dictionary, key: &[u8]) -> Option<f64> {
+    dict.get(key).ok().and_then(number)
+}
+
+/// Numeric value of an integer or real object
+fn number(object: &Object) -> Option<f64> {
+    match object {
+        Object::Integer(i) => Some(*i as f64),
+        Object::Real(r) => Some(*r as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiply_translation_then_scale() {
+        let scale = [200.0, 0.0, 0.0, 100.0, 0.0, 0.0];
+        let translate = [1.0, 0.0, 0.0, 1.0, 72.0, 500.0];
+
+        // `cm` prepends: the image's own scale is applied before the page translation
+        let ctm = multiply(&scale, &translate);
+
+        assert_eq!(ctm, [200.0, 0.0, 0.0, 100.0, 72.0, 500.0]);
+        assert_eq!(bounds(&ctm), (72.0, 500.0, 272.0, 600.0));
+    }
+
+    #[test]
+    fn test_encode_png_gray() {
+        let samples = vec![0u8, 128, 255, 64];
+        let png = encode_png(2, 2, 8, &ColorSpace::Gray, &samples).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn test_encode_png_rejects_short_data() {
+        assert!(encode_png(4, 4, 8, &ColorSpace::Rgb, &[0u8; 10]).is_none());
+    }
+
+    #[test]
+    fn test_cmyk_to_rgb() {
+        assert_eq!(cmyk_to_rgb(&[0, 0, 0, 0]), [255, 255, 255]);
+        assert_eq!(cmyk_to_rgb(&[0, 0, 0, 255]), [0, 0, 0]);
+        assert_eq!(cmyk_to_rgb(&[255, 0, 0, 0]), [0, 255, 255]);
+    }
+}
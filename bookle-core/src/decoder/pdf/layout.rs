@@ -0,0 +1,610 @@
+//! Page layout analysis for PDF text extraction
+//!
+//! PDF content streams position every glyph absolutely, so the raw text stream has
+//! no notion of paragraphs, running heads or page numbers. This module collects
+//! positioned text lines per page and cleans them up before they become blocks.
+
+use crate::decoder::text::is_roman_numeral;
+use crate::error::ParseError;
+use crate::progress::ProgressTracker;
+use pdf_extract::{MediaBox, OutputDev, OutputError, Transform};
+use std::collections::{HashMap, HashSet};
+
+/// Fraction of the page height at the top and bottom treated as page margin
+const MARGIN_BAND: f64 = 0.1;
+
+/// Minimum number of pages a line must repeat on to count as a running header/footer
+const MIN_RUNNING_REPEATS: usize = 3;
+
+/// Minimum share of pages (between first and last occurrence) a running line must appear on
+const RUNNING_DENSITY: f64 = 0.4;
+
+/// Vertical tolerance (in points) for treating two lines as being at the same position
+const POSITION_TOLERANCE: f64 = 3.0;
+
+/// Horizontal gap (in ems) that splits text on the same baseline into separate lines
+const COLUMN_GAP_EMS: f64 = 2.5;
+
+/// A single line of positioned text
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TextLine {
+    /// Text content
+    pub text: String,
+    /// Left edge in points
+    pub x: f64,
+    /// Baseline position in points, measured from the top of the page
+    pub y: f64,
+    /// Width in points
+    pub width: f64,
+    /// Largest font size on the line, in points
+    pub font_size: f64,
+}
+
+impl TextLine {
+    /// Right edge in points
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+}
+
+/// Text lines collected from a single page
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PageText {
+    /// Page height in points
+    pub height: f64,
+    /// Lines in content stream order
+    pub lines: Vec<TextLine>,
+}
+
+/// `OutputDev` that records glyphs as positioned lines, page by page
+pub(crate) struct LineCollector<'a> {
+    pages: Vec<PageText>,
+    /// Right edge of the last glyph written
+    last_end: f64,
+    /// Counts finished pages, and stops extraction when cancelled
+    progress: &'a ProgressTracker,
+}
+
+impl<'a> LineCollector<'a> {
+    pub fn new(progress: &'a ProgressTracker) -> Self {
+        Self {
+            pages: Vec::new(),
+            last_end: 0.0,
+            progress,
+        }
+    }
+
+    /// Finish collection, dropping whitespace-only lines
+    pub fn into_pages(self) -> Vec<PageText> {
+        self.pages
+            .into_iter()
+            .map(|mut page| {
+                for line in &mut page.lines {
+                    line.text = line.text.trim().to_string();
+                }
+                page.lines.retain(|line| !line.text.is_empty());
+                page
+            })
+            .collect()
+    }
+}
+
+impl OutputDev for LineCollector<'_> {
+    fn begin_page(
+        &mut self,
+        _page_num: u32,
+        media_box: &MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        if self.progress.is_cancelled() {
+            return Err(OutputError::IoError(std::io::Error::other("cancelled")));
+        }
+        self.pages.push(PageText {
+            height: media_box.ury - media_box.lly,
+            lines: Vec::new(),
+        });
+        self.last_end = 0.0;
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        self.progress.chapter_done();
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> Result<(), OutputError> {
+        let Some(page) = self.pages.last_mut() else {
+            return Ok(());
+        };
+
+        // Flip into top-down coordinates and scale the font size by the text matrix
+        let x = trm.m31;
+        let y = page.height - trm.m32;
+        let scaled_x = font_size * (trm.m11 + trm.m21);
+        let scaled_y = font_size * (trm.m12 + trm.m22);
+        let mut size = (scaled_x * scaled_y).abs().sqrt();
+        if size <= f64::EPSILON {
+            size = font_size.abs().max(1.0);
+        }
+        let advance = width * size;
+
+        let last_end = self.last_end;
+        let continues = page.lines.last().is_some_and(|line| {
+            (line.y - y).abs() <= size * 0.5
+                && x >= last_end - size * 0.5
+                && x - last_end <= size * COLUMN_GAP_EMS
+        });
+
+        match page.lines.last_mut() {
+            Some(line) if continues => {
+                if x > last_end + size * 0.1 && !line.text.ends_with(' ') && char != " " {
+                    line.text.push(' ');
+                }
+                line.text.push_str(char);
+                line.width = line.width.max(x + advance - line.x);
+                line.font_size = line.font_size.max(size);
+            }
+            _ => page.lines.push(TextLine {
+                text: char.to_string(),
+                x,
+                y,
+                width: advance,
+                font_size: size,
+            }),
+        }
+
+        self.last_end = x + advance;
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// Extract positioned text lines for every page of a document, counting pages on `progress`
+pub(crate) fn extract_pages(
+    doc: &pdf_extract::Document,
+    progress: &ProgressTracker,
+) -> Result<Vec<PageText>, ParseError> {
+    progress.set_chapters_total(doc.get_pages().len());
+    let mut collector = LineCollector::new(progress);
+    pdf_extract::output_doc(doc, &mut collector).map_err(|e| {
+        if progress.is_cancelled() {
+            ParseError::Cancelled
+        } else {
+            ParseError::MalformedContent(format!("Failed to extract PDF text: {}", e))
+        }
+    })?;
+    Ok(collector.into_pages())
+}
+
+/// Remove running headers and footers
+///
+/// A line is considered running page furniture when the same text (ignoring digits,
+/// so "Page 12" matches "Page 13") appears in the page margin at the same vertical
+/// position on a sufficient share of pages.
+pub(crate) fn strip_running_lines(pages: &mut [PageText]) {
+    let mut occurrences: HashMap<String, Vec<(usize, f64)>> = HashMap::new();
+
+    for (index, page) in pages.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for line in page
+            .lines
+            .iter()
+            .filter(|line| in_margin(page.height, line))
+        {
+            let key = running_key(&line.text);
+            if seen.insert(key.clone()) {
+                occurrences.entry(key).or_default().push((index, line.y));
+            }
+        }
+    }
+
+    // Map each running text to the vertical position it repeats at
+    let mut running: HashMap<String, f64> = HashMap::new();
+    for (key, hits) in occurrences {
+        let mut positions: Vec<f64> = hits.iter().map(|(_, y)| *y).collect();
+        positions.sort_by(f64::total_cmp);
+        let position = positions[positions.len() / 2];
+
+        let aligned: Vec<usize> = hits
+            .iter()
+            .filter(|(_, y)| (y - position).abs() <= POSITION_TOLERANCE)
+            .map(|(page, _)| *page)
+            .collect();
+
+        if is_running(&aligned) {
+            running.insert(key, position);
+        }
+    }
+
+    if running.is_empty() {
+        return;
+    }
+
+    for page in pages.iter_mut() {
+        let height = page.height;
+        page.lines.retain(|line| {
+            !(in_margin(height, line)
+                && running
+                    .get(&running_key(&line.text))
+                    .is_some_and(|position| (line.y - position).abs() <= POSITION_TOLERANCE))
+        });
+    }
+}
+
+/// Whether a set of page indices (in ascending order) is dense enough to be running furniture
+fn is_running(pages: &[usize]) -> bool {
+    let (Some(first), Some(last)) = (pages.first(), pages.last()) else {
+        return false;
+    };
+    if pages.len() < MIN_RUNNING_REPEATS {
+        return false;
+    }
+    let span = last - first + 1;
+    pages.len() as f64 / span as f64 >= RUNNING_DENSITY
+}
+
+/// Normalize line text for running header comparison
+fn running_key(text: &str) -> String {
+    let mut key = String::with_capacity(text.len());
+    let mut last_was_digit = false;
+    for word in text.split_whitespace() {
+        if !key.is_empty() {
+            key.push(' ');
+            last_was_digit = false;
+        }
+        for c in word.chars() {
+            if c.is_ascii_digit() {
+                if !last_was_digit {
+                    key.push('#');
+                }
+                last_was_digit = true;
+            } else {
+                key.extend(c.to_lowercase());
+                last_was_digit = false;
+            }
+        }
+    }
+    key
+}
+
+/// Remove bare page numbers ("12", "- 12 -", "Page 12 of 300", "xiv") from page margins
+pub(crate) fn strip_page_numbers(pages: &mut [PageText]) {
+    for page in pages.iter_mut() {
+        let height = page.height;
+        let count = page.lines.len();
+        let mut index = 0;
+        page.lines.retain(|line| {
+            // Without a page size, fall back to the first and last line of the page
+            let at_edge = if height > 0.0 {
+                in_margin(height, line)
+            } else {
+                index == 0 || index + 1 == count
+            };
+            index += 1;
+            !(at_edge && is_page_number(&line.text))
+        });
+    }
+}
+
+/// Whether a line sits in the top or bottom margin of a page of the given height
+fn in_margin(height: f64, line: &TextLine) -> bool {
+    height > 0.0 && (line.y <= height * MARGIN_BAND || line.y >= height * (1.0 - MARGIN_BAND))
+}
+
+/// Whether text is nothing but a page number
+pub(crate) fn is_page_number(text: &str) -> bool {
+    let decoration =
+        |c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | '[' | ']' | '(' | ')' | '|');
+    let mut rest = text.trim_matches(decoration).to_lowercase();
+
+    for prefix in ["page", "p."] {
+        if let Some(stripped) = rest.strip_prefix(prefix) {
+            rest = stripped.trim_start().to_string();
+            break;
+        }
+    }
+
+    // Drop a trailing "of 300" or "/ 300"
+    if let Some((number, total)) = rest.split_once(" of ").or_else(|| rest.split_once('/')) {
+        if !total.trim().chars().all(|c| c.is_ascii_digit()) {
+            return false;
+        }
+        rest = number.trim().to_string();
+    }
+
+    if rest.is_empty() || rest.len() > 8 {
+        return false;
+    }
+
+    if rest.chars().all(|c| c.is_ascii_digit()) {
+        return true;
+    }
+
+    // Lowercase roman numerals are used for front matter; uppercase ones are usually
+    // chapter numbers, so only accept them when the original text was lowercase.
+    is_roman_numeral(text.trim_matches(decoration))
+}
+
+/// Group lines into paragraphs using vertical spacing, font size and line length
+pub(crate) fn split_paragraphs(lines: &[TextLine]) -> Vec<&[TextLine]> {
+    let spacing = typical_line_spacing(lines);
+    let left = lines.iter().map(|l| l.x).fold(f64::INFINITY, f64::min);
+    let right = lines.iter().map(TextLine::right).fold(0.0, f64::max);
+    let block_width = (right - left).max(0.0);
+
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+
+    for (index, pair) in lines.windows(2).enumerate() {
+        if starts_new_paragraph(&pair[0], &pair[1], spacing, right, block_width) {
+            paragraphs.push(&lines[start..=index]);
+            start = index + 1;
+        }
+    }
+
+    if start < lines.len() {
+        paragraphs.push(&lines[start..]);
+    }
+
+    paragraphs
+}
+
+/// Join the lines of a paragraph into a single string
+pub(crate) fn paragraph_text(lines: &[TextLine]) -> String {
+    let mut text = String::new();
+    for line in lines {
+        join_line(&mut text, &line.text);
+    }
+    text
+}
+
+/// Lower median distance between consecutive baselines
+fn typical_line_spacing(lines: &[TextLine]) -> Option<f64> {
+    let mut gaps: Vec<f64> = lines
+        .windows(2)
+        .map(|pair| pair[1].y - pair[0].y)
+        .filter(|gap| *gap > 1.0)
+        .collect();
+    if gaps.is_empty() {
+        return None;
+    }
+    gaps.sort_by(f64::total_cmp);
+    Some(gaps[(gaps.len() - 1) / 2])
+}
+
+/// Decide whether `line` begins a new paragraph after `prev`
+fn starts_new_paragraph(
+    prev: &TextLine,
+    line: &TextLine,
+    spacing: Option<f64>,
+    right_edge: f64,
+    block_width: f64,
+) -> bool {
+    let gap = line.y - prev.y;
+
+    // Same baseline: a fragment of the same visual line
+    if gap.abs() <= prev.font_size * 0.5 {
+        return false;
+    }
+
+    // Moving back up the page means the content stream jumped elsewhere
+    if gap < 0.0 {
+        return true;
+    }
+
+    // Noticeably more leading than usual
+    let leading = spacing.unwrap_or(prev.font_size * 1.2);
+    if gap > leading * 1.5 {
+        return true;
+    }
+
+    // A change of font size separates headings from body text
+    let size_ratio = line.font_size.max(prev.font_size) / line.font_size.min(prev.font_size);
+    if size_ratio > 1.15 {
+        return true;
+    }
+
+    // A sentence ending on a short line closes its paragraph
+    let short = right_edge - prev.right() > block_width * 0.1;
+    let indented = line.x - prev.x > prev.font_size;
+    ends_sentence(&prev.text) && (short || indented)
+}
+
+/// Append a line to a paragraph, re-joining words hyphenated across the line break
+pub(crate) fn join_line(paragraph: &mut String, line: &str) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    if paragraph.is_empty() {
+        paragraph.push_str(line);
+        return;
+    }
+
+    // Soft hyphens only ever mark a line-break opportunity
+    if paragraph.ends_with('\u{ad}') {
+        paragraph.pop();
+        paragraph.push_str(line);
+        return;
+    }
+
+    // "exam-" + "ple" is a broken word; "Jean-" + "Paul" is a hyphenated compound
+    if ends_with_hyphenated_word(paragraph) {
+        if starts_lowercase(line) {
+            paragraph.pop();
+        }
+        paragraph.push_str(line);
+        return;
+    }
+
+    if !paragraph.ends_with(' ') {
+        paragraph.push(' ');
+    }
+    paragraph.push_str(line);
+}
+
+/// Whether `next` continues the paragraph `prev` on the following page
+pub(crate) fn continues_across_break(prev: &str, next: &str) -> bool {
+    let prev = prev.trim_end();
+    if prev.ends_with(',') || prev.ends_with(';') || prev.ends_with('\u{ad}') {
+        return true;
+    }
+    !ends_sentence(prev) && starts_lowercase(next)
+}
+
+/// Whether text ends with a word broken by a hyphen ("exam-")
+fn ends_with_hyphenated_word(text: &str) -> bool {
+    let mut chars = text.chars().rev();
+    chars.next() == Some('-') && chars.next().is_some_and(char::is_alphabetic)
+}
+
+/// Whether text starts with a lowercase letter
+fn starts_lowercase(text: &str) -> bool {
+    text.trim_start()
+        .chars()
+        .next()
+        .is_some_and(char::is_lowercase)
+}
+
+/// Whether text ends with sentence-final punctuation (ignoring closing quotes and brackets)
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(['"', '\'', '”', '’', ')', ']', '»'])
+        .ends_with(['.', '!', '?', ':', '…'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, y: f64) -> TextLine {
+        TextLine {
+            text: text.to_string(),
+            x: 72.0,
+            y,
+            width: 468.0,
+            font_size: 12.0,
+        }
+    }
+
+    fn page(lines: Vec<TextLine>) -> PageText {
+        PageText {
+            height: 792.0,
+            lines,
+        }
+    }
+
+    #[test]
+    fn test_strip_running_lines() {
+        let mut pages: Vec<PageText> = (1..=4)
+            .map(|n| {
+                page(vec![
+                    line(&format!("The Great Novel {}", n + 10), 40.0),
+                    line(&format!("Body text on page {}.", n), 100.0),
+                ])
+            })
+            .collect();
+
+        strip_running_lines(&mut pages);
+
+        for page in &pages {
+            assert_eq!(page.lines.len(), 1);
+            assert!(page.lines[0].text.starts_with("Body text"));
+        }
+    }
+
+    #[test]
+    fn test_running_lines_need_repeats() {
+        let mut pages = vec![
+            page(vec![line("Chapter 1", 40.0), line("Text.", 100.0)]),
+            page(vec![line("Chapter 2", 40.0), line("Text.", 100.0)]),
+        ];
+
+        strip_running_lines(&mut pages);
+
+        assert_eq!(pages[0].lines.len(), 2);
+        assert_eq!(pages[1].lines.len(), 2);
+    }
+
+    #[test]
+    fn test_is_page_number() {
+        assert!(is_page_number("12"));
+        assert!(is_page_number("- 12 -"));
+        assert!(is_page_number("Page 12"));
+        assert!(is_page_number("Page 12 of 300"));
+        assert!(is_page_number("xiv"));
+        assert!(!is_page_number("XIV"));
+        assert!(is_page_number("mcmxcix"));
+        assert!(!is_page_number("mild"));
+        assert!(!is_page_number("civil"));
+        assert!(!is_page_number("dim"));
+        assert!(!is_page_number("iiii"));
+        assert!(!is_page_number("Chapter 12"));
+        assert!(!is_page_number("12 monkeys"));
+    }
+
+    #[test]
+    fn test_strip_page_numbers_only_in_margin() {
+        let mut pages = vec![page(vec![
+            line("42", 300.0),
+            line("Body.", 320.0),
+            line("7", 760.0),
+        ])];
+
+        strip_page_numbers(&mut pages);
+
+        let texts: Vec<&str> = pages[0].lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["42", "Body."]);
+    }
+
+    #[test]
+    fn test_join_line_dehyphenates() {
+        let mut paragraph = String::from("an exam-");
+        join_line(&mut paragraph, "ple of text");
+        assert_eq!(paragraph, "an example of text");
+
+        let mut paragraph = String::from("see Jean-");
+        join_line(&mut paragraph, "Paul");
+        assert_eq!(paragraph, "see Jean-Paul");
+    }
+
+    #[test]
+    fn test_split_paragraphs_on_gap() {
+        let lines = vec![
+            line("First paragraph line one", 100.0),
+            line("continues here.", 114.0),
+            line("Second paragraph.", 150.0),
+        ];
+
+        let paragraphs: Vec<String> = split_paragraphs(&lines)
+            .into_iter()
+            .map(paragraph_text)
+            .collect();
+
+        assert_eq!(
+            paragraphs,
+            vec![
+                "First paragraph line one continues here.".to_string(),
+                "Second paragraph.".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,875 @@
+//! PDF decoder implementation
+
+mod flow;
+mod images;
+mod layout;
+mod password;
+
+use super::chapters::{ChapterSplitter, SplitOptions, SplitStrategy};
+use super::{DecodeContext, DecodeOptions};
+use crate::error::ParseError;
+use crate::types::{Block, Book, Inline, Metadata, ReadingDirection, ResourceStore};
+use pdf_extract::{Document, Object};
+use std::io::Read;
+use std::ops::RangeInclusive;
+
+/// Decoder for PDF format
+///
+/// This decoder extracts text content from PDF files and converts it to the IR format.
+/// Note that PDF is a visual format, so structural information (headings, lists, etc.)
+/// is inferred heuristically from font sizes and spacing. Embedded images are extracted
+/// as resources and placed between the paragraphs they appear between on the page.
+pub struct PdfDecoder {
+    /// Minimum font size ratio to consider as a heading
+    heading_size_ratio: f32,
+    /// Whether to strip running headers/footers and page numbers
+    strip_page_furniture: bool,
+    /// Whether to extract embedded images
+    extract_images: bool,
+    /// Minimum width and height (in pixels) of images to extract
+    min_image_size: u32,
+    /// Column order override; detected from the document when unset
+    reading_direction: Option<ReadingDirection>,
+    /// 1-based inclusive range of pages to decode
+    page_range: Option<RangeInclusive<u32>>,
+    /// User or owner password for encrypted documents
+    password: Option<String>,
+    /// Where the document is divided into chapters
+    splitter: ChapterSplitter,
+}
+
+impl PdfDecoder {
+    pub fn new() -> Self {
+        Self {
+            heading_size_ratio: 1.2, // 20% larger than body text
+            strip_page_furniture: true,
+            extract_images: true,
+            min_image_size: 32, // skip bullets, rules and other decoration
+            reading_direction: None,
+            page_range: None,
+            password: None,
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(2)),
+        }
+    }
+
+    /// Set the heading size ratio threshold
+    pub fn with_heading_ratio(mut self, ratio: f32) -> Self {
+        self.heading_size_ratio = ratio;
+        self
+    }
+
+    /// Enable or disable removal of running headers/footers and page numbers
+    pub fn with_page_furniture_removal(mut self, enable: bool) -> Self {
+        self.strip_page_furniture = enable;
+        self
+    }
+
+    /// Enable or disable extraction of embedded images
+    pub fn with_image_extraction(mut self, enable: bool) -> Self {
+        self.extract_images = enable;
+        self
+    }
+
+    /// Set the minimum width and height (in pixels) of images to extract
+    pub fn with_min_image_size(mut self, size: u32) -> Self {
+        self.min_image_size = size;
+        self
+    }
+
+    /// Set the reading direction used to order columns
+    ///
+    /// By default this is taken from the document's viewer preferences.
+    pub fn with_reading_direction(mut self, direction: ReadingDirection) -> Self {
+        self.reading_direction = Some(direction);
+        self
+    }
+
+    /// Only decode the given 1-based, inclusive range of pages
+    pub fn with_page_range(mut self, pages: RangeInclusive<u32>) -> Self {
+        self.page_range = Some(pages);
+        self
+    }
+
+    /// Set the password used to open encrypted documents
+    ///
+    /// Either the user or the owner password is accepted.
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Set where the document is divided into chapters (H1 and H2 headings by default)
+    ///
+    /// [`SplitStrategy::PageBreak`] starts a chapter on every page.
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+
+    /// Load a PDF document, decrypting it and dropping pages outside the page range
+    fn load_document(&self, data: &[u8]) -> Result<Document, ParseError> {
+        let mut doc = Document::load_mem(data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to load PDF: {}", e)))?;
+
+        if doc.is_encrypted() {
+            self.decrypt(&mut doc)?;
+        }
+
+        if let Some(range) = &self.page_range {
+            let pages = doc.get_pages();
+            let count = pages.len() as u32;
+            if *range.start() == 0 || range.start() > range.end() || *range.start() > count {
+                return Err(ParseError::MalformedContent(format!(
+                    "Page range {}-{} is outside the document's {} pages",
+                    range.start(),
+                    range.end(),
+                    count
+                )));
+            }
+
+            let excluded: Vec<u32> = pages
+                .into_keys()
+                .filter(|page| !range.contains(page))
+                .collect();
+            doc.delete_pages(&excluded);
+        }
+
+        Ok(doc)
+    }
+
+    /// Decrypt a document with the configured password
+    ///
+    /// Documents encrypted with an empty user password (permissions only) open
+    /// without one.
+    fn decrypt(&self, doc: &mut Document) -> Result<(), ParseError> {
+        use pdf_extract::encryption::DecryptionError;
+
+        let password = self.password.as_deref().unwrap_or("");
+        let mut result = doc.decrypt(password);
+
+        if let Err(pdf_extract::Error::Decryption(DecryptionError::IncorrectPassword)) = result {
+            if let Some(user) = password::user_password_from_owner(doc, password.as_bytes()) {
+                result = doc.decrypt(user);
+            }
+        }
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(pdf_extract::Error::Decryption(DecryptionError::IncorrectPassword)) => {
+                Err(ParseError::PasswordRequired(if self.password.is_some() {
+                    "incorrect password for encrypted PDF".to_string()
+                } else {
+                    "PDF is encrypted; supply a password to open it".to_string()
+                }))
+            }
+            Err(pdf_extract::Error::Decryption(DecryptionError::UnsupportedEncryption)) => Err(
+                ParseError::UnsupportedFormat("PDF uses an unsupported encryption method".into()),
+            ),
+            Err(e) => Err(ParseError::MalformedContent(format!(
+                "Failed to decrypt PDF: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Reading direction declared in the document's viewer preferences
+    fn document_direction(doc: &Document) -> ReadingDirection {
+        let direction = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"ViewerPreferences"))
+            .and_then(|prefs| doc.dereference(prefs))
+            .and_then(|(_, prefs)| prefs.as_dict())
+            .and_then(|prefs| prefs.get(b"Direction"))
+            .and_then(Object::as_name);
+
+        match direction {
+            Ok(b"R2L") => ReadingDirection::RightToLeft,
+            _ => ReadingDirection::LeftToRight,
+        }
+    }
+
+    /// Extract text and images from PDF and convert to blocks
+    fn extract_blocks(
+        &self,
+        doc: &Document,
+        direction: ReadingDirection,
+        resources: &mut ResourceStore,
+        context: &DecodeContext,
+    ) -> Result<(Vec<Block>, Vec<usize>), ParseError> {
+        // Collect positioned lines page by page
+        let mut pages = layout::extract_pages(doc, context.progress())?;
+
+        if self.strip_page_furniture {
+            layout::strip_running_lines(&mut pages);
+            layout::strip_page_numbers(&mut pages);
+        }
+
+        // Images are found separately; pdf-extract only reports text
+        let mut page_images: Vec<Vec<images::PlacedImage>> = if self.extract_images {
+            doc.get_pages()
+                .into_values()
+                .zip(&pages)
+                .map(|(page_id, page)| {
+                    images::page_images(doc, page_id, page.height, self.min_image_size)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        context.check_cancelled()?;
+        if self.strip_page_furniture {
+            images::drop_repeated(&mut page_images);
+        }
+        page_images.resize_with(pages.len(), Vec::new);
+
+        // Put each page in reading order, then stitch together paragraphs split by page breaks
+        let body_size = flow::body_font_size(&pages);
+        let page_items = pages
+            .iter()
+            .zip(page_images)
+            .map(|(page, placed)| {
+                let images: Vec<flow::PageImage> = placed
+                    .into_iter()
+                    .map(|image| flow::PageImage {
+                        key: resources.add(image.resource),
+                        left: image.left,
+                        top: image.top,
+                        right: image.right,
+                        bottom: image.bottom,
+                    })
+                    .collect();
+                flow::page_items(&page.lines, &images, body_size, direction)
+            })
+            .collect();
+
+        let (items, page_starts) = flow::merge_pages(page_items);
+        let blocks = items
+            .into_iter()
+            .map(|item| match item {
+                flow::PageItem::Paragraph(text) => self.text_to_block(&text),
+                flow::PageItem::Aside(paragraphs) => Block::Blockquote(
+                    paragraphs
+                        .into_iter()
+                        .map(|text| Block::Paragraph(vec![Inline::Text(text)]))
+                        .collect(),
+                ),
+                flow::PageItem::Image { key, caption } => Block::Image {
+                    resource_key: key,
+                    caption,
+                    alt: String::new(),
+                },
+            })
+            .collect();
+
+        Ok((blocks, page_starts))
+    }
+
+    /// Convert text to a block, detecting if it's likely a heading
+    fn text_to_block(&self, text: &str) -> Block {
+        let trimmed = text.trim();
+
+        // Heuristics for heading detection:
+        // 1. Short lines (likely chapter titles)
+        // 2. Lines that look like numbered chapters
+        // 3. ALL CAPS lines
+
+        let is_likely_heading = self.is_likely_heading(trimmed);
+
+        if is_likely_heading {
+            let level = self.detect_heading_level(trimmed);
+            Block::Header {
+                level,
+                content: vec![Inline::Text(trimmed.to_string())],
+                anchor: None,
+            }
+        } else {
+            Block::Paragraph(vec![Inline::Text(trimmed.to_string())])
+        }
+    }
+
+    /// Detect if text is likely a heading based on heuristics
+    fn is_likely_heading(&self, text: &str) -> bool {
+        // Check various heading patterns
+
+        // Pattern 1: Short text (< 100 chars) that doesn't end with common sentence enders
+        let is_short = text.len() < 100
+            && !text.ends_with('.')
+            && !text.ends_with('?')
+            && !text.ends_with('!');
+
+        // Pattern 2: Numbered chapter patterns
+        let numbered_patterns = [
+            "chapter",
+            "part",
+            "section",
+            "book",
+            "volume",
+            "prologue",
+            "epilogue",
+            "introduction",
+            "conclusion",
+            "preface",
+            "appendix",
+            "foreword",
+            "afterword",
+        ];
+        let text_lower = text.to_lowercase();
+        let has_chapter_keyword = numbered_patterns.iter().any(|p| text_lower.starts_with(p));
+
+        // Pattern 3: All uppercase (but not too long)
+        let is_all_caps = text.len() < 60
+            && text
+                .chars()
+                .filter(|c| c.is_alphabetic())
+                .all(|c| c.is_uppercase());
+
+        // Pattern 4: Roman numeral or number at start
+        let starts_with_number = text.chars().next().map(|c| c.is_numeric()).unwrap_or(false);
+        let roman_numerals = [
+            "I.", "II.", "III.", "IV.", "V.", "VI.", "VII.", "VIII.", "IX.", "X.",
+        ];
+        let starts_with_roman = roman_numerals.iter().any(|r| text.starts_with(r));
+
+        is_short && (has_chapter_keyword || is_all_caps || starts_with_number || starts_with_roman)
+    }
+
+    /// Detect heading level based on text characteristics
+    fn detect_heading_level(&self, text: &str) -> u8 {
+        let text_lower = text.to_lowercase();
+
+        // Level 1: Book, Part, Major divisions
+        if text_lower.starts_with("book ") || text_lower.starts_with("part ") {
+            return 1;
+        }
+
+        // Level 2: Chapters
+        if text_lower.starts_with("chapter ")
+            || text_lower.starts_with("prologue")
+            || text_lower.starts_with("epilogue")
+        {
+            return 2;
+        }
+
+        // Level 3: Sections
+        if text_lower.starts_with("section ") {
+            return 3;
+        }
+
+        // Default to level 2 for other headings
+        2
+    }
+
+    /// Extract title from first heading or use filename
+    fn extract_title(blocks: &[Block]) -> Option<String> {
+        for block in blocks {
+            if let Block::Header { content, level, .. } = block {
+                if *level <= 2 {
+                    return Some(inlines_to_text(content));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Default for PdfDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for PdfDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    /// Decode a PDF, counting its pages on the context's progress
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        // Read all data into memory
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read PDF: {}", e)))?;
+
+        let doc = self.load_document(&data)?;
+        let direction = self
+            .reading_direction
+            .unwrap_or_else(|| Self::document_direction(&doc));
+
+        // Extract text and images and convert to blocks
+        let mut resources = ResourceStore::new();
+        let (blocks, page_starts) =
+            self.extract_blocks(&doc, direction, &mut resources, context)?;
+
+        // Extract title from first heading
+        let title = Self::extract_title(&blocks).unwrap_or_else(|| "Untitled PDF".to_string());
+
+        // Create metadata
+        let mut metadata = Metadata::new(title, "en");
+        metadata.reading_direction = direction;
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+
+        // Split into chapters
+        let chapters = self.splitter.split_with_page_breaks(blocks, &page_starts);
+        for chapter in chapters {
+            book.add_chapter(chapter);
+        }
+
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["pdf"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/pdf"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+
+    fn set_decode_options(&mut self, options: &DecodeOptions) -> bool {
+        if options.main_content {
+            return false;
+        }
+        if let Some(pages) = &options.pages {
+            self.page_range = Some(pages.clone());
+        }
+        if let Some(password) = &options.password {
+            self.password = Some(password.clone());
+        }
+        true
+    }
+}
+
+/// Convert inline elements to plain text
+fn inlines_to_text(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|i| match i {
+            Inline::Text(s) => s.clone(),
+            Inline::Bold(children) | Inline::Italic(children) | Inline::Strikethrough(children) => {
+                inlines_to_text(children)
+            }
+            Inline::Link { children, .. } => inlines_to_text(children),
+            Inline::Code(s) => s.clone(),
+            Inline::Superscript(children) | Inline::Subscript(children) => {
+                inlines_to_text(children)
+            }
+            Inline::FootnoteRef { id } => format!("[{}]", id),
+            Inline::Ruby { base, .. } => base.clone(),
+            Inline::Break => " ".to_string(),
+            Inline::Checkbox { .. } => String::new(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use pdf_extract::{Dictionary, Stream};
+
+    /// Build a Letter-sized PDF where each page is a list of (baseline from top, text) lines
+    fn build_pdf(pages: &[Vec<(f64, &str)>]) -> Vec<u8> {
+        build_pdf_with_image(pages, None)
+    }
+
+    /// Like `build_pdf`, optionally drawing a 200x100pt image XObject on one page
+    /// at `(page index, top edge from top)`
+    fn build_pdf_with_image(
+        pages: &[Vec<(f64, &str)>],
+        image: Option<(usize, f64, Stream)>,
+    ) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+
+        let mut font = Dictionary::new();
+        font.set("Type", Object::Name(b"Font".to_vec()));
+        font.set("Subtype", Object::Name(b"Type1".to_vec()));
+        font.set("BaseFont", Object::Name(b"Helvetica".to_vec()));
+        let font_id = doc.add_object(font);
+
+        let mut fonts = Dictionary::new();
+        fonts.set("F1", Object::Reference(font_id));
+        let mut resources = Dictionary::new();
+        resources.set("Font", Object::Dictionary(fonts));
+        let image = image.map(|(page, top, stream)| {
+            let mut xobjects = Dictionary::new();
+            xobjects.set("Im1", Object::Reference(doc.add_object(stream)));
+            resources.set("XObject", Object::Dictionary(xobjects));
+            (page, top)
+        });
+        let resources_id = doc.add_object(resources);
+
+        let mut kids = Vec::new();
+        for (index, lines) in pages.iter().enumerate() {
+            let mut content = String::new();
+            if let Some((_, top)) = image.filter(|(page, _)| *page == index) {
+                content.push_str(&format!(
+                    "q 200 0 0 100 72 {} cm /Im1 Do Q\n",
+                    792.0 - top - 100.0
+                ));
+            }
+            for (y, text) in lines {
+                content.push_str(&format!(
+                    "BT /F1 12 Tf 72 {} Td ({}) Tj ET\n",
+                    792.0 - y,
+                    text
+                ));
+            }
+            let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+
+            let mut page = Dictionary::new();
+            page.set("Type", Object::Name(b"Page".to_vec()));
+            page.set("Parent", Object::Reference(pages_id));
+            page.set("Contents", Object::Reference(content_id));
+            kids.push(Object::Reference(doc.add_object(page)));
+        }
+
+        let mut pages_dict = Dictionary::new();
+        pages_dict.set("Type", Object::Name(b"Pages".to_vec()));
+        pages_dict.set("Count", Object::Integer(kids.len() as i64));
+        pages_dict.set("Kids", Object::Array(kids));
+        pages_dict.set("Resources", Object::Reference(resources_id));
+        pages_dict.set(
+            "MediaBox",
+            Object::Array(vec![0.into(), 0.into(), 612.into(), 792.into()]),
+        );
+        doc.objects.insert(pages_id, Object::Dictionary(pages_dict));
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set("Pages", Object::Reference(pages_id));
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        let mut data = Vec::new();
+        doc.save_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_decode_strips_page_furniture() {
+        let pages: Vec<Vec<(f64, &str)>> = vec![
+            vec![
+                (40.0, "A Tale of Testing"),
+                (100.0, "CHAPTER ONE"),
+                (140.0, "It was the best of tests, it was the worst of"),
+                (154.0, "tests, and the decoder was running out of pa-"),
+                (760.0, "1"),
+            ],
+            vec![
+                (40.0, "A Tale of Testing"),
+                (100.0, "tience with every page."),
+                (760.0, "2"),
+            ],
+            vec![
+                (40.0, "A Tale of Testing"),
+                (100.0, "The end came quickly."),
+                (760.0, "3"),
+            ],
+        ];
+        let data = build_pdf(&pages);
+
+        let book = PdfDecoder::new()
+            .decode(&mut std::io::Cursor::new(data))
+            .unwrap();
+
+        assert_eq!(
+            block_texts(&book),
+            vec![
+                "CHAPTER ONE".to_string(),
+                "It was the best of tests, it was the worst of tests, and the decoder was \
+                 running out of patience with every page."
+                    .to_string(),
+                "The end came quickly.".to_string(),
+            ]
+        );
+    }
+
+    /// Text of every paragraph and heading in a book
+    fn block_texts(book: &Book) -> Vec<String> {
+        book.chapters
+            .iter()
+            .flat_map(|c| c.content.iter())
+            .map(|b| match b {
+                Block::Paragraph(inlines)
+                | Block::Header {
+                    content: inlines, ..
+                } => inlines_to_text(inlines),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    /// Encrypt a PDF with the RC4 40-bit standard security handler (revision 2 or 3)
+    ///
+    /// The keys are derived here step by step from the specification, rather
+    /// than with the decoder's own helpers, so that the tests check them.
+    fn encrypt_pdf(data: Vec<u8>, user: &[u8], owner: &[u8], revision: i64) -> Vec<u8> {
+        use md5::{Digest, Md5};
+        use pdf_extract::StringFormat;
+
+        const KEY_LEN: usize = 5;
+        let mut doc = Document::load_mem(&data).unwrap();
+        let file_id = b"0123456789abcdef".to_vec();
+        let permissions: i32 = -4;
+        let rc4 = |key: &[u8], data: &[u8]| password::rc4(key, data).unwrap();
+        // Revision 3 runs RC4 20 times, XOR-ing each byte of the key with the round
+        let rounds = |key: &[u8], data: &[u8]| {
+            let rounds = if revision == 2 { 0..=0 } else { 0..=19 };
+            rounds.fold(data.to_vec(), |data, i| {
+                let round_key: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+                rc4(&round_key, &data)
+            })
+        };
+        // Revision 3 hashes the first KEY_LEN bytes of the digest 50 more times
+        let rehash = |mut hash: Vec<u8>| {
+            if revision >= 3 {
+                for _ in 0..50 {
+                    hash = Md5::digest(&hash[..KEY_LEN]).to_vec();
+                }
+            }
+            hash[..KEY_LEN].to_vec()
+        };
+
+        // Algorithm 3: the O entry
+        let owner_key = rehash(Md5::digest(password::pad(owner)).to_vec());
+        let owner_entry = rounds(&owner_key, &password::pad(user));
+
+        // Algorithm 2: the file key
+        let mut hasher = Md5::new();
+        hasher.update(password::pad(user));
+        hasher.update(&owner_entry);
+        hasher.update(permissions.to_le_bytes());
+        hasher.update(&file_id);
+        let key = rehash(hasher.finalize().to_vec());
+
+        // Algorithms 4 and 5: the U entry
+        let user_entry = if revision == 2 {
+            rc4(&key, &password::pad(b""))
+        } else {
+            let mut hasher = Md5::new();
+            hasher.update(password::pad(b""));
+            hasher.update(&file_id);
+            let mut entry = rounds(&key, &hasher.finalize());
+            entry.resize(32, 0);
+            entry
+        };
+
+        for (id, object) in doc.objects.iter_mut() {
+            if let Object::Stream(stream) = object {
+                let mut object_key = key.clone();
+                object_key.extend_from_slice(&id.0.to_le_bytes()[..3]);
+                object_key.extend_from_slice(&id.1.to_le_bytes()[..2]);
+                let object_key = &Md5::digest(&object_key)[..KEY_LEN + 5];
+                let content = rc4(object_key, &stream.content);
+                stream.set_content(content);
+            }
+        }
+
+        let mut encrypt = Dictionary::new();
+        encrypt.set("Filter", Object::Name(b"Standard".to_vec()));
+        encrypt.set("V", Object::Integer(if revision == 2 { 1 } else { 2 }));
+        encrypt.set("R", Object::Integer(revision));
+        encrypt.set("Length", Object::Integer(KEY_LEN as i64 * 8));
+        encrypt.set("O", Object::String(owner_entry, StringFormat::Hexadecimal));
+        encrypt.set("U", Object::String(user_entry, StringFormat::Hexadecimal));
+        encrypt.set("P", Object::Integer(permissions as i64));
+        let encrypt_id = doc.add_object(encrypt);
+        doc.trailer.set("Encrypt", Object::Reference(encrypt_id));
+        let id = Object::String(file_id, StringFormat::Hexadecimal);
+        doc.trailer.set("ID", Object::Array(vec![id.clone(), id]));
+
+        let mut data = Vec::new();
+        doc.save_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_decode_encrypted_pdf() {
+        let pages: Vec<Vec<(f64, &str)>> = vec![vec![(100.0, "Secret text.")]];
+        let data = encrypt_pdf(build_pdf(&pages), b"user", b"owner", 2);
+        let decode = |decoder: PdfDecoder| decoder.decode(&mut std::io::Cursor::new(data.clone()));
+
+        assert!(matches!(
+            decode(PdfDecoder::new()),
+            Err(ParseError::PasswordRequired(_))
+        ));
+        assert!(matches!(
+            decode(PdfDecoder::new().with_password("wrong")),
+            Err(ParseError::PasswordRequired(_))
+        ));
+
+        for password in ["user", "owner"] {
+            let book = decode(PdfDecoder::new().with_password(password)).unwrap();
+            assert_eq!(block_texts(&book), vec!["Secret text.".to_string()]);
+        }
+    }
+
+    #[test]
+    fn test_decode_revision_3_owner_password() {
+        let pages: Vec<Vec<(f64, &str)>> = vec![vec![(100.0, "Secret text.")]];
+        let data = encrypt_pdf(build_pdf(&pages), b"user", b"owner", 3);
+        let decode = |password: &str| {
+            PdfDecoder::new()
+                .with_password(password)
+                .decode(&mut std::io::Cursor::new(data.clone()))
+        };
+
+        let book = decode("owner").unwrap();
+        assert_eq!(block_texts(&book), vec!["Secret text.".to_string()]);
+        assert!(matches!(
+            decode("wrong"),
+            Err(ParseError::PasswordRequired(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_page_range() {
+        let pages: Vec<Vec<(f64, &str)>> = vec![
+            vec![(100.0, "First page.")],
+            vec![(100.0, "Second page.")],
+            vec![(100.0, "Third page.")],
+        ];
+        let data = build_pdf(&pages);
+
+        let book = PdfDecoder::new()
+            .with_page_range(2..=3)
+            .decode(&mut std::io::Cursor::new(data.clone()))
+            .unwrap();
+        assert_eq!(
+            block_texts(&book),
+            vec!["Second page.".to_string(), "Third page.".to_string()]
+        );
+
+        let result = PdfDecoder::new()
+            .with_page_range(5..=9)
+            .decode(&mut std::io::Cursor::new(data));
+        assert!(matches!(result, Err(ParseError::MalformedContent(_))));
+    }
+
+    /// Build an uncompressed RGB image XObject
+    fn rgb_image(width: u32, height: u32) -> Stream {
+        let mut dict = Dictionary::new();
+        dict.set("Type", Object::Name(b"XObject".to_vec()));
+        dict.set("Subtype", Object::Name(b"Image".to_vec()));
+        dict.set("Width", Object::Integer(width as i64));
+        dict.set("Height", Object::Integer(height as i64));
+        dict.set("ColorSpace", Object::Name(b"DeviceRGB".to_vec()));
+        dict.set("BitsPerComponent", Object::Integer(8));
+        let samples = vec![200u8; (width * height * 3) as usize];
+        let mut stream = Stream::new(dict, samples);
+        stream.compress().unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_decode_extracts_images_in_reading_order() {
+        let pages: Vec<Vec<(f64, &str)>> = vec![vec![
+            (100.0, "Text above the picture."),
+            (400.0, "Text below the picture."),
+        ]];
+        let data = build_pdf_with_image(&pages, Some((0, 200.0, rgb_image(64, 32))));
+
+        let book = PdfDecoder::new()
+            .decode(&mut std::io::Cursor::new(data))
+            .unwrap();
+
+        let blocks: Vec<&Block> = book.chapters.iter().flat_map(|c| &c.content).collect();
+        assert_eq!(blocks.len(), 3);
+        let Block::Image { resource_key, .. } = blocks[1] else {
+            panic!("Expected image between paragraphs, got {:?}", blocks[1]);
+        };
+
+        let resource = book.resources.get(resource_key).unwrap();
+        assert_eq!(resource.mime_type, "image/png");
+    }
+
+    #[test]
+    fn test_decode_skips_small_images() {
+        let pages: Vec<Vec<(f64, &str)>> = vec![vec![(100.0, "Just text here.")]];
+        let data = build_pdf_with_image(&pages, Some((0, 200.0, rgb_image(16, 16))));
+
+        let book = PdfDecoder::new()
+            .decode(&mut std::io::Cursor::new(data.clone()))
+            .unwrap();
+        assert!(book.resources.is_empty());
+
+        let book = PdfDecoder::new()
+            .with_min_image_size(8)
+            .decode(&mut std::io::Cursor::new(data))
+            .unwrap();
+        assert_eq!(book.resources.len(), 1);
+    }
+
+    #[test]
+    fn test_is_likely_heading() {
+        let decoder = PdfDecoder::new();
+
+        assert!(decoder.is_likely_heading("Chapter 1"));
+        assert!(decoder.is_likely_heading("INTRODUCTION"));
+        assert!(decoder.is_likely_heading("Part I"));
+        assert!(decoder.is_likely_heading("Prologue"));
+
+        // Regular text should not be detected as heading
+        assert!(!decoder.is_likely_heading(
+            "This is a regular paragraph that continues for a while and discusses various topics."
+        ));
+    }
+
+    #[test]
+    fn test_detect_heading_level() {
+        let decoder = PdfDecoder::new();
+
+        assert_eq!(decoder.detect_heading_level("Book One"), 1);
+        assert_eq!(decoder.detect_heading_level("Part I"), 1);
+        assert_eq!(decoder.detect_heading_level("Chapter 1"), 2);
+        assert_eq!(decoder.detect_heading_level("Prologue"), 2);
+        assert_eq!(decoder.detect_heading_level("Section 3.1"), 3);
+    }
+
+    #[test]
+    fn test_text_to_block_paragraph() {
+        let decoder = PdfDecoder::new();
+
+        let block = decoder.text_to_block("This is a regular paragraph with normal text content.");
+
+        match block {
+            Block::Paragraph(inlines) => {
+                assert_eq!(inlines.len(), 1);
+            }
+            _ => panic!("Expected paragraph"),
+        }
+    }
+
+    #[test]
+    fn test_text_to_block_heading() {
+        let decoder = PdfDecoder::new();
+
+        let block = decoder.text_to_block("Chapter 1");
+
+        match block {
+            Block::Header { level, .. } => {
+                assert_eq!(level, 2);
+            }
+            _ => panic!("Expected header"),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Password handling for encrypted PDFs
+//!
+//! lopdf only authenticates user passwords. For the standard RC4 security handler
+//! (revisions 2 and 3) the user password can be recovered from the owner password
+//! (PDF 1.7, section 7.6.3.4, algorithm 7), so either password unlocks the document.
+
+use md5::{Digest, Md5};
+use pdf_extract::{Document, Object};
+
+/// Padding string used to extend passwords to 32 bytes
+const PAD_BYTES: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08,
+    0x2E, 0x2E, 0x00, 0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+/// Recover the padded user password from the owner password
+pub(crate) fn user_password_from_owner(doc: &Document, owner: &[u8]) -> Option<Vec<u8>> {
+    let encrypt = doc.get_encrypted().ok()?;
+    let revision = encrypt.get(b"R").and_then(Object::as_i64).ok()?;
+    let owner_entry = encrypt.get(b"O").and_then(Object::as_str).ok()?;
+
+    let key = owner_key(encrypt, owner, revision)?;
+
+    match revision {
+        2 => rc4(&key, owner_entry),
+        3 => (0..=19u8).rev().try_fold(owner_entry.to_vec(), |data, i| {
+            let round_key: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+            rc4(&round_key, &data)
+        }),
+        _ => None,
+    }
+}
+
+/// RC4 key derived from the owner password (algorithm 3, steps a-d)
+pub(crate) fn owner_key(
+    encrypt: &pdf_extract::Dictionary,
+    owner: &[u8],
+    revision: i64,
+) -> Option<Vec<u8>> {
+    let key_len = if revision == 2 {
+        5
+    } else {
+        encrypt
+            .get(b"Length")
+            .and_then(Object::as_i64)
+            .map_or(5, |bits| (bits / 8) as usize)
+    };
+    // RC4 keys are 40 to 128 bits; anything else is a malformed /Length
+    if !(5..=16).contains(&key_len) {
+        return None;
+    }
+
+    let mut hash = Md5::digest(pad(owner));
+    if revision >= 3 {
+        for _ in 0..50 {
+            hash = Md5::digest(&hash[..key_len]);
+        }
+    }
+
+    Some(hash[..key_len].to_vec())
+}
+
+/// Truncate or pad a password to 32 bytes
+pub(crate) fn pad(password: &[u8]) -> Vec<u8> {
+    let len = password.len().min(32);
+    let mut padded = password[..len].to_vec();
+    padded.extend_from_slice(&PAD_BYTES[..32 - len]);
+    padded
+}
+
+/// RC4 stream cipher (encryption and decryption are the same operation)
+///
+/// Returns `None` for an empty key.
+pub(crate) fn rc4(key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    if key.is_empty() {
+        return None;
+    }
+
+    let mut state: Vec<u8> = (0..=255).collect();
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+
+    let (mut i, mut j) = (0u8, 0u8);
+    let output = data
+        .iter()
+        .map(|byte| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(state[i as usize]);
+            state.swap(i as usize, j as usize);
+            byte ^ state[state[i as usize].wrapping_add(state[j as usize]) as usize]
+        })
+        .collect();
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rc4_known_vector() {
+        // Test vector from RFC 6229 style examples ("Key" / "Plaintext")
+        let encrypted = rc4(b"Key", b"Plaintext").unwrap();
+        assert_eq!(hex::encode(&encrypted), "bbf316e8d940af0ad3");
+        assert_eq!(rc4(b"Key", &encrypted).unwrap(), b"Plaintext");
+        assert_eq!(rc4(b"", b"Plaintext"), None);
+    }
+
+    #[test]
+    fn test_owner_key_rejects_bad_lengths() {
+        use pdf_extract::Dictionary;
+
+        for bits in [7, 32, 0, -40, 256] {
+            let mut encrypt = Dictionary::new();
+            encrypt.set("Length", Object::Integer(bits));
+            assert_eq!(owner_key(&encrypt, b"owner", 3), None, "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad(b"")[..], PAD_BYTES[..]);
+        assert_eq!(&pad(b"abc")[..3], b"abc");
+        assert_eq!(pad(&[b'x'; 40]).len(), 32);
+    }
+}
@@ -0,0 +1,996 @@
+//! Multi-file Markdown book projects
+//!
+//! A project is a directory of Markdown chapters. Chapter order and nesting come
+//! from the first of these found in the project root:
+//!
+//! - `bookle.yaml`, `bookle.yml` or `bookle.toml`: book metadata plus a `chapters` list
+//! - `book.toml`: mdBook configuration, with `SUMMARY.md` in its `src` directory
+//! - `SUMMARY.md`: an mdBook-style nested list of chapter links
+//!
+//! Without a manifest every Markdown file is included in natural order, with each
+//! directory's `README.md` or `index.md` first and the rest of the directory nested
+//! below it.
+
+use super::context::{resolve_link, AssetLoader};
+use super::front_matter::FrontMatter;
+use super::markdown::inlines_to_text;
+use super::{DecodeContext, MarkdownDecoder};
+use crate::diagnostic::Diagnostic;
+use crate::error::{ParseError, StorageError};
+use crate::types::{Block, Book, Chapter, Inline, Metadata, ResourceStore, TocEntry};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Manifest file names, in order of preference
+const MANIFESTS: &[&str] = &[
+    "bookle.yaml",
+    "bookle.yml",
+    "bookle.toml",
+    "book.toml",
+    "SUMMARY.md",
+];
+
+/// File names treated as a directory's landing page
+const INDEX_FILES: &[&str] = &["README.md", "readme.md", "index.md", "_index.md"];
+
+/// Extensions of Markdown chapter files
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd"];
+
+/// A chapter in the project outline
+#[derive(Debug, Default, PartialEq)]
+struct Entry {
+    /// Title given by the outline
+    title: Option<String>,
+    /// Chapter file relative to the project root (`None` for part titles and drafts)
+    path: Option<String>,
+    /// Nested chapters
+    children: Vec<Entry>,
+}
+
+/// Decoder for books written as a directory of Markdown files
+pub struct MarkdownProjectDecoder {
+    /// Decoder used for each chapter file
+    markdown: MarkdownDecoder,
+}
+
+impl MarkdownProjectDecoder {
+    pub fn new() -> Self {
+        Self {
+            markdown: MarkdownDecoder::new(),
+        }
+    }
+
+    /// Use a configured Markdown decoder for the chapter files
+    pub fn with_markdown(mut self, markdown: MarkdownDecoder) -> Self {
+        self.markdown = markdown;
+        self
+    }
+
+    /// Split a project path into its root directory and manifest file name
+    ///
+    /// Accepts a project directory or a manifest inside one, and returns `None`
+    /// for any other path.
+    pub fn project_root(path: &Path) -> Option<(&Path, Option<&str>)> {
+        if path.is_dir() {
+            return Some((path, None));
+        }
+
+        let name = path.file_name()?.to_str()?;
+        let root = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        MANIFESTS.contains(&name).then_some((root, Some(name)))
+    }
+
+    /// Decode a project directory or manifest file from the local filesystem
+    pub fn decode_path(&self, path: &Path) -> Result<Book, ParseError> {
+        let (root, manifest) = Self::project_root(path).ok_or_else(|| {
+            ParseError::UnsupportedFormat(format!(
+                "{} is not a directory or project manifest",
+                path.display()
+            ))
+        })?;
+
+        let context = DecodeContext::new().with_base_path(root);
+        self.decode_project(&context, manifest)
+    }
+
+    /// Decode a project whose files are read through `context`
+    ///
+    /// `manifest` names the manifest to use; when `None` the project root is
+    /// searched for one.
+    pub fn decode_project(
+        &self,
+        context: &DecodeContext,
+        manifest: Option<&str>,
+    ) -> Result<Book, ParseError> {
+        let manifest = match manifest {
+            Some(name) => Some((name, read_text(context, name)?)),
+            None => find_manifest(context)?,
+        };
+
+        let (settings, outline) = match manifest {
+            Some((name, source)) => read_manifest(context, name, &source)?,
+            None => (None, directory_outline(context, "")?),
+        };
+
+        let mut project = Project::default();
+        let toc = self.load_entries(context, &outline, 0, &mut project)?;
+        if project.chapters.is_empty() {
+            return Err(ParseError::MalformedContent(
+                "Project contains no Markdown chapters".to_string(),
+            ));
+        }
+
+        // Book metadata comes from the manifest, then from chapter front matter
+        let title = settings
+            .as_ref()
+            .and_then(FrontMatter::title)
+            .or_else(|| project.chapters.first().map(|c| c.chapter.title.clone()))
+            .unwrap_or_else(|| "Untitled".to_string());
+        let mut metadata = Metadata::new(title, "en");
+        project.merge_front_matter(&mut metadata);
+        if let Some(settings) = &settings {
+            settings.apply_to(&mut metadata);
+        }
+
+        // Point links at the merged chapters and load images relative to each file
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        let mut chapters = Vec::with_capacity(project.chapters.len());
+        for loaded in &project.chapters {
+            let mut chapter = loaded.chapter.clone();
+            rewrite_links(&mut chapter.content, &|url| {
+                project.resolve(&loaded.path, url)
+            });
+
+            loader.set_dir(parent_dir(&loaded.path));
+            loader.load_images(&mut chapter.content);
+            chapters.push(chapter);
+        }
+
+        loader.set_dir("");
+        if let Some(cover) = &metadata.cover_resource_key {
+            metadata.cover_resource_key = loader.load(cover).or(metadata.cover_resource_key);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+        book.chapters = chapters;
+        book.toc = toc;
+
+        Ok(book)
+    }
+
+    /// Load the chapters of an outline, returning their TOC entries
+    fn load_entries(
+        &self,
+        context: &DecodeContext,
+        entries: &[Entry],
+        level: u32,
+        project: &mut Project,
+    ) -> Result<Vec<TocEntry>, ParseError> {
+        let mut toc = Vec::new();
+
+        for entry in entries {
+            let chapter = match &entry.path {
+                Some(path) => self.load_chapter(context, entry, path, project)?,
+                None => None,
+            };
+            let children = self.load_entries(context, &entry.children, level + 1, project)?;
+
+            // Part titles and drafts link to their first chapter, if they have any
+            let (title, href) = match chapter {
+                Some(index) => {
+                    let chapter = &project.chapters[index].chapter;
+                    (
+                        chapter.title.clone(),
+                        chapter.id.clone().unwrap_or_default(),
+                    )
+                }
+                None => match (&entry.title, children.first()) {
+                    (Some(title), Some(first)) => (
+                        title.clone(),
+                        first.href.trim_start_matches('#').to_string(),
+                    ),
+                    _ => {
+                        toc.extend(children);
+                        continue;
+                    }
+                },
+            };
+
+            toc.push(
+                TocEntry::new(title, format!("#{}", href))
+                    .with_level(level)
+                    .with_children(children),
+            );
+        }
+
+        Ok(toc)
+    }
+
+    /// Read and parse a chapter file, returning its index in the project
+    fn load_chapter(
+        &self,
+        context: &DecodeContext,
+        entry: &Entry,
+        path: &str,
+        project: &mut Project,
+    ) -> Result<Option<usize>, ParseError> {
+        if let Some(index) = project.files.get(path) {
+            return Ok(Some(*index));
+        }
+
+        let content = match context.read_asset(path) {
+            Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+            Err(StorageError::NotFound(_)) => {
+                context.report(
+                    Diagnostic::warning("missing-chapter", format!("Missing chapter: {}", path))
+                        .with_path(path),
+                );
+                return Ok(None);
+            }
+            Err(e) => {
+                context.report(
+                    Diagnostic::warning(
+                        "unreadable-chapter",
+                        format!("Could not load chapter {}: {}", path, e),
+                    )
+                    .with_path(path),
+                );
+                return Ok(None);
+            }
+        };
+
+        let (front_matter, mut blocks) = self.markdown.parse_document(&content, context)?;
+
+        let title = entry
+            .title
+            .clone()
+            .or_else(|| front_matter.as_ref().and_then(FrontMatter::title))
+            .or_else(|| first_heading(&blocks))
+            .unwrap_or_else(|| file_stem(path).to_string());
+
+        let id = project.unique_id(&slugify(&strip_extension(path).replace('/', "-")));
+        for block in &mut blocks {
+            if let Block::Header {
+                content, anchor, ..
+            } = block
+            {
+                let original = anchor
+                    .clone()
+                    .unwrap_or_else(|| slugify(&inlines_to_text(content)));
+                let unique = project.unique_id(&original);
+                project
+                    .anchors
+                    .insert((path.to_string(), original), unique.clone());
+                *anchor = Some(unique);
+            }
+        }
+
+        let index = project.chapters.len();
+        project.files.insert(path.to_string(), index);
+        project.chapters.push(LoadedChapter {
+            path: path.to_string(),
+            chapter: Chapter::new(title).with_id(id).with_content(blocks),
+            front_matter,
+        });
+
+        Ok(Some(index))
+    }
+}
+
+impl Default for MarkdownProjectDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A chapter file loaded from the project
+struct LoadedChapter {
+    path: String,
+    chapter: Chapter,
+    front_matter: Option<FrontMatter>,
+}
+
+/// Chapters and link targets collected while loading a project
+#[derive(Default)]
+struct Project {
+    chapters: Vec<LoadedChapter>,
+    /// Chapter index by file path
+    files: HashMap<String, usize>,
+    /// Book-wide heading anchor by file path and the anchor used within that file
+    anchors: HashMap<(String, String), String>,
+    /// Chapter IDs and anchors already in use
+    ids: HashSet<String>,
+}
+
+impl Project {
+    /// Reserve an ID that is unique across the book
+    fn unique_id(&mut self, base: &str) -> String {
+        let base = if base.is_empty() { "section" } else { base };
+        let mut id = base.to_string();
+        let mut n = 1;
+        while self.ids.contains(&id) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        self.ids.insert(id.clone());
+        id
+    }
+
+    /// Internal target for a link in the chapter at `from`, if it points into the project
+    fn resolve(&self, from: &str, url: &str) -> Option<String> {
+        let (path, fragment) = match url.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (url, None),
+        };
+
+        if path.is_empty() {
+            let anchor = self
+                .anchors
+                .get(&(from.to_string(), fragment?.to_string()))?;
+            return Some(format!("#{}", anchor));
+        }
+
+        let target = resolve_link(parent_dir(from), path)?;
+        // mdBook sources often link to the rendered page
+        let target = match target.strip_suffix(".html") {
+            Some(stem) => format!("{}.md", stem),
+            None => target,
+        };
+
+        let chapter = &self.chapters[*self.files.get(&target)?].chapter;
+        let anchor = fragment
+            .and_then(|fragment| self.anchors.get(&(target.clone(), fragment.to_string())))
+            .or(chapter.id.as_ref())?;
+        Some(format!("#{}", anchor))
+    }
+
+    /// Merge chapter front matter into book metadata, earlier chapters taking precedence
+    ///
+    /// Chapter titles stay with their chapters, and cover paths are resolved
+    /// relative to the file that names them.
+    fn merge_front_matter(&self, metadata: &mut Metadata) {
+        for loaded in self.chapters.iter().rev() {
+            let Some(front_matter) = &loaded.front_matter else {
+                continue;
+            };
+
+            let title = std::mem::take(&mut metadata.title);
+            front_matter.apply_to(metadata);
+            metadata.title = title;
+
+            if let Some(cover) = front_matter.cover() {
+                metadata.cover_resource_key =
+                    resolve_link(parent_dir(&loaded.path), &cover).or(Some(cover));
+            }
+        }
+    }
+}
+
+/// Look for a manifest in the project root
+fn find_manifest(context: &DecodeContext) -> Result<Option<(&'static str, String)>, ParseError> {
+    for name in MANIFESTS {
+        match context.read_asset(name) {
+            Ok(data) => return Ok(Some((name, String::from_utf8_lossy(&data).into_owned()))),
+            Err(StorageError::NotFound(_)) => continue,
+            Err(e) => return Err(storage_error(name, e)),
+        }
+    }
+    Ok(None)
+}
+
+/// Parse a manifest into book settings and a chapter outline
+fn read_manifest(
+    context: &DecodeContext,
+    name: &str,
+    source: &str,
+) -> Result<(Option<FrontMatter>, Vec<Entry>), ParseError> {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+
+    if file_name.eq_ignore_ascii_case("SUMMARY.md") {
+        return Ok((None, parse_summary(source, parent_dir(name))));
+    }
+
+    let settings = if file_name.ends_with(".toml") {
+        FrontMatter::from_toml(source)?
+    } else {
+        FrontMatter::from_yaml(source)?.ok_or_else(|| {
+            ParseError::MalformedContent(format!("{} must be a YAML mapping", name))
+        })?
+    };
+
+    // mdBook keeps its metadata in [book] and its chapters in src/SUMMARY.md
+    if file_name == "book.toml" {
+        let book = settings.section("book").unwrap_or_default();
+        let src = book.value("src").and_then(Value::as_str).unwrap_or("src");
+        let summary = join(src, "SUMMARY.md");
+        let outline = parse_summary(&read_text(context, &summary)?, src);
+        return Ok((Some(book), outline));
+    }
+
+    let src = settings.value("src").and_then(Value::as_str).unwrap_or("");
+    let outline = if let Some(chapters) = settings.value("chapters") {
+        outline_from_value(chapters, src)
+    } else {
+        let summary = settings
+            .value("summary")
+            .and_then(Value::as_str)
+            .map(|summary| join(src, summary))
+            .unwrap_or_else(|| join(src, "SUMMARY.md"));
+        match context.read_asset(&summary) {
+            Ok(data) => parse_summary(&String::from_utf8_lossy(&data), parent_dir(&summary)),
+            Err(StorageError::NotFound(_)) => directory_outline(context, src)?,
+            Err(e) => return Err(storage_error(&summary, e)),
+        }
+    };
+
+    Ok((Some(settings), outline))
+}
+
+/// Parse an mdBook `SUMMARY.md`, whose links are relative to `dir`
+///
+/// Links outside lists are prefix and suffix chapters, nested lists nest chapters,
+/// and headings after the first start a new part.
+fn parse_summary(source: &str, dir: &str) -> Vec<Entry> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut link: Option<(String, String)> = None;
+    let mut heading: Option<String> = None;
+    let mut seen_title = false;
+
+    for event in Parser::new(source) {
+        match event {
+            Event::Start(Tag::List(_)) => depth += 1,
+            Event::End(TagEnd::List(_)) => depth -= 1,
+            Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(_)) => {
+                let title = heading.take().unwrap_or_default();
+                // The first heading is the summary's own title
+                if seen_title {
+                    items.push((
+                        0,
+                        Entry {
+                            title: Some(title.trim().to_string()),
+                            ..Entry::default()
+                        },
+                    ));
+                }
+                seen_title = true;
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                link = Some((dest_url.to_string(), String::new()));
+            }
+            Event::End(TagEnd::Link) => {
+                if let Some((dest, title)) = link.take() {
+                    items.push((
+                        depth.max(1),
+                        Entry {
+                            title: Some(title.trim().to_string()),
+                            path: resolve_link(dir, &dest).filter(|path| !path.is_empty()),
+                            children: Vec::new(),
+                        },
+                    ));
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, title)) = &mut link {
+                    title.push_str(&text);
+                } else if let Some(title) = &mut heading {
+                    title.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    build_tree(items)
+}
+
+/// Nest a flat list of `(depth, entry)` pairs
+fn build_tree(items: Vec<(usize, Entry)>) -> Vec<Entry> {
+    fn attach(stack: &mut [(usize, Entry)], roots: &mut Vec<Entry>, entry: Entry) {
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(entry),
+            None => roots.push(entry),
+        }
+    }
+
+    let mut roots = Vec::new();
+    let mut stack: Vec<(usize, Entry)> = Vec::new();
+
+    for (depth, entry) in items {
+        while stack.last().is_some_and(|(d, _)| *d >= depth) {
+            let (_, done) = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, done);
+        }
+        stack.push((depth, entry));
+    }
+    while let Some((_, done)) = stack.pop() {
+        attach(&mut stack, &mut roots, done);
+    }
+
+    roots
+}
+
+/// Outline from a manifest `chapters` list of paths or `{title, file, chapters}` tables
+fn outline_from_value(value: &Value, dir: &str) -> Vec<Entry> {
+    let Value::Array(items) = value else {
+        return Vec::new();
+    };
+
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Value::String(path) => Some(Entry {
+                path: resolve_link(dir, path),
+                ..Entry::default()
+            }),
+            Value::Object(fields) => {
+                let field = |keys: &[&str]| keys.iter().find_map(|key| fields.get(*key));
+                Some(Entry {
+                    title: field(&["title"])
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    path: field(&["file", "path"])
+                        .and_then(Value::as_str)
+                        .and_then(|path| resolve_link(dir, path)),
+                    children: field(&["chapters", "children", "sections"])
+                        .map(|children| outline_from_value(children, dir))
+                        .unwrap_or_default(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Outline of every Markdown file below `dir`
+fn directory_outline(context: &DecodeContext, dir: &str) -> Result<Vec<Entry>, ParseError> {
+    let files = context.list_assets(dir).map_err(|e| {
+        ParseError::MalformedContent(format!(
+            "No SUMMARY.md, book.toml or bookle.yaml found, and the project can't be listed: {}",
+            e
+        ))
+    })?;
+
+    let files: Vec<String> = files
+        .into_iter()
+        .filter(|file| is_chapter_file(file))
+        .collect();
+
+    let (index, rest) = directory_entries(&files, dir);
+    let mut outline: Vec<Entry> = index
+        .map(|path| Entry {
+            path: Some(path),
+            ..Entry::default()
+        })
+        .into_iter()
+        .collect();
+    outline.extend(rest);
+    Ok(outline)
+}
+
+/// Landing page and remaining entries of a directory, from the files below it
+///
+/// Subdirectories with a landing page become a chapter with their other files
+/// nested below it; those without one are spliced in place.
+fn directory_entries(files: &[String], dir: &str) -> (Option<String>, Vec<Entry>) {
+    let prefix = if dir.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", dir.trim_end_matches('/'))
+    };
+
+    // Directories keep their trailing slash, so `intro.md` sorts before `intro/`
+    let mut names: Vec<&str> = Vec::new();
+    for file in files {
+        let Some(rest) = file.strip_prefix(&prefix) else {
+            continue;
+        };
+        let name = match rest.find('/') {
+            Some(end) => &rest[..=end],
+            None => rest,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.sort_by(|a, b| natural_cmp(a, b));
+
+    let mut index = None;
+    let mut entries = Vec::new();
+    for name in names {
+        let path = format!("{}{}", prefix, name.trim_end_matches('/'));
+
+        if !name.ends_with('/') {
+            if INDEX_FILES.contains(&name) && index.is_none() {
+                index = Some(path);
+            } else {
+                entries.push(Entry {
+                    path: Some(path),
+                    ..Entry::default()
+                });
+            }
+            continue;
+        }
+
+        match directory_entries(files, &path) {
+            (Some(landing), children) => entries.push(Entry {
+                path: Some(landing),
+                children,
+                ..Entry::default()
+            }),
+            (None, children) => entries.extend(children),
+        }
+    }
+
+    (index, entries)
+}
+
+/// Rewrite every link in `blocks` for which `rewrite` gives a new target
+pub(crate) fn rewrite_links(blocks: &mut [Block], rewrite: &dyn Fn(&str) -> Option<String>) {
+    for block in blocks {
+        match block {
+            Block::Paragraph(content) | Block::Header { content, .. } => {
+                rewrite_inline_links(content, rewrite)
+            }
+            Block::List { items, .. } => {
+                for item in items {
+                    rewrite_links(item, rewrite);
+                }
+            }
+            Block::Blockquote(children)
+            | Block::Footnote {
+                content: children, ..
+            }
+            | Block::Admonition {
+                content: children, ..
+            }
+            | Block::Container {
+                content: children, ..
+            } => rewrite_links(children, rewrite),
+            Block::DefinitionList(items) => {
+                for item in items {
+                    rewrite_inline_links(&mut item.term, rewrite);
+                    for definition in &mut item.definitions {
+                        rewrite_links(definition, rewrite);
+                    }
+                }
+            }
+            Block::Table(table) => {
+                for cell in table
+                    .headers
+                    .iter_mut()
+                    .chain(table.rows.iter_mut().flatten())
+                {
+                    rewrite_inline_links(&mut cell.content, rewrite);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rewrite links within inline content
+fn rewrite_inline_links(inlines: &mut [Inline], rewrite: &dyn Fn(&str) -> Option<String>) {
+    for inline in inlines {
+        match inline {
+            Inline::Link { children, url } => {
+                if let Some(target) = rewrite(url) {
+                    *url = target;
+                }
+                rewrite_inline_links(children, rewrite);
+            }
+            Inline::Bold(children)
+            | Inline::Italic(children)
+            | Inline::Superscript(children)
+            | Inline::Subscript(children)
+            | Inline::Strikethrough(children) => rewrite_inline_links(children, rewrite),
+            _ => {}
+        }
+    }
+}
+
+/// Text of the first heading of any level
+fn first_heading(blocks: &[Block]) -> Option<String> {
+    blocks.iter().find_map(|block| match block {
+        Block::Header { content, .. } => Some(inlines_to_text(content)),
+        _ => None,
+    })
+}
+
+/// Anchor for a heading, matching the IDs mdBook generates
+pub(crate) fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                Some(c.to_ascii_lowercase())
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Compare names with embedded numbers in numeric order (`2-intro` before `10-outro`)
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let ((na, ra), (nb, rb)) = (split_digits(a), split_digits(b));
+            let (na, nb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+            let order = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+            if order != Ordering::Equal {
+                return order;
+            }
+            (a, b) = (ra, rb);
+        } else {
+            let order = x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase());
+            if order != Ordering::Equal {
+                return order;
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+/// Split a string after its leading digits
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+/// Whether a project file is a Markdown chapter
+fn is_chapter_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    name != "SUMMARY.md" && extension.is_some_and(|ext| MARKDOWN_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Read a project file as text
+fn read_text(context: &DecodeContext, path: &str) -> Result<String, ParseError> {
+    context
+        .read_asset(path)
+        .map(|data| String::from_utf8_lossy(&data).into_owned())
+        .map_err(|e| storage_error(path, e))
+}
+
+fn storage_error(path: &str, error: StorageError) -> ParseError {
+    ParseError::MalformedContent(format!("Could not read {}: {}", path, error))
+}
+
+/// Directory part of a project path
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Join a project directory and a relative path
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), path)
+    }
+}
+
+fn file_stem(path: &str) -> &str {
+    strip_extension(path.rsplit('/').next().unwrap_or(path))
+}
+
+fn strip_extension(path: &str) -> &str {
+    match path.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem,
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_project(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let full = dir.path().join(path);
+            std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+            std::fs::write(full, content).unwrap();
+        }
+        dir
+    }
+
+    fn titles(toc: &[TocEntry]) -> Vec<String> {
+        toc.iter()
+            .map(|entry| {
+                if entry.children.is_empty() {
+                    entry.title.clone()
+                } else {
+                    format!("{} {:?}", entry.title, titles(&entry.children))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = "# Summary\n\n[Intro](README.md)\n\n- [One](one.md)\n  - [One A](one/a.md)\n- [Draft]()\n\n# Reference\n\n- [Two](./two.md)\n";
+        let outline = parse_summary(summary, "src");
+
+        assert_eq!(outline.len(), 4);
+        assert_eq!(outline[0].path.as_deref(), Some("src/README.md"));
+        assert_eq!(outline[1].children[0].path.as_deref(), Some("src/one/a.md"));
+        assert_eq!(outline[2].path, None);
+        assert_eq!(outline[3].title.as_deref(), Some("Reference"));
+        assert_eq!(outline[3].children[0].path.as_deref(), Some("src/two.md"));
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["10-end.md", "2-middle.md", "1-start.md", "appendix.md"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["1-start.md", "2-middle.md", "10-end.md", "appendix.md"]
+        );
+    }
+
+    #[test]
+    fn test_decode_mdbook() {
+        let project = write_project(&[
+            (
+                "book.toml",
+                "[book]\ntitle = \"The Guide\"\nauthors = [\"Ann\"]\nlanguage = \"de\"\n",
+            ),
+            (
+                "src/SUMMARY.md",
+                "# Summary\n\n- [Start](start.md)\n  - [Details](start/details.md)\n- [End](end.md)\n",
+            ),
+            (
+                "src/start.md",
+                "# Getting Started\n\nSee [the details](start/details.md#fine-print).\n",
+            ),
+            ("src/start/details.md", "# Details\n\n## Fine Print\n\nBack to [start](../start.html).\n"),
+            ("src/end.md", "# Getting Started\n\nThe end.\n"),
+        ]);
+
+        let book = MarkdownProjectDecoder::new()
+            .decode_path(project.path())
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "The Guide");
+        assert_eq!(book.metadata.creator, vec!["Ann"]);
+        assert_eq!(book.metadata.language, "de");
+        assert_eq!(titles(&book.toc), vec!["Start [\"Details\"]", "End"]);
+
+        let ids: Vec<_> = book
+            .chapters
+            .iter()
+            .map(|c| c.id.clone().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["src-start", "src-start-details", "src-end"]);
+
+        // Repeated headings get book-wide unique anchors
+        let Block::Header { anchor, .. } = &book.chapters[2].content[0] else {
+            panic!("expected a heading");
+        };
+        assert_eq!(anchor.as_deref(), Some("getting-started-2"));
+
+        let Block::Paragraph(content) = &book.chapters[0].content[1] else {
+            panic!("expected a paragraph");
+        };
+        assert!(content.contains(&Inline::Link {
+            children: vec![Inline::text("the details")],
+            url: "#fine-print".into(),
+        }));
+
+        let Block::Paragraph(content) = &book.chapters[1].content[2] else {
+            panic!("expected a paragraph");
+        };
+        assert!(content.contains(&Inline::Link {
+            children: vec![Inline::text("start")],
+            url: "#src-start".into(),
+        }));
+    }
+
+    #[test]
+    fn test_decode_manifest_and_front_matter() {
+        let project = write_project(&[
+            (
+                "bookle.yaml",
+                "title: Field Notes\nchapters:\n  - intro.md\n  - title: Part One\n    chapters:\n      - part1/a.md\n",
+            ),
+            (
+                "intro.md",
+                "---\nauthor: Bob\ndescription: Notes\ncover: img/cover.png\n---\nIntro text.\n",
+            ),
+            ("part1/a.md", "---\ntitle: Alpha\nauthor: Carol\n---\nText.\n"),
+            ("img/cover.png", "\u{89}PNG\r\n\u{1a}\n"),
+        ]);
+
+        let book = MarkdownProjectDecoder::new()
+            .decode_path(&project.path().join("bookle.yaml"))
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "Field Notes");
+        assert_eq!(book.metadata.creator, vec!["Bob"]);
+        assert_eq!(book.metadata.description.as_deref(), Some("Notes"));
+        assert!(book.metadata.cover_resource_key.is_some());
+        assert_eq!(book.resources.len(), 1);
+        assert_eq!(titles(&book.toc), vec!["intro", "Part One [\"Alpha\"]"]);
+    }
+
+    #[test]
+    fn test_decode_directory() {
+        let project = write_project(&[
+            ("README.md", "# Welcome\n"),
+            ("10-last.md", "# Last\n"),
+            ("2-second.md", "# Second\n\n![Map](images/map.png)\n"),
+            ("2-second/notes.md", "# Notes\n"),
+            ("2-second/README.md", "# Second Part\n"),
+            (".drafts/idea.md", "# Idea\n"),
+        ]);
+
+        let context = DecodeContext::new().with_base_path(project.path());
+        let book = MarkdownProjectDecoder::new()
+            .decode_project(&context, None)
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "Welcome");
+        assert_eq!(
+            titles(&book.toc),
+            vec!["Welcome", "Second", "Second Part [\"Notes\"]", "Last"]
+        );
+        assert_eq!(context.warnings(), vec!["Missing asset: images/map.png"]);
+    }
+
+    #[test]
+    fn test_same_named_assets_stay_apart_in_epub() {
+        use crate::encoder::{Encoder, EpubEncoder};
+        use std::io::Read;
+
+        let project = write_project(&[
+            ("ch1/README.md", "# One\n\n![Fig](img/fig.png)\n"),
+            ("ch1/img/fig.png", "first"),
+            ("ch2/README.md", "# Two\n\n![Fig](img/fig.png)\n"),
+            ("ch2/img/fig.png", "second"),
+        ]);
+        let context = DecodeContext::new().with_base_path(project.path());
+        let book = MarkdownProjectDecoder::new()
+            .decode_project(&context, None)
+            .unwrap();
+        assert_eq!(book.resources.len(), 2);
+
+        let mut output = Vec::new();
+        EpubEncoder::new().encode(&book, &mut output).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive
+                .by_name(&format!("OEBPS/{}", name))
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let mut images = Vec::new();
+        for chapter in ["chapter_1.xhtml", "chapter_2.xhtml"] {
+            let xhtml = read(chapter);
+            let start = xhtml.find("src=\"").unwrap() + 5;
+            let end = start + xhtml[start..].find('"').unwrap();
+            images.push(read(&xhtml[start..end]));
+        }
+        assert_eq!(images, vec!["first", "second"]);
+    }
+}
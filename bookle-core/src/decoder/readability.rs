@@ -0,0 +1,184 @@
+//! Main content extraction for saved web pages
+//!
+//! Web articles come wrapped in navigation, sidebars, comment threads and
+//! footers. [`main_content`] picks the element most likely to hold the article
+//! itself, in the manner of Readability: paragraphs add to the score of their
+//! parent and grandparent by length, class and id names count for or against an
+//! element, and elements made mostly of links are discounted.
+
+use super::html::text_content;
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashMap;
+
+/// Elements that never hold the main content
+const BOILERPLATE_ELEMENTS: &[&str] = &["nav", "aside", "footer", "form", "button", "menu"];
+
+/// Class and id words suggesting an element holds the content
+const POSITIVE_NAMES: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "text", "story", "prose",
+];
+
+/// Class and id words suggesting boilerplate
+const NEGATIVE_NAMES: &[&str] = &[
+    "comment",
+    "footer",
+    "nav",
+    "sidebar",
+    "menu",
+    "share",
+    "social",
+    "related",
+    "promo",
+    "sponsor",
+    "advert",
+    "banner",
+    "widget",
+    "cookie",
+    "subscribe",
+    "breadcrumb",
+];
+
+/// Inner HTML of the element most likely to hold a page's main content
+///
+/// Returns `None` when no element stands out, in which case the whole body should
+/// be used.
+pub(crate) fn main_content(document: &Html) -> Option<String> {
+    let mut document = document.clone();
+
+    // Boilerplate is removed first so that it can't win, or dilute the winner
+    let boilerplate: Vec<NodeId> = document
+        .tree
+        .root()
+        .descendants()
+        .filter(|node| {
+            node.value().as_element().is_some_and(|element| {
+                BOILERPLATE_ELEMENTS.contains(&element.name())
+                    || (!matches!(element.name(), "html" | "body") && name_weight(element) < 0.0)
+            })
+        })
+        .map(|node| node.id())
+        .collect();
+    for id in boilerplate {
+        if let Some(mut node) = document.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    let mut scores: HashMap<NodeId, f32> = HashMap::new();
+    for node in document.tree.root().descendants() {
+        let is_paragraph = node
+            .value()
+            .as_element()
+            .is_some_and(|element| matches!(element.name(), "p" | "pre" | "td" | "blockquote"));
+        if !is_paragraph {
+            continue;
+        }
+
+        let text = text_content(node);
+        let len = text.trim().chars().count();
+        if len < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f32 + (len as f32 / 100.0).min(3.0);
+
+        let ancestors = node
+            .ancestors()
+            .filter(|ancestor| ancestor.value().is_element())
+            .take(2);
+        for (depth, ancestor) in ancestors.enumerate() {
+            let entry = scores
+                .entry(ancestor.id())
+                .or_insert_with(|| initial_score(ancestor));
+            *entry += if depth == 0 { score } else { score / 2.0 };
+        }
+    }
+
+    let (best, _) = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let node = document.tree.get(id)?;
+            Some((id, score * (1.0 - link_density(node))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    let node = document.tree.get(best)?;
+    Some(ElementRef::wrap(node)?.inner_html())
+}
+
+/// Score an element starts with, from its tag and names
+fn initial_score(node: NodeRef<Node>) -> f32 {
+    let Some(element) = node.value().as_element() else {
+        return 0.0;
+    };
+    let tag_score = match element.name() {
+        "article" | "main" => 10.0,
+        "div" => 5.0,
+        "section" | "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag_score + name_weight(element)
+}
+
+/// Weight of an element's class and id, positive for content-like names
+fn name_weight(element: &scraper::node::Element) -> f32 {
+    let names = [element.attr("class"), element.id()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if names.is_empty() {
+        return 0.0;
+    }
+
+    let mut weight = 0.0;
+    if NEGATIVE_NAMES.iter().any(|name| names.contains(name)) {
+        weight -= 25.0;
+    }
+    if POSITIVE_NAMES.iter().any(|name| names.contains(name)) {
+        weight += 25.0;
+    }
+    weight
+}
+
+/// Share of an element's text that is inside links
+fn link_density(node: NodeRef<Node>) -> f32 {
+    let total = text_content(node).chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+    let links: usize = node
+        .descendants()
+        .filter(|n| n.value().as_element().is_some_and(|e| e.name() == "a"))
+        .map(|link| text_content(link).chars().count())
+        .sum();
+    links as f32 / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main_content_skips_boilerplate() {
+        let html = r#"<html><body>
+            <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+            <div class="sidebar"><p>Subscribe to our newsletter for weekly updates, tips and more.</p></div>
+            <div class="post-content">
+              <h1>Headline</h1>
+              <p>The first paragraph of the article, which is long enough to count, and has commas.</p>
+              <p>A second paragraph continues the story, with more detail than the sidebar offers.</p>
+            </div>
+            <footer><p>Copyright notice and a long list of legal terms that nobody reads.</p></footer>
+        </body></html>"#;
+
+        let content = main_content(&Html::parse_document(html)).unwrap();
+        assert!(content.contains("Headline"));
+        assert!(content.contains("second paragraph"));
+        assert!(!content.contains("newsletter"));
+        assert!(!content.contains("Copyright"));
+    }
+}
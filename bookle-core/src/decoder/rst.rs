@@ -0,0 +1,2464 @@
+//! reStructuredText decoder implementation
+//!
+//! Covers the commonly used subset of reStructuredText and its Sphinx
+//! extensions: section titles (levels follow the order adornment styles first
+//! appear, and a lone top-level title becomes the document title), the
+//! bibliographic field list after the title, paragraphs, literal blocks, lists,
+//! block quotes, grid and simple tables, footnotes and citations, hyperlink
+//! targets and references, and the usual directives (admonitions, `code-block`,
+//! `image`, `figure`, `list-table`, `csv-table`, `topic`). `include` and
+//! `literalinclude` directives are resolved before parsing, relative to the file
+//! that contains them.
+
+use super::chapters::{place_footnotes, ChapterSplitter, SplitOptions, SplitStrategy};
+use super::context::{resolve_link, AssetLoader};
+use super::front_matter::FrontMatter;
+use super::markdown::inlines_to_text;
+use super::markup::{bare_url, dedent, default_alt, MAX_INCLUDE_DEPTH};
+use super::text::decode_bytes;
+use super::xml::trim_inlines;
+use super::DecodeContext;
+use crate::diagnostic::Diagnostic;
+use crate::error::ParseError;
+use crate::types::{
+    AdmonitionKind, Block, Book, DefinitionItem, Inline, Metadata, ResourceStore, TableCell,
+    TableData,
+};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::sync::OnceLock;
+
+/// Schemes of URLs that are links without any markup
+const URL_SCHEMES: &[&str] = &["https://", "http://", "ftp://", "mailto:"];
+
+/// Characters that may adorn a section title
+const ADORNMENTS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// Roles whose text is code
+const CODE_ROLES: &[&str] = &[
+    "literal",
+    "code",
+    "file",
+    "command",
+    "program",
+    "option",
+    "envvar",
+    "kbd",
+    "samp",
+    "func",
+    "meth",
+    "class",
+    "mod",
+    "attr",
+    "data",
+    "const",
+    "exc",
+    "obj",
+    "makevar",
+    "math",
+    "regexp",
+    "mailheader",
+    "mimetype",
+    "keyword",
+    "token",
+];
+
+/// Decoder for reStructuredText documents
+pub struct RstDecoder {
+    /// Where the document is divided into chapters
+    splitter: ChapterSplitter,
+}
+
+impl RstDecoder {
+    pub fn new() -> Self {
+        Self {
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Set where the document is divided into chapters (top-level sections by default)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+}
+
+impl Default for RstDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for RstDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(|e| {
+            ParseError::MalformedContent(format!("Failed to read reStructuredText: {}", e))
+        })?;
+        let (text, _) = decode_bytes(&data);
+
+        let mut lines = Vec::new();
+        expand_includes(&text, "", 0, context, &mut lines);
+
+        let mut parser = Parser::new(&lines);
+        let mut blocks = parser.document(&lines);
+
+        let title = parser
+            .title
+            .clone()
+            .or_else(|| super::MarkdownDecoder::extract_title(&blocks))
+            .unwrap_or_else(|| "Untitled".to_string());
+        let mut metadata = Metadata::new(title, "en");
+        FrontMatter::from_fields(std::mem::take(&mut parser.docinfo)).apply_to(&mut metadata);
+
+        // Load images, pointing references at the stored resources
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        loader.load_images(&mut blocks);
+        if let Some(cover) = &metadata.cover_resource_key {
+            metadata.cover_resource_key = loader.load(cover);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.resources = resources;
+        let mut chapters = self.splitter.split(blocks);
+        place_footnotes(&mut chapters, parser.footnotes);
+        for chapter in chapters {
+            book.add_chapter(chapter);
+        }
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["rst", "rest"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/x-rst", "text/prs.fallenstein.rst"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// Append the lines of `text`, read from directory `dir`, to `out`, replacing
+/// `include` and `literalinclude` directives with what they include
+///
+/// Literal includes become `code-block` directives, so the parser sees them as
+/// any other listing.
+fn expand_includes(
+    text: &str,
+    dir: &str,
+    depth: usize,
+    context: &DecodeContext,
+    out: &mut Vec<String>,
+) {
+    let lines: Vec<String> = text.lines().map(expand_tabs).collect();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = &lines[index];
+        index += 1;
+        let Some((name, target)) = line
+            .strip_prefix(".. ")
+            .and_then(|rest| rest.split_once("::"))
+            .filter(|(name, _)| matches!(name.trim(), "include" | "literalinclude"))
+        else {
+            out.push(line.clone());
+            continue;
+        };
+
+        // Options follow on indented lines
+        let mut options = HashMap::new();
+        while let Some(option) = lines.get(index).filter(|l| l.starts_with([' ', '\t'])) {
+            if let Some((key, value)) = field(option.trim()) {
+                options.insert(key.to_string(), value.trim().to_string());
+            }
+            index += 1;
+        }
+
+        let target = target.trim();
+        let Some(path) = resolve_link(dir, target).filter(|_| depth < MAX_INCLUDE_DEPTH) else {
+            context.report(
+                Diagnostic::warning("skipped-include", format!("Skipped include of {}", target))
+                    .with_path(target),
+            );
+            continue;
+        };
+        let included = match context.read_asset(&path) {
+            Ok(data) => decode_bytes(&data).0,
+            Err(e) => {
+                context.report(
+                    Diagnostic::warning(
+                        "unresolved-include",
+                        format!("Unresolved include {}: {}", target, e),
+                    )
+                    .with_path(path.clone()),
+                );
+                continue;
+            }
+        };
+        let included = select_lines(&included, &options);
+
+        let language = options
+            .get("language")
+            .or_else(|| options.get("code"))
+            .cloned();
+        if name.trim() == "literalinclude" || options.contains_key("literal") || language.is_some()
+        {
+            out.push(format!(".. code-block:: {}", language.unwrap_or_default()));
+            out.push(String::new());
+            out.extend(
+                included
+                    .lines()
+                    .map(|line| format!("   {}", expand_tabs(line))),
+            );
+            out.push(String::new());
+        } else {
+            let included_dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            expand_includes(&included, included_dir, depth + 1, context, out);
+        }
+    }
+}
+
+/// Lines of an included file chosen by its `start-line`/`end-line` or
+/// `start-after`/`end-before` options
+fn select_lines(text: &str, options: &HashMap<String, String>) -> String {
+    let mut text = text.to_string();
+    if let Some(marker) = options.get("start-after") {
+        if let Some(start) = text.find(marker.as_str()) {
+            text = text[start + marker.len()..].to_string();
+        }
+    }
+    if let Some(marker) = options.get("end-before") {
+        if let Some(end) = text.find(marker.as_str()) {
+            text.truncate(end);
+        }
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let start = options
+        .get("start-line")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0usize)
+        .min(lines.len());
+    let end = options
+        .get("end-line")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(lines.len())
+        .clamp(start, lines.len());
+    lines[start..end].join("\n")
+}
+
+/// Replace tabs with spaces to the next multiple of eight columns
+fn expand_tabs(line: &str) -> String {
+    if !line.contains('\t') {
+        return line.trim_end().to_string();
+    }
+    let mut expanded = String::new();
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = 8 - expanded.chars().count() % 8;
+            expanded.push_str(&" ".repeat(spaces));
+        } else {
+            expanded.push(c);
+        }
+    }
+    expanded.trim_end().to_string()
+}
+
+/// Key and value of a field list line, `:key: value`
+fn field(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix(':')?;
+    let end = rest
+        .find(": ")
+        .or_else(|| rest.strip_suffix(':').map(str::len))?;
+    let key = &rest[..end];
+    if key.is_empty() || key.starts_with(' ') {
+        return None;
+    }
+    Some((key, rest.get(end + 1..).unwrap_or_default().trim()))
+}
+
+/// Adornment style of a section title: its character and whether it has an overline
+type Style = (char, bool);
+
+/// A section title at the start of `lines`, with its style and the number of
+/// lines it occupies
+fn section_at(lines: &[String], index: usize) -> Option<(Style, String, usize)> {
+    let line = lines.get(index)?;
+    let next = lines.get(index + 1);
+
+    // Overline, title and underline
+    if let Some(c) = adornment(line) {
+        let title = next?;
+        let under = lines.get(index + 2)?;
+        if !title.trim().is_empty() && adornment(under) == Some(c) && under.len() == line.len() {
+            return Some(((c, true), title.trim().to_string(), 3));
+        }
+        return None;
+    }
+
+    // Title and underline
+    if line.starts_with(' ') || line.trim().is_empty() {
+        return None;
+    }
+    let under = next?;
+    let c = adornment(under)?;
+    if under.chars().count() < line.trim_end().chars().count().min(4) {
+        return None;
+    }
+    Some(((c, false), line.trim().to_string(), 2))
+}
+
+/// The character of a line made of one repeated adornment character
+fn adornment(line: &str) -> Option<char> {
+    let c = line.chars().next()?;
+    (ADORNMENTS.contains(c) && line.len() >= 2 && line.chars().all(|x| x == c)).then_some(c)
+}
+
+/// Normalised name of a reference or target, as docutils compares them
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Id for a section or target name: lowercase words joined by hyphens
+fn make_id(name: &str) -> String {
+    let mut id = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            id.extend(c.to_lowercase());
+        } else if !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+    id.trim_end_matches('-').to_string()
+}
+
+/// Lines of the block that starts at `index` and continues on indented lines
+///
+/// Returns the first line's text after `skip` columns, the following lines with
+/// their shared indentation removed, and the index after the block.
+fn indented_block(lines: &[String], index: usize, skip: usize) -> (String, Vec<String>, usize) {
+    let first = lines[index].get(skip..).unwrap_or_default().to_string();
+    let (rest, end) = indented_lines(lines, index + 1);
+    (first, rest, end)
+}
+
+/// The indented lines from `start`, dedented, and the index after them
+fn indented_lines(lines: &[String], start: usize) -> (Vec<String>, usize) {
+    let mut end = start;
+    while end < lines.len() {
+        let line = &lines[end];
+        if !line.trim().is_empty() && !line.starts_with(' ') {
+            break;
+        }
+        end += 1;
+    }
+    // Trailing blank lines belong to what follows
+    while end > start && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    (dedent(&lines[start..end]), end)
+}
+
+/// Parser state for a document
+struct Parser {
+    /// Document title, from a lone top-level section title or the `title` directive
+    title: Option<String>,
+    /// Bibliographic fields, in front matter form
+    docinfo: Map<String, Value>,
+    /// Heading level of each section style, by order of first appearance
+    styles: Vec<Style>,
+    /// Styles of the document title and subtitle, which are not sections
+    title_styles: Vec<Style>,
+    /// External targets by normalised name, `.. _name: url`
+    targets: HashMap<String, String>,
+    /// Anonymous targets, `__ url`, in order
+    anonymous: VecDeque<String>,
+    /// Section anchors by normalised title or label
+    anchors: HashMap<String, String>,
+    /// Section titles by anchor, for the text of `:ref:` links
+    titles: HashMap<String, String>,
+    /// Substitution definitions, `.. |name| replace:: text`
+    substitutions: HashMap<String, String>,
+    /// Footnotes defined in the document, in order
+    footnotes: Vec<(String, Block)>,
+    /// Auto-numbered footnote references and definitions seen so far
+    auto_refs: usize,
+    auto_notes: usize,
+    /// Language of `::` literal blocks, set by the `highlight` directive
+    highlight: Option<String>,
+    /// Labels waiting for the section they come before
+    pending_labels: Vec<String>,
+}
+
+impl Parser {
+    fn new(lines: &[String]) -> Self {
+        let mut parser = Self {
+            title: None,
+            docinfo: Map::new(),
+            styles: Vec::new(),
+            title_styles: Vec::new(),
+            targets: HashMap::new(),
+            anonymous: VecDeque::new(),
+            anchors: HashMap::new(),
+            titles: HashMap::new(),
+            substitutions: HashMap::new(),
+            footnotes: Vec::new(),
+            auto_refs: 0,
+            auto_notes: 0,
+            highlight: None,
+            pending_labels: Vec::new(),
+        };
+        parser.scan_targets(lines);
+        parser
+    }
+
+    /// Find targets, substitutions and section titles before parsing, so that
+    /// references can be resolved wherever they appear
+    fn scan_targets(&mut self, lines: &[String]) {
+        static TARGET: OnceLock<Regex> = OnceLock::new();
+        static SUBSTITUTION: OnceLock<Regex> = OnceLock::new();
+        let target =
+            TARGET.get_or_init(|| Regex::new(r"^\s*\.\. _(`[^`]+`|[^:]+):\s*(.*)$").unwrap());
+        let substitution = SUBSTITUTION.get_or_init(|| {
+            Regex::new(r"^\s*\.\. \|([^|]+)\| (replace|unicode)::\s*(.*)$").unwrap()
+        });
+
+        let mut labels = Vec::new();
+        let mut counts: HashMap<Style, usize> = HashMap::new();
+        // The first two things in the document: a section title's style, or
+        // None for other content
+        let mut leading: Vec<Option<Style>> = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            if let Some(captures) = target.captures(line) {
+                let name = captures[1].trim_matches('`');
+                let url = captures[2].trim();
+                if url.is_empty() {
+                    labels.push(name.to_string());
+                } else if name == "_" {
+                    self.anonymous.push_back(url.to_string());
+                } else {
+                    self.targets
+                        .insert(normalize_name(name), url.replace(' ', ""));
+                }
+                index += 1;
+                continue;
+            }
+            if let Some(anonymous) = line.strip_prefix("__ ") {
+                self.anonymous.push_back(anonymous.trim().to_string());
+            }
+            if let Some(captures) = substitution.captures(line) {
+                let text = if &captures[2] == "unicode" {
+                    unicode_substitution(&captures[3])
+                } else {
+                    captures[3].to_string()
+                };
+                self.substitutions.insert(captures[1].to_string(), text);
+            }
+            if let Some((style, title, consumed)) = section_at(lines, index) {
+                let anchor = labels
+                    .first()
+                    .map(|label| make_id(label))
+                    .unwrap_or_else(|| make_id(&title));
+                for label in labels.drain(..) {
+                    self.anchors.insert(normalize_name(&label), anchor.clone());
+                }
+                self.anchors
+                    .entry(normalize_name(&title))
+                    .or_insert_with(|| anchor.clone());
+                self.titles.insert(anchor, title);
+                *counts.entry(style).or_default() += 1;
+                if leading.len() < 2 {
+                    leading.push(Some(style));
+                }
+                index += consumed;
+                continue;
+            }
+            if !line.trim().is_empty() && !line.starts_with("..") && !line.starts_with(' ') {
+                labels.clear();
+                if leading.len() < 2 {
+                    leading.push(None);
+                }
+            }
+            index += 1;
+        }
+
+        // A title whose style is used only once, before any other content,
+        // is the document title; another directly after it is the subtitle
+        let once =
+            |style: Option<&Option<Style>>| style.copied().flatten().filter(|s| counts[s] == 1);
+        if let Some(title) = once(leading.first()) {
+            self.title_styles.push(title);
+            self.title_styles.extend(once(leading.get(1)));
+        }
+    }
+
+    /// Parse the whole document
+    fn document(&mut self, lines: &[String]) -> Vec<Block> {
+        self.blocks(lines, true)
+    }
+
+    /// Parse blocks; sections are recognised only at the top level
+    fn blocks(&mut self, lines: &[String], top_level: bool) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            if line.trim().is_empty() {
+                index += 1;
+                continue;
+            }
+
+            if top_level {
+                if let Some((style, title, consumed)) = section_at(lines, index) {
+                    index += consumed;
+                    if self.title_styles.contains(&style) {
+                        // The docinfo follows the title, or the subtitle if there is one
+                        if self.title.is_none() {
+                            self.title = Some(title);
+                        }
+                        index = self.docinfo(lines, index);
+                        continue;
+                    }
+                    if !self.styles.contains(&style) {
+                        self.styles.push(style);
+                    }
+                    let level = self.styles.iter().position(|s| *s == style).unwrap_or(0) + 1;
+                    let anchor = self.anchors.get(&normalize_name(&title)).cloned();
+                    let anchor = self
+                        .pending_labels
+                        .drain(..)
+                        .next()
+                        .and_then(|label| self.anchors.get(&normalize_name(&label)).cloned())
+                        .or(anchor);
+                    blocks.push(Block::Header {
+                        level: level.min(6) as u8,
+                        content: self.inlines(&title),
+                        anchor,
+                    });
+                    continue;
+                }
+            }
+
+            let (found, next) = self.block(lines, index);
+            blocks.extend(found);
+            index = next.max(index + 1);
+        }
+        blocks
+    }
+
+    /// Parse the bibliographic field list after the title, returning where the
+    /// document continues
+    fn docinfo(&mut self, lines: &[String], mut index: usize) -> usize {
+        while lines.get(index).is_some_and(|line| line.trim().is_empty()) {
+            index += 1;
+        }
+        while let Some((key, value)) = lines.get(index).and_then(|line| field(line)) {
+            let (_, body, next) = indented_block(lines, index, 0);
+            let mut value = value.to_string();
+            for line in body.iter().filter(|line| !line.trim().is_empty()) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            let text = inlines_to_text(&self.inlines(&value));
+            let (key, value) = match key.to_lowercase().as_str() {
+                "author" | "authors" => (
+                    "author",
+                    Value::Array(
+                        text.split([';', ','])
+                            .map(str::trim)
+                            .filter(|a| !a.is_empty())
+                            .map(|a| Value::String(a.to_string()))
+                            .collect(),
+                    ),
+                ),
+                "date" => ("date", Value::String(text)),
+                "copyright" => ("rights", Value::String(text)),
+                "organization" | "publisher" => ("publisher", Value::String(text)),
+                "abstract" | "description" | "summary" => ("description", Value::String(text)),
+                "keywords" | "tags" | "subject" => (
+                    "keywords",
+                    Value::Array(
+                        text.split(',')
+                            .map(|k| Value::String(k.trim().to_string()))
+                            .collect(),
+                    ),
+                ),
+                "language" | "lang" => ("lang", Value::String(text)),
+                "isbn" | "identifier" => ("identifier", Value::String(text)),
+                _ => {
+                    index = next;
+                    continue;
+                }
+            };
+            self.docinfo.insert(key.to_string(), value);
+            index = next;
+            while lines.get(index).is_some_and(|line| line.trim().is_empty()) {
+                index += 1;
+            }
+        }
+        index
+    }
+
+    /// Parse the block starting at `index`, returning it and the index after it
+    fn block(&mut self, lines: &[String], index: usize) -> (Vec<Block>, usize) {
+        static BULLET: OnceLock<Regex> = OnceLock::new();
+        static ENUMERATED: OnceLock<Regex> = OnceLock::new();
+        let bullet =
+            BULLET.get_or_init(|| Regex::new(r"^([-*+\u{2022}\u{2023}\u{2043}])( +|$)").unwrap());
+        let enumerated = ENUMERATED.get_or_init(|| {
+            Regex::new(r"^(\(?)(\d+|#|[a-zA-Z]|[ivxlcdm]+|[IVXLCDM]+)([.)])( +|$)").unwrap()
+        });
+
+        let line = &lines[index];
+
+        if line.starts_with(' ') {
+            let (quoted, next) = indented_lines(lines, index);
+            return (vec![self.block_quote(&quoted)], next);
+        }
+
+        if line.starts_with("..") && (line.len() == 2 || line.starts_with(".. ")) {
+            return self.explicit(lines, index);
+        }
+        if line.starts_with("__ ") {
+            // Anonymous targets were gathered before parsing
+            let (_, _, next) = indented_block(lines, index, 0);
+            return (vec![], next);
+        }
+
+        if adornment(line).is_some_and(|_| line.len() >= 4)
+            && lines
+                .get(index + 1)
+                .is_none_or(|next| next.trim().is_empty())
+        {
+            return (vec![Block::ThematicBreak], index + 1);
+        }
+
+        if line.starts_with("+-") && line.ends_with('+') {
+            let end = lines[index..]
+                .iter()
+                .position(|l| !l.starts_with(['+', '|']))
+                .map_or(lines.len(), |offset| index + offset);
+            if let Some(table) = self.grid_table(&lines[index..end]) {
+                return (vec![Block::Table(table)], end);
+            }
+        }
+        if is_simple_table_border(line) {
+            if let Some((table, next)) = self.simple_table(lines, index) {
+                return (vec![Block::Table(table)], next);
+            }
+        }
+
+        if let Some(captures) = bullet.captures(line) {
+            let marker = captures[1].to_string();
+            return self.list(
+                lines,
+                index,
+                |line| {
+                    bullet
+                        .captures(line)
+                        .filter(|c| c[1] == marker)
+                        .map(|c| c[0].len())
+                },
+                false,
+            );
+        }
+        if let Some(captures) = enumerated.captures(line) {
+            let (open, close) = (captures[1].to_string(), captures[3].to_string());
+            // A lone capital letter and full stop is more likely an initial
+            let single = lines
+                .get(index + 1)
+                .is_none_or(|l| l.trim().is_empty() || l.starts_with(' '));
+            if single || !captures[2].chars().all(char::is_alphabetic) {
+                return self.list(
+                    lines,
+                    index,
+                    |line| {
+                        enumerated
+                            .captures(line)
+                            .filter(|c| c[1] == open && c[3] == close)
+                            .map(|c| c[0].len())
+                    },
+                    true,
+                );
+            }
+        }
+
+        if field(line).is_some() {
+            return self.field_list(lines, index);
+        }
+
+        if line.starts_with("| ") || line == "|" {
+            let mut content = Vec::new();
+            let mut next = index;
+            while let Some(line) = lines
+                .get(next)
+                .filter(|l| l.starts_with('|') || l.starts_with(' '))
+            {
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(text) = line.strip_prefix('|') {
+                    if !content.is_empty() {
+                        content.push(Inline::Break);
+                    }
+                    content.extend(self.inlines(text.trim()));
+                } else {
+                    content.push(Inline::text(" "));
+                    content.extend(self.inlines(line.trim()));
+                }
+                next += 1;
+            }
+            return (vec![Block::Paragraph(content)], next);
+        }
+
+        if line.starts_with(">>> ") {
+            let end = lines[index..]
+                .iter()
+                .position(|l| l.trim().is_empty())
+                .map_or(lines.len(), |offset| index + offset);
+            return (
+                vec![Block::CodeBlock {
+                    lang: Some("pycon".to_string()),
+                    code: lines[index..end].join("\n"),
+                }],
+                end,
+            );
+        }
+
+        // A definition list item is a term with its definition indented below
+        if lines
+            .get(index + 1)
+            .is_some_and(|next| next.starts_with(' ') && !next.trim().is_empty())
+        {
+            return self.definition_list(lines, index);
+        }
+
+        self.paragraph(lines, index)
+    }
+
+    /// Parse a paragraph, and the literal block that follows it when it ends in `::`
+    fn paragraph(&mut self, lines: &[String], index: usize) -> (Vec<Block>, usize) {
+        let end = lines[index..]
+            .iter()
+            .position(|l| l.trim().is_empty() || l.starts_with(' '))
+            .map_or(lines.len(), |offset| index + offset);
+        let mut text = lines[index..end].join("\n");
+
+        let literal = text.ends_with("::");
+        if literal {
+            text.truncate(text.len() - 2);
+            if text.ends_with(char::is_whitespace) || text.is_empty() {
+                text = text.trim_end().to_string();
+            } else {
+                text.push(':');
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let inlines = self.inlines(&text);
+        if !inlines.is_empty() {
+            blocks.push(Block::Paragraph(inlines));
+        }
+        if !literal {
+            return (blocks, end);
+        }
+
+        let mut next = end;
+        while lines.get(next).is_some_and(|l| l.trim().is_empty()) {
+            next += 1;
+        }
+        if lines.get(next).is_some_and(|l| l.starts_with(' ')) {
+            let (code, after) = indented_lines(lines, next);
+            blocks.push(Block::CodeBlock {
+                lang: self.highlight.clone(),
+                code: code.join("\n").trim_end().to_string(),
+            });
+            next = after;
+        }
+        (blocks, next)
+    }
+
+    /// Parse an indented block quote, with an attribution after `--`
+    fn block_quote(&mut self, lines: &[String]) -> Block {
+        let attribution = lines
+            .iter()
+            .rposition(|line| line.starts_with("-- ") || line.starts_with("\u{2014} "))
+            .filter(|&at| at == 0 || lines[at - 1].trim().is_empty());
+        let (content_lines, credit) = match attribution {
+            Some(at) => (&lines[..at], Some(lines[at..].join(" "))),
+            None => (lines, None),
+        };
+        let mut content = self.blocks(content_lines, false);
+        if let Some(credit) = credit {
+            let credit = credit
+                .trim_start_matches("--")
+                .trim_start_matches('\u{2014}')
+                .trim();
+            let mut inlines = vec![Inline::text("\u{2014} ")];
+            inlines.extend(self.inlines(credit));
+            content.push(Block::Paragraph(inlines));
+        }
+        Block::Blockquote(content)
+    }
+
+    /// Parse a bullet or enumerated list, whose items are matched by `item`
+    /// (which gives the width of the item's marker)
+    fn list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        item: impl Fn(&str) -> Option<usize>,
+        ordered: bool,
+    ) -> (Vec<Block>, usize) {
+        let mut items = Vec::new();
+        while let Some(width) = lines.get(index).and_then(|line| item(line)) {
+            let (first, rest, next) = indented_block(lines, index, width);
+            let mut content = vec![first];
+            content.extend(rest);
+            items.push(self.blocks(&content, false));
+            index = next;
+            // Items may be separated by blank lines
+            let mut after = index;
+            while lines.get(after).is_some_and(|l| l.trim().is_empty()) {
+                after += 1;
+            }
+            if lines.get(after).and_then(|line| item(line)).is_none() {
+                break;
+            }
+            index = after;
+        }
+        (vec![Block::List { items, ordered }], index)
+    }
+
+    /// Parse a definition list: terms with indented definitions
+    fn definition_list(&mut self, lines: &[String], mut index: usize) -> (Vec<Block>, usize) {
+        let mut items = Vec::new();
+        loop {
+            let (term, definition, next) = indented_block(lines, index, 0);
+            // A classifier may follow the term after " : "
+            let term = term
+                .split(" : ")
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            items.push(DefinitionItem {
+                term: self.inlines(&term),
+                definitions: vec![self.blocks(&definition, false)],
+            });
+            index = next;
+
+            let mut after = index;
+            while lines.get(after).is_some_and(|l| l.trim().is_empty()) {
+                after += 1;
+            }
+            let continues = lines.get(after).is_some_and(|line| {
+                !line.starts_with(' ')
+                    && lines
+                        .get(after + 1)
+                        .is_some_and(|next| next.starts_with(' ') && !next.trim().is_empty())
+                    && !line.starts_with("..")
+            });
+            if !continues {
+                break;
+            }
+            index = after;
+        }
+        (vec![Block::DefinitionList(items)], index)
+    }
+
+    /// Parse a field list, which outside the document header is shown as a
+    /// definition list
+    fn field_list(&mut self, lines: &[String], mut index: usize) -> (Vec<Block>, usize) {
+        let mut items = Vec::new();
+        while let Some((key, value)) = lines.get(index).and_then(|line| field(line)) {
+            let (_, body, next) = indented_block(lines, index, 0);
+            let mut content = vec![value.to_string()];
+            content.extend(body);
+            items.push(DefinitionItem {
+                term: self.inlines(key),
+                definitions: vec![self.blocks(&dedent(&content), false)],
+            });
+            index = next;
+            while lines.get(index).is_some_and(|l| l.trim().is_empty()) {
+                index += 1;
+            }
+        }
+        (vec![Block::DefinitionList(items)], index)
+    }
+
+    /// Parse an explicit markup block: a directive, footnote, target,
+    /// substitution definition or comment
+    fn explicit(&mut self, lines: &[String], index: usize) -> (Vec<Block>, usize) {
+        static FOOTNOTE: OnceLock<Regex> = OnceLock::new();
+        static DIRECTIVE: OnceLock<Regex> = OnceLock::new();
+        let footnote = FOOTNOTE.get_or_init(|| Regex::new(r"^\.\. \[([^\]]+)\](?:\s+|$)").unwrap());
+        let directive = DIRECTIVE
+            .get_or_init(|| Regex::new(r"^\.\. (?:\|[^|]+\| )?([\w:.+-]+)::(?:\s+|$)").unwrap());
+
+        let line = &lines[index];
+        if let Some(captures) = footnote.captures(line) {
+            let label = captures[1].to_string();
+            let (first, rest, next) = indented_block(lines, index, captures[0].len());
+            let mut body = vec![first];
+            body.extend(rest);
+            let content = self.blocks(&dedent(&body), false);
+            let id = self.footnote_id(&label, false);
+            self.footnotes
+                .push((id.clone(), Block::Footnote { id, content }));
+            return (vec![], next);
+        }
+
+        if let Some(label) = line.strip_prefix(".. _") {
+            let (_, _, next) = indented_block(lines, index, 0);
+            if label.trim_end().ends_with(':') {
+                // An internal target labels the section that follows
+                self.pending_labels.push(
+                    label
+                        .trim_end()
+                        .trim_end_matches(':')
+                        .trim_matches('`')
+                        .to_string(),
+                );
+            }
+            return (vec![], next);
+        }
+
+        let Some(captures) = directive.captures(line) else {
+            // A comment
+            let (_, _, next) = indented_block(lines, index, 0);
+            return (vec![], next);
+        };
+        let name = captures[1].to_lowercase();
+        let (argument, body, next) = indented_block(lines, index, captures[0].len());
+        if line.starts_with(".. |") {
+            // Substitution definitions were gathered before parsing
+            return (vec![], next);
+        }
+
+        // Options come first in the body, then a blank line and the content
+        let mut options = HashMap::new();
+        let mut argument = argument.trim().to_string();
+        let mut body_start = 0;
+        for line in &body {
+            if line.trim().is_empty() {
+                break;
+            }
+            match field(line) {
+                Some((key, value)) => {
+                    options.insert(key.to_string(), value.to_string());
+                }
+                None if options.is_empty() && !argument.is_empty() => {
+                    argument.push(' ');
+                    argument.push_str(line.trim());
+                }
+                None => break,
+            }
+            body_start += 1;
+        }
+        let content: Vec<String> = body[body_start..]
+            .iter()
+            .skip_while(|line| line.trim().is_empty())
+            .cloned()
+            .collect();
+
+        (self.directive(&name, argument, &options, &content), next)
+    }
+
+    /// Convert a directive
+    fn directive(
+        &mut self,
+        name: &str,
+        argument: String,
+        options: &HashMap<String, String>,
+        content: &[String],
+    ) -> Vec<Block> {
+        let admonition = match name {
+            "note" | "seealso" | "versionadded" | "versionchanged" => Some(AdmonitionKind::Note),
+            "tip" | "hint" => Some(AdmonitionKind::Tip),
+            "important" | "attention" => Some(AdmonitionKind::Important),
+            "warning" | "deprecated" => Some(AdmonitionKind::Warning),
+            "caution" | "danger" | "error" => Some(AdmonitionKind::Caution),
+            "admonition" => Some(AdmonitionKind::Note),
+            _ => None,
+        };
+        if let Some(kind) = admonition {
+            let mut blocks = Vec::new();
+            let lead = match name {
+                "admonition" => None,
+                "seealso" => Some("See also".to_string()),
+                "versionadded" => Some(format!("New in version {}", argument)),
+                "versionchanged" => Some(format!("Changed in version {}", argument)),
+                "deprecated" => Some(format!("Deprecated since version {}", argument)),
+                _ => None,
+            };
+            if name == "admonition" && !argument.is_empty() {
+                blocks.push(Block::Paragraph(vec![Inline::Bold(
+                    self.inlines(&argument),
+                )]));
+            } else if let Some(lead) = lead {
+                blocks.push(Block::Paragraph(vec![Inline::Bold(vec![Inline::Text(
+                    lead,
+                )])]));
+            } else if !argument.is_empty() {
+                blocks.extend(self.paragraph(&[argument], 0).0);
+            }
+            blocks.extend(self.blocks(content, false));
+            return vec![Block::Admonition {
+                kind,
+                content: blocks,
+            }];
+        }
+
+        match name {
+            "code" | "code-block" | "sourcecode" => {
+                let lang = Some(argument)
+                    .filter(|lang| !lang.is_empty())
+                    .or_else(|| self.highlight.clone());
+                let mut blocks = self.caption(options);
+                blocks.push(Block::CodeBlock {
+                    lang,
+                    code: content.join("\n").trim_end().to_string(),
+                });
+                blocks
+            }
+            "parsed-literal" | "math" => vec![Block::CodeBlock {
+                lang: (name == "math").then(|| "math".to_string()),
+                code: content.join("\n").trim_end().to_string(),
+            }],
+            "highlight" => {
+                self.highlight = Some(argument).filter(|lang| !lang.is_empty());
+                vec![]
+            }
+            "image" | "figure" => {
+                if argument.is_empty() {
+                    return vec![];
+                }
+                let alt = options
+                    .get("alt")
+                    .cloned()
+                    .unwrap_or_else(|| default_alt(&argument));
+                let mut blocks = Vec::new();
+                let mut caption = None;
+                if name == "figure" {
+                    // The first paragraph is the caption, and the rest the legend
+                    let end = content
+                        .iter()
+                        .position(|l| l.trim().is_empty())
+                        .unwrap_or(content.len());
+                    if end > 0 {
+                        caption = Some(inlines_to_text(&self.inlines(&content[..end].join("\n"))));
+                    }
+                    blocks.extend(self.blocks(&content[end..], false));
+                }
+                blocks.insert(
+                    0,
+                    Block::Image {
+                        resource_key: argument,
+                        caption,
+                        alt,
+                    },
+                );
+                blocks
+            }
+            "list-table" => {
+                let mut blocks = self.titled(&argument);
+                blocks.push(Block::Table(self.list_table(content, options)));
+                blocks
+            }
+            "csv-table" => {
+                let mut blocks = self.titled(&argument);
+                blocks.push(Block::Table(self.csv_table(content, options)));
+                blocks
+            }
+            "table" => {
+                let mut blocks = self.titled(&argument);
+                blocks.extend(self.blocks(content, false));
+                blocks
+            }
+            "topic" | "sidebar" => {
+                let mut blocks = self.titled(&argument);
+                blocks.extend(self.blocks(content, false));
+                vec![Block::Container {
+                    id: None,
+                    classes: vec![name.to_string()],
+                    content: blocks,
+                }]
+            }
+            "container" => vec![Block::Container {
+                id: options.get("name").map(|name| make_id(name)),
+                classes: argument.split_whitespace().map(str::to_string).collect(),
+                content: self.blocks(content, false),
+            }],
+            "rubric" => self.titled(&argument),
+            "epigraph" | "highlights" | "pull-quote" => vec![self.block_quote(content)],
+            "title" => {
+                self.title = Some(argument);
+                vec![]
+            }
+            "meta" => {
+                for (key, value) in options {
+                    match key.as_str() {
+                        "description" => {
+                            self.docinfo
+                                .insert("description".to_string(), Value::String(value.clone()));
+                        }
+                        "keywords" => {
+                            let keywords = value
+                                .split(',')
+                                .map(|k| Value::String(k.trim().to_string()))
+                                .collect();
+                            self.docinfo
+                                .insert("keywords".to_string(), Value::Array(keywords));
+                        }
+                        _ => {}
+                    }
+                }
+                vec![]
+            }
+            "contents" | "sectnum" | "section-numbering" | "toctree" | "index" | "raw"
+            | "include" | "header" | "footer" | "target-notes" | "role" | "default-role"
+            | "tabularcolumns" | "todo" | "literalinclude" | "class" | "unicode" | "replace"
+            | "date" => vec![],
+            // Other directives, such as `only` and `compound`, show their content
+            _ => self.blocks(content, false),
+        }
+    }
+
+    /// A bold paragraph for a directive's title, if it has one
+    fn titled(&mut self, title: &str) -> Vec<Block> {
+        if title.is_empty() {
+            return vec![];
+        }
+        vec![Block::Paragraph(vec![Inline::Bold(self.inlines(title))])]
+    }
+
+    /// A bold paragraph for a code block's `:caption:` option
+    fn caption(&mut self, options: &HashMap<String, String>) -> Vec<Block> {
+        match options.get("caption") {
+            Some(caption) => self.titled(&caption.clone()),
+            None => vec![],
+        }
+    }
+
+    /// Convert a `list-table`: a bullet list of rows, each a bullet list of cells
+    fn list_table(&mut self, content: &[String], options: &HashMap<String, String>) -> TableData {
+        let header_rows = options
+            .get("header-rows")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0usize);
+        let mut rows: Vec<Vec<TableCell>> = Vec::new();
+        for block in self.blocks(content, false) {
+            let Block::List { items, .. } = block else {
+                continue;
+            };
+            for item in items {
+                let cells = match item.into_iter().next() {
+                    Some(Block::List { items, .. }) => items,
+                    _ => continue,
+                };
+                rows.push(
+                    cells
+                        .iter()
+                        .map(|cell| TableCell::new(cell_inlines(cell)))
+                        .collect(),
+                );
+            }
+        }
+        let headers = if header_rows > 0 && !rows.is_empty() {
+            rows.remove(0)
+        } else {
+            Vec::new()
+        };
+        TableData { headers, rows }
+    }
+
+    /// Convert a `csv-table`, whose header may be given as an option
+    fn csv_table(&mut self, content: &[String], options: &HashMap<String, String>) -> TableData {
+        let delimiter = options
+            .get("delim")
+            .and_then(|d| match d.as_str() {
+                "tab" => Some('\t'),
+                "space" => Some(' '),
+                d => d.chars().next(),
+            })
+            .unwrap_or(',');
+        let mut rows: Vec<Vec<TableCell>> = content
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                split_csv(line, delimiter)
+                    .iter()
+                    .map(|value| TableCell::new(self.inlines(value)))
+                    .collect()
+            })
+            .collect();
+
+        let headers = match options.get("header") {
+            Some(header) => split_csv(header, ',')
+                .iter()
+                .map(|value| TableCell::new(self.inlines(value)))
+                .collect(),
+            None if options.get("header-rows").is_some_and(|n| n.trim() != "0")
+                && !rows.is_empty() =>
+            {
+                rows.remove(0)
+            }
+            None => Vec::new(),
+        };
+        TableData { headers, rows }
+    }
+
+    /// Parse a grid table, following each cell's borders to find its spans
+    fn grid_table(&mut self, lines: &[String]) -> Option<TableData> {
+        let grid: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+        let width = grid.iter().map(Vec::len).max()?;
+        let at = |row: usize, column: usize| grid[row].get(column).copied().unwrap_or(' ');
+        let bottom = grid.len() - 1;
+        let right = width - 1;
+        let header_border = grid
+            .iter()
+            .position(|row| row.first() == Some(&'+') && row.contains(&'='));
+
+        // Find each cell from its top-left corner, as docutils does
+        let mut cells = Vec::new();
+        let mut corners = VecDeque::from([(0, 0)]);
+        let mut done = HashSet::new();
+        while let Some((top, left)) = corners.pop_front() {
+            if top >= bottom || left >= right || !done.insert((top, left)) {
+                continue;
+            }
+            let Some((cell_bottom, cell_right)) = scan_cell(&at, top, left, bottom, right) else {
+                continue;
+            };
+            cells.push((top, left, cell_bottom, cell_right));
+            corners.push_back((top, cell_right));
+            corners.push_back((cell_bottom, left));
+        }
+        if cells.is_empty() {
+            return None;
+        }
+
+        let mut row_tops: Vec<usize> = cells.iter().map(|c| c.0).collect();
+        row_tops.sort_unstable();
+        row_tops.dedup();
+        let mut column_lefts: Vec<usize> = cells.iter().map(|c| c.1).collect();
+        column_lefts.sort_unstable();
+        column_lefts.dedup();
+        cells.sort_unstable();
+
+        let mut headers = Vec::new();
+        let mut rows: Vec<Vec<TableCell>> = vec![Vec::new(); row_tops.len()];
+        for (top, left, cell_bottom, cell_right) in cells {
+            let text: Vec<String> = (top + 1..cell_bottom)
+                .map(|row| {
+                    (left + 1..cell_right)
+                        .map(|column| at(row, column))
+                        .collect::<String>()
+                        .trim()
+                        .to_string()
+                })
+                .collect();
+            let mut cell = TableCell::new(self.cell_text(&text));
+            cell.colspan = column_lefts
+                .iter()
+                .filter(|&&c| c >= left && c < cell_right)
+                .count()
+                .max(1) as u32;
+            cell.rowspan = row_tops
+                .iter()
+                .filter(|&&r| r >= top && r < cell_bottom)
+                .count()
+                .max(1) as u32;
+            let row = row_tops.iter().position(|&r| r == top).unwrap_or(0);
+            rows[row].push(cell);
+        }
+
+        if let Some(border) = header_border {
+            let header_rows = row_tops.iter().filter(|&&r| r < border).count();
+            let mut header: Vec<Vec<TableCell>> = rows.drain(..header_rows).collect();
+            headers = header.drain(..).next().unwrap_or_default();
+        }
+        rows.retain(|row| !row.is_empty());
+        Some(TableData { headers, rows })
+    }
+
+    /// Parse a simple table, bordered by lines of `=` columns
+    fn simple_table(&mut self, lines: &[String], index: usize) -> Option<(TableData, usize)> {
+        let border = &lines[index];
+        // Column ranges from the runs of `=` in the top border
+        let mut columns = Vec::new();
+        let mut start = None;
+        for (position, c) in border.char_indices() {
+            match (c, start) {
+                ('=', None) => start = Some(position),
+                (' ', Some(begin)) => {
+                    columns.push(begin);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(begin) = start {
+            columns.push(begin);
+        }
+        if columns.len() < 2 {
+            return None;
+        }
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut borders = 1;
+        let mut header_rows = None;
+        let mut next = index + 1;
+        while let Some(line) = lines.get(next) {
+            next += 1;
+            if is_simple_table_border(line) {
+                borders += 1;
+                let at_end = lines.get(next).is_none_or(|l| l.trim().is_empty());
+                if at_end {
+                    break;
+                }
+                header_rows = Some(rows.len());
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.trim_start().starts_with('-') && line.chars().all(|c| c == '-' || c == ' ') {
+                continue;
+            }
+            let cells: Vec<String> = columns
+                .iter()
+                .enumerate()
+                .map(|(i, &begin)| {
+                    let end = columns.get(i + 1).copied().unwrap_or(line.len());
+                    line.get(begin.min(line.len())..end.min(line.len()))
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string()
+                })
+                .collect();
+            // A line with an empty first column continues the row above
+            match rows.last_mut() {
+                Some(row) if cells[0].is_empty() => {
+                    for (cell, more) in row.iter_mut().zip(cells) {
+                        if !more.is_empty() {
+                            cell.push(' ');
+                            cell.push_str(&more);
+                        }
+                    }
+                }
+                _ => rows.push(cells),
+            }
+        }
+        if borders < 2 {
+            return None;
+        }
+
+        let mut rows: Vec<Vec<TableCell>> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|text| TableCell::new(self.inlines(text)))
+                    .collect()
+            })
+            .collect();
+        let headers = match header_rows {
+            Some(count) if count > 0 => {
+                let mut header: Vec<Vec<TableCell>> = rows.drain(..count).collect();
+                header.remove(0)
+            }
+            _ => Vec::new(),
+        };
+        Some((TableData { headers, rows }, next))
+    }
+
+    /// Inline content of a table cell, with its paragraphs on separate lines
+    fn cell_text(&mut self, lines: &[String]) -> Vec<Inline> {
+        let mut content = Vec::new();
+        for paragraph in lines.split(|line| line.is_empty()) {
+            if paragraph.is_empty() {
+                continue;
+            }
+            if !content.is_empty() {
+                content.push(Inline::Break);
+            }
+            content.extend(self.inlines(&paragraph.join("\n")));
+        }
+        content
+    }
+
+    /// Id for a footnote label, for both references and definitions
+    ///
+    /// Auto-numbered footnotes (`[#]`) are matched in order, so references and
+    /// definitions keep separate counts.
+    fn footnote_id(&mut self, label: &str, reference: bool) -> String {
+        match label {
+            "#" | "*" => {
+                let count = if reference {
+                    self.auto_refs += 1;
+                    self.auto_refs
+                } else {
+                    self.auto_notes += 1;
+                    self.auto_notes
+                };
+                format!("footnote-auto-{}", count)
+            }
+            _ => match label.strip_prefix('#') {
+                Some(name) => make_id(name),
+                None if label.chars().all(|c| c.is_ascii_digit()) => format!("footnote-{}", label),
+                None => make_id(label),
+            },
+        }
+    }
+
+    /// Convert inline text, trimming the whitespace at its ends
+    fn inlines(&mut self, text: &str) -> Vec<Inline> {
+        let mut inlines = self.scan(text);
+        trim_inlines(&mut inlines);
+        inlines
+    }
+
+    /// Convert inline markup
+    fn scan(&mut self, text: &str) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut plain = String::new();
+        let mut index = 0;
+        while index < text.len() {
+            let rest = &text[index..];
+            let previous = text[..index].chars().next_back();
+
+            if can_start(previous) {
+                if let Some((found, length)) = self.markup(rest) {
+                    for inline in found {
+                        match inline {
+                            Inline::Text(text) => plain.push_str(&text),
+                            inline => {
+                                if !plain.is_empty() {
+                                    inlines.push(Inline::Text(std::mem::take(&mut plain)));
+                                }
+                                inlines.push(inline);
+                            }
+                        }
+                    }
+                    index += length;
+                    continue;
+                }
+            }
+
+            let c = rest.chars().next().unwrap_or_default();
+            match c {
+                '\\' => {
+                    // A backslash escapes the next character; an escaped space disappears
+                    match rest[1..].chars().next() {
+                        Some(next) => {
+                            if !next.is_whitespace() {
+                                plain.push(next);
+                            }
+                            index += 1 + next.len_utf8();
+                        }
+                        None => index += 1,
+                    }
+                    continue;
+                }
+                '\n' => plain.push(' '),
+                _ => plain.push(c),
+            }
+            index += c.len_utf8();
+        }
+        if !plain.is_empty() {
+            inlines.push(Inline::Text(plain));
+        }
+        inlines
+    }
+
+    /// Recognise inline markup at the start of `rest`, returning what it
+    /// converts to and its length
+    fn markup(&mut self, rest: &str) -> Option<(Vec<Inline>, usize)> {
+        static REFERENCE: OnceLock<Regex> = OnceLock::new();
+        static FOOTNOTE_REF: OnceLock<Regex> = OnceLock::new();
+        static ROLE: OnceLock<Regex> = OnceLock::new();
+        let reference = REFERENCE
+            .get_or_init(|| Regex::new(r"^([A-Za-z0-9][\w.+-]*?)(__?)(?:[^\w]|$)").unwrap());
+        let footnote_ref =
+            FOOTNOTE_REF.get_or_init(|| Regex::new(r"^\[(#?[\w-]*|\*|\d+)\]_").unwrap());
+        let role = ROLE.get_or_init(|| Regex::new(r"^:([\w:.+-]+):`").unwrap());
+
+        if let Some(content) = delimited(rest, "**") {
+            let children = self.scan(content);
+            return Some((vec![Inline::Bold(children)], content.len() + 4));
+        }
+        if let Some(content) = delimited(rest, "``") {
+            return Some((
+                vec![Inline::Code(content.replace('\n', " "))],
+                content.len() + 4,
+            ));
+        }
+        if let Some(content) = delimited(rest, "*") {
+            let children = self.scan(content);
+            return Some((vec![Inline::Italic(children)], content.len() + 2));
+        }
+        if let Some(captures) = role.captures(rest) {
+            let name = captures[1].to_string();
+            let head = captures[0].len() - 1;
+            let content = delimited(&rest[head..], "`")?;
+            return Some((self.role(&name, content), head + content.len() + 2));
+        }
+        if rest.starts_with('`') {
+            let content = delimited(rest, "`")?;
+            let after = &rest[content.len() + 2..];
+            if after.starts_with("__") {
+                return Some((self.reference(content, true), content.len() + 4));
+            }
+            if after.starts_with('_') {
+                return Some((self.reference(content, false), content.len() + 3));
+            }
+            if let Some(captures) = role.captures(after) {
+                let name = captures[1].trim_end_matches(':').to_string();
+                let length = content.len() + 2 + captures[0].len() - 1;
+                return Some((self.role(&name, content), length));
+            }
+            // The default role is a title reference, shown in italics
+            let children = self.scan(content);
+            return Some((vec![Inline::Italic(children)], content.len() + 2));
+        }
+        if let Some(captures) = footnote_ref.captures(rest) {
+            let label = &captures[1];
+            if label.is_empty() {
+                return None;
+            }
+            let id = self.footnote_id(label, true);
+            return Some((vec![Inline::FootnoteRef { id }], captures[0].len()));
+        }
+        if let Some(body) = rest.strip_prefix('|') {
+            let name = &body[..body.find('|')?];
+            let end = name.len() + 1;
+            if name.is_empty() || name.starts_with(' ') || name.ends_with(' ') {
+                return None;
+            }
+            let text = self
+                .substitutions
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| name.to_string());
+            let mut length = end + 1;
+            length += rest[length..]
+                .chars()
+                .take_while(|c| *c == '_')
+                .take(2)
+                .count();
+            let children = self.scan(&text);
+            return Some((children, length));
+        }
+        if let Some(url) = bare_url(rest, URL_SCHEMES, &['<', '>', '`']) {
+            return Some((
+                vec![Inline::Link {
+                    children: vec![Inline::text(url.trim_start_matches("mailto:"))],
+                    url: url.to_string(),
+                }],
+                url.len(),
+            ));
+        }
+        if let Some(captures) = reference.captures(rest) {
+            let name = captures.get(1)?.as_str();
+            let anonymous = captures[2].len() == 2;
+            let length = name.len() + captures[2].len();
+            return Some((self.reference(name, anonymous), length));
+        }
+        None
+    }
+
+    /// A hyperlink reference: `` `text <url>`_ ``, `` `name`_ `` or `name_`
+    fn reference(&mut self, content: &str, anonymous: bool) -> Vec<Inline> {
+        let (text, target) = split_embedded(content);
+        let url = match target {
+            Some(target) if target.ends_with('_') => self.resolve(target.trim_end_matches('_')),
+            Some(target) => target.replace(char::is_whitespace, ""),
+            None if anonymous => self
+                .anonymous
+                .pop_front()
+                .unwrap_or_else(|| format!("#{}", make_id(text))),
+            None => self.resolve(text),
+        };
+        let url = match url.strip_suffix('_') {
+            // A target that refers to another target by name
+            Some(name) if !url.contains("://") => self.resolve(name),
+            _ => url,
+        };
+        let children = if text.is_empty() {
+            vec![Inline::text(&url)]
+        } else {
+            self.scan(text)
+        };
+        vec![Inline::Link { children, url }]
+    }
+
+    /// URL of a named target or section
+    fn resolve(&self, name: &str) -> String {
+        let key = normalize_name(name);
+        if let Some(url) = self.targets.get(&key) {
+            return url.clone();
+        }
+        let anchor = self
+            .anchors
+            .get(&key)
+            .cloned()
+            .unwrap_or_else(|| make_id(name));
+        format!("#{}", anchor)
+    }
+
+    /// Convert interpreted text with a role, such as `` :code:`x` ``
+    fn role(&mut self, name: &str, content: &str) -> Vec<Inline> {
+        let name = name.rsplit(':').next().unwrap_or(name);
+        match name {
+            "emphasis" | "title" | "title-reference" | "t" | "dfn" | "term" | "cite" => {
+                vec![Inline::Italic(self.scan(content))]
+            }
+            "strong" | "guilabel" | "menuselection" => vec![Inline::Bold(self.scan(content))],
+            "sup" | "superscript" => vec![Inline::Superscript(self.scan(content))],
+            "sub" | "subscript" => vec![Inline::Subscript(self.scan(content))],
+            "ref" | "numref" | "doc" | "download" => {
+                let (text, target) = split_embedded(content);
+                let target = target.unwrap_or(text);
+                let url = match name {
+                    "doc" | "download" => target.to_string(),
+                    _ => self.resolve(target),
+                };
+                let text = if target == text && name != "doc" {
+                    url.strip_prefix('#')
+                        .and_then(|anchor| self.titles.get(anchor))
+                        .cloned()
+                        .unwrap_or_else(|| text.to_string())
+                } else {
+                    text.to_string()
+                };
+                vec![Inline::Link {
+                    children: vec![Inline::Text(text)],
+                    url,
+                }]
+            }
+            "abbr" => {
+                let text = content.split(" (").next().unwrap_or(content);
+                vec![Inline::text(text)]
+            }
+            "pep" => vec![Inline::Text(format!("PEP {}", content))],
+            "rfc" => vec![Inline::Text(format!("RFC {}", content))],
+            _ if CODE_ROLES.contains(&name) => {
+                // Sphinx cross-reference roles may give `text <target>`, or `~a.b` to show `b`
+                let (text, _) = split_embedded(content);
+                let text = match text.strip_prefix('~') {
+                    Some(path) => path.rsplit('.').next().unwrap_or(path),
+                    None => text,
+                };
+                vec![Inline::Code(text.replace('\n', " "))]
+            }
+            _ => vec![Inline::Text(content.replace('\n', " "))],
+        }
+    }
+}
+
+/// Text and target of `text <target>`, or the text alone
+fn split_embedded(content: &str) -> (&str, Option<&str>) {
+    let content = content.trim();
+    if content.ends_with('>') {
+        if let Some(open) = content.rfind('<') {
+            let text = content[..open].trim();
+            let target = &content[open + 1..content.len() - 1];
+            if open == 0 || content[..open].ends_with(char::is_whitespace) {
+                return (text, Some(target.trim()));
+            }
+        }
+    }
+    (content, None)
+}
+
+/// Whether inline markup may start after `previous`
+fn can_start(previous: Option<char>) -> bool {
+    previous.is_none_or(|c| c.is_whitespace() || "-:/'\"<([{\u{2018}\u{201C}".contains(c))
+}
+
+/// Content between a start and end string, following the inline markup rules
+fn delimited<'a>(rest: &'a str, marks: &str) -> Option<&'a str> {
+    let body = rest.strip_prefix(marks)?;
+    if body.starts_with(char::is_whitespace) || body.starts_with(marks) {
+        return None;
+    }
+    let mut offset = 0;
+    while let Some(found) = body[offset..].find(marks) {
+        let end = offset + found;
+        let before = body[..end].chars().next_back();
+        let after = body[end + marks.len()..].chars().next();
+        let escaped = before == Some('\\');
+        if end > 0
+            && before.is_some_and(|c| !c.is_whitespace())
+            && !escaped
+            && after.is_none_or(|c| {
+                c.is_whitespace() || "-.,:;!?\\/'\")]}>_\u{2019}\u{201D}".contains(c)
+            })
+        {
+            return Some(&body[..end]);
+        }
+        offset = end + marks.len().max(1);
+    }
+    None
+}
+
+/// Whether a line is the border of a simple table: runs of `=` separated by spaces
+fn is_simple_table_border(line: &str) -> bool {
+    line.starts_with('=') && line.contains(' ') && line.chars().all(|c| c == '=' || c == ' ')
+}
+
+/// Follow the borders of a grid table cell from its top-left corner, returning
+/// its bottom-right corner
+fn scan_cell(
+    at: &impl Fn(usize, usize) -> char,
+    top: usize,
+    left: usize,
+    bottom: usize,
+    right: usize,
+) -> Option<(usize, usize)> {
+    for column in left + 1..=right {
+        match at(top, column) {
+            '+' => {
+                // A corner on the top border: look down its right edge
+                for row in top + 1..=bottom {
+                    match at(row, column) {
+                        '+' => {
+                            let bottom_edge =
+                                (left..=column).all(|c| matches!(at(row, c), '+' | '-' | '='));
+                            let left_edge = (top..=row).all(|r| matches!(at(r, left), '+' | '|'));
+                            if bottom_edge && left_edge {
+                                return Some((row, column));
+                            }
+                        }
+                        '|' => {}
+                        _ => break,
+                    }
+                }
+            }
+            '-' | '=' => {}
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Inline content of a `list-table` cell
+fn cell_inlines(blocks: &[Block]) -> Vec<Inline> {
+    let mut content = Vec::new();
+    for block in blocks {
+        let inlines = match block {
+            Block::Paragraph(inlines) => inlines.clone(),
+            Block::CodeBlock { code, .. } => vec![Inline::Code(code.clone())],
+            _ => continue,
+        };
+        if !content.is_empty() {
+            content.push(Inline::Break);
+        }
+        content.extend(inlines);
+    }
+    content
+}
+
+/// Split a line of CSV, honouring double quotes
+fn split_csv(line: &str, delimiter: char) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => {
+                values.push(std::mem::take(&mut current).trim().to_string())
+            }
+            _ => current.push(c),
+        }
+    }
+    values.push(current.trim().to_string());
+    values
+}
+
+/// Text of a `unicode` substitution, whose codes are written `U+2014` or `0x2014`
+fn unicode_substitution(codes: &str) -> String {
+    codes
+        .split_whitespace()
+        .map(|code| {
+            let hex = code
+                .strip_prefix("U+")
+                .or_else(|| code.strip_prefix("u+"))
+                .or_else(|| code.strip_prefix("0x"))
+                .or_else(|| code.strip_prefix("\\x"));
+            match hex.and_then(|hex| u32::from_str_radix(hex, 16).ok()) {
+                Some(value) => char::from_u32(value).map(String::from).unwrap_or_default(),
+                None => code.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    fn decode(text: &str) -> Book {
+        RstDecoder::new()
+            .decode(&mut Cursor::new(text.as_bytes()))
+            .unwrap()
+    }
+
+    /// Blocks of every chapter, in order
+    fn blocks(text: &str) -> Vec<Block> {
+        decode(text)
+            .chapters
+            .into_iter()
+            .flat_map(|chapter| chapter.content)
+            .collect()
+    }
+
+    /// Inlines of the only paragraph in `text`
+    fn paragraph(text: &str) -> Vec<Inline> {
+        match &blocks(text)[..] {
+            [Block::Paragraph(inlines)] => inlines.clone(),
+            other => panic!("expected one paragraph, got {:?}", other),
+        }
+    }
+
+    /// The text and URL of every link in `inlines`
+    fn links(inlines: &[Inline]) -> Vec<(String, String)> {
+        inlines
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Link { children, url } => Some((inlines_to_text(children), url.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The only table in `text`
+    fn table(text: &str) -> TableData {
+        blocks(text)
+            .into_iter()
+            .find_map(|block| match block {
+                Block::Table(table) => Some(table),
+                _ => None,
+            })
+            .expect("expected a table")
+    }
+
+    /// Text of each cell in a row
+    fn cell_text(cells: &[TableCell]) -> Vec<String> {
+        cells
+            .iter()
+            .map(|cell| inlines_to_text(&cell.content))
+            .collect()
+    }
+
+    /// Decode `text` with its includes read from `files` in a temporary directory
+    fn decode_with_files(text: &str, files: &[(&str, &str)]) -> (Book, DecodeContext) {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            std::fs::write(dir.path().join(path), content).unwrap();
+        }
+        let context = DecodeContext::new().with_base_path(dir.path());
+        let book = RstDecoder::new()
+            .decode_with_context(&mut Cursor::new(text.as_bytes()), &context)
+            .unwrap();
+        (book, context)
+    }
+
+    #[test]
+    fn test_decode_title_and_docinfo() {
+        let book = decode(
+            "====================\nEngineering Handbook\n====================\n\n\
+             Second Edition\n--------------\n\n\
+             :Authors: Ada Lovelace; Charles Babbage\n:Date: 2021-06-01\n\
+             :Abstract: How we build\n   *good* things\n:Version: 2\n\nText.\n",
+        );
+        assert_eq!(book.metadata.title, "Engineering Handbook");
+        assert_eq!(
+            book.metadata.creator,
+            vec!["Ada Lovelace", "Charles Babbage"]
+        );
+        assert_eq!(
+            book.metadata.description.as_deref(),
+            Some("How we build good things")
+        );
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("2021-06-01".to_string())
+        );
+        // The subtitle and the docinfo are not content
+        assert_eq!(
+            book.chapters[0].content,
+            vec![Block::Paragraph(vec![Inline::text("Text.")])]
+        );
+    }
+
+    #[test]
+    fn test_section_levels_follow_adornment_order() {
+        let found = blocks(
+            "Intro.\n\nFirst\n=====\n\nSub\n---\n\n=======\nOverline\n=======\n\n\
+             Second\n======\n\nSub Again\n---------\n",
+        );
+        let headers: Vec<(u8, String)> = found
+            .iter()
+            .filter_map(|block| match block {
+                Block::Header { level, content, .. } => Some((*level, inlines_to_text(content))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                (1, "First".to_string()),
+                (2, "Sub".to_string()),
+                (3, "Overline".to_string()),
+                (1, "Second".to_string()),
+                (2, "Sub Again".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hyperlink_references() {
+        let text = paragraph(
+            "See Python_, `the docs`_, `inline <https://example.org/a b>`_, \
+             `anonymous`__, `chained`_, Deploying_ and `Getting  Started`_.\n\n\
+             .. _Python: https://python.org\n.. _the docs: https://docs.example.com\n\
+             __ https://anon.example.com\n.. _chained: Python_\n",
+        );
+        assert_eq!(
+            links(&text),
+            [
+                ("Python".to_string(), "https://python.org".to_string()),
+                (
+                    "the docs".to_string(),
+                    "https://docs.example.com".to_string()
+                ),
+                ("inline".to_string(), "https://example.org/ab".to_string()),
+                (
+                    "anonymous".to_string(),
+                    "https://anon.example.com".to_string()
+                ),
+                ("chained".to_string(), "https://python.org".to_string()),
+                ("Deploying".to_string(), "#deploying".to_string()),
+                (
+                    "Getting  Started".to_string(),
+                    "#getting-started".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cross_references_to_sections() {
+        let book = decode(
+            ".. _setup:\n\nGetting Started\n===============\n\n\
+             See :ref:`setup`, :ref:`the rollout <deploy>`, `Deploying`_ and :doc:`guide/index`.\n\n\
+             .. _deploy:\n\nDeploying\n=========\n\nText.\n",
+        );
+        assert_eq!(
+            book.chapters[0].content[0],
+            Block::Header {
+                level: 1,
+                content: vec![Inline::text("Getting Started")],
+                anchor: Some("setup".to_string()),
+            }
+        );
+        let Block::Paragraph(text) = &book.chapters[0].content[1] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            links(text),
+            [
+                ("Getting Started".to_string(), "#setup".to_string()),
+                ("the rollout".to_string(), "#deploy".to_string()),
+                ("Deploying".to_string(), "#deploy".to_string()),
+                ("guide/index".to_string(), "guide/index".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footnotes_and_citations() {
+        let book = decode(
+            "Auto [#]_ and [#]_, numbered [1]_, named [#note]_, cited [CIT2002]_.\n\n\
+             .. [#] First auto.\n.. [#] Second auto.\n.. [1] Numbered.\n\n   More.\n\
+             .. [#note] Named.\n.. [CIT2002] A citation.\n",
+        );
+        let content = &book.chapters[0].content;
+        let Block::Paragraph(text) = &content[0] else {
+            panic!("expected a paragraph, got {:?}", content[0]);
+        };
+        let refs: Vec<&str> = text
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::FootnoteRef { id } => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        let ids = [
+            "footnote-auto-1",
+            "footnote-auto-2",
+            "footnote-1",
+            "note",
+            "cit2002",
+        ];
+        assert_eq!(refs, ids);
+
+        let notes: HashMap<&str, &Vec<Block>> = content
+            .iter()
+            .filter_map(|block| match block {
+                Block::Footnote { id, content } => Some((id.as_str(), content)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(notes.len(), ids.len());
+        assert_eq!(
+            notes["footnote-auto-2"][0],
+            Block::Paragraph(vec![Inline::text("Second auto.")])
+        );
+        assert_eq!(notes["footnote-1"].len(), 2);
+    }
+
+    #[test]
+    fn test_substitutions() {
+        assert_eq!(
+            paragraph(
+                "The |product| |---| |site|_ and |unknown|.\n\n\
+                 .. |product| replace:: *Widget*\n.. |---| unicode:: U+2014\n\
+                 .. |site| replace:: home\n.. _site: https://example.com\n"
+            ),
+            vec![
+                Inline::text("The "),
+                Inline::Italic(vec![Inline::text("Widget")]),
+                Inline::text(" \u{2014} home and unknown."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grid_table_spans() {
+        let data = table(
+            "+-------+-------+\n\
+             | Tool  | Use   |\n\
+             +=======+=======+\n\
+             | Spans both    |\n\
+             +-------+-------+\n\
+             | Tall  | One   |\n\
+             |       +-------+\n\
+             |       | Two   |\n\
+             +-------+-------+\n",
+        );
+        assert_eq!(cell_text(&data.headers), ["Tool", "Use"]);
+        assert_eq!(data.rows.len(), 3);
+        assert_eq!(data.rows[0][0].colspan, 2);
+        assert_eq!(data.rows[1][0].rowspan, 2);
+        assert_eq!(cell_text(&data.rows[2]), ["Two"]);
+    }
+
+    #[test]
+    fn test_simple_table() {
+        let data = table(
+            "=====  ========\nTool   Use\n=====  ========\ncargo  Builds\n       everything\n\
+             rustc  Compiles\n=====  ========\n",
+        );
+        assert_eq!(cell_text(&data.headers), ["Tool", "Use"]);
+        assert_eq!(
+            data.rows
+                .iter()
+                .map(|row| cell_text(row))
+                .collect::<Vec<_>>(),
+            [["cargo", "Builds everything"], ["rustc", "Compiles"]]
+        );
+
+        // Without a header border every row is a body row
+        let data = table("===  ===\na    b\nc    d\n===  ===\n");
+        assert!(data.headers.is_empty());
+        assert_eq!(data.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_list_and_csv_tables() {
+        let found = blocks(
+            ".. list-table:: Tools\n   :header-rows: 1\n\n   * - Tool\n     - Use\n   * - cargo\n     - ``build``\n\n\
+             .. csv-table::\n   :header: \"Name\", \"Motto\"\n   :delim: ;\n\n   \"Lovelace; Ada\";\"Say \"\"hi\"\"\"\n",
+        );
+        assert_eq!(
+            found[0],
+            Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Tools")])])
+        );
+        let Block::Table(list) = &found[1] else {
+            panic!("expected a table, got {:?}", found[1]);
+        };
+        assert_eq!(cell_text(&list.headers), ["Tool", "Use"]);
+        assert_eq!(
+            list.rows[0][1].content,
+            vec![Inline::Code("build".to_string())]
+        );
+
+        let Block::Table(csv) = &found[2] else {
+            panic!("expected a table, got {:?}", found[2]);
+        };
+        assert_eq!(cell_text(&csv.headers), ["Name", "Motto"]);
+        assert_eq!(cell_text(&csv.rows[0]), ["Lovelace; Ada", "Say \"hi\""]);
+    }
+
+    #[test]
+    fn test_include_options_select_text() {
+        let (book, _) = decode_with_files(
+            ".. include:: part.rst\n   :start-after: .. start\n   :end-before: .. end\n\n\
+             .. literalinclude:: code.py\n   :start-line: 1\n   :end-line: 2\n",
+            &[
+                (
+                    "part.rst",
+                    "Skipped.\n\n.. start\n\nKept.\n\n.. end\n\nSkipped too.\n",
+                ),
+                ("code.py", "import os\nprint('hi')\nexit()\n"),
+            ],
+        );
+        assert_eq!(
+            book.chapters[0].content,
+            vec![
+                Block::Paragraph(vec![Inline::text("Kept.")]),
+                Block::CodeBlock {
+                    lang: None,
+                    code: "print('hi')".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_and_cyclic_includes_are_reported() {
+        let (book, context) = decode_with_files(
+            "Start.\n\n.. include:: missing.rst\n\n.. include:: loop.rst\n",
+            &[("loop.rst", "Loop.\n\n.. include:: loop.rst\n")],
+        );
+        let codes: Vec<String> = context
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect();
+        assert_eq!(codes, ["unresolved-include", "skipped-include"]);
+        let loops = book.chapters[0]
+            .content
+            .iter()
+            .filter(|block| **block == Block::Paragraph(vec![Inline::text("Loop.")]))
+            .count();
+        assert_eq!(loops, MAX_INCLUDE_DEPTH);
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        assert_eq!(
+            paragraph(
+                "**bold** *italic* ``code`` `title` :sup:`2` H\\ :sub:`2`\\ O \
+                 :py:func:`~os.path.join` :kbd:`Ctrl` :pep:`8` :abbr:`LIFO (last in, first out)`"
+            ),
+            vec![
+                Inline::Bold(vec![Inline::text("bold")]),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("italic")]),
+                Inline::text(" "),
+                Inline::Code("code".to_string()),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("title")]),
+                Inline::text(" "),
+                Inline::Superscript(vec![Inline::text("2")]),
+                Inline::text(" H"),
+                Inline::Subscript(vec![Inline::text("2")]),
+                Inline::text("O "),
+                Inline::Code("join".to_string()),
+                Inline::text(" "),
+                Inline::Code("Ctrl".to_string()),
+                Inline::text(" PEP 8 LIFO"),
+            ]
+        );
+        assert_eq!(
+            links(&paragraph(
+                "Mail mailto:ada@example.com or https://example.com/a."
+            )),
+            [
+                (
+                    "ada@example.com".to_string(),
+                    "mailto:ada@example.com".to_string()
+                ),
+                (
+                    "https://example.com/a".to_string(),
+                    "https://example.com/a".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_markup_needs_boundaries() {
+        for text in [
+            "2*3*4 and a**b**c",
+            "a * spaced * and ** spaced **",
+            "\\*escaped* and \\``literal``",
+            "snake_case_name and __init__",
+        ] {
+            let found = paragraph(text);
+            assert!(
+                found.iter().all(|inline| matches!(inline, Inline::Text(_))),
+                "{:?} gave {:?}",
+                text,
+                found
+            );
+        }
+    }
+
+    #[test]
+    fn test_literal_blocks() {
+        let found = blocks(
+            "Example::\n\n    fn main() {}\n\nPlain ::\n\n  text\n\n\
+             .. highlight:: python\n\n::\n\n  print()\n\n>>> 1 + 1\n2\n\n\
+             .. code-block:: rust\n   :caption: Entry point\n\n   fn main() {}\n",
+        );
+        assert_eq!(
+            found,
+            vec![
+                Block::Paragraph(vec![Inline::text("Example:")]),
+                Block::CodeBlock {
+                    lang: None,
+                    code: "fn main() {}".to_string(),
+                },
+                Block::Paragraph(vec![Inline::text("Plain")]),
+                Block::CodeBlock {
+                    lang: None,
+                    code: "text".to_string(),
+                },
+                Block::CodeBlock {
+                    lang: Some("python".to_string()),
+                    code: "print()".to_string(),
+                },
+                Block::CodeBlock {
+                    lang: Some("pycon".to_string()),
+                    code: ">>> 1 + 1\n2".to_string(),
+                },
+                Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Entry point")])]),
+                Block::CodeBlock {
+                    lang: Some("rust".to_string()),
+                    code: "fn main() {}".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lists_and_quotes() {
+        let found = blocks(
+            "- One\n\n  - Nested\n- Two\n\n#. First\n#. Second\n\n\
+             term : classifier\n   Definition.\n\n:Field: Value\n\n\
+             | Line one\n| Line two\n\n   Quoted text.\n\n   -- Ada Lovelace\n",
+        );
+        let Block::List {
+            items,
+            ordered: false,
+        } = &found[0]
+        else {
+            panic!("expected a list, got {:?}", found[0]);
+        };
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0][1], Block::List { .. }));
+        assert!(matches!(&found[1], Block::List { items, ordered: true } if items.len() == 2));
+        let Block::DefinitionList(terms) = &found[2] else {
+            panic!("expected a definition list, got {:?}", found[2]);
+        };
+        assert_eq!(terms[0].term, vec![Inline::text("term")]);
+        assert!(
+            matches!(&found[3], Block::DefinitionList(fields) if fields[0].term == vec![Inline::text("Field")])
+        );
+        assert_eq!(
+            found[4],
+            Block::Paragraph(vec![
+                Inline::text("Line one"),
+                Inline::Break,
+                Inline::text("Line two"),
+            ])
+        );
+        assert_eq!(
+            found[5],
+            Block::Blockquote(vec![
+                Block::Paragraph(vec![Inline::text("Quoted text.")]),
+                Block::Paragraph(vec![
+                    Inline::text("\u{2014} "),
+                    Inline::text("Ada Lovelace")
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let found = blocks(
+            ".. note:: Read this *first*.\n\n\
+             .. versionadded:: 2.0\n\n   Added things.\n\n\
+             .. figure:: img/big_diagram.png\n\n   Overview\n\n   Legend text.\n\n\
+             .. image:: logo.png\n   :alt: Logo\n\n\
+             .. topic:: Summary\n\n   In short.\n\n\
+             .. toctree::\n   :maxdepth: 2\n\n   intro\n\n.. This is a comment\n   over lines.\n",
+        );
+        assert_eq!(
+            found[0],
+            Block::Admonition {
+                kind: AdmonitionKind::Note,
+                content: vec![Block::Paragraph(vec![
+                    Inline::text("Read this "),
+                    Inline::Italic(vec![Inline::text("first")]),
+                    Inline::text("."),
+                ])],
+            }
+        );
+        assert_eq!(
+            found[1],
+            Block::Admonition {
+                kind: AdmonitionKind::Note,
+                content: vec![
+                    Block::Paragraph(vec![Inline::Bold(vec![Inline::text("New in version 2.0")])]),
+                    Block::Paragraph(vec![Inline::text("Added things.")]),
+                ],
+            }
+        );
+        assert_eq!(
+            found[2],
+            Block::Image {
+                resource_key: "img/big_diagram.png".to_string(),
+                caption: Some("Overview".to_string()),
+                alt: "big diagram".to_string(),
+            }
+        );
+        assert_eq!(
+            found[3],
+            Block::Paragraph(vec![Inline::text("Legend text.")])
+        );
+        assert!(matches!(&found[4], Block::Image { alt, .. } if alt == "Logo"));
+        assert!(matches!(&found[5], Block::Container { classes, .. } if classes == &["topic"]));
+        assert_eq!(found.len(), 6);
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        // Unclosed markup stays text
+        assert_eq!(
+            inlines_to_text(&paragraph(
+                "Open **bold and ``code and `ref and |sub and [#"
+            )),
+            "Open **bold and ``code and `ref and |sub and [#"
+        );
+
+        // Directives without their arguments or content, and references to
+        // missing targets, are harmless
+        let found = blocks(
+            ".. image::\n\n.. figure::\n\n.. list-table::\n\n.. csv-table::\n\n\
+             .. code-block::\n\nSee missing_ and [9]_.\n",
+        );
+        assert_eq!(
+            found,
+            vec![
+                Block::Table(TableData {
+                    headers: vec![],
+                    rows: vec![],
+                }),
+                Block::Table(TableData {
+                    headers: vec![],
+                    rows: vec![],
+                }),
+                Block::CodeBlock {
+                    lang: None,
+                    code: String::new(),
+                },
+                Block::Paragraph(vec![
+                    Inline::text("See "),
+                    Inline::Link {
+                        children: vec![Inline::text("missing")],
+                        url: "#missing".to_string(),
+                    },
+                    Inline::text(" and "),
+                    Inline::FootnoteRef {
+                        id: "footnote-9".to_string(),
+                    },
+                    Inline::text("."),
+                ]),
+            ]
+        );
+
+        // A broken grid table, a short underline and invalid bytes still decode
+        let book = RstDecoder::new()
+            .decode(&mut Cursor::new(
+                b"+---+\n| a\n+--\nTitle\n=\n\xff\n".as_slice(),
+            ))
+            .unwrap();
+        assert!(!book.chapters.is_empty());
+    }
+
+    #[test]
+    fn test_single_use_style_after_content_is_a_section() {
+        let book = decode("=====\nGuide\n=====\n\nIntro.\n\nAppendix\n~~~~~~~~\n\nEnd.\n");
+        assert_eq!(book.metadata.title, "Guide");
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles[1..], ["Appendix"]);
+    }
+
+    #[test]
+    fn test_includes_resolved_relative_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("chapters")).unwrap();
+        std::fs::write(
+            dir.path().join("chapters/intro.rst"),
+            "Introduction\n============\n\n.. literalinclude:: snippet.py\n   :language: python\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("chapters/snippet.py"), "print('hi')\n").unwrap();
+
+        let context = DecodeContext::new().with_base_path(dir.path());
+        let book = RstDecoder::new()
+            .decode_with_context(
+                &mut Cursor::new("Intro text.\n\n.. include:: chapters/intro.rst\n"),
+                &context,
+            )
+            .unwrap();
+        assert_eq!(book.chapters[1].title, "Introduction");
+        assert_eq!(
+            book.chapters[1].content[1],
+            Block::CodeBlock {
+                lang: Some("python".to_string()),
+                code: "print('hi')".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,1023 @@
+//! RTF decoder implementation
+//!
+//! An RTF document is plain text made of groups (`{...}`), control words
+//! (`\b`, `\par`, `\ansicpg1251`) and the text between them. Each group starts
+//! with the formatting of its parent; some groups are destinations whose text is
+//! not part of the body, such as the font table, the `\info` group holding the
+//! document properties, and `\pict` pictures given as hex data. Text is encoded
+//! in the document's ANSI code page, with `\uN` escapes for other characters.
+
+use super::chapters::{ChapterSplitter, SplitOptions, SplitStrategy};
+use super::front_matter::parse_date;
+use super::xml::trim_inlines;
+use crate::error::ParseError;
+use crate::types::{Block, Book, Inline, Metadata, Resource, ResourceStore};
+use encoding_rs::{Encoding, MACINTOSH, WINDOWS_1252};
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Destinations whose content is never part of the text
+const SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "colortbl",
+    "listtable",
+    "listoverridetable",
+    "revtbl",
+    "rsidtbl",
+    "filetbl",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "footnote",
+    "annotation",
+    "objdata",
+    "nonshppict",
+    "fldinst",
+    "revtim",
+    "printim",
+    "buptim",
+    "operator",
+    "comment",
+];
+
+/// `\info` destinations and the metadata they hold
+const INFO_FIELDS: &[&str] = &[
+    "title", "author", "subject", "keywords", "doccomm", "company",
+];
+
+/// Decoder for Rich Text Format documents
+pub struct RtfDecoder {
+    splitter: ChapterSplitter,
+}
+
+impl RtfDecoder {
+    pub fn new() -> Self {
+        Self {
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(1)),
+        }
+    }
+
+    /// Choose where chapters begin (by default, at outline level 1 paragraphs)
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+}
+
+impl Default for RtfDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for RtfDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read RTF: {}", e)))?;
+
+        if !data.starts_with(b"{\\rtf") {
+            return Err(ParseError::UnsupportedFormat(
+                "Not an RTF document".to_string(),
+            ));
+        }
+
+        let mut converter = Converter::default();
+        for token in Tokenizer::new(&data) {
+            converter.token(token);
+        }
+        // A truncated document may end in text that no token has flushed
+        converter.flush_bytes();
+        converter.end_paragraph();
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        converter.apply_info(&mut metadata);
+
+        let mut book = Book::with_metadata(metadata);
+        book.chapters = self
+            .splitter
+            .split_with_page_breaks(converter.blocks, &converter.page_breaks);
+        book.resources = converter.resources;
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["rtf"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/rtf", "text/rtf"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// A piece of RTF syntax
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    GroupStart,
+    GroupEnd,
+    /// A control word and its numeric parameter, such as `\fs24`
+    Word(&'a str, Option<i32>),
+    /// A control symbol, such as `\~` or `\*`
+    Symbol(u8),
+    /// A byte given as `\'hh`
+    Hex(u8),
+    /// Literal text
+    Text(&'a [u8]),
+    /// Raw data following `\binN`
+    Binary(&'a [u8]),
+}
+
+/// Splits RTF data into tokens
+struct Tokenizer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Read a control word, symbol or hex escape after a backslash
+    fn control(&mut self) -> Option<Token<'a>> {
+        let first = *self.data.get(self.pos)?;
+        if !first.is_ascii_alphabetic() {
+            self.pos += 1;
+            if first == b'\'' {
+                let hex = self.data.get(self.pos..self.pos + 2)?;
+                self.pos += 2;
+                let hex = std::str::from_utf8(hex).ok()?;
+                return Some(
+                    u8::from_str_radix(hex, 16)
+                        .map(Token::Hex)
+                        .unwrap_or(Token::Text(b"")),
+                );
+            }
+            return Some(Token::Symbol(first));
+        }
+
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(u8::is_ascii_alphabetic) {
+            self.pos += 1;
+        }
+        let name = std::str::from_utf8(&self.data[start..self.pos]).unwrap_or_default();
+
+        let digits_start = self.pos;
+        if self.data.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        while self.data.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        let param = std::str::from_utf8(&self.data[digits_start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok());
+
+        // A single space ends a control word and belongs to it
+        if self.data.get(self.pos) == Some(&b' ') {
+            self.pos += 1;
+        }
+
+        if name == "bin" {
+            let len = param.unwrap_or(0).max(0) as usize;
+            let end = (self.pos + len).min(self.data.len());
+            let data = &self.data[self.pos..end];
+            self.pos = end;
+            return Some(Token::Binary(data));
+        }
+        Some(Token::Word(name, param))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let byte = *self.data.get(self.pos)?;
+            match byte {
+                b'{' => {
+                    self.pos += 1;
+                    return Some(Token::GroupStart);
+                }
+                b'}' => {
+                    self.pos += 1;
+                    return Some(Token::GroupEnd);
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    return self.control();
+                }
+                // Line endings in the source carry no meaning
+                b'\r' | b'\n' => self.pos += 1,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .data
+                        .get(self.pos)
+                        .is_some_and(|b| !matches!(b, b'{' | b'}' | b'\\' | b'\r' | b'\n'))
+                    {
+                        self.pos += 1;
+                    }
+                    return Some(Token::Text(&self.data[start..self.pos]));
+                }
+            }
+        }
+    }
+}
+
+/// Where the text of a group goes
+#[derive(Clone, Copy, PartialEq)]
+enum Destination {
+    Body,
+    Skip,
+    /// The `\info` group, whose own text is ignored
+    Info,
+    /// A document property within `\info`
+    Property(&'static str),
+    /// The creation time within `\info`, given by `\yr`, `\mo` and `\dy`
+    Created,
+    StyleSheet,
+    /// One style definition within the style sheet
+    Style,
+    Picture,
+}
+
+/// Character formatting
+#[derive(Clone, Copy, PartialEq, Default)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+    superscript: bool,
+    subscript: bool,
+}
+
+impl Format {
+    /// Wrap text in this formatting
+    fn apply(self, content: Vec<Inline>) -> Vec<Inline> {
+        let mut content = content;
+        if self.subscript {
+            content = vec![Inline::Subscript(content)];
+        }
+        if self.superscript {
+            content = vec![Inline::Superscript(content)];
+        }
+        if self.strikethrough {
+            content = vec![Inline::Strikethrough(content)];
+        }
+        if self.italic {
+            content = vec![Inline::Italic(content)];
+        }
+        if self.bold {
+            content = vec![Inline::Bold(content)];
+        }
+        content
+    }
+}
+
+/// State that a group inherits from its parent and restores on exit
+#[derive(Clone, Copy)]
+struct GroupState {
+    destination: Destination,
+    format: Format,
+    /// Number of fallback characters following each `\uN`
+    unicode_skip: usize,
+}
+
+impl Default for GroupState {
+    fn default() -> Self {
+        Self {
+            destination: Destination::Body,
+            format: Format::default(),
+            unicode_skip: 1,
+        }
+    }
+}
+
+/// A style sheet entry
+#[derive(Default)]
+struct Style {
+    name: String,
+    outline_level: Option<u8>,
+}
+
+/// Picture data being collected from a `\pict` group
+#[derive(Default)]
+struct Picture {
+    mime: Option<&'static str>,
+    hex: Vec<u8>,
+    binary: Vec<u8>,
+}
+
+/// Converts a token stream into blocks
+struct Converter {
+    encoding: &'static Encoding,
+    state: GroupState,
+    stack: Vec<GroupState>,
+    /// The last token was `\*`, marking the next destination as ignorable
+    ignorable: bool,
+    /// Text bytes awaiting decoding, kept together for multi-byte code pages
+    bytes: Vec<u8>,
+    /// Fallback characters still to skip after a `\uN`
+    skip: usize,
+
+    /// Runs of the current paragraph
+    runs: Vec<(Format, Vec<Inline>)>,
+    /// Images in the current paragraph
+    images: Vec<Block>,
+    /// Style and outline level of the current paragraph
+    style: i32,
+    outline_level: Option<u8>,
+    /// A page break precedes the next block
+    page_break: bool,
+
+    blocks: Vec<Block>,
+    page_breaks: Vec<usize>,
+    resources: ResourceStore,
+    styles: HashMap<i32, Style>,
+    /// Number and definition of the style being read
+    current_style: (i32, Style),
+    picture: Picture,
+    info: HashMap<&'static str, String>,
+    created: [Option<i32>; 3],
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            encoding: WINDOWS_1252,
+            state: GroupState::default(),
+            stack: Vec::new(),
+            ignorable: false,
+            bytes: Vec::new(),
+            skip: 0,
+            runs: Vec::new(),
+            images: Vec::new(),
+            style: 0,
+            outline_level: None,
+            page_break: false,
+            blocks: Vec::new(),
+            page_breaks: Vec::new(),
+            resources: ResourceStore::new(),
+            styles: HashMap::new(),
+            current_style: (0, Style::default()),
+            picture: Picture::default(),
+            info: HashMap::new(),
+            created: [None; 3],
+        }
+    }
+}
+
+impl Converter {
+    fn token(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => {
+                let skipped = self.skip.min(text.len());
+                self.skip -= skipped;
+                self.bytes.extend_from_slice(&text[skipped..]);
+                return;
+            }
+            Token::Hex(byte) => {
+                if self.skip > 0 {
+                    self.skip -= 1;
+                } else {
+                    self.bytes.push(byte);
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        self.flush_bytes();
+        self.skip = 0;
+        match token {
+            Token::GroupStart => {
+                self.stack.push(self.state);
+                self.ignorable = false;
+                if self.state.destination == Destination::StyleSheet {
+                    self.state.destination = Destination::Style;
+                }
+            }
+            Token::GroupEnd => {
+                let ended = self.state.destination;
+                self.state = self.stack.pop().unwrap_or_default();
+                if ended != self.state.destination {
+                    self.end_destination(ended);
+                }
+            }
+            Token::Symbol(b'*') => self.ignorable = true,
+            Token::Symbol(symbol) => self.symbol(symbol),
+            Token::Word(word, param) => {
+                self.word(word, param);
+                self.ignorable = false;
+            }
+            Token::Binary(data) => {
+                if self.state.destination == Destination::Picture {
+                    self.picture.binary.extend_from_slice(data);
+                }
+            }
+            Token::Text(_) | Token::Hex(_) => unreachable!(),
+        }
+    }
+
+    /// Handle a control word
+    fn word(&mut self, word: &str, param: Option<i32>) {
+        let on = param != Some(0);
+        let destination = self.state.destination;
+        if destination == Destination::Skip {
+            return;
+        }
+
+        if let Some(entered) = self.entered_destination(word) {
+            if entered == Destination::Picture {
+                self.picture = Picture::default();
+            }
+            self.state.destination = entered;
+            return;
+        }
+
+        // Unicode escapes appear in any text, not only the body
+        match word {
+            "uc" => {
+                self.state.unicode_skip = param.unwrap_or(1).max(0) as usize;
+                return;
+            }
+            "u" => {
+                if let Some(code) = param {
+                    let code = if code < 0 { code + 65536 } else { code };
+                    if let Some(c) = char::from_u32(code as u32) {
+                        self.push_text(&c.to_string());
+                    }
+                }
+                self.skip = self.state.unicode_skip;
+                return;
+            }
+            _ => {}
+        }
+
+        match destination {
+            Destination::Body => {}
+            Destination::Created => {
+                let index = match word {
+                    "yr" => 0,
+                    "mo" => 1,
+                    "dy" => 2,
+                    _ => return,
+                };
+                self.created[index] = param;
+                return;
+            }
+            Destination::Style => {
+                match word {
+                    "s" => self.current_style.0 = param.unwrap_or(0),
+                    "outlinelevel" => {
+                        self.current_style.1.outline_level = param.map(|p| p.clamp(0, 8) as u8);
+                    }
+                    // Character and table styles share numbers with paragraph styles
+                    "cs" | "ds" | "ts" => self.current_style.0 = -1,
+                    _ => {}
+                }
+                return;
+            }
+            Destination::Picture => {
+                match word {
+                    "pngblip" => self.picture.mime = Some("image/png"),
+                    "jpegblip" => self.picture.mime = Some("image/jpeg"),
+                    _ => {}
+                }
+                return;
+            }
+            _ => return,
+        }
+
+        let format = &mut self.state.format;
+        match word {
+            // Document
+            "ansicpg" => {
+                if let Some(page) = param {
+                    self.encoding = code_page(page);
+                }
+            }
+            "mac" => self.encoding = MACINTOSH,
+
+            // Characters
+            "plain" => *format = Format::default(),
+            "b" => format.bold = on,
+            "i" => format.italic = on,
+            "strike" | "striked" => format.strikethrough = on,
+            "super" => {
+                format.superscript = on;
+                format.subscript = false;
+            }
+            "sub" => {
+                format.subscript = on;
+                format.superscript = false;
+            }
+            "nosupersub" => {
+                format.superscript = false;
+                format.subscript = false;
+            }
+
+            // Paragraphs
+            "par" | "sect" | "cell" => self.end_paragraph(),
+            "pard" => {
+                self.style = 0;
+                self.outline_level = None;
+            }
+            "s" => self.style = param.unwrap_or(0),
+            "outlinelevel" => self.outline_level = param.map(|p| p.clamp(0, 8) as u8),
+            "page" => {
+                self.end_paragraph();
+                self.page_break = true;
+            }
+            "pagebb" => self.page_break = true,
+            "line" => self.push_inline(Inline::Break),
+
+            // Special characters
+            "tab" => self.push_text("\t"),
+            "emdash" => self.push_text("\u{2014}"),
+            "endash" => self.push_text("\u{2013}"),
+            "emspace" | "enspace" | "qmspace" => self.push_text(" "),
+            "bullet" => self.push_text("\u{2022}"),
+            "lquote" => self.push_text("\u{2018}"),
+            "rquote" => self.push_text("\u{2019}"),
+            "ldblquote" => self.push_text("\u{201C}"),
+            "rdblquote" => self.push_text("\u{201D}"),
+            _ => {}
+        }
+    }
+
+    /// Destination that a control word starts, if any
+    fn entered_destination(&self, word: &str) -> Option<Destination> {
+        let destination = self.state.destination;
+        match word {
+            "info" => Some(Destination::Info),
+            "stylesheet" => Some(Destination::StyleSheet),
+            "pict" => Some(Destination::Picture),
+            "creatim" if destination == Destination::Info => Some(Destination::Created),
+            _ if destination == Destination::Info => INFO_FIELDS
+                .iter()
+                .find(|field| **field == word)
+                .map(|field| Destination::Property(field))
+                .or(Some(Destination::Skip)),
+            // Modern pictures are wrapped in an ignorable group, which is read
+            "shppict" => None,
+            _ if SKIPPED_DESTINATIONS.contains(&word) || self.ignorable => Some(Destination::Skip),
+            _ => None,
+        }
+    }
+
+    /// Handle a control symbol
+    fn symbol(&mut self, symbol: u8) {
+        match symbol {
+            b'\\' | b'{' | b'}' => self.bytes.push(symbol),
+            b'~' => self.push_text("\u{A0}"),
+            b'_' => self.push_text("\u{2011}"),
+            b'\n' | b'\r' if self.state.destination == Destination::Body => self.end_paragraph(),
+            _ => {}
+        }
+    }
+
+    /// Decode pending text bytes and send them to the current destination
+    fn flush_bytes(&mut self) {
+        if self.bytes.is_empty() {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.bytes);
+        match self.state.destination {
+            Destination::Picture => self
+                .picture
+                .hex
+                .extend(bytes.iter().filter(|b| b.is_ascii_hexdigit())),
+            _ => {
+                let (text, _, _) = self.encoding.decode(&bytes);
+                self.push_text(&text);
+            }
+        }
+    }
+
+    /// Add text to the current destination
+    fn push_text(&mut self, text: &str) {
+        match self.state.destination {
+            Destination::Body => {
+                let format = self.state.format;
+                match self.runs.last_mut() {
+                    Some((current, inlines)) if *current == format => match inlines.last_mut() {
+                        Some(Inline::Text(previous)) => previous.push_str(text),
+                        _ => inlines.push(Inline::text(text)),
+                    },
+                    _ => self.runs.push((format, vec![Inline::text(text)])),
+                }
+            }
+            Destination::Property(field) => self.info.entry(field).or_default().push_str(text),
+            Destination::Style => self.current_style.1.name.push_str(text),
+            _ => {}
+        }
+    }
+
+    /// Add an inline other than text to the current paragraph
+    fn push_inline(&mut self, inline: Inline) {
+        if self.state.destination != Destination::Body {
+            return;
+        }
+        let format = self.state.format;
+        match self.runs.last_mut() {
+            Some((current, inlines)) if *current == format => inlines.push(inline),
+            _ => self.runs.push((format, vec![inline])),
+        }
+    }
+
+    /// Finish the group of a destination
+    fn end_destination(&mut self, destination: Destination) {
+        match destination {
+            Destination::Style => self.end_style(),
+            Destination::Picture => self.end_picture(),
+            _ => {}
+        }
+    }
+
+    /// Record the style definition just read
+    fn end_style(&mut self) {
+        let (number, mut style) = std::mem::take(&mut self.current_style);
+        style.name = style
+            .name
+            .trim()
+            .trim_end_matches(';')
+            .trim()
+            .to_lowercase();
+        if number >= 0 {
+            self.styles.insert(number, style);
+        }
+    }
+
+    /// Store a PNG or JPEG picture and place it in the current paragraph
+    fn end_picture(&mut self) {
+        let picture = std::mem::take(&mut self.picture);
+        let Some(mime) = picture.mime else {
+            return;
+        };
+        let data = if picture.binary.is_empty() {
+            picture
+                .hex
+                .chunks_exact(2)
+                .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                .collect()
+        } else {
+            picture.binary
+        };
+        if data.is_empty() {
+            return;
+        }
+
+        let extension = if mime == "image/png" { "png" } else { "jpg" };
+        let filename = format!("image{}.{}", self.resources.len() + 1, extension);
+        let key = self
+            .resources
+            .add(Resource::new(mime, data).with_filename(filename));
+        self.images.push(Block::Image {
+            resource_key: key,
+            caption: None,
+            alt: String::new(),
+        });
+    }
+
+    /// Heading level of the current paragraph, from its outline level or style
+    fn heading_level(&self) -> Option<u8> {
+        let style = self.styles.get(&self.style);
+        let level = self.outline_level.or_else(|| {
+            let style = style?;
+            style.outline_level.or_else(|| {
+                let level: u8 = style.name.strip_prefix("heading ")?.parse().ok()?;
+                level.checked_sub(1)
+            })
+        })?;
+        Some((level + 1).min(6))
+    }
+
+    /// Turn the runs collected since the last paragraph mark into blocks
+    fn end_paragraph(&mut self) {
+        let mut inlines = Vec::new();
+        for (format, content) in std::mem::take(&mut self.runs) {
+            inlines.extend(format.apply(content));
+        }
+        trim_inlines(&mut inlines);
+        let images = std::mem::take(&mut self.images);
+        if inlines.is_empty() && images.is_empty() {
+            return;
+        }
+
+        if self.page_break {
+            self.page_breaks.push(self.blocks.len());
+            self.page_break = false;
+        }
+        if !inlines.is_empty() {
+            let block = match self.heading_level() {
+                Some(level) => Block::header(level, inlines),
+                None => Block::Paragraph(inlines),
+            };
+            self.blocks.push(block);
+        }
+        self.blocks.extend(images);
+    }
+
+    /// Fill in metadata from the `\info` group
+    fn apply_info(&self, metadata: &mut Metadata) {
+        let field = |name: &str| {
+            self.info
+                .get(name)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        if let Some(title) = field("title") {
+            metadata.title = title;
+        }
+        if let Some(author) = field("author") {
+            metadata.creator = author
+                .split(';')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        metadata.subject = field("subject").into_iter().collect();
+        for keyword in field("keywords")
+            .unwrap_or_default()
+            .split([',', ';'])
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+        {
+            if !metadata.subject.iter().any(|subject| subject == keyword) {
+                metadata.subject.push(keyword.to_string());
+            }
+        }
+        metadata.description = field("doccomm");
+        metadata.publisher = field("company");
+
+        if let [Some(year), month, day] = self.created {
+            let date = match (month, day) {
+                (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+                (Some(month), None) => format!("{:04}-{:02}", year, month),
+                _ => format!("{:04}", year),
+            };
+            metadata.date = parse_date(&date);
+        }
+    }
+}
+
+/// Encoding for a Windows code page number
+fn code_page(page: i32) -> &'static Encoding {
+    let label = match page {
+        932 => "shift_jis".to_string(),
+        936 => "gbk".to_string(),
+        949 => "euc-kr".to_string(),
+        950 => "big5".to_string(),
+        10000 => "macintosh".to_string(),
+        20866 => "koi8-r".to_string(),
+        65001 => "utf-8".to_string(),
+        page => format!("windows-{}", page),
+    };
+    Encoding::for_label(label.as_bytes()).unwrap_or(WINDOWS_1252)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    fn decode(rtf: &str) -> Book {
+        RtfDecoder::new()
+            .decode(&mut Cursor::new(rtf.as_bytes().to_vec()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<Token> = Tokenizer::new(b"{\\b0 bold\\'e9\\u-3913?\\*}").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::GroupStart,
+                Token::Word("b", Some(0)),
+                Token::Text(b"bold"),
+                Token::Hex(0xE9),
+                Token::Word("u", Some(-3913)),
+                Token::Text(b"?"),
+                Token::Symbol(b'*'),
+                Token::GroupEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_document() {
+        let book = decode(concat!(
+            r"{\rtf1\ansi\ansicpg1251\deff0",
+            r"{\fonttbl{\f0\froman Times New Roman;}}",
+            r"{\stylesheet{\s0 Normal;}{\s1\outlinelevel0 heading 1;}{\s2 Heading 2;}}",
+            r"{\info{\title The Storm}{\author Anna Petrova; Ivan Petrov}{\keywords weather, sea}",
+            r"{\doccomm A short story.}{\creatim\yr2003\mo4\dy9\hr10\min5}}",
+            "\n",
+            r"\pard\s1 Chapter One\par",
+            r"\pard \'cf\'f0\'e8\'e2\'e5\'f2, {\b bold}, {\i italic\i0  plain}, ",
+            r"{\strike gone}, x{\super 2} and H{\sub 2}O\par",
+            r"\pard\s2 Part A\par",
+            r"\pard Caf\u233?, \u8212? and\line next\par",
+            r"{\*\shppict{\pict\pngblip\picw1\pich1 89504e470d0a1a0a}}",
+            r"{\nonshppict{\pict\wmetafile8 0100}}\par",
+            r"\page\pard\s1 Chapter Two\par",
+            r"\pard The end.\par}",
+        ));
+
+        let metadata = &book.metadata;
+        assert_eq!(metadata.title, "The Storm");
+        assert_eq!(metadata.creator, vec!["Anna Petrova", "Ivan Petrov"]);
+        assert_eq!(metadata.subject, vec!["weather", "sea"]);
+        assert_eq!(metadata.description.as_deref(), Some("A short story."));
+        assert_eq!(
+            metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("2003-04-09".to_string())
+        );
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Chapter One", "Chapter Two"]);
+
+        let content = &book.chapters[0].content;
+        assert_eq!(
+            content[1],
+            Block::Paragraph(vec![
+                Inline::text("Привет, "),
+                Inline::Bold(vec![Inline::text("bold")]),
+                Inline::text(", "),
+                Inline::Italic(vec![Inline::text("italic")]),
+                Inline::text(" plain, "),
+                Inline::Strikethrough(vec![Inline::text("gone")]),
+                Inline::text(", x"),
+                Inline::Superscript(vec![Inline::text("2")]),
+                Inline::text(" and H"),
+                Inline::Subscript(vec![Inline::text("2")]),
+                Inline::text("O"),
+            ])
+        );
+        assert!(matches!(&content[2], Block::Header { level: 2, .. }));
+        assert_eq!(
+            content[3],
+            Block::Paragraph(vec![
+                Inline::text("Café, \u{2014} and"),
+                Inline::Break,
+                Inline::text("next"),
+            ])
+        );
+
+        // Only the PNG picture is kept, not its metafile fallback
+        assert_eq!(book.resources.len(), 1);
+        let Block::Image { resource_key, .. } = &content[4] else {
+            panic!("expected image, got {:?}", content[4]);
+        };
+        let image = book.resources.get(resource_key).unwrap();
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!(image.original_filename.as_deref(), Some("image1.png"));
+    }
+
+    /// Blocks of all chapters of a decoded document
+    fn blocks(rtf: &[u8]) -> Vec<Block> {
+        RtfDecoder::new()
+            .decode(&mut Cursor::new(rtf.to_vec()))
+            .unwrap()
+            .chapters
+            .into_iter()
+            .flat_map(|chapter| chapter.content)
+            .collect()
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph(vec![Inline::text(text)])
+    }
+
+    #[test]
+    fn test_unicode_skip_counts() {
+        assert_eq!(
+            blocks(br"{\rtf1 {\uc2\u8212\'97\'97 a}\u233?b\uc0\u233 c\par}"),
+            vec![paragraph("\u{2014} a\u{e9}b\u{e9}c")]
+        );
+
+        // The fallback may be text, hex escapes or a mix, and the count never
+        // reaches past the next control word
+        assert_eq!(
+            blocks(br"{\rtf1\uc3\u8364 EUR!\u8364\'80\par}"),
+            vec![paragraph("\u{20ac}!\u{20ac}")]
+        );
+    }
+
+    #[test]
+    fn test_binary_data() {
+        // Binary data may hold braces and backslashes, which are not markup
+        assert_eq!(
+            blocks(b"{\\rtf1 Before{\\*\\objdata\\bin4 }{\\\\}after\\par}"),
+            vec![paragraph("Beforeafter")]
+        );
+
+        let mut rtf = b"{\\rtf1 {\\pict\\pngblip\\bin4 ".to_vec();
+        rtf.extend_from_slice(b"\x89{}\\");
+        rtf.extend_from_slice(b"}\\par}");
+        let book = RtfDecoder::new().decode(&mut Cursor::new(rtf)).unwrap();
+        let Some(Block::Image { resource_key, .. }) = book.chapters[0].content.first() else {
+            panic!("expected image, got {:?}", book.chapters[0].content);
+        };
+        assert_eq!(
+            book.resources
+                .get(resource_key)
+                .unwrap()
+                .data
+                .as_bytes()
+                .unwrap(),
+            b"\x89{}\\".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_nested_destinations() {
+        let book = RtfDecoder::new()
+            .decode(&mut Cursor::new(
+                concat!(
+                    r"{\rtf1{\fonttbl{\f0 Arial;}{\f1{\*\panose 02020603}Times;}}",
+                    r"{\info{\title Tide {\*\revision 3}Tables}{\*\company Acme}}",
+                    r"Body{\*\unknown skipped {\b deeper {\i still}} gone} text",
+                    r"{\header Page header}\par}",
+                )
+                .as_bytes()
+                .to_vec(),
+            ))
+            .unwrap();
+
+        assert_eq!(book.metadata.title, "Tide Tables");
+        assert_eq!(book.chapters[0].content, vec![paragraph("Body text")]);
+    }
+
+    #[test]
+    fn test_code_pages() {
+        assert_eq!(
+            blocks(br"{\rtf1\ansi\ansicpg932 \'82\'a0\par}"),
+            vec![paragraph("\u{3042}")]
+        );
+        assert_eq!(
+            blocks(br"{\rtf1\mac Caf\'8e\par}"),
+            vec![paragraph("Caf\u{e9}")]
+        );
+
+        // Unknown and missing code pages fall back to Windows-1252
+        assert_eq!(
+            blocks(br"{\rtf1\ansi\ansicpg99999 Caf\'e9 \'93q\'94\par}"),
+            vec![paragraph("Caf\u{e9} \u{201c}q\u{201d}")]
+        );
+        assert_eq!(
+            blocks(br"{\rtf1\ansi\ansicpg Caf\'e9\par}"),
+            vec![paragraph("Caf\u{e9}")]
+        );
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        // Unbalanced groups, bad and truncated escapes, and binary data past the
+        // end are read as far as they make sense
+        assert_eq!(
+            blocks(br"{\rtf1 One}} two{{\b three\par"),
+            vec![Block::Paragraph(vec![
+                Inline::text("One two"),
+                Inline::Bold(vec![Inline::text("three")]),
+            ])]
+        );
+        assert_eq!(
+            blocks(br"{\rtf1\uc-5 a\'zzb\u c\u99999999?d\'4"),
+            vec![paragraph("abc?d")]
+        );
+        assert_eq!(
+            blocks(br"{\rtf1 Text\par\bin999 x"),
+            vec![paragraph("Text")]
+        );
+
+        assert!(matches!(
+            RtfDecoder::new().decode(&mut Cursor::new(b"Plain text".to_vec())),
+            Err(ParseError::UnsupportedFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,1061 @@
+//! TEI decoder implementation
+//!
+//! A TEI P5 document is a `<TEI>` element holding a `<teiHeader>`, which
+//! describes the work, and a `<text>` whose `<front>`, `<body>` and `<back>`
+//! are divided into nested `<div>`s. Each top-level division becomes a chapter,
+//! except that divisions typed as parts or volumes hold chapters of their own;
+//! deeper divisions become headings. Notes become footnotes, `<lg>`/`<l>` verse
+//! keeps its line breaks, and `<graphic>` images are loaded through the decode
+//! context.
+
+use super::chapters::place_footnotes;
+use super::context::AssetLoader;
+use super::front_matter::parse_date;
+use super::xml::{self, trim_inlines};
+use super::DecodeContext;
+use crate::error::ParseError;
+use crate::types::{
+    Block, Book, Chapter, DefinitionItem, Inline, Metadata, ResourceStore, TableCell, TableData,
+    TocEntry,
+};
+use roxmltree::Node;
+use std::cell::RefCell;
+use std::io::Read;
+
+/// Division types that hold chapters rather than being one
+const GROUPING_TYPES: &[&str] = &["part", "book", "volume"];
+
+/// Block elements that may appear inside a paragraph
+const BLOCKS_IN_PARAGRAPH: &[&str] = &["list", "table", "figure", "lg", "quote", "cit", "eg"];
+
+/// Decoder for TEI P5 XML
+pub struct TeiDecoder;
+
+/// State shared while converting a document
+struct Converter {
+    /// Footnotes collected from the text, in order
+    footnotes: RefCell<Vec<(String, Block)>>,
+    /// Number of chapters made, for generating ids
+    chapters: usize,
+}
+
+impl TeiDecoder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for TeiDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for TeiDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read TEI: {}", e)))?;
+
+        let text = xml::decode_xml(&data);
+        let document = xml::parse(&text)?;
+        let root = document.root_element();
+        if root.tag_name().name() != "TEI" {
+            return Err(ParseError::UnsupportedFormat(
+                "Not a TEI document".to_string(),
+            ));
+        }
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        if let Some(lang) = xml::attribute(root, "lang") {
+            metadata.language = lang.to_string();
+        }
+        if let Some(header) = xml::child(root, "teiHeader") {
+            apply_header(header, &mut metadata);
+        }
+
+        let text =
+            xml::child(root, "text").ok_or_else(|| ParseError::MissingField("text".to_string()))?;
+        if let Some(lang) = xml::attribute(text, "lang") {
+            metadata.language = lang.to_string();
+        }
+
+        let mut converter = Converter {
+            footnotes: RefCell::new(Vec::new()),
+            chapters: 0,
+        };
+        let mut chapters = Vec::new();
+        let mut toc = Vec::new();
+        // A <group> of texts, such as an anthology, is read text by text
+        let texts: Vec<Node> = match xml::child(text, "group") {
+            Some(group) => xml::children(group, "text").collect(),
+            None => vec![text],
+        };
+        for text in texts {
+            for part in ["front", "body", "back"] {
+                if let Some(part) = xml::child(text, part) {
+                    converter.part_to_chapters(part, &metadata.title, &mut chapters, &mut toc);
+                }
+            }
+        }
+        place_footnotes(&mut chapters, converter.footnotes.into_inner());
+
+        // Graphics are referenced by URL, relative to the document
+        let mut resources = ResourceStore::new();
+        let mut loader = AssetLoader::new(context, &mut resources);
+        for chapter in &mut chapters {
+            loader.load_images(&mut chapter.content);
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        book.chapters = chapters;
+        book.toc = toc;
+        book.resources = resources;
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["tei"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/tei+xml"]
+    }
+}
+
+impl Converter {
+    /// Convert a `<front>`, `<body>` or `<back>`: content outside its divisions
+    /// becomes a chapter of its own, followed by one for each division
+    fn part_to_chapters(
+        &mut self,
+        part: Node,
+        title: &str,
+        chapters: &mut Vec<Chapter>,
+        toc: &mut Vec<TocEntry>,
+    ) {
+        let intro = self.blocks(part);
+        if !intro.is_empty() {
+            let title = match part.tag_name().name() {
+                "front" => "Front Matter",
+                "back" => "Back Matter",
+                _ => title,
+            };
+            let id = self.next_id(None);
+            toc.push(TocEntry::new(title, format!("#{}", id)));
+            chapters.push(Chapter::new(title).with_id(id).with_content(intro));
+        }
+        for division in divisions(part) {
+            toc.push(self.division_to_chapters(division, 0, chapters));
+        }
+    }
+
+    /// Convert a division, returning its TOC entry
+    ///
+    /// Grouping divisions (parts and volumes) hold their subdivisions as chapters;
+    /// any other division keeps its subdivisions as headings.
+    fn division_to_chapters(
+        &mut self,
+        division: Node,
+        depth: u32,
+        chapters: &mut Vec<Chapter>,
+    ) -> TocEntry {
+        let head = xml::child(division, "head");
+        let title = head
+            .map(xml::text)
+            .filter(|title| !title.is_empty())
+            .or_else(|| division.attribute("n").map(str::to_string))
+            .unwrap_or_else(|| format!("Chapter {}", self.chapters + 1));
+        let id = self.next_id(xml::attribute(division, "id"));
+        let level = (depth + 1).min(6) as u8;
+
+        let mut content = vec![Block::Header {
+            level,
+            content: match head {
+                Some(head) => self.inlines(head),
+                None => vec![Inline::text(&title)],
+            },
+            anchor: None,
+        }];
+
+        let grouping = is_grouping(division);
+        if grouping {
+            content.extend(self.blocks(division));
+        } else {
+            content.extend(self.blocks_with_sections(division, level));
+        }
+        chapters.push(Chapter::new(&title).with_id(&id).with_content(content));
+
+        let children = if grouping {
+            divisions(division)
+                .map(|child| self.division_to_chapters(child, depth + 1, chapters))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        TocEntry::new(title, format!("#{}", id))
+            .with_level(depth)
+            .with_children(children)
+    }
+
+    /// A chapter id, from the division's id or generated
+    fn next_id(&mut self, id: Option<&str>) -> String {
+        self.chapters += 1;
+        id.map(str::to_string)
+            .unwrap_or_else(|| format!("chapter-{}", self.chapters))
+    }
+
+    /// Convert the block children of a container, with its subdivisions as
+    /// headings
+    fn blocks_with_sections(&self, node: Node, level: u8) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        for child in node.children().filter(|n| n.is_element()) {
+            if is_division(child) {
+                let level = (level + 1).min(6);
+                if let Some(head) = xml::child(child, "head") {
+                    blocks.push(Block::Header {
+                        level,
+                        content: self.inlines(head),
+                        anchor: xml::attribute(child, "id").map(str::to_string),
+                    });
+                }
+                blocks.extend(self.blocks_with_sections(child, level));
+            } else if child.tag_name().name() != "head" {
+                blocks.extend(self.element_to_blocks(child));
+            }
+        }
+        blocks
+    }
+
+    /// Convert the block children of a container, skipping its heading and
+    /// any divisions within it
+    fn blocks(&self, node: Node) -> Vec<Block> {
+        node.children()
+            .filter(|n| n.is_element() && !is_division(*n) && n.tag_name().name() != "head")
+            .flat_map(|child| self.element_to_blocks(child))
+            .collect()
+    }
+
+    /// Convert the children of an element with block content
+    fn child_blocks(&self, node: Node) -> Vec<Block> {
+        let has_blocks = node.children().any(|n| {
+            n.is_element()
+                && matches!(
+                    n.tag_name().name(),
+                    "p" | "ab" | "lg" | "list" | "quote" | "table" | "figure" | "sp"
+                )
+        });
+        if has_blocks {
+            self.blocks(node)
+        } else {
+            self.paragraph(node)
+        }
+    }
+
+    /// Convert a block-level element
+    fn element_to_blocks(&self, element: Node) -> Vec<Block> {
+        match element.tag_name().name() {
+            "p" | "ab" | "byline" | "dateline" | "docAuthor" | "docDate" | "docImprint"
+            | "salute" | "signed" | "trailer" | "argument" => self.paragraph(element),
+            "head" => vec![Block::Paragraph(vec![Inline::Bold(self.inlines(element))])],
+            "lg" => vec![Block::Blockquote(self.verse(element))],
+            "l" => vec![Block::Paragraph(self.inlines(element))],
+            "quote" | "cit" | "epigraph" => {
+                let mut content = Vec::new();
+                for child in element.children().filter(|n| n.is_element()) {
+                    match child.tag_name().name() {
+                        "quote" => content.extend(self.child_blocks(child)),
+                        "bibl" => {
+                            let mut credit = vec![Inline::text("\u{2014} ")];
+                            credit.extend(self.inlines(child));
+                            content.push(Block::Paragraph(credit));
+                        }
+                        _ => content.extend(self.element_to_blocks(child)),
+                    }
+                }
+                if content.is_empty() {
+                    content = self.paragraph(element);
+                }
+                vec![Block::Blockquote(content)]
+            }
+            "list" => self.list(element),
+            "table" => {
+                let mut blocks = Vec::new();
+                if let Some(head) = xml::child(element, "head") {
+                    blocks.push(Block::Paragraph(vec![Inline::Bold(self.inlines(head))]));
+                }
+                blocks.push(Block::Table(self.table(element)));
+                blocks
+            }
+            "figure" => {
+                let caption = xml::child(element, "head").map(xml::text);
+                let alt = xml::child(element, "figDesc")
+                    .map(xml::text)
+                    .unwrap_or_default();
+                element
+                    .descendants()
+                    .filter(|n| n.tag_name().name() == "graphic")
+                    .filter_map(|graphic| {
+                        Some(Block::Image {
+                            resource_key: graphic.attribute("url")?.to_string(),
+                            caption: caption.clone(),
+                            alt: alt.clone(),
+                        })
+                    })
+                    .collect()
+            }
+            "graphic" => element
+                .attribute("url")
+                .map(|url| Block::Image {
+                    resource_key: url.to_string(),
+                    caption: None,
+                    alt: String::new(),
+                })
+                .into_iter()
+                .collect(),
+            "eg" | "egXML" => vec![Block::CodeBlock {
+                lang: element.attribute("lang").map(str::to_string),
+                code: code_text(element),
+            }],
+            "sp" => {
+                // A speech in a play: the speaker, then their lines
+                let mut blocks = Vec::new();
+                for child in element.children().filter(|n| n.is_element()) {
+                    if child.tag_name().name() == "speaker" {
+                        blocks.push(Block::Paragraph(vec![Inline::Bold(self.inlines(child))]));
+                    } else {
+                        blocks.extend(self.element_to_blocks(child));
+                    }
+                }
+                blocks
+            }
+            "stage" => vec![Block::Paragraph(vec![Inline::Italic(
+                self.inlines(element),
+            )])],
+            "opener" | "closer" | "postscript" | "floatingText" | "div" | "div1" | "div2"
+            | "div3" | "div4" | "div5" | "div6" | "div7" | "titlePage" | "docTitle"
+            | "titlePart" | "castList" | "castGroup" | "castItem" | "listBibl" | "bibl" => {
+                self.child_blocks(element)
+            }
+            "note" => {
+                // A note between blocks is a footnote anchored to nothing; keep
+                // its text in place instead
+                vec![Block::Blockquote(self.child_blocks(element))]
+            }
+            "pb" | "milestone" | "anchor" | "fw" | "index" | "interpGrp" => vec![],
+            _ => self.paragraph(element),
+        }
+    }
+
+    /// Convert a paragraph, whose content may include block elements such as
+    /// lists and verse
+    fn paragraph(&self, element: Node) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut inlines = Vec::new();
+        let flush = |inlines: &mut Vec<Inline>, blocks: &mut Vec<Block>| {
+            trim_inlines(inlines);
+            if !inlines.is_empty() {
+                blocks.push(Block::Paragraph(std::mem::take(inlines)));
+            }
+        };
+
+        for child in element.children() {
+            if child.is_element() && BLOCKS_IN_PARAGRAPH.contains(&child.tag_name().name()) {
+                flush(&mut inlines, &mut blocks);
+                blocks.extend(self.element_to_blocks(child));
+            } else {
+                inlines.extend(self.inline(child));
+            }
+        }
+        flush(&mut inlines, &mut blocks);
+        blocks
+    }
+
+    /// Convert a line group: its lines become one paragraph with line breaks,
+    /// and nested groups (stanzas) become paragraphs of their own
+    fn verse(&self, group: Node) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut lines = Vec::new();
+        for child in group.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "l" => {
+                    if !lines.is_empty() {
+                        lines.push(Inline::Break);
+                    }
+                    lines.extend(self.inlines(child));
+                }
+                "lg" => {
+                    if !lines.is_empty() {
+                        blocks.push(Block::Paragraph(std::mem::take(&mut lines)));
+                    }
+                    blocks.extend(self.verse(child));
+                }
+                "head" => blocks.push(Block::Paragraph(vec![Inline::Bold(self.inlines(child))])),
+                _ => {
+                    if !lines.is_empty() {
+                        blocks.push(Block::Paragraph(std::mem::take(&mut lines)));
+                    }
+                    blocks.extend(self.element_to_blocks(child));
+                }
+            }
+        }
+        if !lines.is_empty() {
+            blocks.push(Block::Paragraph(lines));
+        }
+        blocks
+    }
+
+    /// Convert a list, which is a definition list when its items have labels
+    fn list(&self, list: Node) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        if let Some(head) = xml::child(list, "head") {
+            blocks.push(Block::Paragraph(vec![Inline::Bold(self.inlines(head))]));
+        }
+
+        let is_gloss =
+            list.attribute("type") == Some("gloss") || xml::child(list, "label").is_some();
+        if is_gloss {
+            let mut items = Vec::new();
+            let mut term: Option<Vec<Inline>> = None;
+            for child in list.children().filter(|n| n.is_element()) {
+                match child.tag_name().name() {
+                    "label" => term = Some(self.inlines(child)),
+                    "item" => items.push(DefinitionItem {
+                        term: term.take().unwrap_or_default(),
+                        definitions: vec![self.child_blocks(child)],
+                    }),
+                    _ => {}
+                }
+            }
+            blocks.push(Block::DefinitionList(items));
+            return blocks;
+        }
+
+        let rend = list
+            .attribute("rend")
+            .or_else(|| list.attribute("type"))
+            .unwrap_or_default();
+        blocks.push(Block::List {
+            items: xml::children(list, "item")
+                .map(|item| self.child_blocks(item))
+                .collect(),
+            ordered: ["numbered", "ordered", "decimal", "arabic", "roman"]
+                .iter()
+                .any(|kind| rend.contains(kind)),
+        });
+        blocks
+    }
+
+    /// Convert a table, whose header rows are those with the `label` role
+    fn table(&self, table: Node) -> TableData {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for row in xml::children(table, "row") {
+            let cells: Vec<TableCell> = xml::children(row, "cell")
+                .map(|cell| {
+                    let span = |name| {
+                        cell.attribute(name)
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(1)
+                    };
+                    let mut table_cell = TableCell::new(self.inlines(cell));
+                    table_cell.colspan = span("cols");
+                    table_cell.rowspan = span("rows");
+                    table_cell
+                })
+                .collect();
+            let is_label = row.attribute("role") == Some("label");
+            if is_label && headers.is_empty() && rows.is_empty() {
+                headers = cells;
+            } else {
+                rows.push(cells);
+            }
+        }
+        TableData { headers, rows }
+    }
+
+    /// Convert inline content, trimming the whitespace at its ends
+    fn inlines(&self, node: Node) -> Vec<Inline> {
+        let mut inlines: Vec<Inline> = node
+            .children()
+            .flat_map(|child| self.inline(child))
+            .collect();
+        trim_inlines(&mut inlines);
+        inlines
+    }
+
+    /// Convert the children of an inline container, without trimming
+    fn children_to_inlines(&self, node: Node) -> Vec<Inline> {
+        node.children()
+            .flat_map(|child| self.inline(child))
+            .collect()
+    }
+
+    /// Convert one inline node
+    fn inline(&self, node: Node) -> Vec<Inline> {
+        if node.is_text() {
+            let text = xml::collapse_whitespace(node.text().unwrap_or_default());
+            return if text.is_empty() {
+                vec![]
+            } else {
+                vec![Inline::Text(text)]
+            };
+        }
+        if !node.is_element() {
+            return vec![];
+        }
+
+        let children = || self.children_to_inlines(node);
+        match node.tag_name().name() {
+            "hi" => {
+                let rend = node.attribute("rend").unwrap_or("italic");
+                if rend.contains("bold") {
+                    vec![Inline::Bold(children())]
+                } else if rend.contains("sup") {
+                    vec![Inline::Superscript(children())]
+                } else if rend.contains("sub") {
+                    vec![Inline::Subscript(children())]
+                } else if rend.contains("strike") || rend.contains("line-through") {
+                    vec![Inline::Strikethrough(children())]
+                } else if rend.contains("code") || rend.contains("mono") {
+                    vec![Inline::Code(xml::text(node))]
+                } else if rend.contains("italic") || rend.is_empty() {
+                    vec![Inline::Italic(children())]
+                } else {
+                    children()
+                }
+            }
+            "emph" | "foreign" | "title" | "term" | "mentioned" | "soCalled" => {
+                vec![Inline::Italic(children())]
+            }
+            "code" | "ident" | "gi" | "att" | "val" | "tag" => vec![Inline::Code(xml::text(node))],
+            "del" => vec![Inline::Strikethrough(children())],
+            "q" | "said" => {
+                let mut content = vec![Inline::text("\u{201C}")];
+                content.extend(children());
+                content.push(Inline::text("\u{201D}"));
+                content
+            }
+            "ref" | "ptr" => {
+                let Some(url) = node.attribute("target") else {
+                    return children();
+                };
+                let mut content = children();
+                if content.is_empty() {
+                    content.push(Inline::text(url.trim_start_matches('#')));
+                }
+                vec![Inline::Link {
+                    children: content,
+                    url: url.to_string(),
+                }]
+            }
+            "lb" => vec![Inline::Break],
+            "note" => {
+                let mut footnotes = self.footnotes.borrow_mut();
+                let id = xml::attribute(node, "id")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("note-{}", footnotes.len() + 1));
+                drop(footnotes);
+                let content = self.child_blocks(node);
+                footnotes = self.footnotes.borrow_mut();
+                footnotes.push((
+                    id.clone(),
+                    Block::Footnote {
+                        id: id.clone(),
+                        content,
+                    },
+                ));
+                vec![Inline::FootnoteRef { id }]
+            }
+            "choice" => {
+                // Show the editor's reading: corrected, regularised or expanded
+                ["corr", "reg", "expan"]
+                    .iter()
+                    .find_map(|name| xml::child(node, name))
+                    .or_else(|| node.children().find(|n| n.is_element()))
+                    .map(|child| self.children_to_inlines(child))
+                    .unwrap_or_default()
+            }
+            "gap" => vec![Inline::text("[\u{2026}]")],
+            "pb" | "milestone" | "anchor" | "fw" | "index" | "sic" | "orig" | "abbr"
+            | "figDesc" => vec![],
+            // <name>, <persName>, <date> and unknown elements pass their content through
+            _ => children(),
+        }
+    }
+}
+
+/// Whether an element is a division
+fn is_division(node: Node) -> bool {
+    let name = node.tag_name().name();
+    name == "div" || (name.starts_with("div") && name[3..].parse::<u8>().is_ok())
+}
+
+/// Whether a division holds chapters, such as a part or volume
+fn is_grouping(division: Node) -> bool {
+    division
+        .attribute("type")
+        .is_some_and(|kind| GROUPING_TYPES.contains(&kind))
+        && divisions(division).next().is_some()
+}
+
+/// Divisions directly within an element
+fn divisions<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(|n| n.is_element() && is_division(*n))
+}
+
+/// Text of an example, as written
+fn code_text(element: Node) -> String {
+    let text: String = element
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    text.trim_start_matches(['\r', '\n']).trim_end().to_string()
+}
+
+/// Fill in metadata from the `<teiHeader>`
+fn apply_header(header: Node, metadata: &mut Metadata) {
+    let file_desc = xml::child(header, "fileDesc");
+
+    if let Some(title_stmt) = file_desc.and_then(|desc| xml::child(desc, "titleStmt")) {
+        // The main title, unless only subtitles are typed
+        let titles: Vec<Node> = xml::children(title_stmt, "title").collect();
+        let title = titles
+            .iter()
+            .find(|t| matches!(t.attribute("type"), None | Some("main")))
+            .or(titles.first());
+        if let Some(title) = title.map(|t| xml::text(*t)).filter(|t| !t.is_empty()) {
+            metadata.title = title;
+        }
+
+        metadata.creator = xml::children(title_stmt, "author")
+            .map(person_name)
+            .filter(|name| !name.is_empty())
+            .collect();
+        if metadata.creator.is_empty() {
+            metadata.creator = xml::children(title_stmt, "editor")
+                .map(person_name)
+                .filter(|name| !name.is_empty())
+                .collect();
+        }
+    }
+
+    if let Some(publication) = file_desc.and_then(|desc| xml::child(desc, "publicationStmt")) {
+        metadata.publisher = xml::child(publication, "publisher")
+            .or_else(|| xml::child(publication, "distributor"))
+            .map(xml::text)
+            .filter(|publisher| !publisher.is_empty());
+        if let Some(date) = xml::child(publication, "date") {
+            let value = date
+                .attribute("when")
+                .map(str::to_string)
+                .unwrap_or_else(|| xml::text(date));
+            metadata.date = parse_date(&value);
+        }
+        if let Some(idno) = xml::children(publication, "idno")
+            .map(xml::text)
+            .find(|idno| !idno.is_empty())
+        {
+            metadata.identifier = idno;
+        }
+        metadata.rights = xml::child(publication, "availability")
+            .map(xml::text)
+            .filter(|rights| !rights.is_empty());
+    }
+
+    if let Some(profile) = xml::child(header, "profileDesc") {
+        if let Some(language) = xml::child(profile, "langUsage")
+            .and_then(|usage| xml::child(usage, "language"))
+            .and_then(|language| language.attribute("ident"))
+        {
+            metadata.language = language.to_string();
+        }
+        metadata.subject = profile
+            .descendants()
+            .filter(|n| n.tag_name().name() == "term")
+            .map(xml::text)
+            .filter(|term| !term.is_empty())
+            .collect();
+        if let Some(abstract_) = xml::child(profile, "abstract") {
+            let text = xml::text(abstract_);
+            if !text.is_empty() {
+                metadata.description = Some(text);
+            }
+        }
+    }
+}
+
+/// Name of an author or editor, from its name parts or its text
+fn person_name(person: Node) -> String {
+    let name = xml::child(person, "persName").unwrap_or(person);
+    let parts: Vec<String> = ["forename", "surname"]
+        .iter()
+        .flat_map(|part| xml::children(name, part).map(xml::text))
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty() {
+        xml::text(name)
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::markdown::inlines_to_text;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    const NS: &str = r#"xmlns="http://www.tei-c.org/ns/1.0""#;
+
+    fn decode(xml: &str) -> Book {
+        TeiDecoder::new()
+            .decode(&mut Cursor::new(xml.as_bytes()))
+            .unwrap()
+    }
+
+    /// Blocks of a body with no divisions, which all open its first chapter
+    fn body(content: &str) -> Vec<Block> {
+        let book = decode(&format!(
+            "<TEI {}><text><body>{}</body></text></TEI>",
+            NS, content
+        ));
+        assert_eq!(book.chapters.len(), 1);
+        book.chapters.into_iter().next().unwrap().content
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph(vec![Inline::text(text)])
+    }
+
+    #[test]
+    fn test_decode_metadata() {
+        let book = decode(&format!(
+            r#"<TEI {} xml:lang="en">
+              <teiHeader>
+                <fileDesc>
+                  <titleStmt>
+                    <title type="sub">A Selection</title>
+                    <title type="main">Collected Poems</title>
+                    <author><persName><forename>Ann</forename> <surname>Poet</surname></persName></author>
+                  </titleStmt>
+                  <publicationStmt>
+                    <publisher>University Press</publisher>
+                    <date when="1901-05-01">1901</date>
+                    <availability><p>Public domain.</p></availability>
+                  </publicationStmt>
+                </fileDesc>
+                <profileDesc>
+                  <textClass><keywords><term>Poetry</term></keywords></textClass>
+                </profileDesc>
+              </teiHeader>
+              <text xml:lang="fr"><body><p>Text.</p></body></text>
+            </TEI>"#,
+            NS
+        ));
+
+        assert_eq!(book.metadata.title, "Collected Poems");
+        assert_eq!(book.metadata.creator, vec!["Ann Poet"]);
+        assert_eq!(book.metadata.publisher.as_deref(), Some("University Press"));
+        assert_eq!(
+            book.metadata.date.map(|d| d.format("%Y-%m-%d").to_string()),
+            Some("1901-05-01".to_string())
+        );
+        assert_eq!(book.metadata.subject, vec!["Poetry"]);
+        assert_eq!(book.metadata.rights.as_deref(), Some("Public domain."));
+        assert_eq!(book.metadata.language, "fr");
+    }
+
+    #[test]
+    fn test_divisions() {
+        let book = decode(&format!(
+            r#"<TEI {}><text>
+              <front><p>Dedication.</p></front>
+              <body>
+                <div type="part" xml:id="p1">
+                  <head>Part One</head>
+                  <div xml:id="c1"><head>Spring</head><p>One.</p>
+                    <div xml:id="c1-1"><head>Variant</head><p>Two.</p></div>
+                  </div>
+                  <div n="II"><p>Three.</p></div>
+                </div>
+                <div type="part"><head>Lonely part</head><p>No chapters.</p></div>
+                <div><p>Four.</p></div>
+              </body>
+              <back><div><head>Notes</head></div></back>
+            </text></TEI>"#,
+            NS
+        ));
+
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Front Matter",
+                "Part One",
+                "Spring",
+                "II",
+                "Lonely part",
+                "Chapter 6",
+                "Notes"
+            ]
+        );
+        assert_eq!(book.chapters[0].content, vec![paragraph("Dedication.")]);
+        assert_eq!(
+            book.chapters[2].content[2],
+            Block::Header {
+                level: 3,
+                content: vec![Inline::text("Variant")],
+                anchor: Some("c1-1".into()),
+            }
+        );
+
+        let toc: Vec<&str> = book.toc.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(
+            toc,
+            vec![
+                "Front Matter",
+                "Part One",
+                "Lonely part",
+                "Chapter 6",
+                "Notes"
+            ]
+        );
+        let parts: Vec<&str> = book.toc[1]
+            .children
+            .iter()
+            .map(|e| e.href.as_str())
+            .collect();
+        assert_eq!(parts, vec!["#c1", "#chapter-4"]);
+    }
+
+    #[test]
+    fn test_verse() {
+        assert_eq!(
+            body(
+                r#"<lg type="poem"><head>Spring</head>
+                  <lg type="stanza"><l>The buds <hi>unfold</hi>,</l><l>the days grow long.</l></lg>
+                  <lg type="stanza"><l>Then summer.</l></lg>
+                </lg>
+                <p>Prose<lb/>broken.</p>"#
+            ),
+            vec![
+                Block::Blockquote(vec![
+                    Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Spring")])]),
+                    Block::Paragraph(vec![
+                        Inline::text("The buds "),
+                        Inline::Italic(vec![Inline::text("unfold")]),
+                        Inline::text(","),
+                        Inline::Break,
+                        Inline::text("the days grow long."),
+                    ]),
+                    paragraph("Then summer."),
+                ]),
+                Block::Paragraph(vec![
+                    Inline::text("Prose"),
+                    Inline::Break,
+                    Inline::text("broken."),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        assert_eq!(
+            body(
+                r#"<p>Long<note>Written in <choice><sic>Apirl</sic><corr>April</corr></choice>.</note> days<note xml:id="n2"><p>A <choice><abbr>Dr</abbr><expan>Doctor</expan></choice>.</p></note></p>
+                <note><p>Between blocks.</p></note>"#
+            ),
+            vec![
+                Block::Paragraph(vec![
+                    Inline::text("Long"),
+                    Inline::FootnoteRef {
+                        id: "note-1".into()
+                    },
+                    Inline::text(" days"),
+                    Inline::FootnoteRef { id: "n2".into() },
+                ]),
+                // A note between blocks anchors to nothing, so stays in place
+                Block::Blockquote(vec![paragraph("Between blocks.")]),
+                Block::Footnote {
+                    id: "note-1".into(),
+                    content: vec![Block::Paragraph(vec![
+                        Inline::text("Written in "),
+                        Inline::text("April"),
+                        Inline::text("."),
+                    ])],
+                },
+                Block::Footnote {
+                    id: "n2".into(),
+                    content: vec![Block::Paragraph(vec![
+                        Inline::text("A "),
+                        Inline::text("Doctor"),
+                        Inline::text("."),
+                    ])],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        assert_eq!(
+            body(
+                r##"<p><hi rend="bold">a</hi> <hi rend="sup">b</hi> <hi rend="strike">c</hi> <hi rend="mono">d()</hi> <hi rend="smallcaps">e</hi> <emph>f</emph> <gi>p</gi> <del>g</del> <q>h</q> <ref target="#x">i</ref> <ptr target="#y"/> <gap/></p>"##
+            ),
+            vec![Block::Paragraph(vec![
+                Inline::Bold(vec![Inline::text("a")]),
+                Inline::text(" "),
+                Inline::Superscript(vec![Inline::text("b")]),
+                Inline::text(" "),
+                Inline::Strikethrough(vec![Inline::text("c")]),
+                Inline::text(" "),
+                Inline::Code("d()".into()),
+                Inline::text(" "),
+                Inline::text("e"),
+                Inline::text(" "),
+                Inline::Italic(vec![Inline::text("f")]),
+                Inline::text(" "),
+                Inline::Code("p".into()),
+                Inline::text(" "),
+                Inline::Strikethrough(vec![Inline::text("g")]),
+                Inline::text(" "),
+                Inline::text("\u{201C}"),
+                Inline::text("h"),
+                Inline::text("\u{201D}"),
+                Inline::text(" "),
+                Inline::Link {
+                    children: vec![Inline::text("i")],
+                    url: "#x".into(),
+                },
+                Inline::text(" "),
+                Inline::Link {
+                    children: vec![Inline::text("y")],
+                    url: "#y".into(),
+                },
+                Inline::text(" "),
+                Inline::text("[\u{2026}]"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            body(
+                r#"<list rend="numbered"><head>Steps</head><item>One</item><item><p>Two</p></item></list>
+                <list type="gloss"><label>A</label><item>Letter</item></list>"#
+            ),
+            vec![
+                Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Steps")])]),
+                Block::List {
+                    items: vec![vec![paragraph("One")], vec![paragraph("Two")]],
+                    ordered: true,
+                },
+                Block::DefinitionList(vec![DefinitionItem {
+                    term: vec![Inline::text("A")],
+                    definitions: vec![vec![paragraph("Letter")]],
+                }]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        let blocks = body(
+            r#"<table><head>Sizes</head>
+              <row role="label"><cell>Bird</cell><cell>Size</cell></row>
+              <row><cell rows="2">Wren</cell><cell>10</cell></row>
+              <row role="label"><cell cols="2">Later label</cell></row>
+            </table>"#,
+        );
+
+        assert_eq!(
+            blocks[0],
+            Block::Paragraph(vec![Inline::Bold(vec![Inline::text("Sizes")])])
+        );
+        let Block::Table(table) = &blocks[1] else {
+            panic!("expected a table, got {:?}", blocks[1]);
+        };
+        let headers: Vec<String> = table
+            .headers
+            .iter()
+            .map(|cell| inlines_to_text(&cell.content))
+            .collect();
+        assert_eq!(headers, vec!["Bird", "Size"]);
+        assert_eq!(table.rows[0][0].rowspan, 2);
+        // Only leading label rows are headers
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[1][0].colspan, 2);
+    }
+
+    #[test]
+    fn test_drama_and_quotations() {
+        assert_eq!(
+            body(
+                r#"<sp><speaker>HAMLET</speaker><stage>Aside</stage><l>To be.</l></sp>
+                <cit><quote>Words.</quote><bibl>Someone</bibl></cit>
+                <eg lang="xml">
+  &lt;p/&gt;</eg>"#
+            ),
+            vec![
+                Block::Paragraph(vec![Inline::Bold(vec![Inline::text("HAMLET")])]),
+                Block::Paragraph(vec![Inline::Italic(vec![Inline::text("Aside")])]),
+                paragraph("To be."),
+                Block::Blockquote(vec![
+                    paragraph("Words."),
+                    Block::Paragraph(vec![Inline::text("\u{2014} "), Inline::text("Someone")]),
+                ]),
+                Block::CodeBlock {
+                    lang: Some("xml".into()),
+                    code: "  <p/>".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        let decoder = TeiDecoder::new();
+        let decode = |xml: &str| decoder.decode(&mut Cursor::new(xml.as_bytes()));
+
+        assert!(decode("<TEI><text>").is_err());
+        assert!(matches!(
+            decode("<book/>"),
+            Err(ParseError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            decode(&format!("<TEI {}><teiHeader/></TEI>", NS)),
+            Err(ParseError::MissingField(_))
+        ));
+
+        // Empty elements and unknown renditions are tolerated
+        let book = decode(&format!(
+            r#"<TEI {}><teiHeader><fileDesc><titleStmt><title/></titleStmt></fileDesc></teiHeader>
+              <text><body><div/><p><choice/><ref>plain</ref><hi rend="">x</hi></p><table/></body></text></TEI>"#,
+            NS
+        ))
+        .unwrap();
+        assert_eq!(book.metadata.title, "Untitled");
+        assert_eq!(
+            book.chapters[0].content[0],
+            Block::Paragraph(vec![
+                Inline::text("plain"),
+                Inline::Italic(vec![Inline::text("x")]),
+            ])
+        );
+    }
+}
@@ -0,0 +1,576 @@
+//! Plain text decoder implementation
+//!
+//! Turns hard-wrapped plain text, such as Project Gutenberg releases, into
+//! paragraphs. Chapter headings are recognised by their wording ("CHAPTER IV",
+//! "Book 2", a lone roman numeral) and become headings that the chapter splitter
+//! divides the book at.
+
+use super::chapters::{ChapterSplitter, SplitOptions, SplitStrategy};
+use crate::error::ParseError;
+use crate::types::{Block, Book, Inline, Metadata};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use regex::Regex;
+use std::io::Read;
+use std::sync::OnceLock;
+
+/// Decoder for plain text
+pub struct TextDecoder {
+    /// Whether to remove Project Gutenberg license boilerplate
+    strip_gutenberg: bool,
+    /// Where the text is divided into chapters
+    splitter: ChapterSplitter,
+}
+
+impl TextDecoder {
+    pub fn new() -> Self {
+        Self {
+            strip_gutenberg: true,
+            splitter: ChapterSplitter::new(SplitStrategy::HeadingLevel(2)),
+        }
+    }
+
+    /// Set whether the Project Gutenberg header and license are removed (on by default)
+    pub fn with_strip_gutenberg(mut self, strip: bool) -> Self {
+        self.strip_gutenberg = strip;
+        self
+    }
+
+    /// Set where the text is divided into chapters (parts and chapters by default)
+    ///
+    /// Detected part, book and volume headings are H1; chapter headings are H2.
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.splitter = self.splitter.with_strategy(strategy);
+        self
+    }
+
+    /// Split chapters longer than `chars` characters of text
+    pub fn with_max_chapter_size(mut self, chars: usize) -> Self {
+        self.splitter = self.splitter.with_max_size(chars);
+        self
+    }
+
+    /// Parse text into blocks
+    fn parse_text(&self, text: &str) -> Vec<Block> {
+        let lines: Vec<&str> = text.lines().map(|line| line.trim_end()).collect();
+        let wrap_width = wrap_width(&lines);
+
+        // Paragraphs are separated by blank lines
+        let chunks: Vec<&[&str]> = lines
+            .split(|line| line.trim().is_empty())
+            .filter(|chunk| !chunk.is_empty())
+            .collect();
+
+        let mut blocks = Vec::new();
+        let mut i = 0;
+        while i < chunks.len() {
+            let chunk = chunks[i];
+            i += 1;
+
+            if chunk.len() == 1 && is_separator(chunk[0]) {
+                blocks.push(Block::ThematicBreak);
+                continue;
+            }
+
+            if let Some(level) = heading_level(chunk[0]) {
+                // A second line must name the chapter, not carry on a sentence
+                if chunk.len() == 1 || chunk.len() == 2 && is_subtitle(&chunk[1..]) {
+                    let mut title: Vec<&str> = chunk.iter().map(|line| line.trim()).collect();
+
+                    // A chapter's name often follows its number on the next line
+                    if title.len() == 1 {
+                        if let Some(next) = chunks.get(i).filter(|next| is_subtitle(next)) {
+                            title.push(next[0].trim());
+                            i += 1;
+                        }
+                    }
+
+                    blocks.push(Block::header(level, vec![Inline::text(join_title(&title))]));
+                    continue;
+                }
+            }
+
+            blocks.push(paragraph(chunk, wrap_width));
+        }
+
+        blocks
+    }
+}
+
+impl Default for TextDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for TextDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read text: {}", e)))?;
+
+        let (text, _) = decode_bytes(&data);
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        let mut metadata = Metadata::new("Untitled", "en");
+        let body = match gutenberg_body(&text) {
+            Some((header, body)) => {
+                apply_gutenberg_header(header, &mut metadata);
+                if self.strip_gutenberg {
+                    body
+                } else {
+                    text.as_str()
+                }
+            }
+            None => text.as_str(),
+        };
+
+        let blocks = self.parse_text(body);
+
+        // Without a Gutenberg header, the first line of the text is taken as the title
+        if metadata.title == "Untitled" {
+            if let Some(first) = body.lines().map(str::trim).find(|line| !line.is_empty()) {
+                if first.chars().count() <= 100 {
+                    metadata.title = first.to_string();
+                }
+            }
+        }
+
+        let mut book = Book::with_metadata(metadata);
+        for chapter in self.splitter.split(blocks) {
+            book.add_chapter(chapter);
+        }
+
+        Ok(book)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["txt", "text"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/plain"]
+    }
+
+    fn set_split_options(&mut self, options: &SplitOptions) -> bool {
+        options.apply(&mut self.splitter);
+        true
+    }
+}
+
+/// Decode text, detecting its encoding
+///
+/// A byte order mark selects UTF-8 or UTF-16. Without one, valid UTF-8 is taken
+/// as UTF-8, text with NUL bytes in alternating positions as UTF-16, and
+/// anything else as Windows-1252 (which agrees with Latin-1 on every printable
+/// character).
+pub(crate) fn decode_bytes(data: &[u8]) -> (String, &'static Encoding) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(data) {
+        let (text, _) = encoding.decode_without_bom_handling(&data[bom_len..]);
+        return (text.into_owned(), encoding);
+    }
+
+    if let Ok(text) = std::str::from_utf8(data) {
+        return (text.to_string(), UTF_8);
+    }
+
+    let encoding = utf16_without_bom(data).unwrap_or(WINDOWS_1252);
+    let (text, _) = encoding.decode_without_bom_handling(data);
+    (text.into_owned(), encoding)
+}
+
+/// UTF-16 byte order, for text whose ASCII characters leave every other byte zero
+fn utf16_without_bom(data: &[u8]) -> Option<&'static Encoding> {
+    let sample = &data[..data.len().min(4096) & !1];
+    if sample.len() < 8 {
+        return None;
+    }
+
+    let zeros = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    let pairs = sample.len() / 2;
+
+    if odd * 3 > pairs && even * 20 < pairs {
+        Some(UTF_16LE)
+    } else if even * 3 > pairs && odd * 20 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Split a Project Gutenberg release into its header and the text between its
+/// START and END markers
+fn gutenberg_body(text: &str) -> Option<(&str, &str)> {
+    let start_line = find_marker(
+        text,
+        &["*** START OF", "***START OF", "*END*THE SMALL PRINT"],
+    )?;
+    let body_start = text[start_line..]
+        .find('\n')
+        .map_or(text.len(), |end| start_line + end + 1);
+
+    let body_end = find_marker(
+        &text[body_start..],
+        &[
+            "*** END OF",
+            "***END OF",
+            "END OF THE PROJECT GUTENBERG",
+            "END OF THIS PROJECT GUTENBERG",
+            "END OF PROJECT GUTENBERG",
+        ],
+    )
+    .map_or(text.len(), |end| body_start + end);
+
+    Some((&text[..start_line], &text[body_start..body_end]))
+}
+
+/// Byte offset of the first line starting with one of `markers` (ignoring case)
+fn find_marker(text: &str, markers: &[&str]) -> Option<usize> {
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let upper = line.trim_start().to_uppercase();
+        let is_marker = markers.iter().any(|marker| upper.starts_with(marker));
+        if is_marker && (upper.contains("GUTENBERG") || upper.contains("SMALL PRINT")) {
+            return Some(offset);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Fill in metadata from the `Title:` and `Author:` lines of a Gutenberg header
+fn apply_gutenberg_header(header: &str, metadata: &mut Metadata) {
+    for line in header.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+
+        match key.trim() {
+            "Title" => metadata.title = value.to_string(),
+            "Author" => metadata.creator.push(value.to_string()),
+            "Language" => {
+                if let Some(code) = language_code(value) {
+                    metadata.language = code.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    metadata.publisher = Some("Project Gutenberg".to_string());
+}
+
+/// ISO 639-1 code for a language name used in Gutenberg headers
+fn language_code(name: &str) -> Option<&'static str> {
+    const LANGUAGES: &[(&str, &str)] = &[
+        ("english", "en"),
+        ("french", "fr"),
+        ("german", "de"),
+        ("spanish", "es"),
+        ("italian", "it"),
+        ("portuguese", "pt"),
+        ("dutch", "nl"),
+        ("finnish", "fi"),
+        ("swedish", "sv"),
+        ("danish", "da"),
+        ("norwegian", "no"),
+        ("russian", "ru"),
+        ("polish", "pl"),
+        ("latin", "la"),
+        ("greek", "el"),
+        ("chinese", "zh"),
+        ("japanese", "ja"),
+    ];
+
+    let name = name.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(language, _)| name.starts_with(language))
+        .map(|(_, code)| *code)
+}
+
+/// Heading level of a line that looks like a part (1) or chapter (2) heading
+///
+/// The number may be followed by `.` or `:` and a short title, but not by the
+/// rest of a sentence.
+fn heading_level(line: &str) -> Option<u8> {
+    static PART: OnceLock<Regex> = OnceLock::new();
+    static CHAPTER: OnceLock<Regex> = OnceLock::new();
+    static BARE: OnceLock<Regex> = OnceLock::new();
+
+    const NUMBER: &str = r"([0-9]+|[IVXLCDM]+|[ivxlcdm]+|(?i:one|two|three|four|five|six|seven|eight|nine|ten|eleven|twelve|the\s+\w+))";
+    const TITLE: &str = r"([.:]?|[.:]\s+.{1,60})$";
+
+    let part = PART.get_or_init(|| {
+        Regex::new(&format!(r"^(?i:part|book|volume)\s+{}{}", NUMBER, TITLE)).unwrap()
+    });
+    let chapter = CHAPTER.get_or_init(|| {
+        Regex::new(&format!(
+            r"^(?i:chapter|chap\.|letter|canto|stave|section)\s+{}{}",
+            NUMBER, TITLE
+        ))
+        .unwrap()
+    });
+    let bare = BARE.get_or_init(|| {
+        Regex::new(r"^(?:([IVXLCDM]{2,})|PROLOGUE|EPILOGUE|PREFACE|INTRODUCTION|CONCLUSION)\.?$")
+            .unwrap()
+    });
+
+    // Letters that only look like a numeral, as in "Part did", aren't one
+    let is_number = |number: &str| {
+        !number.chars().all(|c| "ivxlcdmIVXLCDM".contains(c))
+            || is_roman_numeral(&number.to_lowercase())
+    };
+    let line = line.trim();
+    let matches = |regex: &Regex| {
+        regex.captures(line).is_some_and(|captures| {
+            captures
+                .get(1)
+                .is_none_or(|number| is_number(number.as_str()))
+        })
+    };
+
+    if matches(part) {
+        Some(1)
+    } else if matches(chapter) || matches(bare) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Whether text is a lowercase roman numeral in standard form, such as "xiv"
+///
+/// Words made of the same letters ("mild", "civil", "dim") are not numerals.
+pub(crate) fn is_roman_numeral(text: &str) -> bool {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    let mut rest = text;
+    let mut value = 0;
+    for (numeral_value, numeral) in NUMERALS {
+        while let Some(stripped) = rest.strip_prefix(numeral) {
+            value += numeral_value;
+            rest = stripped;
+        }
+    }
+    if value == 0 || !rest.is_empty() {
+        return false;
+    }
+
+    // Writing the value back out gives the same text only for the standard form
+    let mut canonical = String::new();
+    for (numeral_value, numeral) in NUMERALS {
+        while value >= numeral_value {
+            canonical.push_str(numeral);
+            value -= numeral_value;
+        }
+    }
+    canonical == text
+}
+
+/// Whether a paragraph is a short line naming the chapter before it
+fn is_subtitle(chunk: &[&str]) -> bool {
+    let [line] = chunk else {
+        return false;
+    };
+    let line = line.trim();
+    let shouting = line.chars().any(char::is_alphabetic) && !line.chars().any(char::is_lowercase);
+    let ends_sentence = line.ends_with(['.', ',', ';', ':', '!', '?', '"', '\u{201d}', '\u{2019}']);
+
+    line.chars().count() < 60 && heading_level(line).is_none() && (shouting || !ends_sentence)
+}
+
+/// Join the lines of a heading into one title
+fn join_title(lines: &[&str]) -> String {
+    match lines {
+        [number, name] if !number.ends_with(['.', ':']) => format!("{}: {}", number, name),
+        _ => lines.join(" "),
+    }
+}
+
+/// Whether a line is a section separator such as `* * *`
+fn is_separator(line: &str) -> bool {
+    let line = line.trim();
+    line.len() >= 3
+        && line
+            .chars()
+            .all(|c| matches!(c, '*' | '-' | '_' | '~' | '=' | ' '))
+}
+
+/// The width text is wrapped at, taken as the length most long lines reach
+fn wrap_width(lines: &[&str]) -> usize {
+    let mut lengths: Vec<usize> = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .filter(|len| *len > 0)
+        .collect();
+    if lengths.is_empty() {
+        return 0;
+    }
+    lengths.sort_unstable();
+    lengths[lengths.len() * 9 / 10]
+}
+
+/// Build a paragraph, joining wrapped lines but keeping the breaks in verse and
+/// indented blocks
+fn paragraph(chunk: &[&str], wrap_width: usize) -> Block {
+    let indented = chunk
+        .iter()
+        .all(|line| line.starts_with("  ") || line.starts_with('\t'));
+    let short = chunk
+        .iter()
+        .all(|line| line.chars().count() * 4 < wrap_width * 3);
+
+    if chunk.len() > 1 && (indented || short) {
+        let mut content = Vec::new();
+        for (i, line) in chunk.iter().enumerate() {
+            if i > 0 {
+                content.push(Inline::Break);
+            }
+            content.push(Inline::text(line.trim()));
+        }
+        Block::Paragraph(content)
+    } else {
+        let text: Vec<&str> = chunk.iter().map(|line| line.trim()).collect();
+        Block::Paragraph(vec![Inline::text(text.join(" "))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+    use std::io::Cursor;
+
+    fn decode(text: &[u8]) -> Book {
+        TextDecoder::new()
+            .decode(&mut Cursor::new(text.to_vec()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode_encodings() {
+        assert_eq!(decode_bytes(b"\xEF\xBB\xBFcaf\xC3\xA9").0, "café");
+        assert_eq!(decode_bytes(b"\xFF\xFEc\0a\0f\0\xE9\0").0, "café");
+        assert_eq!(decode_bytes(b"c\0a\0f\0\xE9\0 \0o\0k\0").0, "café ok");
+        assert_eq!(decode_bytes(b"\x93caf\xE9\x94").0, "\u{201c}café\u{201d}");
+    }
+
+    #[test]
+    fn test_heading_detection() {
+        assert_eq!(heading_level("CHAPTER I."), Some(2));
+        assert_eq!(heading_level("Chapter 12"), Some(2));
+        assert_eq!(heading_level("XIV."), Some(2));
+        assert_eq!(heading_level("BOOK THE FIRST"), Some(1));
+        assert_eq!(heading_level("Chapters are long."), None);
+        assert_eq!(heading_level("I said nothing."), None);
+        assert_eq!(heading_level("mild"), None);
+        assert_eq!(heading_level("Chapter 4: The Storm"), Some(2));
+        assert_eq!(heading_level("Part did"), None);
+        assert_eq!(heading_level("I"), None);
+        assert_eq!(heading_level("CIVIL"), None);
+    }
+
+    #[test]
+    fn test_prose_about_parts_and_chapters_stays_prose() {
+        for text in [
+            "Part one of the plan was simple, he thought,\nbut part two was harder.\n",
+            "Chapter 3 of the report shows that sales\nincreased a lot.\n",
+            "Chapter 3.\nincreased a lot, or so they said.\n",
+        ] {
+            let blocks = TextDecoder::new().parse_text(text);
+            assert!(
+                matches!(blocks[..], [Block::Paragraph(_)]),
+                "{:?} became {:?}",
+                text,
+                blocks
+            );
+        }
+    }
+
+    #[test]
+    fn test_lone_pronoun_is_not_a_chapter() {
+        let text = "First paragraph of text.\n\nI\n\nSecond paragraph of text.\n";
+        let blocks = TextDecoder::new().parse_text(text);
+        assert!(blocks
+            .iter()
+            .all(|block| matches!(block, Block::Paragraph(_))));
+    }
+
+    #[test]
+    fn test_decode_chapters_and_paragraphs() {
+        let text = "My Story\n\nCHAPTER I.\nThe Start\n\nIt was a dark and stormy night; the rain fell in\ntorrents, except at occasional intervals.\n\nCHAPTER II\n\nThe Middle\n\nMore text here.\n";
+        let book = decode(text.as_bytes());
+
+        assert_eq!(book.metadata.title, "My Story");
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Untitled", "CHAPTER I. The Start", "CHAPTER II: The Middle"]
+        );
+        assert_eq!(
+            book.chapters[1].content[1],
+            Block::Paragraph(vec![Inline::text(
+                "It was a dark and stormy night; the rain fell in torrents, except at occasional intervals."
+            )])
+        );
+    }
+
+    #[test]
+    fn test_verse_keeps_line_breaks() {
+        let text = "A long line of prose that sets the wrap width for this test text.\nAnd another line of prose of much the same length as the first.\n\n    Twinkle, twinkle,\n    little star\n";
+        let blocks = TextDecoder::new().parse_text(text);
+
+        assert_eq!(
+            blocks[1],
+            Block::Paragraph(vec![
+                Inline::text("Twinkle, twinkle,"),
+                Inline::Break,
+                Inline::text("little star"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_strip_gutenberg() {
+        let text = "The Project Gutenberg eBook of Emma\n\nTitle: Emma\n\nAuthor: Jane Austen\n\nLanguage: English\n\n*** START OF THE PROJECT GUTENBERG EBOOK EMMA ***\n\nCHAPTER I\n\nEmma Woodhouse, handsome, clever, and rich.\n\n*** END OF THE PROJECT GUTENBERG EBOOK EMMA ***\n\nLicense text.\n";
+
+        let book = decode(text.as_bytes());
+        assert_eq!(book.metadata.title, "Emma");
+        assert_eq!(book.metadata.creator, vec!["Jane Austen"]);
+        assert_eq!(book.chapters.len(), 1);
+        assert_eq!(book.chapters[0].content.len(), 2);
+
+        let book = TextDecoder::new()
+            .with_strip_gutenberg(false)
+            .decode(&mut Cursor::new(text.as_bytes().to_vec()))
+            .unwrap();
+        assert_eq!(book.metadata.title, "Emma");
+        assert!(book.chapters.len() > 1);
+    }
+}
@@ -0,0 +1,218 @@
+//! Helpers shared by the XML-based decoders, and a decoder for `.xml` files
+//!
+//! Generic XML files are told apart by their root element: DocBook, TEI and
+//! FictionBook documents are handed to the decoder for that vocabulary.
+
+use super::{docbook, DecodeContext, DocBookDecoder, Fb2Decoder, TeiDecoder};
+use crate::error::ParseError;
+use crate::types::{Book, Inline};
+use encoding_rs::{Encoding, UTF_8};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::io::{Cursor, Read};
+
+/// Decoder for XML documents, chosen by their root element
+pub struct XmlDecoder;
+
+impl XmlDecoder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The decoder for a document's vocabulary, from its root element
+    fn decoder_for(data: &[u8]) -> Result<Box<dyn super::Decoder>, ParseError> {
+        let text = decode_xml(data);
+        let document = parse(&text)?;
+        let root = document.root_element().tag_name().name();
+        if docbook::ROOT_ELEMENTS.contains(&root) {
+            Ok(Box::new(DocBookDecoder::new()))
+        } else if root == "TEI" {
+            Ok(Box::new(TeiDecoder::new()))
+        } else if root == "FictionBook" {
+            Ok(Box::new(Fb2Decoder::new()))
+        } else {
+            Err(ParseError::UnsupportedFormat(format!(
+                "XML document with root element <{}>",
+                root
+            )))
+        }
+    }
+}
+
+impl Default for XmlDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl super::Decoder for XmlDecoder {
+    fn decode(&self, reader: &mut dyn Read) -> Result<Book, ParseError> {
+        self.decode_with_context(reader, &DecodeContext::new())
+    }
+
+    fn decode_with_context(
+        &self,
+        reader: &mut dyn Read,
+        context: &DecodeContext,
+    ) -> Result<Book, ParseError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| ParseError::MalformedContent(format!("Failed to read XML: {}", e)))?;
+        Self::decoder_for(&data)?.decode_with_context(&mut Cursor::new(data), context)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["xml"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/xml", "text/xml"]
+    }
+}
+
+/// Decode an XML document using its byte order mark or `encoding` declaration
+///
+/// Documents without either are read as UTF-8.
+pub(crate) fn decode_xml(data: &[u8]) -> String {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(data) {
+        let (text, _) = encoding.decode_without_bom_handling(&data[bom_len..]);
+        return text.into_owned();
+    }
+
+    let encoding = declared_encoding(data).unwrap_or(UTF_8);
+    let (text, _) = encoding.decode_without_bom_handling(data);
+    text.into_owned()
+}
+
+/// Encoding named by an `<?xml ... encoding="..."?>` declaration
+fn declared_encoding(data: &[u8]) -> Option<&'static Encoding> {
+    let prolog = &data[..data.len().min(256)];
+    let end = prolog.windows(2).position(|w| w == b"?>")?;
+    let declaration = std::str::from_utf8(&prolog[..end]).ok()?;
+
+    let value = declaration.split("encoding").nth(1)?;
+    let value = value.trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = value[1..].split(quote).next()?;
+
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Parse an XML document, allowing (but not loading) a DTD
+pub(crate) fn parse(text: &str) -> Result<Document<'_>, ParseError> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(text, options)
+        .map_err(|e| ParseError::MalformedContent(format!("Invalid XML: {}", e)))
+}
+
+/// Child elements with a local name, in any namespace
+pub(crate) fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// First child element with a local name
+pub(crate) fn child<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// Value of the attribute with a local name, in any namespace
+///
+/// Used for attributes such as `xlink:href`, whose prefix varies between files.
+pub(crate) fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|attribute| attribute.name() == name)
+        .map(|attribute| attribute.value())
+}
+
+/// Text of an element and its descendants, with whitespace collapsed
+pub(crate) fn text(node: Node) -> String {
+    let text: String = node
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    collapse_whitespace(&text).trim().to_string()
+}
+
+/// Replace each run of whitespace with a single space
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(c);
+            in_space = false;
+        }
+    }
+    collapsed
+}
+
+/// Trim whitespace at the ends of a paragraph's content
+pub(crate) fn trim_inlines(inlines: &mut Vec<Inline>) {
+    if let Some(Inline::Text(text)) = inlines.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Inline::Text(text)) = inlines.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    inlines.retain(|inline| !matches!(inline, Inline::Text(text) if text.is_empty()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder;
+
+    #[test]
+    fn test_decode_declared_encoding() {
+        let data =
+            b"<?xml version=\"1.0\" encoding=\"windows-1251\"?><p>\xcf\xf0\xe8\xe2\xe5\xf2</p>";
+        assert!(decode_xml(data).ends_with("<p>Привет</p>"));
+
+        let data = "<?xml version='1.0'?><p>Привет</p>".as_bytes();
+        assert!(decode_xml(data).ends_with("<p>Привет</p>"));
+    }
+
+    #[test]
+    fn test_text_collapses_whitespace() {
+        let document = parse("<p>  One\n   <b>two</b>  three </p>").unwrap();
+        assert_eq!(text(document.root_element()), "One two three");
+    }
+
+    #[test]
+    fn test_xml_decoder_sniffs_root() {
+        let decoder = XmlDecoder::new();
+        let docbook = r#"<book xmlns="http://docbook.org/ns/docbook"><info><title>Manual</title></info>
+            <chapter><title>Start</title><para>Hello.</para></chapter></book>"#;
+        let book = decoder.decode(&mut Cursor::new(docbook)).unwrap();
+        assert_eq!(book.metadata.title, "Manual");
+        assert_eq!(book.chapters[0].title, "Start");
+
+        let tei = r#"<TEI xmlns="http://www.tei-c.org/ns/1.0"><teiHeader><fileDesc>
+            <titleStmt><title>Letters</title></titleStmt></fileDesc></teiHeader>
+            <text><body><div><head>One</head><p>Dear friend.</p></div></body></text></TEI>"#;
+        let book = decoder.decode(&mut Cursor::new(tei)).unwrap();
+        assert_eq!(book.metadata.title, "Letters");
+        assert_eq!(book.chapters[0].title, "One");
+
+        assert!(matches!(
+            decoder.decode(&mut Cursor::new("<svg/>")),
+            Err(ParseError::UnsupportedFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+//! Non-fatal problems found while decoding and encoding
+//!
+//! Decoders and encoders keep going when they meet content they can't map,
+//! such as an unknown element or an image whose file is missing. Each such
+//! loss is reported as a [`Diagnostic`] through the decode or encode context,
+//! so callers can see exactly what did not make it into the output.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Mutex;
+
+/// How much a diagnostic matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Formatting or structure was simplified, but no text was lost
+    Info,
+    /// Content was dropped or left unresolved
+    Warning,
+    /// A part of the input could not be read at all
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Where in the input or output a diagnostic applies
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    /// Index of the chapter in the book
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<usize>,
+    /// File path, such as an entry in an archive or an included document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// 1-based line number, in `path` if it is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl Location {
+    /// Whether no location is known
+    pub fn is_empty(&self) -> bool {
+        self.chapter.is_none() && self.path.is_none() && self.line.is_none()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match (&self.path, self.line) {
+            (Some(path), Some(line)) => parts.push(format!("{}:{}", path, line)),
+            (Some(path), None) => parts.push(path.clone()),
+            (None, Some(line)) => parts.push(format!("line {}", line)),
+            (None, None) => {}
+        }
+        if let Some(chapter) = self.chapter {
+            parts.push(format!("chapter {}", chapter + 1));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// A non-fatal problem, with a stable code such as `missing-asset`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Location::is_empty")]
+    pub location: Location,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: code.into(),
+            message: message.into(),
+            location: Location::default(),
+        }
+    }
+
+    /// A diagnostic for simplified formatting or structure
+    pub fn info(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Info, code, message)
+    }
+
+    /// A diagnostic for dropped or unresolved content
+    pub fn warning(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    /// A diagnostic for part of the input that could not be read
+    pub fn error(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    /// Set the index of the chapter the diagnostic applies to
+    pub fn with_chapter(mut self, chapter: usize) -> Self {
+        self.location.chapter = Some(chapter);
+        self
+    }
+
+    /// Set the file the diagnostic applies to
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.location.path = Some(path.into());
+        self
+    }
+
+    /// Set the 1-based line the diagnostic applies to
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.location.line = Some(line);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]", self.severity, self.code)?;
+        if !self.location.is_empty() {
+            write!(f, " {}", self.location)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Diagnostics collected while decoding or encoding
+///
+/// Reporting takes `&self` so that a context can be shared across threads.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    reported: Mutex<Vec<Diagnostic>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a diagnostic
+    pub fn report(&self, diagnostic: Diagnostic) {
+        if let Ok(mut reported) = self.reported.lock() {
+            reported.push(diagnostic);
+        }
+    }
+
+    /// Diagnostics recorded so far, in the order they were reported
+    pub fn to_vec(&self) -> Vec<Diagnostic> {
+        self.reported
+            .lock()
+            .map(|reported| reported.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether nothing has been reported
+    pub fn is_empty(&self) -> bool {
+        self.reported
+            .lock()
+            .map(|reported| reported.is_empty())
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic::warning("unresolved-image", "No resource for cover.png")
+            .with_path("OEBPS/ch1.xhtml")
+            .with_chapter(2);
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning[unresolved-image] OEBPS/ch1.xhtml, chapter 3: No resource for cover.png"
+        );
+        assert_eq!(
+            Diagnostic::info("unknown-element", "Kept <blink> as text")
+                .with_line(4)
+                .to_string(),
+            "info[unknown-element] line 4: Kept <blink> as text"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Encode context for reporting what an output format could not represent,
+//! and how far encoding has got
+
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::error::ConversionError;
+use crate::progress::{CancellationToken, Progress, ProgressTracker};
+
+/// Context passed to encoders alongside the book
+#[derive(Default)]
+pub struct EncodeContext {
+    diagnostics: Diagnostics,
+    progress: ProgressTracker,
+}
+
+impl EncodeContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `listener` with the encode's progress as it changes
+    pub fn with_progress(mut self, listener: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.progress.set_listener(listener);
+        self
+    }
+
+    /// Stop encoding when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.progress.set_cancellation(token);
+        self
+    }
+
+    /// Progress of the encode, for encoders to update
+    pub fn progress(&self) -> &ProgressTracker {
+        &self.progress
+    }
+
+    /// Fail with [`ConversionError::Cancelled`] if encoding has been cancelled
+    pub fn check_cancelled(&self) -> Result<(), ConversionError> {
+        if self.progress.is_cancelled() {
+            Err(ConversionError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Record a non-fatal problem found while encoding
+    pub fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.report(diagnostic);
+    }
+
+    /// Diagnostics recorded so far
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.to_vec()
+    }
+}
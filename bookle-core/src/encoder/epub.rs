@@ -1,7 +1,9 @@
 //! EPUB encoder implementation
 
+use super::EncodeContext;
 use crate::error::ConversionError;
-use crate::types::{Block, Book, Inline};
+use crate::types::{Block, Book, Inline, ReadingDirection};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 
 /// Encoder for EPUB 3 format
@@ -137,6 +139,44 @@ impl EpubEncoder {
                     self.blocks_to_xhtml(content)
                 )
             }
+            Block::DefinitionList(items) => {
+                let mut html = String::from("<dl>\n");
+                for item in items {
+                    html.push_str(&format!("<dt>{}</dt>\n", self.inlines_to_xhtml(&item.term)));
+                    for definition in &item.definitions {
+                        html.push_str(&format!("<dd>{}</dd>\n", self.blocks_to_xhtml(definition)));
+                    }
+                }
+                html.push_str("</dl>\n");
+                html
+            }
+            Block::Admonition { kind, content } => {
+                format!(
+                    "<div class=\"admonition {}\"><p class=\"admonition-title\">{}</p>{}</div>\n",
+                    kind.name(),
+                    kind.title(),
+                    self.blocks_to_xhtml(content)
+                )
+            }
+            Block::Container {
+                id,
+                classes,
+                content,
+            } => {
+                let id_attr = id
+                    .as_ref()
+                    .map(|id| format!(" id=\"{}\"", escape_html(id)))
+                    .unwrap_or_default();
+                let class_attr = if classes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" class=\"{}\"", escape_html(&classes.join(" ")))
+                };
+                format!(
+                    "<div{id_attr}{class_attr}>{}</div>\n",
+                    self.blocks_to_xhtml(content)
+                )
+            }
         }
     }
 
@@ -191,6 +231,9 @@ impl EpubEncoder {
                 )
             }
             Inline::Break => "<br/>".to_string(),
+            Inline::Checkbox { checked } => {
+                if *checked { "\u{2611} " } else { "\u{2610} " }.to_string()
+            }
         }
     }
 
@@ -222,6 +265,15 @@ impl Default for EpubEncoder {
 
 impl super::Encoder for EpubEncoder {
     fn encode(&self, book: &Book, writer: &mut dyn Write) -> Result<(), ConversionError> {
+        self.encode_with_context(book, writer, &EncodeContext::new())
+    }
+
+    fn encode_with_context(
+        &self,
+        book: &Book,
+        writer: &mut dyn Write,
+        context: &EncodeContext,
+    ) -> Result<(), ConversionError> {
         use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
 
         let mut builder = EpubBuilder::new(ZipLibrary::new().map_err(|e| {
@@ -231,6 +283,11 @@ impl super::Encoder for EpubEncoder {
             ConversionError::EncodingFailed(format!("Failed to create EPUB builder: {}", e))
         })?;
 
+        builder.epub_version(match self.version {
+            EpubVersion::V2 => epub_builder::EpubVersion::V20,
+            EpubVersion::V3 => epub_builder::EpubVersion::V30,
+        });
+
         // Set metadata
         builder
             .metadata("title", &book.metadata.title)
@@ -246,28 +303,45 @@ impl super::Encoder for EpubEncoder {
             .metadata("lang", &book.metadata.language)
             .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
 
+        if book.metadata.reading_direction == ReadingDirection::RightToLeft {
+            builder
+                .metadata("direction", "rtl")
+                .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+        }
+
+        super::report_missing_resources(book, context);
+        let progress = context.progress();
+        progress.set_resources_total(book.resources.len());
+        progress.set_chapters_total(book.chapters.len());
+
         // Add resources (images, fonts, etc.)
+        let mut paths = HashMap::new();
+        let mut used = HashSet::new();
         for (key, resource) in book.resources.iter() {
+            context.check_cancelled()?;
             let data = resource.data.as_bytes().map_err(|e| {
                 ConversionError::EncodingFailed(format!("Failed to read resource: {}", e))
             })?;
             let mime = &resource.mime_type;
 
             // Determine filename from key or original filename
-            let filename = resource
-                .original_filename
-                .as_ref()
-                .map(|f| format!("images/{}", f))
-                .unwrap_or_else(|| format!("images/{}", key));
+            let filename = super::resource_path(key, resource, &mut used);
 
-            builder
-                .add_resource(&filename, data.as_slice(), mime)
-                .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+            if book.metadata.cover_resource_key.as_deref() == Some(key.as_str()) {
+                builder.add_cover_image(&filename, data.as_slice(), mime)
+            } else {
+                builder.add_resource(&filename, data.as_slice(), mime)
+            }
+            .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+            paths.insert(key.clone(), filename);
+            progress.resource_done();
         }
 
         // Add chapters
         for (i, chapter) in book.chapters.iter().enumerate() {
-            let xhtml = self.chapter_to_xhtml(&chapter.title, &chapter.content);
+            context.check_cancelled()?;
+            let content = super::with_resource_paths(&chapter.content, &paths);
+            let xhtml = self.chapter_to_xhtml(&chapter.title, &content);
             let filename = format!("chapter_{}.xhtml", i + 1);
 
             builder
@@ -277,6 +351,7 @@ impl super::Encoder for EpubEncoder {
                         .reftype(epub_builder::ReferenceType::Text),
                 )
                 .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+            progress.chapter_done();
         }
 
         // Generate EPUB
@@ -325,4 +400,94 @@ mod tests {
         assert!(html.contains("<p>"));
         assert!(html.contains("<strong>world</strong>"));
     }
+
+    #[test]
+    fn test_encode_images_and_direction() {
+        use crate::encoder::Encoder;
+        use crate::types::{Chapter, Metadata, Resource};
+        use std::io::{Cursor, Read};
+
+        let mut metadata = Metadata::new("Manga", "ja");
+        metadata.reading_direction = ReadingDirection::RightToLeft;
+        let mut book = Book::with_metadata(metadata);
+        let key = book.resources.add(
+            Resource::new("image/png", b"\x89PNG\r\n\x1a\n".to_vec()).with_filename("001.png"),
+        );
+        book.metadata.cover_resource_key = Some(key.clone());
+        book.add_chapter(Chapter::new("Page 1").with_content(vec![Block::Image {
+            resource_key: key,
+            caption: None,
+            alt: "Page 1".to_string(),
+        }]));
+
+        let mut output = Vec::new();
+        EpubEncoder::new().encode(&book, &mut output).unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(output)).unwrap();
+        let mut read = |suffix: &str| {
+            let name = archive
+                .file_names()
+                .find(|name| name.ends_with(suffix))
+                .unwrap()
+                .to_string();
+            let mut text = String::new();
+            archive
+                .by_name(&name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        assert!(read("chapter_1.xhtml").contains("src=\"images/001.png\""));
+        let opf = read(".opf");
+        assert!(opf.contains("page-progression-direction=\"rtl\""));
+        assert!(opf.contains("cover-image"));
+    }
+
+    #[test]
+    fn test_reports_missing_resources() {
+        use crate::encoder::{EncodeContext, Encoder};
+        use crate::types::Chapter;
+
+        let mut book = Book::new("Atlas", "en");
+        book.add_chapter(Chapter::new("Maps").with_content(vec![Block::Image {
+            resource_key: "maps/world.png".to_string(),
+            caption: None,
+            alt: String::new(),
+        }]));
+
+        let context = EncodeContext::new();
+        EpubEncoder::new()
+            .encode_with_context(&book, &mut Vec::new(), &context)
+            .unwrap();
+
+        let diagnostics = context.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "missing-resource");
+        assert_eq!(diagnostics[0].location.chapter, Some(0));
+    }
+
+    #[test]
+    fn test_progress_and_cancellation() {
+        use crate::encoder::{EncodeContext, Encoder};
+        use crate::progress::CancellationToken;
+        use crate::types::Chapter;
+
+        let mut book = Book::new("Atlas", "en");
+        book.add_chapter(Chapter::new("Maps"));
+        book.add_chapter(Chapter::new("Tables"));
+
+        let context = EncodeContext::new();
+        EpubEncoder::new()
+            .encode_with_context(&book, &mut Vec::new(), &context)
+            .unwrap();
+        assert_eq!(context.progress().progress().chapters, 2);
+        assert_eq!(context.progress().progress().chapters_total, Some(2));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let context = EncodeContext::new().with_cancellation(token);
+        let result = EpubEncoder::new().encode_with_context(&book, &mut Vec::new(), &context);
+        assert!(matches!(result, Err(ConversionError::Cancelled)));
+    }
 }
@@ -3,8 +3,10 @@
 //! KEPUB is Kobo's proprietary EPUB variant. It wraps text content in
 //! special spans for reading position tracking on Kobo devices.
 
+use super::EncodeContext;
 use crate::error::ConversionError;
-use crate::types::{Block, Book, Inline};
+use crate::types::{Block, Book, Inline, ReadingDirection};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -152,6 +154,44 @@ impl KepubEncoder {
                     self.blocks_to_xhtml(content)
                 )
             }
+            Block::DefinitionList(items) => {
+                let mut html = String::from("<dl>\n");
+                for item in items {
+                    html.push_str(&format!("<dt>{}</dt>\n", self.inlines_to_xhtml(&item.term)));
+                    for definition in &item.definitions {
+                        html.push_str(&format!("<dd>{}</dd>\n", self.blocks_to_xhtml(definition)));
+                    }
+                }
+                html.push_str("</dl>\n");
+                html
+            }
+            Block::Admonition { kind, content } => {
+                format!(
+                    "<div class=\"admonition {}\"><p class=\"admonition-title\">{}</p>{}</div>\n",
+                    kind.name(),
+                    self.wrap_kobo_span(kind.title()),
+                    self.blocks_to_xhtml(content)
+                )
+            }
+            Block::Container {
+                id,
+                classes,
+                content,
+            } => {
+                let id_attr = id
+                    .as_ref()
+                    .map(|id| format!(" id=\"{}\"", escape_html(id)))
+                    .unwrap_or_default();
+                let class_attr = if classes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" class=\"{}\"", escape_html(&classes.join(" ")))
+                };
+                format!(
+                    "<div{id_attr}{class_attr}>{}</div>\n",
+                    self.blocks_to_xhtml(content)
+                )
+            }
         }
     }
 
@@ -209,6 +249,9 @@ impl KepubEncoder {
                 )
             }
             Inline::Break => "<br/>".to_string(),
+            Inline::Checkbox { checked } => {
+                if *checked { "\u{2611} " } else { "\u{2610} " }.to_string()
+            }
         }
     }
 
@@ -243,6 +286,15 @@ impl Default for KepubEncoder {
 
 impl super::Encoder for KepubEncoder {
     fn encode(&self, book: &Book, writer: &mut dyn Write) -> Result<(), ConversionError> {
+        self.encode_with_context(book, writer, &EncodeContext::new())
+    }
+
+    fn encode_with_context(
+        &self,
+        book: &Book,
+        writer: &mut dyn Write,
+        context: &EncodeContext,
+    ) -> Result<(), ConversionError> {
         use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
 
         // Reset chapter counter for this encoding
@@ -256,6 +308,9 @@ impl super::Encoder for KepubEncoder {
             ConversionError::EncodingFailed(format!("Failed to create EPUB builder: {}", e))
         })?;
 
+        // EPUB 3, for page progression direction
+        builder.epub_version(epub_builder::EpubVersion::V30);
+
         // Set metadata
         builder
             .metadata("title", &book.metadata.title)
@@ -271,29 +326,46 @@ impl super::Encoder for KepubEncoder {
             .metadata("lang", &book.metadata.language)
             .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
 
+        if book.metadata.reading_direction == ReadingDirection::RightToLeft {
+            builder
+                .metadata("direction", "rtl")
+                .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+        }
+
+        super::report_missing_resources(book, context);
+        let progress = context.progress();
+        progress.set_resources_total(book.resources.len());
+        progress.set_chapters_total(book.chapters.len());
+
         // Add resources (images, fonts, etc.)
+        let mut paths = HashMap::new();
+        let mut used = HashSet::new();
         for (key, resource) in book.resources.iter() {
+            context.check_cancelled()?;
             let data = resource.data.as_bytes().map_err(|e| {
                 ConversionError::EncodingFailed(format!("Failed to read resource: {}", e))
             })?;
             let mime = &resource.mime_type;
 
             // Determine filename from key or original filename
-            let filename = resource
-                .original_filename
-                .as_ref()
-                .map(|f| format!("images/{}", f))
-                .unwrap_or_else(|| format!("images/{}", key));
+            let filename = super::resource_path(key, resource, &mut used);
 
-            builder
-                .add_resource(&filename, data.as_slice(), mime)
-                .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+            if book.metadata.cover_resource_key.as_deref() == Some(key.as_str()) {
+                builder.add_cover_image(&filename, data.as_slice(), mime)
+            } else {
+                builder.add_resource(&filename, data.as_slice(), mime)
+            }
+            .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+            paths.insert(key.clone(), filename);
+            progress.resource_done();
         }
 
         // Add chapters with Kobo spans
         for (i, chapter) in book.chapters.iter().enumerate() {
+            context.check_cancelled()?;
             self.reset_chapter();
-            let xhtml = self.chapter_to_xhtml(&chapter.title, &chapter.content);
+            let content = super::with_resource_paths(&chapter.content, &paths);
+            let xhtml = self.chapter_to_xhtml(&chapter.title, &content);
             let filename = format!("chapter_{}.xhtml", i + 1);
 
             builder
@@ -303,6 +375,7 @@ impl super::Encoder for KepubEncoder {
                         .reftype(epub_builder::ReferenceType::Text),
                 )
                 .map_err(|e| ConversionError::EncodingFailed(e.to_string()))?;
+            progress.chapter_done();
         }
 
         // Generate EPUB
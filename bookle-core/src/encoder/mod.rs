@@ -1,15 +1,20 @@
 //! Encoders for converting the IR to output formats
 
+mod context;
 mod epub;
 mod kepub;
 mod typst_pdf;
 
+pub use context::EncodeContext;
 pub use epub::EpubEncoder;
 pub use kepub::KepubEncoder;
 pub use typst_pdf::TypstPdfEncoder;
 
+use crate::diagnostic::Diagnostic;
 use crate::error::ConversionError;
-use crate::types::Book;
+use crate::registry::FormatRegistry;
+use crate::types::{Block, Book, Resource};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 
 /// Trait for encoding books to output formats
@@ -17,6 +22,17 @@ pub trait Encoder: Send + Sync {
     /// Encode a book to a writer
     fn encode(&self, book: &Book, writer: &mut dyn Write) -> Result<(), ConversionError>;
 
+    /// Encode a book, reporting content the format could not represent through `context`
+    fn encode_with_context(
+        &self,
+        book: &Book,
+        writer: &mut dyn Write,
+        context: &EncodeContext,
+    ) -> Result<(), ConversionError> {
+        let _ = context;
+        self.encode(book, writer)
+    }
+
     /// Format name (e.g., "EPUB", "PDF")
     fn format_name(&self) -> &str;
 
@@ -29,10 +45,83 @@ pub trait Encoder: Send + Sync {
 
 /// Get an encoder by format name
 pub fn encoder_for_format(format: &str) -> Option<Box<dyn Encoder>> {
-    match format.to_lowercase().as_str() {
-        "epub" => Some(Box::new(EpubEncoder::new())),
-        "kepub" | "kepub.epub" => Some(Box::new(KepubEncoder::new())),
-        "pdf" | "typ" | "typst" => Some(Box::new(TypstPdfEncoder::new())),
-        _ => None,
+    FormatRegistry::builtin().encoder_for_format(format)
+}
+
+/// Path a resource is stored at inside an EPUB container
+///
+/// A resource whose original filename is already in `used` is stored under
+/// its key instead, so that pages from different folders don't overwrite
+/// each other.
+pub(crate) fn resource_path(key: &str, resource: &Resource, used: &mut HashSet<String>) -> String {
+    let path = resource
+        .original_filename
+        .as_deref()
+        .map(|name| format!("images/{}", name))
+        .filter(|path| !used.contains(path))
+        .unwrap_or_else(|| format!("images/{}", key));
+    used.insert(path.clone());
+    path
+}
+
+/// Copy of `blocks` with image keys replaced by the paths in `paths`
+pub(crate) fn with_resource_paths(blocks: &[Block], paths: &HashMap<String, String>) -> Vec<Block> {
+    let mut blocks = blocks.to_vec();
+    crate::decoder::rewrite_images(&mut blocks, &mut |key| paths.get(key).cloned());
+    blocks
+}
+
+/// Report images and a cover that refer to resources missing from the book
+pub(crate) fn report_missing_resources(book: &Book, context: &EncodeContext) {
+    if let Some(cover) = &book.metadata.cover_resource_key {
+        if book.resources.get(cover).is_none() {
+            context.report(Diagnostic::warning(
+                "missing-resource",
+                format!("Cover image {} is not in the book's resources", cover),
+            ));
+        }
+    }
+    for (index, chapter) in book.chapters.iter().enumerate() {
+        for_each_image(&chapter.content, &mut |key| {
+            if book.resources.get(key).is_none() {
+                context.report(
+                    Diagnostic::warning(
+                        "missing-resource",
+                        format!("Image {} is not in the book's resources", key),
+                    )
+                    .with_chapter(index),
+                );
+            }
+        });
+    }
+}
+
+/// Call `visit` with the resource key of every image in `blocks`
+pub(crate) fn for_each_image(blocks: &[Block], visit: &mut dyn FnMut(&str)) {
+    for block in blocks {
+        match block {
+            Block::Image { resource_key, .. } => visit(resource_key),
+            Block::Blockquote(children)
+            | Block::Footnote {
+                content: children, ..
+            }
+            | Block::Admonition {
+                content: children, ..
+            }
+            | Block::Container {
+                content: children, ..
+            } => for_each_image(children, visit),
+            Block::List { items, .. } => {
+                for item in items {
+                    for_each_image(item, visit);
+                }
+            }
+            Block::DefinitionList(items) => {
+                for definition in items.iter().flat_map(|item| &item.definitions) {
+                    for_each_image(definition, visit);
+                }
+            }
+            _ => {}
+        }
     }
 }
@@ -6,6 +6,8 @@
 //!
 //! Future versions will include direct PDF compilation.
 
+use super::EncodeContext;
+use crate::diagnostic::Diagnostic;
 use crate::error::ConversionError;
 use crate::types::{Block, Book, Inline};
 use std::io::Write;
@@ -76,6 +78,12 @@ impl TypstPdfEncoder {
 
     /// Convert Book to Typst markup
     pub fn book_to_typst(&self, book: &Book) -> String {
+        // A fresh context is never cancelled
+        self.render(book, &EncodeContext::new()).unwrap_or_default()
+    }
+
+    /// Convert Book to Typst markup, counting chapters on the context's progress
+    fn render(&self, book: &Book, context: &EncodeContext) -> Result<String, ConversionError> {
         let mut typst = String::new();
 
         // Document setup
@@ -126,13 +134,16 @@ impl TypstPdfEncoder {
         }
 
         // Chapters
+        context.progress().set_chapters_total(book.chapters.len());
         for chapter in &book.chapters {
+            context.check_cancelled()?;
             typst.push_str(&format!("= {}\n\n", escape_typst(&chapter.title)));
             typst.push_str(&self.blocks_to_typst(&chapter.content));
             typst.push_str("\n#pagebreak()\n\n");
+            context.progress().chapter_done();
         }
 
-        typst
+        Ok(typst)
     }
 
     /// Convert blocks to Typst
@@ -242,6 +253,36 @@ impl TypstPdfEncoder {
                     id
                 )
             }
+            Block::DefinitionList(items) => {
+                let mut typst = String::new();
+                for item in items {
+                    let definitions: Vec<String> = item
+                        .definitions
+                        .iter()
+                        .map(|definition| self.blocks_to_typst(definition).trim().to_string())
+                        .collect();
+                    typst.push_str(&format!(
+                        "/ {}: {}\n",
+                        self.inlines_to_typst(&item.term),
+                        definitions.join("\n  ")
+                    ));
+                }
+                typst
+            }
+            Block::Admonition { kind, content } => {
+                format!(
+                    "#block(width: 100%, inset: 8pt, stroke: (left: 2pt + gray))[\n*{}*\n\n{}\n]\n",
+                    kind.title(),
+                    self.blocks_to_typst(content)
+                )
+            }
+            Block::Container { id, content, .. } => {
+                let label = id
+                    .as_ref()
+                    .map(|id| format!(" <{}>", id))
+                    .unwrap_or_default();
+                format!("#block[\n{}\n]{}\n", self.blocks_to_typst(content), label)
+            }
         }
     }
 
@@ -302,6 +343,9 @@ impl TypstPdfEncoder {
                 )
             }
             Inline::Break => "\\\n".to_string(),
+            Inline::Checkbox { checked } => {
+                if *checked { "\u{2611} " } else { "\u{2610} " }.to_string()
+            }
         }
     }
 }
@@ -314,7 +358,35 @@ impl Default for TypstPdfEncoder {
 
 impl super::Encoder for TypstPdfEncoder {
     fn encode(&self, book: &Book, writer: &mut dyn Write) -> Result<(), ConversionError> {
-        let typst_source = self.book_to_typst(book);
+        self.encode_with_context(book, writer, &EncodeContext::new())
+    }
+
+    /// Encode a book, reporting the images whose files are not written
+    ///
+    /// The Typst source refers to images by resource key; the resources
+    /// themselves have to be placed next to it before it is compiled.
+    fn encode_with_context(
+        &self,
+        book: &Book,
+        writer: &mut dyn Write,
+        context: &EncodeContext,
+    ) -> Result<(), ConversionError> {
+        super::report_missing_resources(book, context);
+        for (index, chapter) in book.chapters.iter().enumerate() {
+            super::for_each_image(&chapter.content, &mut |key| {
+                if book.resources.get(key).is_some() {
+                    context.report(
+                        Diagnostic::info(
+                            "unwritten-resource",
+                            format!("Image {} must be placed next to the Typst source", key),
+                        )
+                        .with_chapter(index),
+                    );
+                }
+            });
+        }
+
+        let typst_source = self.render(book, context)?;
 
         // Output Typst source
         // Users can compile to PDF using: typst compile output.typ output.pdf
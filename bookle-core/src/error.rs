@@ -41,6 +41,12 @@ pub enum ParseError {
 
     #[error("Malformed content: {0}")]
     MalformedContent(String),
+
+    #[error("Password required: {0}")]
+    PasswordRequired(String),
+
+    #[error("Decoding cancelled")]
+    Cancelled,
 }
 
 /// Errors that occur during encoding/conversion
@@ -57,6 +63,9 @@ pub enum ConversionError {
 
     #[error("Typst compilation error: {0}")]
     TypstError(String),
+
+    #[error("Encoding cancelled")]
+    Cancelled,
 }
 
 /// Errors that occur during storage operations
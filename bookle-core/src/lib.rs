@@ -5,15 +5,21 @@
 //! encoded to target formats.
 
 pub mod decoder;
+pub mod diagnostic;
 pub mod encoder;
 pub mod error;
+pub mod progress;
+pub mod registry;
 pub mod storage;
 pub mod types;
 
+pub use diagnostic::{Diagnostic, Severity};
 pub use error::{BookleError, ConversionError, ParseError, Result};
+pub use progress::{CancellationToken, Progress};
+pub use registry::FormatRegistry;
 pub use types::{
-    Block, Book, Chapter, Inline, Metadata, ReadingDirection, Resource, ResourceData,
-    ResourceStore, SeriesInfo, TableCell, TableData, TocEntry,
+    AdmonitionKind, Block, Book, Chapter, DefinitionItem, Inline, Metadata, ReadingDirection,
+    Resource, ResourceData, ResourceStore, SeriesInfo, TableCell, TableData, TocEntry,
 };
 
 #[cfg(test)]
@@ -0,0 +1,186 @@
+//! Progress reporting and cancellation for long-running conversions
+//!
+//! Decode and encode contexts carry a [`ProgressTracker`]. Decoders and
+//! encoders count the chapters and resources they process on it, and check it
+//! for cancellation between them; callers watch the counts through a listener
+//! and cancel through a [`CancellationToken`].
+
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Progress of a decode or encode so far
+///
+/// Totals are `None` until the decoder or encoder knows them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Chapters processed, or pages for page-based input such as PDF and comics
+    pub chapters: usize,
+    pub chapters_total: Option<usize>,
+    /// Resources such as images read or written
+    pub resources: usize,
+    pub resources_total: Option<usize>,
+    /// Bytes of input read
+    pub bytes: u64,
+    pub bytes_total: Option<u64>,
+}
+
+/// Cancels a conversion from another thread
+///
+/// Clones share the same flag, so a token kept by the caller cancels the
+/// contexts it was given to.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every conversion holding this token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether cancellation has been asked for
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Called with the latest progress after every update
+type ProgressListener = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Counts progress for a context and tells its listener about it
+#[derive(Default)]
+pub struct ProgressTracker {
+    progress: Mutex<Progress>,
+    listener: Option<ProgressListener>,
+    cancellation: CancellationToken,
+}
+
+impl ProgressTracker {
+    /// Call `listener` with the progress after every update
+    pub(crate) fn set_listener(&mut self, listener: impl Fn(&Progress) + Send + Sync + 'static) {
+        self.listener = Some(Arc::new(listener));
+    }
+
+    /// Stop when `token` is cancelled
+    pub(crate) fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    /// Progress so far
+    pub fn progress(&self) -> Progress {
+        self.progress
+            .lock()
+            .map(|progress| *progress)
+            .unwrap_or_default()
+    }
+
+    /// Whether the conversion has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Set the number of chapters, or pages, to process
+    pub fn set_chapters_total(&self, total: usize) {
+        self.update(|progress| progress.chapters_total = Some(total));
+    }
+
+    /// Count a processed chapter, or page
+    pub fn chapter_done(&self) {
+        self.update(|progress| progress.chapters += 1);
+    }
+
+    /// Set the number of resources to process
+    pub fn set_resources_total(&self, total: usize) {
+        self.update(|progress| progress.resources_total = Some(total));
+    }
+
+    /// Count a processed resource
+    pub fn resource_done(&self) {
+        self.update(|progress| progress.resources += 1);
+    }
+
+    /// Set the size of the input in bytes
+    pub fn set_bytes_total(&self, total: u64) {
+        self.update(|progress| progress.bytes_total = Some(total));
+    }
+
+    /// Count bytes of input read
+    pub fn add_bytes(&self, bytes: u64) {
+        self.update(|progress| progress.bytes += bytes);
+    }
+
+    /// Wrap an input so that reading it counts bytes and stops on cancellation
+    pub fn reader<R: Read>(&self, inner: R) -> ProgressReader<'_, R> {
+        ProgressReader {
+            inner,
+            tracker: self,
+        }
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Progress)) {
+        let Ok(mut progress) = self.progress.lock() else {
+            return;
+        };
+        apply(&mut progress);
+        let snapshot = *progress;
+        drop(progress);
+
+        if let Some(listener) = &self.listener {
+            listener(&snapshot);
+        }
+    }
+}
+
+/// An input that counts the bytes read from it on a [`ProgressTracker`]
+///
+/// Reads fail once the conversion is cancelled.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    tracker: &'a ProgressTracker,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.tracker.is_cancelled() {
+            return Err(io::Error::other("cancelled"));
+        }
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.tracker.add_bytes(read as u64);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listener_and_reader() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut tracker = ProgressTracker::default();
+        let log = seen.clone();
+        tracker.set_listener(move |progress| log.lock().unwrap().push(progress.bytes));
+        let token = CancellationToken::new();
+        tracker.set_cancellation(token.clone());
+
+        let mut data = Vec::new();
+        tracker
+            .reader(&b"twelve bytes"[..])
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(tracker.progress().bytes, 12);
+        assert_eq!(seen.lock().unwrap().last(), Some(&12));
+
+        token.cancel();
+        assert!(tracker.is_cancelled());
+        assert!(tracker.reader(&b"more"[..]).read_to_end(&mut data).is_err());
+    }
+}
@@ -0,0 +1,688 @@
+//! Registry of the formats that can be read and written
+//!
+//! A [`FormatRegistry`] maps extensions, MIME types, aliases and magic-byte
+//! probes to decoders and encoders. [`FormatRegistry::new`] starts with the
+//! built-in formats; applications add their own with
+//! [`register_decoder`](FormatRegistry::register_decoder) and
+//! [`register_encoder`](FormatRegistry::register_encoder). Formats registered
+//! later take precedence, so a built-in format can be replaced by registering
+//! another decoder for its extension.
+//!
+//! ```
+//! use bookle_core::decoder::{Decoder, TextDecoder};
+//! use bookle_core::registry::{DecoderEntry, FormatRegistry};
+//!
+//! let mut registry = FormatRegistry::new();
+//! registry.register_decoder(
+//!     DecoderEntry::new("Field notes", || Box::new(TextDecoder::new()))
+//!         .with_extensions(&["notes"])
+//!         .with_probe(|data| data.starts_with(b"NOTES\n")),
+//! );
+//! assert!(registry.decoder_for_extension("notes").is_some());
+//! assert!(registry.decoder_for_bytes(b"NOTES\nFirst entry").is_some());
+//! ```
+
+use crate::decoder::{
+    detect_format, detect_format_in, read_head, AsciiDocDecoder, CbzDecoder, DecodeOptions,
+    Decoder, DocBookDecoder, DocxDecoder, EpubDecoder, Fb2Decoder, Format, HtmlDecoder,
+    KepubDecoder, LitDecoder, MarkdownDecoder, MobiDecoder, OdtDecoder, OrgDecoder, PdbDecoder,
+    PdfDecoder, RstDecoder, RtfDecoder, SplitOptions, TeiDecoder, TextDecoder, XmlDecoder,
+};
+use crate::encoder::{Encoder, EpubEncoder, KepubEncoder, TypstPdfEncoder};
+use std::fmt;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// Creates a decoder for a registered format
+type DecoderFactory = Arc<dyn Fn() -> Box<dyn Decoder> + Send + Sync>;
+
+/// Creates an encoder for a registered format
+type EncoderFactory = Arc<dyn Fn() -> Box<dyn Encoder> + Send + Sync>;
+
+/// Tests a file's contents for a registered format
+type ProbeFn = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// How a format is recognised from a file's contents
+#[derive(Clone)]
+enum Probe {
+    /// Formats found by [`detect_format`], which is run once for all of them
+    Detected(Vec<Format>),
+    /// A test supplied with the registration
+    Custom(ProbeFn),
+}
+
+/// A format that can be decoded, with the ways it is recognised
+#[derive(Clone)]
+pub struct DecoderEntry {
+    name: String,
+    extensions: Vec<String>,
+    mime_types: Vec<String>,
+    aliases: Vec<String>,
+    probe: Option<Probe>,
+    factory: DecoderFactory,
+}
+
+impl DecoderEntry {
+    /// A format called `name`, decoded by the decoders `factory` creates
+    pub fn new<F>(name: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn() -> Box<dyn Decoder> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            extensions: Vec::new(),
+            mime_types: Vec::new(),
+            aliases: Vec::new(),
+            probe: None,
+            factory: Arc::new(factory),
+        }
+    }
+
+    /// Set the file extensions of the format, without the leading dot
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|e| e.to_lowercase()).collect();
+        self
+    }
+
+    /// Set the MIME types of the format
+    pub fn with_mime_types(mut self, mime_types: &[&str]) -> Self {
+        self.mime_types = mime_types.iter().map(|m| m.to_lowercase()).collect();
+        self
+    }
+
+    /// Set other names the format can be asked for by
+    pub fn with_aliases(mut self, aliases: &[&str]) -> Self {
+        self.aliases = aliases.iter().map(|a| a.to_lowercase()).collect();
+        self
+    }
+
+    /// Recognise the format from a file's contents
+    pub fn with_probe<P>(mut self, probe: P) -> Self
+    where
+        P: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.probe = Some(Probe::Custom(Arc::new(probe)));
+        self
+    }
+
+    /// Recognise the format when [`detect_format`] finds one of `formats`
+    fn with_detected(mut self, formats: &[Format]) -> Self {
+        self.probe = Some(Probe::Detected(formats.to_vec()));
+        self
+    }
+
+    /// Name of the format
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// File extensions of the format, the preferred one first
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// MIME types of the format
+    pub fn mime_types(&self) -> &[String] {
+        &self.mime_types
+    }
+
+    /// Other names of the format
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    /// Whether the format can be recognised from a file's contents
+    pub fn has_probe(&self) -> bool {
+        self.probe.is_some()
+    }
+
+    /// Create a decoder for the format
+    pub fn decoder(&self) -> Box<dyn Decoder> {
+        (self.factory)()
+    }
+
+    /// Create a decoder for the format that splits chapters as `options` say
+    ///
+    /// Returns `None` if the format's chapters come from the document's own
+    /// structure, so they can't be split differently.
+    pub fn decoder_with_split_options(&self, options: &SplitOptions) -> Option<Box<dyn Decoder>> {
+        let mut decoder = self.decoder();
+        decoder.set_split_options(options).then_some(decoder)
+    }
+
+    /// Whether the format's chapters can be split with [`SplitOptions`]
+    pub fn splits_chapters(&self) -> bool {
+        self.decoder_with_split_options(&SplitOptions::default())
+            .is_some()
+    }
+
+    /// Create a decoder for the format with format-specific `options` applied
+    ///
+    /// Returns `None` if the format doesn't support one of the options set.
+    pub fn decoder_with_options(&self, options: &DecodeOptions) -> Option<Box<dyn Decoder>> {
+        let mut decoder = self.decoder();
+        decoder.set_decode_options(options).then_some(decoder)
+    }
+
+    /// Whether the format supports every option set in `options`
+    pub fn supports_options(&self, options: &DecodeOptions) -> bool {
+        self.decoder_with_options(options).is_some()
+    }
+
+    /// Whether `name` names this format, by name, extension or alias
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self.extensions.iter().any(|e| e == name)
+            || self.aliases.iter().any(|a| a == name)
+    }
+}
+
+impl fmt::Debug for DecoderEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecoderEntry")
+            .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .field("mime_types", &self.mime_types)
+            .field("aliases", &self.aliases)
+            .field("has_probe", &self.has_probe())
+            .finish()
+    }
+}
+
+/// A format that can be encoded
+#[derive(Clone)]
+pub struct EncoderEntry {
+    name: String,
+    extensions: Vec<String>,
+    mime_types: Vec<String>,
+    aliases: Vec<String>,
+    factory: EncoderFactory,
+}
+
+impl EncoderEntry {
+    /// A format called `name`, encoded by the encoders `factory` creates
+    pub fn new<F>(name: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn() -> Box<dyn Encoder> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            extensions: Vec::new(),
+            mime_types: Vec::new(),
+            aliases: Vec::new(),
+            factory: Arc::new(factory),
+        }
+    }
+
+    /// Set the file extensions of the format, without the leading dot
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|e| e.to_lowercase()).collect();
+        self
+    }
+
+    /// Set the MIME types of the format
+    pub fn with_mime_types(mut self, mime_types: &[&str]) -> Self {
+        self.mime_types = mime_types.iter().map(|m| m.to_lowercase()).collect();
+        self
+    }
+
+    /// Set other names the format can be asked for by, such as `pdf` for Typst
+    pub fn with_aliases(mut self, aliases: &[&str]) -> Self {
+        self.aliases = aliases.iter().map(|a| a.to_lowercase()).collect();
+        self
+    }
+
+    /// Name of the format
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// File extensions of the format, the preferred one first
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// MIME types of the format
+    pub fn mime_types(&self) -> &[String] {
+        &self.mime_types
+    }
+
+    /// Other names of the format
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    /// Create an encoder for the format
+    pub fn encoder(&self) -> Box<dyn Encoder> {
+        (self.factory)()
+    }
+
+    /// Whether `format` names this format, by name, extension or alias
+    fn matches(&self, format: &str) -> bool {
+        self.name.eq_ignore_ascii_case(format)
+            || self.extensions.iter().any(|e| e == format)
+            || self.aliases.iter().any(|a| a == format)
+    }
+}
+
+impl fmt::Debug for EncoderEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncoderEntry")
+            .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .field("mime_types", &self.mime_types)
+            .field("aliases", &self.aliases)
+            .finish()
+    }
+}
+
+/// The decoders and encoders available, and how formats are matched to them
+#[derive(Clone, Debug)]
+pub struct FormatRegistry {
+    decoders: Vec<DecoderEntry>,
+    encoders: Vec<EncoderEntry>,
+}
+
+impl FormatRegistry {
+    /// A registry with the built-in formats
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        for entry in builtin_decoders() {
+            registry.register_decoder(entry);
+        }
+        for entry in builtin_encoders() {
+            registry.register_encoder(entry);
+        }
+        registry
+    }
+
+    /// A registry without any formats
+    pub fn empty() -> Self {
+        Self {
+            decoders: Vec::new(),
+            encoders: Vec::new(),
+        }
+    }
+
+    /// The shared registry of built-in formats
+    pub fn builtin() -> &'static FormatRegistry {
+        static REGISTRY: OnceLock<FormatRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(FormatRegistry::new)
+    }
+
+    /// Add a decoder, taking precedence over those registered before it
+    pub fn register_decoder(&mut self, entry: DecoderEntry) -> &mut Self {
+        self.decoders.push(entry);
+        self
+    }
+
+    /// Add an encoder, taking precedence over those registered before it
+    pub fn register_encoder(&mut self, entry: EncoderEntry) -> &mut Self {
+        self.encoders.push(entry);
+        self
+    }
+
+    /// Registered decoders, in registration order
+    pub fn decoders(&self) -> impl Iterator<Item = &DecoderEntry> {
+        self.decoders.iter()
+    }
+
+    /// Registered encoders, in registration order
+    pub fn encoders(&self) -> impl Iterator<Item = &EncoderEntry> {
+        self.encoders.iter()
+    }
+
+    /// The decodable format with a file extension
+    pub fn decoder_entry_for_extension(&self, ext: &str) -> Option<&DecoderEntry> {
+        let ext = ext.to_lowercase();
+        self.decoders
+            .iter()
+            .rev()
+            .find(|entry| entry.extensions.contains(&ext))
+    }
+
+    /// The decodable format with a MIME type
+    pub fn decoder_entry_for_mime_type(&self, mime: &str) -> Option<&DecoderEntry> {
+        let mime = mime.to_lowercase();
+        self.decoders
+            .iter()
+            .rev()
+            .find(|entry| entry.mime_types.contains(&mime))
+    }
+
+    /// The decodable format with a name, extension or alias
+    pub fn decoder_entry_for_name(&self, name: &str) -> Option<&DecoderEntry> {
+        let name = name.to_lowercase();
+        self.decoders
+            .iter()
+            .rev()
+            .find(|entry| entry.matches(&name))
+    }
+
+    /// Get the extension of an input file for [`decoder_for_extension`](Self::decoder_for_extension)
+    ///
+    /// Registered extensions made of two parts, such as `.fb2.zip`, are
+    /// returned whole.
+    pub fn input_extension(&self, path: &Path) -> Option<String> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        self.decoders
+            .iter()
+            .flat_map(|entry| &entry.extensions)
+            .filter(|ext| ext.contains('.'))
+            .find(|ext| {
+                name.strip_suffix(ext.as_str())
+                    .is_some_and(|stem| stem.ends_with('.') && stem.len() > 1)
+            })
+            .cloned()
+            .or_else(|| path.extension()?.to_str().map(str::to_string))
+    }
+
+    /// The decodable format recognised from a file's contents
+    ///
+    /// Formats without a signature, such as Markdown, are not recognised.
+    pub fn detect(&self, data: &[u8]) -> Option<&DecoderEntry> {
+        self.find_probed(data, || detect_format(data))
+    }
+
+    /// The decodable format recognised from a file read from `reader`, without
+    /// reading all of it
+    ///
+    /// Only the start of the file is read, as by [`detect_format_in`], and
+    /// custom probes see just that start.
+    pub fn detect_reader<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<Option<&DecoderEntry>> {
+        let head = read_head(reader)?;
+        Ok(self.find_probed(&head, || {
+            reader.rewind().ok()?;
+            detect_format_in(reader).ok().flatten()
+        }))
+    }
+
+    /// The last registered decoder whose probe accepts `data`, running the
+    /// built-in detection at most once
+    fn find_probed(
+        &self,
+        data: &[u8],
+        detect: impl FnOnce() -> Option<Format>,
+    ) -> Option<&DecoderEntry> {
+        let detected = OnceLock::new();
+        let mut detect = Some(detect);
+        self.decoders.iter().rev().find(|entry| match &entry.probe {
+            Some(Probe::Detected(formats)) => detected
+                .get_or_init(|| detect.take().and_then(|detect| detect()))
+                .is_some_and(|format| formats.contains(&format)),
+            Some(Probe::Custom(probe)) => probe(data),
+            None => false,
+        })
+    }
+
+    /// Get a decoder for a file extension
+    pub fn decoder_for_extension(&self, ext: &str) -> Option<Box<dyn Decoder>> {
+        self.decoder_entry_for_extension(ext)
+            .map(DecoderEntry::decoder)
+    }
+
+    /// Get a decoder for a MIME type
+    pub fn decoder_for_mime_type(&self, mime: &str) -> Option<Box<dyn Decoder>> {
+        self.decoder_entry_for_mime_type(mime)
+            .map(DecoderEntry::decoder)
+    }
+
+    /// Get a decoder for a file's contents
+    pub fn decoder_for_bytes(&self, data: &[u8]) -> Option<Box<dyn Decoder>> {
+        self.detect(data).map(DecoderEntry::decoder)
+    }
+
+    /// The encodable format with a name, extension or alias
+    pub fn encoder_entry(&self, format: &str) -> Option<&EncoderEntry> {
+        let format = format.to_lowercase();
+        self.encoders
+            .iter()
+            .rev()
+            .find(|entry| entry.matches(&format))
+    }
+
+    /// Get an encoder by format name, extension or alias
+    pub fn encoder_for_format(&self, format: &str) -> Option<Box<dyn Encoder>> {
+        self.encoder_entry(format).map(EncoderEntry::encoder)
+    }
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decoders for the built-in formats
+///
+/// KEPUB comes after EPUB so that Kobo books are told apart from plain EPUB.
+fn builtin_decoders() -> Vec<DecoderEntry> {
+    vec![
+        DecoderEntry::new("EPUB", || Box::new(EpubDecoder::new()))
+            .with_extensions(&["epub"])
+            .with_mime_types(&["application/epub+zip"])
+            .with_detected(&[Format::Epub]),
+        DecoderEntry::new("KEPUB", || Box::new(KepubDecoder::new()))
+            .with_extensions(&["kepub.epub", "kepub"])
+            .with_mime_types(&["application/x-kobo-epub+zip"])
+            .with_detected(&[Format::Kepub]),
+        DecoderEntry::new("LIT", || Box::new(LitDecoder::new()))
+            .with_extensions(&["lit"])
+            .with_mime_types(&["application/x-ms-reader", "application/x-ms-lit"])
+            .with_detected(&[Format::Lit]),
+        DecoderEntry::new("Markdown", || Box::new(MarkdownDecoder::new()))
+            .with_extensions(&["md", "markdown", "mdown", "mkd"])
+            .with_mime_types(&["text/markdown", "text/x-markdown"]),
+        DecoderEntry::new("PDF", || Box::new(PdfDecoder::new()))
+            .with_extensions(&["pdf"])
+            .with_mime_types(&["application/pdf"])
+            .with_detected(&[Format::Pdf]),
+        DecoderEntry::new("MOBI", || Box::new(MobiDecoder::new()))
+            .with_extensions(&["mobi", "azw", "azw3"])
+            .with_mime_types(&[
+                "application/x-mobipocket-ebook",
+                "application/vnd.amazon.ebook",
+            ])
+            .with_aliases(&["kf8"])
+            .with_detected(&[Format::Mobi, Format::Azw3]),
+        DecoderEntry::new("PalmDOC", || Box::new(PdbDecoder::new()))
+            .with_extensions(&["pdb", "prc"])
+            .with_mime_types(&["application/vnd.palm", "application/x-palm-database"])
+            .with_detected(&[Format::PalmDoc]),
+        DecoderEntry::new("RTF", || Box::new(RtfDecoder::new()))
+            .with_extensions(&["rtf"])
+            .with_mime_types(&["application/rtf", "text/rtf"])
+            .with_detected(&[Format::Rtf]),
+        DecoderEntry::new("Plain text", || Box::new(TextDecoder::new()))
+            .with_extensions(&["txt", "text"])
+            .with_mime_types(&["text/plain"]),
+        DecoderEntry::new("FictionBook", || Box::new(Fb2Decoder::new()))
+            .with_extensions(&["fb2", "fbz", "fb2.zip"])
+            .with_mime_types(&[
+                "application/x-fictionbook+xml",
+                "application/x-zip-compressed-fb2",
+            ])
+            .with_detected(&[Format::Fb2]),
+        DecoderEntry::new("Word", || Box::new(DocxDecoder::new()))
+            .with_extensions(&["docx"])
+            .with_mime_types(&[
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            ])
+            .with_detected(&[Format::Docx]),
+        DecoderEntry::new("OpenDocument Text", || Box::new(OdtDecoder::new()))
+            .with_extensions(&["odt"])
+            .with_mime_types(&["application/vnd.oasis.opendocument.text"])
+            .with_detected(&[Format::Odt]),
+        DecoderEntry::new("HTML", || Box::new(HtmlDecoder::new()))
+            .with_extensions(&["html", "htm", "xhtml", "htmlz"])
+            .with_mime_types(&["text/html", "application/xhtml+xml", "application/x-htmlz"])
+            .with_detected(&[Format::Html, Format::Htmlz]),
+        DecoderEntry::new("Comic book archive", || Box::new(CbzDecoder::new()))
+            .with_extensions(&["cbz"])
+            .with_mime_types(&["application/vnd.comicbook+zip", "application/x-cbz"])
+            .with_detected(&[Format::Cbz]),
+        DecoderEntry::new("DocBook", || Box::new(DocBookDecoder::new()))
+            .with_extensions(&["dbk", "docbook"])
+            .with_mime_types(&["application/docbook+xml"])
+            .with_detected(&[Format::DocBook]),
+        DecoderEntry::new("TEI", || Box::new(TeiDecoder::new()))
+            .with_extensions(&["tei"])
+            .with_mime_types(&["application/tei+xml"])
+            .with_detected(&[Format::Tei]),
+        DecoderEntry::new("AsciiDoc", || Box::new(AsciiDocDecoder::new()))
+            .with_extensions(&["adoc", "asciidoc", "asc"])
+            .with_mime_types(&["text/asciidoc", "text/x-asciidoc"]),
+        DecoderEntry::new("reStructuredText", || Box::new(RstDecoder::new()))
+            .with_extensions(&["rst", "rest"])
+            .with_mime_types(&["text/x-rst", "text/prs.fallenstein.rst"]),
+        DecoderEntry::new("Org", || Box::new(OrgDecoder::new()))
+            .with_extensions(&["org"])
+            .with_mime_types(&["text/org", "text/x-org"]),
+        DecoderEntry::new("XML", || Box::new(XmlDecoder::new()))
+            .with_extensions(&["xml"])
+            .with_mime_types(&["application/xml", "text/xml"]),
+    ]
+}
+
+/// Encoders for the built-in formats
+fn builtin_encoders() -> Vec<EncoderEntry> {
+    vec![
+        EncoderEntry::new("EPUB", || Box::new(EpubEncoder::new()))
+            .with_extensions(&["epub"])
+            .with_mime_types(&["application/epub+zip"]),
+        EncoderEntry::new("KEPUB", || Box::new(KepubEncoder::new()))
+            .with_extensions(&["kepub.epub"])
+            .with_mime_types(&["application/x-kobo-epub+zip"])
+            .with_aliases(&["kepub"]),
+        EncoderEntry::new("Typst", || Box::new(TypstPdfEncoder::new()))
+            .with_extensions(&["typ"])
+            .with_mime_types(&["text/x-typst"])
+            .with_aliases(&["pdf", "typst"]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ParseError;
+    use crate::types::Book;
+    use std::io::Read;
+
+    /// A third-party decoder for a made-up format
+    struct NotesDecoder;
+
+    impl Decoder for NotesDecoder {
+        fn decode(&self, _reader: &mut dyn Read) -> Result<Book, ParseError> {
+            Ok(Book::new("Notes", "en"))
+        }
+
+        fn supported_extensions(&self) -> &[&str] {
+            &["notes"]
+        }
+
+        fn supported_mime_types(&self) -> &[&str] {
+            &["application/x-notes"]
+        }
+    }
+
+    #[test]
+    fn test_builtin_lookups() {
+        let registry = FormatRegistry::builtin();
+        assert_eq!(
+            registry
+                .decoder_entry_for_extension("EPUB")
+                .map(|e| e.name()),
+            Some("EPUB")
+        );
+        assert_eq!(
+            registry
+                .decoder_entry_for_mime_type("text/x-rst")
+                .map(|e| e.name()),
+            Some("reStructuredText")
+        );
+        assert_eq!(
+            registry.detect(b"%PDF-1.4\n").map(|e| e.name()),
+            Some("PDF")
+        );
+        assert!(registry.detect(b"# Markdown").is_none());
+        assert!(registry
+            .decoder_entry_for_name("pdb")
+            .is_some_and(DecoderEntry::splits_chapters));
+        assert!(!registry
+            .decoder_entry_for_name("epub")
+            .is_some_and(DecoderEntry::splits_chapters));
+        assert_eq!(
+            registry.encoder_entry("pdf").map(|e| e.name()),
+            Some("Typst")
+        );
+        assert!(registry.encoder_for_format("docx").is_none());
+    }
+
+    #[test]
+    fn test_decode_options() {
+        let registry = FormatRegistry::builtin();
+        let entry = |name| registry.decoder_entry_for_name(name).unwrap();
+        let pages = DecodeOptions {
+            pages: Some(2..=5),
+            password: Some("secret".into()),
+            ..DecodeOptions::default()
+        };
+        let main_content = DecodeOptions {
+            main_content: true,
+            ..DecodeOptions::default()
+        };
+
+        assert!(entry("pdf").supports_options(&pages));
+        assert!(!entry("pdf").supports_options(&main_content));
+        assert!(entry("html").supports_options(&main_content));
+        assert!(!entry("html").supports_options(&pages));
+        assert!(!entry("epub").supports_options(&pages));
+        assert!(!entry("epub").supports_options(&main_content));
+        assert!(registry
+            .decoders()
+            .all(|entry| entry.supports_options(&DecodeOptions::default())));
+    }
+
+    #[test]
+    fn test_registered_formats_take_precedence() {
+        let mut registry = FormatRegistry::new();
+        registry.register_decoder(
+            DecoderEntry::new("Notes", || Box::new(NotesDecoder))
+                .with_extensions(&["notes", "txt"])
+                .with_mime_types(&["application/x-notes"])
+                .with_aliases(&["field-notes"])
+                .with_probe(|data| data.starts_with(b"NOTES")),
+        );
+
+        assert_eq!(
+            registry
+                .decoder_entry_for_extension("txt")
+                .map(|e| e.name()),
+            Some("Notes")
+        );
+        assert_eq!(
+            registry
+                .decoder_entry_for_name("Field-Notes")
+                .map(|e| e.name()),
+            Some("Notes")
+        );
+        let book = registry
+            .decoder_for_bytes(b"NOTES 1")
+            .unwrap()
+            .decode(&mut &b"NOTES 1"[..])
+            .unwrap();
+        assert_eq!(book.metadata.title, "Notes");
+
+        // The shared registry is unaffected
+        assert_eq!(
+            FormatRegistry::builtin()
+                .decoder_entry_for_extension("txt")
+                .map(|e| e.name()),
+            Some("Plain text")
+        );
+    }
+}
@@ -43,6 +43,22 @@ pub enum Block {
 
     /// Footnote definition
     Footnote { id: String, content: Vec<Block> },
+
+    /// Definition list of terms and their descriptions
+    DefinitionList(Vec<DefinitionItem>),
+
+    /// Callout such as a note or warning
+    Admonition {
+        kind: AdmonitionKind,
+        content: Vec<Block>,
+    },
+
+    /// Generic container carrying an id and classes from the source
+    Container {
+        id: Option<String>,
+        classes: Vec<String>,
+        content: Vec<Block>,
+    },
 }
 
 /// Inline content element
@@ -81,6 +97,9 @@ pub enum Inline {
 
     /// Line break
     Break,
+
+    /// Task list checkbox
+    Checkbox { checked: bool },
 }
 
 impl Inline {
@@ -117,7 +136,7 @@ impl Block {
     /// Create a header
     pub fn header(level: u8, content: Vec<Inline>) -> Self {
         Block::Header {
-            level: level.min(6).max(1),
+            level: level.clamp(1, 6),
             content,
             anchor: None,
         }
@@ -132,6 +151,63 @@ impl Block {
     }
 }
 
+/// Term and descriptions in a definition list
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DefinitionItem {
+    /// Term being defined
+    pub term: Vec<Inline>,
+
+    /// One or more descriptions of the term
+    pub definitions: Vec<Vec<Block>>,
+}
+
+/// Kind of admonition, following GitHub's alert types
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdmonitionKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AdmonitionKind {
+    /// Parse a kind name such as `note` or `WARNING`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "note" => Some(Self::Note),
+            "tip" => Some(Self::Tip),
+            "important" => Some(Self::Important),
+            "warning" => Some(Self::Warning),
+            "caution" => Some(Self::Caution),
+            _ => None,
+        }
+    }
+
+    /// Lowercase name, as used for CSS classes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Tip => "tip",
+            Self::Important => "important",
+            Self::Warning => "warning",
+            Self::Caution => "caution",
+        }
+    }
+
+    /// Title shown above the admonition's content
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::Tip => "Tip",
+            Self::Important => "Important",
+            Self::Warning => "Warning",
+            Self::Caution => "Caution",
+        }
+    }
+}
+
 /// Table data structure
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TableData {
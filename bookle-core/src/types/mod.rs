@@ -7,7 +7,7 @@ mod metadata;
 mod resource;
 mod toc;
 
-pub use block::{Block, Inline, TableCell, TableData};
+pub use block::{AdmonitionKind, Block, DefinitionItem, Inline, TableCell, TableData};
 pub use book::Book;
 pub use chapter::Chapter;
 pub use metadata::{Metadata, ReadingDirection, SeriesInfo};
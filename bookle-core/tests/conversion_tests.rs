@@ -168,8 +168,8 @@ fn decode_file(filename: &str, extension: &str) -> Result<Book, String> {
 
 /// Encode a book to bytes
 fn encode_to_bytes(book: &Book, format: &str) -> Result<Vec<u8>, String> {
-    let encoder = encoder_for_format(format)
-        .ok_or_else(|| format!("No encoder for format: {}", format))?;
+    let encoder =
+        encoder_for_format(format).ok_or_else(|| format!("No encoder for format: {}", format))?;
 
     let mut output = Vec::new();
     encoder
@@ -212,10 +212,9 @@ fn assert_books_similar(book1: &Book, book2: &Book, context: &str) {
 /// Check if titles match (allowing for format-specific variations)
 fn titles_match(t1: &str, t2: &str) -> bool {
     let normalize = |s: &str| -> String {
-        s.replace('\u{2018}', "'") // left single quote
-            .replace('\u{2019}', "'") // right single quote
-            .replace('\u{201C}', "\"") // left double quote
-            .replace('\u{201D}', "\"") // right double quote
+        // Curly single and double quotes fold to their ASCII forms
+        s.replace(['\u{2018}', '\u{2019}'], "'")
+            .replace(['\u{201C}', '\u{201D}'], "\"")
             .chars()
             .filter(|c| c.is_alphanumeric() || c.is_whitespace())
             .collect::<String>()
@@ -232,7 +231,9 @@ fn titles_match(t1: &str, t2: &str) -> bool {
 fn create_minimal_book(title: &str, language: &str) -> Book {
     let mut book = Book::new(title, language);
     let mut chapter = Chapter::new("Chapter 1");
-    chapter.add_block(Block::Paragraph(vec![Inline::Text("Test content.".to_string())]));
+    chapter.add_block(Block::Paragraph(vec![Inline::Text(
+        "Test content.".to_string(),
+    )]));
     book.add_chapter(chapter);
     book
 }
@@ -272,8 +273,8 @@ fn test_decode_epub_around_the_world() {
 
 #[test]
 fn test_decode_epub_famous_paintings() {
-    let book = decode_file(FAMOUS_PAINTINGS.epub, "epub")
-        .expect("Failed to decode Famous Paintings EPUB");
+    let book =
+        decode_file(FAMOUS_PAINTINGS.epub, "epub").expect("Failed to decode Famous Paintings EPUB");
     assert!(!book.metadata.title.is_empty(), "Title should not be empty");
     assert_metadata_snapshot!("famous_paintings_epub_metadata", &book.metadata);
 }
@@ -307,8 +308,8 @@ fn test_decode_mobi_around_the_world() {
 
 #[test]
 fn test_decode_mobi_famous_paintings() {
-    let book = decode_file(FAMOUS_PAINTINGS.mobi, "mobi")
-        .expect("Failed to decode Famous Paintings MOBI");
+    let book =
+        decode_file(FAMOUS_PAINTINGS.mobi, "mobi").expect("Failed to decode Famous Paintings MOBI");
     assert!(!book.metadata.title.is_empty(), "Title should not be empty");
     assert_metadata_snapshot!("famous_paintings_mobi_metadata", &book.metadata);
 }
@@ -342,8 +343,8 @@ fn test_decode_azw3_around_the_world() {
 
 #[test]
 fn test_decode_azw3_famous_paintings() {
-    let book = decode_file(FAMOUS_PAINTINGS.azw3, "azw3")
-        .expect("Failed to decode Famous Paintings AZW3");
+    let book =
+        decode_file(FAMOUS_PAINTINGS.azw3, "azw3").expect("Failed to decode Famous Paintings AZW3");
     assert!(!book.metadata.title.is_empty(), "Title should not be empty");
     assert_metadata_snapshot!("famous_paintings_azw3_metadata", &book.metadata);
 }
@@ -514,7 +515,7 @@ fn test_epub_to_kepub_alice() {
     // KEPUB should be a valid zip file (similar to EPUB)
     let cursor = Cursor::new(&kepub);
     let archive = zip::ZipArchive::new(cursor).expect("KEPUB should be a valid ZIP archive");
-    assert!(archive.len() > 0, "KEPUB archive should contain files");
+    assert!(!archive.is_empty(), "KEPUB archive should contain files");
 }
 
 #[test]
@@ -553,8 +554,8 @@ fn test_chapter_content_preserved_alice() {
 
 #[test]
 fn test_resources_extracted_famous_paintings() {
-    let book = decode_file(FAMOUS_PAINTINGS.epub, "epub")
-        .expect("Failed to decode Famous Paintings EPUB");
+    let book =
+        decode_file(FAMOUS_PAINTINGS.epub, "epub").expect("Failed to decode Famous Paintings EPUB");
 
     // Famous paintings book should have image resources
     let resource_count = book.resources.len();
@@ -580,7 +581,10 @@ fn test_empty_book_encoding() {
 
     // Should encode to Typst
     let result = encode_to_bytes(&book, "typ");
-    assert!(result.is_ok(), "Empty book should encode to Typst without error");
+    assert!(
+        result.is_ok(),
+        "Empty book should encode to Typst without error"
+    );
 }
 
 #[test]
@@ -593,7 +597,9 @@ fn test_minimal_book_roundtrip() {
     // Decode back
     let mut cursor = Cursor::new(encoded);
     let decoder = decoder_for_extension("epub").unwrap();
-    let roundtrip = decoder.decode(&mut cursor).expect("Failed to decode minimal book");
+    let roundtrip = decoder
+        .decode(&mut cursor)
+        .expect("Failed to decode minimal book");
 
     assert_eq!(original.metadata.title, roundtrip.metadata.title);
     assert_eq!(original.chapters.len(), roundtrip.chapters.len());
@@ -603,11 +609,11 @@ fn test_minimal_book_roundtrip() {
 fn test_unicode_title_handling() {
     // Test various Unicode scripts in book titles
     let test_cases = [
-        ("日本語タイトル", "ja"),      // Japanese
-        ("Título en español", "es"),   // Spanish with accents
-        ("Ελληνικά", "el"),            // Greek
-        ("العربية", "ar"),              // Arabic
-        ("🎉 Emoji Title 📚", "en"),   // Emojis
+        ("日本語タイトル", "ja"),    // Japanese
+        ("Título en español", "es"), // Spanish with accents
+        ("Ελληνικά", "el"),          // Greek
+        ("العربية", "ar"),           // Arabic
+        ("🎉 Emoji Title 📚", "en"), // Emojis
     ];
 
     for (title, lang) in test_cases {
@@ -640,13 +646,17 @@ fn test_long_chapter_title() {
     book.add_chapter(chapter);
 
     // Should encode without truncation issues
-    let encoded = encode_to_bytes(&book, "epub").expect("Failed to encode book with long chapter title");
+    let encoded =
+        encode_to_bytes(&book, "epub").expect("Failed to encode book with long chapter title");
 
     let mut cursor = Cursor::new(encoded);
     let decoder = decoder_for_extension("epub").unwrap();
     let decoded = decoder.decode(&mut cursor).expect("Failed to decode");
 
-    assert_eq!(decoded.chapters[0].title, long_title, "Long chapter title should be preserved");
+    assert_eq!(
+        decoded.chapters[0].title, long_title,
+        "Long chapter title should be preserved"
+    );
 }
 
 #[test]
@@ -655,21 +665,27 @@ fn test_special_characters_in_content() {
     let mut chapter = Chapter::new("Test Chapter");
 
     // Add content with special characters that might need escaping
-    chapter.add_block(Block::Paragraph(vec![
-        Inline::Text("Ampersand & less than < greater than > quotes \"double\" 'single'".to_string()),
-    ]));
-    chapter.add_block(Block::Paragraph(vec![
-        Inline::Text("Backslash \\ and forward slash / and hash #".to_string()),
-    ]));
+    chapter.add_block(Block::Paragraph(vec![Inline::Text(
+        "Ampersand & less than < greater than > quotes \"double\" 'single'".to_string(),
+    )]));
+    chapter.add_block(Block::Paragraph(vec![Inline::Text(
+        "Backslash \\ and forward slash / and hash #".to_string(),
+    )]));
 
     book.add_chapter(chapter);
 
     // Should encode without issues
     let epub_result = encode_to_bytes(&book, "epub");
-    assert!(epub_result.is_ok(), "Special characters should encode to EPUB");
+    assert!(
+        epub_result.is_ok(),
+        "Special characters should encode to EPUB"
+    );
 
     let typst_result = encode_to_bytes(&book, "typ");
-    assert!(typst_result.is_ok(), "Special characters should encode to Typst");
+    assert!(
+        typst_result.is_ok(),
+        "Special characters should encode to Typst"
+    );
 }
 
 // =============================================================================